@@ -0,0 +1,75 @@
+// Compares the size of the DELTA format (see src/dvs/codec.rs) with EVT2 and EVT3, on the samples under
+// tests/data and on the bench input, and measures its encoding and decoding throughput. Sizes include the text
+// header, which dominates for the small samples.
+// The synthetic events are spread uniformly over the sensor, the worst case for delta encoding: pass a real
+// recording for typical ratios.
+//
+// Usage: [DVS_BENCH_INPUT=<million events> | <recording>] cargo bench --bench codec
+
+mod common;
+
+use common::{drain, BenchInput};
+use criterion::Throughput;
+use dvs::dvs::raw_decoder_csv::CsvOptions;
+use dvs::dvs::{prep_encoder, prep_file_decoder, prep_stream_decoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat, FormatHint};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+
+const HEADER: &str = "% format EVT2;height=720;width=1280\n";
+
+// Samples checked in under tests/data
+const SAMPLES: [&str; 4] = ["golden_evt2.raw", "golden_evt21.raw", "golden_evt3.raw", "golden.dat"];
+
+fn encode(events: &[DVSEvent], format: EventFormat) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Cursor::new(Vec::new());
+    let mut encoder = prep_encoder(&mut bytes, format, CsvOptions::default())?;
+    encoder.write_header(vec![HEADER.into()])?;
+    for event in events {
+        encoder.write_event(*event)?;
+    }
+    encoder.flush()?;
+    drop(encoder);
+    Ok(bytes.into_inner())
+}
+
+// Prints the size of the events in each format, and the ratio of the DELTA size to it
+fn compare(name: &str, events: &[DVSEvent]) -> anyhow::Result<()> {
+    let delta = encode(events, EventFormat::Delta)?.len();
+    for (format_name, format) in [("evt2", EventFormat::Evt2), ("evt3", EventFormat::Evt3), ("delta", EventFormat::Delta)] {
+        let bytes = encode(events, format)?.len();
+        println!(
+            "{:<20} {:<6} {:>12} bytes {:>6.2} bits/event  delta is {:>5.1}%",
+            name,
+            format_name,
+            bytes,
+            bytes as f64 * 8.0 / events.len().max(1) as f64,
+            delta as f64 * 100.0 / bytes as f64
+        );
+    }
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    for sample in SAMPLES {
+        let path = format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), sample);
+        let mut decoder = prep_file_decoder::<BufReader<File>>(&path)?;
+        let mut events = Vec::new();
+        while decoder.read_events_into(&mut events, common::BATCH_SIZE)? > 0 {}
+        compare(sample, &events)?;
+    }
+
+    let events = BenchInput::from_env()?.events()?;
+    compare("input", &events)?;
+
+    let bytes = encode(&events, EventFormat::Delta)?;
+    let mut criterion = common::criterion().configure_from_args();
+    let mut group = criterion.benchmark_group("codec");
+    group.throughput(Throughput::Elements(events.len() as u64));
+    group.bench_function("delta encode", |b| b.iter(|| encode(&events, EventFormat::Delta).unwrap()));
+    group.bench_function("delta decode", |b| {
+        b.iter(|| drain(&mut prep_stream_decoder(bytes.as_slice(), FormatHint::Format(EventFormat::Delta)).unwrap()).unwrap())
+    });
+    group.finish();
+    criterion.final_summary();
+    Ok(())
+}
@@ -0,0 +1,111 @@
+// Inputs and configuration shared by the benches, which run under criterion. Each bench runs on a synthetic
+// recording, or on a real one given in the DVS_BENCH_INPUT environment variable, as criterion takes the
+// command line arguments. Throughputs are reported in events per second.
+//
+// Usage: [DVS_BENCH_INPUT=<million events> | <recording>] cargo bench --bench <name> [-- <criterion options>]
+
+#![allow(dead_code)]
+
+use criterion::Criterion;
+use dvs::dvs::raw_encoder_evt2::DVSRawEncoderEvt2;
+use dvs::dvs::{prep_file_decoder, DVSEvent, DvsRawDecoder, DvsRawEncoder};
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Number of events decoded at a time
+pub const BATCH_SIZE: usize = 1 << 16;
+
+// What a bench runs on
+pub enum BenchInput {
+    // Millions of synthetic events
+    Synthetic(usize),
+    Recording(PathBuf),
+}
+
+impl BenchInput {
+    // Reads DVS_BENCH_INPUT. Defaults to a million synthetic events
+    pub fn from_env() -> anyhow::Result<Self> {
+        match std::env::var("DVS_BENCH_INPUT") {
+            Err(_) => Ok(BenchInput::Synthetic(1)),
+            Ok(arg) => match arg.parse() {
+                Ok(millions) => Ok(BenchInput::Synthetic(millions)),
+                Err(_) if Path::new(&arg).is_file() => Ok(BenchInput::Recording(PathBuf::from(arg))),
+                Err(_) => anyhow::bail!("Expected a number of million events or a recording in DVS_BENCH_INPUT, got {}", arg),
+            },
+        }
+    }
+
+    // The events of the input, in memory
+    pub fn events(&self) -> anyhow::Result<Vec<DVSEvent>> {
+        match self {
+            BenchInput::Synthetic(millions) => Ok(synthetic_events(millions * 1_000_000)),
+            BenchInput::Recording(path) => {
+                let mut decoder = prep_file_decoder::<BufReader<File>>(path.to_str().unwrap())?;
+                let mut events = Vec::new();
+                while decoder.read_events_into(&mut events, BATCH_SIZE)? > 0 {}
+                Ok(events)
+            }
+        }
+    }
+
+    // An EVT2 recording of the input at path, or the recording itself
+    pub fn recording(&self, path: &Path) -> anyhow::Result<PathBuf> {
+        match self {
+            BenchInput::Synthetic(millions) => {
+                write_recording(path, &synthetic_events(millions * 1_000_000))?;
+                Ok(path.to_path_buf())
+            }
+            BenchInput::Recording(recording) => Ok(recording.clone()),
+        }
+    }
+}
+
+// Each bench pass handles the whole input, which takes long enough that a few samples give a stable median
+pub fn criterion() -> Criterion {
+    Criterion::default().sample_size(10).warm_up_time(Duration::from_secs(1)).measurement_time(Duration::from_secs(5))
+}
+
+// Events spread over a 1280x720 sensor at about 10 Mev/s
+pub fn synthetic_events(count: usize) -> Vec<DVSEvent> {
+    let mut state = 0x2545_F491_4F6C_DD1Du64;
+    (0..count)
+        .map(|i| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            DVSEvent { timestamp: i as i64 / 10, x: (state % 1280) as i16, y: ((state >> 16) % 720) as i16, polarity: (state >> 32) as u8 & 1 }
+        })
+        .collect()
+}
+
+// Writes events as an EVT2 recording of a 1280x720 sensor
+pub fn write_recording(path: &Path, events: &[DVSEvent]) -> anyhow::Result<()> {
+    let mut encoder = DVSRawEncoderEvt2::new(BufWriter::new(File::create(path)?));
+    encoder.write_header(vec!["% evt 2.0\n".into(), "% format EVT2;height=720;width=1280\n".into(), "% end\n".into()])?;
+    for event in events {
+        encoder.write_event(*event)?;
+    }
+    Ok(encoder.flush()?)
+}
+
+// Decodes all events, returning how many there were
+pub fn drain<R: Read + BufRead + Seek>(decoder: &mut impl DvsRawDecoder<R>) -> anyhow::Result<usize> {
+    let mut events = Vec::with_capacity(BATCH_SIZE);
+    let mut total = 0;
+    loop {
+        events.clear();
+        match decoder.read_events_into(&mut events, BATCH_SIZE)? {
+            0 => return Ok(total),
+            count => total += count,
+        }
+    }
+}
+
+// A directory for the files of a bench, removed by the bench when done
+pub fn bench_dir(name: &str) -> anyhow::Result<PathBuf> {
+    let dir = std::env::temp_dir().join(format!("dvs-bench-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
@@ -0,0 +1,52 @@
+// Measures decoding throughput for each input format. The recording is written to the temporary directory in
+// every format, then decoded from the file and through the stream decoder.
+//
+// Usage: [DVS_BENCH_INPUT=<million events> | <recording>] cargo bench --bench decode
+
+mod common;
+
+use common::{bench_dir, drain, BenchInput};
+use criterion::Throughput;
+use dvs::dvs::convert::transcode_file;
+use dvs::dvs::raw_decoder_evt2::DVSRawDecoderEvt2;
+use dvs::dvs::{prep_file_decoder, prep_stream_decoder, DvsRawDecoder, EventFormat, FormatHint};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+fn main() -> anyhow::Result<()> {
+    let input = BenchInput::from_env()?;
+    let dir = bench_dir("decode")?;
+    let source = input.recording(&dir.join("source.raw"))?;
+    let mut criterion = common::criterion().configure_from_args();
+    let mut group = criterion.benchmark_group("decode");
+
+    for (name, format) in [("evt2", EventFormat::Evt2), ("evt21", EventFormat::Evt21), ("evt3", EventFormat::Evt3), ("dat", EventFormat::Dat), ("csv", EventFormat::Csv)] {
+        let path: PathBuf = dir.join(format!("events.{}", name));
+        transcode_file(source.to_str().unwrap(), path.to_str().unwrap(), format, |_| {})?;
+        let path_str = path.to_str().unwrap();
+        let events = drain(&mut prep_file_decoder::<BufReader<File>>(path_str)?)?;
+        group.throughput(Throughput::Elements(events as u64));
+
+        group.bench_function(name, |b| b.iter(|| drain(&mut prep_file_decoder::<BufReader<File>>(path_str).unwrap()).unwrap()));
+        group.bench_function(format!("{} stream", name), |b| {
+            b.iter(|| drain(&mut prep_stream_decoder(File::open(&path).unwrap(), FormatHint::Auto).unwrap()).unwrap())
+        });
+
+        if format == EventFormat::Evt2 {
+            group.bench_function("evt2 mmap", |b| {
+                b.iter(|| {
+                    // Safety: the bench wrote the recording, and nothing changes it while it is decoded
+                    let mut decoder = unsafe { DVSRawDecoderEvt2::from_mmap(&path) }.unwrap();
+                    decoder.read_header().unwrap();
+                    drain(&mut decoder).unwrap()
+                })
+            });
+        }
+    }
+
+    group.finish();
+    criterion.final_summary();
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
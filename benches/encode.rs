@@ -0,0 +1,38 @@
+// Measures encoding throughput for each output format. The events are decoded into memory first, so only the
+// encoder and the writes to the file are timed.
+//
+// Usage: [DVS_BENCH_INPUT=<million events> | <recording>] cargo bench --bench encode
+
+mod common;
+
+use common::{bench_dir, BenchInput};
+use criterion::Throughput;
+use dvs::dvs::raw_decoder_csv::CsvOptions;
+use dvs::dvs::{prep_file_encoder_with_options, DvsRawEncoder, EventFormat};
+
+fn main() -> anyhow::Result<()> {
+    let events = BenchInput::from_env()?.events()?;
+    let dir = bench_dir("encode")?;
+    let mut criterion = common::criterion().configure_from_args();
+    let mut group = criterion.benchmark_group("encode");
+    group.throughput(Throughput::Elements(events.len() as u64));
+
+    for (name, format) in [("evt2", EventFormat::Evt2), ("evt21", EventFormat::Evt21), ("evt3", EventFormat::Evt3), ("dat", EventFormat::Dat), ("csv", EventFormat::Csv)] {
+        let path = dir.join(format!("events.{}", name));
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                let mut encoder = prep_file_encoder_with_options(path.to_str().unwrap(), format, CsvOptions::default()).unwrap();
+                encoder.write_header(vec!["% format EVT2;height=720;width=1280\n".into()]).unwrap();
+                for event in &events {
+                    encoder.write_event(*event).unwrap();
+                }
+                encoder.flush().unwrap();
+            })
+        });
+    }
+
+    group.finish();
+    criterion.final_summary();
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+// Measures the throughput of each loss model, applied to events in memory by a LossFilter. The bandwidth is set
+// to half the mean rate of the recording, so the rate-based models drop events in most chunks.
+//
+// Usage: [DVS_BENCH_INPUT=<million events> | <recording>] cargo bench --bench loss
+
+mod common;
+
+use common::BenchInput;
+use criterion::Throughput;
+use dvs::dvs::filters::DvsFilter;
+use dvs::dvs::bitrate::{format_bits_per_event, BandwidthBudget};
+use dvs::dvs::loss::{LossFilter, LossModels, LossOptions, LossParams};
+use dvs::dvs::EventFormat;
+
+fn main() -> anyhow::Result<()> {
+    let events = BenchInput::from_env()?.events()?;
+    let duration_us = match (events.first(), events.last()) {
+        (Some(first), Some(last)) => (last.timestamp - first.timestamp).max(1),
+        _ => anyhow::bail!("The recording has no events"),
+    };
+    let bits_per_event = format_bits_per_event(EventFormat::Evt2);
+    let budget = BandwidthBudget {
+        bandwidth_mbps: events.len() as f64 * bits_per_event / duration_us as f64 / 2.0,
+        bits_per_event,
+        ..BandwidthBudget::default()
+    };
+    let params = LossParams { budget, ..LossParams::default() };
+    let mut criterion = common::criterion().configure_from_args();
+    let mut group = criterion.benchmark_group("loss");
+    group.throughput(Throughput::Elements(events.len() as u64));
+
+    let models = LossModels::default();
+    for name in models.names() {
+        let options = LossOptions { budget, chunk_us: params.chunk_us };
+        let run = || -> anyhow::Result<usize> {
+            let mut filter = LossFilter::new(options, models.create(name, &params)?);
+            let mut kept = 0usize;
+            for event in &events {
+                filter.process(*event, &mut |_| kept += 1);
+            }
+            filter.finish(&mut |_| kept += 1);
+            Ok(kept)
+        };
+        println!("{:<20} {:>6.1}% kept", name, run()? as f64 * 100.0 / events.len() as f64);
+        group.bench_function(name, |b| b.iter(|| run().unwrap()));
+    }
+
+    group.finish();
+    criterion.final_summary();
+    Ok(())
+}
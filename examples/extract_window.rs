@@ -0,0 +1,40 @@
+// Extracts the events of a time window from an EVT2 or EVT3 recording, seeking to the start of the window
+// instead of decoding the recording from the beginning.
+//
+// Usage: cargo run --example extract_window -- <input file> <start us> <end us> <output file> [format]
+
+use dvs::dvs::{prep_file_decoder, prep_file_encoder, DvsRawDecoder, DvsRawEncoder, EventFormat};
+use std::io::{BufReader, BufWriter};
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let (Some(input), Some(start), Some(end), Some(output)) = (args.get(1), args.get(2), args.get(3), args.get(4)) else {
+        anyhow::bail!("Usage: extract_window <input file> <start us> <end us> <output file> [format]");
+    };
+    let (start, end): (i64, i64) = (start.parse()?, end.parse()?);
+    let format = match args.get(5) {
+        Some(format) => format.parse()?,
+        None => EventFormat::from_path(output).unwrap_or_default(),
+    };
+
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(input)?;
+    let header = decoder.read_header()?;
+    decoder.seek_to_timestamp(start)?;
+
+    let mut encoder = prep_file_encoder::<BufWriter<std::fs::File>>(output, format)?;
+    encoder.write_header(header)?;
+    let mut count = 0;
+    // The seek may land up to one time base before the window
+    while let Some(event) = decoder.read_event()? {
+        if event.timestamp >= end {
+            break;
+        }
+        if event.timestamp >= start {
+            encoder.write_event(event)?;
+            count += 1;
+        }
+    }
+    encoder.flush()?;
+    println!("Wrote {} events from {} us to {} us", count, start, end);
+    Ok(())
+}
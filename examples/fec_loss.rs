@@ -0,0 +1,110 @@
+// Simulates sending a recording over a lossy link with plain loss, forward error correction and
+// retransmissions (ARQ), and reports how many events arrive, how many bytes are sent and, for ARQ, the
+// latency retransmissions add.
+//
+// Usage: cargo run --example fec_loss -- <input file> [loss probability] [xor|rs:<parity>] [group size]
+
+use dvs::dvs::arq::{Arq, ArqOptions};
+use dvs::dvs::fec::{FecDecoder, FecEncoder, FecOptions, FecPacket, FEC_PARITY_OVERHEAD_BYTES};
+use dvs::dvs::bitrate::UDP_PAYLOAD_BYTES;
+use dvs::dvs::packet::{Packet, Packetizer, PacketizerOptions};
+use dvs::dvs::rng::SplitMix64;
+use dvs::dvs::{prep_file_decoder, DVSEvent, DvsRawDecoder};
+use std::io::BufReader;
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 10_000;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(input) = args.get(1) else {
+        anyhow::bail!("Usage: fec_loss <input file> [loss probability] [xor|rs:<parity>] [group size]");
+    };
+    let probability: f64 = args.get(2).map(|p| p.parse()).transpose()?.unwrap_or(0.05);
+    let mut options = FecOptions::default();
+    if let Some(scheme) = args.get(3) {
+        options.scheme = scheme.parse()?;
+    }
+    if let Some(group_size) = args.get(4) {
+        options.group_size = group_size.parse()?;
+    }
+
+    // Leave room for the FEC header and length prefix of parity packets in a full UDP datagram
+    let packet_options = PacketizerOptions { max_bytes: UDP_PAYLOAD_BYTES - FEC_PARITY_OVERHEAD_BYTES, max_span_us: None };
+    let mut packetizer = Packetizer::new(packet_options);
+    let mut encoder = FecEncoder::new(options)?;
+    let mut decoder = FecDecoder::new(16);
+    let mut arq = Arq::new(ArqOptions { loss_probability: probability, packet: packet_options, ..ArqOptions::default() });
+    let mut arq_events = 0;
+    // Without FEC, only the source packets are sent, so both cases see the same losses of source packets
+    let mut link = SplitMix64::new(0);
+
+    let mut input_decoder = prep_file_decoder::<BufReader<std::fs::File>>(input)?;
+    input_decoder.read_header()?;
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    let mut packets: Vec<Packet> = Vec::new();
+    let mut fec_packets: Vec<FecPacket> = Vec::new();
+    let (mut sent_events, mut plain_events, mut fec_events) = (0, 0, 0);
+    let (mut plain_bytes, mut fec_bytes) = (0, 0);
+    loop {
+        events.clear();
+        let count = input_decoder.read_events_into(&mut events, BATCH_SIZE)?;
+        for event in &events {
+            packetizer.process(*event, &mut |packet| packets.push(packet));
+            arq.process(*event, &mut |_| arq_events += 1);
+        }
+        if count == 0 {
+            packetizer.finish(&mut |packet| packets.push(packet));
+            arq.finish(&mut |_| arq_events += 1);
+        }
+        for packet in packets.drain(..) {
+            encoder.process(&packet, &mut |fec_packet| fec_packets.push(fec_packet));
+            sent_events += packet.events.len();
+            plain_bytes += packet.encoded_len();
+        }
+        if count == 0 {
+            encoder.finish(&mut |fec_packet| fec_packets.push(fec_packet));
+        }
+        for fec_packet in fec_packets.drain(..) {
+            fec_bytes += fec_packet.encoded_len();
+            if link.chance(probability) {
+                continue;
+            }
+            if !fec_packet.is_parity() {
+                plain_events += Packet::decode(&fec_packet.payload)?.events.len();
+            }
+            decoder.receive(fec_packet, &mut |packet| fec_events += packet.events.len())?;
+        }
+        if count == 0 {
+            break;
+        }
+    }
+    decoder.finish();
+
+    println!("Sent {} events, losing {:.1}% of packets", sent_events, probability * 100.0);
+    println!("Without FEC: {} events arrived ({:.2}%), {} bytes", plain_events, 100.0 * plain_events as f64 / sent_events as f64, plain_bytes);
+    println!(
+        "With {:?} over {} packets: {} events arrived ({:.2}%), {} bytes (+{:.1}%), {} packets recovered, {} lost",
+        options.scheme,
+        options.group_size,
+        fec_events,
+        100.0 * fec_events as f64 / sent_events as f64,
+        fec_bytes,
+        100.0 * (fec_bytes as f64 / plain_bytes as f64 - 1.0),
+        decoder.packets_recovered,
+        decoder.packets_lost
+    );
+    let arq_bytes: usize = plain_bytes * (arq.packets_sent + arq.retransmissions) as usize / arq.packets_sent.max(1) as usize;
+    println!(
+        "With ARQ ({} us round trip, {} retries): {} events arrived ({:.2}%), about {} bytes, {} retransmissions, mean latency {:.0} us, max {} us",
+        ArqOptions::default().rtt_us,
+        ArqOptions::default().max_retries,
+        arq_events,
+        100.0 * arq_events as f64 / sent_events as f64,
+        arq_bytes,
+        arq.retransmissions,
+        arq.mean_latency_us(),
+        arq.latency_max_us
+    );
+    Ok(())
+}
@@ -0,0 +1,48 @@
+// Prints per-second event statistics for a recording, following the file while it is still being written.
+//
+// Usage: cargo run --example live_stats -- <input.raw|input.dat> [idle timeout in seconds]
+
+use dvs::dvs::{prep_follow_decoder, DVSEvent, DvsRawDecoder};
+use std::time::Duration;
+
+const MICROSECONDS_PER_SECOND: i64 = 1_000_000;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(input) = args.get(1) else {
+        anyhow::bail!("Usage: live_stats <input file> [idle timeout in seconds]");
+    };
+    let idle_timeout = args.get(2).map(|s| s.parse()).transpose()?.unwrap_or(5);
+
+    let mut decoder = prep_follow_decoder(input, Duration::from_millis(50), Some(Duration::from_secs(idle_timeout)))?;
+    decoder.read_header()?;
+
+    let mut events: Vec<DVSEvent> = Vec::new();
+    // Events and ON events in the current second of the recording
+    let mut second: Option<i64> = None;
+    let mut count = 0;
+    let mut on_count = 0;
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, 4096)? == 0 {
+            break;
+        }
+        for event in &events {
+            let event_second = event.timestamp / MICROSECONDS_PER_SECOND;
+            if let Some(current) = second {
+                if event_second != current {
+                    println!("t={}s: {} events/s, {:.1}% ON", current, count, 100.0 * on_count as f64 / count as f64);
+                    count = 0;
+                    on_count = 0;
+                }
+            }
+            second = Some(event_second);
+            count += 1;
+            on_count += event.polarity as u64;
+        }
+    }
+    if let Some(current) = second {
+        println!("t={}s: {} events/s, {:.1}% ON", current, count, 100.0 * on_count as f64 / count as f64);
+    }
+    Ok(())
+}
@@ -0,0 +1,52 @@
+// Receives a recording streamed by the quic_sender example and writes it to a file.
+// Chunks the sender abandoned are counted as lost.
+//
+// Usage: cargo run --example quic_receiver --features quic -- <output file> [address] [certificate file]
+// The receiver's self-signed certificate is written to the certificate file, for the sender to check.
+
+use dvs::dvs::net::quic::{QuicEventReceiver, QuicReceiverOptions};
+use dvs::dvs::{prep_file_encoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat};
+use std::io::{BufReader, BufWriter};
+
+// Number of events written at a time
+const BATCH_SIZE: usize = 10_000;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(output) = args.get(1) else {
+        anyhow::bail!("Usage: quic_receiver <output file> [address] [certificate file]");
+    };
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:5002");
+
+    // Wait for the sender to connect, then end the stream if it goes quiet
+    let mut receiver = QuicEventReceiver::bind(address, QuicReceiverOptions { timeout: None })?;
+    if let Some(path) = args.get(3) {
+        std::fs::write(path, receiver.certificate())?;
+    }
+    println!("Listening on {}", receiver.local_addr()?);
+    let header = DvsRawDecoder::<BufReader<std::fs::File>>::read_header(&mut receiver)?;
+    receiver.set_timeout(QuicReceiverOptions::default().timeout);
+
+    let format = EventFormat::from_path(output).unwrap_or_default();
+    let mut encoder = prep_file_encoder::<BufWriter<std::fs::File>>(output, format)?;
+    encoder.write_header(header)?;
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    let mut received_events = 0;
+    loop {
+        events.clear();
+        if DvsRawDecoder::<BufReader<std::fs::File>>::read_events_into(&mut receiver, &mut events, BATCH_SIZE)? == 0 {
+            break;
+        }
+        for event in &events {
+            encoder.write_event(*event)?;
+        }
+        received_events += events.len();
+    }
+    encoder.flush()?;
+
+    println!(
+        "Received {} events in {} chunks, {} lost",
+        received_events, receiver.chunks_received, receiver.chunks_lost
+    );
+    Ok(())
+}
@@ -0,0 +1,47 @@
+// Streams a recording to a receiver over QUIC, paced to the timestamps of the events.
+// Each 10 ms chunk of events is sent on its own stream, and abandoned if it isn't delivered within 200 ms.
+//
+// Usage: cargo run --example quic_sender --features quic -- <input file> [address] [speed] [certificate file]
+// Run the quic_receiver example first. Pass the certificate it wrote to check the receiver's identity.
+
+use dvs::dvs::net::quic::{QuicEventSender, QuicSenderOptions};
+use dvs::dvs::net::Pacing;
+use dvs::dvs::{prep_file_decoder, DVSEvent, DvsRawDecoder};
+use std::io::BufReader;
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 10_000;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(input) = args.get(1) else {
+        anyhow::bail!("Usage: quic_sender <input file> [address] [speed] [certificate file]");
+    };
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:5002");
+    let speed = args.get(3).map(|speed| speed.parse()).transpose()?.unwrap_or(1.0);
+    let certificate = args.get(4).map(std::fs::read).transpose()?;
+
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(input)?;
+    let header = decoder.read_header()?;
+    let options = QuicSenderOptions { pacing: Pacing::RealTime { speed }, certificate, ..QuicSenderOptions::default() };
+    let mut sender = QuicEventSender::connect(address, options)?;
+    sender.send_header(&header)?;
+
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    let mut sent_events = 0;
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            break;
+        }
+        sender.send_events(&events)?;
+        sent_events += events.len();
+    }
+    sender.finish()?;
+
+    println!(
+        "Sent {} events in {} chunks ({} bytes), {} abandoned",
+        sent_events, sender.chunks_sent, sender.bytes_sent, sender.chunks_abandoned()
+    );
+    Ok(())
+}
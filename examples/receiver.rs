@@ -0,0 +1,52 @@
+// Receives a recording streamed by the sender example and writes it to a file.
+// Each length-prefixed message is a self-contained EVT3 stream, decoded from memory as it arrives.
+//
+// Usage: cargo run --example receiver -- <output.raw> [address]
+
+use dvs::dvs::raw_decoder_evt3::DVSRawDecoderEvt3;
+use dvs::dvs::{prep_file_encoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat};
+use std::io::{BufWriter, Cursor, Read};
+use std::net::TcpListener;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(output) = args.get(1) else {
+        anyhow::bail!("Usage: receiver <output file> [address]");
+    };
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:5000");
+
+    let listener = TcpListener::bind(address)?;
+    println!("Listening on {}", address);
+    let (mut stream, peer) = listener.accept()?;
+    println!("Receiving from {}", peer);
+
+    let mut encoder = prep_file_encoder::<BufWriter<std::fs::File>>(output, EventFormat::Evt2)?;
+    let mut header_written = false;
+    let mut events: Vec<DVSEvent> = Vec::new();
+    let mut received_events = 0;
+    let mut length = [0u8; 4];
+    while stream.read_exact(&mut length).is_ok() {
+        let mut message = vec![0u8; u32::from_le_bytes(length) as usize];
+        stream.read_exact(&mut message)?;
+
+        let mut decoder = DVSRawDecoderEvt3::new(Cursor::new(message));
+        let header = decoder.read_header()?;
+        if !header_written {
+            encoder.write_header(header)?;
+            header_written = true;
+        }
+        events.clear();
+        while decoder.read_events_into(&mut events, usize::MAX)? > 0 {}
+        for event in &events {
+            encoder.write_event(*event)?;
+        }
+        received_events += events.len();
+        if let (Some(first), Some(last)) = (events.first(), events.last()) {
+            println!("{} events, {} to {} us", events.len(), first.timestamp, last.timestamp);
+        }
+    }
+    encoder.flush()?;
+
+    println!("Received {} events", received_events);
+    Ok(())
+}
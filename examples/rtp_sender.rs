@@ -0,0 +1,59 @@
+// Streams a recording as RTP over UDP, in real time. Capture it in Wireshark and decode UDP on the port as
+// RTP ("Decode As...") to inspect sequence numbers, timestamps and the marker bit ending each 10 ms chunk.
+//
+// Usage: cargo run --example rtp_sender -- <input file> [address]
+
+use dvs::dvs::rtp::{RtpOptions, RtpPacketizer};
+use dvs::dvs::{prep_file_decoder, DVSEvent, DvsRawDecoder};
+use std::io::BufReader;
+use std::net::UdpSocket;
+use std::time::{Duration, Instant};
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 10_000;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(input) = args.get(1) else {
+        anyhow::bail!("Usage: rtp_sender <input file> [address]");
+    };
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:5004");
+
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(input)?;
+    decoder.read_header()?;
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(address)?;
+
+    let mut packetizer = RtpPacketizer::new(RtpOptions::default());
+    let mut packets = Vec::new();
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    let mut start: Option<(Instant, i64)> = None;
+    let mut sent_packets = 0;
+    loop {
+        events.clear();
+        let count = decoder.read_events_into(&mut events, BATCH_SIZE)?;
+        for event in &events {
+            packetizer.process(*event, &mut |packet| packets.push(packet));
+        }
+        if count == 0 {
+            packetizer.finish(&mut |packet| packets.push(packet));
+        }
+        for packet in packets.drain(..) {
+            // Send each packet once the time of its first event has passed
+            let (wall, first) = *start.get_or_insert((Instant::now(), packet.packet.base_timestamp));
+            let deadline = wall + Duration::from_micros((packet.packet.base_timestamp - first).max(0) as u64);
+            if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+            // Nobody may be listening, which is fine when capturing
+            let _ = socket.send(&packet.encode());
+            sent_packets += 1;
+        }
+        if count == 0 {
+            break;
+        }
+    }
+
+    println!("Sent {} RTP packets", sent_packets);
+    Ok(())
+}
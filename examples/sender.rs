@@ -0,0 +1,56 @@
+// Streams a recording to a receiver over TCP.
+// The events are decoded in batches, and each batch is re-encoded as a self-contained EVT3 stream
+// (header included) and sent as a length-prefixed message.
+//
+// Usage: cargo run --example sender -- <input.raw|input.dat> [address]
+// Run the receiver example first.
+
+use dvs::dvs::raw_encoder_evt3::DVSRawEncoderEvt3;
+use dvs::dvs::{prep_file_decoder, DVSEvent, DvsRawDecoder, DvsRawEncoder};
+use std::io::{BufReader, Cursor, Write};
+use std::net::TcpStream;
+
+// Number of events per message
+const BATCH_SIZE: usize = 10_000;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(input) = args.get(1) else {
+        anyhow::bail!("Usage: sender <input file> [address]");
+    };
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:5000");
+
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(input)?;
+    let header = decoder.read_header()?;
+    let mut stream = TcpStream::connect(address)?;
+
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    let mut sent_events = 0;
+    let mut sent_bytes = 0;
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            break;
+        }
+
+        // Encode the batch into memory
+        let mut message = Cursor::new(Vec::new());
+        {
+            let mut encoder = DVSRawEncoderEvt3::new(&mut message);
+            encoder.write_header(header.clone())?;
+            for event in &events {
+                encoder.write_event(*event)?;
+            }
+            encoder.flush()?;
+        }
+        let message = message.into_inner();
+
+        stream.write_all(&(message.len() as u32).to_le_bytes())?;
+        stream.write_all(&message)?;
+        sent_events += events.len();
+        sent_bytes += message.len();
+    }
+
+    println!("Sent {} events in {} bytes", sent_events, sent_bytes);
+    Ok(())
+}
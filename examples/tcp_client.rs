@@ -0,0 +1,24 @@
+// Receives a recording relayed by the tcp_server example and writes it to a file.
+//
+// Usage: cargo run --example tcp_client --features transport -- <output file> [address]
+
+use dvs::dvs::convert::transcode_with;
+use dvs::dvs::net::tcp::TcpEventClient;
+use dvs::dvs::{prep_file_encoder, EventFormat};
+use std::io::{BufReader, BufWriter};
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(output) = args.get(1) else {
+        anyhow::bail!("Usage: tcp_client <output file> [address]");
+    };
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:5002");
+
+    let mut client = TcpEventClient::connect(address)?;
+    let format = EventFormat::from_path(output).unwrap_or_default();
+    let mut encoder = prep_file_encoder::<BufWriter<std::fs::File>>(output, format)?;
+    let totals = transcode_with::<BufReader<std::fs::File>, _, _, _, _>(&mut client, &mut encoder, |_| {})?;
+
+    println!("Received {} events in {} packets", totals.events, client.packets_received);
+    Ok(())
+}
@@ -0,0 +1,32 @@
+// Relays a recording to clients over TCP, such as the tcp_client example.
+// By default each client receives the whole recording as fast as possible. With --live, the recording is
+// played back in real time and broadcast to the clients connected at the time, like a live camera.
+//
+// Usage: cargo run --example tcp_server --features transport -- <input file> [address] [--live]
+
+use dvs::dvs::net::tcp::{TcpEventServer, TcpServerOptions};
+use dvs::dvs::net::Pacing;
+use dvs::dvs::prep_file_decoder;
+use std::io::BufReader;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().filter(|arg| arg != "--live").collect();
+    let live = std::env::args().any(|arg| arg == "--live");
+    let Some(input) = args.get(1) else {
+        anyhow::bail!("Usage: tcp_server <input file> [address] [--live]");
+    };
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:5002");
+
+    if live {
+        let options = TcpServerOptions { pacing: Pacing::RealTime { speed: 1.0 }, ..TcpServerOptions::default() };
+        let server = TcpEventServer::bind(address, options)?;
+        println!("Broadcasting {} on {}", input, server.local_addr()?);
+        let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(input)?;
+        server.serve_live(&mut decoder)?;
+    } else {
+        let server = TcpEventServer::bind(address, TcpServerOptions::default())?;
+        println!("Serving {} on {}", input, server.local_addr()?);
+        server.serve_file(input, None)?;
+    }
+    Ok(())
+}
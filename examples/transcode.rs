@@ -0,0 +1,24 @@
+// Converts a recording to another format without loading it into memory, printing progress as it goes.
+//
+// Usage: cargo run --example transcode -- <input file> <output file> [format]
+
+use dvs::dvs::convert::transcode_file;
+use dvs::dvs::EventFormat;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let (Some(input), Some(output)) = (args.get(1), args.get(2)) else {
+        anyhow::bail!("Usage: transcode <input file> <output file> [format]");
+    };
+    let format = match args.get(3) {
+        Some(format) => format.parse()?,
+        None => EventFormat::from_path(output).unwrap_or_default(),
+    };
+
+    let totals = transcode_file(input, output, format, |progress| {
+        eprint!("\r{} events", progress.events);
+    })?;
+    eprintln!();
+    println!("Wrote {} events as {} words", totals.events, totals.words);
+    Ok(())
+}
@@ -0,0 +1,48 @@
+// Receives a recording streamed by the udp_sender example and writes it to a file.
+// Missing packets are counted as lost once later packets have arrived, or when the sender ends the stream.
+//
+// Usage: cargo run --example udp_receiver --features transport -- <output file> [address]
+
+use dvs::dvs::net::udp::{UdpEventReceiver, UdpReceiverOptions};
+use dvs::dvs::{prep_file_encoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat};
+use std::io::{BufReader, BufWriter};
+
+// Number of events written at a time
+const BATCH_SIZE: usize = 10_000;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(output) = args.get(1) else {
+        anyhow::bail!("Usage: udp_receiver <output file> [address]");
+    };
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:5001");
+
+    // Wait for the sender to start, then end the stream if it goes quiet
+    let mut receiver = UdpEventReceiver::bind(address, UdpReceiverOptions { timeout: None, ..UdpReceiverOptions::default() })?;
+    println!("Listening on {}", receiver.local_addr()?);
+    let header = DvsRawDecoder::<BufReader<std::fs::File>>::read_header(&mut receiver)?;
+    receiver.set_timeout(UdpReceiverOptions::default().timeout)?;
+
+    let format = EventFormat::from_path(output).unwrap_or_default();
+    let mut encoder = prep_file_encoder::<BufWriter<std::fs::File>>(output, format)?;
+    encoder.write_header(header)?;
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    let mut received_events = 0;
+    loop {
+        events.clear();
+        if DvsRawDecoder::<BufReader<std::fs::File>>::read_events_into(&mut receiver, &mut events, BATCH_SIZE)? == 0 {
+            break;
+        }
+        for event in &events {
+            encoder.write_event(*event)?;
+        }
+        received_events += events.len();
+    }
+    encoder.flush()?;
+
+    println!(
+        "Received {} events in {} packets, {} lost, {} discarded",
+        received_events, receiver.packets_received, receiver.packets_lost, receiver.packets_discarded
+    );
+    Ok(())
+}
@@ -0,0 +1,43 @@
+// Streams a recording to a receiver over UDP, paced to the timestamps of the events.
+// Events are packetized into datagrams that fit in an Ethernet frame.
+//
+// Usage: cargo run --example udp_sender --features transport -- <input file> [address] [speed]
+// Run the udp_receiver example first.
+
+use dvs::dvs::net::udp::{UdpEventSender, UdpSenderOptions};
+use dvs::dvs::net::Pacing;
+use dvs::dvs::{prep_file_decoder, DVSEvent, DvsRawDecoder};
+use std::io::BufReader;
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 10_000;
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(input) = args.get(1) else {
+        anyhow::bail!("Usage: udp_sender <input file> [address] [speed]");
+    };
+    let address = args.get(2).map(String::as_str).unwrap_or("127.0.0.1:5001");
+    let speed = args.get(3).map(|speed| speed.parse()).transpose()?.unwrap_or(1.0);
+
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(input)?;
+    let header = decoder.read_header()?;
+    let options = UdpSenderOptions { pacing: Pacing::RealTime { speed }, ..UdpSenderOptions::default() };
+    let mut sender = UdpEventSender::connect(address, options)?;
+    sender.send_header(&header)?;
+
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    let mut sent_events = 0;
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            break;
+        }
+        sender.send_events(&events)?;
+        sent_events += events.len();
+    }
+    sender.finish()?;
+
+    println!("Sent {} events in {} packets ({} bytes)", sent_events, sender.packets_sent, sender.bytes_sent);
+    Ok(())
+}
@@ -0,0 +1,27 @@
+#![no_main]
+
+use dvs::dvs::raw_decoder_aedat3::DVSRawDecoderAedat3;
+use dvs::dvs::raw_decoder_aedat4::DVSRawDecoderAedat4;
+use dvs::dvs::DvsRawDecoder;
+use libfuzzer_sys::fuzz_target;
+use std::io::{BufRead, Cursor, Read, Seek};
+
+// Arbitrary bytes through the AEDAT 3.1 and 4 header and packet parsers
+
+// Reads a decoder to the end or to its first error
+fn drain<R: Read + BufRead + Seek>(mut decoder: impl DvsRawDecoder<R>) {
+    if decoder.read_header().is_err() {
+        return;
+    }
+    let mut events = Vec::new();
+    // Stops at the end of the data or at the first error; either is fine, as long as nothing panics
+    while let Ok(1..) = decoder.read_events_into(&mut events, 256) {
+        events.clear();
+        decoder.take_triggers();
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    drain(DVSRawDecoderAedat3::new(Cursor::new(data)));
+    drain(DVSRawDecoderAedat4::new(Cursor::new(data)));
+});
@@ -0,0 +1,20 @@
+#![no_main]
+
+use dvs::dvs::{prep_stream_decoder, DvsRawDecoder, FormatHint};
+use libfuzzer_sys::fuzz_target;
+
+// Arbitrary bytes through format detection, then the decoder it picks
+fuzz_target!(|data: &[u8]| {
+    let Ok(mut decoder) = prep_stream_decoder(data, FormatHint::Auto) else {
+        return;
+    };
+    if decoder.read_header().is_err() {
+        return;
+    }
+    let mut events = Vec::new();
+    // Stops at the end of the data or at the first error; either is fine, as long as nothing panics
+    while let Ok(1..) = decoder.read_events_into(&mut events, 256) {
+        events.clear();
+        decoder.take_triggers();
+    }
+});
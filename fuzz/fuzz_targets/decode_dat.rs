@@ -0,0 +1,20 @@
+#![no_main]
+
+use dvs::dvs::DvsRawDecoder;
+use dvs::dvs::raw_decoder_dat::DVSRawDecoderDat;
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+
+// Arbitrary bytes through the DAT header and event parsers
+fuzz_target!(|data: &[u8]| {
+    let mut decoder = DVSRawDecoderDat::new(Cursor::new(data));
+    if decoder.read_header().is_err() {
+        return;
+    }
+    let mut events = Vec::new();
+    // Stops at the end of the data or at the first error; either is fine, as long as nothing panics
+    while let Ok(1..) = decoder.read_events_into(&mut events, 256) {
+        events.clear();
+        decoder.take_triggers();
+    }
+});
@@ -0,0 +1,200 @@
+use crate::dvs::bitrate::{mbps, BandwidthBudget, RunningBitrate};
+use crate::dvs::loss::{LossModel, LossParams};
+use crate::dvs::DVSEvent;
+
+/*
+This file implements adaptive streaming, in the spirit of adaptive bitrate (ABR) video streaming. The sender
+doesn't know the capacity of the link. A RateController picks the rate each chunk is sent at, from feedback
+on how much of the previous chunk was delivered, and a loss model thins each chunk down to that rate.
+Events sent beyond the capacity of the link are lost to congestion.
+
+AdaptiveLoss is itself a loss model, so it plugs into LossFilter: the budget LossFilter passes to it is the
+capacity of the simulated link, and the rate the controller picks is passed on to the inner model as its
+budget. Feedback arrives one chunk later, as it would after a round trip.
+*/
+
+// What the sender learns about a chunk it sent
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct RateFeedback {
+    pub duration_us: i64,
+    pub bits_sent: f64,
+    pub bits_delivered: f64,
+}
+
+impl RateFeedback {
+    pub fn sent_mbps(&self) -> f64 {
+        mbps(self.bits_sent, self.duration_us)
+    }
+
+    // Achieved throughput
+    pub fn delivered_mbps(&self) -> f64 {
+        mbps(self.bits_delivered, self.duration_us)
+    }
+
+    pub fn loss_fraction(&self) -> f64 {
+        if self.bits_sent > 0.0 {
+            1.0 - self.bits_delivered / self.bits_sent
+        } else {
+            0.0
+        }
+    }
+}
+
+// Picks the rate to send at from feedback. Implement this to plug in other policies
+pub trait RateController {
+    // Rate to send the next chunk at, in megabits per second
+    fn target_mbps(&self) -> f64;
+    fn update(&mut self, feedback: &RateFeedback);
+}
+
+impl<C: RateController + ?Sized> RateController for Box<C> {
+    fn target_mbps(&self) -> f64 {
+        (**self).target_mbps()
+    }
+
+    fn update(&mut self, feedback: &RateFeedback) {
+        (**self).update(feedback)
+    }
+}
+
+// Additive increase, multiplicative decrease, like TCP congestion control: the rate grows steadily while
+// chunks are delivered in full, and is cut back when some of a chunk is lost
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aimd {
+    pub rate_mbps: f64,
+    // Added to the rate after each chunk without loss
+    pub increase_mbps: f64,
+    // Multiplies the rate after each chunk with loss
+    pub decrease_factor: f64,
+    pub min_mbps: f64,
+    pub max_mbps: f64,
+}
+
+impl Default for Aimd {
+    fn default() -> Self {
+        Aimd { rate_mbps: 1.0, increase_mbps: 0.1, decrease_factor: 0.5, min_mbps: 0.01, max_mbps: f64::INFINITY }
+    }
+}
+
+impl RateController for Aimd {
+    fn target_mbps(&self) -> f64 {
+        self.rate_mbps
+    }
+
+    fn update(&mut self, feedback: &RateFeedback) {
+        self.rate_mbps = if feedback.loss_fraction() > 0.0 {
+            self.rate_mbps * self.decrease_factor
+        } else {
+            self.rate_mbps + self.increase_mbps
+        }
+        .clamp(self.min_mbps, self.max_mbps);
+    }
+}
+
+// Sends at a fraction of the recent throughput, like throughput-based ABR. As throughput can't exceed the
+// rate sent at, the rate is probed upwards after chunks without loss
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ThroughputEstimate {
+    // Smoothed throughput, whose smoothing is the weight of the latest chunk in the estimate
+    pub throughput: RunningBitrate,
+    // Fraction of the estimate sent at after a chunk with loss
+    pub safety: f64,
+    // Factor the estimate is raised by after a chunk without loss
+    pub probe: f64,
+    probing: bool,
+}
+
+impl Default for ThroughputEstimate {
+    fn default() -> Self {
+        ThroughputEstimate { throughput: RunningBitrate::new(0.3, 1.0), safety: 0.9, probe: 1.25, probing: true }
+    }
+}
+
+impl RateController for ThroughputEstimate {
+    fn target_mbps(&self) -> f64 {
+        if self.probing {
+            self.throughput.smoothed_mbps * self.probe
+        } else {
+            self.throughput.smoothed_mbps * self.safety
+        }
+    }
+
+    fn update(&mut self, feedback: &RateFeedback) {
+        if feedback.bits_sent <= 0.0 {
+            return;
+        }
+        self.throughput.add(feedback.bits_delivered, feedback.duration_us);
+        self.probing = feedback.loss_fraction() <= 0.0;
+    }
+}
+
+// A loss model whose budget is picked by a RateController, over a link whose capacity is the budget passed
+// to begin_chunk
+pub struct AdaptiveLoss<M: LossModel, C: RateController> {
+    model: M,
+    controller: C,
+    budget: BandwidthBudget,
+    chunk_us: i64,
+    // Events of the current chunk the link can carry, admitted by the model, and delivered
+    capacity: usize,
+    sent: usize,
+    delivered: usize,
+    started: bool,
+    // Rate picked for each chunk so far, in megabits per second
+    pub targets_mbps: Vec<f64>,
+}
+
+impl<M: LossModel, C: RateController> AdaptiveLoss<M, C> {
+    // The event cost and chunk duration convert between events and rates
+    pub fn new(model: M, controller: C, params: &LossParams) -> Self {
+        Self {
+            model,
+            controller,
+            budget: params.budget,
+            chunk_us: params.chunk_us,
+            capacity: 0,
+            sent: 0,
+            delivered: 0,
+            started: false,
+            targets_mbps: Vec::new(),
+        }
+    }
+
+    pub fn controller(&self) -> &C {
+        &self.controller
+    }
+}
+
+impl<M: LossModel, C: RateController> LossModel for AdaptiveLoss<M, C> {
+    fn begin_chunk(&mut self, chunk: &[DVSEvent], budget: usize) {
+        // Report the previous chunk
+        if self.started {
+            self.controller.update(&RateFeedback {
+                duration_us: self.chunk_us,
+                bits_sent: self.budget.bits_for_events(self.sent),
+                bits_delivered: self.budget.bits_for_events(self.delivered),
+            });
+        }
+        self.started = true;
+
+        let target_mbps = self.controller.target_mbps();
+        self.targets_mbps.push(target_mbps);
+        let target = BandwidthBudget { bandwidth_mbps: target_mbps, ..self.budget }.events_in(self.chunk_us);
+        self.model.begin_chunk(chunk, target);
+        self.capacity = budget;
+        self.sent = 0;
+        self.delivered = 0;
+    }
+
+    fn admit(&mut self, event: &DVSEvent) -> bool {
+        if !self.model.admit(event) {
+            return false;
+        }
+        self.sent += 1;
+        if self.sent > self.capacity {
+            return false;
+        }
+        self.delivered += 1;
+        true
+    }
+}
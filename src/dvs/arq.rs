@@ -0,0 +1,159 @@
+use crate::dvs::packet::{Packet, Packetizer, PacketizerOptions};
+use crate::dvs::rng::SplitMix64;
+use crate::dvs::DVSEvent;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+/*
+This file implements a retransmission (ARQ) simulator. Events are packetized, and each packet is sent once
+its last event has happened. Each transmission is lost with a given probability. The receiver reports a
+lost packet with a NACK, so it is retransmitted one round trip after the previous attempt, up to a retry
+limit, after which the packet is lost for good. NACKs themselves are assumed to arrive.
+
+Events are re-emitted in order of arrival, together with their arrival time, so the effective latency of
+each event (the time waiting for its packet to fill, plus half a round trip per successful transmission,
+plus a round trip per retransmission) can be recorded and compared with FEC or plain loss.
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ArqOptions {
+    // Probability of each transmission being lost
+    pub loss_probability: f64,
+    // Round trip time of the link, in microseconds
+    pub rtt_us: i64,
+    // Retransmissions of a packet before it is given up on
+    pub max_retries: u32,
+    pub seed: u64,
+    pub packet: PacketizerOptions,
+}
+
+impl Default for ArqOptions {
+    fn default() -> Self {
+        ArqOptions {
+            loss_probability: 0.1,
+            rtt_us: 20_000,
+            max_retries: 3,
+            seed: 0,
+            packet: PacketizerOptions::default(),
+        }
+    }
+}
+
+// An event that reached the receiver
+#[derive(Debug, Copy, Clone)]
+pub struct ArqDelivery {
+    pub event: DVSEvent,
+    // Arrival time at the receiver, in microseconds
+    pub arrival: i64,
+    // Transmissions of the event's packet, including the successful one
+    pub attempts: u32,
+}
+
+impl ArqDelivery {
+    pub fn latency_us(&self) -> i64 {
+        self.arrival - self.event.timestamp
+    }
+}
+
+pub struct Arq {
+    options: ArqOptions,
+    rng: SplitMix64,
+    packetizer: Packetizer,
+    ready: Vec<Packet>,
+    // Packets in flight, by arrival time and sequence number
+    arrivals: BinaryHeap<Reverse<(i64, u32)>>,
+    in_flight: HashMap<u32, (Packet, u32)>,
+    pub packets_sent: u64,
+    pub retransmissions: u64,
+    pub packets_lost: u64,
+    pub events_lost: u64,
+    pub events_delivered: u64,
+    pub latency_total_us: i64,
+    pub latency_max_us: i64,
+}
+
+impl Arq {
+    pub fn new(options: ArqOptions) -> Self {
+        Self {
+            options,
+            rng: SplitMix64::new(options.seed),
+            packetizer: Packetizer::new(options.packet),
+            ready: Vec::new(),
+            arrivals: BinaryHeap::new(),
+            in_flight: HashMap::new(),
+            packets_sent: 0,
+            retransmissions: 0,
+            packets_lost: 0,
+            events_lost: 0,
+            events_delivered: 0,
+            latency_total_us: 0,
+            latency_max_us: 0,
+        }
+    }
+
+    pub fn mean_latency_us(&self) -> f64 {
+        self.latency_total_us as f64 / self.events_delivered.max(1) as f64
+    }
+
+    // Simulates the transmissions of a packet, scheduling its arrival unless all of them are lost
+    fn transmit(&mut self, packet: Packet) {
+        let sent = packet.events.last().map_or(packet.base_timestamp, |event| event.timestamp);
+        self.packets_sent += 1;
+        for attempt in 0..=self.options.max_retries {
+            if attempt > 0 {
+                self.retransmissions += 1;
+            }
+            if !self.rng.chance(self.options.loss_probability) {
+                let arrival = sent + self.options.rtt_us / 2 + attempt as i64 * self.options.rtt_us;
+                self.arrivals.push(Reverse((arrival, packet.sequence)));
+                self.in_flight.insert(packet.sequence, (packet, attempt + 1));
+                return;
+            }
+        }
+        self.packets_lost += 1;
+        self.events_lost += packet.events.len() as u64;
+    }
+
+    fn transmit_ready(&mut self) {
+        for packet in std::mem::take(&mut self.ready) {
+            self.transmit(packet);
+        }
+    }
+
+    // Passes the events of packets that have arrived by the given time to out
+    fn deliver(&mut self, until: i64, out: &mut impl FnMut(ArqDelivery)) {
+        while let Some(&Reverse((arrival, sequence))) = self.arrivals.peek() {
+            if arrival > until {
+                break;
+            }
+            self.arrivals.pop();
+            let Some((packet, attempts)) = self.in_flight.remove(&sequence) else {
+                continue;
+            };
+            for event in packet.events {
+                let delivery = ArqDelivery { event, arrival, attempts };
+                self.events_delivered += 1;
+                self.latency_total_us += delivery.latency_us();
+                self.latency_max_us = self.latency_max_us.max(delivery.latency_us());
+                out(delivery);
+            }
+        }
+    }
+
+    // Sends the next event of a time-ordered stream, passing any events that have arrived to out
+    pub fn process(&mut self, event: DVSEvent, out: &mut impl FnMut(ArqDelivery)) {
+        let ready = &mut self.ready;
+        self.packetizer.process(event, &mut |packet| ready.push(packet));
+        self.transmit_ready();
+        // Packets sent from now on can't arrive before this event happened
+        self.deliver(event.timestamp, out);
+    }
+
+    // Sends the last, partially filled packet and passes all events still in flight to out
+    pub fn finish(&mut self, out: &mut impl FnMut(ArqDelivery)) {
+        let ready = &mut self.ready;
+        self.packetizer.finish(&mut |packet| ready.push(packet));
+        self.transmit_ready();
+        self.deliver(i64::MAX, out);
+    }
+}
@@ -0,0 +1,245 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::raw_decoder_evt2::{is_time_high, parse_header_line, Decoded, Metadata, TimeBase};
+use crate::dvs::raw_encoder_evt2::{evt2_header, Evt2Words};
+use crate::dvs::{DVSEvent, TriggerEvent};
+use futures_util::stream::{self, Stream};
+use std::collections::VecDeque;
+use std::future::Future;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/*
+This file implements asynchronous counterparts of the decoder and encoder traits, over tokio's AsyncBufRead and
+AsyncWrite, so async servers can decode and encode event streams without blocking a runtime thread. It is built
+with the async feature.
+The traits mirror DvsRawDecoder and DvsRawEncoder, with the same errors and the same meaning of their results,
+but no Seek bound: the async decoders read their input once from the start, like a network stream. EVT2 is
+implemented by AsyncDVSRawDecoderEvt2 and AsyncDVSRawEncoderEvt2, which share the header parsing and word
+handling of the synchronous EVT2 decoder and encoder. event_stream turns a decoder into a futures Stream of events.
+*/
+
+// Number of events decoded at a time by event_stream
+const BATCH_SIZE: usize = 4096;
+// Bytes the encoder buffers before writing them to its writer
+const WRITE_BUFFER: usize = 64 * 1024;
+
+pub trait AsyncDvsRawDecoder {
+    // Reads the header, returning its lines
+    fn read_header(&mut self) -> impl Future<Output = Result<Vec<String>, DvsError>> + Send;
+    // Returns Ok(None) at the end of the stream
+    fn read_event(&mut self) -> impl Future<Output = Result<Option<DVSEvent>, DvsError>> + Send;
+    // Appends up to max events, returning how many were appended. Returns Ok(0) at the end of the stream
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> impl Future<Output = Result<usize, DvsError>> + Send;
+    // Trigger events decoded since the last call
+    fn take_triggers(&mut self) -> Vec<TriggerEvent>;
+}
+
+pub trait AsyncDvsRawEncoder {
+    fn write_header(&mut self, header: Vec<String>) -> impl Future<Output = Result<(), DvsError>> + Send;
+    // Returns the number of words or records written
+    fn write_event(&mut self, event: DVSEvent) -> impl Future<Output = Result<usize, DvsError>> + Send;
+    fn write_trigger(&mut self, trigger: TriggerEvent) -> impl Future<Output = Result<usize, DvsError>> + Send;
+    // Writes everything buffered and flushes the writer
+    fn flush(&mut self) -> impl Future<Output = Result<(), DvsError>> + Send;
+}
+
+// The events of a decoder whose header was read, as a stream ending after the last event or the first error.
+// Events are decoded in batches. Pin the stream (e.g. with Box::pin) to poll it
+pub fn event_stream<D>(decoder: D) -> impl Stream<Item = Result<DVSEvent, DvsError>> + Send
+where
+    D: AsyncDvsRawDecoder + Send,
+{
+    // The decoder, its decoded events not yet returned, and whether the stream has ended
+    stream::unfold((decoder, VecDeque::new(), false), |(mut decoder, mut pending, ended)| async move {
+        if ended {
+            return None;
+        }
+        if pending.is_empty() {
+            let mut events = Vec::with_capacity(BATCH_SIZE);
+            match decoder.read_events_into(&mut events, BATCH_SIZE).await {
+                Ok(_) => pending.extend(events),
+                Err(e) => return Some((Err(e), (decoder, pending, true))),
+            }
+        }
+        let event = pending.pop_front()?;
+        Some((Ok(event), (decoder, pending, false)))
+    })
+}
+
+// Decodes an EVT2 stream, see raw_decoder_evt2.rs for the format
+pub struct AsyncDVSRawDecoderEvt2<R: AsyncBufRead + Unpin + Send> {
+    reader: R,
+    time: TimeBase,
+    // Bytes read so far, for the offsets of truncation errors
+    offset: u64,
+    // Trigger events decoded since the last take_triggers
+    triggers: Vec<TriggerEvent>,
+}
+
+impl<R: AsyncBufRead + Unpin + Send> AsyncDVSRawDecoderEvt2<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader, time: TimeBase::default(), offset: 0, triggers: Vec::new() }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Reads the next 32-bit word, or None at the end of the stream. Fails with DvsError::Truncated if the stream
+    // ends part way through a word
+    async fn read_word(&mut self) -> Result<Option<u32>, DvsError> {
+        let mut word = [0u8; 4];
+        let mut filled = 0;
+        while filled < word.len() {
+            match self.reader.read(&mut word[filled..]).await? {
+                0 if filled == 0 => return Ok(None),
+                0 => return Err(DvsError::Truncated { offset: self.offset }),
+                n => filled += n,
+            }
+        }
+        self.offset += word.len() as u64;
+        Ok(Some(u32::from_le_bytes(word)))
+    }
+}
+
+impl<R: AsyncBufRead + Unpin + Send> AsyncDvsRawDecoder for AsyncDVSRawDecoderEvt2<R> {
+    // Reads the '%' lines of the header, then skips the words before the first EVT_TIME_HIGH word
+    async fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        let mut header = Vec::new();
+        let mut metadata = Metadata::default();
+        while self.reader.fill_buf().await?.first() == Some(&b'%') {
+            let mut line = String::new();
+            self.offset += self.reader.read_line(&mut line).await? as u64;
+            tracing::trace!(?line, "header");
+            let end = parse_header_line(&line[1..], &mut metadata)?;
+            header.push(line);
+            if end {
+                break;
+            }
+        }
+        metadata.log_geometry();
+
+        // A stream without any EVT_TIME_HIGH word has no events
+        while let Some(word) = self.read_word().await? {
+            if is_time_high(word) {
+                self.time.current_time_base = ((word & 0x0FFF_FFFF) as u64) << 6;
+                break;
+            }
+        }
+        Ok(header)
+    }
+
+    async fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        while let Some(word) = self.read_word().await? {
+            match self.time.decode(word) {
+                Decoded::Event(event) => return Ok(Some(event)),
+                Decoded::Trigger(trigger) => self.triggers.push(trigger),
+                Decoded::TimeHigh | Decoded::Skipped => {}
+            }
+        }
+        Ok(None)
+    }
+
+    // Decodes the whole words of the reader's buffer at a time, like the synchronous decoder
+    async fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        let mut count = 0;
+        while count < max {
+            let buffer = self.reader.fill_buf().await?;
+            if buffer.is_empty() {
+                break;
+            }
+            if buffer.len() < 4 {
+                // A word straddles the end of the buffer
+                match self.read_event().await? {
+                    Some(event) => {
+                        events.push(event);
+                        count += 1;
+                    }
+                    None => break,
+                }
+                continue;
+            }
+
+            let mut consumed = 0;
+            for word in buffer.chunks_exact(4) {
+                consumed += 4;
+                match self.time.decode(u32::from_le_bytes([word[0], word[1], word[2], word[3]])) {
+                    Decoded::Event(event) => {
+                        events.push(event);
+                        count += 1;
+                        if count == max {
+                            break;
+                        }
+                    }
+                    Decoded::Trigger(trigger) => self.triggers.push(trigger),
+                    Decoded::TimeHigh | Decoded::Skipped => {}
+                }
+            }
+            self.reader.consume(consumed);
+            self.offset += consumed as u64;
+        }
+        Ok(count)
+    }
+
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        std::mem::take(&mut self.triggers)
+    }
+}
+
+// Encodes an EVT2 stream, see raw_encoder_evt2.rs for the format. Words are buffered and written once the
+// buffer is full, or on flush
+pub struct AsyncDVSRawEncoderEvt2<W: AsyncWrite + Unpin + Send> {
+    writer: W,
+    words: Evt2Words,
+    buffer: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin + Send> AsyncDVSRawEncoderEvt2<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer, words: Evt2Words::default(), buffer: Vec::with_capacity(WRITE_BUFFER) }
+    }
+
+    // Returns the writer. Call flush first, or the buffered words are lost
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    async fn write_buffer(&mut self) -> Result<(), DvsError> {
+        self.writer.write_all(&self.buffer).await?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    async fn write_if_full(&mut self) -> Result<(), DvsError> {
+        if self.buffer.len() >= WRITE_BUFFER {
+            self.write_buffer().await?;
+        }
+        Ok(())
+    }
+}
+
+impl<W: AsyncWrite + Unpin + Send> AsyncDvsRawEncoder for AsyncDVSRawEncoderEvt2<W> {
+    async fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        for line in evt2_header(header) {
+            self.buffer.extend_from_slice(line.as_bytes());
+        }
+        self.write_if_full().await
+    }
+
+    async fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        let written = self.words.write_event(&mut self.buffer, event)?;
+        self.write_if_full().await?;
+        Ok(written)
+    }
+
+    async fn write_trigger(&mut self, trigger: TriggerEvent) -> Result<usize, DvsError> {
+        let written = self.words.write_trigger(&mut self.buffer, trigger)?;
+        self.write_if_full().await?;
+        Ok(written)
+    }
+
+    async fn flush(&mut self) -> Result<(), DvsError> {
+        self.write_buffer().await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,339 @@
+use crate::dvs::compress::{strip_compression_suffix, Compression};
+use crate::dvs::loss::json_number;
+use crate::dvs::EventFormat;
+use std::fmt::Write;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/*
+This file implements batch processing: running the same job on many recordings, e.g. a loss simulation over a
+whole dataset. Inputs are found under a directory (optionally recursively) or by a glob pattern, outputs mirror
+their paths under an output directory, and a pool of worker threads takes the inputs one at a time, so a slow
+file doesn't hold up the others. A failed file doesn't stop the batch: each job's outcome is collected into a
+consolidated report, in input order.
+Glob patterns are matched component by component: * matches any characters but /, ? matches one character,
+and a ** component matches any number of directories.
+*/
+
+// Extensions of the recordings found in directories
+pub const BATCH_EXTENSIONS: [&str; 3] = ["raw", "dat", "delta"];
+
+// Whether an input names a glob pattern rather than a file or directory
+pub fn is_glob(input: &str) -> bool {
+    input.contains(['*', '?'])
+}
+
+// Whether a file name matches a pattern of * and ? wildcards
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) = (pattern.chars().collect(), name.chars().collect());
+    // Position in the pattern after the last *, and the position in the name it was tried at
+    let (mut p, mut n, mut star): (usize, usize, Option<(usize, usize)>) = (0, 0, None);
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                // Let the last * take one more character
+                Some((after_star, tried)) => {
+                    p = after_star;
+                    n = tried + 1;
+                    star = Some((after_star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+// Entries of a directory, sorted by name
+fn read_dir_sorted(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = std::fs::read_dir(dir)?.map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<Vec<_>>>()?;
+    entries.sort();
+    Ok(entries)
+}
+
+// Recordings in a directory, by BATCH_EXTENSIONS, and in its subdirectories if recursive
+fn find_in_dir(dir: &Path, recursive: bool, inputs: &mut Vec<PathBuf>) -> io::Result<()> {
+    for path in read_dir_sorted(dir)? {
+        if path.is_dir() {
+            if recursive {
+                find_in_dir(&path, recursive, inputs)?;
+            }
+            continue;
+        }
+        // Compressed recordings too, e.g. recording.raw.zst
+        let name = path.to_string_lossy();
+        let extension = Path::new(strip_compression_suffix(&name)).extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+        if BATCH_EXTENSIONS.contains(&extension.as_str()) {
+            inputs.push(path);
+        }
+    }
+    Ok(())
+}
+
+// Files under dir matching the remaining components of a glob pattern
+fn find_glob(dir: &Path, components: &[&str], inputs: &mut Vec<PathBuf>) -> io::Result<()> {
+    let Some((&component, rest)) = components.split_first() else {
+        return Ok(());
+    };
+    if component == "**" {
+        // Zero directories, then one more for each subdirectory
+        find_glob(dir, rest, inputs)?;
+        for path in read_dir_sorted(dir)? {
+            if path.is_dir() {
+                find_glob(&path, components, inputs)?;
+            }
+        }
+        return Ok(());
+    }
+    if !is_glob(component) {
+        let path = dir.join(component);
+        match rest.is_empty() {
+            true if path.is_file() => inputs.push(path),
+            false if path.is_dir() => find_glob(&path, rest, inputs)?,
+            _ => {}
+        }
+        return Ok(());
+    }
+    for path in read_dir_sorted(dir)? {
+        let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+        if !glob_match(component, name) {
+            continue;
+        }
+        match rest.is_empty() {
+            true if path.is_file() => inputs.push(path),
+            false if path.is_dir() => find_glob(&path, rest, inputs)?,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Recordings named by an input: the files under a directory, the files matching a glob pattern, or a single file.
+// Fails if nothing is found
+pub fn find_inputs(input: &str, recursive: bool) -> anyhow::Result<Vec<PathBuf>> {
+    let mut inputs = Vec::new();
+    let path = Path::new(input);
+    if is_glob(input) {
+        let (root, pattern) = match path.is_absolute() {
+            true => (PathBuf::from("/"), input.trim_start_matches('/')),
+            false => (PathBuf::from("."), input),
+        };
+        let components: Vec<&str> = pattern.split('/').filter(|component| !component.is_empty() && *component != ".").collect();
+        find_glob(&root, &components, &mut inputs)?;
+        // Relative patterns give relative paths
+        inputs = inputs.into_iter().map(|path| path.strip_prefix(".").map(Path::to_path_buf).unwrap_or(path)).collect();
+    } else if path.is_dir() {
+        find_in_dir(path, recursive, &mut inputs)?;
+    } else if path.is_file() {
+        inputs.push(path.to_path_buf());
+    }
+    if inputs.is_empty() {
+        anyhow::bail!("No recordings found for {}", input);
+    }
+    Ok(inputs)
+}
+
+// The directory an input path is relative to: the directory itself, or the part of a glob pattern before its
+// first wildcard
+pub fn input_root(input: &str) -> PathBuf {
+    if !is_glob(input) {
+        return PathBuf::from(input);
+    }
+    let components: Vec<&str> = input.split('/').take_while(|component| !is_glob(component)).collect();
+    match components.join("/") {
+        root if root.is_empty() && input.starts_with('/') => PathBuf::from("/"),
+        root if root.is_empty() => PathBuf::new(),
+        root => PathBuf::from(root),
+    }
+}
+
+// Output path of an input: its path relative to root, under out_dir, with the extension of the format and the
+// suffix of the compression. The compression suffix of the input is dropped
+pub fn output_path(input: &Path, root: &Path, out_dir: &Path, format: EventFormat, compression: Compression) -> PathBuf {
+    let relative = input.strip_prefix(root).ok().filter(|relative| !relative.as_os_str().is_empty());
+    let relative = relative.unwrap_or_else(|| Path::new(input.file_name().unwrap_or_default())).to_string_lossy();
+    let path = out_dir.join(strip_compression_suffix(&relative)).with_extension(format.extension());
+    PathBuf::from(format!("{}{}", path.display(), compression.suffix()))
+}
+
+// Runs job on every item on a pool of threads (0 for one per core), returning the results in item order
+pub fn run_pool<I, T, F>(items: &[I], workers: usize, job: F) -> Vec<anyhow::Result<T>>
+where
+    I: Sync,
+    T: Send,
+    F: Fn(&I) -> anyhow::Result<T> + Sync,
+{
+    let workers = match workers {
+        0 => std::thread::available_parallelism().map_or(1, |workers| workers.get()),
+        workers => workers,
+    };
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<anyhow::Result<T>>>> = Mutex::new(items.iter().map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..workers.min(items.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else {
+                    break;
+                };
+                let result = job(item);
+                if let Ok(mut results) = results.lock() {
+                    results[index] = Some(result);
+                }
+            });
+        }
+    });
+    let results = results.into_inner().unwrap_or_else(|poisoned| poisoned.into_inner());
+    results.into_iter().map(|result| result.unwrap_or_else(|| Err(anyhow::anyhow!("Job did not finish")))).collect()
+}
+
+// Events in and out of the job on one file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct BatchCounts {
+    pub events_in: u64,
+    pub events_out: u64,
+}
+
+// Outcome of the job on one file
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchEntry {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub counts: BatchCounts,
+    pub seconds: f64,
+    // Why the job failed, if it did
+    pub error: Option<String>,
+}
+
+// Outcomes of a batch, in input order
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BatchReport {
+    pub entries: Vec<BatchEntry>,
+}
+
+impl BatchReport {
+    // Runs job(input, output) on every input on a pool of workers, see run_pool, and collects the outcomes
+    pub fn run<F>(inputs: &[PathBuf], outputs: &[PathBuf], workers: usize, job: F) -> Self
+    where
+        F: Fn(&Path, &Path) -> anyhow::Result<BatchCounts> + Sync,
+    {
+        let pairs: Vec<(&PathBuf, &PathBuf)> = inputs.iter().zip(outputs).collect();
+        let results = run_pool(&pairs, workers, |(input, output)| {
+            let start = Instant::now();
+            let counts = job(input, output);
+            Ok((counts, start.elapsed().as_secs_f64()))
+        });
+        let entries = inputs
+            .iter()
+            .zip(outputs)
+            .zip(results)
+            .map(|((input, output), result)| {
+                let (counts, seconds) = result.unwrap_or_else(|e| (Err(e), 0.0));
+                BatchEntry {
+                    input: input.clone(),
+                    output: output.clone(),
+                    counts: counts.as_ref().copied().unwrap_or_default(),
+                    seconds,
+                    error: counts.err().map(|e| e.to_string()),
+                }
+            })
+            .collect();
+        BatchReport { entries }
+    }
+
+    pub fn failures(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.error.is_some()).count()
+    }
+
+    // Totals of the files that succeeded
+    pub fn totals(&self) -> BatchCounts {
+        self.entries.iter().filter(|entry| entry.error.is_none()).fold(BatchCounts::default(), |totals, entry| BatchCounts {
+            events_in: totals.events_in + entry.counts.events_in,
+            events_out: totals.events_out + entry.counts.events_out,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        let totals = self.totals();
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"files\":{},\"failed\":{},\"events_in\":{},\"events_out\":{},\"entries\":[",
+            self.entries.len(),
+            self.failures(),
+            totals.events_in,
+            totals.events_out
+        );
+        for (i, entry) in self.entries.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let error = entry.error.as_ref().map_or("null".to_string(), |error| json_string(error));
+            let _ = write!(
+                json,
+                "{{\"input\":{},\"output\":{},\"events_in\":{},\"events_out\":{},\"seconds\":{},\"error\":{}}}",
+                json_string(&entry.input.display().to_string()),
+                json_string(&entry.output.display().to_string()),
+                entry.counts.events_in,
+                entry.counts.events_out,
+                json_number(entry.seconds),
+                error
+            );
+        }
+        json.push_str("]}\n");
+        json
+    }
+
+    // One row per file. Errors are quoted, with quotes doubled
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("input,output,events_in,events_out,seconds,error\n");
+        for entry in &self.entries {
+            let error = entry.error.as_ref().map_or(String::new(), |error| format!("\"{}\"", error.replace('"', "\"\"")));
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                entry.input.display(),
+                entry.output.display(),
+                entry.counts.events_in,
+                entry.counts.events_out,
+                entry.seconds,
+                error
+            );
+        }
+        csv
+    }
+
+    // Writes the report as JSON if the path ends in .json, and as CSV otherwise
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let contents = if path.to_lowercase().ends_with(".json") { self.to_json() } else { self.to_csv() };
+        std::fs::write(path, contents)
+    }
+}
+
+// Quotes a string for JSON
+fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
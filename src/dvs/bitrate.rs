@@ -0,0 +1,175 @@
+use crate::dvs::EventFormat;
+use std::time::Duration;
+
+/*
+This file implements the bitrate accounting shared by the loss models, the reports and the adaptive sender:
+the size of events in each format, the bits a number of events take on a link including the headers of the
+packets carrying them, and conversions between bits, durations and bitrates. Durations are in microseconds and
+bitrates in megabits per second, so that one megabit per second is one bit per microsecond.
+RunningBitrate averages the bitrate of a stream sent chunk by chunk, both over the whole stream and over recent
+chunks.
+*/
+
+// Size of IPv4 and UDP headers, in bytes
+pub const UDP_OVERHEAD_BYTES: usize = 28;
+// Largest UDP payload that fits in a 1500 byte Ethernet frame
+pub const UDP_PAYLOAD_BYTES: usize = 1472;
+
+// Typical size of an event in each format, in bits. Vectorized formats are estimates, as their size depends on
+// how many events share a word
+pub fn format_bits_per_event(format: EventFormat) -> f64 {
+    match format {
+        EventFormat::Evt2 => 32.0,
+        // A 64-bit vector word usually carries a few events
+        EventFormat::Evt21 => 32.0,
+        // A 16-bit EVT_ADDR_X word, plus the amortized row and time words
+        EventFormat::Evt3 => 24.0,
+        EventFormat::Dat => 64.0,
+        // About 20 characters per line
+        EventFormat::Csv | EventFormat::Tsv => 160.0,
+        EventFormat::Npy | EventFormat::Npz => 104.0,
+        // A CDR dvs_msgs/Event, padded to 16 bytes
+        EventFormat::Mcap => 128.0,
+        #[cfg(feature = "ros")]
+        EventFormat::Rosbag2 => 128.0,
+        // Delta-encoded timestamps and dictionary-encoded coordinates, before compression
+        #[cfg(feature = "parquet")]
+        EventFormat::Parquet => 40.0,
+        // About 2 bytes for events close to the previous one, see codec.rs
+        EventFormat::Delta => 16.0,
+    }
+}
+
+pub fn bytes_to_bits(bytes: u64) -> f64 {
+    bytes as f64 * 8.0
+}
+
+// Bitrate of sending the given bits in the given time
+pub fn mbps(bits: f64, duration_us: i64) -> f64 {
+    bits / duration_us.max(1) as f64
+}
+
+// Bits sent at the given bitrate in the given time
+pub fn bits_in(mbps: f64, duration_us: i64) -> f64 {
+    mbps * duration_us as f64
+}
+
+// Time the given bits take to send at the given bitrate. Bits that can't be sent take forever
+pub fn transmission_time(bits: f64, mbps: f64) -> Duration {
+    Duration::try_from_secs_f64(bits / (mbps * 1e6)).unwrap_or(Duration::MAX)
+}
+
+// The cost of events on a link: the size of an event in the target format, plus the headers of the packets
+// carrying them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BandwidthBudget {
+    // Bandwidth of the link, in megabits per second
+    pub bandwidth_mbps: f64,
+    // Average size of an event in the target format, in bits
+    pub bits_per_event: f64,
+    // Payload carried by each packet, in bytes, or 0 to ignore packet overhead
+    pub packet_payload_bytes: usize,
+    // Headers added to each packet, in bytes
+    pub packet_overhead_bytes: usize,
+}
+
+impl Default for BandwidthBudget {
+    fn default() -> Self {
+        BandwidthBudget {
+            bandwidth_mbps: 10.0,
+            bits_per_event: 32.0,
+            packet_payload_bytes: UDP_PAYLOAD_BYTES,
+            packet_overhead_bytes: UDP_OVERHEAD_BYTES,
+        }
+    }
+}
+
+impl BandwidthBudget {
+    // A budget for events sent in the given format over UDP
+    pub fn for_format(bandwidth_mbps: f64, format: EventFormat) -> Self {
+        BandwidthBudget {
+            bandwidth_mbps,
+            bits_per_event: format_bits_per_event(format),
+            ..Self::default()
+        }
+    }
+
+    // Bits needed on the link to send the given number of events, including packet headers
+    pub fn bits_for_events(&self, events: usize) -> f64 {
+        let payload_bits = events as f64 * self.bits_per_event;
+        if self.packet_payload_bytes == 0 {
+            return payload_bits;
+        }
+        let packets = (payload_bits / bytes_to_bits(self.packet_payload_bytes as u64)).ceil();
+        payload_bits + packets * bytes_to_bits(self.packet_overhead_bytes as u64)
+    }
+
+    // Cost of one event including its share of packet headers, in bits
+    pub fn effective_bits_per_event(&self) -> f64 {
+        if self.packet_payload_bytes == 0 {
+            return self.bits_per_event;
+        }
+        let overhead_share = self.packet_overhead_bytes as f64 / self.packet_payload_bytes as f64;
+        self.bits_per_event * (1.0 + overhead_share)
+    }
+
+    // Number of events that can be sent in the given time
+    pub fn events_in(&self, duration_us: i64) -> usize {
+        let bits = bits_in(self.bandwidth_mbps, duration_us);
+        if bits.is_infinite() {
+            return usize::MAX;
+        }
+        let mut events = (bits / self.effective_bits_per_event().max(f64::MIN_POSITIVE)).max(0.0) as usize;
+        // The estimate ignores that the last packet may be partly filled
+        while events > 0 && self.bits_for_events(events) > bits {
+            events -= 1;
+        }
+        events
+    }
+
+    // Bitrate of sending the given number of events in the given time
+    pub fn bitrate_mbps(&self, events: usize, duration_us: i64) -> f64 {
+        mbps(self.bits_for_events(events), duration_us)
+    }
+}
+
+// Average bitrate of a stream sent chunk by chunk: the mean over the whole stream, and a moving average
+// weighting recent chunks more
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RunningBitrate {
+    // Weight of the latest chunk in the moving average
+    pub smoothing: f64,
+    // Moving average, in megabits per second, starting from an initial estimate
+    pub smoothed_mbps: f64,
+    bits: f64,
+    duration_us: i64,
+}
+
+impl Default for RunningBitrate {
+    fn default() -> Self {
+        RunningBitrate::new(0.3, 0.0)
+    }
+}
+
+impl RunningBitrate {
+    pub fn new(smoothing: f64, initial_mbps: f64) -> Self {
+        RunningBitrate { smoothing, smoothed_mbps: initial_mbps, bits: 0.0, duration_us: 0 }
+    }
+
+    // Adds a chunk of the given bits sent in the given time
+    pub fn add(&mut self, bits: f64, duration_us: i64) {
+        self.bits += bits;
+        self.duration_us += duration_us.max(0);
+        self.smoothed_mbps += self.smoothing * (mbps(bits, duration_us) - self.smoothed_mbps);
+    }
+
+    // Bits of all chunks so far
+    pub fn bits(&self) -> f64 {
+        self.bits
+    }
+
+    // Bitrate over all chunks so far
+    pub fn mean_mbps(&self) -> f64 {
+        mbps(self.bits, self.duration_us)
+    }
+}
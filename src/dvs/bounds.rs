@@ -0,0 +1,143 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::{header_geometry, DVSEvent, DvsRawDecoder, TriggerEvent};
+use std::io::{BufRead, Read, Seek};
+use std::marker::PhantomData;
+
+/*
+This file implements checking event coordinates against the sensor geometry. Corrupt files can hold events
+outside of the sensor, which would otherwise pass through silently and break consumers that index arrays by
+pixel. Bounds wraps a decoder and either drops those events or fails on the first one. The geometry is read
+from the header of the stream (% geometry, or the width and height options of % format), or can be given if
+the header has none. Streams without a known geometry pass through unchecked.
+*/
+
+// What to do with events outside the sensor
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BoundsMode {
+    Drop,
+    // Fail with DvsError::InvalidData
+    Error,
+}
+
+// Parses "drop" or "error"
+impl std::str::FromStr for BoundsMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "drop" => Ok(BoundsMode::Drop),
+            "error" => Ok(BoundsMode::Error),
+            _ => anyhow::bail!("Unsupported bounds mode '{}'. Expected drop or error", s),
+        }
+    }
+}
+
+// Wraps a decoder, dropping or failing on events outside the sensor geometry
+pub struct Bounds<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> {
+    decoder: D,
+    mode: BoundsMode,
+    // Given with with_geometry, overriding the header
+    fixed_geometry: Option<(u32, u32)>,
+    // Width and height checked against, once the header has been read
+    geometry: Option<(u32, u32)>,
+    // Number of events outside the sensor, dropped or not
+    pub events_out_of_bounds: u64,
+    warned: bool,
+    _reader: PhantomData<R>,
+}
+
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> Bounds<R, D> {
+    pub fn new(decoder: D, mode: BoundsMode) -> Self {
+        Self { decoder, mode, fixed_geometry: None, geometry: None, events_out_of_bounds: 0, warned: false, _reader: PhantomData }
+    }
+
+    // Checks against the given width and height instead of the geometry in the header
+    pub fn with_geometry(mut self, width: u32, height: u32) -> Self {
+        self.fixed_geometry = Some((width, height));
+        self
+    }
+
+    // Width and height checked against, once the header has been read
+    pub fn geometry(&self) -> Option<(u32, u32)> {
+        self.geometry
+    }
+
+    pub fn into_inner(self) -> D {
+        self.decoder
+    }
+
+    // Reports the dropped events once the end of the stream is reached
+    fn warn(&mut self) {
+        if let (Some((width, height)), false) = (self.geometry, self.warned) {
+            if self.events_out_of_bounds > 0 {
+                tracing::warn!(count = self.events_out_of_bounds, width, height, "dropped events outside the sensor");
+            }
+        }
+        self.warned = true;
+    }
+
+    // Whether an event is on the sensor. Events off the sensor fail in BoundsMode::Error
+    fn check(&mut self, event: &DVSEvent) -> anyhow::Result<bool> {
+        let Some((width, height)) = self.geometry else {
+            return Ok(true);
+        };
+        if event.x >= 0 && event.y >= 0 && (event.x as u32) < width && (event.y as u32) < height {
+            return Ok(true);
+        }
+        self.events_out_of_bounds += 1;
+        match self.mode {
+            BoundsMode::Drop => Ok(false),
+            BoundsMode::Error => Err(DvsError::InvalidData(format!(
+                "Event at ({}, {}) at {} us is outside the {}x{} sensor",
+                event.x, event.y, event.timestamp, width, height
+            ))
+            .into()),
+        }
+    }
+}
+
+// Implemented like DvsRawDecoderEnum, so a checked decoder can be used wherever a decoder is expected
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> DvsRawDecoder<R> for Bounds<R, D> {
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        let header = self.decoder.read_header()?;
+        self.geometry = self.fixed_geometry.or_else(|| header_geometry(&header));
+        self.events_out_of_bounds = 0;
+        self.warned = false;
+        Ok(header)
+    }
+
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        while let Some(event) = self.decoder.read_event()? {
+            if self.check(&event)? {
+                return Ok(Some(event));
+            }
+        }
+        self.warn();
+        Ok(None)
+    }
+
+    // Returns Ok(0) only at the end of the stream, even if a whole batch was dropped
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        let before = events.len();
+        while events.len() == before {
+            if self.decoder.read_events_into(events, max)? == 0 {
+                self.warn();
+                return Ok(0);
+            }
+            let mut kept = before;
+            for index in before..events.len() {
+                let event = events[index];
+                if self.check(&event)? {
+                    events[kept] = event;
+                    kept += 1;
+                }
+            }
+            events.truncate(kept);
+        }
+        Ok(events.len() - before)
+    }
+
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        self.decoder.take_triggers()
+    }
+}
@@ -0,0 +1,138 @@
+use crate::dvs::rewind::RewindReader;
+use crate::dvs::{prep_stream_decoder, DvsRawDecoderEnum, FormatHint};
+use std::io::{BufReader, Read};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::time::Duration;
+
+/*
+This file implements live capture from Prophesee cameras, so that the streaming and loss simulation stages can
+run on a camera as well as on recordings. Cameras are driven by the Metavision HAL, whose plugins know how to
+configure each sensor over USB: a small Python program using its bindings (capture_helper.py, embedded in the
+library) opens the camera and writes the raw event data it sends to a pipe, after a header like those of
+Prophesee .raw files giving the encoding (EVT3 for most sensors, EVT2 or EVT2.1 for some) and the geometry.
+The pipe is read like any other stream (see prep_stream_decoder), so the camera is a DvsRawDecoder like a file.
+Capture runs until the given duration has passed, the camera is disconnected, or the capture is dropped, which
+stops the helper. Another program writing the same kind of stream to its stdout can be used instead, e.g. a
+helper built against the C++ HAL.
+Cameras are named by URIs, prophesee:// for the first camera found or prophesee://<serial> for a given one,
+with optional duration=<seconds> and biases=<bias file> parameters, e.g. prophesee://00050423?duration=10.
+*/
+
+// Scheme of the URIs naming cameras
+pub const CAPTURE_SCHEME: &str = "prophesee://";
+// Program streaming the camera, run by python3 unless another command is given
+const HELPER: &str = include_str!("capture_helper.py");
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CaptureOptions {
+    // Serial number of the camera, or None for the first one found
+    pub serial: Option<String>,
+    // Bias file (.bias) to configure the sensor with, or None for the defaults of the camera
+    pub biases: Option<String>,
+    // How long to capture for, or None until the capture is dropped
+    pub duration: Option<Duration>,
+    // Program and arguments to run instead of the embedded helper. The --serial, --biases and --duration
+    // arguments are appended
+    pub command: Option<Vec<String>>,
+}
+
+impl CaptureOptions {
+    // Parses a camera URI, see the top of this file
+    pub fn from_uri(uri: &str) -> anyhow::Result<Self> {
+        let Some(rest) = uri.strip_prefix(CAPTURE_SCHEME) else {
+            anyhow::bail!("Invalid camera {}. Expected {}[serial][?duration=<seconds>&biases=<file>]", uri, CAPTURE_SCHEME);
+        };
+        let (serial, parameters) = rest.split_once('?').unwrap_or((rest, ""));
+        let mut options = CaptureOptions { serial: Some(serial.to_string()).filter(|serial| !serial.is_empty()), ..CaptureOptions::default() };
+        for parameter in parameters.split('&').filter(|parameter| !parameter.is_empty()) {
+            match parameter.split_once('=') {
+                Some(("duration", seconds)) => {
+                    let seconds: f64 = seconds.parse().map_err(|_| anyhow::anyhow!("Invalid capture duration '{}'", seconds))?;
+                    options.duration = Some(Duration::try_from_secs_f64(seconds).map_err(|e| anyhow::anyhow!("Invalid capture duration: {}", e))?);
+                }
+                Some(("biases", path)) => options.biases = Some(path.to_string()),
+                _ => anyhow::bail!("Unsupported camera parameter '{}'. Expected duration or biases", parameter),
+            }
+        }
+        Ok(options)
+    }
+}
+
+// The raw data of a camera, read from the helper program streaming it. Dropping it stops the capture
+pub struct CameraCapture {
+    child: Child,
+    stdout: Option<ChildStdout>,
+}
+
+impl CameraCapture {
+    pub fn open(options: &CaptureOptions) -> anyhow::Result<Self> {
+        let mut command = match options.command.as_deref() {
+            Some([program, args @ ..]) => {
+                let mut command = Command::new(program);
+                command.args(args);
+                command
+            }
+            Some([]) => anyhow::bail!("The capture command is empty"),
+            None => {
+                let mut command = Command::new("python3");
+                command.args(["-c", HELPER]);
+                command
+            }
+        };
+        if let Some(serial) = &options.serial {
+            command.args(["--serial", serial]);
+        }
+        if let Some(biases) = &options.biases {
+            command.args(["--biases", biases]);
+        }
+        if let Some(duration) = options.duration {
+            command.args(["--duration", &duration.as_secs_f64().to_string()]);
+        }
+        // The helper reports errors, such as a missing camera, on stderr
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to start the capture program, which needs python3 and the Metavision SDK: {}", e))?;
+        let stdout = child.stdout.take();
+        Ok(CameraCapture { child, stdout })
+    }
+
+    // Waits for the helper to exit, failing if it did
+    fn finish(&mut self) -> std::io::Result<()> {
+        drop(self.stdout.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            return Err(std::io::Error::other(format!("Capture program failed with {}", status)));
+        }
+        Ok(())
+    }
+}
+
+impl Read for CameraCapture {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(stdout) = self.stdout.as_mut() else {
+            return Ok(0);
+        };
+        match stdout.read(buf)? {
+            // The end of the stream is only reported once the helper has exited, with its failure if it failed
+            0 => self.finish().map(|_| 0),
+            read => Ok(read),
+        }
+    }
+}
+
+impl Drop for CameraCapture {
+    fn drop(&mut self) {
+        if self.stdout.is_some() {
+            let _ = self.child.kill();
+            let _ = self.finish();
+        }
+    }
+}
+
+// Starts capturing from a camera, returning a decoder of its events, positioned after the header. The format is
+// detected from the header written by the helper
+pub fn prep_capture_decoder(options: &CaptureOptions) -> anyhow::Result<DvsRawDecoderEnum<BufReader<RewindReader<CameraCapture>>>> {
+    prep_stream_decoder(CameraCapture::open(options)?, FormatHint::Auto)
+}
@@ -0,0 +1,67 @@
+# Streams the raw events of a Prophesee camera to stdout for dvs::capture, through the Python bindings of the
+# Metavision HAL (Metavision SDK 4). Writes a header like those of Prophesee .raw files, giving the encoding and
+# geometry of the sensor, then the raw event data as the camera sends it, until --duration has passed, the camera
+# is disconnected, or the reader closes the pipe.
+import argparse
+import sys
+import time
+
+import metavision_hal
+
+
+def set_biases(device, path):
+    # Bias files have one "<value> % <name>" line per bias
+    ll_biases = device.get_i_ll_biases()
+    with open(path) as biases:
+        for line in biases:
+            value, _, name = line.partition("%")
+            if value.strip() and name.strip():
+                ll_biases.set(name.strip(), int(value))
+
+
+def encoding(device):
+    # Older plugins don't report it; their sensors send EVT3 unless configured otherwise
+    try:
+        return device.get_i_hw_identification().get_current_data_encoding_format()
+    except AttributeError:
+        return "EVT3"
+
+
+def main():
+    parser = argparse.ArgumentParser()
+    parser.add_argument("--serial", default="")
+    parser.add_argument("--biases")
+    parser.add_argument("--duration", type=float)
+    args = parser.parse_args()
+
+    device = metavision_hal.DeviceDiscovery.open(args.serial)
+    if device is None:
+        sys.exit("No Prophesee camera found" + (" with serial " + args.serial if args.serial else ""))
+    if args.biases:
+        set_biases(device, args.biases)
+    geometry = device.get_i_geometry()
+    width, height = geometry.get_width(), geometry.get_height()
+
+    out = sys.stdout.buffer
+    out.write("% format {};height={};width={}\n% geometry {}x{}\n% end\n".format(encoding(device), height, width, width, height).encode())
+    out.flush()
+
+    stream = device.get_i_events_stream()
+    stream.start()
+    start = time.monotonic()
+    try:
+        while args.duration is None or time.monotonic() - start < args.duration:
+            if stream.wait_next_buffer() < 0:
+                break
+            data = stream.get_latest_raw_data()
+            if data is not None:
+                out.write(data.tobytes())
+                out.flush()
+    except (BrokenPipeError, KeyboardInterrupt):
+        pass
+    finally:
+        stream.stop()
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,95 @@
+use crate::dvs::DVSEvent;
+
+/*
+This file implements chunking, which groups a stream of events into chunks covering fixed windows of chunk_us
+microseconds, as the loss simulation (see loss.rs), the lossy codec (see quantize.rs) and the entropy coding
+statistics (see entropy.rs) process events. Windows are aligned to multiples of chunk_us from timestamp 0,
+whatever the timestamp of the first event, so a recording is chunked the same way whether it starts at 0 or was
+cut from a longer one. Windows without events are skipped. An event earlier than the window being filled, e.g.
+out of order, joins that window rather than reopening an earlier one, so chunks are emitted in stream order.
+
+Chunker takes events one at a time, as filters receive them, and Chunks turns any iterator of events into an
+iterator of chunks.
+*/
+
+// Events of one window
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    // Start of the window, in microseconds
+    pub start_us: i64,
+    pub events: Vec<DVSEvent>,
+}
+
+pub struct Chunker {
+    chunk_us: i64,
+    // The chunk being filled
+    chunk: Chunk,
+}
+
+impl Chunker {
+    pub fn new(chunk_us: i64) -> Self {
+        Chunker { chunk_us: chunk_us.max(1), chunk: Chunk::default() }
+    }
+
+    pub fn chunk_us(&self) -> i64 {
+        self.chunk_us
+    }
+
+    // Adds an event, returning the chunk it completes if it falls after the window being filled
+    pub fn push(&mut self, event: DVSEvent) -> Option<Chunk> {
+        let completed = match self.chunk.events.is_empty() {
+            false if event.timestamp >= self.chunk.start_us + self.chunk_us => Some(self.take()),
+            _ => None,
+        };
+        if self.chunk.events.is_empty() {
+            self.chunk.start_us = event.timestamp.div_euclid(self.chunk_us) * self.chunk_us;
+        }
+        self.chunk.events.push(event);
+        completed
+    }
+
+    // Returns the chunk being filled, if it holds any events, at the end of the stream
+    pub fn finish(&mut self) -> Option<Chunk> {
+        (!self.chunk.events.is_empty()).then(|| self.take())
+    }
+
+    // Events of the chunk being filled
+    pub fn pending(&self) -> &[DVSEvent] {
+        &self.chunk.events
+    }
+
+    // Drops the chunk being filled, to start over
+    pub fn clear(&mut self) {
+        self.chunk.events.clear();
+    }
+
+    // Takes the chunk being filled, leaving an empty one sized like it
+    fn take(&mut self) -> Chunk {
+        let capacity = self.chunk.events.len();
+        std::mem::replace(&mut self.chunk, Chunk { start_us: 0, events: Vec::with_capacity(capacity) })
+    }
+}
+
+// Chunks of the events of an iterator, see chunks
+pub struct Chunks<I> {
+    events: I,
+    chunker: Chunker,
+}
+
+impl<I: Iterator<Item = DVSEvent>> Iterator for Chunks<I> {
+    type Item = Chunk;
+
+    fn next(&mut self) -> Option<Chunk> {
+        for event in self.events.by_ref() {
+            if let Some(chunk) = self.chunker.push(event) {
+                return Some(chunk);
+            }
+        }
+        self.chunker.finish()
+    }
+}
+
+// Groups events into chunks of chunk_us microseconds, holding one chunk at a time
+pub fn chunks<I: IntoIterator<Item = DVSEvent>>(events: I, chunk_us: i64) -> Chunks<I::IntoIter> {
+    Chunks { events: events.into_iter(), chunker: Chunker::new(chunk_us) }
+}
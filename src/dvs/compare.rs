@@ -0,0 +1,284 @@
+use crate::dvs::loss::json_number;
+use crate::dvs::render::{Frame, FrameAccumulator, RenderOptions};
+use crate::dvs::{header_geometry, DVSEvent, DvsRawDecoder};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::io::{self, BufRead, Read, Seek};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+
+/*
+This file implements side by side comparisons of two streams, typically a recording and its copy after a loss
+model, to see what a loss policy drops. Both streams are rendered into frames as in render.rs, and frames
+covering the same time window are put next to each other, the original on the left, separated by a vertical
+bar. A window with events in only one stream is drawn empty on the other side.
+Each composite frame comes with the event counts of both sides and the number of pixels drawn differently,
+which can be written as a CSV or JSON series for plotting next to the video.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 64 * 1024;
+// Width of the bar between the two sides, in pixels
+const SEPARATOR_WIDTH: u32 = 4;
+const SEPARATOR_COLOR: [u8; 3] = [255, 200, 0];
+
+// Events of one time window in both streams
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameComparison {
+    // Start of the window, in microseconds
+    pub start_us: i64,
+    pub original_events: u64,
+    pub lossy_events: u64,
+    // Pixels drawn with different colors on the two sides
+    pub differing_pixels: u64,
+}
+
+impl FrameComparison {
+    // Events of the original missing from the lossy stream, negative if the lossy stream has more
+    pub fn difference(&self) -> i64 {
+        self.original_events as i64 - self.lossy_events as i64
+    }
+
+    // Fraction of the events of the original kept in the lossy stream, 1 for windows without events
+    pub fn kept(&self) -> f64 {
+        match self.original_events {
+            0 => 1.0,
+            original => self.lossy_events as f64 / original as f64,
+        }
+    }
+}
+
+// Comparisons of all the frames of two streams
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComparisonSeries {
+    pub frame_us: i64,
+    pub frames: Vec<FrameComparison>,
+}
+
+impl ComparisonSeries {
+    pub fn original_events(&self) -> u64 {
+        self.frames.iter().map(|frame| frame.original_events).sum()
+    }
+
+    pub fn lossy_events(&self) -> u64 {
+        self.frames.iter().map(|frame| frame.lossy_events).sum()
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = write!(json, "{{\"frame_us\":{},\"frames\":[", self.frame_us);
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"start_us\":{},\"original_events\":{},\"lossy_events\":{},\"difference\":{},\"kept\":{},\"differing_pixels\":{}}}",
+                frame.start_us,
+                frame.original_events,
+                frame.lossy_events,
+                frame.difference(),
+                json_number(frame.kept()),
+                frame.differing_pixels
+            );
+        }
+        json.push_str("]}\n");
+        json
+    }
+
+    // One row per frame, after a "#" comment with the frame length
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        let _ = writeln!(csv, "# frame_us: {}", self.frame_us);
+        csv.push_str("start_us,original_events,lossy_events,difference,kept,differing_pixels\n");
+        for frame in &self.frames {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{}",
+                frame.start_us,
+                frame.original_events,
+                frame.lossy_events,
+                frame.difference(),
+                frame.kept(),
+                frame.differing_pixels
+            );
+        }
+        csv
+    }
+
+    // Writes the series as JSON if the path ends in .json, and as CSV otherwise
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let contents = if path.to_lowercase().ends_with(".json") { self.to_json() } else { self.to_csv() };
+        std::fs::write(path, contents)
+    }
+}
+
+// Puts two frames next to each other, left then right, separated by a bar
+pub fn side_by_side(left: &Frame, right: &Frame) -> Frame {
+    let width = left.width + SEPARATOR_WIDTH + right.width;
+    let height = left.height.max(right.height);
+    let mut composite = Frame::new(left.start_us, width, height, SEPARATOR_COLOR);
+    let row_bytes = width as usize * 3;
+    for (offset, frame) in [(0, left), (left.width + SEPARATOR_WIDTH, right)] {
+        let frame_row_bytes = frame.width as usize * 3;
+        for (y, row) in frame.pixels.chunks(frame_row_bytes.max(1)).take(frame.height as usize).enumerate() {
+            let start = y * row_bytes + offset as usize * 3;
+            composite.pixels[start..start + frame_row_bytes].copy_from_slice(row);
+        }
+    }
+    composite.events = left.events + right.events;
+    composite
+}
+
+// Number of pixels of the area both frames cover that have different colors
+fn differing_pixels(left: &Frame, right: &Frame) -> u64 {
+    let (width, height) = (left.width.min(right.width) as usize, left.height.min(right.height) as usize);
+    let mut count = 0;
+    for y in 0..height {
+        let left_row = &left.pixels[y * left.width as usize * 3..][..width * 3];
+        let right_row = &right.pixels[y * right.width as usize * 3..][..width * 3];
+        count += left_row.chunks(3).zip(right_row.chunks(3)).filter(|(a, b)| a != b).count() as u64;
+    }
+    count
+}
+
+// Frames of one stream, rendered as they are asked for
+struct FrameStream<'a, R: Read + BufRead + Seek, D: DvsRawDecoder<R>> {
+    decoder: &'a mut D,
+    accumulator: FrameAccumulator,
+    ready: VecDeque<Frame>,
+    events: Vec<DVSEvent>,
+    ended: bool,
+    width: u32,
+    height: u32,
+    background: [u8; 3],
+    _reader: PhantomData<R>,
+}
+
+impl<'a, R: Read + BufRead + Seek, D: DvsRawDecoder<R>> FrameStream<'a, R, D> {
+    // Reads the header of the stream
+    fn open(decoder: &'a mut D, options: RenderOptions) -> anyhow::Result<Self> {
+        let header = decoder.read_header()?;
+        let Some((width, height)) = options.geometry.or_else(|| header_geometry(&header)) else {
+            anyhow::bail!("Rendering needs the sensor geometry, which the header doesn't give");
+        };
+        Ok(FrameStream {
+            decoder,
+            accumulator: FrameAccumulator::new(options, width, height),
+            ready: VecDeque::new(),
+            events: Vec::with_capacity(BATCH_SIZE),
+            ended: false,
+            width,
+            height,
+            background: options.colormap.colors().0,
+            _reader: PhantomData,
+        })
+    }
+
+    // Start of the next frame, or None once the stream has ended
+    fn peek_start(&mut self) -> anyhow::Result<Option<i64>> {
+        while self.ready.is_empty() && !self.ended {
+            self.events.clear();
+            let ready = &mut self.ready;
+            if self.decoder.read_events_into(&mut self.events, BATCH_SIZE)? == 0 {
+                self.accumulator.finish(&mut |frame| ready.push_back(frame));
+                self.ended = true;
+            }
+            for event in &self.events {
+                self.accumulator.add(event, &mut |frame| ready.push_back(frame));
+            }
+        }
+        Ok(self.ready.front().map(|frame| frame.start_us))
+    }
+
+    // The next frame if it starts at start_us, or an empty frame
+    fn take(&mut self, start_us: i64) -> Frame {
+        if self.ready.front().is_some_and(|frame| frame.start_us == start_us) {
+            if let Some(frame) = self.ready.pop_front() {
+                return frame;
+            }
+        }
+        Frame::new(start_us, self.width, self.height, self.background)
+    }
+}
+
+// Reads two whole streams, from their headers, and passes their frames side by side to out, in time order.
+// Returns the comparison of each frame
+pub fn compare_frames<R1, D1, R2, D2, F>(original: &mut D1, lossy: &mut D2, options: RenderOptions, mut out: F) -> anyhow::Result<ComparisonSeries>
+where
+    R1: Read + BufRead + Seek,
+    D1: DvsRawDecoder<R1>,
+    R2: Read + BufRead + Seek,
+    D2: DvsRawDecoder<R2>,
+    F: FnMut(Frame) -> anyhow::Result<()>,
+{
+    let mut left = FrameStream::open(original, options)?;
+    let mut right = FrameStream::open(lossy, options)?;
+    let mut series = ComparisonSeries { frame_us: options.frame_us.max(1), frames: Vec::new() };
+    loop {
+        let start_us = match (left.peek_start()?, right.peek_start()?) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => return Ok(series),
+        };
+        let (left_frame, right_frame) = (left.take(start_us), right.take(start_us));
+        series.frames.push(FrameComparison {
+            start_us,
+            original_events: left_frame.events,
+            lossy_events: right_frame.events,
+            differing_pixels: differing_pixels(&left_frame, &right_frame),
+        });
+        out(side_by_side(&left_frame, &right_frame))?;
+    }
+}
+
+// Writes the frames of a comparison to out_dir as frame_<number>.png, see compare_frames. Returns the paths
+// written, in order, and the comparison of each frame
+pub fn compare_png_sequence<R1, D1, R2, D2>(original: &mut D1, lossy: &mut D2, out_dir: &Path, options: RenderOptions) -> anyhow::Result<(Vec<PathBuf>, ComparisonSeries)>
+where
+    R1: Read + BufRead + Seek,
+    D1: DvsRawDecoder<R1>,
+    R2: Read + BufRead + Seek,
+    D2: DvsRawDecoder<R2>,
+{
+    std::fs::create_dir_all(out_dir)?;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let series = compare_frames(original, lossy, options, |frame| {
+        let path = out_dir.join(format!("frame_{:06}.png", paths.len()));
+        frame.write_png(&path)?;
+        paths.push(path);
+        Ok(())
+    })?;
+    Ok((paths, series))
+}
+
+// Encodes the frames of a comparison into a video file, see compare_frames and VideoWriter. Returns the comparison
+// of each frame
+#[cfg(feature = "video")]
+pub fn compare_video<R1, D1, R2, D2>(original: &mut D1, lossy: &mut D2, path: &Path, options: RenderOptions, fps: f64) -> anyhow::Result<ComparisonSeries>
+where
+    R1: Read + BufRead + Seek,
+    D1: DvsRawDecoder<R1>,
+    R2: Read + BufRead + Seek,
+    D2: DvsRawDecoder<R2>,
+{
+    use crate::dvs::render::VideoWriter;
+
+    let mut writer: Option<VideoWriter> = None;
+    let series = compare_frames(original, lossy, options, |frame| {
+        // The size of the frames is only known once the headers have been read
+        if writer.is_none() {
+            writer = Some(VideoWriter::create(path, frame.width, frame.height, fps)?);
+        }
+        match writer.as_mut() {
+            Some(writer) => writer.write_frame(&frame),
+            None => Ok(()),
+        }
+    });
+    // ffmpeg is waited for even if rendering failed, so it doesn't outlive the call
+    let finished = writer.as_mut().map_or(Ok(()), VideoWriter::finish);
+    let series = series?;
+    finished?;
+    Ok(series)
+}
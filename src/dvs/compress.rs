@@ -0,0 +1,280 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/*
+This file implements transparent compression of event files with gzip or zstd, which typically makes them 2-4x
+smaller. prep_file_decoder reads compressed files as if they weren't: they are recognized by the magic bytes at
+their start, whatever their name. prep_file_encoder compresses files whose path ends in .gz or .zst (e.g.
+recording.raw.zst), or with the given compression. Compression needs the compression feature.
+
+Decoders seek while reading the header, and to jump to a timestamp. Decompression only goes forwards, so a seek
+backwards reopens the file and decompresses up to the target again. Seeks to the start of the file, as when
+reading the header, are cheap; jumping around a large compressed file is slow.
+Encoders only seek to rewrite the header of NPY files once all events are written, which can't be done in a
+compressed file, so NPY files can't be written compressed. NPZ archives are written in one go once complete,
+and can.
+*/
+
+// Magic bytes at the start of compressed files
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    // Compression of files named like the given path, by its .gz or .zst suffix
+    pub fn from_path(path: &str) -> Self {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".gz") {
+            Compression::Gzip
+        } else if lower.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    // Suffix added to the names of compressed files
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    // Compression of a file, from its first bytes
+    fn detect(start: &[u8]) -> Self {
+        if start.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if start.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "gzip" | "gz" => Ok(Compression::Gzip),
+            "zstd" | "zst" => Ok(Compression::Zstd),
+            _ => anyhow::bail!("Unsupported compression '{}'. Expected none, gzip or zstd", s),
+        }
+    }
+}
+
+// The path without its compression suffix, e.g. recording.raw for recording.raw.zst
+pub fn strip_compression_suffix(path: &str) -> &str {
+    let suffix = Compression::from_path(path).suffix();
+    &path[..path.len() - suffix.len()]
+}
+
+#[cfg(feature = "compression")]
+fn decompressor(file: File, compression: Compression) -> io::Result<Box<dyn Read + Send>> {
+    Ok(match compression {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(io::BufReader::new(file))),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(file)?),
+    })
+}
+
+#[cfg(not(feature = "compression"))]
+fn decompressor(file: File, compression: Compression) -> io::Result<Box<dyn Read + Send>> {
+    match compression {
+        Compression::None => Ok(Box::new(file)),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Reading compressed files needs the compression feature")),
+    }
+}
+
+#[cfg(feature = "compression")]
+fn compressor(file: File, compression: Compression) -> io::Result<Box<dyn Write + Send>> {
+    Ok(match compression {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(flate2::write::GzEncoder::new(file, flate2::Compression::default())),
+        // Finished when dropped, like the gzip encoder
+        Compression::Zstd => Box::new(zstd::stream::write::Encoder::new(file, 0)?.auto_finish()),
+    })
+}
+
+#[cfg(not(feature = "compression"))]
+fn compressor(file: File, compression: Compression) -> io::Result<Box<dyn Write + Send>> {
+    match compression {
+        Compression::None => Ok(Box::new(file)),
+        _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Writing compressed files needs the compression feature")),
+    }
+}
+
+// A file opened by prep_file_decoder, decompressed as it is read if it is compressed
+pub enum InputFile {
+    Plain(File),
+    Compressed(CompressedReader),
+}
+
+impl InputFile {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let mut file = File::open(path)?;
+        let mut start = [0u8; 4];
+        let mut read = 0;
+        while read < start.len() {
+            match file.read(&mut start[read..])? {
+                0 => break,
+                n => read += n,
+            }
+        }
+        file.seek(SeekFrom::Start(0))?;
+        match Compression::detect(&start[..read]) {
+            Compression::None => Ok(InputFile::Plain(file)),
+            compression => Ok(InputFile::Compressed(CompressedReader {
+                path: path.to_path_buf(),
+                compression,
+                inner: decompressor(file, compression)?,
+                position: 0,
+                len: None,
+            })),
+        }
+    }
+}
+
+impl Read for InputFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            InputFile::Plain(file) => file.read(buf),
+            InputFile::Compressed(reader) => reader.read(buf),
+        }
+    }
+}
+
+impl Seek for InputFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            InputFile::Plain(file) => file.seek(pos),
+            InputFile::Compressed(reader) => reader.seek(pos),
+        }
+    }
+}
+
+// The decompressed contents of a file, seekable by decompressing again, see the top of this file
+pub struct CompressedReader {
+    path: PathBuf,
+    compression: Compression,
+    inner: Box<dyn Read + Send>,
+    // Position in the decompressed contents
+    position: u64,
+    // Length of the decompressed contents, once known
+    len: Option<u64>,
+}
+
+impl CompressedReader {
+    // Decompresses and discards up to count bytes, returning the number skipped
+    fn skip(&mut self, count: u64) -> io::Result<u64> {
+        let skipped = io::copy(&mut (&mut self.inner).take(count), &mut io::sink())?;
+        self.position += skipped;
+        Ok(skipped)
+    }
+}
+
+impl Read for CompressedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for CompressedReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                let len = match self.len {
+                    Some(len) => len,
+                    None => {
+                        self.skip(u64::MAX)?;
+                        self.len = Some(self.position);
+                        self.position
+                    }
+                };
+                len.checked_add_signed(offset)
+            }
+        };
+        let Some(target) = target else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of the file"));
+        };
+        if target < self.position {
+            self.inner = decompressor(File::open(&self.path)?, self.compression)?;
+            self.position = 0;
+        }
+        // Seeking past the end is allowed, like in a file. Reads then return nothing
+        if self.skip(target - self.position)? < target - self.position {
+            self.len = Some(self.position);
+        }
+        Ok(target)
+    }
+}
+
+// A file created by prep_file_encoder, compressed as it is written if a compression is given. Compressed files are
+// completed when dropped
+pub enum OutputFile {
+    Plain(File),
+    // The compressing writer, and the number of bytes written to it
+    Compressed(Box<dyn Write + Send>, u64),
+}
+
+impl OutputFile {
+    pub fn create(path: impl AsRef<Path>, compression: Compression) -> io::Result<Self> {
+        let file = File::create(path)?;
+        match compression {
+            Compression::None => Ok(OutputFile::Plain(file)),
+            compression => Ok(OutputFile::Compressed(compressor(file, compression)?, 0)),
+        }
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputFile::Plain(file) => file.write(buf),
+            OutputFile::Compressed(writer, position) => {
+                let written = writer.write(buf)?;
+                *position += written as u64;
+                Ok(written)
+            }
+        }
+    }
+
+    // Compressed files are flushed at the end of a compressed block, so what was written so far can be decompressed
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputFile::Plain(file) => file.flush(),
+            OutputFile::Compressed(writer, _) => writer.flush(),
+        }
+    }
+}
+
+// Compressed files can only report their position, or seek to where they are
+impl Seek for OutputFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            OutputFile::Plain(file) => file.seek(pos),
+            OutputFile::Compressed(_, position) => match pos {
+                SeekFrom::Start(offset) if offset == *position => Ok(*position),
+                SeekFrom::Current(0) => Ok(*position),
+                _ => Err(io::Error::new(io::ErrorKind::Unsupported, "Compressed files can't seek")),
+            },
+        }
+    }
+}
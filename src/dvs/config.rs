@@ -0,0 +1,157 @@
+use crate::dvs::pipeline::StageConfig;
+use serde::Deserialize;
+
+/*
+This file implements experiment configuration files, which describe a whole run of the command line tool (inputs,
+loss model and parameters, filters and outputs) so that a loss simulation can be repeated exactly, and which the
+tool can write back with every option it resolved to.
+Configuration files are TOML, like pipeline descriptions (see pipeline.rs): options as key = value pairs at the top
+of the file, named like the long command line options without the leading dashes, followed by the filters to run
+as [[stage]] tables, e.g.
+
+file = "recordings/"
+output = "lossy/"
+bandwidth = 10
+loss-type = "gilbert-elliott"
+transform = ["rotate180"]
+
+[[stage]]
+type = "hot-pixels"
+threshold = 1000
+
+Values are booleans, numbers, strings or arrays of them. Which options exist, and what their values mean, is up
+to the user of the configuration, e.g. the command line tool.
+*/
+
+// Value of an option, as written in the file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigValue {
+    Bool(bool),
+    // Kept as written
+    Number(String),
+    String(String),
+    // Items of an array, unquoted
+    Array(Vec<String>),
+}
+
+impl ConfigValue {
+    // Guesses the type of a value given as text, e.g. on a command line: booleans, then numbers, then strings
+    pub fn from_text(text: &str) -> Self {
+        match text {
+            "true" => ConfigValue::Bool(true),
+            "false" => ConfigValue::Bool(false),
+            _ if is_number(text) => ConfigValue::Number(text.to_string()),
+            _ => ConfigValue::String(text.to_string()),
+        }
+    }
+
+    // The value as text, one item per element for arrays
+    pub fn items(&self) -> Vec<String> {
+        match self {
+            ConfigValue::Bool(value) => vec![value.to_string()],
+            ConfigValue::Number(value) | ConfigValue::String(value) => vec![value.clone()],
+            ConfigValue::Array(items) => items.clone(),
+        }
+    }
+
+    // The value of an option read from the file, or None for tables, dates and nested arrays
+    fn from_toml(value: toml::Value) -> Option<Self> {
+        match value {
+            toml::Value::Boolean(value) => Some(ConfigValue::Bool(value)),
+            toml::Value::Integer(value) => Some(ConfigValue::Number(value.to_string())),
+            toml::Value::Float(value) => Some(ConfigValue::Number(value.to_string())),
+            toml::Value::String(value) => Some(ConfigValue::String(value)),
+            toml::Value::Array(items) => items
+                .into_iter()
+                .map(|item| match ConfigValue::from_toml(item)? {
+                    ConfigValue::Array(_) => None,
+                    value => value.items().pop(),
+                })
+                .collect::<Option<Vec<String>>>()
+                .map(ConfigValue::Array),
+            _ => None,
+        }
+    }
+
+    fn to_toml(&self) -> toml::Value {
+        match self {
+            ConfigValue::Bool(value) => toml::Value::Boolean(*value),
+            ConfigValue::Number(value) => match (value.parse::<i64>(), value.parse::<f64>()) {
+                (Ok(value), _) => toml::Value::Integer(value),
+                (_, Ok(value)) => toml::Value::Float(value),
+                _ => toml::Value::String(value.clone()),
+            },
+            ConfigValue::String(value) => toml::Value::String(value.clone()),
+            ConfigValue::Array(items) => toml::Value::Array(items.iter().map(|item| ConfigValue::from_text(item).to_toml()).collect()),
+        }
+    }
+}
+
+// Whether a value is a TOML number: an integer or a float, with an optional sign
+fn is_number(text: &str) -> bool {
+    let digits = text.strip_prefix(['+', '-']).unwrap_or(text);
+    digits.starts_with(|c: char| c.is_ascii_digit()) && digits.parse::<f64>().is_ok()
+}
+
+// Options and filters of a run, see the top of this file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExperimentConfig {
+    // Options in file order
+    pub options: Vec<(String, ConfigValue)>,
+    pub stages: Vec<StageConfig>,
+}
+
+impl ExperimentConfig {
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let file: ConfigFile = toml::from_str(text)?;
+        let mut config = ExperimentConfig { stages: file.stage, ..ExperimentConfig::default() };
+        for (key, value) in file.options {
+            let Some(value) = ConfigValue::from_toml(value) else {
+                anyhow::bail!("Unsupported value of {}. Expected a boolean, number, string or array of them", key);
+            };
+            config = config.set(&key, value);
+        }
+        Ok(config)
+    }
+
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read config {}: {}", path, e))?;
+        ExperimentConfig::parse(&text).map_err(|e| anyhow::anyhow!("Invalid config {}: {}", path, e))
+    }
+
+    // Sets an option, replacing any earlier value
+    pub fn set(mut self, key: &str, value: ConfigValue) -> Self {
+        self.options.retain(|(existing, _)| existing != key);
+        self.options.push((key.to_string(), value));
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&ConfigValue> {
+        self.options.iter().find(|(existing, _)| existing == key).map(|(_, value)| value)
+    }
+
+    // Writes the options and stages in the format parse reads
+    pub fn to_toml(&self) -> String {
+        let mut file = toml::Table::new();
+        for (key, value) in &self.options {
+            file.insert(key.clone(), value.to_toml());
+        }
+        if !self.stages.is_empty() {
+            file.insert("stage".to_string(), toml::Value::Array(self.stages.iter().map(|stage| toml::Value::Table(stage.to_table())).collect()));
+        }
+        file.to_string()
+    }
+
+    pub fn write(&self, path: &str) -> std::io::Result<()> {
+        std::fs::write(path, self.to_toml())
+    }
+}
+
+// A configuration file: options in file order, then the [[stage]] tables
+#[derive(Deserialize)]
+struct ConfigFile {
+    #[serde(default)]
+    stage: Vec<StageConfig>,
+    #[serde(flatten)]
+    options: toml::Table,
+}
@@ -0,0 +1,157 @@
+use crate::dvs::raw_decoder_csv::CsvOptions;
+use crate::dvs::{prep_encoder, prep_file_decoder, prep_file_encoder, prep_stream_decoder, DvsRawDecoder, DvsRawDecoderEnum, DvsRawEncoder, DVSEvent, EventFormat, FormatHint, TriggerEvent};
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read, Seek, Write};
+
+/*
+This file implements conversion between event formats. Events are streamed from a decoder to an encoder
+in batches, so files of any size can be converted without holding all of their events in memory.
+A progress callback is invoked after each batch with the totals so far. Trigger events are carried over too,
+interleaved with the events by timestamp, to the formats that can store them.
+
+Cutting converts only a time window of a recording. EVT2 and EVT3 inputs seek to the start of the window,
+and decoding stops at the first event past its end, so events are assumed to be in time order. The encoders
+derive the EVT_TIME_HIGH words of the output from the event timestamps, so the cut is valid on its own.
+*/
+
+// Number of events decoded before they are passed to the encoder
+const BATCH_SIZE: usize = 64 * 1024;
+
+// Totals of a conversion, passed to the progress callback and returned once it completes
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TranscodeProgress {
+    // Events decoded and encoded
+    pub events: u64,
+    // Words (or records) written by the encoder, excluding the header
+    pub words: u64,
+}
+
+// Converts a stream of one format to another. The input format is detected from the stream if the hint is
+// FormatHint::Auto. The header of the input is carried over to the output
+pub fn transcode<R, W, F>(reader: R, writer: W, from: FormatHint, to: EventFormat, progress: F) -> anyhow::Result<TranscodeProgress>
+where
+    R: Read,
+    W: Write + Seek,
+    F: FnMut(TranscodeProgress),
+{
+    let mut decoder = prep_stream_decoder(reader, from)?;
+    let mut encoder = prep_encoder(writer, to, CsvOptions::default())?;
+    transcode_with(&mut decoder, &mut encoder, progress)
+}
+
+// Converts a file to the given format, detecting the input format from the file's contents
+pub fn transcode_file<F>(input_path: &str, output_path: &str, to: EventFormat, progress: F) -> anyhow::Result<TranscodeProgress>
+where
+    F: FnMut(TranscodeProgress),
+{
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(input_path)?;
+    let mut encoder = prep_file_encoder::<std::fs::File>(output_path, to)?;
+    transcode_with(&mut decoder, &mut encoder, progress)
+}
+
+// Streams all events from an initialized decoder to an encoder, then flushes the encoder
+pub fn transcode_with<R, W, D, E, F>(decoder: &mut D, encoder: &mut E, mut progress: F) -> anyhow::Result<TranscodeProgress>
+where
+    R: Read + BufRead + Seek,
+    W: Write + Seek,
+    D: DvsRawDecoder<R>,
+    E: DvsRawEncoder<W>,
+    F: FnMut(TranscodeProgress),
+{
+    let _span = tracing::trace_span!("transcode").entered();
+    let header = decoder.read_header()?;
+    encoder.write_header(header)?;
+
+    let mut totals = TranscodeProgress::default();
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    let mut triggers: VecDeque<TriggerEvent> = VecDeque::new();
+    loop {
+        events.clear();
+        let count = decoder.read_events_into(&mut events, BATCH_SIZE)?;
+        triggers.extend(decoder.take_triggers());
+        if count == 0 {
+            break;
+        }
+        totals.words += write_with_triggers(encoder, &events, &mut triggers)?;
+        totals.events += count as u64;
+        progress(totals);
+    }
+    totals.words += write_with_triggers(encoder, &[], &mut triggers)?;
+    encoder.flush()?;
+    Ok(totals)
+}
+
+// Writes a batch of events, preceding each with the pending triggers up to its timestamp, and returns the
+// number of words written. Triggers after the last event stay pending for the next batch. Pass no events at
+// the end of the stream to write the remaining triggers
+pub fn write_with_triggers<W, E>(encoder: &mut E, events: &[DVSEvent], triggers: &mut VecDeque<TriggerEvent>) -> anyhow::Result<u64>
+where
+    W: Write + Seek,
+    E: DvsRawEncoder<W>,
+{
+    let mut words = 0;
+    for event in events {
+        while let Some(trigger) = triggers.front().filter(|trigger| trigger.timestamp <= event.timestamp) {
+            words += encoder.write_trigger(*trigger)? as u64;
+            triggers.pop_front();
+        }
+        words += encoder.write_event(*event)? as u64;
+    }
+    if events.is_empty() {
+        for trigger in triggers.drain(..) {
+            words += encoder.write_trigger(trigger)? as u64;
+        }
+    }
+    Ok(words)
+}
+
+// Converts the events of a file from start_us up to, but not including, end_us to the given format. The
+// header of the input is carried over to the output
+pub fn cut_file<F>(input_path: &str, output_path: &str, to: EventFormat, start_us: i64, end_us: i64, mut progress: F) -> anyhow::Result<TranscodeProgress>
+where
+    F: FnMut(TranscodeProgress),
+{
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(input_path)?;
+    let mut encoder = prep_file_encoder::<std::fs::File>(output_path, to)?;
+    encoder.write_header(decoder.read_header()?)?;
+
+    let mut totals = TranscodeProgress::default();
+    read_window(&mut decoder, start_us, end_us, |events| {
+        for event in events {
+            totals.words += encoder.write_event(*event)? as u64;
+        }
+        totals.events += events.len() as u64;
+        progress(totals);
+        Ok(())
+    })?;
+    encoder.flush()?;
+    Ok(totals)
+}
+
+// Passes the events of a time window to f in batches, seeking to the start of the window if the decoder
+// supports it, and reading on from the current position otherwise
+pub(crate) fn read_window<R, F>(decoder: &mut DvsRawDecoderEnum<R>, start_us: i64, end_us: i64, mut f: F) -> anyhow::Result<()>
+where
+    R: Read + BufRead + Seek,
+    F: FnMut(&[DVSEvent]) -> anyhow::Result<()>,
+{
+    if matches!(decoder, DvsRawDecoderEnum::Evt2(_) | DvsRawDecoderEnum::Evt3(_)) {
+        decoder.seek_to_timestamp(start_us)?;
+    }
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            return Ok(());
+        }
+        let past_end = events.iter().position(|event| event.timestamp >= end_us);
+        events.truncate(past_end.unwrap_or(events.len()));
+        events.retain(|event| event.timestamp >= start_us);
+        if !events.is_empty() {
+            f(&events)?;
+        }
+        if past_end.is_some() {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use crate::dvs::rng::mix;
+use crate::dvs::{prep_file_decoder, prep_file_encoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/*
+This file implements reproducible train/val/test partitioning of event recordings.
+Each recording (or fixed-duration time segment of a recording) is assigned to a split from a hash of its
+content mixed with a seed, so the assignment only changes if the data or the seed changes, and not with the
+order or location in which files are processed.
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Split {
+    Train,
+    Val,
+    Test,
+}
+
+impl Split {
+    pub fn name(self) -> &'static str {
+        match self {
+            Split::Train => "train",
+            Split::Val => "val",
+            Split::Test => "test",
+        }
+    }
+}
+
+// Relative sizes of the splits. They don't need to sum to one
+#[derive(Debug, Copy, Clone)]
+pub struct SplitRatios {
+    pub train: f64,
+    pub val: f64,
+    pub test: f64,
+}
+
+impl Default for SplitRatios {
+    fn default() -> Self {
+        SplitRatios {
+            train: 0.8,
+            val: 0.1,
+            test: 0.1,
+        }
+    }
+}
+
+// A recording or segment of a recording, and the split it was assigned to
+#[derive(Debug, Clone)]
+pub struct SplitEntry {
+    pub path: String,
+    // Time window of the segment in microseconds, or None for a whole recording
+    pub window: Option<(i64, i64)>,
+    pub hash: u64,
+    pub split: Split,
+}
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+// FNV-1a hash, which is stable across platforms and releases (unlike std's DefaultHasher)
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+// Hashes the bytes of a file
+pub fn hash_file(path: &str) -> std::io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buffer = [0u8; 64 * 1024];
+    let mut hash = FNV_OFFSET;
+    loop {
+        let n = reader.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(hash);
+        }
+        hash = fnv1a(hash, &buffer[..n]);
+    }
+}
+
+// Hashes the contents of decoded events
+pub fn hash_events(events: &[DVSEvent]) -> u64 {
+    events.iter().fold(FNV_OFFSET, |hash, event| {
+        let hash = fnv1a(hash, &event.timestamp.to_le_bytes());
+        let hash = fnv1a(hash, &event.x.to_le_bytes());
+        let hash = fnv1a(hash, &event.y.to_le_bytes());
+        fnv1a(hash, &[event.polarity])
+    })
+}
+
+// Assigns a content hash to a split
+pub fn assign_split(hash: u64, seed: u64, ratios: &SplitRatios) -> Split {
+    let total = ratios.train + ratios.val + ratios.test;
+    // Uniform value in [0, 1) from the top 53 bits
+    let u = (mix(hash ^ mix(seed)) >> 11) as f64 / (1u64 << 53) as f64 * total;
+    if u < ratios.train {
+        Split::Train
+    } else if u < ratios.train + ratios.val {
+        Split::Val
+    } else {
+        Split::Test
+    }
+}
+
+// Assigns whole recordings to splits
+pub fn partition_recordings(paths: &[String], seed: u64, ratios: &SplitRatios) -> anyhow::Result<Vec<SplitEntry>> {
+    let mut entries: Vec<SplitEntry> = Vec::new();
+    for path in paths {
+        let hash = hash_file(path)?;
+        entries.push(SplitEntry {
+            path: path.clone(),
+            window: None,
+            hash,
+            split: assign_split(hash, seed, ratios),
+        });
+    }
+    Ok(entries)
+}
+
+// Assigns fixed-duration segments of a recording to splits. If cut_dir is given, each segment is also
+// written to <cut_dir>/<split>/<file stem>_<segment index>.<format extension>
+pub fn partition_segments(
+    path: &str,
+    segment_us: i64,
+    seed: u64,
+    ratios: &SplitRatios,
+    cut: Option<(&Path, EventFormat)>,
+) -> anyhow::Result<Vec<SplitEntry>> {
+    if segment_us <= 0 {
+        anyhow::bail!("Segment duration must be positive");
+    }
+    let mut decoder = prep_file_decoder::<BufReader<File>>(path)?;
+    let header = decoder.read_header()?;
+    let stem = Path::new(path).file_stem().and_then(|s| s.to_str()).unwrap_or("segment").to_string();
+
+    let mut entries: Vec<SplitEntry> = Vec::new();
+    let mut segment: Vec<DVSEvent> = Vec::new();
+    let mut segment_index: Option<i64> = None;
+
+    let mut finish_segment = |index: i64, events: &mut Vec<DVSEvent>| -> anyhow::Result<()> {
+        let hash = hash_events(events);
+        let split = assign_split(hash, seed, ratios);
+        let start = index * segment_us;
+        let mut entry_path = path.to_string();
+        if let Some((dir, format)) = cut {
+            let split_dir = dir.join(split.name());
+            fs::create_dir_all(&split_dir)?;
+            let out_path = split_dir.join(format!("{}_{:05}.{}", stem, index, format.extension()));
+            entry_path = out_path.to_string_lossy().into_owned();
+            let mut encoder = prep_file_encoder::<BufWriter<File>>(&entry_path, format)?;
+            encoder.write_header(header.clone())?;
+            for event in events.iter() {
+                encoder.write_event(*event)?;
+            }
+            encoder.flush()?;
+        }
+        entries.push(SplitEntry {
+            path: entry_path,
+            window: Some((start, start + segment_us)),
+            hash,
+            split,
+        });
+        events.clear();
+        Ok(())
+    };
+
+    while let Some(event) = decoder.read_event()? {
+        let index = event.timestamp.div_euclid(segment_us);
+        if let Some(current) = segment_index {
+            if index != current {
+                finish_segment(current, &mut segment)?;
+            }
+        }
+        segment_index = Some(index);
+        segment.push(event);
+    }
+    if let Some(current) = segment_index {
+        finish_segment(current, &mut segment)?;
+    }
+    Ok(entries)
+}
+
+// Writes one manifest per split, <out_dir>/<split>.txt, listing the entries assigned to it as
+// tab-separated path, start and end time in microseconds (empty for whole recordings), and content hash
+pub fn write_manifests(entries: &[SplitEntry], out_dir: &Path) -> std::io::Result<()> {
+    fs::create_dir_all(out_dir)?;
+    for split in [Split::Train, Split::Val, Split::Test] {
+        let mut writer = BufWriter::new(File::create(out_dir.join(format!("{}.txt", split.name())))?);
+        for entry in entries.iter().filter(|entry| entry.split == split) {
+            let (start, end) = match entry.window {
+                Some((start, end)) => (start.to_string(), end.to_string()),
+                None => (String::new(), String::new()),
+            };
+            writeln!(writer, "{}\t{}\t{}\t{:016x}", entry.path, start, end, entry.hash)?;
+        }
+        writer.flush()?;
+    }
+    Ok(())
+}
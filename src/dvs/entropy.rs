@@ -0,0 +1,598 @@
+use crate::dvs::bitrate::{bytes_to_bits, mbps};
+use crate::dvs::chunk::{Chunk, Chunker};
+use crate::dvs::codec::{encode_group, get_varint, put_varint, GROUP_EVENTS};
+use crate::dvs::error::DvsError;
+use crate::dvs::filters::DvsFilter;
+use crate::dvs::loss::json_number;
+use crate::dvs::packet::{Packet, Packetizer, PacketizerOptions};
+use crate::dvs::DVSEvent;
+use std::fmt::Write as _;
+use std::io;
+
+/*
+This file implements an entropy coding stage for packets (see packet.rs), giving the size a stream would take on
+a link if each packet were compressed, as a baseline for the bandwidth experiments. Each packet is compressed on
+its own, so it can still be decoded when others are lost, with a model trained once per recording on its first
+packets and sent to the receiver before streaming:
+  - range: a range coder with a static table of byte frequencies, sent as a u16 per byte value
+  - zstd-dict: zstd with a dictionary of up to 16 KB trained on the packets. Needs the compression feature
+A compressed packet is the length of the packet as a varint, followed by the coded bytes.
+
+EntropyStats is a filter letting events through unchanged, which packetizes each chunk of events on its own and
+records the size of its packets before and after compression, and in the DELTA layout (see codec.rs) for
+comparison. Chunks completed before the model is trained are held back until it is, so every chunk is compressed
+with the same model.
+*/
+
+// Packets the model is trained on by default
+pub const DEFAULT_TRAIN_PACKETS: usize = 256;
+
+// Sum of the frequencies of a range coder model. At most RANGE_BOTTOM, so the range never shrinks to 0
+const MODEL_TOTAL: u32 = 1 << 15;
+// The top byte of the range coder's low end is shifted out once it can't change, or once the range is
+// narrower than RANGE_BOTTOM
+const RANGE_TOP: u32 = 1 << 24;
+const RANGE_BOTTOM: u32 = 1 << 16;
+// Size of a range coder model sent to the receiver
+const MODEL_BYTES: usize = 256 * 2;
+
+#[cfg(feature = "compression")]
+const DICTIONARY_BYTES: usize = 16 * 1024;
+#[cfg(feature = "compression")]
+const ZSTD_LEVEL: i32 = 3;
+
+// Largest decompressed packet, to reject corrupted length prefixes
+const MAX_PACKET_BYTES: usize = 1 << 20;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum EntropyCoding {
+    #[default]
+    Range,
+    #[cfg(feature = "compression")]
+    ZstdDictionary,
+}
+
+impl EntropyCoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            EntropyCoding::Range => "range",
+            #[cfg(feature = "compression")]
+            EntropyCoding::ZstdDictionary => "zstd-dict",
+        }
+    }
+}
+
+impl std::str::FromStr for EntropyCoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "range" => Ok(EntropyCoding::Range),
+            #[cfg(feature = "compression")]
+            "zstd-dict" | "zstd" => Ok(EntropyCoding::ZstdDictionary),
+            #[cfg(not(feature = "compression"))]
+            "zstd-dict" | "zstd" => anyhow::bail!("zstd-dict entropy coding needs the compression feature"),
+            _ => anyhow::bail!("Unsupported entropy coding '{}'. Expected range or zstd-dict", s),
+        }
+    }
+}
+
+// Static byte frequencies of a range coder
+#[derive(Debug, Clone)]
+struct RangeModel {
+    // Byte b covers the values from cumulative[b] up to cumulative[b + 1]
+    cumulative: Vec<u32>,
+    // Byte covering each value below MODEL_TOTAL, for decoding
+    symbols: Vec<u8>,
+}
+
+impl RangeModel {
+    // Frequencies of the bytes of the samples. Every byte keeps a frequency of at least 1, so bytes not seen in
+    // training can still be coded
+    fn train(samples: &[Vec<u8>]) -> Self {
+        let mut counts = [0u64; 256];
+        for sample in samples {
+            for &byte in sample {
+                counts[byte as usize] += 1;
+            }
+        }
+        let mut frequencies = [MODEL_TOTAL / 256; 256];
+        if let Some(total) = std::num::NonZeroU64::new(counts.iter().sum()) {
+            let spare = (MODEL_TOTAL - 256) as u64;
+            for (frequency, count) in frequencies.iter_mut().zip(counts) {
+                *frequency = 1 + (count * spare / total) as u32;
+            }
+            // Rounding down leaves part of the total unused, which goes to the most frequent byte
+            let used: u32 = frequencies.iter().sum();
+            let most = (0..256).max_by_key(|&byte| frequencies[byte]).unwrap_or(0);
+            frequencies[most] += MODEL_TOTAL - used;
+        }
+        let mut cumulative = vec![0u32; 257];
+        let mut symbols = Vec::with_capacity(MODEL_TOTAL as usize);
+        for (byte, frequency) in frequencies.iter().enumerate() {
+            cumulative[byte + 1] = cumulative[byte] + frequency;
+            symbols.extend(std::iter::repeat_n(byte as u8, *frequency as usize));
+        }
+        RangeModel { cumulative, symbols }
+    }
+
+    // Start and size of the values covered by a byte
+    fn interval(&self, byte: u8) -> (u32, u32) {
+        let start = self.cumulative[byte as usize];
+        (start, self.cumulative[byte as usize + 1] - start)
+    }
+
+    fn encode(&self, input: &[u8], out: &mut Vec<u8>) {
+        put_varint(out, input.len() as u64);
+        let (mut low, mut range) = (0u32, u32::MAX);
+        for &byte in input {
+            let (start, size) = self.interval(byte);
+            range /= MODEL_TOTAL;
+            low = low.wrapping_add(start * range);
+            range *= size;
+            // Carryless normalization (Subbotin): shift out the top byte once it is settled, and when the range
+            // gets too narrow first cut it down so that it is
+            loop {
+                if (low ^ low.wrapping_add(range)) >= RANGE_TOP {
+                    if range >= RANGE_BOTTOM {
+                        break;
+                    }
+                    range = low.wrapping_neg() & (RANGE_BOTTOM - 1);
+                }
+                out.push((low >> 24) as u8);
+                low <<= 8;
+                range <<= 8;
+            }
+        }
+        for _ in 0..4 {
+            out.push((low >> 24) as u8);
+            low <<= 8;
+        }
+    }
+
+    fn decode(&self, bytes: &[u8], out: &mut Vec<u8>) -> anyhow::Result<()> {
+        let invalid = || DvsError::InvalidData("Corrupted range coded packet".to_string());
+        let mut pos = 0;
+        let len = get_varint(bytes, &mut pos).ok_or_else(invalid)? as usize;
+        if len > MAX_PACKET_BYTES {
+            return Err(invalid().into());
+        }
+        // Bytes past the end read as 0, and are caught by the length check below
+        let next = |pos: &mut usize| {
+            let byte = bytes.get(*pos).copied().unwrap_or(0);
+            *pos += 1;
+            byte as u32
+        };
+        let mut code = 0u32;
+        for _ in 0..4 {
+            code = code << 8 | next(&mut pos);
+        }
+        let (mut low, mut range) = (0u32, u32::MAX);
+        out.reserve(len);
+        for _ in 0..len {
+            range /= MODEL_TOTAL;
+            let value = (code.wrapping_sub(low) / range).min(MODEL_TOTAL - 1);
+            let byte = self.symbols[value as usize];
+            let (start, size) = self.interval(byte);
+            low = low.wrapping_add(start * range);
+            range *= size;
+            loop {
+                if (low ^ low.wrapping_add(range)) >= RANGE_TOP {
+                    if range >= RANGE_BOTTOM {
+                        break;
+                    }
+                    range = low.wrapping_neg() & (RANGE_BOTTOM - 1);
+                }
+                code = code << 8 | next(&mut pos);
+                low <<= 8;
+                range <<= 8;
+            }
+            out.push(byte);
+        }
+        // The decoder reads exactly the bytes the encoder wrote
+        if pos != bytes.len() {
+            return Err(invalid().into());
+        }
+        Ok(())
+    }
+}
+
+enum Model {
+    Range(RangeModel),
+    #[cfg(feature = "compression")]
+    Zstd {
+        dictionary_bytes: usize,
+        compressor: zstd::bulk::Compressor<'static>,
+        decompressor: zstd::bulk::Decompressor<'static>,
+    },
+}
+
+// Compresses packets one at a time with a model trained on packets of the same recording
+pub struct EntropyCoder {
+    coding: EntropyCoding,
+    model: Model,
+}
+
+impl EntropyCoder {
+    // Trains a model on sample packets, e.g. the first packets of a recording
+    pub fn train(coding: EntropyCoding, samples: &[Vec<u8>]) -> anyhow::Result<Self> {
+        let model = match coding {
+            EntropyCoding::Range => Model::Range(RangeModel::train(samples)),
+            #[cfg(feature = "compression")]
+            EntropyCoding::ZstdDictionary => {
+                use zstd::zstd_safe::CParameter;
+                // Training needs a few samples. Without them, packets are compressed without a dictionary
+                let dictionary = zstd::dict::from_samples(samples, DICTIONARY_BYTES).unwrap_or_else(|e| {
+                    tracing::warn!(samples = samples.len(), error = %e, "compressing without a dictionary");
+                    Vec::new()
+                });
+                let mut compressor = zstd::bulk::Compressor::with_dictionary(ZSTD_LEVEL, &dictionary)?;
+                let decompressor = zstd::bulk::Decompressor::with_dictionary(&dictionary)?;
+                // The receiver knows the dictionary, and the length comes before the frame
+                compressor.set_parameter(CParameter::DictIdFlag(false))?;
+                compressor.set_parameter(CParameter::ContentSizeFlag(false))?;
+                Model::Zstd { dictionary_bytes: dictionary.len(), compressor, decompressor }
+            }
+        };
+        Ok(EntropyCoder { coding, model })
+    }
+
+    pub fn coding(&self) -> EntropyCoding {
+        self.coding
+    }
+
+    // Size of the model the receiver needs, in bytes
+    pub fn model_bytes(&self) -> usize {
+        match &self.model {
+            Model::Range(_) => MODEL_BYTES,
+            #[cfg(feature = "compression")]
+            Model::Zstd { dictionary_bytes, .. } => *dictionary_bytes,
+        }
+    }
+
+    // Appends the compressed packet to out
+    pub fn compress(&mut self, packet: &[u8], out: &mut Vec<u8>) -> anyhow::Result<()> {
+        match &mut self.model {
+            Model::Range(model) => model.encode(packet, out),
+            #[cfg(feature = "compression")]
+            Model::Zstd { compressor, .. } => {
+                put_varint(out, packet.len() as u64);
+                out.extend_from_slice(&compressor.compress(packet)?);
+            }
+        }
+        Ok(())
+    }
+
+    // Appends the packet decompressed from bytes to out
+    pub fn decompress(&mut self, bytes: &[u8], out: &mut Vec<u8>) -> anyhow::Result<()> {
+        match &mut self.model {
+            Model::Range(model) => model.decode(bytes, out),
+            #[cfg(feature = "compression")]
+            Model::Zstd { decompressor, .. } => {
+                let mut pos = 0;
+                let invalid = || DvsError::InvalidData("Corrupted zstd compressed packet".to_string());
+                let len = get_varint(bytes, &mut pos).ok_or_else(invalid)? as usize;
+                if len > MAX_PACKET_BYTES {
+                    return Err(invalid().into());
+                }
+                let packet = decompressor.decompress(&bytes[pos..], len)?;
+                if packet.len() != len {
+                    return Err(invalid().into());
+                }
+                out.extend_from_slice(&packet);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EntropyOptions {
+    pub coding: EntropyCoding,
+    // Time covered by each chunk, in microseconds
+    pub chunk_us: i64,
+    pub packet: PacketizerOptions,
+    // Packets at the start of the recording the model is trained on
+    pub train_packets: usize,
+}
+
+impl Default for EntropyOptions {
+    fn default() -> Self {
+        EntropyOptions {
+            coding: EntropyCoding::default(),
+            chunk_us: 10_000,
+            packet: PacketizerOptions::default(),
+            train_packets: DEFAULT_TRAIN_PACKETS,
+        }
+    }
+}
+
+// Sizes of the packets of one chunk
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct EntropyChunkStats {
+    // Start of the chunk, in microseconds
+    pub start_us: i64,
+    pub events: u64,
+    pub packets: u64,
+    // In the layout of packet.rs
+    pub packet_bytes: u64,
+    // The events of each packet as DELTA groups, after the 4 byte sequence number
+    pub delta_bytes: u64,
+    pub compressed_bytes: u64,
+}
+
+impl EntropyChunkStats {
+    // Compressed size as a fraction of the packet size
+    pub fn ratio(&self) -> f64 {
+        self.compressed_bytes as f64 / self.packet_bytes.max(1) as f64
+    }
+
+    fn add(&mut self, other: &EntropyChunkStats) {
+        self.events += other.events;
+        self.packets += other.packets;
+        self.packet_bytes += other.packet_bytes;
+        self.delta_bytes += other.delta_bytes;
+        self.compressed_bytes += other.compressed_bytes;
+    }
+}
+
+// Summary of the entropy coding of a stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntropyReport {
+    pub options: EntropyOptions,
+    // Size of the trained model, sent once before the packets
+    pub model_bytes: usize,
+    // Chunks with at least one event, in stream order
+    pub chunks: Vec<EntropyChunkStats>,
+}
+
+impl EntropyReport {
+    // Sums of all chunks, starting at the first
+    pub fn totals(&self) -> EntropyChunkStats {
+        let mut totals = EntropyChunkStats { start_us: self.chunks.first().map_or(0, |chunk| chunk.start_us), ..EntropyChunkStats::default() };
+        for chunk in &self.chunks {
+            totals.add(chunk);
+        }
+        totals
+    }
+
+    // Bitrate of sending the bytes in a chunk, in megabits per second
+    fn chunk_mbps(&self, bytes: u64) -> f64 {
+        mbps(bytes_to_bits(bytes), self.options.chunk_us)
+    }
+
+    pub fn to_json(&self) -> String {
+        let totals = self.totals();
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"coding\":\"{}\",\"chunk_us\":{},\"packet_max_bytes\":{},\"train_packets\":{},\"model_bytes\":{},",
+            self.options.coding.name(),
+            self.options.chunk_us,
+            self.options.packet.max_bytes,
+            self.options.train_packets,
+            self.model_bytes
+        );
+        let _ = write!(
+            json,
+            "\"events\":{},\"packets\":{},\"packet_bytes\":{},\"delta_bytes\":{},\"compressed_bytes\":{},\"ratio\":{},\"chunks\":[",
+            totals.events,
+            totals.packets,
+            totals.packet_bytes,
+            totals.delta_bytes,
+            totals.compressed_bytes,
+            json_number(totals.ratio())
+        );
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"start_us\":{},\"events\":{},\"packets\":{},\"packet_bytes\":{},\"delta_bytes\":{},\"compressed_bytes\":{},\"ratio\":{},\"packet_mbps\":{},\"compressed_mbps\":{}}}",
+                chunk.start_us,
+                chunk.events,
+                chunk.packets,
+                chunk.packet_bytes,
+                chunk.delta_bytes,
+                chunk.compressed_bytes,
+                json_number(chunk.ratio()),
+                json_number(self.chunk_mbps(chunk.packet_bytes)),
+                json_number(self.chunk_mbps(chunk.compressed_bytes))
+            );
+        }
+        json.push_str("]}\n");
+        json
+    }
+
+    // One row per chunk, after "#" comments with the totals and options
+    pub fn to_csv(&self) -> String {
+        let totals = self.totals();
+        let mut csv = String::new();
+        let _ = writeln!(csv, "# coding: {}", self.options.coding.name());
+        let _ = writeln!(csv, "# chunk_us: {}", self.options.chunk_us);
+        let _ = writeln!(csv, "# packet_max_bytes: {}", self.options.packet.max_bytes);
+        let _ = writeln!(csv, "# train_packets: {}", self.options.train_packets);
+        let _ = writeln!(csv, "# model_bytes: {}", self.model_bytes);
+        let _ = writeln!(csv, "# events: {}", totals.events);
+        let _ = writeln!(csv, "# packets: {}", totals.packets);
+        let _ = writeln!(csv, "# packet_bytes: {}", totals.packet_bytes);
+        let _ = writeln!(csv, "# delta_bytes: {}", totals.delta_bytes);
+        let _ = writeln!(csv, "# compressed_bytes: {}", totals.compressed_bytes);
+        let _ = writeln!(csv, "# ratio: {}", totals.ratio());
+        csv.push_str("start_us,events,packets,packet_bytes,delta_bytes,compressed_bytes,ratio,packet_mbps,compressed_mbps\n");
+        for chunk in &self.chunks {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{}",
+                chunk.start_us,
+                chunk.events,
+                chunk.packets,
+                chunk.packet_bytes,
+                chunk.delta_bytes,
+                chunk.compressed_bytes,
+                chunk.ratio(),
+                self.chunk_mbps(chunk.packet_bytes),
+                self.chunk_mbps(chunk.compressed_bytes)
+            );
+        }
+        csv
+    }
+
+    // Writes the report as CSV if the path ends in .csv, and as JSON otherwise
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let contents = if path.to_lowercase().ends_with(".csv") { self.to_csv() } else { self.to_json() };
+        std::fs::write(path, contents)
+    }
+}
+
+// Size of a packet's events as DELTA groups, after its sequence number
+fn delta_len(packet: &Packet) -> u64 {
+    let mut bytes = Vec::new();
+    for group in packet.events.chunks(GROUP_EVENTS) {
+        encode_group(group, &mut bytes);
+    }
+    4 + bytes.len() as u64
+}
+
+// Total compressed size of packets. A packet that fails to compress is counted at its own size
+fn compressed_len(coder: &mut EntropyCoder, packets: &[Vec<u8>]) -> u64 {
+    let mut compressed = Vec::new();
+    packets
+        .iter()
+        .map(|packet| {
+            compressed.clear();
+            match coder.compress(packet, &mut compressed) {
+                Ok(()) => compressed.len() as u64,
+                Err(e) => {
+                    tracing::warn!(bytes = packet.len(), error = %e, "counting a packet uncompressed");
+                    packet.len() as u64
+                }
+            }
+        })
+        .sum()
+}
+
+// Records the sizes of the packets of a stream with and without entropy coding, see the top of this file. Events
+// pass through unchanged
+pub struct EntropyStats {
+    options: EntropyOptions,
+    coder: Option<EntropyCoder>,
+    chunker: Chunker,
+    // Packets the model will be trained on
+    samples: Vec<Vec<u8>>,
+    // Chunks completed before the model was trained, with their encoded packets
+    pending: Vec<(EntropyChunkStats, Vec<Vec<u8>>)>,
+    chunks: Vec<EntropyChunkStats>,
+}
+
+impl EntropyStats {
+    pub fn new(options: EntropyOptions) -> Self {
+        Self {
+            options,
+            coder: None,
+            chunker: Chunker::new(options.chunk_us),
+            samples: Vec::new(),
+            pending: Vec::new(),
+            chunks: Vec::new(),
+        }
+    }
+
+    // Statistics of the chunks completed so far. Chunks waiting for the model to be trained are not included
+    pub fn report(&self) -> EntropyReport {
+        let coding = self.coder.as_ref().map_or(self.options.coding, EntropyCoder::coding);
+        EntropyReport {
+            options: EntropyOptions { coding, ..self.options },
+            model_bytes: self.coder.as_ref().map_or(0, EntropyCoder::model_bytes),
+            chunks: self.chunks.clone(),
+        }
+    }
+
+    // Trains the model on the samples collected so far, and compresses the chunks that were waiting for it
+    fn train(&mut self) {
+        let mut coder = EntropyCoder::train(self.options.coding, &self.samples).unwrap_or_else(|e| {
+            tracing::warn!(coding = self.options.coding.name(), error = %e, "falling back to range coding");
+            EntropyCoder { coding: EntropyCoding::Range, model: Model::Range(RangeModel::train(&self.samples)) }
+        });
+        for (mut stats, packets) in self.pending.drain(..) {
+            stats.compressed_bytes = compressed_len(&mut coder, &packets);
+            self.chunks.push(stats);
+        }
+        self.samples = Vec::new();
+        self.coder = Some(coder);
+    }
+
+    // Packetizes the chunk and records the sizes of its packets
+    fn end_chunk(&mut self, chunk: Chunk) {
+        let mut stats = EntropyChunkStats { start_us: chunk.start_us, events: chunk.events.len() as u64, ..EntropyChunkStats::default() };
+        let mut packetizer = Packetizer::new(self.options.packet);
+        let mut packets = Vec::new();
+        for event in chunk.events {
+            packetizer.process(event, &mut |packet| packets.push(packet));
+        }
+        packetizer.finish(&mut |packet| packets.push(packet));
+        stats.packets = packets.len() as u64;
+        let encoded: Vec<Vec<u8>> = packets.iter().map(Packet::encode).collect();
+        stats.packet_bytes = encoded.iter().map(|packet| packet.len() as u64).sum();
+        stats.delta_bytes = packets.iter().map(delta_len).sum();
+
+        match self.coder.as_mut() {
+            Some(coder) => {
+                stats.compressed_bytes = compressed_len(coder, &encoded);
+                self.chunks.push(stats);
+            }
+            None => {
+                let wanted = self.options.train_packets.saturating_sub(self.samples.len());
+                self.samples.extend(encoded.iter().take(wanted).cloned());
+                self.pending.push((stats, encoded));
+                if self.samples.len() >= self.options.train_packets {
+                    self.train();
+                }
+            }
+        }
+    }
+}
+
+impl DvsFilter for EntropyStats {
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        if let Some(chunk) = self.chunker.push(event) {
+            self.end_chunk(chunk);
+        }
+        out(event);
+    }
+
+    // Completes the last chunk, and trains the model on what there is if the stream was too short
+    fn finish(&mut self, out: &mut dyn FnMut(DVSEvent)) {
+        let _ = out;
+        if let Some(chunk) = self.chunker.finish() {
+            self.end_chunk(chunk);
+        }
+        if self.coder.is_none() {
+            self.train();
+        }
+    }
+
+    // Starts over, training a new model
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        self.coder = None;
+        self.chunker.clear();
+        self.samples.clear();
+        self.pending.clear();
+        self.chunks.clear();
+        Ok(header)
+    }
+
+    fn summary(&self) -> Option<String> {
+        let report = self.report();
+        let totals = report.totals();
+        Some(format!(
+            "Entropy coding ({}) compressed {} packet bytes to {} ({:.1}%, {} as DELTA), with a {} byte model",
+            report.options.coding.name(),
+            totals.packet_bytes,
+            totals.compressed_bytes,
+            totals.ratio() * 100.0,
+            totals.delta_bytes,
+            report.model_bytes
+        ))
+    }
+}
@@ -0,0 +1,68 @@
+use std::io;
+
+/*
+This file implements the error type of the library, so users can tell apart the ways decoding can fail. The
+decoder and encoder traits return Result<_, DvsError>, and the other functions of the library, which return
+anyhow::Result, raise DvsError values inside it. Convert those with DvsError::from to match on them:
+
+    match DvsError::from(error) {
+        DvsError::UnexpectedEof => ...,
+        DvsError::UnsupportedFormat(_) => ...,
+        ...
+    }
+
+The end of a stream is not an error: read_event returns Ok(None) once the input ends cleanly, at a word or
+record boundary. Truncated means the events ended in the middle of one, and UnexpectedEof that the input ended
+before its header did. Wrap a decoder in recover::Recover to keep the events before the cut instead.
+
+Messages don't start with "Error:", which is for whoever reports them to add, as the dvs tool does.
+*/
+
+#[derive(Debug, thiserror::Error)]
+pub enum DvsError {
+    // The input ended before its header or metadata did
+    #[error("Unexpected end of input")]
+    UnexpectedEof,
+    // The input ended in the middle of the event, word or packet starting at this byte offset
+    #[error("Input is truncated, it ends in the middle of an event at byte {offset}")]
+    Truncated { offset: u64 },
+    // A header line could not be parsed
+    #[error("Invalid header line '{}'", line.trim_end())]
+    InvalidHeader { line: String },
+    // The input is not in a format the decoder reads, or uses a feature it doesn't support
+    #[error("{0}")]
+    UnsupportedFormat(String),
+    // The events are corrupt, or can't be represented in the output format
+    #[error("{0}")]
+    InvalidData(String),
+    #[error(transparent)]
+    Io(io::Error),
+    // Errors of the layers around the decoders and encoders, e.g. filters, decompression or the network
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<io::Error> for DvsError {
+    fn from(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::UnexpectedEof => DvsError::UnexpectedEof,
+            io::ErrorKind::InvalidData => DvsError::InvalidData(error.to_string()),
+            _ => DvsError::Io(error),
+        }
+    }
+}
+
+// Recovers the DvsError raised inside an anyhow::Error. I/O errors are classified by their kind, and any other
+// error is kept as it is
+impl From<anyhow::Error> for DvsError {
+    fn from(error: anyhow::Error) -> Self {
+        let error = match error.downcast::<DvsError>() {
+            Ok(error) => return error,
+            Err(error) => error,
+        };
+        match error.downcast::<io::Error>() {
+            Ok(error) => DvsError::from(error),
+            Err(error) => DvsError::Other(error),
+        }
+    }
+}
@@ -0,0 +1,407 @@
+use crate::dvs::packet::Packet;
+use std::collections::BTreeMap;
+
+/*
+This file implements forward error correction (FEC) for event packets. Packets are sent in groups, and
+each group is followed by parity packets computed from its source packets. A receiver that gets at least
+as many packets of a group as it has source packets, in any combination, can recover the missing ones.
+
+Two schemes are supported:
+  XOR: one parity packet, the XOR of the source packets, which recovers any single lost packet
+  Reed-Solomon: any number of parity packets, from a Cauchy matrix over GF(256), which recover as many
+                lost packets as there are parity packets
+Source symbols are the encoded packets prefixed with their length (u16, little endian) and padded with
+zeros to the longest packet of the group, so parity packets are slightly longer than source packets.
+
+Each FEC packet starts with an 8 byte header:
+  group (u32, little endian), index (u8), source packets in the group (u8), parity packets (u8), scheme (u8)
+Indexes below the number of source packets are source packets, carrying an encoded packet unchanged.
+The others are parity packets. The last group of a stream may be shorter; its parity packets give its size.
+*/
+
+pub const FEC_HEADER_BYTES: usize = 8;
+// Bytes a parity packet adds to the longest packet of its group: the FEC header and the length prefix
+pub const FEC_PARITY_OVERHEAD_BYTES: usize = FEC_HEADER_BYTES + 2;
+
+const SCHEME_XOR: u8 = 0;
+const SCHEME_REED_SOLOMON: u8 = 1;
+
+// GF(256) with the polynomial x^8 + x^4 + x^3 + x^2 + 1. Exponents are doubled up to skip a modulo
+const GF_TABLES: ([u8; 512], [u8; 256]) = {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        exp[i + 255] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11D;
+        }
+        i += 1;
+    }
+    (exp, log)
+};
+
+fn gf_mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    let (exp, log) = &GF_TABLES;
+    exp[log[a as usize] as usize + log[b as usize] as usize]
+}
+
+fn gf_inv(a: u8) -> u8 {
+    let (exp, log) = &GF_TABLES;
+    exp[255 - log[a as usize] as usize]
+}
+
+// dst += c * src. Addition in GF(256) is XOR
+fn gf_mul_add(dst: &mut [u8], c: u8, src: &[u8]) {
+    match c {
+        0 => {}
+        1 => dst.iter_mut().zip(src).for_each(|(d, s)| *d ^= s),
+        _ => dst.iter_mut().zip(src).for_each(|(d, s)| *d ^= gf_mul(c, *s)),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FecScheme {
+    Xor,
+    ReedSolomon { parity: u8 },
+}
+
+impl Default for FecScheme {
+    fn default() -> Self {
+        FecScheme::ReedSolomon { parity: 2 }
+    }
+}
+
+impl FecScheme {
+    pub fn parity_packets(&self) -> usize {
+        match self {
+            FecScheme::Xor => 1,
+            FecScheme::ReedSolomon { parity } => *parity as usize,
+        }
+    }
+
+    // Coefficient of source packet j in parity packet i of a group of k source packets. Reed-Solomon uses
+    // the Cauchy matrix 1 / (x_i + y_j) with x_i = k + i and y_j = j, any square submatrix of which is
+    // invertible
+    fn coefficient(&self, i: usize, j: usize, k: usize) -> u8 {
+        match self {
+            FecScheme::Xor => 1,
+            FecScheme::ReedSolomon { .. } => gf_inv(((k + i) ^ j) as u8),
+        }
+    }
+}
+
+// Parses "xor" or "rs:<parity packets>"
+impl std::str::FromStr for FecScheme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (name, parity) = s.split_once(':').unwrap_or((s, ""));
+        match name.trim().to_lowercase().as_str() {
+            "xor" if parity.is_empty() => Ok(FecScheme::Xor),
+            "rs" | "reed-solomon" => match parity.trim().parse::<u8>() {
+                Ok(parity) if parity > 0 => Ok(FecScheme::ReedSolomon { parity }),
+                _ => anyhow::bail!("Invalid number of parity packets in '{}'", s),
+            },
+            _ => anyhow::bail!("Unsupported FEC scheme '{}'. Expected xor or rs:<parity packets>", s),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FecOptions {
+    // Source packets per group
+    pub group_size: usize,
+    pub scheme: FecScheme,
+}
+
+impl Default for FecOptions {
+    fn default() -> Self {
+        FecOptions { group_size: 8, scheme: FecScheme::default() }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FecPacket {
+    pub group: u32,
+    pub index: u8,
+    // Source packets in the group
+    pub sources: u8,
+    pub scheme: Option<FecScheme>,
+    // An encoded packet for source packets, or parity data
+    pub payload: Vec<u8>,
+}
+
+impl FecPacket {
+    pub fn is_parity(&self) -> bool {
+        self.index >= self.sources
+    }
+
+    pub fn encoded_len(&self) -> usize {
+        FEC_HEADER_BYTES + self.payload.len()
+    }
+
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        let (parity, scheme) = match self.scheme {
+            Some(FecScheme::Xor) => (1, SCHEME_XOR),
+            Some(FecScheme::ReedSolomon { parity }) => (parity, SCHEME_REED_SOLOMON),
+            None => (0, SCHEME_XOR),
+        };
+        buf.reserve(self.encoded_len());
+        buf.extend_from_slice(&self.group.to_le_bytes());
+        buf.extend_from_slice(&[self.index, self.sources, parity, scheme]);
+        buf.extend_from_slice(&self.payload);
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut buf);
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<FecPacket> {
+        if bytes.len() < FEC_HEADER_BYTES {
+            anyhow::bail!("FEC packet of {} bytes is shorter than its header", bytes.len());
+        }
+        let scheme = match (bytes[6], bytes[7]) {
+            (0, _) => None,
+            (_, SCHEME_XOR) => Some(FecScheme::Xor),
+            (parity, SCHEME_REED_SOLOMON) => Some(FecScheme::ReedSolomon { parity }),
+            (_, scheme) => anyhow::bail!("Unsupported FEC scheme {}", scheme),
+        };
+        Ok(FecPacket {
+            group: u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            index: bytes[4],
+            sources: bytes[5],
+            scheme,
+            payload: bytes[FEC_HEADER_BYTES..].to_vec(),
+        })
+    }
+}
+
+// Adds parity packets to a stream of packets
+pub struct FecEncoder {
+    options: FecOptions,
+    group: u32,
+    // Encoded source packets of the current group
+    sources: Vec<Vec<u8>>,
+    pub parity_packets: u64,
+}
+
+impl FecEncoder {
+    pub fn new(options: FecOptions) -> anyhow::Result<Self> {
+        let parity = options.scheme.parity_packets();
+        if options.group_size == 0 || options.group_size + parity > 255 {
+            anyhow::bail!("FEC groups need 1 to {} source packets with {} parity packets", 255 - parity, parity);
+        }
+        Ok(Self { options, group: 0, sources: Vec::new(), parity_packets: 0 })
+    }
+
+    // Passes the packet to out as a source packet, followed by the parity packets if it completes a group
+    pub fn process(&mut self, packet: &Packet, out: &mut impl FnMut(FecPacket)) {
+        let payload = packet.encode();
+        out(FecPacket {
+            group: self.group,
+            index: self.sources.len() as u8,
+            sources: self.options.group_size as u8,
+            scheme: Some(self.options.scheme),
+            payload: payload.clone(),
+        });
+        self.sources.push(payload);
+        if self.sources.len() == self.options.group_size {
+            self.close(out);
+        }
+    }
+
+    // Passes the parity packets of the last, partially filled group to out
+    pub fn finish(&mut self, out: &mut impl FnMut(FecPacket)) {
+        if !self.sources.is_empty() {
+            self.close(out);
+        }
+    }
+
+    fn close(&mut self, out: &mut impl FnMut(FecPacket)) {
+        let k = self.sources.len();
+        let length = 2 + self.sources.iter().map(Vec::len).max().unwrap_or(0);
+        let symbols: Vec<Vec<u8>> = self.sources.iter().map(|source| symbol(source, length)).collect();
+        for i in 0..self.options.scheme.parity_packets() {
+            let mut parity = vec![0u8; length];
+            for (j, symbol) in symbols.iter().enumerate() {
+                gf_mul_add(&mut parity, self.options.scheme.coefficient(i, j, k), symbol);
+            }
+            out(FecPacket {
+                group: self.group,
+                index: (k + i) as u8,
+                sources: k as u8,
+                scheme: Some(self.options.scheme),
+                payload: parity,
+            });
+            self.parity_packets += 1;
+        }
+        self.group = self.group.wrapping_add(1);
+        self.sources.clear();
+    }
+}
+
+// A source packet prefixed with its length and padded to the given length
+fn symbol(source: &[u8], length: usize) -> Vec<u8> {
+    let mut symbol = Vec::with_capacity(length);
+    symbol.extend_from_slice(&(source.len() as u16).to_le_bytes());
+    symbol.extend_from_slice(source);
+    symbol.resize(length, 0);
+    symbol
+}
+
+#[derive(Default)]
+struct Group {
+    // Source packets in the group, from the parity packets if any have been received
+    sources: Option<usize>,
+    scheme: Option<FecScheme>,
+    // Received packets by index
+    received: BTreeMap<u8, Vec<u8>>,
+    // Whether all source packets have been received or recovered
+    complete: bool,
+}
+
+impl Group {
+    fn received_sources(&self, k: usize) -> usize {
+        self.received.range(..k.min(255) as u8).count()
+    }
+}
+
+// Recovers lost packets from FEC packets. Groups are kept until window later groups have started
+pub struct FecDecoder {
+    window: usize,
+    groups: BTreeMap<u32, Group>,
+    // Groups before this one have been given up on
+    oldest: u32,
+    pub packets_recovered: u64,
+    pub packets_lost: u64,
+}
+
+impl FecDecoder {
+    pub fn new(window: usize) -> Self {
+        Self { window: window.max(1), groups: BTreeMap::new(), oldest: 0, packets_recovered: 0, packets_lost: 0 }
+    }
+
+    // Handles a received FEC packet, passing source packets and any packets it allows to recover to out.
+    // Packets of groups that have been given up on are ignored
+    pub fn receive(&mut self, packet: FecPacket, out: &mut impl FnMut(Packet)) -> anyhow::Result<()> {
+        if packet.group < self.oldest {
+            return Ok(());
+        }
+        // Parity data holds at least the length prefix of a source packet
+        if packet.is_parity() && packet.payload.len() < 2 {
+            anyhow::bail!("FEC parity packet of {} bytes is shorter than a length prefix", packet.payload.len());
+        }
+        let group = self.groups.entry(packet.group).or_default();
+        if group.complete || group.received.contains_key(&packet.index) {
+            return Ok(());
+        }
+        if packet.is_parity() || group.sources.is_none() {
+            group.sources = Some(packet.sources as usize);
+            group.scheme = packet.scheme;
+        }
+        if !packet.is_parity() {
+            out(Packet::decode(&packet.payload)?);
+        }
+        group.received.insert(packet.index, packet.payload);
+
+        if let (Some(k), Some(scheme)) = (group.sources, group.scheme) {
+            let received_sources = group.received_sources(k);
+            if received_sources == k {
+                group.complete = true;
+            } else if group.received.len() >= k && group.received.len() > received_sources {
+                self.packets_recovered += recover(group, k, scheme, out)? as u64;
+            }
+            if group.complete {
+                group.received.clear();
+            }
+        }
+
+        // Give up on the oldest groups
+        while self.groups.len() > self.window {
+            self.evict();
+        }
+        Ok(())
+    }
+
+    fn evict(&mut self) {
+        if let Some((number, group)) = self.groups.pop_first() {
+            if !group.complete {
+                // Without parity packets, the size of a shorter last group isn't known and is overestimated
+                let k = group.sources.unwrap_or(0);
+                self.packets_lost += k.saturating_sub(group.received_sources(k)) as u64;
+            }
+            self.oldest = number.wrapping_add(1);
+        }
+    }
+
+    // Gives up on all incomplete groups, counting their missing packets as lost
+    pub fn finish(&mut self) {
+        while !self.groups.is_empty() {
+            self.evict();
+        }
+    }
+}
+
+// Recovers the missing source packets of a group from its parity packets, returning the number recovered
+fn recover(group: &mut Group, k: usize, scheme: FecScheme, out: &mut impl FnMut(Packet)) -> anyhow::Result<usize> {
+    let missing: Vec<usize> = (0..k).filter(|j| !group.received.contains_key(&(*j as u8))).collect();
+    let parities: Vec<usize> = group.received.range(k as u8..).map(|(i, _)| *i as usize - k).take(missing.len()).collect();
+    let length = group.received[&((k + parities[0]) as u8)].len();
+
+    // The parity packets minus the contributions of the received source packets leave a system of
+    // equations in the missing ones, with a row of coefficients and a value per parity packet
+    let mut rows: Vec<(Vec<u8>, Vec<u8>)> = Vec::with_capacity(missing.len());
+    for &i in &parities {
+        let mut value = group.received[&((k + i) as u8)].clone();
+        if value.len() != length {
+            anyhow::bail!("Parity packets of a group differ in length");
+        }
+        for (&j, source) in group.received.range(..k as u8) {
+            gf_mul_add(&mut value, scheme.coefficient(i, j as usize, k), &symbol(source, length));
+        }
+        rows.push((missing.iter().map(|&j| scheme.coefficient(i, j, k)).collect(), value));
+    }
+
+    // Gauss-Jordan elimination
+    let n = missing.len();
+    for column in 0..n {
+        let Some(pivot) = (column..n).find(|&row| rows[row].0[column] != 0) else {
+            anyhow::bail!("Parity packets can't recover the group");
+        };
+        rows.swap(column, pivot);
+        let inverse = gf_inv(rows[column].0[column]);
+        let (coefficients, value) = &mut rows[column];
+        coefficients.iter_mut().for_each(|c| *c = gf_mul(*c, inverse));
+        value.iter_mut().for_each(|v| *v = gf_mul(*v, inverse));
+
+        let (pivot_coefficients, pivot_value) = rows[column].clone();
+        for (row, (coefficients, value)) in rows.iter_mut().enumerate() {
+            let factor = coefficients[column];
+            if row != column && factor != 0 {
+                gf_mul_add(coefficients, factor, &pivot_coefficients);
+                gf_mul_add(value, factor, &pivot_value);
+            }
+        }
+    }
+
+    for ((_, symbol), &j) in rows.iter().zip(&missing) {
+        let size = u16::from_le_bytes([symbol[0], symbol[1]]) as usize;
+        let Some(source) = symbol.get(2..2 + size) else {
+            anyhow::bail!("Recovered packet has an invalid length");
+        };
+        out(Packet::decode(source)?);
+        group.received.insert(j as u8, source.to_vec());
+    }
+    group.complete = true;
+    Ok(n)
+}
@@ -0,0 +1,422 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::stats::PolarityCounts;
+use crate::dvs::{set_header_geometry, DVSEvent, DvsRawDecoder, TriggerEvent};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{BufRead, Read, Seek};
+use std::marker::PhantomData;
+
+/*
+This file implements filters processing a stream one event at a time, such as removing noise. A filter is a
+DvsFilter, which passes the events it lets through to a callback, now or later, so filters can drop, change or
+hold back events. Filtered wraps a decoder in a filter, so a filtered decoder can be used wherever a decoder is
+expected, and filters can be chained in a Pipeline, see pipeline.rs.
+HotPixelFilter drops the events of hot pixels, defective pixels that fire far more often than the rest of the
+sensor whatever the scene. It learns the event rate of each pixel over a calibration window at the start of the
+stream, holding those events back, and marks as hot the pixels whose rate is above a multiple of the median rate
+of the active pixels. The events of hot pixels are dropped from the whole stream, including the calibration
+window. Events are assumed to be in time order.
+RoiFilter crops the stream to a region of interest, keeping only the events inside a rectangle. Coordinates are
+made relative to the corner of the rectangle and the geometry in the header becomes its size, so the output
+looks like a recording from a smaller sensor.
+PolarityFilter keeps only ON or only OFF events, or flips the polarity of every event, e.g. for sensors wired
+with the opposite convention. It counts the events of each polarity before and after, see stats.rs.
+*/
+
+// Number of events decoded at a time by Filtered
+const BATCH_SIZE: usize = 64 * 1024;
+
+// A stage processing a stream one event at a time
+pub trait DvsFilter {
+    // Takes the next event of the stream, passing the events it lets through to out, now or later
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent));
+
+    // Passes the events still held back to out, at the end of the stream
+    fn finish(&mut self, out: &mut dyn FnMut(DVSEvent)) {
+        let _ = out;
+    }
+
+    // Takes the header of the stream before its first event, and returns the header of the output, e.g. with a
+    // new geometry. Filters start over here
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        Ok(header)
+    }
+
+    // What the filter did so far, e.g. the number of events dropped
+    fn summary(&self) -> Option<String> {
+        None
+    }
+}
+
+impl<F: DvsFilter + ?Sized> DvsFilter for Box<F> {
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        (**self).process(event, out)
+    }
+
+    fn finish(&mut self, out: &mut dyn FnMut(DVSEvent)) {
+        (**self).finish(out)
+    }
+
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        (**self).header(header)
+    }
+
+    fn summary(&self) -> Option<String> {
+        (**self).summary()
+    }
+}
+
+impl<F: DvsFilter + ?Sized> DvsFilter for &mut F {
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        (**self).process(event, out)
+    }
+
+    fn finish(&mut self, out: &mut dyn FnMut(DVSEvent)) {
+        (**self).finish(out)
+    }
+
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        (**self).header(header)
+    }
+
+    fn summary(&self) -> Option<String> {
+        (**self).summary()
+    }
+}
+
+// Wraps a decoder, passing its events through a filter
+pub struct Filtered<R: Read + BufRead + Seek, D: DvsRawDecoder<R>, F: DvsFilter> {
+    decoder: D,
+    filter: F,
+    // Events let through by the filter, not yet returned
+    output: VecDeque<DVSEvent>,
+    batch: Vec<DVSEvent>,
+    // Whether the decoder has reached the end of its input and the filter has been finished
+    finished: bool,
+    _reader: PhantomData<R>,
+}
+
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>, F: DvsFilter> Filtered<R, D, F> {
+    pub fn new(decoder: D, filter: F) -> Self {
+        Self { decoder, filter, output: VecDeque::new(), batch: Vec::new(), finished: false, _reader: PhantomData }
+    }
+
+    pub fn filter(&self) -> &F {
+        &self.filter
+    }
+
+    pub fn into_inner(self) -> D {
+        self.decoder
+    }
+
+    pub fn into_parts(self) -> (D, F) {
+        (self.decoder, self.filter)
+    }
+
+    // Passes the next batch of the decoder through the filter, or finishes the filter at the end of the input
+    fn fill(&mut self) -> anyhow::Result<()> {
+        let output = &mut self.output;
+        self.batch.clear();
+        if self.decoder.read_events_into(&mut self.batch, BATCH_SIZE)? == 0 {
+            self.filter.finish(&mut |event| output.push_back(event));
+            self.finished = true;
+            return Ok(());
+        }
+        for event in &self.batch {
+            self.filter.process(*event, &mut |event| output.push_back(event));
+        }
+        Ok(())
+    }
+}
+
+// Implemented like DvsRawDecoderEnum, so a filtered decoder can be used wherever a decoder is expected
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>, F: DvsFilter> DvsRawDecoder<R> for Filtered<R, D, F> {
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        let header = self.decoder.read_header()?;
+        self.output.clear();
+        self.finished = false;
+        Ok(self.filter.header(header)?)
+    }
+
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        while self.output.is_empty() && !self.finished {
+            self.fill()?;
+        }
+        Ok(self.output.pop_front())
+    }
+
+    // Returns Ok(0) only at the end of the stream, even if the filter dropped a whole batch
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        while self.output.is_empty() && !self.finished {
+            self.fill()?;
+        }
+        let count = self.output.len().min(max);
+        events.extend(self.output.drain(..count));
+        Ok(count)
+    }
+
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        self.decoder.take_triggers()
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct HotPixelOptions {
+    // Length of the calibration window at the start of the stream, in microseconds
+    pub calibration_us: i64,
+    // Pixels with more than this multiple of the median rate are hot
+    pub threshold: f64,
+}
+
+impl Default for HotPixelOptions {
+    fn default() -> Self {
+        HotPixelOptions {
+            calibration_us: 100_000,
+            threshold: 10.0,
+        }
+    }
+}
+
+// Drops the events of pixels found hot over the calibration window
+pub struct HotPixelFilter {
+    options: HotPixelOptions,
+    // End of the calibration window, once the first event is seen
+    calibration_end: Option<i64>,
+    calibrated: bool,
+    // Events of each pixel during calibration
+    counts: HashMap<(i16, i16), u64>,
+    // Events of the calibration window, held back until it ends
+    held: Vec<DVSEvent>,
+    hot: HashSet<(i16, i16)>,
+    // Median number of events of the active pixels over the calibration window
+    median_events: f64,
+    // Number of events of hot pixels dropped
+    pub events_dropped: u64,
+}
+
+impl HotPixelFilter {
+    pub fn new(options: HotPixelOptions) -> Self {
+        Self {
+            options,
+            calibration_end: None,
+            calibrated: false,
+            counts: HashMap::new(),
+            held: Vec::new(),
+            hot: HashSet::new(),
+            median_events: 0.0,
+            events_dropped: 0,
+        }
+    }
+
+    // Hot pixels as (x, y), sorted by position, once calibrated
+    pub fn hot_pixels(&self) -> Vec<(i16, i16)> {
+        let mut pixels: Vec<(i16, i16)> = self.hot.iter().copied().collect();
+        pixels.sort_unstable_by_key(|(x, y)| (*y, *x));
+        pixels
+    }
+
+    // Median event rate of the active pixels over the calibration window, in events per second
+    pub fn median_rate(&self) -> f64 {
+        self.median_events / (self.options.calibration_us as f64 / 1e6)
+    }
+
+    // Finds the hot pixels from the counts of the calibration window, and passes on the events held back
+    fn calibrate(&mut self, out: &mut dyn FnMut(DVSEvent)) {
+        self.calibrated = true;
+        let mut sorted: Vec<u64> = self.counts.values().copied().collect();
+        sorted.sort_unstable();
+        self.median_events = match sorted.len() {
+            0 => 0.0,
+            n if n % 2 == 1 => sorted[n / 2] as f64,
+            n => (sorted[n / 2 - 1] + sorted[n / 2]) as f64 / 2.0,
+        };
+        let limit = self.median_events * self.options.threshold;
+        self.hot = self.counts.drain().filter(|(_, count)| *count as f64 > limit).map(|(pixel, _)| pixel).collect();
+        for event in std::mem::take(&mut self.held) {
+            if self.keep(&event) {
+                out(event);
+            }
+        }
+    }
+
+    // Whether an event is kept, counting those dropped
+    fn keep(&mut self, event: &DVSEvent) -> bool {
+        if self.hot.contains(&(event.x, event.y)) {
+            self.events_dropped += 1;
+            return false;
+        }
+        true
+    }
+}
+
+impl DvsFilter for HotPixelFilter {
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        if !self.calibrated {
+            let end = *self.calibration_end.get_or_insert(event.timestamp.saturating_add(self.options.calibration_us));
+            if event.timestamp < end {
+                *self.counts.entry((event.x, event.y)).or_insert(0) += 1;
+                self.held.push(event);
+                return;
+            }
+            self.calibrate(out);
+        }
+        if self.keep(&event) {
+            out(event);
+        }
+    }
+
+    // Streams shorter than the calibration window are calibrated on all their events
+    fn finish(&mut self, out: &mut dyn FnMut(DVSEvent)) {
+        if !self.calibrated {
+            self.calibrate(out);
+        }
+    }
+
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        *self = HotPixelFilter::new(self.options);
+        Ok(header)
+    }
+
+    fn summary(&self) -> Option<String> {
+        Some(format!(
+            "Dropped {} events of {} hot pixels (median rate {:.1} events/s)",
+            self.events_dropped,
+            self.hot.len(),
+            self.median_rate()
+        ))
+    }
+}
+
+// A rectangle of pixels, from its top left corner
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Roi {
+    pub x: i16,
+    pub y: i16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Roi {
+    pub fn contains(&self, x: i16, y: i16) -> bool {
+        x >= self.x && y >= self.y && (x as i32 - self.x as i32) < self.width as i32 && (y as i32 - self.y as i32) < self.height as i32
+    }
+}
+
+// Parses "<x>,<y>,<width>,<height>"
+impl std::str::FromStr for Roi {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid region '{}'. Expected <x>,<y>,<width>,<height>", s);
+        let values: Vec<&str> = s.split(',').map(str::trim).collect();
+        let [x, y, width, height] = values.as_slice() else {
+            return Err(invalid());
+        };
+        let roi = Roi {
+            x: x.parse().map_err(|_| invalid())?,
+            y: y.parse().map_err(|_| invalid())?,
+            width: width.parse().map_err(|_| invalid())?,
+            height: height.parse().map_err(|_| invalid())?,
+        };
+        if roi.x < 0 || roi.y < 0 || roi.width == 0 || roi.height == 0 {
+            return Err(invalid());
+        }
+        Ok(roi)
+    }
+}
+
+// Keeps only the events inside a region, with coordinates relative to its corner
+pub struct RoiFilter {
+    roi: Roi,
+    // Number of events outside the region
+    pub events_dropped: u64,
+}
+
+impl RoiFilter {
+    pub fn new(roi: Roi) -> Self {
+        Self { roi, events_dropped: 0 }
+    }
+}
+
+impl DvsFilter for RoiFilter {
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        if !self.roi.contains(event.x, event.y) {
+            self.events_dropped += 1;
+            return;
+        }
+        out(DVSEvent { x: event.x - self.roi.x, y: event.y - self.roi.y, ..event });
+    }
+
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        self.events_dropped = 0;
+        Ok(set_header_geometry(header, self.roi.width as u32, self.roi.height as u32))
+    }
+
+    fn summary(&self) -> Option<String> {
+        Some(format!("Dropped {} events outside the region", self.events_dropped))
+    }
+}
+
+// What PolarityFilter does with the polarity of events
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PolarityMode {
+    // Keep only ON events
+    On,
+    // Keep only OFF events
+    Off,
+    // Keep all events, swapping ON and OFF
+    Flip,
+}
+
+// Parses "on", "off" or "flip"
+impl std::str::FromStr for PolarityMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "on" => Ok(PolarityMode::On),
+            "off" => Ok(PolarityMode::Off),
+            "flip" => Ok(PolarityMode::Flip),
+            _ => anyhow::bail!("Unsupported polarity '{}'. Expected on, off or flip", s),
+        }
+    }
+}
+
+// Keeps the events of one polarity or flips the polarity of all events
+pub struct PolarityFilter {
+    mode: PolarityMode,
+    // Events taken, by polarity
+    pub counts_in: PolarityCounts,
+    // Events let through, by polarity
+    pub counts_out: PolarityCounts,
+}
+
+impl PolarityFilter {
+    pub fn new(mode: PolarityMode) -> Self {
+        Self { mode, counts_in: PolarityCounts::default(), counts_out: PolarityCounts::default() }
+    }
+}
+
+impl DvsFilter for PolarityFilter {
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        self.counts_in.add(&event);
+        let event = match self.mode {
+            PolarityMode::On if event.polarity != 1 => return,
+            PolarityMode::Off if event.polarity == 1 => return,
+            PolarityMode::Flip => DVSEvent { polarity: if event.polarity == 1 { 0 } else { 1 }, ..event },
+            _ => event,
+        };
+        self.counts_out.add(&event);
+        out(event);
+    }
+
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        self.counts_in = PolarityCounts::default();
+        self.counts_out = PolarityCounts::default();
+        Ok(header)
+    }
+
+    fn summary(&self) -> Option<String> {
+        let (counts_in, counts_out) = (self.counts_in, self.counts_out);
+        Some(format!("ON events: {} -> {}, OFF events: {} -> {}", counts_in.on, counts_out.on, counts_in.off, counts_out.off))
+    }
+}
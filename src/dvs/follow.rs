@@ -0,0 +1,62 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/*
+This file implements a reader for following a file that is still being written, e.g. by capture software.
+Instead of reporting EOF when it catches up with the writer, the reader polls for new data until the
+file has stopped growing for longer than the idle timeout.
+*/
+
+pub struct FollowReader<R: Read + Seek> {
+    inner: R,
+    poll_interval: Duration,
+    idle_timeout: Option<Duration>,
+}
+
+impl<R: Read + Seek> FollowReader<R> {
+    // Creates a new FollowReader. With no idle timeout, the reader waits for new data forever.
+    pub fn new(inner: R, poll_interval: Duration, idle_timeout: Option<Duration>) -> Self {
+        Self {
+            inner,
+            poll_interval,
+            idle_timeout,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for FollowReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let idle_since = Instant::now();
+        loop {
+            let n = self.inner.read(buf)?;
+            if n > 0 {
+                return Ok(n);
+            }
+            // Caught up with the writer. Report EOF once the file has been idle for too long
+            if let Some(timeout) = self.idle_timeout {
+                if idle_since.elapsed() >= timeout {
+                    return Ok(0);
+                }
+            }
+            thread::sleep(self.poll_interval);
+        }
+    }
+}
+
+impl<R: Read + Seek> Seek for FollowReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
@@ -0,0 +1,463 @@
+use crate::dvs::bitrate::{BandwidthBudget, RunningBitrate};
+use crate::dvs::compress::InputFile;
+use crate::dvs::filters::DvsFilter;
+use crate::dvs::loss::{LossFilter, LossModel, LossOptions};
+use crate::dvs::render::{Colormap, Frame};
+use crate::dvs::replay::{MAX_SPEED, MIN_SPEED};
+use crate::dvs::{header_geometry, prep_file_decoder, DVSEvent, DvsRawDecoder, DvsRawDecoderEnum};
+use pixels::{Pixels, SurfaceTexture};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::BufReader;
+use std::time::{Duration, Instant};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::platform::run_return::EventLoopExtRunReturn;
+use winit::window::WindowBuilder;
+
+/*
+This file implements a window playing back recordings, to watch the effect of loss model parameters as they are
+tuned. It is built with the gui feature, on winit for the window and pixels for drawing. Frames are drawn like
+those of render.rs, at the sensor resolution, and pixels scales them to the window.
+A Player reads the recording and cuts it into frames, each covering 1 / fps seconds of wall time at the playback
+speed, so changing the speed changes the event time of the next frames. Events can go through a loss model
+first, applied by a LossFilter as in the transcoding pipeline. The status line overlaid at the top of each frame
+shows the time, the speed, the events of the frame, the bitrate of the events shown, smoothed over recent frames,
+and the share of events the loss model dropped since playback started or last seeked.
+Seeking uses the index of EVT2 and EVT3 recordings (see index.rs). Other formats are read again from the start
+to seek back, and read on to seek forward. The loss filter starts over after a seek that read the recording
+again.
+Keys: space pauses and resumes, left and right seek by a second and page up and down by ten, home goes back to
+the start, up and down double and halve the speed, the period steps one frame while paused, and q or Esc close
+the window.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 16 * 1024;
+// Weight of the latest frame in the bitrate and loss shown
+const SMOOTHING: f64 = 0.1;
+// Seconds seeked by the arrow keys and by page up and down
+const SEEK_US: i64 = 1_000_000;
+const LONG_SEEK_US: i64 = 10_000_000;
+// Largest initial size of the window, in logical pixels
+const MAX_WINDOW_SIZE: (u32, u32) = (1280, 960);
+// Color of the status line, and of the band behind it
+const TEXT_COLOR: [u8; 3] = [255, 255, 0];
+const TEXT_BACKGROUND: [u8; 3] = [0, 0, 0];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GuiOptions {
+    // Frames drawn per second of wall time
+    pub fps: f64,
+    // Playback speed, as a factor of real time, from MIN_SPEED to MAX_SPEED
+    pub speed: f64,
+    pub colormap: Colormap,
+    // Width and height of the sensor, overriding the header
+    pub geometry: Option<(u32, u32)>,
+    // Cost of the events shown, for their bitrate. Replaced by the budget of the loss model, if any
+    pub budget: BandwidthBudget,
+}
+
+impl Default for GuiOptions {
+    fn default() -> Self {
+        GuiOptions { fps: 30.0, speed: 1.0, colormap: Colormap::Dark, geometry: None, budget: BandwidthBudget::default() }
+    }
+}
+
+// Reads a recording frame by frame, applying the loss model, and seeks in it
+pub struct Player {
+    path: String,
+    decoder: DvsRawDecoderEnum<BufReader<InputFile>>,
+    options: GuiOptions,
+    width: u32,
+    height: u32,
+    loss: Option<LossFilter>,
+    // Events read but not drawn yet, after the loss model
+    events: VecDeque<DVSEvent>,
+    // Timestamps of the events offered to the loss model but not drawn yet, whether it kept them or not
+    offered: VecDeque<i64>,
+    batch: Vec<DVSEvent>,
+    // Events before this timestamp are skipped, after a seek
+    skip_before_us: i64,
+    input_ended: bool,
+    // Start of the next frame, once known
+    next_frame_us: Option<i64>,
+    // Timestamp of the first event of the recording, once read
+    first_us: Option<i64>,
+    // Statistics of the frames drawn since the last seek
+    frame_events: u64,
+    bitrate: RunningBitrate,
+    loss_ratio: f64,
+}
+
+impl Player {
+    pub fn open(path: &str, options: GuiOptions, loss: Option<(LossOptions, Box<dyn LossModel>)>) -> anyhow::Result<Self> {
+        let mut decoder = prep_file_decoder::<BufReader<File>>(path)?;
+        let header = decoder.read_header()?;
+        let Some((width, height)) = options.geometry.or_else(|| header_geometry(&header)) else {
+            anyhow::bail!("Playing back needs the sensor geometry, which the header doesn't give");
+        };
+        let options = GuiOptions { budget: loss.as_ref().map_or(options.budget, |(loss, _)| loss.budget), ..options };
+        Ok(Player {
+            path: path.to_string(),
+            decoder,
+            options,
+            width,
+            height,
+            loss: loss.map(|(options, model)| LossFilter::new(options, model)),
+            events: VecDeque::new(),
+            offered: VecDeque::new(),
+            batch: Vec::with_capacity(BATCH_SIZE),
+            skip_before_us: i64::MIN,
+            input_ended: false,
+            next_frame_us: None,
+            first_us: None,
+            frame_events: 0,
+            bitrate: RunningBitrate::new(SMOOTHING, 0.0),
+            loss_ratio: 0.0,
+        })
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.options.speed
+    }
+
+    // Changes the playback speed, within MIN_SPEED and MAX_SPEED
+    pub fn set_speed(&mut self, speed: f64) {
+        self.options.speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+    }
+
+    // Event time covered by each frame, in microseconds
+    pub fn frame_us(&self) -> i64 {
+        ((1e6 / self.options.fps.max(f64::MIN_POSITIVE)) * self.options.speed).max(1.0) as i64
+    }
+
+    // Wall time between frames
+    pub fn frame_interval(&self) -> Duration {
+        Duration::from_secs_f64(1.0 / self.options.fps.max(f64::MIN_POSITIVE))
+    }
+
+    // Start of the next frame, or the first timestamp before the first frame
+    pub fn position_us(&self) -> i64 {
+        self.next_frame_us.or(self.first_us).unwrap_or(0)
+    }
+
+    // True once every event was drawn
+    pub fn ended(&self) -> bool {
+        self.input_ended && self.events.is_empty()
+    }
+
+    // Bitrate of the events shown, smoothed over recent frames, in megabits per second
+    pub fn bitrate_mbps(&self) -> f64 {
+        self.bitrate.smoothed_mbps
+    }
+
+    // Share of events the loss model dropped, smoothed over recent frames, if there is a loss model
+    pub fn loss_ratio(&self) -> Option<f64> {
+        self.loss.as_ref().map(|_| self.loss_ratio)
+    }
+
+    // Reads a batch of events into the events to draw, through the loss model
+    fn read_batch(&mut self) -> anyhow::Result<()> {
+        self.batch.clear();
+        let events = &mut self.events;
+        if self.decoder.read_events_into(&mut self.batch, BATCH_SIZE)? == 0 {
+            self.input_ended = true;
+            if let Some(loss) = &mut self.loss {
+                loss.finish(&mut |event| events.push_back(event));
+            }
+            return Ok(());
+        }
+        self.first_us.get_or_insert(self.batch[0].timestamp);
+        for event in self.batch.iter().filter(|event| event.timestamp >= self.skip_before_us) {
+            match &mut self.loss {
+                Some(loss) => {
+                    self.offered.push_back(event.timestamp);
+                    loss.process(*event, &mut |event| events.push_back(event));
+                }
+                None => events.push_back(*event),
+            }
+        }
+        Ok(())
+    }
+
+    // Draws the events of the next frame, or returns None once every event was drawn. Events later than a frame
+    // are kept for the next ones, and late events are drawn into the current one
+    pub fn next_frame(&mut self) -> anyhow::Result<Option<Frame>> {
+        let frame_us = self.frame_us();
+        let start_us = loop {
+            match (self.next_frame_us, self.events.front()) {
+                (Some(start_us), _) => break start_us,
+                (None, Some(event)) => break event.timestamp.div_euclid(frame_us) * frame_us,
+                (None, None) if self.input_ended => return Ok(None),
+                (None, None) => self.read_batch()?,
+            }
+        };
+        let end_us = start_us + frame_us;
+        while !self.input_ended && self.events.back().is_none_or(|event| event.timestamp < end_us) {
+            self.read_batch()?;
+        }
+        if self.ended() {
+            return Ok(None);
+        }
+
+        let (background, on, off) = self.options.colormap.colors();
+        let mut frame = Frame::new(start_us, self.width, self.height, background);
+        while let Some(event) = self.events.front().filter(|event| event.timestamp < end_us) {
+            frame.set(event.x, event.y, if event.polarity == 1 { on } else { off });
+            frame.events += 1;
+            self.events.pop_front();
+        }
+        self.next_frame_us = Some(end_us);
+        self.frame_events = frame.events;
+        self.bitrate.add(self.options.budget.bits_for_events(frame.events as usize), frame_us);
+        // The loss model decides on events well before they are drawn, so its drops are counted by frame here
+        let mut offered = 0u64;
+        while self.offered.front().is_some_and(|timestamp| *timestamp < end_us) {
+            self.offered.pop_front();
+            offered += 1;
+        }
+        if offered > 0 {
+            let dropped = offered.saturating_sub(frame.events);
+            self.loss_ratio += SMOOTHING * (dropped as f64 / offered as f64 - self.loss_ratio);
+        }
+        Ok(Some(frame))
+    }
+
+    // Moves to a timestamp, clamped to the start of the recording, so the next frame starts there
+    pub fn seek(&mut self, timestamp: i64) -> anyhow::Result<()> {
+        let target_us = timestamp.max(self.first_us.unwrap_or(i64::MIN));
+        let position_us = self.position_us();
+        if self.decoder.seek_to_timestamp(target_us).is_ok() {
+            self.restart_loss();
+        } else if target_us < position_us || self.first_us.is_none() {
+            self.decoder = prep_file_decoder::<BufReader<File>>(&self.path)?;
+            self.decoder.read_header()?;
+            self.restart_loss();
+        } else {
+            // Reads on, keeping the events already through the loss model
+            self.events.retain(|event| event.timestamp >= target_us);
+            self.offered.retain(|timestamp| *timestamp >= target_us);
+        }
+        self.skip_before_us = target_us;
+        let frame_us = self.frame_us();
+        self.next_frame_us = Some(target_us.div_euclid(frame_us) * frame_us);
+        self.bitrate = RunningBitrate::new(SMOOTHING, 0.0);
+        self.loss_ratio = 0.0;
+        Ok(())
+    }
+
+    // Starts the loss filter over, after the decoder moved
+    fn restart_loss(&mut self) {
+        self.events.clear();
+        self.offered.clear();
+        self.input_ended = false;
+        if let Some(loss) = &mut self.loss {
+            // The loss filter doesn't change the header
+            let _ = loss.header(Vec::new());
+        }
+    }
+
+    // Status line of the last frame
+    pub fn status(&self, paused: bool) -> String {
+        let mut status = format!(
+            "{:.3} S  {}X  {} EV  {:.2} MBIT/S",
+            self.position_us() as f64 / 1e6,
+            self.options.speed,
+            self.frame_events,
+            self.bitrate_mbps()
+        );
+        if let Some(ratio) = self.loss_ratio() {
+            status.push_str(&format!("  LOSS {:.1}%", ratio * 100.0));
+        }
+        if self.ended() {
+            status.push_str("  END");
+        } else if paused {
+            status.push_str("  PAUSED");
+        }
+        status
+    }
+}
+
+// Glyphs of the status line, 3 pixels wide and 5 high, each row's bits from the left
+const GLYPHS: [(char, [u8; 5]); 30] = [
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+];
+
+// Draws a line of text over the top of a frame, on a band of TEXT_BACKGROUND. Glyphs are scaled up on larger
+// sensors, characters without a glyph are left blank, and text past the right edge is cut off
+pub fn overlay_text(frame: &mut Frame, text: &str) {
+    let scale = (frame.width / 320).max(1) as i32;
+    let band_height = (7 * scale).min(frame.height as i32);
+    for y in 0..band_height {
+        for x in 0..frame.width as i32 {
+            frame.set(x as i16, y as i16, TEXT_BACKGROUND);
+        }
+    }
+    for (i, c) in text.chars().enumerate() {
+        let Some((_, rows)) = GLYPHS.iter().find(|(glyph, _)| *glyph == c.to_ascii_uppercase()) else {
+            continue;
+        };
+        let left = (1 + 4 * i as i32) * scale;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..3 {
+                if bits & (0b100 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let (x, y) = (left + column * scale + dx, (1 + row as i32) * scale + dy);
+                        if x <= i16::MAX as i32 && y <= i16::MAX as i32 {
+                            frame.set(x as i16, y as i16, TEXT_COLOR);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Copies an RGB frame into an RGBA buffer of the same size
+fn copy_rgba(frame: &Frame, rgba: &mut [u8]) {
+    for (pixel, rgb) in rgba.chunks_exact_mut(4).zip(frame.pixels.chunks_exact(3)) {
+        pixel[..3].copy_from_slice(rgb);
+        pixel[3] = 255;
+    }
+}
+
+// Opens a window playing back the recording until it is closed, see the top of this file
+pub fn play(mut player: Player) -> anyhow::Result<()> {
+    let (width, height) = player.size();
+    let zoom = (MAX_WINDOW_SIZE.0 / width.max(1)).min(MAX_WINDOW_SIZE.1 / height.max(1)).max(1);
+    // winit panics instead of failing when there is no display
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if std::env::var_os("DISPLAY").is_none() && std::env::var_os("WAYLAND_DISPLAY").is_none() {
+        anyhow::bail!("No display to open a window on, as neither DISPLAY nor WAYLAND_DISPLAY is set");
+    }
+    let mut event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title(format!("dvs - {}", player.path))
+        .with_inner_size(LogicalSize::new(width * zoom, height * zoom))
+        .with_min_inner_size(LogicalSize::new(width.min(320), height.min(240)))
+        .build(&event_loop)?;
+    let size = window.inner_size();
+    let mut pixels = Pixels::new(width, height, SurfaceTexture::new(size.width, size.height, &window))?;
+
+    let mut frame = player.next_frame()?;
+    let mut paused = false;
+    let mut next_draw = Instant::now() + player.frame_interval();
+    let mut result = Ok(());
+    event_loop.run_return(|event, _, control_flow| {
+        let mut step = || -> anyhow::Result<bool> {
+            match event {
+                Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => return Ok(false),
+                Event::WindowEvent { event: WindowEvent::Resized(size), .. } => {
+                    pixels.resize_surface(size.width, size.height)?;
+                    window.request_redraw();
+                }
+                Event::WindowEvent { event: WindowEvent::KeyboardInput { input: KeyboardInput { state: ElementState::Pressed, virtual_keycode: Some(key), .. }, .. }, .. } => {
+                    let position_us = player.position_us();
+                    // The frame shown starts a frame before the position
+                    let shown_us = frame.as_ref().map_or(position_us, |frame| frame.start_us);
+                    let seek = match key {
+                        VirtualKeyCode::Q | VirtualKeyCode::Escape => return Ok(false),
+                        VirtualKeyCode::Space => {
+                            paused = !paused;
+                            next_draw = Instant::now() + player.frame_interval();
+                            None
+                        }
+                        VirtualKeyCode::Left => Some(shown_us - SEEK_US),
+                        VirtualKeyCode::Right => Some(shown_us + SEEK_US),
+                        VirtualKeyCode::PageUp => Some(shown_us - LONG_SEEK_US),
+                        VirtualKeyCode::PageDown => Some(shown_us + LONG_SEEK_US),
+                        VirtualKeyCode::Home => Some(i64::MIN),
+                        VirtualKeyCode::Up => {
+                            player.set_speed(player.speed() * 2.0);
+                            None
+                        }
+                        VirtualKeyCode::Down => {
+                            player.set_speed(player.speed() / 2.0);
+                            None
+                        }
+                        VirtualKeyCode::Period if paused => Some(position_us),
+                        _ => None,
+                    };
+                    if let Some(timestamp) = seek {
+                        if timestamp != position_us {
+                            player.seek(timestamp)?;
+                        }
+                        if let Some(next) = player.next_frame()? {
+                            frame = Some(next);
+                        }
+                    }
+                    window.request_redraw();
+                }
+                Event::MainEventsCleared => {
+                    let now = Instant::now();
+                    if !paused && now >= next_draw {
+                        // Frames are dropped rather than drawn late, and the last one stays in the window
+                        next_draw = (next_draw + player.frame_interval()).max(now);
+                        if let Some(next) = player.next_frame()? {
+                            frame = Some(next);
+                        }
+                        window.request_redraw();
+                    }
+                }
+                Event::RedrawRequested(_) => {
+                    if let Some(frame) = &frame {
+                        let mut shown = frame.clone();
+                        overlay_text(&mut shown, &player.status(paused));
+                        copy_rgba(&shown, pixels.frame_mut());
+                    }
+                    pixels.render()?;
+                }
+                _ => {}
+            }
+            Ok(true)
+        };
+        match step() {
+            Ok(true) if paused || player.ended() => control_flow.set_wait(),
+            Ok(true) => control_flow.set_wait_until(next_draw),
+            Ok(false) => control_flow.set_exit(),
+            Err(e) => {
+                result = Err(e);
+                control_flow.set_exit();
+            }
+        }
+    });
+    result
+}
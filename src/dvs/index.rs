@@ -0,0 +1,175 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::{prep_file_decoder, EventFormat};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+/*
+This file implements index sidecar files for EVT2 and EVT3 recordings. An index maps timestamps to byte
+offsets: it records the decoder state at an EVT_TIME_HIGH word every interval, e.g. every 10 ms, built in a
+single pass over the words of the recording without decoding events. A decoder given the index seeks by
+looking up the last entry before the timestamp and reading on from there, instead of searching the file.
+
+The sidecar of "recording.raw" is "recording.raw.idx". prep_file_decoder loads it, if it is up to date, so
+seek_to_timestamp uses it without further setup. Generate it with `dvs index recording.raw`.
+
+Sidecar layout, little-endian:
+    magic "DVSIDX01", format (u8: 2 for EVT2, 3 for EVT3), interval in us (i64), size of the recording in
+    bytes (u64), number of entries (u64), then per entry: offset (u64), time base (i64), time base loops
+    (i64), y (i16), vector base x (i16), polarity (u8)
+*/
+
+pub const INDEX_EXTENSION: &str = "idx";
+pub const DEFAULT_INTERVAL_US: i64 = 10_000;
+
+const MAGIC: &[u8; 8] = b"DVSIDX01";
+const ENTRY_BYTES: usize = 29;
+
+// The decoder state just after an EVT_TIME_HIGH word, from which decoding can resume. EVT2 decoders only keep
+// the time base, the other fields are zero
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct IndexEntry {
+    // Offset of the EVT_TIME_HIGH word in the recording
+    pub offset: u64,
+    // Time base the word sets, including loops
+    pub time_base: i64,
+    pub n_time_high_loop: i64,
+    pub ev_addr_y: i16,
+    pub base_x: i16,
+    pub polarity: u8,
+}
+
+impl IndexEntry {
+    fn to_le_bytes(self) -> [u8; ENTRY_BYTES] {
+        let mut bytes = [0u8; ENTRY_BYTES];
+        bytes[0..8].copy_from_slice(&self.offset.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.time_base.to_le_bytes());
+        bytes[16..24].copy_from_slice(&self.n_time_high_loop.to_le_bytes());
+        bytes[24..26].copy_from_slice(&self.ev_addr_y.to_le_bytes());
+        bytes[26..28].copy_from_slice(&self.base_x.to_le_bytes());
+        bytes[28] = self.polarity;
+        bytes
+    }
+
+    fn from_le_bytes(bytes: &[u8; ENTRY_BYTES]) -> Self {
+        let u64_at = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+        let i16_at = |at: usize| i16::from_le_bytes([bytes[at], bytes[at + 1]]);
+        IndexEntry {
+            offset: u64_at(0),
+            time_base: u64_at(8) as i64,
+            n_time_high_loop: u64_at(16) as i64,
+            ev_addr_y: i16_at(24),
+            base_x: i16_at(26),
+            polarity: bytes[28],
+        }
+    }
+}
+
+// Keeps the first EVT_TIME_HIGH word of every interval. Entries are always the first word with their time
+// base, so no event of a time base is before its entry
+pub(crate) struct IndexBuilder {
+    interval_us: i64,
+    next_time_base: i64,
+    pub entries: Vec<IndexEntry>,
+}
+
+impl IndexBuilder {
+    pub(crate) fn new(interval_us: i64) -> Self {
+        Self { interval_us: interval_us.max(1), next_time_base: i64::MIN, entries: Vec::new() }
+    }
+
+    pub(crate) fn time_high(&mut self, entry: &IndexEntry) {
+        if entry.time_base >= self.next_time_base {
+            self.entries.push(*entry);
+            self.next_time_base = entry.time_base + self.interval_us;
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventIndex {
+    pub format: EventFormat,
+    pub interval_us: i64,
+    // Size of the indexed recording, to tell whether the index is out of date
+    pub recording_bytes: u64,
+    pub entries: Vec<IndexEntry>,
+}
+
+impl EventIndex {
+    // Indexes an EVT2 or EVT3 recording in one pass, keeping an entry every interval
+    pub fn build(path: impl AsRef<Path>, interval_us: i64) -> anyhow::Result<EventIndex> {
+        let path = path.as_ref();
+        let mut decoder = prep_file_decoder::<BufReader<File>>(&path.to_string_lossy())?;
+        let (format, entries) = decoder.build_index(interval_us)?;
+        Ok(EventIndex { format, interval_us, recording_bytes: std::fs::metadata(path)?.len(), entries })
+    }
+
+    // Path of the sidecar of a recording
+    pub fn sidecar_path(recording: impl AsRef<Path>) -> PathBuf {
+        let mut path = recording.as_ref().as_os_str().to_owned();
+        path.push(".");
+        path.push(INDEX_EXTENSION);
+        PathBuf::from(path)
+    }
+
+    // Loads the sidecar of a recording. Returns None if there is none, or if it doesn't match the size of the
+    // recording, as it was built before the recording changed
+    pub fn load_for(recording: impl AsRef<Path>) -> anyhow::Result<Option<EventIndex>> {
+        let sidecar = EventIndex::sidecar_path(&recording);
+        if !sidecar.exists() {
+            return Ok(None);
+        }
+        let index = EventIndex::read(sidecar)?;
+        if index.recording_bytes != std::fs::metadata(recording)?.len() {
+            return Ok(None);
+        }
+        Ok(Some(index))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[format_byte(self.format)?])?;
+        writer.write_all(&self.interval_us.to_le_bytes())?;
+        writer.write_all(&self.recording_bytes.to_le_bytes())?;
+        writer.write_all(&(self.entries.len() as u64).to_le_bytes())?;
+        for entry in &self.entries {
+            writer.write_all(&entry.to_le_bytes())?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> anyhow::Result<EventIndex> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0u8; 33];
+        reader.read_exact(&mut header)?;
+        if &header[0..8] != MAGIC {
+            return Err(DvsError::UnsupportedFormat("Not an event index file".to_string()).into());
+        }
+        let format = match header[8] {
+            2 => EventFormat::Evt2,
+            3 => EventFormat::Evt3,
+            byte => return Err(DvsError::InvalidData(format!("Invalid event index format {}", byte)).into()),
+        };
+        let interval_us = i64::from_le_bytes(header[9..17].try_into().unwrap());
+        let recording_bytes = u64::from_le_bytes(header[17..25].try_into().unwrap());
+        let count = u64::from_le_bytes(header[25..33].try_into().unwrap());
+
+        let mut entries = Vec::new();
+        let mut bytes = [0u8; ENTRY_BYTES];
+        for _ in 0..count {
+            reader.read_exact(&mut bytes)?;
+            entries.push(IndexEntry::from_le_bytes(&bytes));
+        }
+        Ok(EventIndex { format, interval_us, recording_bytes, entries })
+    }
+}
+
+fn format_byte(format: EventFormat) -> anyhow::Result<u8> {
+    match format {
+        EventFormat::Evt2 => Ok(2),
+        EventFormat::Evt3 => Ok(3),
+        _ => Err(DvsError::UnsupportedFormat("Only EVT2 and EVT3 recordings can be indexed".to_string()).into()),
+    }
+}
@@ -0,0 +1,462 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::raw_decoder_aedat3::{polarity_event, PacketHeader, PACKET_HEADER_SIZE, POLARITY_EVENT, POLARITY_EVENT_SIZE};
+use crate::dvs::{DvsRawDecoder, DVSEvent};
+use std::collections::VecDeque;
+use libloading::Library;
+use std::ffi::{c_char, c_void, CString};
+use std::io::{BufRead, Read, Seek};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/*
+This file implements live capture from iniVation cameras (DAVIS240, DAVIS346 and DVXplorer) through libcaer.
+libcaer is loaded when the first camera is opened rather than linked, so the inivation feature builds without it,
+and only capturing needs it installed. libcaer hands out containers of event packets laid out
+like the packets of AEDAT 3.1 files (see raw_decoder_aedat3.rs): a 28-byte header followed by the events, so the
+polarity events are decoded the same way and the other events (APS frames, IMU samples) are skipped. The camera
+reads like a decoder, so it can be used wherever a decoder is expected, like UdpEventReceiver.
+Capture runs until the given duration has passed, the camera is disconnected, or the capture is dropped.
+The sensor biases are given by a CameraConfig. Fields left as None keep the defaults libcaer sends to the camera.
+Cameras are named by URIs, inivation://<model>[/serial] with model davis240, davis346, dvxplorer or dvxplorer-lite,
+and optional duration=<seconds>, sensitivity=<very-low|low|default|high|very-high> (DVXplorer) and
+diff, on, off, pr, prsf, refr=<coarse>,<fine> (DAVIS) parameters, e.g. inivation://davis346?duration=10&on=5,255.
+*/
+
+// Scheme of the URIs naming cameras
+pub const INIVATION_SCHEME: &str = "inivation://";
+
+// Constants of the libcaer headers (devices/device.h, davis.h and dvxplorer.h)
+const CAER_DEVICE_DAVIS: u16 = 4;
+const CAER_DEVICE_DVXPLORER: u16 = 8;
+const CAER_HOST_CONFIG_DATAEXCHANGE: i8 = -3;
+const CAER_HOST_CONFIG_DATAEXCHANGE_BLOCKING: u8 = 1;
+const DAVIS_CONFIG_BIAS: i8 = 5;
+const DVX_DVS_CHIP_BIAS: i8 = 21;
+const DVX_DVS_CHIP_BIAS_SIMPLE: u8 = 20;
+
+// Bias addresses of the DAVIS chips, in the order of the DavisBiases fields
+const DAVIS240_BIASES: [u8; 6] = [0, 1, 2, 8, 9, 10];
+const DAVIS346_BIASES: [u8; 6] = [10, 11, 12, 14, 15, 16];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum InivationDevice {
+    Davis240,
+    #[default]
+    Davis346,
+    DvXplorer,
+    DvXplorerLite,
+}
+
+impl InivationDevice {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "davis240" => Some(InivationDevice::Davis240),
+            "davis346" => Some(InivationDevice::Davis346),
+            "dvxplorer" => Some(InivationDevice::DvXplorer),
+            "dvxplorer-lite" => Some(InivationDevice::DvXplorerLite),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            InivationDevice::Davis240 => "DAVIS240",
+            InivationDevice::Davis346 => "DAVIS346",
+            InivationDevice::DvXplorer => "DVXplorer",
+            InivationDevice::DvXplorerLite => "DVXplorer Lite",
+        }
+    }
+
+    // Sensor geometry (width, height)
+    pub fn geometry(&self) -> (u32, u32) {
+        match self {
+            InivationDevice::Davis240 => (240, 180),
+            InivationDevice::Davis346 => (346, 260),
+            InivationDevice::DvXplorer => (640, 480),
+            InivationDevice::DvXplorerLite => (320, 240),
+        }
+    }
+
+    fn caer_type(&self) -> u16 {
+        match self {
+            InivationDevice::Davis240 | InivationDevice::Davis346 => CAER_DEVICE_DAVIS,
+            InivationDevice::DvXplorer | InivationDevice::DvXplorerLite => CAER_DEVICE_DVXPLORER,
+        }
+    }
+}
+
+// A bias current of a DAVIS chip, as a coarse (0-7) and a fine (0-255) value
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CoarseFineBias {
+    pub coarse: u8,
+    pub fine: u8,
+}
+
+impl CoarseFineBias {
+    fn parse(value: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid bias '{}'. Expected <coarse 0-7>,<fine 0-255>", value);
+        let (coarse, fine) = value.split_once(',').ok_or_else(invalid)?;
+        let coarse: u8 = coarse.trim().parse().map_err(|_| invalid())?;
+        let fine: u8 = fine.trim().parse().map_err(|_| invalid())?;
+        if coarse > 7 {
+            return Err(invalid());
+        }
+        Ok(CoarseFineBias { coarse, fine })
+    }
+}
+
+// The DVS biases of a DAVIS chip. diff sets the contrast threshold together with on and off, pr and prsf the
+// bandwidth of the photoreceptor, refr the refractory period
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct DavisBiases {
+    pub diff: Option<CoarseFineBias>,
+    pub on: Option<CoarseFineBias>,
+    pub off: Option<CoarseFineBias>,
+    pub pr: Option<CoarseFineBias>,
+    pub prsf: Option<CoarseFineBias>,
+    pub refr: Option<CoarseFineBias>,
+}
+
+impl DavisBiases {
+    fn is_empty(&self) -> bool {
+        *self == DavisBiases::default()
+    }
+}
+
+// The contrast sensitivity presets of the DVXplorer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DvxSensitivity {
+    VeryLow,
+    Low,
+    Default,
+    High,
+    VeryHigh,
+}
+
+impl DvxSensitivity {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "very-low" => Some(DvxSensitivity::VeryLow),
+            "low" => Some(DvxSensitivity::Low),
+            "default" => Some(DvxSensitivity::Default),
+            "high" => Some(DvxSensitivity::High),
+            "very-high" => Some(DvxSensitivity::VeryHigh),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CameraConfig {
+    pub device: InivationDevice,
+    // Serial number of the camera, or None for the first one of its kind found
+    pub serial: Option<String>,
+    // How long to capture for, or None until the capture is dropped
+    pub duration: Option<Duration>,
+    // Biases of DAVIS cameras
+    pub biases: DavisBiases,
+    // Sensitivity of DVXplorer cameras
+    pub sensitivity: Option<DvxSensitivity>,
+}
+
+impl CameraConfig {
+    // Parses a camera URI, see the top of this file
+    pub fn from_uri(uri: &str) -> anyhow::Result<Self> {
+        let Some(rest) = uri.strip_prefix(INIVATION_SCHEME) else {
+            anyhow::bail!("Invalid camera {}. Expected {}<model>[/serial][?parameters]", uri, INIVATION_SCHEME);
+        };
+        let (camera, parameters) = rest.split_once('?').unwrap_or((rest, ""));
+        let (model, serial) = camera.split_once('/').unwrap_or((camera, ""));
+        let Some(device) = InivationDevice::from_name(model) else {
+            anyhow::bail!("Unsupported camera model '{}'. Expected davis240, davis346, dvxplorer or dvxplorer-lite", model);
+        };
+        let mut config = CameraConfig { device, serial: Some(serial.to_string()).filter(|serial| !serial.is_empty()), ..CameraConfig::default() };
+        for parameter in parameters.split('&').filter(|parameter| !parameter.is_empty()) {
+            let biases = &mut config.biases;
+            match parameter.split_once('=') {
+                Some(("duration", seconds)) => {
+                    let seconds: f64 = seconds.parse().map_err(|_| anyhow::anyhow!("Invalid capture duration '{}'", seconds))?;
+                    config.duration = Some(Duration::try_from_secs_f64(seconds).map_err(|e| anyhow::anyhow!("Invalid capture duration: {}", e))?);
+                }
+                Some(("sensitivity", name)) => {
+                    let sensitivity = DvxSensitivity::from_name(name)
+                        .ok_or_else(|| anyhow::anyhow!("Invalid sensitivity '{}'. Expected very-low, low, default, high or very-high", name))?;
+                    config.sensitivity = Some(sensitivity);
+                }
+                Some(("diff", value)) => biases.diff = Some(CoarseFineBias::parse(value)?),
+                Some(("on", value)) => biases.on = Some(CoarseFineBias::parse(value)?),
+                Some(("off", value)) => biases.off = Some(CoarseFineBias::parse(value)?),
+                Some(("pr", value)) => biases.pr = Some(CoarseFineBias::parse(value)?),
+                Some(("prsf", value)) => biases.prsf = Some(CoarseFineBias::parse(value)?),
+                Some(("refr", value)) => biases.refr = Some(CoarseFineBias::parse(value)?),
+                _ => anyhow::bail!("Unsupported camera parameter '{}'. Expected duration, sensitivity, diff, on, off, pr, prsf or refr", parameter),
+            }
+        }
+        Ok(config)
+    }
+}
+
+// struct caer_bias_coarsefine
+#[repr(C)]
+struct CaerCoarseFine {
+    coarse_value: u8,
+    fine_value: u8,
+    enabled: bool,
+    sex_n: bool,
+    type_normal: bool,
+    current_level_normal: bool,
+}
+
+// File names libcaer is loaded from, in order
+#[cfg(target_os = "windows")]
+const LIBCAER_NAMES: [&str; 2] = ["caer.dll", "libcaer.dll"];
+#[cfg(target_os = "macos")]
+const LIBCAER_NAMES: [&str; 2] = ["libcaer.dylib", "libcaer.3.dylib"];
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+const LIBCAER_NAMES: [&str; 2] = ["libcaer.so", "libcaer.so.3"];
+
+type DataCallback = Option<extern "C" fn(*mut c_void)>;
+
+// The functions of libcaer used, with the signatures of its headers
+struct Caer {
+    device_open: unsafe extern "C" fn(device_id: u16, device_type: u16, bus_number_restrict: u8, dev_address_restrict: u8, serial_number_restrict: *const c_char) -> *mut c_void,
+    device_close: unsafe extern "C" fn(handle: *mut *mut c_void) -> bool,
+    device_send_default_config: unsafe extern "C" fn(handle: *mut c_void) -> bool,
+    device_config_set: unsafe extern "C" fn(handle: *mut c_void, mod_addr: i8, param_addr: u8, param: u32) -> bool,
+    device_data_start: unsafe extern "C" fn(
+        handle: *mut c_void,
+        data_notify_increase: DataCallback,
+        data_notify_decrease: DataCallback,
+        data_notify_user_ptr: *mut c_void,
+        data_shutdown_notify: DataCallback,
+        data_shutdown_user_ptr: *mut c_void,
+    ) -> bool,
+    device_data_stop: unsafe extern "C" fn(handle: *mut c_void) -> bool,
+    device_data_get: unsafe extern "C" fn(handle: *mut c_void) -> *mut c_void,
+    bias_coarse_fine_generate: unsafe extern "C" fn(bias: CaerCoarseFine) -> u16,
+    // Keeps the functions loaded
+    _library: Library,
+}
+
+impl Caer {
+    fn load() -> Result<Self, String> {
+        // SAFETY: loading libcaer only runs the initializers of the library and of its dependencies
+        let library = LIBCAER_NAMES
+            .iter()
+            .find_map(|name| unsafe { Library::new(name) }.ok())
+            .ok_or_else(|| format!("libcaer could not be loaded (tried {}). Install libcaer to capture from iniVation cameras", LIBCAER_NAMES.join(", ")))?;
+        let missing = |e: libloading::Error| format!("libcaer is missing a function: {}", e);
+        // SAFETY: the types are those of the functions in the libcaer headers, which outlive them in _library
+        unsafe {
+            Ok(Caer {
+                device_open: *library.get(b"caerDeviceOpen\0").map_err(missing)?,
+                device_close: *library.get(b"caerDeviceClose\0").map_err(missing)?,
+                device_send_default_config: *library.get(b"caerDeviceSendDefaultConfig\0").map_err(missing)?,
+                device_config_set: *library.get(b"caerDeviceConfigSet\0").map_err(missing)?,
+                device_data_start: *library.get(b"caerDeviceDataStart\0").map_err(missing)?,
+                device_data_stop: *library.get(b"caerDeviceDataStop\0").map_err(missing)?,
+                device_data_get: *library.get(b"caerDeviceDataGet\0").map_err(missing)?,
+                bias_coarse_fine_generate: *library.get(b"caerBiasCoarseFineGenerate\0").map_err(missing)?,
+                _library: library,
+            })
+        }
+    }
+
+    // libcaer, loaded by the first call
+    fn get() -> anyhow::Result<&'static Caer> {
+        static CAER: OnceLock<Result<Caer, String>> = OnceLock::new();
+        CAER.get_or_init(Caer::load).as_ref().map_err(|e| anyhow::anyhow!("{}", e))
+    }
+}
+
+// Packet containers and their packets are allocated with malloc. libcaer only frees them in an inline function
+extern "C" {
+    fn free(ptr: *mut c_void);
+}
+
+// Layout of a (packed) packet container: lowest and highest timestamps (i64), number of events and of valid
+// events (i32), number of packets (i32), then pointers to the packets, which may be null
+const CONTAINER_PACKETS_NUMBER_OFFSET: usize = 24;
+const CONTAINER_PACKETS_OFFSET: usize = 28;
+
+// Called by libcaer when the camera is disconnected
+extern "C" fn on_shutdown(flag: *mut c_void) {
+    // SAFETY: the pointer is that of the AtomicBool owned by the capture, which stops the data before freeing it
+    unsafe { (*(flag as *const AtomicBool)).store(true, Ordering::SeqCst) };
+}
+
+// A live iniVation camera. Dropping it stops the capture and closes the camera
+pub struct InivationCapture {
+    caer: &'static Caer,
+    handle: *mut c_void,
+    config: CameraConfig,
+    // Set once the camera has been disconnected
+    shutdown: Box<AtomicBool>,
+    deadline: Option<Instant>,
+    events: VecDeque<DVSEvent>,
+}
+
+impl InivationCapture {
+    pub fn open(config: &CameraConfig) -> anyhow::Result<Self> {
+        let caer = Caer::get()?;
+        let serial = config.serial.as_deref().map(CString::new).transpose()?;
+        let serial_ptr = serial.as_ref().map_or(std::ptr::null(), |serial| serial.as_ptr());
+        // SAFETY: the serial number outlives the call, or is null to open any camera
+        let handle = unsafe { (caer.device_open)(1, config.device.caer_type(), 0, 0, serial_ptr) };
+        if handle.is_null() {
+            anyhow::bail!(
+                "No {} camera found{}",
+                config.device.name(),
+                config.serial.as_ref().map(|serial| format!(" with serial {}", serial)).unwrap_or_default()
+            );
+        }
+        let mut capture = InivationCapture {
+            caer,
+            handle,
+            config: config.clone(),
+            shutdown: Box::new(AtomicBool::new(false)),
+            deadline: None,
+            events: VecDeque::new(),
+        };
+        capture.configure()?;
+        let flag = &*capture.shutdown as *const AtomicBool as *mut c_void;
+        // SAFETY: the handle is open, and the flag lives until the capture is dropped, after the data is stopped
+        if !unsafe { (caer.device_data_start)(capture.handle, None, None, std::ptr::null_mut(), Some(on_shutdown), flag) } {
+            anyhow::bail!("Failed to start the {} camera", config.device.name());
+        }
+        capture.deadline = config.duration.map(|duration| Instant::now() + duration);
+        Ok(capture)
+    }
+
+    fn config_set(&self, module: i8, parameter: u8, value: u32) -> anyhow::Result<()> {
+        // SAFETY: the handle is open
+        if !unsafe { (self.caer.device_config_set)(self.handle, module, parameter, value) } {
+            anyhow::bail!("Failed to configure the {} camera (module {}, parameter {})", self.config.device.name(), module, parameter);
+        }
+        Ok(())
+    }
+
+    // Sends the default configuration of the camera, then the biases of the config
+    fn configure(&self) -> anyhow::Result<()> {
+        // SAFETY: the handle is open
+        if !unsafe { (self.caer.device_send_default_config)(self.handle) } {
+            anyhow::bail!("Failed to configure the {} camera", self.config.device.name());
+        }
+        // Wait for data in read_event, rather than polling
+        self.config_set(CAER_HOST_CONFIG_DATAEXCHANGE, CAER_HOST_CONFIG_DATAEXCHANGE_BLOCKING, 1)?;
+        let addresses = match self.config.device {
+            InivationDevice::Davis240 => DAVIS240_BIASES,
+            InivationDevice::Davis346 => DAVIS346_BIASES,
+            InivationDevice::DvXplorer | InivationDevice::DvXplorerLite => {
+                if !self.config.biases.is_empty() {
+                    anyhow::bail!("The {} has no coarse/fine biases. Set its sensitivity instead", self.config.device.name());
+                }
+                if let Some(sensitivity) = self.config.sensitivity {
+                    self.config_set(DVX_DVS_CHIP_BIAS, DVX_DVS_CHIP_BIAS_SIMPLE, sensitivity as u32)?;
+                }
+                return Ok(());
+            }
+        };
+        if self.config.sensitivity.is_some() {
+            anyhow::bail!("The {} has no sensitivity presets. Set its biases instead", self.config.device.name());
+        }
+        let biases = &self.config.biases;
+        let biases = [biases.diff, biases.on, biases.off, biases.pr, biases.prsf, biases.refr];
+        for (index, (bias, address)) in biases.into_iter().zip(addresses).enumerate() {
+            let Some(bias) = bias else { continue };
+            // diff, on and off are N-type, the others P-type
+            let bias = CaerCoarseFine {
+                coarse_value: bias.coarse,
+                fine_value: bias.fine,
+                enabled: true,
+                sex_n: index < 3,
+                type_normal: true,
+                current_level_normal: true,
+            };
+            // SAFETY: plain computation on a struct passed by value
+            let value = unsafe { (self.caer.bias_coarse_fine_generate)(bias) };
+            self.config_set(DAVIS_CONFIG_BIAS, address, value as u32)?;
+        }
+        Ok(())
+    }
+
+    // Waits for the next packet container and queues its polarity events. Returns false once the capture has ended
+    fn receive(&mut self) -> bool {
+        loop {
+            if self.shutdown.load(Ordering::SeqCst) || self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return false;
+            }
+            // SAFETY: the handle is open and the data started. Returns null when no data arrived in time
+            let container = unsafe { (self.caer.device_data_get)(self.handle) } as *mut u8;
+            if container.is_null() {
+                continue;
+            }
+            // SAFETY: the container and its packets are laid out as described above, and are freed once read
+            unsafe {
+                let packets_number = std::ptr::read_unaligned(container.add(CONTAINER_PACKETS_NUMBER_OFFSET) as *const i32).max(0) as usize;
+                for index in 0..packets_number {
+                    let packet = std::ptr::read_unaligned(container.add(CONTAINER_PACKETS_OFFSET + index * size_of::<*mut u8>()) as *const *mut u8);
+                    if packet.is_null() {
+                        continue;
+                    }
+                    self.queue_packet(packet);
+                    free(packet as *mut c_void);
+                }
+                free(container as *mut c_void);
+            }
+            if !self.events.is_empty() {
+                return true;
+            }
+        }
+    }
+
+    // Queues the valid polarity events of a packet
+    //
+    // SAFETY: the packet must hold a 28-byte header followed by as many events as the header says
+    unsafe fn queue_packet(&mut self, packet: *const u8) {
+        let header = PacketHeader::from_bytes(&*(packet as *const [u8; PACKET_HEADER_SIZE]));
+        if header.event_type != POLARITY_EVENT || header.event_size < POLARITY_EVENT_SIZE {
+            return;
+        }
+        let events = std::slice::from_raw_parts(packet.add(PACKET_HEADER_SIZE), header.event_size * header.event_number);
+        for bytes in events.chunks_exact(header.event_size) {
+            if let Some(event) = polarity_event(bytes, header.ts_overflow) {
+                self.events.push_back(event);
+            }
+        }
+    }
+}
+
+impl Drop for InivationCapture {
+    fn drop(&mut self) {
+        // SAFETY: the handle is open. Stopping the data joins the thread that may call on_shutdown
+        unsafe {
+            (self.caer.device_data_stop)(self.handle);
+            (self.caer.device_close)(&mut self.handle);
+        }
+    }
+}
+
+// Implemented like DvsRawDecoderEnum, so the camera can be used wherever a decoder is expected
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for InivationCapture {
+    // Returns a header like those of Prophesee .raw files, giving the camera and its geometry
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        let (width, height) = self.config.device.geometry();
+        let mut header = vec!["% camera_integrator_name iniVation\n".to_string(), format!("% plugin_name {}\n", self.config.device.name())];
+        if let Some(serial) = &self.config.serial {
+            header.push(format!("% serial_number {}\n", serial));
+        }
+        header.push(format!("% geometry {}x{}\n", width, height));
+        header.push("% end\n".to_string());
+        Ok(header)
+    }
+
+    // Returns the next polarity event of the camera. Returns Ok(None) once the capture has ended, like the decoders
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        if self.events.is_empty() && !self.receive() {
+            return Ok(None);
+        }
+        Ok(self.events.pop_front())
+    }
+}
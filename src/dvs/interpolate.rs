@@ -0,0 +1,124 @@
+use crate::dvs::DVSEvent;
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, HashMap};
+
+/*
+This file implements a reconstruction stage that fills gaps left by dropped events in a lossy stream.
+Synthetic events are interpolated between consecutive surviving events of the same pixel and polarity.
+Output stays in timestamp order: events are held in a reorder buffer until no later input can produce an
+earlier synthetic event, which bounds memory by the maximum interpolation gap.
+*/
+
+// How synthetic events are placed between two surviving events of the same pixel and polarity
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InterpolationStrategy {
+    // Insert factor - 1 evenly spaced events, undoing a uniform 1/factor thinning
+    Linear { factor: u32 },
+    // Repeat the earlier event every interval_us microseconds until the next event
+    Hold { interval_us: i64 },
+}
+
+// An event in the reorder buffer, ordered by timestamp and then by arrival
+struct Pending {
+    event: DVSEvent,
+    seq: u64,
+}
+
+impl PartialEq for Pending {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Pending {}
+
+impl PartialOrd for Pending {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Pending {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.event.timestamp, self.seq).cmp(&(other.event.timestamp, other.seq))
+    }
+}
+
+pub struct Interpolator {
+    strategy: InterpolationStrategy,
+    // Pairs of events further apart than this are left alone
+    max_gap_us: i64,
+    last_event: HashMap<(i16, i16), DVSEvent>,
+    buffer: BinaryHeap<Reverse<Pending>>,
+    seq: u64,
+    // Number of synthetic events produced so far
+    pub events_inserted: u64,
+}
+
+impl Interpolator {
+    pub fn new(strategy: InterpolationStrategy, max_gap_us: i64) -> Self {
+        Self {
+            strategy,
+            max_gap_us,
+            last_event: HashMap::new(),
+            buffer: BinaryHeap::new(),
+            seq: 0,
+            events_inserted: 0,
+        }
+    }
+
+    // Adds an event to the reorder buffer
+    fn push(&mut self, event: DVSEvent) {
+        self.buffer.push(Reverse(Pending { event, seq: self.seq }));
+        self.seq += 1;
+    }
+
+    // Inserts the synthetic events between two consecutive events of the same pixel
+    fn fill_gap(&mut self, previous: DVSEvent, next: &DVSEvent) {
+        let gap = next.timestamp - previous.timestamp;
+        if previous.polarity != next.polarity || gap <= 1 || gap > self.max_gap_us {
+            return;
+        }
+        let timestamps: Vec<i64> = match self.strategy {
+            InterpolationStrategy::Linear { factor } => (1..factor as i64)
+                .map(|i| previous.timestamp + gap * i / factor as i64)
+                .collect(),
+            InterpolationStrategy::Hold { interval_us } => (1..)
+                .map(|i| previous.timestamp + i * interval_us.max(1))
+                .take_while(|t| *t < next.timestamp)
+                .collect(),
+        };
+        for timestamp in timestamps {
+            if timestamp > previous.timestamp && timestamp < next.timestamp {
+                self.push(DVSEvent { timestamp, ..previous });
+                self.events_inserted += 1;
+            }
+        }
+    }
+
+    // Processes the next event of a time-ordered stream, passing any events that are ready to out
+    pub fn process(&mut self, event: DVSEvent, out: &mut impl FnMut(DVSEvent)) {
+        if let Some(previous) = self.last_event.insert((event.x, event.y), event) {
+            self.fill_gap(previous, &event);
+        }
+        self.push(event);
+
+        // Later inputs can only add synthetic events after (their timestamp - max gap)
+        let watermark = event.timestamp - self.max_gap_us;
+        while let Some(Reverse(pending)) = self.buffer.peek() {
+            if pending.event.timestamp > watermark {
+                break;
+            }
+            if let Some(Reverse(pending)) = self.buffer.pop() {
+                out(pending.event);
+            }
+        }
+    }
+
+    // Passes all remaining buffered events to out
+    pub fn finish(&mut self, out: &mut impl FnMut(DVSEvent)) {
+        while let Some(Reverse(pending)) = self.buffer.pop() {
+            out(pending.event);
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use crate::dvs::rng::SplitMix64;
+use crate::dvs::DVSEvent;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/*
+This file implements a network jitter simulator. Each event is assigned a transmission delay drawn from a
+configurable distribution, and events are re-emitted in order of arrival, so delays larger than the spacing
+between events reorder them. Arrival times can replace the original timestamps, or the original timestamps
+can be kept to study out-of-order delivery. Events are held in a buffer until no later input can arrive
+before them, which bounds memory by the largest delay.
+*/
+
+// Distribution of the delay added to each event, in microseconds
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum DelayDistribution {
+    Fixed { delay_us: f64 },
+    Uniform { min_us: f64, max_us: f64 },
+    // Negative samples are clamped to zero
+    Normal { mean_us: f64, std_dev_us: f64 },
+    // Heavy-tailed delays of at least scale_us. Smaller shapes give heavier tails
+    Pareto { scale_us: f64, shape: f64 },
+}
+
+impl DelayDistribution {
+    fn sample(&self, rng: &mut SplitMix64) -> f64 {
+        let delay = match *self {
+            DelayDistribution::Fixed { delay_us } => delay_us,
+            DelayDistribution::Uniform { min_us, max_us } => min_us + (max_us - min_us) * rng.next_f64(),
+            DelayDistribution::Normal { mean_us, std_dev_us } => {
+                // Box-Muller transform. 1 - u avoids the logarithm of zero
+                let u1 = 1.0 - rng.next_f64();
+                let u2 = rng.next_f64();
+                mean_us + std_dev_us * (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+            }
+            DelayDistribution::Pareto { scale_us, shape } => scale_us / (1.0 - rng.next_f64()).powf(1.0 / shape),
+        };
+        delay.max(0.0)
+    }
+}
+
+// Parses "fixed:<us>", "uniform:<min>,<max>", "normal:<mean>,<std dev>" or "pareto:<scale>,<shape>"
+impl std::str::FromStr for DelayDistribution {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (name, params) = s.split_once(':').unwrap_or((s, ""));
+        let params: Vec<f64> = params
+            .split(',')
+            .filter(|param| !param.trim().is_empty())
+            .map(|param| param.trim().parse::<f64>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| anyhow::anyhow!("Invalid jitter parameters in '{}'", s))?;
+        match (name.trim().to_lowercase().as_str(), params.as_slice()) {
+            ("fixed", [delay_us]) => Ok(DelayDistribution::Fixed { delay_us: *delay_us }),
+            ("uniform", [min_us, max_us]) if min_us <= max_us => Ok(DelayDistribution::Uniform { min_us: *min_us, max_us: *max_us }),
+            ("normal", [mean_us, std_dev_us]) => Ok(DelayDistribution::Normal { mean_us: *mean_us, std_dev_us: *std_dev_us }),
+            ("pareto", [scale_us, shape]) if *shape > 0.0 => Ok(DelayDistribution::Pareto { scale_us: *scale_us, shape: *shape }),
+            _ => anyhow::bail!(
+                "Unsupported jitter '{}'. Expected fixed:<us>, uniform:<min>,<max>, normal:<mean>,<std dev> or pareto:<scale>,<shape>",
+                s
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct JitterOptions {
+    pub delay: DelayDistribution,
+    // Replace each event's timestamp with its arrival time, instead of keeping the original timestamp
+    pub restamp: bool,
+    pub seed: u64,
+}
+
+// An event in the arrival buffer, ordered by arrival time and then by sending order
+struct InFlight {
+    event: DVSEvent,
+    arrival: i64,
+    seq: u64,
+}
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for InFlight {}
+
+impl PartialOrd for InFlight {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlight {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.arrival, self.seq).cmp(&(other.arrival, other.seq))
+    }
+}
+
+pub struct Jitter {
+    options: JitterOptions,
+    rng: SplitMix64,
+    buffer: BinaryHeap<Reverse<InFlight>>,
+    seq: u64,
+    // Number of events that arrived before an event sent earlier
+    pub events_reordered: u64,
+    // Latest sending time passed to out, for counting reordered events
+    last_sent: Option<i64>,
+}
+
+impl Jitter {
+    pub fn new(options: JitterOptions) -> Self {
+        Self {
+            options,
+            rng: SplitMix64::new(options.seed),
+            buffer: BinaryHeap::new(),
+            seq: 0,
+            events_reordered: 0,
+            last_sent: None,
+        }
+    }
+
+    fn emit(&mut self, pending: InFlight, out: &mut impl FnMut(DVSEvent)) {
+        if self.last_sent.is_some_and(|last| pending.event.timestamp < last) {
+            self.events_reordered += 1;
+        }
+        self.last_sent = Some(self.last_sent.map_or(pending.event.timestamp, |last| last.max(pending.event.timestamp)));
+        let mut event = pending.event;
+        if self.options.restamp {
+            event.timestamp = pending.arrival;
+        }
+        out(event);
+    }
+
+    // Sends the next event of a time-ordered stream, passing any events that have arrived to out
+    pub fn process(&mut self, event: DVSEvent, out: &mut impl FnMut(DVSEvent)) {
+        let delay = self.options.delay.sample(&mut self.rng).round() as i64;
+        self.buffer.push(Reverse(InFlight { event, arrival: event.timestamp.saturating_add(delay), seq: self.seq }));
+        self.seq += 1;
+
+        // Delays are never negative, so later inputs can't arrive before this event was sent
+        while let Some(Reverse(pending)) = self.buffer.peek() {
+            if pending.arrival > event.timestamp {
+                break;
+            }
+            if let Some(Reverse(pending)) = self.buffer.pop() {
+                self.emit(pending, out);
+            }
+        }
+    }
+
+    // Passes all events still in flight to out
+    pub fn finish(&mut self, out: &mut impl FnMut(DVSEvent)) {
+        while let Some(Reverse(pending)) = self.buffer.pop() {
+            self.emit(pending, out);
+        }
+    }
+}
@@ -0,0 +1,636 @@
+use crate::dvs::adaptive::{AdaptiveLoss, Aimd, ThroughputEstimate};
+use crate::dvs::bitrate::{bits_in, BandwidthBudget, RunningBitrate};
+use crate::dvs::chunk::{Chunk, Chunker};
+use crate::dvs::rng::SplitMix64;
+use crate::dvs::filters::DvsFilter;
+use crate::dvs::DVSEvent;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io;
+
+/*
+This file implements the loss module, which simulates streaming events over a link with limited bandwidth.
+The stream is split into chunks covering a fixed time window (see chunk.rs), and a LossModel decides which events of each
+chunk are kept, given the number of events the bandwidth allows in that window. LossFilter is a filter (see
+filters.rs) holding back one chunk at a time, so memory use is bounded by the size of a chunk rather than the
+recording. The cost of events on the link is accounted for by a BandwidthBudget, see bitrate.rs.
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LossOptions {
+    pub budget: BandwidthBudget,
+    // Time covered by each chunk, in microseconds
+    pub chunk_us: i64,
+}
+
+impl Default for LossOptions {
+    fn default() -> Self {
+        LossOptions {
+            budget: BandwidthBudget::default(),
+            chunk_us: 10_000,
+        }
+    }
+}
+
+impl LossOptions {
+    // Number of events that fit in the bandwidth of one chunk
+    pub fn events_per_chunk(&self) -> usize {
+        self.budget.events_in(self.chunk_us)
+    }
+}
+
+// Decides which events survive the simulated link. The events of each chunk are offered in order,
+// after begin_chunk is called with all of the chunk's events and the number of events that fit in the budget
+pub trait LossModel {
+    fn begin_chunk(&mut self, chunk: &[DVSEvent], budget: usize) {
+        let _ = (chunk, budget);
+    }
+    fn admit(&mut self, event: &DVSEvent) -> bool;
+}
+
+impl<M: LossModel + ?Sized> LossModel for Box<M> {
+    fn begin_chunk(&mut self, chunk: &[DVSEvent], budget: usize) {
+        (**self).begin_chunk(chunk, budget)
+    }
+
+    fn admit(&mut self, event: &DVSEvent) -> bool {
+        (**self).admit(event)
+    }
+}
+
+// Keeps the first events of each chunk and drops the rest ("End Biased" loss)
+#[derive(Debug, Default)]
+pub struct TailDrop {
+    remaining: usize,
+}
+
+impl LossModel for TailDrop {
+    fn begin_chunk(&mut self, _chunk: &[DVSEvent], budget: usize) {
+        self.remaining = budget;
+    }
+
+    fn admit(&mut self, _event: &DVSEvent) -> bool {
+        if self.remaining == 0 {
+            return false;
+        }
+        self.remaining -= 1;
+        true
+    }
+}
+
+// Keeps events spread evenly over each chunk ("Evenly Distributed" loss)
+#[derive(Debug, Default)]
+pub struct UniformThinning {
+    len: usize,
+    budget: usize,
+    index: usize,
+}
+
+impl LossModel for UniformThinning {
+    fn begin_chunk(&mut self, chunk: &[DVSEvent], budget: usize) {
+        self.len = chunk.len();
+        self.budget = budget.min(chunk.len());
+        self.index = 0;
+    }
+
+    // Keeps event i when the running share of the budget crosses an integer, which keeps exactly budget
+    // events at even spacing
+    fn admit(&mut self, _event: &DVSEvent) -> bool {
+        let i = self.index;
+        self.index += 1;
+        (i + 1) * self.budget / self.len > i * self.budget / self.len
+    }
+}
+
+// Drops each event independently with a fixed probability, regardless of the budget
+#[derive(Debug)]
+pub struct RandomDrop {
+    probability: f64,
+    rng: SplitMix64,
+}
+
+impl RandomDrop {
+    pub fn new(probability: f64, seed: u64) -> Self {
+        RandomDrop {
+            probability,
+            rng: SplitMix64::new(seed),
+        }
+    }
+}
+
+impl LossModel for RandomDrop {
+    fn admit(&mut self, _event: &DVSEvent) -> bool {
+        !self.rng.chance(self.probability)
+    }
+}
+
+// Admits events while the bucket holds enough credit for them. Credit accumulates at the link rate as
+// stream time passes, up to the burst size, so bursts are absorbed without hard chunk boundaries
+#[derive(Debug)]
+pub struct TokenBucket {
+    // Credit added per microsecond, in bits (one megabit per second is one bit per microsecond)
+    rate_mbps: f64,
+    burst_bits: f64,
+    bits_per_event: f64,
+    tokens: f64,
+    last_timestamp: Option<i64>,
+}
+
+impl TokenBucket {
+    // Creates a bucket that starts full. Each event costs its effective size under the budget
+    pub fn new(budget: &BandwidthBudget, burst_bits: f64) -> Self {
+        TokenBucket {
+            rate_mbps: budget.bandwidth_mbps,
+            burst_bits,
+            bits_per_event: budget.effective_bits_per_event(),
+            tokens: burst_bits,
+            last_timestamp: None,
+        }
+    }
+}
+
+impl LossModel for TokenBucket {
+    fn admit(&mut self, event: &DVSEvent) -> bool {
+        if let Some(last) = self.last_timestamp {
+            // Out of order events add no credit
+            let elapsed_us = (event.timestamp - last).max(0);
+            self.tokens = (self.tokens + bits_in(self.rate_mbps, elapsed_us)).min(self.burst_bits);
+        }
+        self.last_timestamp = Some(self.last_timestamp.map_or(event.timestamp, |last| last.max(event.timestamp)));
+        if self.tokens < self.bits_per_event {
+            return false;
+        }
+        self.tokens -= self.bits_per_event;
+        true
+    }
+}
+
+// Keeps the budget's worth of events with the highest priority in each chunk. The priority function is
+// pluggable, e.g. PriorityLoss::age_weighted keeps the newest events. Ties keep the later event
+pub struct PriorityLoss<F: FnMut(&DVSEvent) -> f64> {
+    priority: F,
+    // Whether each event of the chunk is kept
+    keep: Vec<bool>,
+    index: usize,
+}
+
+impl<F: FnMut(&DVSEvent) -> f64> PriorityLoss<F> {
+    pub fn new(priority: F) -> Self {
+        PriorityLoss {
+            priority,
+            keep: Vec::new(),
+            index: 0,
+        }
+    }
+}
+
+impl PriorityLoss<fn(&DVSEvent) -> f64> {
+    // Keeps the newest events of each chunk, as freshness matters most for real-time streaming
+    pub fn age_weighted() -> Self {
+        PriorityLoss::new(|event| event.timestamp as f64)
+    }
+}
+
+impl<F: FnMut(&DVSEvent) -> f64> LossModel for PriorityLoss<F> {
+    fn begin_chunk(&mut self, chunk: &[DVSEvent], budget: usize) {
+        self.index = 0;
+        self.keep.clear();
+        self.keep.resize(chunk.len(), chunk.len() <= budget);
+        if chunk.len() <= budget {
+            return;
+        }
+        let mut ranked: Vec<(f64, usize)> = chunk.iter().map(|event| (self.priority)(event)).zip(0..).collect();
+        ranked.sort_by(|(priority_a, index_a), (priority_b, index_b)| {
+            priority_b.total_cmp(priority_a).then(index_b.cmp(index_a))
+        });
+        for (_, index) in ranked.into_iter().take(budget) {
+            self.keep[index] = true;
+        }
+    }
+
+    fn admit(&mut self, _event: &DVSEvent) -> bool {
+        let keep = self.keep.get(self.index).copied().unwrap_or(true);
+        self.index += 1;
+        keep
+    }
+}
+
+// Keeps the events of the most active tiles of each chunk, dropping low-activity regions first, so salient
+// motion is preserved. The tile that crosses the budget keeps its first events
+#[derive(Debug)]
+pub struct Spatial {
+    tile_size: u16,
+    // Number of events still admitted from each tile of the chunk, or None if the chunk fits in the budget
+    quotas: Option<HashMap<(i16, i16), usize>>,
+}
+
+impl Spatial {
+    pub fn new(tile_size: u16) -> Self {
+        Spatial {
+            tile_size: tile_size.max(1),
+            quotas: None,
+        }
+    }
+
+    fn tile(&self, event: &DVSEvent) -> (i16, i16) {
+        let size = self.tile_size as i16;
+        (event.x.div_euclid(size), event.y.div_euclid(size))
+    }
+}
+
+impl LossModel for Spatial {
+    fn begin_chunk(&mut self, chunk: &[DVSEvent], budget: usize) {
+        if chunk.len() <= budget {
+            self.quotas = None;
+            return;
+        }
+        // Activity histogram of the chunk
+        let mut histogram: HashMap<(i16, i16), usize> = HashMap::new();
+        for event in chunk {
+            *histogram.entry(self.tile(event)).or_insert(0) += 1;
+        }
+        // Most active tiles first, ties broken by position so the outcome is reproducible
+        let mut tiles: Vec<((i16, i16), usize)> = histogram.into_iter().collect();
+        tiles.sort_by(|(tile_a, count_a), (tile_b, count_b)| count_b.cmp(count_a).then(tile_a.cmp(tile_b)));
+        let mut remaining = budget;
+        let quotas = tiles
+            .into_iter()
+            .map(|(tile, count)| {
+                let quota = count.min(remaining);
+                remaining -= quota;
+                (tile, quota)
+            })
+            .collect();
+        self.quotas = Some(quotas);
+    }
+
+    fn admit(&mut self, event: &DVSEvent) -> bool {
+        let tile = self.tile(event);
+        let Some(quotas) = &mut self.quotas else {
+            return true;
+        };
+        match quotas.get_mut(&tile) {
+            Some(quota) if *quota > 0 => {
+                *quota -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+// Transition and drop probabilities of the Gilbert-Elliott model
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GilbertElliottParams {
+    // Probability of moving from the good to the bad state, per event
+    pub good_to_bad: f64,
+    // Probability of moving from the bad to the good state, per event
+    pub bad_to_good: f64,
+    // Drop probability in each state
+    pub loss_good: f64,
+    pub loss_bad: f64,
+}
+
+impl Default for GilbertElliottParams {
+    // Mostly lossless, with bursts averaging 10 events of heavy loss
+    fn default() -> Self {
+        GilbertElliottParams {
+            good_to_bad: 0.01,
+            bad_to_good: 0.1,
+            loss_good: 0.0,
+            loss_bad: 1.0,
+        }
+    }
+}
+
+// Drops events in bursts, like a wireless link: a two-state Markov chain switches between a good and a
+// bad state, each with its own drop probability. Independent of the budget
+#[derive(Debug)]
+pub struct GilbertElliott {
+    params: GilbertElliottParams,
+    bad: bool,
+    rng: SplitMix64,
+}
+
+impl GilbertElliott {
+    // Creates a model that starts in the good state
+    pub fn new(params: GilbertElliottParams, seed: u64) -> Self {
+        GilbertElliott {
+            params,
+            bad: false,
+            rng: SplitMix64::new(seed),
+        }
+    }
+}
+
+impl LossModel for GilbertElliott {
+    fn admit(&mut self, _event: &DVSEvent) -> bool {
+        let transition = if self.bad { self.params.bad_to_good } else { self.params.good_to_bad };
+        if self.rng.chance(transition) {
+            self.bad = !self.bad;
+        }
+        let loss = if self.bad { self.params.loss_bad } else { self.params.loss_good };
+        !self.rng.chance(loss)
+    }
+}
+
+// Parameters passed to loss model factories, from the command line or the caller
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct LossParams {
+    // Drop probability of random loss models
+    pub probability: f64,
+    // Seed of random loss models
+    pub seed: u64,
+    // Link rate and event cost of rate-based models
+    pub budget: BandwidthBudget,
+    // Burst size of the token bucket, in bits
+    pub burst_bits: f64,
+    pub gilbert_elliott: GilbertElliottParams,
+    // Tile size of the spatial model, in pixels
+    pub tile_size: u16,
+    // Time covered by each chunk, in microseconds, for models that convert between events and rates
+    pub chunk_us: i64,
+}
+
+impl Default for LossParams {
+    fn default() -> Self {
+        LossParams {
+            probability: 0.1,
+            seed: 0,
+            budget: BandwidthBudget::default(),
+            burst_bits: 100_000.0,
+            gilbert_elliott: GilbertElliottParams::default(),
+            tile_size: 32,
+            chunk_us: 10_000,
+        }
+    }
+}
+
+type LossModelFactory = Box<dyn Fn(&LossParams) -> Box<dyn LossModel>>;
+
+// Loss models available by name, e.g. for selection from the command line. Custom models can be added
+// with register
+pub struct LossModels {
+    factories: Vec<(String, LossModelFactory)>,
+}
+
+impl Default for LossModels {
+    // The built-in models
+    fn default() -> Self {
+        let mut models = LossModels { factories: Vec::new() };
+        models.register("end-biased", |_| Box::new(TailDrop::default()));
+        models.register("evenly-distributed", |_| Box::new(UniformThinning::default()));
+        models.register("random", |params| Box::new(RandomDrop::new(params.probability, params.seed)));
+        models.register("token-bucket", |params| {
+            Box::new(TokenBucket::new(&params.budget, params.burst_bits))
+        });
+        models.register("age-weighted", |_| Box::new(PriorityLoss::age_weighted()));
+        models.register("spatial", |params| Box::new(Spatial::new(params.tile_size)));
+        models.register("gilbert-elliott", |params| Box::new(GilbertElliott::new(params.gilbert_elliott, params.seed)));
+        models.register("adaptive", |params| Box::new(AdaptiveLoss::new(TailDrop::default(), Aimd::default(), params)));
+        models.register("adaptive-throughput", |params| {
+            Box::new(AdaptiveLoss::new(TailDrop::default(), ThroughputEstimate::default(), params))
+        });
+        models
+    }
+}
+
+impl LossModels {
+    // Adds a model, replacing any model registered under the same name
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&LossParams) -> Box<dyn LossModel> + 'static,
+    {
+        self.factories.retain(|(existing, _)| existing != name);
+        self.factories.push((name.to_string(), Box::new(factory)));
+    }
+
+    // Names of the registered models, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub fn create(&self, name: &str, params: &LossParams) -> anyhow::Result<Box<dyn LossModel>> {
+        match self.factories.iter().find(|(existing, _)| existing == name) {
+            Some((_, factory)) => Ok(factory(params)),
+            None => anyhow::bail!("Unknown loss model '{}'. Expected one of: {}", name, self.names().join(", ")),
+        }
+    }
+}
+
+// Outcome of the loss model for one chunk
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChunkStats {
+    // Start of the chunk, in microseconds
+    pub start_us: i64,
+    pub events_in: u64,
+    pub events_out: u64,
+    // Bitrate of the surviving events on the link, in megabits per second
+    pub bitrate_mbps: f64,
+}
+
+// Summary of a loss simulation, for aggregating experiments
+#[derive(Debug, Clone, PartialEq)]
+pub struct LossReport {
+    pub options: LossOptions,
+    pub events_in: u64,
+    pub events_out: u64,
+    // Bitrate of the surviving events over the whole stream, in megabits per second
+    pub mean_bitrate_mbps: f64,
+    // Chunks with at least one input event, in stream order
+    pub chunks: Vec<ChunkStats>,
+}
+
+// Formats a number for JSON, which has no representation for infinity or NaN
+pub(crate) fn json_number(value: f64) -> String {
+    if value.is_finite() {
+        value.to_string()
+    } else {
+        "null".to_string()
+    }
+}
+
+impl LossReport {
+    pub fn events_dropped(&self) -> u64 {
+        self.events_in - self.events_out
+    }
+
+    pub fn to_json(&self) -> String {
+        let budget = &self.options.budget;
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"bandwidth_mbps\":{},\"bits_per_event\":{},\"packet_payload_bytes\":{},\"packet_overhead_bytes\":{},\"chunk_us\":{},",
+            json_number(budget.bandwidth_mbps),
+            json_number(budget.bits_per_event),
+            budget.packet_payload_bytes,
+            budget.packet_overhead_bytes,
+            self.options.chunk_us
+        );
+        let _ = write!(
+            json,
+            "\"events_in\":{},\"events_out\":{},\"events_dropped\":{},\"mean_bitrate_mbps\":{},\"chunks\":[",
+            self.events_in,
+            self.events_out,
+            self.events_dropped(),
+            json_number(self.mean_bitrate_mbps)
+        );
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"start_us\":{},\"events_in\":{},\"events_out\":{},\"events_dropped\":{},\"bitrate_mbps\":{}}}",
+                chunk.start_us,
+                chunk.events_in,
+                chunk.events_out,
+                chunk.events_in - chunk.events_out,
+                json_number(chunk.bitrate_mbps)
+            );
+        }
+        json.push_str("]}\n");
+        json
+    }
+
+    // One row per chunk, after "#" comments with the totals and options
+    pub fn to_csv(&self) -> String {
+        let budget = &self.options.budget;
+        let mut csv = String::new();
+        let _ = writeln!(csv, "# bandwidth_mbps: {}", budget.bandwidth_mbps);
+        let _ = writeln!(csv, "# bits_per_event: {}", budget.bits_per_event);
+        let _ = writeln!(csv, "# packet_payload_bytes: {}", budget.packet_payload_bytes);
+        let _ = writeln!(csv, "# packet_overhead_bytes: {}", budget.packet_overhead_bytes);
+        let _ = writeln!(csv, "# chunk_us: {}", self.options.chunk_us);
+        let _ = writeln!(csv, "# events_in: {}", self.events_in);
+        let _ = writeln!(csv, "# events_out: {}", self.events_out);
+        let _ = writeln!(csv, "# events_dropped: {}", self.events_dropped());
+        let _ = writeln!(csv, "# mean_bitrate_mbps: {}", self.mean_bitrate_mbps);
+        csv.push_str("start_us,events_in,events_out,events_dropped,bitrate_mbps\n");
+        for chunk in &self.chunks {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{}",
+                chunk.start_us,
+                chunk.events_in,
+                chunk.events_out,
+                chunk.events_in - chunk.events_out,
+                chunk.bitrate_mbps
+            );
+        }
+        csv
+    }
+
+    // Writes the report as CSV if the path ends in .csv, and as JSON otherwise
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let contents = if path.to_lowercase().ends_with(".csv") { self.to_csv() } else { self.to_json() };
+        std::fs::write(path, contents)
+    }
+}
+
+// Applies bandwidth-capped loss to a stream, chunk by chunk. Wrap a decoder in a filters::Filtered with a
+// LossFilter to read the surviving events of any decoder
+pub struct LossFilter<M: LossModel = Box<dyn LossModel>> {
+    options: LossOptions,
+    model: M,
+    chunker: Chunker,
+    // Number of events taken and let through so far
+    pub events_in: u64,
+    pub events_out: u64,
+    // Bitrate of the surviving events of the chunks so far
+    bitrate: RunningBitrate,
+    // Statistics of each chunk, if recording was enabled with record_chunks
+    chunk_stats: Option<Vec<ChunkStats>>,
+}
+
+impl<M: LossModel> LossFilter<M> {
+    pub fn new(options: LossOptions, model: M) -> Self {
+        Self {
+            options,
+            model,
+            chunker: Chunker::new(options.chunk_us),
+            events_in: 0,
+            events_out: 0,
+            bitrate: RunningBitrate::default(),
+            chunk_stats: None,
+        }
+    }
+
+    // Keeps statistics of every chunk for the report. Off by default, as they grow with the recording
+    pub fn record_chunks(mut self) -> Self {
+        self.chunk_stats = Some(Vec::new());
+        self
+    }
+
+    // Summary of the events processed so far
+    pub fn report(&self) -> LossReport {
+        LossReport {
+            options: self.options,
+            events_in: self.events_in,
+            events_out: self.events_out,
+            mean_bitrate_mbps: self.bitrate.mean_mbps(),
+            chunks: self.chunk_stats.clone().unwrap_or_default(),
+        }
+    }
+
+    // Number of events dropped so far. The events of the chunk being read are not counted until it is complete
+    pub fn events_dropped(&self) -> u64 {
+        self.events_in - self.events_out - self.chunker.pending().len() as u64
+    }
+
+    // Lets the model decide which events of the chunk survive, and passes them to out
+    fn end_chunk(&mut self, chunk: Chunk, out: &mut dyn FnMut(DVSEvent)) {
+        let chunk_us = self.chunker.chunk_us();
+        self.model.begin_chunk(&chunk.events, self.options.events_per_chunk());
+        let events_in = chunk.events.len();
+        let mut events_out = 0;
+        for event in chunk.events {
+            if self.model.admit(&event) {
+                out(event);
+                events_out += 1;
+            }
+        }
+        self.events_out += events_out as u64;
+        self.bitrate.add(self.options.budget.bits_for_events(events_out), chunk_us);
+        if let Some(chunk_stats) = &mut self.chunk_stats {
+            chunk_stats.push(ChunkStats {
+                start_us: chunk.start_us,
+                events_in: events_in as u64,
+                events_out: events_out as u64,
+                bitrate_mbps: self.options.budget.bitrate_mbps(events_out, chunk_us),
+            });
+        }
+    }
+}
+
+impl<M: LossModel> DvsFilter for LossFilter<M> {
+    // Holds events back until their chunk is complete
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        self.events_in += 1;
+        if let Some(chunk) = self.chunker.push(event) {
+            self.end_chunk(chunk, out);
+        }
+    }
+
+    fn finish(&mut self, out: &mut dyn FnMut(DVSEvent)) {
+        if let Some(chunk) = self.chunker.finish() {
+            self.end_chunk(chunk, out);
+        }
+    }
+
+    // Starts over from the first chunk
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        self.chunker.clear();
+        self.events_in = 0;
+        self.events_out = 0;
+        self.bitrate = RunningBitrate::default();
+        if let Some(chunk_stats) = &mut self.chunk_stats {
+            chunk_stats.clear();
+        }
+        Ok(header)
+    }
+
+    fn summary(&self) -> Option<String> {
+        Some(format!("Kept {} of {} events ({} dropped)", self.events_out, self.events_in, self.events_dropped()))
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+
+/*
+This file implements a minimal writer for the MCAP container format (https://mcap.dev/spec).
+An MCAP file is the magic bytes, a Header record, a data section of Schema, Channel and Message records,
+a DataEnd record, a summary section, a Footer record and the magic bytes again. Records are an opcode, a u64
+content length and the content, with all integers little-endian. CRCs are written as zero, which the spec allows.
+With a chunk window, messages are grouped into uncompressed Chunk records covering that much log time, each
+followed by per-channel MessageIndex records, and the summary section lists every chunk's time range and
+offset, so readers can seek to a point in time without scanning the file.
+*/
+
+const MAGIC: &[u8] = b"\x89MCAP0\r\n";
+
+// Record opcodes
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_MESSAGE_INDEX: u8 = 0x07;
+const OP_CHUNK_INDEX: u8 = 0x08;
+const OP_STATISTICS: u8 = 0x0B;
+const OP_SUMMARY_OFFSET: u8 = 0x0E;
+const OP_DATA_END: u8 = 0x0F;
+
+// Size of a record's opcode and content length
+const RECORD_PREFIX_SIZE: usize = 9;
+
+// Name of this library in the Header record
+const LIBRARY: &str = concat!("dvs-streaming ", env!("CARGO_PKG_VERSION"));
+
+// Appends MCAP primitive fields to a record's content
+#[derive(Default)]
+struct Record {
+    content: Vec<u8>,
+}
+
+impl Record {
+    fn u16(mut self, value: u16) -> Self {
+        self.content.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, value: u32) -> Self {
+        self.content.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u64(mut self, value: u64) -> Self {
+        self.content.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    // A string, byte array, array or map with a u32 length prefix
+    fn bytes(self, value: &[u8]) -> Self {
+        let mut record = self.u32(value.len() as u32);
+        record.content.extend_from_slice(value);
+        record
+    }
+
+    fn string(self, value: &str) -> Self {
+        self.bytes(value.as_bytes())
+    }
+
+    // A map of strings, prefixed with its length in bytes
+    fn map(self, value: &BTreeMap<String, String>) -> Self {
+        let entries = value.iter().fold(Record::default(), |record, (k, v)| record.string(k).string(v));
+        self.bytes(&entries.content)
+    }
+
+    // A map from channel ids to counts or offsets, prefixed with its length in bytes
+    fn channel_map(self, value: &BTreeMap<u16, u64>) -> Self {
+        let entries = value.iter().fold(Record::default(), |record, (k, v)| record.u16(*k).u64(*v));
+        self.bytes(&entries.content)
+    }
+
+    // Bytes with no length prefix, which must be the last field of the record
+    fn raw(mut self, value: &[u8]) -> Self {
+        self.content.extend_from_slice(value);
+        self
+    }
+
+    // The record with its opcode and length
+    fn encode(self, opcode: u8) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.content.len() + RECORD_PREFIX_SIZE);
+        bytes.push(opcode);
+        bytes.extend_from_slice(&(self.content.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&self.content);
+        bytes
+    }
+}
+
+// Messages of the chunk being built
+#[derive(Default)]
+struct ChunkBuilder {
+    records: Vec<u8>,
+    message_start_time: u64,
+    message_end_time: u64,
+    // For each channel, the log time and offset in records of its messages
+    message_indexes: BTreeMap<u16, Vec<(u64, u64)>>,
+}
+
+pub struct McapWriter<W: Write> {
+    writer: W,
+    // Number of bytes written so far
+    position: u64,
+    next_schema_id: u16,
+    next_channel_id: u16,
+    finished: bool,
+    // Log time covered by each chunk in nanoseconds, or None to write messages outside chunks
+    chunk_window_ns: Option<u64>,
+    chunk: ChunkBuilder,
+    // Records repeated in the summary section
+    schemas: Vec<Vec<u8>>,
+    channels: Vec<Vec<u8>>,
+    chunk_indexes: Vec<Vec<u8>>,
+    // Statistics
+    message_count: u64,
+    channel_message_counts: BTreeMap<u16, u64>,
+    message_time_range: Option<(u64, u64)>,
+}
+
+impl<W: Write> McapWriter<W> {
+    // Creates a writer that writes messages outside chunks
+    pub fn new(writer: W) -> Self {
+        McapWriter {
+            writer,
+            position: 0,
+            next_schema_id: 1,
+            next_channel_id: 0,
+            finished: false,
+            chunk_window_ns: None,
+            chunk: ChunkBuilder::default(),
+            schemas: Vec::new(),
+            channels: Vec::new(),
+            chunk_indexes: Vec::new(),
+            message_count: 0,
+            channel_message_counts: BTreeMap::new(),
+            message_time_range: None,
+        }
+    }
+
+    // Creates a writer that groups messages into indexed chunks covering window_ns of log time each
+    pub fn with_chunk_window(writer: W, window_ns: u64) -> Self {
+        Self {
+            chunk_window_ns: Some(window_ns.max(1)),
+            ..Self::new(writer)
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        self.writer.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    // Writes the magic and the Header record. The profile names the conventions of the file, e.g. "ros2"
+    pub fn start(&mut self, profile: &str) -> anyhow::Result<()> {
+        self.write_bytes(MAGIC)?;
+        self.write_bytes(&Record::default().string(profile).string(LIBRARY).encode(OP_HEADER))
+    }
+
+    // Adds a message schema, returning its id. Encoding is e.g. "ros2msg" or "jsonschema"
+    pub fn add_schema(&mut self, name: &str, encoding: &str, data: &[u8]) -> anyhow::Result<u16> {
+        let id = self.next_schema_id;
+        self.next_schema_id += 1;
+        let record = Record::default().u16(id).string(name).string(encoding).bytes(data).encode(OP_SCHEMA);
+        self.write_bytes(&record)?;
+        self.schemas.push(record);
+        Ok(id)
+    }
+
+    // Adds a channel of messages on a topic, returning its id. Message encoding is e.g. "cdr" or "json"
+    pub fn add_channel(&mut self, schema_id: u16, topic: &str, message_encoding: &str, metadata: &BTreeMap<String, String>) -> anyhow::Result<u16> {
+        let id = self.next_channel_id;
+        self.next_channel_id += 1;
+        let record = Record::default().u16(id).u16(schema_id).string(topic).string(message_encoding).map(metadata).encode(OP_CHANNEL);
+        self.write_bytes(&record)?;
+        self.channels.push(record);
+        Ok(id)
+    }
+
+    // Writes a message. Times are nanoseconds
+    pub fn write_message(&mut self, channel_id: u16, sequence: u32, log_time: u64, publish_time: u64, data: &[u8]) -> anyhow::Result<()> {
+        let record = Record::default().u16(channel_id).u32(sequence).u64(log_time).u64(publish_time).raw(data).encode(OP_MESSAGE);
+        self.message_count += 1;
+        *self.channel_message_counts.entry(channel_id).or_insert(0) += 1;
+        self.message_time_range = Some(match self.message_time_range {
+            Some((start, end)) => (start.min(log_time), end.max(log_time)),
+            None => (log_time, log_time),
+        });
+
+        let Some(window) = self.chunk_window_ns else {
+            return self.write_bytes(&record);
+        };
+        if !self.chunk.records.is_empty() && log_time >= self.chunk.message_start_time.saturating_add(window) {
+            self.write_chunk()?;
+        }
+        if self.chunk.records.is_empty() {
+            self.chunk.message_start_time = log_time;
+            self.chunk.message_end_time = log_time;
+        }
+        self.chunk.message_start_time = self.chunk.message_start_time.min(log_time);
+        self.chunk.message_end_time = self.chunk.message_end_time.max(log_time);
+        let offset = self.chunk.records.len() as u64;
+        self.chunk.message_indexes.entry(channel_id).or_default().push((log_time, offset));
+        self.chunk.records.extend_from_slice(&record);
+        Ok(())
+    }
+
+    // Writes the current chunk and its message indexes, keeping its chunk index for the summary
+    fn write_chunk(&mut self) -> anyhow::Result<()> {
+        if self.chunk.records.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::take(&mut self.chunk);
+        let size = chunk.records.len() as u64;
+        // Uncompressed, so the compressed and uncompressed sizes are the same
+        let record = Record::default()
+            .u64(chunk.message_start_time)
+            .u64(chunk.message_end_time)
+            .u64(size)
+            .u32(0)
+            .string("")
+            .u64(size)
+            .raw(&chunk.records)
+            .encode(OP_CHUNK);
+        let chunk_start_offset = self.position;
+        let chunk_length = record.len() as u64;
+        self.write_bytes(&record)?;
+
+        let message_index_start = self.position;
+        let mut message_index_offsets: BTreeMap<u16, u64> = BTreeMap::new();
+        for (channel_id, mut entries) in chunk.message_indexes {
+            entries.sort_by_key(|(log_time, _)| *log_time);
+            let index = entries.iter().fold(Record::default(), |record, (log_time, offset)| record.u64(*log_time).u64(*offset));
+            message_index_offsets.insert(channel_id, self.position);
+            self.write_bytes(&Record::default().u16(channel_id).bytes(&index.content).encode(OP_MESSAGE_INDEX))?;
+        }
+
+        let chunk_index = Record::default()
+            .u64(chunk.message_start_time)
+            .u64(chunk.message_end_time)
+            .u64(chunk_start_offset)
+            .u64(chunk_length)
+            .channel_map(&message_index_offsets)
+            .u64(self.position - message_index_start)
+            .string("")
+            .u64(size)
+            .u64(size)
+            .encode(OP_CHUNK_INDEX);
+        self.chunk_indexes.push(chunk_index);
+        Ok(())
+    }
+
+    // Writes a group of summary records, returning the SummaryOffset record pointing to it
+    fn write_summary_group(&mut self, opcode: u8, records: &[Vec<u8>]) -> anyhow::Result<Option<Vec<u8>>> {
+        if records.is_empty() {
+            return Ok(None);
+        }
+        let start = self.position;
+        for record in records {
+            self.write_bytes(record)?;
+        }
+        let length = self.position - start;
+        Ok(Some(Record::default().raw(&[opcode]).u64(start).u64(length).encode(OP_SUMMARY_OFFSET)))
+    }
+
+    // Writes the last chunk, the DataEnd record, the summary section, the Footer record and the closing
+    // magic. Does nothing if already finished
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        self.write_chunk()?;
+        self.write_bytes(&Record::default().u32(0).encode(OP_DATA_END))?;
+
+        let summary_start = self.position;
+        let (message_start_time, message_end_time) = self.message_time_range.unwrap_or((0, 0));
+        let statistics = Record::default()
+            .u64(self.message_count)
+            .u16(self.schemas.len() as u16)
+            .u32(self.channels.len() as u32)
+            // No attachments or metadata records
+            .u32(0)
+            .u32(0)
+            .u32(self.chunk_indexes.len() as u32)
+            .u64(message_start_time)
+            .u64(message_end_time)
+            .channel_map(&self.channel_message_counts)
+            .encode(OP_STATISTICS);
+
+        let schemas = std::mem::take(&mut self.schemas);
+        let channels = std::mem::take(&mut self.channels);
+        let chunk_indexes = std::mem::take(&mut self.chunk_indexes);
+        let mut summary_offsets: Vec<Vec<u8>> = Vec::new();
+        for (opcode, records) in [
+            (OP_SCHEMA, schemas),
+            (OP_CHANNEL, channels),
+            (OP_STATISTICS, vec![statistics]),
+            (OP_CHUNK_INDEX, chunk_indexes),
+        ] {
+            summary_offsets.extend(self.write_summary_group(opcode, &records)?);
+        }
+
+        let summary_offset_start = self.position;
+        for record in &summary_offsets {
+            self.write_bytes(record)?;
+        }
+        self.write_bytes(&Record::default().u64(summary_start).u64(summary_offset_start).u32(0).encode(OP_FOOTER))?;
+        self.write_bytes(MAGIC)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,161 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::convert::{transcode_with, TranscodeProgress};
+use crate::dvs::compress::InputFile;
+use crate::dvs::{header_geometry, prep_file_decoder, prep_file_encoder, set_header_geometry, DVSEvent, DvsRawDecoder, DvsRawDecoderEnum, EventFormat, TriggerEvent};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::marker::PhantomData;
+
+/*
+This file implements merging several event streams into one, e.g. the recordings of several cameras of an
+experiment. Each stream can be shifted in time, to line up cameras whose clocks started at different times,
+and in x and y, to place the sensors of a stitched array side by side. The shifted streams are interleaved by
+timestamp, assuming each of them is in time order. Events with equal timestamps come out in the order of the
+streams.
+The merged sensor spans the shifted sensors of all streams, and the header of the first stream is given its
+geometry. Events shifted off it, to negative coordinates or past its edges, are dropped and counted rather than
+handed to an encoder that can't represent them.
+*/
+
+// Shift applied to the events of one stream before merging
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MergeOffset {
+    pub time_us: i64,
+    pub x: i16,
+    pub y: i16,
+}
+
+impl MergeOffset {
+    // The shifted event, or None if it lands off the merged sensor of the given geometry (if known)
+    fn apply(self, event: DVSEvent, geometry: Option<(u32, u32)>) -> Option<DVSEvent> {
+        let x = event.x.checked_add(self.x).filter(|x| *x >= 0)?;
+        let y = event.y.checked_add(self.y).filter(|y| *y >= 0)?;
+        if geometry.is_some_and(|(width, height)| x as u32 >= width || y as u32 >= height) {
+            return None;
+        }
+        Some(DVSEvent { timestamp: event.timestamp.saturating_add(self.time_us), x, y, polarity: event.polarity })
+    }
+}
+
+struct MergeStream<D> {
+    decoder: D,
+    offset: MergeOffset,
+    // Next event of the stream, shifted, once it has been read
+    next: Option<DVSEvent>,
+}
+
+// Interleaves the events of several decoders by their shifted timestamps
+pub struct Merge<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> {
+    streams: Vec<MergeStream<D>>,
+    // Timestamp and stream index of the next event of each stream that has one
+    heap: BinaryHeap<Reverse<(i64, usize)>>,
+    // Geometry of the merged sensor, if the headers give one
+    geometry: Option<(u32, u32)>,
+    // Events shifted off the merged sensor
+    pub events_dropped: u64,
+    _reader: PhantomData<R>,
+}
+
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> Merge<R, D> {
+    pub fn new(streams: Vec<(D, MergeOffset)>) -> Self {
+        Self {
+            streams: streams.into_iter().map(|(decoder, offset)| MergeStream { decoder, offset, next: None }).collect(),
+            heap: BinaryHeap::new(),
+            geometry: None,
+            events_dropped: 0,
+            _reader: PhantomData,
+        }
+    }
+
+    pub fn into_inner(self) -> Vec<D> {
+        self.streams.into_iter().map(|stream| stream.decoder).collect()
+    }
+
+    // Reads the next event of a stream that lands on the merged sensor into the heap, if it has one
+    fn advance(&mut self, index: usize) -> anyhow::Result<()> {
+        let stream = &mut self.streams[index];
+        stream.next = None;
+        while let Some(event) = stream.decoder.read_event()? {
+            stream.next = stream.offset.apply(event, self.geometry);
+            if stream.next.is_some() {
+                break;
+            }
+            self.events_dropped += 1;
+        }
+        if let Some(event) = stream.next {
+            self.heap.push(Reverse((event.timestamp, index)));
+        }
+        Ok(())
+    }
+}
+
+// Implemented like DvsRawDecoderEnum, so merged streams can be transcoded like a single one
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> DvsRawDecoder<R> for Merge<R, D> {
+    // Reads the headers of all streams, and returns the header of the first one with the geometry of the merged
+    // sensor, which covers the shifted sensors of the streams whose headers give a geometry
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        let mut header = None;
+        self.geometry = None;
+        for stream in self.streams.iter_mut() {
+            let stream_header = stream.decoder.read_header()?;
+            if let Some((width, height)) = header_geometry(&stream_header) {
+                let shifted = |side: u32, offset: i16| (side as i64 + offset as i64).max(0) as u32;
+                let (merged_width, merged_height) = self.geometry.unwrap_or_default();
+                self.geometry = Some((merged_width.max(shifted(width, stream.offset.x)), merged_height.max(shifted(height, stream.offset.y))));
+            }
+            header.get_or_insert(stream_header);
+        }
+        let header = header.unwrap_or_default();
+        let header = match self.geometry {
+            Some((width, height)) => set_header_geometry(header, width, height),
+            None => header,
+        };
+        self.heap.clear();
+        for index in 0..self.streams.len() {
+            self.advance(index)?;
+        }
+        Ok(header)
+    }
+
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        let Some(Reverse((_, index))) = self.heap.pop() else {
+            return Ok(None);
+        };
+        let event = self.streams[index].next.take();
+        self.advance(index)?;
+        Ok(event)
+    }
+
+    // Returns the triggers of all streams, shifted in time like their events
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        let mut triggers: Vec<TriggerEvent> = Vec::new();
+        for stream in self.streams.iter_mut() {
+            let time_us = stream.offset.time_us;
+            triggers.extend(stream.decoder.take_triggers().into_iter().map(|trigger| TriggerEvent {
+                timestamp: trigger.timestamp.saturating_add(time_us),
+                ..trigger
+            }));
+        }
+        triggers.sort_by_key(|trigger| trigger.timestamp);
+        triggers
+    }
+}
+
+// Merges files into one of the given format, detecting the format of each input from its contents. The header
+// of the first input is carried over to the output. Returns the totals written and the number of events dropped
+// off the merged sensor
+pub fn merge_files<F>(inputs: &[(String, MergeOffset)], output_path: &str, to: EventFormat, progress: F) -> anyhow::Result<(TranscodeProgress, u64)>
+where
+    F: FnMut(TranscodeProgress),
+{
+    let mut streams: Vec<(DvsRawDecoderEnum<BufReader<InputFile>>, MergeOffset)> = Vec::new();
+    for (path, offset) in inputs {
+        streams.push((prep_file_decoder::<BufReader<File>>(path)?, *offset));
+    }
+    let mut merge = Merge::new(streams);
+    let mut encoder = prep_file_encoder::<File>(output_path, to)?;
+    let totals = transcode_with(&mut merge, &mut encoder, progress)?;
+    Ok((totals, merge.events_dropped))
+}
@@ -0,0 +1,278 @@
+use crate::dvs::loss::json_number;
+use crate::dvs::{header_geometry, DVSEvent, DvsRawDecoder};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, Read, Seek};
+use std::marker::PhantomData;
+
+/*
+This file implements objective measures of how far a stream is from an original, to score loss models and
+lossy codecs. Both streams are read in lockstep, in windows of a fixed duration aligned to multiples of it, like
+the chunks of the loss filter. Events are assumed to be in time order.
+- Count errors: the mean absolute and root mean square difference between the event counts of the two streams
+  per window, and the total absolute difference relative to the events of the original.
+- Pixel RMSE: the root mean square difference between the event counts of each pixel over the whole streams,
+  over the pixels of the sensor (or the pixels with events, without a sensor geometry).
+- Chamfer distance: the mean distance from each event to the nearest event of the other stream in the same
+  window, averaged over both directions, in (x, y, t) space where time_scale_us microseconds count as one
+  pixel. Windows with more than max_samples events in a stream are evenly subsampled, which keeps the search
+  quadratic in max_samples only. Windows with events in only one stream don't count towards the distance;
+  what they lose shows in the count errors.
+- Polarity accuracy: over the pixels with events in both streams within a window, the fraction whose majority
+  polarity (ON, OFF, or a tie) agrees.
+Measures without anything to measure, e.g. the Chamfer distance of a stream without events, are NaN.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MetricsOptions {
+    // Duration of the windows compared, in microseconds
+    pub window_us: i64,
+    // Microseconds counting as one pixel in the Chamfer distance
+    pub time_scale_us: f64,
+    // Most events of a window and stream searched for the Chamfer distance
+    pub max_samples: usize,
+    // Width and height of the sensor, overriding the header of the original
+    pub geometry: Option<(u32, u32)>,
+}
+
+impl Default for MetricsOptions {
+    fn default() -> Self {
+        MetricsOptions { window_us: 10_000, time_scale_us: 1000.0, max_samples: 2000, geometry: None }
+    }
+}
+
+// Distortion of a stream relative to an original, see the top of this file
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StreamMetrics {
+    pub window_us: i64,
+    pub windows: u64,
+    pub original_events: u64,
+    pub lossy_events: u64,
+    // Event count errors per window
+    pub count_mae: f64,
+    pub count_rmse: f64,
+    // Sum of the absolute count errors over the events of the original
+    pub count_relative_error: f64,
+    pub pixel_rmse: f64,
+    pub chamfer_distance: f64,
+    pub polarity_accuracy: f64,
+}
+
+impl StreamMetrics {
+    // The measures and their values, in order
+    fn fields(&self) -> [(&'static str, f64); 10] {
+        [
+            ("window_us", self.window_us as f64),
+            ("windows", self.windows as f64),
+            ("original_events", self.original_events as f64),
+            ("lossy_events", self.lossy_events as f64),
+            ("count_mae", self.count_mae),
+            ("count_rmse", self.count_rmse),
+            ("count_relative_error", self.count_relative_error),
+            ("pixel_rmse", self.pixel_rmse),
+            ("chamfer_distance", self.chamfer_distance),
+            ("polarity_accuracy", self.polarity_accuracy),
+        ]
+    }
+
+    pub fn to_json(&self) -> String {
+        let fields: Vec<String> = self.fields().iter().map(|(name, value)| format!("\"{}\":{}", name, json_number(*value))).collect();
+        format!("{{{}}}\n", fields.join(","))
+    }
+
+    // A row of the measures under a row of their names
+    pub fn to_csv(&self) -> String {
+        let fields = self.fields();
+        let names: Vec<&str> = fields.iter().map(|(name, _)| *name).collect();
+        let values: Vec<String> = fields.iter().map(|(_, value)| value.to_string()).collect();
+        format!("{}\n{}\n", names.join(","), values.join(","))
+    }
+
+    // Writes the measures as JSON if the path ends in .json, and as CSV otherwise
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let contents = if path.to_lowercase().ends_with(".json") { self.to_json() } else { self.to_csv() };
+        std::fs::write(path, contents)
+    }
+}
+
+// One measure per line, for printing
+impl std::fmt::Display for StreamMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, value) in self.fields() {
+            writeln!(f, "{}: {}", name, value)?;
+        }
+        Ok(())
+    }
+}
+
+// Events of one stream, handed out by window
+struct WindowReader<'a, R: Read + BufRead + Seek, D: DvsRawDecoder<R>> {
+    decoder: &'a mut D,
+    window_us: i64,
+    pending: VecDeque<DVSEvent>,
+    batch: Vec<DVSEvent>,
+    ended: bool,
+    _reader: PhantomData<R>,
+}
+
+impl<'a, R: Read + BufRead + Seek, D: DvsRawDecoder<R>> WindowReader<'a, R, D> {
+    fn new(decoder: &'a mut D, window_us: i64) -> Self {
+        WindowReader { decoder, window_us, pending: VecDeque::new(), batch: Vec::with_capacity(BATCH_SIZE), ended: false, _reader: PhantomData }
+    }
+
+    fn window_of(&self, event: &DVSEvent) -> i64 {
+        event.timestamp.div_euclid(self.window_us) * self.window_us
+    }
+
+    // Start of the window of the next event, or None once the stream has ended
+    fn peek_window(&mut self) -> anyhow::Result<Option<i64>> {
+        if self.pending.is_empty() && !self.ended {
+            self.fill()?;
+        }
+        Ok(self.pending.front().map(|event| self.window_of(event)))
+    }
+
+    // Reads a batch of events, or notes the end of the stream
+    fn fill(&mut self) -> anyhow::Result<()> {
+        self.batch.clear();
+        if self.decoder.read_events_into(&mut self.batch, BATCH_SIZE)? == 0 {
+            self.ended = true;
+        }
+        self.pending.extend(self.batch.drain(..));
+        Ok(())
+    }
+
+    // Moves the events of the window starting at start_us, and any late events before them, into events
+    fn take_window(&mut self, start_us: i64, events: &mut Vec<DVSEvent>) -> anyhow::Result<()> {
+        events.clear();
+        loop {
+            while self.pending.front().is_some_and(|event| self.window_of(event) <= start_us) {
+                events.extend(self.pending.pop_front());
+            }
+            if !self.pending.is_empty() || self.ended {
+                return Ok(());
+            }
+            self.fill()?;
+        }
+    }
+}
+
+// Every step-th event, so at most max_samples remain
+fn subsample(events: &[DVSEvent], max_samples: usize) -> impl Iterator<Item = &DVSEvent> {
+    events.iter().step_by(events.len().div_ceil(max_samples.max(1)).max(1))
+}
+
+// Sum of the distances from each sampled event of from to the nearest sampled event of to, and the number of
+// events summed
+fn nearest_distances(from: &[DVSEvent], to: &[DVSEvent], options: &MetricsOptions) -> (f64, u64) {
+    let time_scale_us = options.time_scale_us.max(f64::MIN_POSITIVE);
+    let (mut sum, mut count) = (0.0, 0);
+    for a in subsample(from, options.max_samples) {
+        let nearest = subsample(to, options.max_samples)
+            .map(|b| {
+                let (dx, dy) = ((a.x as f64) - (b.x as f64), (a.y as f64) - (b.y as f64));
+                let dt = (a.timestamp - b.timestamp) as f64 / time_scale_us;
+                dx * dx + dy * dy + dt * dt
+            })
+            .fold(f64::INFINITY, f64::min);
+        if nearest.is_finite() {
+            sum += nearest.sqrt();
+            count += 1;
+        }
+    }
+    (sum, count)
+}
+
+// ON events minus OFF events of each pixel of a window
+fn net_polarities(events: &[DVSEvent]) -> HashMap<(i16, i16), i64> {
+    let mut net = HashMap::new();
+    for event in events {
+        *net.entry((event.x, event.y)).or_insert(0) += if event.polarity == 1 { 1 } else { -1 };
+    }
+    net
+}
+
+// Reads two whole streams, from their headers, and measures how far the second is from the first, see the top of
+// this file
+pub fn compare_streams<R1, D1, R2, D2>(original: &mut D1, lossy: &mut D2, options: MetricsOptions) -> anyhow::Result<StreamMetrics>
+where
+    R1: Read + BufRead + Seek,
+    D1: DvsRawDecoder<R1>,
+    R2: Read + BufRead + Seek,
+    D2: DvsRawDecoder<R2>,
+{
+    let window_us = options.window_us.max(1);
+    let header = original.read_header()?;
+    let geometry = options.geometry.or_else(|| header_geometry(&header));
+    lossy.read_header()?;
+
+    let mut left = WindowReader::new(original, window_us);
+    let mut right = WindowReader::new(lossy, window_us);
+    let (mut left_events, mut right_events) = (Vec::new(), Vec::new());
+    let mut pixel_counts: HashMap<(i16, i16), (u64, u64)> = HashMap::new();
+    let (mut windows, mut original_events, mut lossy_events) = (0u64, 0u64, 0u64);
+    let (mut absolute_error, mut squared_error) = (0.0, 0.0);
+    let (mut left_distance, mut left_matched, mut right_distance, mut right_matched) = (0.0, 0, 0.0, 0);
+    let (mut shared_pixels, mut agreeing_pixels) = (0u64, 0u64);
+    // Start of the previous window, to count the windows without events in between
+    let mut previous_us: Option<i64> = None;
+    loop {
+        let start_us = match (left.peek_window()?, right.peek_window()?) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => break,
+        };
+        left.take_window(start_us, &mut left_events)?;
+        right.take_window(start_us, &mut right_events)?;
+        windows += previous_us.map_or(1, |previous_us| ((start_us - previous_us) / window_us).max(1) as u64);
+        previous_us = Some(start_us);
+
+        original_events += left_events.len() as u64;
+        lossy_events += right_events.len() as u64;
+        let error = left_events.len() as f64 - right_events.len() as f64;
+        absolute_error += error.abs();
+        squared_error += error * error;
+
+        for event in &left_events {
+            pixel_counts.entry((event.x, event.y)).or_default().0 += 1;
+        }
+        for event in &right_events {
+            pixel_counts.entry((event.x, event.y)).or_default().1 += 1;
+        }
+
+        let (sum, count) = nearest_distances(&left_events, &right_events, &options);
+        (left_distance, left_matched) = (left_distance + sum, left_matched + count);
+        let (sum, count) = nearest_distances(&right_events, &left_events, &options);
+        (right_distance, right_matched) = (right_distance + sum, right_matched + count);
+
+        let right_net = net_polarities(&right_events);
+        for (pixel, net) in net_polarities(&left_events) {
+            if let Some(other) = right_net.get(&pixel) {
+                shared_pixels += 1;
+                agreeing_pixels += (net.signum() == other.signum()) as u64;
+            }
+        }
+    }
+
+    let pixels = match geometry {
+        Some((width, height)) => width as usize * height as usize,
+        None => pixel_counts.len(),
+    };
+    let pixel_squared_error: f64 = pixel_counts.values().map(|&(a, b)| (a as f64 - b as f64).powi(2)).sum();
+    let ratio = |value: f64, count: u64| if count == 0 { f64::NAN } else { value / count as f64 };
+    Ok(StreamMetrics {
+        window_us,
+        windows,
+        original_events,
+        lossy_events,
+        count_mae: ratio(absolute_error, windows),
+        count_rmse: ratio(squared_error, windows).sqrt(),
+        count_relative_error: ratio(absolute_error, original_events),
+        pixel_rmse: ratio(pixel_squared_error, pixels as u64).sqrt(),
+        chamfer_distance: (ratio(left_distance, left_matched) + ratio(right_distance, right_matched)) / 2.0,
+        polarity_accuracy: ratio(agreeing_pixels as f64, shared_pixels),
+    })
+}
@@ -0,0 +1,40 @@
+use std::fs::File;
+use std::io::{self, Cursor};
+use std::path::Path;
+
+pub use memmap2::Mmap;
+
+/*
+This file implements read-only memory maps of files, so decoders can parse large recordings straight out of
+the page cache without copying them through buffers. The maps are memmap2's.
+
+Mapping a file is unsafe: the map is read as a &[u8], which Rust assumes doesn't change, but nothing stops
+another process from writing to the file or truncating it while it is mapped. Writes change bytes under the
+decoder, and reading pages past the new end of a truncated file raises SIGBUS. Callers of open promise that
+the file stays as it is until the map is dropped. Don't map files that are still being written, use
+FollowReader for those.
+*/
+
+// A Cursor over a mapped file, which implements Read, BufRead and Seek without any further buffering
+pub type MmapReader = Cursor<Mmap>;
+
+/// Maps the whole file read-only. The map stays valid after the file is closed.
+///
+/// # Safety
+///
+/// The file must not be written to or truncated, by this process or another, until the map is dropped.
+pub unsafe fn open(path: impl AsRef<Path>) -> io::Result<Mmap> {
+    let file = File::open(path)?;
+    // Safety: upheld by the caller
+    unsafe { Mmap::map(&file) }
+}
+
+/// Maps the whole file and wraps the map in a reader starting at the beginning of the file.
+///
+/// # Safety
+///
+/// As for [`open`]: the file must not be written to or truncated until the reader is dropped.
+pub unsafe fn open_reader(path: impl AsRef<Path>) -> io::Result<MmapReader> {
+    // Safety: upheld by the caller
+    unsafe { open(path) }.map(Cursor::new)
+}
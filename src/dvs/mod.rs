@@ -1,14 +1,110 @@
 use crate::dvs::raw_decoder_evt2::DVSRawDecoderEvt2;
+use crate::dvs::raw_decoder_evt21::DVSRawDecoderEvt21;
 use crate::dvs::raw_decoder_evt3::DVSRawDecoderEvt3;
 use crate::dvs::raw_encoder_evt2::DVSRawEncoderEvt2;
+use crate::dvs::raw_encoder_evt21::DVSRawEncoderEvt21;
+use crate::dvs::raw_encoder_evt3::DVSRawEncoderEvt3;
+use crate::dvs::raw_encoder_dat::DVSRawEncoderDat;
 use crate::dvs::raw_decoder_dat::DVSRawDecoderDat;
+use crate::dvs::raw_decoder_aedat3::DVSRawDecoderAedat3;
+use crate::dvs::raw_decoder_aedat4::DVSRawDecoderAedat4;
+use crate::dvs::raw_decoder_csv::{CsvOptions, DVSRawDecoderCsv};
+use crate::dvs::raw_encoder_csv::DVSRawEncoderCsv;
+use crate::dvs::raw_encoder_npy::DVSRawEncoderNpy;
+use crate::dvs::raw_encoder_mcap::DVSRawEncoderMcap;
+#[cfg(feature = "parquet")]
+use crate::dvs::raw_encoder_parquet::DVSRawEncoderParquet;
+use crate::dvs::codec::{DVSRawDecoderDelta, DVSRawEncoderDelta};
+#[cfg(feature = "ros")]
+use crate::dvs::raw_encoder_mcap::McapOptions;
+use crate::dvs::compress::{strip_compression_suffix, Compression, InputFile, OutputFile};
+use crate::dvs::error::DvsError;
+use crate::dvs::index::{EventIndex, IndexEntry};
+use crate::dvs::follow::FollowReader;
+use crate::dvs::rewind::RewindReader;
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader, BufWriter, Read, Seek, Write};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::time::Duration;
 
+pub mod adaptive;
+pub mod arq;
+#[cfg(feature = "async")]
+pub mod async_io;
+pub mod batch;
+pub mod bitrate;
+pub mod bounds;
+#[cfg(feature = "capture")]
+pub mod capture;
+pub mod chunk;
+pub mod codec;
+#[cfg(feature = "viz")]
+pub mod compare;
+pub mod compress;
+pub mod config;
+pub mod convert;
+pub mod dataset;
+pub mod entropy;
+pub mod error;
+pub mod fec;
+pub mod filters;
+pub mod follow;
+#[cfg(feature = "gui")]
+pub mod gui;
+pub mod index;
+#[cfg(feature = "inivation")]
+pub mod inivation;
+pub mod interpolate;
+pub mod jitter;
+pub mod loss;
+pub mod mcap;
+pub mod merge;
+pub mod metrics;
+pub mod mmap;
+#[cfg(feature = "transport")]
+pub mod net;
+pub mod packet;
+pub mod rate;
+pub mod parallel;
+pub mod quantize;
+pub mod pipeline;
 pub mod raw_decoder_evt2;
+pub mod raw_decoder_evt21;
 pub mod raw_decoder_evt3;
 pub mod raw_decoder_dat;
+pub mod raw_decoder_aedat3;
+pub mod raw_decoder_aedat4;
+pub mod raw_decoder_csv;
 pub mod raw_encoder_evt2;
+pub mod raw_encoder_evt21;
+pub mod raw_encoder_evt3;
+pub mod raw_encoder_dat;
+pub mod raw_encoder_csv;
+pub mod raw_encoder_npy;
+pub mod raw_encoder_mcap;
+#[cfg(feature = "parquet")]
+pub mod raw_encoder_parquet;
+#[cfg(feature = "viz")]
+pub mod reconstruct;
+pub mod record;
+pub mod recover;
+#[cfg(feature = "viz")]
+pub mod render;
+pub mod replay;
+pub mod representation;
+pub mod rewind;
+pub mod rng;
+pub mod rtp;
+pub mod split;
+pub mod stats;
+pub mod sync;
+#[cfg(feature = "viz")]
+pub mod terminal;
+pub mod transforms;
+#[cfg(feature = "v2e")]
+pub mod v2e;
+#[cfg(all(feature = "v4l2", target_os = "linux"))]
+pub mod v4l2;
+pub mod validate;
 
 
 
@@ -20,117 +116,778 @@ pub struct DVSEvent {
     pub polarity: u8,
 }
 
+// An external trigger edge, recorded by EVT2 and EVT3 cameras on their trigger inputs, e.g. from a signal shared
+// by several cameras. Decoders keep them aside from the CD events, see take_triggers
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct TriggerEvent {
+    pub timestamp: i64,
+    // Trigger channel
+    pub id: u8,
+    // Level of the trigger input after the edge: 1 for a rising edge, 0 for a falling edge
+    pub value: u8,
+}
+
+// Size of an event in its wire layout
+pub const DVS_EVENT_WIRE_BYTES: usize = 13;
+
+impl DVSEvent {
+    // Encodes the event in a fixed layout with no padding, independent of the struct's memory layout and
+    // of the host's endianness: timestamp (i64), x (i16), y (i16), polarity (u8), all little-endian
+    pub fn to_le_bytes(&self) -> [u8; DVS_EVENT_WIRE_BYTES] {
+        let mut bytes = [0u8; DVS_EVENT_WIRE_BYTES];
+        bytes[0..8].copy_from_slice(&self.timestamp.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.x.to_le_bytes());
+        bytes[10..12].copy_from_slice(&self.y.to_le_bytes());
+        bytes[12] = self.polarity;
+        bytes
+    }
 
+    // Decodes an event written by to_le_bytes
+    pub fn from_le_bytes(bytes: [u8; DVS_EVENT_WIRE_BYTES]) -> Self {
+        DVSEvent {
+            timestamp: i64::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5], bytes[6], bytes[7]]),
+            x: i16::from_le_bytes([bytes[8], bytes[9]]),
+            y: i16::from_le_bytes([bytes[10], bytes[11]]),
+            polarity: bytes[12],
+        }
+    }
+}
+
+impl From<DVSEvent> for [u8; DVS_EVENT_WIRE_BYTES] {
+    fn from(event: DVSEvent) -> Self {
+        event.to_le_bytes()
+    }
+}
+
+impl From<[u8; DVS_EVENT_WIRE_BYTES]> for DVSEvent {
+    fn from(bytes: [u8; DVS_EVENT_WIRE_BYTES]) -> Self {
+        DVSEvent::from_le_bytes(bytes)
+    }
+}
+
+// Decodes an event from a slice holding exactly one event in the wire layout
+impl TryFrom<&[u8]> for DVSEvent {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> anyhow::Result<Self> {
+        match <[u8; DVS_EVENT_WIRE_BYTES]>::try_from(bytes) {
+            Ok(bytes) => Ok(DVSEvent::from_le_bytes(bytes)),
+            Err(_) => anyhow::bail!("An event is {} bytes, got {}", DVS_EVENT_WIRE_BYTES, bytes.len()),
+        }
+    }
+}
 
+
+
+// Decoders are created by their own constructors, as wrappers, sockets and cameras aren't made from a reader
 pub trait DvsRawDecoder<R: Read + BufRead + Seek>: Sized {
-    fn new(reader: R) -> Self;
-    fn read_header(&mut self) -> anyhow::Result<Vec<String>>;
-    fn read_event(&mut self) -> anyhow::Result<Option<DVSEvent>>;
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError>;
+    // Returns the next event, or Ok(None) once the end of the stream has been reached. Errors are DvsErrors,
+    // see error.rs
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError>;
+
+    // Appends up to max events to the given vector, returning the number of events appended.
+    // Returns Ok(0) once the end of the stream has been reached. Input that ends in the middle of an event
+    // fails with DvsError::Truncated, after appending the events before it.
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        let mut count = 0;
+        while count < max {
+            match self.read_event() {
+                Ok(Some(event)) => {
+                    events.push(event);
+                    count += 1;
+                }
+                Ok(None) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(count)
+    }
+
+    // Returns the trigger events decoded so far and clears them. Only EVT2 and EVT3 streams carry triggers, so
+    // other decoders have none
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        Vec::new()
+    }
+}
+
+// Whether a decoder error was caused by the input ending in the middle of an event
+pub fn is_eof(error: &anyhow::Error) -> bool {
+    matches!(error.downcast_ref::<DvsError>(), Some(DvsError::UnexpectedEof | DvsError::Truncated { .. }))
+        || error
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|e| e.kind() == std::io::ErrorKind::UnexpectedEof)
+}
+
+// Whether a reader is at the end of its input, so the stream ended cleanly rather than mid-event
+pub(crate) fn at_end<R: BufRead>(reader: &mut R) -> std::io::Result<bool> {
+    Ok(reader.fill_buf()?.is_empty())
 }
 
+// Fills the buffer like read_exact, but fails with DvsError::Truncated, giving the offset of the start of the
+// buffer, if the input ends part way through it
+pub(crate) fn read_exact_or_truncated<R: Read + Seek>(reader: &mut R, buf: &mut [u8]) -> anyhow::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => {
+                let offset = reader.stream_position()? - filled as u64;
+                return Err(DvsError::Truncated { offset }.into());
+            }
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+// Reads len bytes, or fails with DvsError::Truncated. The buffer grows with the bytes actually read, so a
+// corrupted length can't allocate more than the rest of the stream
+pub(crate) fn read_vec_or_truncated<R: Read + Seek>(reader: &mut R, len: u64) -> anyhow::Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    let read = reader.by_ref().take(len).read_to_end(&mut buf)?;
+    if (read as u64) < len {
+        let offset = reader.stream_position()? - read as u64;
+        return Err(DvsError::Truncated { offset }.into());
+    }
+    Ok(buf)
+}
+
+// Like decoders, encoders are created by their own constructors
 pub trait DvsRawEncoder<R: Write + Seek>: Sized {
-    fn new(reader: R) -> Self;
-    fn write_header(&mut self, header: Vec<String>) -> anyhow::Result<()>;
-    fn write_event(&mut self, event: DVSEvent) -> anyhow::Result<u8>;
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError>;
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError>;
+    // Writes a trigger event, returning the number of words written. Formats that can't store triggers drop
+    // them
+    fn write_trigger(&mut self, trigger: TriggerEvent) -> Result<usize, DvsError> {
+        let _ = trigger;
+        Ok(0)
+    }
+    // Writes any buffered events and flushes the underlying writer
+    fn flush(&mut self) -> Result<(), DvsError>;
+}
+
+// Output formats supported by prep_file_encoder
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum EventFormat {
+    #[default]
+    Evt2,
+    // Vectorized EVT2, with 64-bit words
+    Evt21,
+    Evt3,
+    Dat,
+    // Delimited text, one "t,x,y,p" line per event
+    Csv,
+    Tsv,
+    // NumPy structured array, and a zip archive containing one. Output only
+    Npy,
+    Npz,
+    // dvs_msgs/EventArray messages in time-indexed MCAP chunks. Output only
+    Mcap,
+    // Groups of delta-encoded events, see codec.rs
+    Delta,
+    // ROS 2 bag of dvs_msgs/EventArray messages in MCAP storage. Output only
+    #[cfg(feature = "ros")]
+    Rosbag2,
+    // Columns of events in a Parquet file. Output only
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl EventFormat {
+    // File extension conventionally used for the format
+    pub fn extension(self) -> &'static str {
+        match self {
+            EventFormat::Evt2 | EventFormat::Evt21 | EventFormat::Evt3 => "raw",
+            EventFormat::Dat => "dat",
+            EventFormat::Csv => "csv",
+            EventFormat::Tsv => "tsv",
+            EventFormat::Npy => "npy",
+            EventFormat::Npz => "npz",
+            EventFormat::Mcap => "mcap",
+            #[cfg(feature = "ros")]
+            EventFormat::Rosbag2 => "mcap",
+            #[cfg(feature = "parquet")]
+            EventFormat::Parquet => "parquet",
+            EventFormat::Delta => "delta",
+        }
+    }
+
+    // Format conventionally stored with the extension of the given path, ignoring a compression suffix (e.g.
+    // .raw.zst). .raw files are assumed to be EVT2
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(strip_compression_suffix(path)).extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "raw" => Some(EventFormat::Evt2),
+            "dat" => Some(EventFormat::Dat),
+            "csv" => Some(EventFormat::Csv),
+            "tsv" => Some(EventFormat::Tsv),
+            "npy" => Some(EventFormat::Npy),
+            "npz" => Some(EventFormat::Npz),
+            "mcap" => Some(EventFormat::Mcap),
+            "delta" => Some(EventFormat::Delta),
+            #[cfg(feature = "parquet")]
+            "parquet" => Some(EventFormat::Parquet),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for EventFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "evt2" => Ok(EventFormat::Evt2),
+            "evt21" | "evt2.1" => Ok(EventFormat::Evt21),
+            "evt3" => Ok(EventFormat::Evt3),
+            "dat" => Ok(EventFormat::Dat),
+            "csv" => Ok(EventFormat::Csv),
+            "tsv" => Ok(EventFormat::Tsv),
+            "npy" => Ok(EventFormat::Npy),
+            "npz" => Ok(EventFormat::Npz),
+            "mcap" => Ok(EventFormat::Mcap),
+            #[cfg(feature = "ros")]
+            "rosbag2" => Ok(EventFormat::Rosbag2),
+            "delta" => Ok(EventFormat::Delta),
+            #[cfg(feature = "parquet")]
+            "parquet" => Ok(EventFormat::Parquet),
+            _ => anyhow::bail!("Unsupported event format '{}'. Expected evt2, evt21, evt3, dat, csv, tsv, npy, npz, mcap or delta", s),
+        }
+    }
+}
 
+// Input format of a stream passed to prep_stream_decoder
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum FormatHint {
+    // Detect the format from the stream's header
+    #[default]
+    Auto,
+    Format(EventFormat),
+    // AEDAT 3.1 and AEDAT4, which can be decoded but not encoded
+    Aedat3,
+    Aedat4,
 }
 
 pub enum DvsRawDecoderEnum<R: Read + BufRead + Seek> {
     Evt2(DVSRawDecoderEvt2<R>),
+    Evt21(DVSRawDecoderEvt21<R>),
     Evt3(DVSRawDecoderEvt3<R>),
     Dat(DVSRawDecoderDat<R>),
+    Aedat3(DVSRawDecoderAedat3<R>),
+    Aedat4(DVSRawDecoderAedat4<R>),
+    Csv(DVSRawDecoderCsv<R>),
+    Delta(DVSRawDecoderDelta<R>),
 }
 
 pub enum DvsRawEncoderEnum<R: Write + Seek> {
     Evt2(DVSRawEncoderEvt2<R>),
+    Evt21(DVSRawEncoderEvt21<R>),
+    Evt3(DVSRawEncoderEvt3<R>),
+    Dat(DVSRawEncoderDat<R>),
+    Csv(DVSRawEncoderCsv<R>),
+    Npy(DVSRawEncoderNpy<R>),
+    // Boxed, as the chunk buffers make the MCAP encoder much larger than the others
+    Mcap(Box<DVSRawEncoderMcap<R>>),
+    Delta(DVSRawEncoderDelta<R>),
+    #[cfg(feature = "parquet")]
+    Parquet(Box<DVSRawEncoderParquet<R>>),
 }
 
 // Implement the DvsRawDecoder trait for the enum, using enum dispatch (to avoid heap allocation and boxing)
-impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DvsRawDecoderEnum<R> {
-    fn new(reader: R) -> Self {
-        let _ = reader;
-        // This method is not used in the enum implementation
-        unimplemented!()
+impl<R: Read + BufRead + Seek> DvsRawDecoderEnum<R> {
+    // Moves to the last time base at or before the timestamp, see the seek_to_timestamp methods of the EVT2
+    // and EVT3 decoders. Other formats don't support seeking
+    pub fn seek_to_timestamp(&mut self, timestamp: i64) -> anyhow::Result<()> {
+        match self {
+            DvsRawDecoderEnum::Evt2(decoder) => decoder.seek_to_timestamp(timestamp),
+            DvsRawDecoderEnum::Evt3(decoder) => decoder.seek_to_timestamp(timestamp),
+            _ => Err(DvsError::UnsupportedFormat("Seeking to a timestamp is only supported for EVT2 and EVT3 input".to_string()).into()),
+        }
+    }
+
+    // Returns the events from start_us up to, but not including, end_us. EVT2 and EVT3 decoders seek to the
+    // start of the window, others read on from the current position. See convert::cut_file to write a window
+    // to a file
+    pub fn events_between(&mut self, start_us: i64, end_us: i64) -> anyhow::Result<Vec<DVSEvent>> {
+        let mut window = Vec::new();
+        convert::read_window(self, start_us, end_us, |events| {
+            window.extend_from_slice(events);
+            Ok(())
+        })?;
+        Ok(window)
+    }
+
+    // Uses an index of the recording for seek_to_timestamp. Fails if the index is of another format
+    pub fn use_index(&mut self, index: EventIndex) -> anyhow::Result<()> {
+        match (self, index.format) {
+            (DvsRawDecoderEnum::Evt2(decoder), EventFormat::Evt2) => decoder.use_index(index),
+            (DvsRawDecoderEnum::Evt3(decoder), EventFormat::Evt3) => decoder.use_index(index),
+            _ => return Err(DvsError::UnsupportedFormat("The index is not of the format of the recording".to_string()).into()),
+        }
+        Ok(())
     }
 
-    fn read_header(&mut self) -> anyhow::Result<Vec<String>> {
+    // Indexes the recording in one pass, see index.rs
+    pub(crate) fn build_index(&mut self, interval_us: i64) -> anyhow::Result<(EventFormat, Vec<IndexEntry>)> {
+        match self {
+            DvsRawDecoderEnum::Evt2(decoder) => Ok((EventFormat::Evt2, decoder.build_index(interval_us)?)),
+            DvsRawDecoderEnum::Evt3(decoder) => Ok((EventFormat::Evt3, decoder.build_index(interval_us)?)),
+            _ => Err(DvsError::UnsupportedFormat("Only EVT2 and EVT3 recordings can be indexed".to_string()).into()),
+        }
+    }
+}
+
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DvsRawDecoderEnum<R> {
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
         match self {
             DvsRawDecoderEnum::Evt2(decoder) => decoder.read_header(),
+            DvsRawDecoderEnum::Evt21(decoder) => decoder.read_header(),
             DvsRawDecoderEnum::Evt3(decoder) => decoder.read_header(),
             DvsRawDecoderEnum::Dat(decoder) => decoder.read_header(),
+            DvsRawDecoderEnum::Aedat3(decoder) => decoder.read_header(),
+            DvsRawDecoderEnum::Aedat4(decoder) => decoder.read_header(),
+            DvsRawDecoderEnum::Csv(decoder) => decoder.read_header(),
+            DvsRawDecoderEnum::Delta(decoder) => decoder.read_header(),
         }
     }
 
-    fn read_event(&mut self) -> anyhow::Result<Option<DVSEvent>> {
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
         match self {
             DvsRawDecoderEnum::Evt2(decoder) => decoder.read_event(),
+            DvsRawDecoderEnum::Evt21(decoder) => decoder.read_event(),
             DvsRawDecoderEnum::Evt3(decoder) => decoder.read_event(),
             DvsRawDecoderEnum::Dat(decoder) => decoder.read_event(),
+            DvsRawDecoderEnum::Aedat3(decoder) => decoder.read_event(),
+            DvsRawDecoderEnum::Aedat4(decoder) => decoder.read_event(),
+            DvsRawDecoderEnum::Csv(decoder) => decoder.read_event(),
+            DvsRawDecoderEnum::Delta(decoder) => decoder.read_event(),
+        }
+    }
+
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        match self {
+            DvsRawDecoderEnum::Evt2(decoder) => decoder.read_events_into(events, max),
+            DvsRawDecoderEnum::Evt21(decoder) => decoder.read_events_into(events, max),
+            DvsRawDecoderEnum::Evt3(decoder) => decoder.read_events_into(events, max),
+            DvsRawDecoderEnum::Dat(decoder) => decoder.read_events_into(events, max),
+            DvsRawDecoderEnum::Aedat3(decoder) => decoder.read_events_into(events, max),
+            DvsRawDecoderEnum::Aedat4(decoder) => decoder.read_events_into(events, max),
+            DvsRawDecoderEnum::Csv(decoder) => decoder.read_events_into(events, max),
+            DvsRawDecoderEnum::Delta(decoder) => decoder.read_events_into(events, max),
+        }
+    }
+
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        match self {
+            DvsRawDecoderEnum::Evt2(decoder) => decoder.take_triggers(),
+            DvsRawDecoderEnum::Evt3(decoder) => decoder.take_triggers(),
+            _ => Vec::new(),
         }
     }
 }
 
 // Implementations for DVSRawEncoder traits
 impl<R: Write + Seek> DvsRawEncoder<R> for DvsRawEncoderEnum<R> {
-    // Constructor
-    fn new(reader: R) -> Self {
-        let _ = reader;
-        unimplemented!()
-    }
-
     // Delegates work to specific implementations
-    fn write_header(&mut self, header: Vec<String>) -> anyhow::Result<()> {
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
         match self {
             DvsRawEncoderEnum::Evt2(encoder) => encoder.write_header(header),
+            DvsRawEncoderEnum::Evt21(encoder) => encoder.write_header(header),
+            DvsRawEncoderEnum::Evt3(encoder) => encoder.write_header(header),
+            DvsRawEncoderEnum::Dat(encoder) => encoder.write_header(header),
+            DvsRawEncoderEnum::Csv(encoder) => encoder.write_header(header),
+            DvsRawEncoderEnum::Npy(encoder) => encoder.write_header(header),
+            DvsRawEncoderEnum::Mcap(encoder) => encoder.write_header(header),
+            DvsRawEncoderEnum::Delta(encoder) => encoder.write_header(header),
+            #[cfg(feature = "parquet")]
+            DvsRawEncoderEnum::Parquet(encoder) => encoder.write_header(header),
         }
     }
 
-    fn write_event(&mut self, event: DVSEvent) -> anyhow::Result<u8> {
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
         match self {
             DvsRawEncoderEnum::Evt2(encoder) => encoder.write_event(event),
+            DvsRawEncoderEnum::Evt21(encoder) => encoder.write_event(event),
+            DvsRawEncoderEnum::Evt3(encoder) => encoder.write_event(event),
+            DvsRawEncoderEnum::Dat(encoder) => encoder.write_event(event),
+            DvsRawEncoderEnum::Csv(encoder) => encoder.write_event(event),
+            DvsRawEncoderEnum::Npy(encoder) => encoder.write_event(event),
+            DvsRawEncoderEnum::Mcap(encoder) => encoder.write_event(event),
+            DvsRawEncoderEnum::Delta(encoder) => encoder.write_event(event),
+            #[cfg(feature = "parquet")]
+            DvsRawEncoderEnum::Parquet(encoder) => encoder.write_event(event),
+        }
+    }
+
+    fn write_trigger(&mut self, trigger: TriggerEvent) -> Result<usize, DvsError> {
+        match self {
+            DvsRawEncoderEnum::Evt2(encoder) => encoder.write_trigger(trigger),
+            _ => Ok(0),
+        }
+    }
+
+    fn flush(&mut self) -> Result<(), DvsError> {
+        match self {
+            DvsRawEncoderEnum::Evt2(encoder) => encoder.flush(),
+            DvsRawEncoderEnum::Evt21(encoder) => encoder.flush(),
+            DvsRawEncoderEnum::Evt3(encoder) => encoder.flush(),
+            DvsRawEncoderEnum::Dat(encoder) => encoder.flush(),
+            DvsRawEncoderEnum::Csv(encoder) => encoder.flush(),
+            DvsRawEncoderEnum::Npy(encoder) => encoder.flush(),
+            DvsRawEncoderEnum::Mcap(encoder) => encoder.flush(),
+            DvsRawEncoderEnum::Delta(encoder) => encoder.flush(),
+            #[cfg(feature = "parquet")]
+            DvsRawEncoderEnum::Parquet(encoder) => encoder.flush(),
         }
     }
 
 }
 
-pub fn prep_file_decoder<R: std::io::BufRead + std::io::Seek>(file_path: &str) -> anyhow::Result<DvsRawDecoderEnum<BufReader<File>>> {
-    // If file extension is .dat, try reading as DAT file
-    if file_path.ends_with(".dat") {
-        let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
-        let mut decoder = raw_decoder_dat::DVSRawDecoderDat::new(reader);
-        decoder.read_header()?;
-        return Ok(DvsRawDecoderEnum::Dat(decoder));
-    } else if file_path.ends_with(".raw") {
-        // If file extension is .raw, try reading as RAW file
-        // Try reading it as an EVT2 file
+// Prepares a decoder for a file. EVT2 and EVT3 decoders use the index sidecar of the file, if there is an up
+// to date one, see index.rs
+pub fn prep_file_decoder<R: std::io::BufRead + std::io::Seek>(file_path: &str) -> anyhow::Result<DvsRawDecoderEnum<BufReader<InputFile>>> {
+    let mut decoder = prep_decoder(file_path, || Ok(BufReader::new(InputFile::open(file_path)?)))?;
+    if matches!(decoder, DvsRawDecoderEnum::Evt2(_) | DvsRawDecoderEnum::Evt3(_)) {
+        if let Err(e) = EventIndex::load_for(file_path).and_then(|index| index.map_or(Ok(()), |index| decoder.use_index(index))) {
+            tracing::warn!(path = file_path, error = %e, "ignoring the index");
+        }
+    }
+    Ok(decoder)
+}
+
+// Prepares a decoder for a file that is still being written. Reads wait for new data instead of
+// stopping at EOF, until the file has not grown for idle_timeout (or forever, if None).
+pub fn prep_follow_decoder(file_path: &str, poll_interval: Duration, idle_timeout: Option<Duration>) -> anyhow::Result<DvsRawDecoderEnum<BufReader<FollowReader<File>>>> {
+    prep_decoder(file_path, || {
         let file = File::open(file_path)?;
-        let reader = BufReader::new(file);
-        let mut decoder = DVSRawDecoderEvt2::new(reader);
-        match decoder.read_header() {
-            Ok(_) => Ok(DvsRawDecoderEnum::Evt2(decoder)),
-            Err(_) => {
-                // Try reading as an EVT3 file
-                let file = File::open(file_path)?;
-                let reader = BufReader::new(file);
-                let mut decoder = DVSRawDecoderEvt3::new(reader);
-                decoder.read_header().expect("Error parsing file header. Invalid file type");
-                Ok(DvsRawDecoderEnum::Evt3(decoder))
+        Ok(BufReader::new(FollowReader::new(file, poll_interval, idle_timeout)))
+    })
+}
+
+// Prepares a decoder for any byte source, e.g. stdin, a TcpStream or a Cursor<Vec<u8>>. The source does not
+// need to be seekable: the start of the stream is recorded so the header can be re-read.
+pub fn prep_stream_decoder<R: Read>(reader: R, hint: FormatHint) -> anyhow::Result<DvsRawDecoderEnum<BufReader<RewindReader<R>>>> {
+    let mut reader = BufReader::new(RewindReader::new(reader));
+    let format = match hint {
+        FormatHint::Format(format) => format,
+        FormatHint::Aedat3 | FormatHint::Aedat4 => return init_aedat_decoder(hint, reader),
+        FormatHint::Auto => match detect_aedat(&mut reader)? {
+            Some(hint) => return init_aedat_decoder(hint, reader),
+            None => match detect_format(&mut reader)? {
+                Some(format) => format,
+                None => return Err(DvsError::UnsupportedFormat("Unsupported stream format. Could not detect an EVT2, EVT3, DAT or AEDAT header.".to_string()).into()),
+            },
+        },
+    };
+    init_decoder(format, reader)
+}
+
+// Creates a decoder of the given format and reads the header
+fn init_decoder<R: Read + BufRead + Seek>(format: EventFormat, reader: R) -> anyhow::Result<DvsRawDecoderEnum<R>> {
+    let mut decoder = match format {
+        EventFormat::Evt2 => DvsRawDecoderEnum::Evt2(DVSRawDecoderEvt2::new(reader)),
+        EventFormat::Evt21 => DvsRawDecoderEnum::Evt21(DVSRawDecoderEvt21::new(reader)),
+        EventFormat::Evt3 => DvsRawDecoderEnum::Evt3(DVSRawDecoderEvt3::new(reader)),
+        EventFormat::Dat => DvsRawDecoderEnum::Dat(DVSRawDecoderDat::new(reader)),
+        EventFormat::Csv | EventFormat::Tsv => DvsRawDecoderEnum::Csv(DVSRawDecoderCsv::new(reader)),
+        EventFormat::Delta => DvsRawDecoderEnum::Delta(DVSRawDecoderDelta::new(reader)),
+        EventFormat::Npy | EventFormat::Npz => anyhow::bail!("NumPy files can be written but not decoded"),
+        EventFormat::Mcap => anyhow::bail!("MCAP files can be written but not decoded"),
+        #[cfg(feature = "ros")]
+        EventFormat::Rosbag2 => anyhow::bail!("ROS 2 bags can be written but not decoded"),
+        #[cfg(feature = "parquet")]
+        EventFormat::Parquet => anyhow::bail!("Parquet files can be written but not decoded"),
+    };
+    decoder.read_header()?;
+    Ok(decoder)
+}
+
+// Creates an AEDAT decoder of the given version and reads the header
+fn init_aedat_decoder<R: Read + BufRead + Seek>(hint: FormatHint, reader: R) -> anyhow::Result<DvsRawDecoderEnum<R>> {
+    let mut decoder = match hint {
+        FormatHint::Aedat4 => DvsRawDecoderEnum::Aedat4(DVSRawDecoderAedat4::new(reader)),
+        _ => DvsRawDecoderEnum::Aedat3(DVSRawDecoderAedat3::new(reader)),
+    };
+    decoder.read_header()?;
+    Ok(decoder)
+}
+
+// Detects the AEDAT version of a stream positioned at its start, from its first line
+fn detect_aedat<R: BufRead>(reader: &mut R) -> std::io::Result<Option<FormatHint>> {
+    let start = reader.fill_buf()?;
+    if start.starts_with(raw_decoder_aedat3::AEDAT3_MAGIC.as_bytes()) {
+        Ok(Some(FormatHint::Aedat3))
+    } else if start.starts_with(raw_decoder_aedat4::AEDAT4_MAGIC.as_bytes()) {
+        Ok(Some(FormatHint::Aedat4))
+    } else {
+        Ok(None)
+    }
+}
+
+// Detects the format of an event stream from its contents. Reads the "%" header lines, looking for the
+// "% format" and "% evt" lines of .raw files, and otherwise checks for the event type/size preamble that
+// follows a DAT header. Returns None if the contents are inconclusive. The reader is rewound afterwards.
+pub fn detect_format<R: BufRead + Seek>(reader: &mut R) -> anyhow::Result<Option<EventFormat>> {
+    reader.seek(SeekFrom::Start(0))?;
+    let mut format: Option<EventFormat> = None;
+    let mut raw_end = false;
+    let mut dat_header = false;
+    while reader.fill_buf()?.first() == Some(&b'%') {
+        let mut bytes: Vec<u8> = Vec::new();
+        reader.read_until(b'\n', &mut bytes)?;
+        let line = String::from_utf8_lossy(&bytes).trim_end().to_lowercase();
+        if let Some(format_str) = line.strip_prefix("% format ") {
+            match format_str.split(';').next().map(str::trim) {
+                Some("evt2") => format = Some(EventFormat::Evt2),
+                Some("evt21") => format = Some(EventFormat::Evt21),
+                Some("evt3") => format = Some(EventFormat::Evt3),
+                Some("delta") => format = Some(EventFormat::Delta),
+                _ => {}
+            }
+        } else if let Some(version) = line.strip_prefix("% evt ") {
+            if format.is_none() {
+                match version.trim() {
+                    "2.0" => format = Some(EventFormat::Evt2),
+                    "2.1" => format = Some(EventFormat::Evt21),
+                    "3.0" => format = Some(EventFormat::Evt3),
+                    _ => {}
+                }
             }
+        } else if line == "% end" {
+            raw_end = true;
+            break;
+        } else if line.starts_with("% data file") || line.starts_with("% version ") {
+            dat_header = true;
         }
+    }
+
+    if format.is_none() && !raw_end {
+        // A DAT header is followed by the event type and a record size of at least one CD event
+        let preamble = reader.fill_buf()?;
+        if preamble.len() >= 2
+            && preamble[1] >= raw_decoder_dat::DAT_EVENT_SIZE_CD
+            && (dat_header || preamble[0] == raw_decoder_dat::DAT_EVENT_TYPE_CD)
+        {
+            format = Some(EventFormat::Dat);
+        } else {
+            format = detect_text_format(reader.fill_buf()?);
+        }
+    } else if format.is_none() {
+        // .raw files from before the "% format" line was introduced are EVT2
+        format = Some(EventFormat::Evt2);
+    }
+    reader.seek(SeekFrom::Start(0))?;
+    Ok(format)
+}
+
+// Detects delimited text events from the first row after any "#" comments, which must be printable text
+// with at least four comma or tab separated fields
+fn detect_text_format(start: &[u8]) -> Option<EventFormat> {
+    let row = start
+        .split(|b| *b == b'\n')
+        .find(|line| !line.starts_with(b"#"))?;
+    let row = row.strip_suffix(b"\r").unwrap_or(row);
+    if row.is_empty() || !row.iter().all(|b| b.is_ascii_graphic() || *b == b' ' || *b == b'\t') {
+        return None;
+    }
+    if row.split(|b| *b == b'\t').count() >= 4 {
+        Some(EventFormat::Tsv)
+    } else if row.split(|b| *b == b',').count() >= 4 {
+        Some(EventFormat::Csv)
     } else {
-        // If file extension is not .dat or .raw, return an error
-        anyhow::bail!("Unsupported file format. Please provide a .dat or .raw file.");
+        None
+    }
+}
+
+// Selects and initializes a decoder for the given file. The format is detected from the file's contents,
+// falling back to its extension if they are inconclusive. The open callback is invoked once per
+// attempted format, so that each candidate decoder starts from a fresh reader.
+fn prep_decoder<R, F>(file_path: &str, open: F) -> anyhow::Result<DvsRawDecoderEnum<R>>
+where
+    R: Read + BufRead + Seek,
+    F: Fn() -> anyhow::Result<R>,
+{
+    let _span = tracing::trace_span!("open").entered();
+    let mut reader = open()?;
+    // Compressed files are named after the file they hold
+    let file_path = strip_compression_suffix(file_path);
+    if let Some(hint) = detect_aedat(&mut reader)? {
+        tracing::debug!(format = ?hint, path = file_path, "detected format");
+        return init_aedat_decoder(hint, reader);
+    }
+    match detect_format(&mut reader)? {
+        Some(format) => {
+            tracing::debug!(?format, path = file_path, "detected format");
+            init_decoder(format, reader)
+        }
+        None if file_path.ends_with(".dat") => init_decoder(EventFormat::Dat, reader),
+        None if file_path.ends_with(".aedat") => init_aedat_decoder(FormatHint::Aedat3, reader),
+        None if file_path.ends_with(".aedat4") => init_aedat_decoder(FormatHint::Aedat4, reader),
+        None if file_path.ends_with(".raw") => {
+            // Try reading it as an EVT2 file
+            let mut decoder = DVSRawDecoderEvt2::new(reader);
+            match decoder.read_header() {
+                Ok(_) => Ok(DvsRawDecoderEnum::Evt2(decoder)),
+                Err(_) => {
+                    // Try reading as an EVT3 file
+                    let mut decoder = DVSRawDecoderEvt3::new(open()?);
+                    if let Err(e) = decoder.read_header() {
+                        anyhow::bail!("Error parsing file header. Invalid file type: {}", e);
+                    }
+                    Ok(DvsRawDecoderEnum::Evt3(decoder))
+                }
+            }
+        }
+        None => {
+            Err(DvsError::UnsupportedFormat("Unsupported file format. Could not detect an EVT2, EVT3, DAT or AEDAT header.".to_string()).into())
+        }
     }
 }
 
-pub fn prep_file_encoder<R: std::io::Seek>(file_path: &str) -> anyhow::Result<DvsRawEncoderEnum<BufWriter<File>>> {
+pub fn prep_file_encoder<R: std::io::Seek>(file_path: &str, format: EventFormat) -> anyhow::Result<DvsRawEncoderEnum<BufWriter<OutputFile>>> {
+    prep_file_encoder_with_options(file_path, format, CsvOptions::default())
+}
+
+// Prepares an encoder, with the column order and timestamp unit used for CSV/TSV output. The delimiter
+// is set by the format. Paths ending in .gz or .zst are compressed, see compress.rs
+pub fn prep_file_encoder_with_options(file_path: &str, format: EventFormat, csv_options: CsvOptions) -> anyhow::Result<DvsRawEncoderEnum<BufWriter<OutputFile>>> {
+    prep_file_encoder_compressed(file_path, format, csv_options, Compression::from_path(file_path))
+}
+
+// Prepares an encoder writing a file with the given compression, whatever its path
+pub fn prep_file_encoder_compressed(file_path: &str, format: EventFormat, csv_options: CsvOptions, compression: Compression) -> anyhow::Result<DvsRawEncoderEnum<BufWriter<OutputFile>>> {
+    if format == EventFormat::Npy && compression != Compression::None {
+        anyhow::bail!("NPY files can't be written compressed, as their header is rewritten once complete. Write .npz instead");
+    }
     // Delete the file if it exists
     let file_ = File::open(file_path);
     if file_.is_ok() {
         let _ = fs::remove_file(file_path);
     }
-    let file =  File::create(file_path).unwrap();
-    let writer = BufWriter::new(file);
-    Ok(DvsRawEncoderEnum::Evt2(DVSRawEncoderEvt2::new(writer)))
+    let file = OutputFile::create(file_path, compression)?;
+    prep_encoder(BufWriter::new(file), format, csv_options)
+}
+
+// Prepares an encoder writing to any seekable destination, e.g. a File or a Cursor<Vec<u8>>
+pub fn prep_encoder<W: Write + Seek>(writer: W, format: EventFormat, csv_options: CsvOptions) -> anyhow::Result<DvsRawEncoderEnum<W>> {
+    match format {
+        EventFormat::Evt2 => Ok(DvsRawEncoderEnum::Evt2(DVSRawEncoderEvt2::new(writer))),
+        EventFormat::Evt21 => Ok(DvsRawEncoderEnum::Evt21(DVSRawEncoderEvt21::new(writer))),
+        EventFormat::Evt3 => Ok(DvsRawEncoderEnum::Evt3(DVSRawEncoderEvt3::new(writer))),
+        EventFormat::Dat => Ok(DvsRawEncoderEnum::Dat(DVSRawEncoderDat::new(writer))),
+        EventFormat::Csv => Ok(DvsRawEncoderEnum::Csv(DVSRawEncoderCsv::with_options(writer, CsvOptions { delimiter: b',', ..csv_options }))),
+        EventFormat::Tsv => Ok(DvsRawEncoderEnum::Csv(DVSRawEncoderCsv::with_options(writer, CsvOptions { delimiter: b'\t', ..csv_options }))),
+        EventFormat::Npy => Ok(DvsRawEncoderEnum::Npy(DVSRawEncoderNpy::new(writer))),
+        EventFormat::Npz => Ok(DvsRawEncoderEnum::Npy(DVSRawEncoderNpy::new_npz(writer))),
+        EventFormat::Mcap => Ok(DvsRawEncoderEnum::Mcap(Box::new(DVSRawEncoderMcap::new(writer)))),
+        #[cfg(feature = "ros")]
+        EventFormat::Rosbag2 => Ok(DvsRawEncoderEnum::Mcap(Box::new(DVSRawEncoderMcap::with_options(writer, McapOptions::rosbag2())))),
+        EventFormat::Delta => Ok(DvsRawEncoderEnum::Delta(DVSRawEncoderDelta::new(writer))),
+        #[cfg(feature = "parquet")]
+        EventFormat::Parquet => Ok(DvsRawEncoderEnum::Parquet(Box::new(DVSRawEncoderParquet::new(writer)?))),
+    }
+}
+
+// Finds the sensor geometry (width, height) in a header copied from a .raw or .dat file
+pub(crate) fn header_geometry(header: &[String]) -> Option<(u32, u32)> {
+    let mut width: Option<u32> = None;
+    let mut height: Option<u32> = None;
+    for line in header {
+        let line = line.trim_end();
+        let lower = line.to_lowercase();
+        if let Some(geometry) = line.strip_prefix("% geometry ") {
+            if let Some((w, h)) = geometry.split_once('x') {
+                width = w.trim().parse().ok().or(width);
+                height = h.trim().parse().ok().or(height);
+            }
+        } else if let Some(format_str) = line.strip_prefix("% format ") {
+            for option in format_str.split(';').skip(1) {
+                match option.split_once('=') {
+                    Some(("width", value)) => width = value.trim().parse().ok().or(width),
+                    Some(("height", value)) => height = value.trim().parse().ok().or(height),
+                    _ => {}
+                }
+            }
+        } else if let Some(value) = lower.strip_prefix("% width ") {
+            width = value.trim().parse().ok().or(width);
+        } else if let Some(value) = lower.strip_prefix("% height ") {
+            height = value.trim().parse().ok().or(height);
+        }
+    }
+    width.zip(height)
+}
+
+// Sets the sensor geometry given by a header, for streams whose coordinates were changed, e.g. cropped. The
+// "% geometry" line and the width and height options of "% format" are updated, and a "% geometry" line is added
+// before "% end" if the header gives no geometry
+pub(crate) fn set_header_geometry(header: Vec<String>, width: u32, height: u32) -> Vec<String> {
+    let mut found = false;
+    let mut lines: Vec<String> = Vec::with_capacity(header.len() + 1);
+    for line in header {
+        let newline = if line.ends_with('\n') { "\n" } else { "" };
+        let trimmed = line.trim_end();
+        let lower = trimmed.to_lowercase();
+        if trimmed.starts_with("% geometry ") {
+            lines.push(format!("% geometry {}x{}{}", width, height, newline));
+            found = true;
+        } else if let Some(format_str) = trimmed.strip_prefix("% format ") {
+            let options: Vec<String> = format_str
+                .split(';')
+                .enumerate()
+                .map(|(i, option)| match option.split_once('=') {
+                    Some(("width", _)) if i > 0 => format!("width={}", width),
+                    Some(("height", _)) if i > 0 => format!("height={}", height),
+                    _ => option.to_string(),
+                })
+                .collect();
+            found |= header_geometry(std::slice::from_ref(&line)).is_some();
+            lines.push(format!("% format {}{}", options.join(";"), newline));
+        } else if lower.starts_with("% width ") {
+            lines.push(format!("% width {}{}", width, newline));
+            found = true;
+        } else if lower.starts_with("% height ") {
+            lines.push(format!("% height {}{}", height, newline));
+            found = true;
+        } else {
+            lines.push(line);
+        }
+    }
+    if !found {
+        let end = lines.iter().position(|line| line.trim_end() == "% end").unwrap_or(lines.len());
+        lines.insert(end, format!("% geometry {}x{}\n", width, height));
+    }
+    lines
+}
+
+// Rewrites a header copied from an input file so that it describes a .raw file of the given format.
+// The "% evt" and "% format" lines are updated, lines that are not part of a .raw header are dropped,
+// and the header is terminated with "% end".
+pub(crate) fn rewrite_raw_header(header: Vec<String>, format: &str, evt_version: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    let mut has_evt = false;
+    for line in header {
+        let line = line.trim_end();
+        if !line.starts_with('%') || line == "% end" {
+            continue;
+        } else if line.starts_with("% evt ") {
+            lines.push(format!("% evt {}\n", evt_version));
+            has_evt = true;
+        } else if let Some(format_str) = line.strip_prefix("% format ") {
+            // Keep the format options, e.g. the sensor geometry
+            let options = format_str.split_once(';').map(|(_, options)| options);
+            match options {
+                Some(options) => lines.push(format!("% format {};{}\n", format, options)),
+                None => lines.push(format!("% format {}\n", format)),
+            }
+        } else {
+            lines.push(format!("{}\n", line));
+        }
+    }
+    if !has_evt {
+        lines.insert(0, format!("% evt {}\n", evt_version));
+    }
+    lines.push("% end\n".to_string());
+    lines
 }
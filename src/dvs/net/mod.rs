@@ -0,0 +1,83 @@
+use crate::dvs::bitrate::{bytes_to_bits, transmission_time};
+use crate::dvs::packet::Packet;
+use crate::dvs::replay::ReplayClock;
+use std::time::Instant;
+
+/*
+This module implements network transports for event streams, enabled by the transport feature.
+Events are segmented into packets by the packetizer (see packet.rs) and sent over the network.
+
+The transports send the same kinds of messages, identified by their first byte:
+  0: the header of the recording, as lines separated by '\n'
+  1: a packet of events, see packet.rs
+  2: the end of the stream, followed by the number of event packets sent (u32, little endian)
+*/
+
+#[cfg(feature = "quic")]
+pub mod quic;
+pub mod tcp;
+pub mod udp;
+
+pub(crate) const KIND_HEADER: u8 = 0;
+pub(crate) const KIND_EVENTS: u8 = 1;
+pub(crate) const KIND_END: u8 = 2;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum Pacing {
+    // Send packets as fast as possible
+    #[default]
+    Unpaced,
+    // Send packets no faster than a link of the given bandwidth, including protocol headers
+    Bandwidth { mbps: f64 },
+    // Send each packet once the time of its last event has passed, sped up by the given factor
+    RealTime { speed: f64 },
+}
+
+// Delays packets according to a Pacing
+pub(crate) struct Pacer {
+    pacing: Pacing,
+    // Headers added to each message by the transport, in bytes
+    overhead_bytes: usize,
+    // For real time pacing
+    clock: Option<ReplayClock>,
+    // Earliest time the next packet can be sent, for bandwidth pacing
+    next_send: Option<Instant>,
+}
+
+fn sleep_until(deadline: Instant) {
+    let now = Instant::now();
+    if deadline > now {
+        std::thread::sleep(deadline - now);
+    }
+}
+
+impl Pacer {
+    pub(crate) fn new(pacing: Pacing, overhead_bytes: usize) -> Self {
+        let clock = match pacing {
+            Pacing::RealTime { speed } => Some(ReplayClock::new(speed)),
+            _ => None,
+        };
+        Self { pacing, overhead_bytes, clock, next_send: None }
+    }
+
+    // Waits until the packet can be sent
+    pub(crate) fn wait(&mut self, packet: &Packet) {
+        if let Some(next_send) = self.next_send {
+            sleep_until(next_send);
+        }
+        if let Some(clock) = self.clock.as_mut() {
+            clock.deadline(packet.base_timestamp);
+            clock.wait(packet.events.last().map_or(packet.base_timestamp, |event| event.timestamp));
+        }
+    }
+
+    // Records that a message of the given size was sent
+    pub(crate) fn sent(&mut self, bytes: usize) {
+        if let Pacing::Bandwidth { mbps } = self.pacing {
+            let bits = bytes_to_bits((bytes + self.overhead_bytes) as u64);
+            let now = Instant::now();
+            let from = self.next_send.map_or(now, |next_send| next_send.max(now));
+            self.next_send = Some(from + transmission_time(bits, mbps));
+        }
+    }
+}
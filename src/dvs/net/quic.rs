@@ -0,0 +1,616 @@
+use crate::dvs::bitrate::UDP_OVERHEAD_BYTES;
+use crate::dvs::chunk::{Chunk, Chunker};
+use crate::dvs::error::DvsError;
+use crate::dvs::net::tcp::encode_frame;
+use crate::dvs::net::{Pacer, Pacing, KIND_END, KIND_EVENTS, KIND_HEADER};
+use crate::dvs::packet::{Packet, Packetizer, PacketizerOptions};
+use crate::dvs::{DvsRawDecoder, DvsRawEncoder, DVSEvent};
+use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+use quinn::{ClientConfig, Connection, Endpoint, SendStream, ServerConfig, TransportConfig, VarInt};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, RootCertStore, SignatureScheme};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, Read, Seek, Write};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+/*
+This file implements streaming events over QUIC, enabled by the quic feature. The sender groups events into
+chunks of fixed windows of event time (see chunk.rs), like the loss simulation, and sends each chunk on its own
+unidirectional QUIC stream, as length-prefixed messages like those of the TCP transport (see tcp.rs): packets of
+events, see packet.rs. QUIC retransmits lost data within a stream, so a chunk arrives whole or not at all, and a
+slow chunk doesn't hold up the ones after it. A chunk that isn't delivered within the sender's deadline is
+abandoned: its stream is reset and QUIC stops retransmitting it. This is the network counterpart of the chunk
+loss models of loss.rs.
+
+The first stream carries the header of the recording, and the last one the end of the stream, whose message
+holds the number of event packets sent, the number of chunks, then the stream numbers of the abandoned chunks
+(u32, little endian). Stream numbers give the order of chunks, so the receiver reorders them without a sequence
+number of its own, and knows which chunk a reset stream belonged to. Unlike the UDP receiver, it doesn't give up
+on a missing chunk after a window of later ones: the chunk either arrives or is reset by the sender, which then
+counts as lost. The sender only ends the stream once every chunk was delivered or abandoned, so the receiver
+needn't wait for resets that were still in flight when the connection closed.
+
+QUIC is always encrypted. The receiver generates a self-signed certificate for "localhost". The sender trusts
+that certificate when given it, and otherwise accepts any certificate, which encrypts the stream but doesn't
+authenticate the receiver, like the UDP and TCP transports. Each end runs a tokio runtime with a single worker
+thread, which drives the connection and delivers streams in the background, behind a blocking interface.
+*/
+
+// Name the receiver's certificate is issued for, and that the sender connects to
+const SERVER_NAME: &str = "localhost";
+
+// Bytes of the QUIC packets carrying a stream after the UDP header: the smallest maximum datagram size
+// QUIC allows, less a short header with an 8 byte connection ID, the AEAD tag and a stream frame header
+const QUIC_PAYLOAD_BYTES: usize = 1200 - 37;
+// Headers of a QUIC packet, over IPv4 and UDP
+const QUIC_OVERHEAD_BYTES: usize = UDP_OVERHEAD_BYTES + 37;
+// Length and kind of a message
+const FRAME_HEADER_BYTES: usize = 5;
+
+// Largest stream the receiver reads. Longer streams are abandoned
+const MAX_STREAM_BYTES: usize = 64 * 1024 * 1024;
+// Streams the sender may have open at a time, each holding one chunk
+const MAX_OPEN_STREAMS: u32 = 1024;
+
+// Application error codes, for stream resets and the connection close
+const CODE_DONE: u32 = 0;
+const CODE_ABANDONED: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuicSenderOptions {
+    pub packet: PacketizerOptions,
+    pub pacing: Pacing,
+    // Window of event time sent on each stream, in microseconds
+    pub chunk_us: i64,
+    // Time a chunk has to be delivered once it is handed to QUIC, after which it is abandoned, or None to
+    // deliver every chunk
+    pub deadline: Option<Duration>,
+    // DER certificate of the receiver, or None to accept any certificate
+    pub certificate: Option<Vec<u8>>,
+}
+
+impl Default for QuicSenderOptions {
+    fn default() -> Self {
+        QuicSenderOptions {
+            // Leave room for the message header in a QUIC packet
+            packet: PacketizerOptions { max_bytes: QUIC_PAYLOAD_BYTES - FRAME_HEADER_BYTES, ..PacketizerOptions::default() },
+            pacing: Pacing::default(),
+            chunk_us: 10_000,
+            deadline: Some(Duration::from_millis(200)),
+            certificate: None,
+        }
+    }
+}
+
+fn runtime() -> anyhow::Result<Runtime> {
+    Ok(tokio::runtime::Builder::new_multi_thread().worker_threads(1).enable_all().build()?)
+}
+
+fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+// Accepts any certificate, while still checking the handshake signatures made with it
+#[derive(Debug)]
+struct AnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AnyCertificate {
+    fn verify_server_cert(&self, _: &CertificateDer<'_>, _: &[CertificateDer<'_>], _: &ServerName<'_>, _: &[u8], _: UnixTime) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(&self, message: &[u8], cert: &CertificateDer<'_>, dss: &DigitallySignedStruct) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn client_config(certificate: Option<&[u8]>) -> anyhow::Result<ClientConfig> {
+    let provider = crypto_provider();
+    let builder = rustls::ClientConfig::builder_with_provider(provider.clone()).with_protocol_versions(&[&rustls::version::TLS13])?;
+    let tls = match certificate {
+        Some(certificate) => {
+            let mut roots = RootCertStore::empty();
+            roots.add(CertificateDer::from(certificate.to_vec()))?;
+            builder.with_root_certificates(roots).with_no_client_auth()
+        }
+        None => builder.dangerous().with_custom_certificate_verifier(Arc::new(AnyCertificate(provider))).with_no_client_auth(),
+    };
+    Ok(ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls)?)))
+}
+
+// A server configuration with a new self-signed certificate, and that certificate
+fn server_config() -> anyhow::Result<(ServerConfig, Vec<u8>)> {
+    let certified = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])?;
+    let certificate = certified.cert.der().to_vec();
+    let key = PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der()));
+    let tls = rustls::ServerConfig::builder_with_provider(crypto_provider())
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(vec![CertificateDer::from(certificate.clone())], key)?;
+    let mut config = ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls)?));
+    let mut transport = TransportConfig::default();
+    transport.max_concurrent_uni_streams(VarInt::from_u32(MAX_OPEN_STREAMS));
+    config.transport_config(Arc::new(transport));
+    Ok((config, certificate))
+}
+
+// Writes a chunk to its stream and waits until the receiver has acknowledged all of it
+async fn deliver(stream: &mut SendStream, bytes: &[u8]) -> anyhow::Result<()> {
+    stream.write_all(bytes).await?;
+    stream.finish()?;
+    stream.stopped().await?;
+    Ok(())
+}
+
+pub struct QuicEventSender {
+    runtime: Runtime,
+    endpoint: Endpoint,
+    connection: Connection,
+    options: QuicSenderOptions,
+    chunker: Chunker,
+    packetizer: Packetizer,
+    pacer: Pacer,
+    header_sent: bool,
+    frame: Vec<u8>,
+    // Chunks still being delivered
+    deliveries: Vec<JoinHandle<()>>,
+    // Stream numbers of the chunks abandoned so far
+    abandoned: Arc<Mutex<Vec<u32>>>,
+    pub chunks_sent: u64,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+}
+
+impl QuicEventSender {
+    // Connects to a receiver, from an unspecified local port
+    pub fn connect(address: impl ToSocketAddrs, options: QuicSenderOptions) -> anyhow::Result<Self> {
+        let Some(address) = address.to_socket_addrs()?.next() else {
+            anyhow::bail!("No address to send events to");
+        };
+        let local: SocketAddr = if address.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+        let runtime = runtime()?;
+        let config = client_config(options.certificate.as_deref())?;
+        let (endpoint, connection) = runtime.block_on(async {
+            let mut endpoint = Endpoint::client(local)?;
+            endpoint.set_default_client_config(config);
+            let connection = endpoint.connect(address, SERVER_NAME)?.await?;
+            anyhow::Ok((endpoint, connection))
+        })?;
+        Ok(Self {
+            runtime,
+            endpoint,
+            connection,
+            chunker: Chunker::new(options.chunk_us),
+            packetizer: Packetizer::new(options.packet),
+            pacer: Pacer::new(options.pacing, QUIC_OVERHEAD_BYTES),
+            options,
+            header_sent: false,
+            frame: Vec::new(),
+            deliveries: Vec::new(),
+            abandoned: Arc::new(Mutex::new(Vec::new())),
+            chunks_sent: 0,
+            packets_sent: 0,
+            bytes_sent: 0,
+        })
+    }
+
+    // Chunks whose stream was reset as they missed the deadline
+    pub fn chunks_abandoned(&self) -> u64 {
+        self.abandoned.lock().unwrap().len() as u64
+    }
+
+    // Sends a stream that isn't abandoned, returning it to wait for its delivery
+    fn send_stream(&mut self, bytes: Vec<u8>) -> anyhow::Result<SendStream> {
+        let connection = &self.connection;
+        let stream = self.runtime.block_on(async {
+            let mut stream = connection.open_uni().await?;
+            stream.write_all(&bytes).await?;
+            stream.finish()?;
+            anyhow::Ok(stream)
+        })?;
+        self.bytes_sent += bytes.len() as u64;
+        Ok(stream)
+    }
+
+    // Sends the header of the recording on the first stream. Call before sending events, or an empty
+    // header is sent
+    pub fn send_header(&mut self, header: &[String]) -> anyhow::Result<()> {
+        if self.header_sent {
+            anyhow::bail!("The header was already sent");
+        }
+        encode_frame(&mut self.frame, KIND_HEADER, |buf| buf.extend_from_slice(header.join("\n").as_bytes()));
+        self.send_stream(self.frame.clone())?;
+        self.header_sent = true;
+        Ok(())
+    }
+
+    // Sends the packets of a chunk on a new stream, and resets the stream if it isn't delivered in time
+    fn send_chunk(&mut self, chunk: Chunk) -> anyhow::Result<()> {
+        if !self.header_sent {
+            self.send_header(&[])?;
+        }
+        let mut packets: Vec<Packet> = Vec::new();
+        for event in chunk.events {
+            self.packetizer.process(event, &mut |packet| packets.push(packet));
+        }
+        self.packetizer.finish(&mut |packet| packets.push(packet));
+
+        let mut bytes = Vec::new();
+        for packet in &packets {
+            self.pacer.wait(packet);
+            encode_frame(&mut self.frame, KIND_EVENTS, |buf| packet.encode_into(buf));
+            bytes.extend_from_slice(&self.frame);
+            self.pacer.sent(self.frame.len());
+        }
+        self.packets_sent += packets.len() as u64;
+        self.bytes_sent += bytes.len() as u64;
+        self.chunks_sent += 1;
+
+        let connection = self.connection.clone();
+        let mut stream = self.runtime.block_on(connection.open_uni())?;
+        let deadline = self.options.deadline;
+        let abandoned = self.abandoned.clone();
+        self.deliveries.retain(|delivery| !delivery.is_finished());
+        self.deliveries.push(self.runtime.spawn(async move {
+            let delivered = match deadline {
+                Some(deadline) => tokio::time::timeout(deadline, deliver(&mut stream, &bytes)).await.is_ok(),
+                None => deliver(&mut stream, &bytes).await.is_ok(),
+            };
+            // A stream the connection already lost can't be reset, it is lost anyway
+            if !delivered && stream.reset(VarInt::from_u32(CODE_ABANDONED)).is_ok() {
+                abandoned.lock().unwrap().push(stream.id().index() as u32);
+            }
+        }));
+        Ok(())
+    }
+
+    // Adds the next event of a time-ordered stream, sending the chunk it completes
+    pub fn send(&mut self, event: DVSEvent) -> anyhow::Result<()> {
+        match self.chunker.push(event) {
+            Some(chunk) => self.send_chunk(chunk),
+            None => Ok(()),
+        }
+    }
+
+    pub fn send_events(&mut self, events: &[DVSEvent]) -> anyhow::Result<()> {
+        for event in events {
+            self.send(*event)?;
+        }
+        Ok(())
+    }
+
+    // Sends the last chunk, waits until every chunk is delivered or abandoned, then marks the end of the
+    // stream and closes the connection
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        if let Some(chunk) = self.chunker.finish() {
+            self.send_chunk(chunk)?;
+        }
+        if !self.header_sent {
+            self.send_header(&[])?;
+        }
+        let deliveries = std::mem::take(&mut self.deliveries);
+        self.runtime.block_on(async {
+            for delivery in deliveries {
+                delivery.await?;
+            }
+            anyhow::Ok(())
+        })?;
+
+        let packets = self.packetizer.packets();
+        let chunks = self.chunks_sent as u32;
+        let abandoned = self.abandoned.lock().unwrap().clone();
+        encode_frame(&mut self.frame, KIND_END, |buf| {
+            buf.extend_from_slice(&packets.to_le_bytes());
+            buf.extend_from_slice(&chunks.to_le_bytes());
+            for number in &abandoned {
+                buf.extend_from_slice(&number.to_le_bytes());
+            }
+        });
+        let end = self.send_stream(self.frame.clone())?;
+        let connection = &self.connection;
+        let endpoint = &self.endpoint;
+        self.runtime.block_on(async {
+            // The receiver may close the connection as soon as it has the end of the stream
+            let _ = end.stopped().await;
+            connection.close(VarInt::from_u32(CODE_DONE), b"");
+            endpoint.wait_idle().await;
+            anyhow::Ok(())
+        })
+    }
+}
+
+// Implemented like DvsRawEncoderEnum, so events can be sent wherever an encoder is expected
+impl<W: Write + Seek> DvsRawEncoder<W> for QuicEventSender {
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        Ok(self.send_header(&header)?)
+    }
+
+    // Returns the number of packets sent
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        let sent = self.packets_sent;
+        self.send(event)?;
+        Ok((self.packets_sent - sent) as usize)
+    }
+
+    // Ends the stream, see finish
+    fn flush(&mut self) -> Result<(), DvsError> {
+        Ok(self.finish()?)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct QuicReceiverOptions {
+    // Time without a connection or a chunk after which the stream is considered to have ended, or None to
+    // wait forever
+    pub timeout: Option<Duration>,
+}
+
+impl Default for QuicReceiverOptions {
+    fn default() -> Self {
+        QuicReceiverOptions { timeout: Some(Duration::from_secs(5)) }
+    }
+}
+
+// A stream received by the connection: its number and its bytes, or None if it was reset or lost
+type ReceivedStream = (u64, Option<Vec<u8>>);
+
+// Reads the streams of a connection as they arrive, each in its own task, until the connection closes
+async fn receive_streams(connection: Connection, streams: mpsc::UnboundedSender<ReceivedStream>) {
+    while let Ok(mut stream) = connection.accept_uni().await {
+        let streams = streams.clone();
+        tokio::spawn(async move {
+            let body = stream.read_to_end(MAX_STREAM_BYTES).await.ok();
+            let _ = streams.send((stream.id().index(), body));
+        });
+    }
+}
+
+// Splits the body of a stream into its messages, as (kind, body)
+fn messages(mut bytes: &[u8]) -> anyhow::Result<Vec<(u8, &[u8])>> {
+    let mut messages = Vec::new();
+    while !bytes.is_empty() {
+        let length = match bytes {
+            [a, b, c, d, ..] => u32::from_le_bytes([*a, *b, *c, *d]) as usize,
+            _ => anyhow::bail!("Truncated message length"),
+        };
+        if length == 0 || bytes.len() < 4 + length {
+            anyhow::bail!("Invalid message of {} bytes", length);
+        }
+        messages.push((bytes[4], &bytes[5..4 + length]));
+        bytes = &bytes[4 + length..];
+    }
+    Ok(messages)
+}
+
+// Receives events from one sender
+pub struct QuicEventReceiver {
+    runtime: Runtime,
+    endpoint: Endpoint,
+    certificate: Vec<u8>,
+    options: QuicReceiverOptions,
+    // Streams of the connection, once the sender has connected
+    streams: Option<mpsc::UnboundedReceiver<ReceivedStream>>,
+    header: Option<Vec<String>>,
+    // Chunks received ahead of the next one, by stream number, or None for chunks the sender abandoned
+    pending: BTreeMap<u64, Option<Vec<DVSEvent>>>,
+    // Stream number of the next chunk. The header is on stream 0
+    next_chunk: u64,
+    // Number of chunks sent, once the sender has ended the stream
+    total_chunks: Option<u64>,
+    ready: VecDeque<DVSEvent>,
+    ended: bool,
+    pub chunks_received: u64,
+    pub chunks_lost: u64,
+    pub packets_received: u64,
+}
+
+impl QuicEventReceiver {
+    pub fn bind(address: impl ToSocketAddrs, options: QuicReceiverOptions) -> anyhow::Result<Self> {
+        let Some(address) = address.to_socket_addrs()?.next() else {
+            anyhow::bail!("No address to receive events on");
+        };
+        let runtime = runtime()?;
+        let (config, certificate) = server_config()?;
+        let endpoint = {
+            let _runtime = runtime.enter();
+            Endpoint::server(config, address)?
+        };
+        Ok(Self {
+            runtime,
+            endpoint,
+            certificate,
+            options,
+            streams: None,
+            header: None,
+            pending: BTreeMap::new(),
+            next_chunk: 1,
+            total_chunks: None,
+            ready: VecDeque::new(),
+            ended: false,
+            chunks_received: 0,
+            chunks_lost: 0,
+            packets_received: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        Ok(self.endpoint.local_addr()?)
+    }
+
+    // The receiver's self-signed certificate, in DER, for senders to trust
+    pub fn certificate(&self) -> &[u8] {
+        &self.certificate
+    }
+
+    // Changes the time without a connection or a chunk after which the stream is considered to have ended
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) {
+        self.options.timeout = timeout;
+    }
+
+    // Moves chunks that are next in order to the ready events
+    fn deliver(&mut self) {
+        while let Some(chunk) = self.pending.remove(&self.next_chunk) {
+            match chunk {
+                Some(events) => self.ready.extend(events),
+                None => self.chunks_lost += 1,
+            }
+            self.next_chunk += 1;
+        }
+        if self.total_chunks.is_some_and(|total| self.next_chunk > total) {
+            self.ended = true;
+        }
+    }
+
+    // Skips the missing chunks before the earliest pending chunk
+    fn skip_gap(&mut self) {
+        if let Some(&chunk) = self.pending.keys().next() {
+            self.chunks_lost += chunk - self.next_chunk;
+            self.next_chunk = chunk;
+            self.deliver();
+        }
+    }
+
+    // Delivers all pending chunks, counting the missing ones up to the total as lost
+    fn end(&mut self) {
+        while !self.pending.is_empty() {
+            self.skip_gap();
+        }
+        if let Some(total) = self.total_chunks.filter(|total| *total >= self.next_chunk) {
+            self.chunks_lost += total + 1 - self.next_chunk;
+            self.next_chunk = total + 1;
+        }
+        self.ended = true;
+    }
+
+    // Waits for the sender to connect and for a stream. Returns None if the timeout expired first, or the
+    // connection closed
+    fn next_stream(&mut self) -> anyhow::Result<Option<ReceivedStream>> {
+        let timeout = self.options.timeout;
+        let endpoint = &self.endpoint;
+        let streams = &mut self.streams;
+        self.runtime.block_on(async {
+            let wait = async {
+                if streams.is_none() {
+                    let Some(incoming) = endpoint.accept().await else {
+                        return Ok(None);
+                    };
+                    let connection = incoming.await?;
+                    let (sender, receiver) = mpsc::unbounded_channel();
+                    tokio::spawn(receive_streams(connection, sender));
+                    *streams = Some(receiver);
+                }
+                anyhow::Ok(streams.as_mut().unwrap().recv().await)
+            };
+            match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, wait).await.unwrap_or(Ok(None)),
+                None => wait.await,
+            }
+        })
+    }
+
+    // Receives and handles one stream. Returns false if the timeout expired first, or the connection closed
+    fn receive(&mut self) -> anyhow::Result<bool> {
+        let Some((number, body)) = self.next_stream()? else {
+            return Ok(false);
+        };
+        let Some(body) = body else {
+            // Only chunks are abandoned, a header lost with the connection is left empty
+            if number == 0 {
+                self.header.get_or_insert_with(Vec::new);
+            } else if number >= self.next_chunk {
+                self.pending.insert(number, None);
+                self.deliver();
+            }
+            return Ok(true);
+        };
+        let mut events = Vec::new();
+        let mut is_chunk = false;
+        for (kind, message) in messages(&body)? {
+            match kind {
+                KIND_HEADER => {
+                    if self.header.is_none() {
+                        let header = String::from_utf8_lossy(message);
+                        self.header = Some(header.lines().map(str::to_string).collect());
+                    }
+                }
+                KIND_EVENTS => {
+                    events.extend(Packet::decode(message)?.events);
+                    self.packets_received += 1;
+                    is_chunk = true;
+                }
+                KIND_END if message.len() >= 8 && message.len() % 4 == 0 => {
+                    let mut numbers = message.chunks_exact(4).map(|n| u32::from_le_bytes([n[0], n[1], n[2], n[3]]) as u64);
+                    self.total_chunks = numbers.nth(1);
+                    // Abandoned chunks whose reset hasn't arrived yet. A chunk that arrived whole is kept
+                    for number in numbers {
+                        if number >= self.next_chunk {
+                            self.pending.entry(number).or_insert(None);
+                        }
+                    }
+                    self.deliver();
+                }
+                _ => anyhow::bail!("Unexpected message of kind {}", kind),
+            }
+        }
+        // A chunk arriving after it was counted as lost is dropped
+        if is_chunk && number >= self.next_chunk && self.pending.get(&number).is_none_or(Option::is_none) {
+            self.chunks_received += 1;
+            self.pending.insert(number, Some(events));
+            self.deliver();
+        }
+        Ok(true)
+    }
+}
+
+// Closes the connection, so the sender doesn't wait for it to time out
+impl Drop for QuicEventReceiver {
+    fn drop(&mut self) {
+        self.endpoint.close(VarInt::from_u32(CODE_DONE), b"");
+        self.runtime.block_on(self.endpoint.wait_idle());
+    }
+}
+
+// Implemented like DvsRawDecoderEnum, so received events can be used wherever a decoder is expected
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for QuicEventReceiver {
+    // Waits for the header of the recording, which chunks may overtake. Returns an empty header if the stream
+    // ended first
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        while self.header.is_none() && !self.ended {
+            if !self.receive()? {
+                self.end();
+            }
+        }
+        Ok(self.header.clone().unwrap_or_default())
+    }
+
+    // Returns the next event in chunk order. Returns Ok(None) once the sender has ended the stream, the
+    // connection closed or the timeout expires, like the decoders
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.ended {
+                return Ok(None);
+            }
+            if !self.receive()? {
+                self.end();
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::net::{Pacer, Pacing, KIND_END, KIND_EVENTS, KIND_HEADER};
+use crate::dvs::packet::{Packet, Packetizer, PacketizerOptions};
+use crate::dvs::{prep_file_decoder, DvsRawDecoder, DVSEvent};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Seek, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+
+/*
+This file implements relaying events over TCP. Messages (see net/mod.rs) are framed with their length as a
+u32, little endian, counting the kind byte. The server sends the header of the recording, then packets of
+events, then the end of the stream. The client reads frames and implements DvsRawDecoder, so a relayed
+stream can be used wherever a decoder is expected.
+
+The server either sends a file to each client from the start, with a thread per client, or broadcasts a
+live source to all clients connected at the time. Broadcasting writes to each client in turn, so a slow
+client holds up the others; clients that disconnect are dropped.
+*/
+
+// Size of IPv4 and TCP headers, in bytes
+pub const TCP_OVERHEAD_BYTES: usize = 40;
+
+// Largest frame the client accepts
+const MAX_FRAME_BYTES: usize = 16 * 1024 * 1024;
+
+// Number of events read from the source at a time. Kept small so live sources are relayed promptly
+const BATCH_SIZE: usize = 1024;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct TcpServerOptions {
+    pub packet: PacketizerOptions,
+    pub pacing: Pacing,
+}
+
+// Replaces buf with a frame holding a message of the given kind, with the body written by fill
+pub(crate) fn encode_frame(buf: &mut Vec<u8>, kind: u8, fill: impl FnOnce(&mut Vec<u8>)) {
+    buf.clear();
+    buf.extend_from_slice(&[0; 4]);
+    buf.push(kind);
+    fill(buf);
+    let length = (buf.len() - 4) as u32;
+    buf[..4].copy_from_slice(&length.to_le_bytes());
+}
+
+// The clients a stream is sent to
+struct Clients {
+    streams: Vec<TcpStream>,
+    // Sent to clients added later, once known
+    header: Option<Vec<String>>,
+    frame: Vec<u8>,
+}
+
+impl Clients {
+    fn new() -> Self {
+        Self { streams: Vec::new(), header: None, frame: Vec::new() }
+    }
+
+    // Sends the current frame to all clients, dropping the ones that have disconnected
+    fn send_frame(&mut self) {
+        let frame = &self.frame;
+        self.streams.retain_mut(|stream| stream.write_all(frame).is_ok());
+    }
+
+    fn send_header(&mut self, header: Vec<String>) {
+        encode_frame(&mut self.frame, KIND_HEADER, |buf| buf.extend_from_slice(header.join("\n").as_bytes()));
+        self.header = Some(header);
+        self.send_frame();
+    }
+
+    fn send_packet(&mut self, packet: &Packet) {
+        encode_frame(&mut self.frame, KIND_EVENTS, |buf| packet.encode_into(buf));
+        self.send_frame();
+    }
+
+    fn send_end(&mut self, packets: u32) {
+        encode_frame(&mut self.frame, KIND_END, |buf| buf.extend_from_slice(&packets.to_le_bytes()));
+        self.send_frame();
+    }
+
+    // Adds a client, sending it the header of the stream if it has already been sent to the others
+    fn add(&mut self, mut stream: TcpStream) {
+        let _ = stream.set_nodelay(true);
+        if let Some(header) = &self.header {
+            let mut frame = Vec::new();
+            encode_frame(&mut frame, KIND_HEADER, |buf| buf.extend_from_slice(header.join("\n").as_bytes()));
+            if stream.write_all(&frame).is_err() {
+                return;
+            }
+        }
+        self.streams.push(stream);
+    }
+
+    // Adds the clients waiting on a non-blocking listener
+    fn accept(&mut self, listener: &TcpListener) -> anyhow::Result<()> {
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => {
+                    stream.set_nonblocking(false)?;
+                    self.add(stream);
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+}
+
+// Sends the events of a decoder to the clients, accepting new clients from the listener if given.
+// Without a listener, stops early once all clients have disconnected
+fn relay<R, D>(decoder: &mut D, clients: &mut Clients, options: TcpServerOptions, listener: Option<&TcpListener>) -> anyhow::Result<()>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+{
+    clients.send_header(decoder.read_header()?);
+    let mut packetizer = Packetizer::new(options.packet);
+    let mut pacer = Pacer::new(options.pacing, TCP_OVERHEAD_BYTES);
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    let mut ready: Vec<Packet> = Vec::new();
+    loop {
+        if let Some(listener) = listener {
+            clients.accept(listener)?;
+        }
+        events.clear();
+        let count = decoder.read_events_into(&mut events, BATCH_SIZE)?;
+        for event in &events {
+            packetizer.process(*event, &mut |packet| ready.push(packet));
+        }
+        if count == 0 {
+            packetizer.finish(&mut |packet| ready.push(packet));
+        }
+        for packet in ready.drain(..) {
+            pacer.wait(&packet);
+            clients.send_packet(&packet);
+            pacer.sent(clients.frame.len());
+        }
+        if count == 0 || (listener.is_none() && clients.streams.is_empty()) {
+            break;
+        }
+    }
+    clients.send_end(packetizer.packets());
+    Ok(())
+}
+
+pub struct TcpEventServer {
+    listener: TcpListener,
+    options: TcpServerOptions,
+}
+
+impl TcpEventServer {
+    pub fn bind(address: impl ToSocketAddrs, options: TcpServerOptions) -> anyhow::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        Ok(Self { listener, options })
+    }
+
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    // Sends the whole recording to each client that connects, from the start, in a thread per client.
+    // Returns once max_clients clients have been served, or keeps serving clients if None
+    pub fn serve_file(&self, path: &str, max_clients: Option<usize>) -> anyhow::Result<()> {
+        self.listener.set_nonblocking(false)?;
+        let mut handles = Vec::new();
+        for stream in self.listener.incoming().take(max_clients.unwrap_or(usize::MAX)) {
+            let stream = stream?;
+            let path = path.to_string();
+            let options = self.options;
+            handles.push(std::thread::spawn(move || -> anyhow::Result<()> {
+                let mut decoder = prep_file_decoder::<BufReader<File>>(&path)?;
+                let mut clients = Clients::new();
+                clients.add(stream);
+                relay(&mut decoder, &mut clients, options, None)
+            }));
+            // When serving indefinitely, threads are dropped once their client has been served, so errors
+            // only stop the server when max_clients is given
+            if max_clients.is_none() {
+                handles.retain(|handle| !handle.is_finished());
+            }
+        }
+        for handle in handles {
+            handle.join().map_err(|_| anyhow::anyhow!("Client thread panicked"))??;
+        }
+        Ok(())
+    }
+
+    // Broadcasts the events of a decoder to the clients connected at the time, returning once the decoder
+    // reaches its end. Clients that connect midway receive the header, then the events from then on
+    pub fn serve_live<R, D>(&self, decoder: &mut D) -> anyhow::Result<()>
+    where
+        R: Read + BufRead + Seek,
+        D: DvsRawDecoder<R>,
+    {
+        self.listener.set_nonblocking(true)?;
+        let mut clients = Clients::new();
+        relay(decoder, &mut clients, self.options, Some(&self.listener))
+    }
+}
+
+pub struct TcpEventClient {
+    stream: BufReader<TcpStream>,
+    frame: Vec<u8>,
+    header: Option<Vec<String>>,
+    events: VecDeque<DVSEvent>,
+    ended: bool,
+    pub packets_received: u64,
+}
+
+impl TcpEventClient {
+    pub fn connect(address: impl ToSocketAddrs) -> anyhow::Result<Self> {
+        let stream = TcpStream::connect(address)?;
+        Ok(Self {
+            stream: BufReader::new(stream),
+            frame: Vec::new(),
+            header: None,
+            events: VecDeque::new(),
+            ended: false,
+            packets_received: 0,
+        })
+    }
+
+    // Reads and handles one frame. A connection closed between frames ends the stream
+    fn read_frame(&mut self) -> anyhow::Result<()> {
+        let mut length = [0u8; 4];
+        match self.stream.read_exact(&mut length) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => {
+                self.ended = true;
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let length = u32::from_le_bytes(length) as usize;
+        if length == 0 || length > MAX_FRAME_BYTES {
+            anyhow::bail!("Invalid frame of {} bytes", length);
+        }
+        self.frame.resize(length, 0);
+        self.stream.read_exact(&mut self.frame)?;
+
+        let body = &self.frame[1..];
+        match self.frame[0] {
+            KIND_HEADER => {
+                let header = String::from_utf8_lossy(body);
+                self.header = Some(header.lines().map(str::to_string).collect());
+            }
+            KIND_EVENTS => {
+                self.events.extend(Packet::decode(body)?.events);
+                self.packets_received += 1;
+            }
+            KIND_END => self.ended = true,
+            kind => anyhow::bail!("Unexpected frame of kind {}", kind),
+        }
+        Ok(())
+    }
+}
+
+// Implemented like DvsRawDecoderEnum, so relayed events can be used wherever a decoder is expected
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for TcpEventClient {
+    // Waits for the header sent by the server
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        while self.header.is_none() && !self.ended {
+            self.read_frame()?;
+        }
+        Ok(self.header.clone().unwrap_or_default())
+    }
+
+    // Returns the next event. Returns Ok(None) once the server has ended the stream or closed
+    // the connection, like the decoders
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.ended {
+                return Ok(None);
+            }
+            self.read_frame()?;
+        }
+    }
+}
@@ -0,0 +1,332 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::bitrate::{UDP_OVERHEAD_BYTES, UDP_PAYLOAD_BYTES};
+use crate::dvs::net::{Pacer, Pacing, KIND_END, KIND_EVENTS, KIND_HEADER};
+use crate::dvs::packet::{Packet, Packetizer, PacketizerOptions};
+use crate::dvs::{DvsRawDecoder, DvsRawEncoder, DVSEvent};
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, ErrorKind, Read, Seek, Write};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+/*
+This file implements streaming events over UDP. The sender packetizes events and sends one packet per
+datagram, optionally paced to a link bandwidth or to the timestamps of the events. The receiver puts
+packets back in sequence order and reads like a decoder, so it can be used wherever a decoder is expected.
+
+Each datagram carries one message (see net/mod.rs). UDP is unreliable, so packets may be lost, duplicated
+or reordered. The receiver waits for missing packets until a window of later packets has arrived, then
+skips them and counts them as lost.
+*/
+
+// Largest datagram the receiver accepts
+const MAX_DATAGRAM_BYTES: usize = 65536;
+
+// Times the end of stream datagram is sent, as it may be lost like any other
+const END_REPEATS: usize = 3;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct UdpSenderOptions {
+    pub packet: PacketizerOptions,
+    pub pacing: Pacing,
+}
+
+impl Default for UdpSenderOptions {
+    fn default() -> Self {
+        UdpSenderOptions {
+            // Leave room for the kind byte in a full UDP datagram
+            packet: PacketizerOptions { max_bytes: UDP_PAYLOAD_BYTES - 1, ..PacketizerOptions::default() },
+            pacing: Pacing::default(),
+        }
+    }
+}
+
+pub struct UdpEventSender {
+    socket: UdpSocket,
+    packetizer: Packetizer,
+    ready: Vec<Packet>,
+    datagram: Vec<u8>,
+    pacer: Pacer,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+}
+
+impl UdpEventSender {
+    // Sends events to the given address from an unspecified local port
+    pub fn connect(address: impl ToSocketAddrs, options: UdpSenderOptions) -> anyhow::Result<Self> {
+        let Some(address) = address.to_socket_addrs()?.next() else {
+            anyhow::bail!("No address to send events to");
+        };
+        let local: SocketAddr = if address.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(address)?;
+        Ok(Self::from_socket(socket, options))
+    }
+
+    // Sends events over a socket that is already connected to the receiver
+    pub fn from_socket(socket: UdpSocket, options: UdpSenderOptions) -> Self {
+        Self {
+            socket,
+            packetizer: Packetizer::new(options.packet),
+            ready: Vec::new(),
+            datagram: Vec::with_capacity(options.packet.max_bytes + 1),
+            pacer: Pacer::new(options.pacing, UDP_OVERHEAD_BYTES),
+            packets_sent: 0,
+            bytes_sent: 0,
+        }
+    }
+
+    fn send_datagram(&mut self) -> anyhow::Result<()> {
+        match self.socket.send(&self.datagram) {
+            Ok(_) => {}
+            // Nobody is listening (yet). UDP is unreliable, so this is no different from a lost datagram
+            Err(e) if e.kind() == ErrorKind::ConnectionRefused => {}
+            Err(e) => return Err(e.into()),
+        }
+        self.bytes_sent += self.datagram.len() as u64;
+        Ok(())
+    }
+
+    fn send_packet(&mut self, packet: &Packet) -> anyhow::Result<()> {
+        self.pacer.wait(packet);
+        self.datagram.clear();
+        self.datagram.push(KIND_EVENTS);
+        packet.encode_into(&mut self.datagram);
+        self.send_datagram()?;
+        self.packets_sent += 1;
+        self.pacer.sent(self.datagram.len());
+        Ok(())
+    }
+
+    fn send_ready(&mut self) -> anyhow::Result<()> {
+        let ready = std::mem::take(&mut self.ready);
+        for packet in &ready {
+            self.send_packet(packet)?;
+        }
+        self.ready = ready;
+        self.ready.clear();
+        Ok(())
+    }
+
+    // Sends the header of the recording. Call before sending events
+    pub fn send_header(&mut self, header: &[String]) -> anyhow::Result<()> {
+        self.datagram.clear();
+        self.datagram.push(KIND_HEADER);
+        self.datagram.extend_from_slice(header.join("\n").as_bytes());
+        if self.datagram.len() > MAX_DATAGRAM_BYTES {
+            anyhow::bail!("Header of {} bytes doesn't fit in a datagram", self.datagram.len());
+        }
+        self.send_datagram()
+    }
+
+    // Adds the next event of a time-ordered stream, sending any packet it completes
+    pub fn send(&mut self, event: DVSEvent) -> anyhow::Result<()> {
+        let ready = &mut self.ready;
+        self.packetizer.process(event, &mut |packet| ready.push(packet));
+        self.send_ready()
+    }
+
+    pub fn send_events(&mut self, events: &[DVSEvent]) -> anyhow::Result<()> {
+        for event in events {
+            let ready = &mut self.ready;
+            self.packetizer.process(*event, &mut |packet| ready.push(packet));
+        }
+        self.send_ready()
+    }
+
+    // Sends the last, partially filled packet, then marks the end of the stream
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        let ready = &mut self.ready;
+        self.packetizer.finish(&mut |packet| ready.push(packet));
+        self.send_ready()?;
+
+        self.datagram.clear();
+        self.datagram.push(KIND_END);
+        self.datagram.extend_from_slice(&self.packetizer.packets().to_le_bytes());
+        for _ in 0..END_REPEATS {
+            self.send_datagram()?;
+        }
+        Ok(())
+    }
+}
+
+// Implemented like DvsRawEncoderEnum, so events can be sent wherever an encoder is expected
+impl<W: Write + Seek> DvsRawEncoder<W> for UdpEventSender {
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        Ok(self.send_header(&header)?)
+    }
+
+    // Returns the number of packets sent
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        let sent = self.packets_sent;
+        self.send(event)?;
+        Ok((self.packets_sent - sent) as usize)
+    }
+
+    // Ends the stream, see finish
+    fn flush(&mut self) -> Result<(), DvsError> {
+        Ok(self.finish()?)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct UdpReceiverOptions {
+    // Number of packets received after a missing packet before it is counted as lost
+    pub reorder_window: usize,
+    // Time without datagrams after which the stream is considered to have ended, or None to wait forever
+    pub timeout: Option<Duration>,
+}
+
+impl Default for UdpReceiverOptions {
+    fn default() -> Self {
+        UdpReceiverOptions { reorder_window: 64, timeout: Some(Duration::from_secs(5)) }
+    }
+}
+
+// Receives events from one sender at a time
+pub struct UdpEventReceiver {
+    socket: UdpSocket,
+    options: UdpReceiverOptions,
+    datagram: Vec<u8>,
+    header: Option<Vec<String>>,
+    // Packets received ahead of the next sequence number
+    pending: BTreeMap<u32, Vec<DVSEvent>>,
+    next_sequence: u32,
+    ready: VecDeque<DVSEvent>,
+    ended: bool,
+    pub packets_received: u64,
+    pub packets_lost: u64,
+    // Duplicates and packets that arrived after they were counted as lost
+    pub packets_discarded: u64,
+}
+
+impl UdpEventReceiver {
+    pub fn bind(address: impl ToSocketAddrs, options: UdpReceiverOptions) -> anyhow::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        Self::from_socket(socket, options)
+    }
+
+    pub fn from_socket(socket: UdpSocket, options: UdpReceiverOptions) -> anyhow::Result<Self> {
+        socket.set_read_timeout(options.timeout)?;
+        Ok(Self {
+            socket,
+            options,
+            datagram: vec![0; MAX_DATAGRAM_BYTES],
+            header: None,
+            pending: BTreeMap::new(),
+            next_sequence: 0,
+            ready: VecDeque::new(),
+            ended: false,
+            packets_received: 0,
+            packets_lost: 0,
+            packets_discarded: 0,
+        })
+    }
+
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    // Changes the time without datagrams after which the stream is considered to have ended
+    pub fn set_timeout(&mut self, timeout: Option<Duration>) -> anyhow::Result<()> {
+        self.socket.set_read_timeout(timeout)?;
+        self.options.timeout = timeout;
+        Ok(())
+    }
+
+    // Moves packets that are next in sequence to the ready events
+    fn deliver(&mut self) {
+        while let Some(events) = self.pending.remove(&self.next_sequence) {
+            self.ready.extend(events);
+            self.next_sequence = self.next_sequence.wrapping_add(1);
+        }
+    }
+
+    // Skips the missing packets before the earliest pending packet
+    fn skip_gap(&mut self) {
+        if let Some(&sequence) = self.pending.keys().next() {
+            self.packets_lost += (sequence - self.next_sequence) as u64;
+            self.next_sequence = sequence;
+            self.deliver();
+        }
+    }
+
+    // Delivers all pending packets, counting the missing ones up to total as lost
+    fn end(&mut self, total: Option<u32>) {
+        while !self.pending.is_empty() {
+            self.skip_gap();
+        }
+        if let Some(total) = total.filter(|total| *total > self.next_sequence) {
+            self.packets_lost += (total - self.next_sequence) as u64;
+            self.next_sequence = total;
+        }
+        self.ended = true;
+    }
+
+    // Receives and handles one datagram. Returns false if the timeout expired first
+    fn receive(&mut self) -> anyhow::Result<bool> {
+        let length = match self.socket.recv(&mut self.datagram) {
+            Ok(length) => length,
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        let Some((&kind, body)) = self.datagram[..length].split_first() else {
+            return Ok(true);
+        };
+        match kind {
+            KIND_HEADER => {
+                if self.header.is_none() {
+                    let header = String::from_utf8_lossy(body);
+                    self.header = Some(header.lines().map(str::to_string).collect());
+                }
+            }
+            KIND_EVENTS => {
+                let packet = Packet::decode(body)?;
+                if packet.sequence < self.next_sequence || self.pending.contains_key(&packet.sequence) {
+                    self.packets_discarded += 1;
+                    return Ok(true);
+                }
+                self.packets_received += 1;
+                self.pending.insert(packet.sequence, packet.events);
+                self.deliver();
+                if self.pending.len() > self.options.reorder_window {
+                    self.skip_gap();
+                }
+            }
+            KIND_END if body.len() >= 4 => {
+                self.end(Some(u32::from_le_bytes([body[0], body[1], body[2], body[3]])));
+            }
+            _ => anyhow::bail!("Unexpected datagram of kind {}", kind),
+        }
+        Ok(true)
+    }
+}
+
+// Implemented like DvsRawDecoderEnum, so received events can be used wherever a decoder is expected
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for UdpEventReceiver {
+    // Waits for the header of the recording. Returns an empty header if it was lost, or if events
+    // arrived first
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        while self.header.is_none() && self.ready.is_empty() && self.pending.is_empty() && !self.ended {
+            if !self.receive()? {
+                self.end(None);
+            }
+        }
+        Ok(self.header.clone().unwrap_or_default())
+    }
+
+    // Returns the next event in sequence order. Returns Ok(None) once the sender has ended
+    // the stream or the timeout expires, like the decoders
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        loop {
+            if let Some(event) = self.ready.pop_front() {
+                return Ok(Some(event));
+            }
+            if self.ended {
+                return Ok(None);
+            }
+            if !self.receive()? {
+                self.end(None);
+            }
+        }
+    }
+}
@@ -0,0 +1,161 @@
+use crate::dvs::bitrate::UDP_PAYLOAD_BYTES;
+use crate::dvs::DVSEvent;
+
+/*
+This file implements the packetizer, which segments an event stream into packets for transmission. A packet
+is closed once it reaches a maximum size in bytes or covers a maximum time span, whichever comes first.
+
+Each packet starts with a 14 byte header, all fields little endian:
+  sequence number (u32), base timestamp in microseconds (i64), event count (u16)
+followed by 8 bytes per event:
+  timestamp offset from the base timestamp (u32), x (u16), y (15 bits) with the polarity in the top bit (u16)
+Packets are self-contained, so each can be decoded on its own when others are lost or reordered.
+*/
+
+pub const PACKET_HEADER_BYTES: usize = 14;
+pub const PACKET_EVENT_BYTES: usize = 8;
+
+#[derive(Debug, Clone, Default)]
+pub struct Packet {
+    pub sequence: u32,
+    // Timestamp of the first event, which the other timestamps are relative to
+    pub base_timestamp: i64,
+    pub events: Vec<DVSEvent>,
+}
+
+impl Packet {
+    // Size of the encoded packet, in bytes
+    pub fn encoded_len(&self) -> usize {
+        PACKET_HEADER_BYTES + self.events.len() * PACKET_EVENT_BYTES
+    }
+
+    // Appends the encoded packet to buf
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        buf.reserve(self.encoded_len());
+        buf.extend_from_slice(&self.sequence.to_le_bytes());
+        buf.extend_from_slice(&self.base_timestamp.to_le_bytes());
+        buf.extend_from_slice(&(self.events.len() as u16).to_le_bytes());
+        for event in &self.events {
+            let offset = (event.timestamp - self.base_timestamp) as u32;
+            let y = (event.y as u16 & 0x7FFF) | ((event.polarity as u16 & 1) << 15);
+            buf.extend_from_slice(&offset.to_le_bytes());
+            buf.extend_from_slice(&(event.x as u16).to_le_bytes());
+            buf.extend_from_slice(&y.to_le_bytes());
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut buf);
+        buf
+    }
+
+    // Decodes a packet produced by encode. Fails if the packet is truncated
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<Packet> {
+        if bytes.len() < PACKET_HEADER_BYTES {
+            anyhow::bail!("Packet of {} bytes is shorter than its header", bytes.len());
+        }
+        let sequence = u32::from_le_bytes(bytes[0..4].try_into()?);
+        let base_timestamp = i64::from_le_bytes(bytes[4..12].try_into()?);
+        let count = u16::from_le_bytes(bytes[12..14].try_into()?) as usize;
+        let body = &bytes[PACKET_HEADER_BYTES..];
+        if body.len() < count * PACKET_EVENT_BYTES {
+            anyhow::bail!("Packet {} holds {} bytes of events, expected {}", sequence, body.len(), count * PACKET_EVENT_BYTES);
+        }
+
+        let events = body
+            .chunks_exact(PACKET_EVENT_BYTES)
+            .take(count)
+            .map(|word| {
+                let offset = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+                let x = u16::from_le_bytes([word[4], word[5]]);
+                let y = u16::from_le_bytes([word[6], word[7]]);
+                DVSEvent {
+                    timestamp: base_timestamp + offset as i64,
+                    x: x as i16,
+                    y: (y & 0x7FFF) as i16,
+                    polarity: (y >> 15) as u8,
+                }
+            })
+            .collect();
+        Ok(Packet { sequence, base_timestamp, events })
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct PacketizerOptions {
+    // Largest encoded packet, in bytes. Defaults to the largest UDP payload of an Ethernet frame
+    pub max_bytes: usize,
+    // Longest time covered by a packet, in microseconds, or None for no limit
+    pub max_span_us: Option<i64>,
+}
+
+impl Default for PacketizerOptions {
+    fn default() -> Self {
+        PacketizerOptions { max_bytes: UDP_PAYLOAD_BYTES, max_span_us: None }
+    }
+}
+
+impl PacketizerOptions {
+    // Number of events that fit in a packet
+    pub fn max_events(&self) -> usize {
+        (self.max_bytes.saturating_sub(PACKET_HEADER_BYTES) / PACKET_EVENT_BYTES).clamp(1, u16::MAX as usize)
+    }
+}
+
+pub struct Packetizer {
+    options: PacketizerOptions,
+    max_events: usize,
+    current: Packet,
+    // Sequence number of the next packet
+    sequence: u32,
+}
+
+impl Packetizer {
+    pub fn new(options: PacketizerOptions) -> Self {
+        Self {
+            options,
+            max_events: options.max_events(),
+            current: Packet::default(),
+            sequence: 0,
+        }
+    }
+
+    // Number of packets produced so far
+    pub fn packets(&self) -> u32 {
+        self.sequence
+    }
+
+    fn close(&mut self, out: &mut impl FnMut(Packet)) {
+        let next = Packet { sequence: self.sequence.wrapping_add(1), ..Packet::default() };
+        let packet = std::mem::replace(&mut self.current, next);
+        self.sequence = self.sequence.wrapping_add(1);
+        out(packet);
+    }
+
+    // Adds the next event of a time-ordered stream, passing any packet it completes to out
+    pub fn process(&mut self, event: DVSEvent, out: &mut impl FnMut(Packet)) {
+        if let Some(first) = self.current.events.first() {
+            let span = event.timestamp - first.timestamp;
+            // Offsets are stored as u32, so out of order events also start a new packet
+            let too_long = self.options.max_span_us.is_some_and(|max| span >= max);
+            if too_long || span < 0 || span > u32::MAX as i64 {
+                self.close(out);
+            }
+        }
+        if self.current.events.is_empty() {
+            self.current.base_timestamp = event.timestamp;
+        }
+        self.current.events.push(event);
+        if self.current.events.len() >= self.max_events {
+            self.close(out);
+        }
+    }
+
+    // Passes the last, partially filled packet to out
+    pub fn finish(&mut self, out: &mut impl FnMut(Packet)) {
+        if !self.current.events.is_empty() {
+            self.close(out);
+        }
+    }
+}
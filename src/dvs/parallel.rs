@@ -0,0 +1,115 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::mmap;
+use crate::dvs::raw_decoder_evt2::{decode_segment, is_time_high, TimeBase, TIME_LOOP};
+use crate::dvs::{detect_format, DVSEvent, EventFormat};
+use rayon::prelude::*;
+use std::io::Cursor;
+use std::path::Path;
+
+/*
+This file implements parallel decoding of EVT2 files. Every EVT_TIME_HIGH word sets the upper bits of the
+timestamp on its own, so the file can be split into segments that start at EVT_TIME_HIGH words and be
+decoded independently, straight out of a memory map. The file is split into one segment per thread, and the
+segments are decoded on a rayon pool of that many threads.
+
+The only state a segment can't know about is how many times the 28-bit time base looped (about every 4.8
+hours) before the segment. Segments are decoded as if it never had, then stitched together in order,
+shifting the timestamps of segments that come after a loop.
+
+A file ending part way through a word fails with the DvsError::Truncated error of the sequential decoder, but
+without returning the events before it.
+*/
+
+// A decoded EVT2 file
+pub struct ParallelDecoded {
+    pub header: Vec<String>,
+    pub events: Vec<DVSEvent>,
+}
+
+/// Decodes a whole EVT2 file on the given number of threads (0 for one per core), returning the same events
+/// as DVSRawDecoderEvt2 would, in order. The events are held in memory.
+///
+/// # Safety
+///
+/// The file is memory mapped, and must not be written to or truncated until this returns, see mmap.rs.
+pub unsafe fn parallel_decode(path: impl AsRef<Path>, threads: usize) -> anyhow::Result<ParallelDecoded> {
+    // Safety: upheld by the caller
+    let map = unsafe { mmap::open(path) }?;
+    match detect_format(&mut Cursor::new(&map[..]))? {
+        Some(EventFormat::Evt2) => {}
+        _ => anyhow::bail!("Detected non-EVT2 input file"),
+    }
+    let (header, data_start) = split_header(&map);
+    // A pool of 0 threads has one per core
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build()?;
+
+    let data = &map[data_start..];
+    if data.len() % 4 != 0 {
+        let offset = (data_start + data.len() / 4 * 4) as u64;
+        return Err(DvsError::Truncated { offset }.into());
+    }
+    let bounds = segment_bounds(data, pool.current_num_threads());
+    let segments: Vec<_> = pool.install(|| bounds.par_windows(2).map(|range| decode_segment(&data[range[0]..range[1]])).collect());
+
+    // Stitch the segments, counting the loops of the time base before each one
+    let mut events = Vec::with_capacity(segments.iter().map(|segment| segment.events.len()).sum());
+    let mut time = TimeBase::default();
+    for mut segment in segments {
+        time.time_high(segment.first_time_high);
+        let offset = time.n_time_high_loop * TIME_LOOP;
+        if offset > 0 {
+            for event in segment.events.iter_mut() {
+                event.timestamp += offset as i64;
+            }
+        }
+        events.append(&mut segment.events);
+        time = TimeBase {
+            current_time_base: segment.time.current_time_base + offset,
+            n_time_high_loop: time.n_time_high_loop + segment.time.n_time_high_loop,
+        };
+    }
+    Ok(ParallelDecoded { header, events })
+}
+
+// Reads the "%" header lines, up to and including "% end", returning them and the offset of the first word
+fn split_header(bytes: &[u8]) -> (Vec<String>, usize) {
+    let mut header = Vec::new();
+    let mut start = 0;
+    while bytes.get(start) == Some(&b'%') {
+        let end = bytes[start..].iter().position(|&byte| byte == b'\n').map_or(bytes.len(), |newline| start + newline + 1);
+        let line = String::from_utf8_lossy(&bytes[start..end]).into_owned();
+        start = end;
+        let done = line.trim_end() == "% end";
+        header.push(line);
+        if done {
+            break;
+        }
+    }
+    (header, start)
+}
+
+// Splits the words into about the given number of segments, each starting at an EVT_TIME_HIGH word. Words
+// before the first EVT_TIME_HIGH word are skipped, as the sequential decoder does. Returns the byte offsets
+// of the segment boundaries, including the end
+fn segment_bounds(data: &[u8], segments: usize) -> Vec<usize> {
+    let words = data.len() / 4;
+    let next_time_high = |from: usize| {
+        (from..words).find(|&index| is_time_high(u32::from_le_bytes([data[4 * index], data[4 * index + 1], data[4 * index + 2], data[4 * index + 3]])))
+    };
+
+    let mut bounds = Vec::new();
+    let Some(first) = next_time_high(0) else {
+        return bounds;
+    };
+    bounds.push(4 * first);
+    for segment in 1..segments.max(1) {
+        let from = (words * segment / segments).max(first + 1);
+        match next_time_high(from) {
+            Some(index) if 4 * index > *bounds.last().unwrap() => bounds.push(4 * index),
+            Some(_) => {}
+            None => break,
+        }
+    }
+    bounds.push(4 * words);
+    bounds
+}
@@ -0,0 +1,334 @@
+use crate::dvs::convert::{transcode_with, TranscodeProgress};
+use crate::dvs::filters::{DvsFilter, Filtered, HotPixelFilter, HotPixelOptions, PolarityFilter, PolarityMode, Roi, RoiFilter};
+use crate::dvs::loss::{LossFilter, LossModels, LossOptions, LossParams};
+use crate::dvs::quantize::{QuantizeFilter, QuantizeOptions};
+use crate::dvs::transforms::{Transform, Transformer};
+use crate::dvs::{DVSEvent, DvsRawDecoder, DvsRawEncoder};
+use serde::Deserialize;
+use std::io::{BufRead, Read, Seek, Write};
+
+/*
+This file implements pipelines of filters (see filters.rs) run between a decoder and an encoder. A Pipeline is
+built stage by stage, either in code or from a description file, and is itself a filter, passing each event
+through its stages in order.
+Description files are TOML, read with the toml crate: one [[stage]] table per stage, in order, with a type naming
+the stage and its options as key = value pairs. Values are numbers, booleans, strings or arrays of them, e.g.
+
+[[stage]]
+type = "roi"
+x = 100
+y = 50
+width = 320
+height = 240
+
+[[stage]]
+type = "transform"
+transforms = ["rotate90", "flip-x"]
+
+Stage types are looked up in a DvsFilters registry, to which custom filters can be added. Besides the filters of
+filters.rs and transforms.rs, loss stages simulate a link of limited bandwidth, see loss.rs, and quantize stages
+bin events to fit one, see quantize.rs.
+*/
+
+// Options of one stage of a pipeline description, as written in the file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "StageTable")]
+pub struct StageConfig {
+    // Type of the stage, e.g. "roi"
+    pub name: String,
+    // Options in file order. Arrays are joined with commas
+    values: Vec<(String, String)>,
+}
+
+impl StageConfig {
+    pub fn new(name: &str) -> Self {
+        StageConfig { name: name.to_string(), values: Vec::new() }
+    }
+
+    // Sets an option, replacing any earlier value
+    pub fn set(mut self, key: &str, value: &str) -> Self {
+        self.values.retain(|(existing, _)| existing != key);
+        self.values.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.iter().find(|(existing, _)| existing == key).map(|(_, value)| value.as_str())
+    }
+
+    // Parses an option, or returns None if it isn't given
+    pub fn parse<T>(&self, key: &str) -> anyhow::Result<Option<T>>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        match self.get(key) {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(e) => anyhow::bail!("Invalid {} '{}' of stage '{}': {}", key, value, self.name, e),
+            },
+            None => Ok(None),
+        }
+    }
+
+    // Writes the stage as a [[stage]] table, which parse_stages reads back
+    pub fn to_toml(&self) -> String {
+        let mut file = toml::Table::new();
+        file.insert("stage".to_string(), toml::Value::Array(vec![toml::Value::Table(self.to_table())]));
+        file.to_string()
+    }
+
+    // The stage as a TOML table. Numbers and booleans are written as they are, other values as strings
+    pub(crate) fn to_table(&self) -> toml::Table {
+        let mut table = toml::Table::new();
+        table.insert("type".to_string(), toml::Value::String(self.name.clone()));
+        for (key, value) in &self.values {
+            let value = match (value.parse::<i64>(), value.parse::<f64>(), value.parse::<bool>()) {
+                (Ok(value), _, _) => toml::Value::Integer(value),
+                (_, Ok(value), _) if value.is_finite() => toml::Value::Float(value),
+                (_, _, Ok(value)) => toml::Value::Boolean(value),
+                _ => toml::Value::String(value.clone()),
+            };
+            table.insert(key.clone(), value);
+        }
+        table
+    }
+
+    // Parses an option that must be given
+    pub fn require<T>(&self, key: &str) -> anyhow::Result<T>
+    where
+        T: std::str::FromStr,
+        T::Err: std::fmt::Display,
+    {
+        self.parse(key)?.ok_or_else(|| anyhow::anyhow!("Stage '{}' needs {}", self.name, key))
+    }
+}
+
+type DvsFilterFactory = Box<dyn Fn(&StageConfig) -> anyhow::Result<Box<dyn DvsFilter>>>;
+
+// Filters available by name, for pipeline description files. Custom filters can be added with register
+pub struct DvsFilters {
+    factories: Vec<(String, DvsFilterFactory)>,
+}
+
+impl Default for DvsFilters {
+    // The built-in filters
+    fn default() -> Self {
+        let mut filters = DvsFilters { factories: Vec::new() };
+        filters.register("hot-pixels", |config| {
+            let defaults = HotPixelOptions::default();
+            let options = HotPixelOptions {
+                calibration_us: config.parse::<f64>("calibration_ms")?.map_or(defaults.calibration_us, |ms| (ms * 1000.0) as i64),
+                threshold: config.parse("threshold")?.unwrap_or(defaults.threshold),
+            };
+            Ok(Box::new(HotPixelFilter::new(options)))
+        });
+        filters.register("roi", |config| {
+            let roi = Roi { x: config.require("x")?, y: config.require("y")?, width: config.require("width")?, height: config.require("height")? };
+            Ok(Box::new(RoiFilter::new(roi)))
+        });
+        filters.register("polarity", |config| Ok(Box::new(PolarityFilter::new(config.require::<PolarityMode>("mode")?))));
+        filters.register("loss", |config| {
+            let defaults = LossParams::default();
+            let mut params = LossParams {
+                probability: config.parse("probability")?.unwrap_or(defaults.probability),
+                seed: config.parse("seed")?.unwrap_or(defaults.seed),
+                burst_bits: config.parse("burst")?.unwrap_or(defaults.burst_bits),
+                tile_size: config.parse("tile_size")?.unwrap_or(defaults.tile_size),
+                chunk_us: config.parse("chunk_us")?.unwrap_or(defaults.chunk_us),
+                ..defaults
+            };
+            params.budget.bandwidth_mbps = config.parse("bandwidth")?.unwrap_or(f64::INFINITY);
+            params.budget.bits_per_event = config.parse("bits_per_event")?.unwrap_or(params.budget.bits_per_event);
+            let model = LossModels::default().create(config.get("model").unwrap_or("end-biased"), &params)?;
+            Ok(Box::new(LossFilter::new(LossOptions { budget: params.budget, chunk_us: params.chunk_us }, model)))
+        });
+        filters.register("quantize", |config| {
+            let defaults = QuantizeOptions::default();
+            let mut options = QuantizeOptions {
+                chunk_us: config.parse("chunk_us")?.unwrap_or(defaults.chunk_us),
+                max_spatial_level: config.parse("max_spatial_level")?.unwrap_or(defaults.max_spatial_level),
+                max_temporal_level: config.parse("max_temporal_level")?.unwrap_or(defaults.max_temporal_level),
+                ..defaults
+            };
+            options.budget.bandwidth_mbps = config.parse("bandwidth")?.unwrap_or(f64::INFINITY);
+            Ok(Box::new(QuantizeFilter::new(options)))
+        });
+        filters.register("transform", |config| {
+            let transforms = config.require::<String>("transforms")?.split(',').map(str::parse).collect::<anyhow::Result<Vec<Transform>>>()?;
+            let transformer = Transformer::new(transforms);
+            match (config.parse("width")?, config.parse("height")?) {
+                (Some(width), Some(height)) => Ok(Box::new(transformer.with_geometry(width, height))),
+                _ => Ok(Box::new(transformer)),
+            }
+        });
+        filters
+    }
+}
+
+impl DvsFilters {
+    // Adds a filter, replacing any filter registered under the same name
+    pub fn register<F>(&mut self, name: &str, factory: F)
+    where
+        F: Fn(&StageConfig) -> anyhow::Result<Box<dyn DvsFilter>> + 'static,
+    {
+        self.factories.retain(|(existing, _)| existing != name);
+        self.factories.push((name.to_string(), Box::new(factory)));
+    }
+
+    // Names of the registered filters, in registration order
+    pub fn names(&self) -> Vec<&str> {
+        self.factories.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    pub fn create(&self, config: &StageConfig) -> anyhow::Result<Box<dyn DvsFilter>> {
+        match self.factories.iter().find(|(existing, _)| *existing == config.name) {
+            Some((_, factory)) => factory(config),
+            None => anyhow::bail!("Unknown filter '{}'. Expected one of: {}", config.name, self.names().join(", ")),
+        }
+    }
+}
+
+// Filters run one after the other
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn DvsFilter>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds a stage after the others
+    pub fn stage(mut self, filter: impl DvsFilter + 'static) -> Self {
+        self.stages.push(Box::new(filter));
+        self
+    }
+
+    pub fn boxed_stage(mut self, filter: Box<dyn DvsFilter>) -> Self {
+        self.stages.push(filter);
+        self
+    }
+
+    // Builds the stages of a description, see the top of this file
+    pub fn from_toml(text: &str, filters: &DvsFilters) -> anyhow::Result<Self> {
+        let mut pipeline = Pipeline::new();
+        for config in parse_stages(text)? {
+            pipeline = pipeline.boxed_stage(filters.create(&config)?);
+        }
+        Ok(pipeline)
+    }
+
+    // Reads a description file with the built-in filters
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let text = std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("Failed to read pipeline {}: {}", path, e))?;
+        Pipeline::from_toml(&text, &DvsFilters::default())
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    // Summaries of the stages, in order
+    pub fn summaries(&self) -> Vec<String> {
+        self.stages.iter().filter_map(|stage| stage.summary()).collect()
+    }
+
+    // Reads a whole stream from its header, passes its events through the stages and writes them out
+    pub fn run<R, W, D, E, F>(&mut self, decoder: D, encoder: &mut E, progress: F) -> anyhow::Result<TranscodeProgress>
+    where
+        R: Read + BufRead + Seek,
+        W: Write + Seek,
+        D: DvsRawDecoder<R>,
+        E: DvsRawEncoder<W>,
+        F: FnMut(TranscodeProgress),
+    {
+        transcode_with(&mut Filtered::new(decoder, self), encoder, progress)
+    }
+}
+
+// Passes an event through the stages, in order
+fn process_stages(stages: &mut [Box<dyn DvsFilter>], event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+    match stages.split_first_mut() {
+        Some((first, rest)) => first.process(event, &mut |event| process_stages(rest, event, out)),
+        None => out(event),
+    }
+}
+
+impl DvsFilter for Pipeline {
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        process_stages(&mut self.stages, event, out);
+    }
+
+    // Finishes the stages in order, so the events held back by each still go through the stages after it
+    fn finish(&mut self, out: &mut dyn FnMut(DVSEvent)) {
+        for index in 0..self.stages.len() {
+            let (done, rest) = self.stages.split_at_mut(index + 1);
+            done[index].finish(&mut |event| process_stages(rest, event, out));
+        }
+    }
+
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        self.stages.iter_mut().try_fold(header, |header, stage| stage.header(header))
+    }
+
+    fn summary(&self) -> Option<String> {
+        let summaries = self.summaries();
+        (!summaries.is_empty()).then(|| summaries.join("\n"))
+    }
+}
+
+// Splits the stages of a description into their options
+pub fn parse_stages(text: &str) -> anyhow::Result<Vec<StageConfig>> {
+    let file: StagesFile = toml::from_str(text)?;
+    Ok(file.stage)
+}
+
+// A description file: nothing but [[stage]] tables
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StagesFile {
+    #[serde(default)]
+    stage: Vec<StageConfig>,
+}
+
+// A [[stage]] table as written in the file, before its values are turned into text
+#[derive(Deserialize)]
+struct StageTable {
+    #[serde(rename = "type")]
+    name: String,
+    #[serde(flatten)]
+    values: toml::Table,
+}
+
+impl TryFrom<StageTable> for StageConfig {
+    type Error = String;
+
+    fn try_from(table: StageTable) -> Result<Self, String> {
+        let mut stage = StageConfig::new(&table.name);
+        for (key, value) in table.values {
+            let value = match value {
+                toml::Value::Array(items) => items.into_iter().map(|item| stage_value(&key, item)).collect::<Result<Vec<_>, _>>()?.join(","),
+                value => stage_value(&key, value)?,
+            };
+            stage = stage.set(&key, &value);
+        }
+        Ok(stage)
+    }
+}
+
+// The text of a single value, strings unquoted. Numbers are written back the way parse reads them
+fn stage_value(key: &str, value: toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(value) => Ok(value),
+        toml::Value::Integer(value) => Ok(value.to_string()),
+        toml::Value::Float(value) => Ok(value.to_string()),
+        toml::Value::Boolean(value) => Ok(value.to_string()),
+        value => Err(format!("unsupported value {} of {}. Expected a number, boolean, string or array of them", value, key)),
+    }
+}
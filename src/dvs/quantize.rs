@@ -0,0 +1,395 @@
+use crate::dvs::bitrate::{bits_in, mbps, BandwidthBudget};
+use crate::dvs::chunk::{Chunk, Chunker};
+use crate::dvs::filters::DvsFilter;
+use crate::dvs::loss::json_number;
+use crate::dvs::{header_geometry, DVSEvent};
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io;
+
+/*
+This file implements a lossy codec with rate control, an alternative to dropping events when a stream is over
+the bandwidth of a link (see loss.rs). Instead of dropping events, each chunk is binned: pixels are grouped
+into square bins of 2^spatial pixels and time into bins of 2^temporal microseconds from the start of the chunk,
+and each bin holding events of a polarity is sent as one event, at the centre of its pixels and the start of its
+time bin. Coarser levels merge more events and need fewer bits per event, as the coordinates of a bin take
+fewer bits:
+  ceil(log2(columns)) + ceil(log2(rows)) + ceil(log2(time bins)) + 1 bit of polarity
+plus a 48 bit chunk header with the levels and the number of events, and the packet headers of the budget.
+
+For each chunk, the finest levels that fit in the bandwidth are picked: those with the smallest sum of the
+spatial and temporal levels, preferring spatial detail on ties. As coarser temporal levels never need more bits,
+the finest temporal level of each spatial level is found by bisection. If even the coarsest levels don't fit,
+the bins past the budget are dropped, as in the end-biased loss model.
+*/
+
+// Levels and event count sent at the start of each chunk
+const CHUNK_HEADER_BITS: f64 = 48.0;
+
+// Sizes of the bins an event is merged into, as powers of two
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct QuantizeLevels {
+    pub spatial: u32,
+    pub temporal: u32,
+}
+
+impl QuantizeLevels {
+    // Side of a pixel bin
+    pub fn bin_px(&self) -> i64 {
+        1 << self.spatial
+    }
+
+    // Duration of a time bin, in microseconds
+    pub fn bin_us(&self) -> i64 {
+        1 << self.temporal
+    }
+
+    // Bin of an event in the chunk starting at chunk_start
+    fn bin(&self, event: &DVSEvent, chunk_start: i64) -> (i64, i16, i16, u8) {
+        let time = (event.timestamp - chunk_start).max(0) >> self.temporal;
+        (time, event.x >> self.spatial, event.y >> self.spatial, event.polarity)
+    }
+
+    // Event standing for the events of a bin
+    fn representative(&self, bin: (i64, i16, i16, u8), chunk_start: i64, (width, height): (u32, u32)) -> DVSEvent {
+        let (time, x, y, polarity) = bin;
+        let centre = |coordinate: i16, size: u32| {
+            let pixel = ((coordinate as i64) << self.spatial) + self.bin_px() / 2;
+            pixel.min(size as i64 - 1) as i16
+        };
+        DVSEvent { timestamp: chunk_start + (time << self.temporal), x: centre(x, width), y: centre(y, height), polarity }
+    }
+
+    // Size of a binned event, in bits
+    pub fn bits_per_event(&self, (width, height): (u32, u32), chunk_us: i64) -> f64 {
+        let bins = |size: i64, bin: i64| (size.max(1) + bin - 1) / bin;
+        let bits = |count: i64| (count.max(1) as u64).next_power_of_two().trailing_zeros();
+        let columns = bins(width as i64, self.bin_px());
+        let rows = bins(height as i64, self.bin_px());
+        let times = bins(chunk_us, self.bin_us());
+        (bits(columns) + bits(rows) + bits(times) + 1) as f64
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QuantizeOptions {
+    // Target bitrate and packet headers. bits_per_event is not used, as the size of an event depends on the levels
+    pub budget: BandwidthBudget,
+    // Time covered by each chunk, in microseconds
+    pub chunk_us: i64,
+    // Coarsest levels tried. Time bins are never longer than needed to cover a chunk
+    pub max_spatial_level: u32,
+    pub max_temporal_level: u32,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        QuantizeOptions {
+            budget: BandwidthBudget::default(),
+            chunk_us: 10_000,
+            max_spatial_level: 5,
+            max_temporal_level: 20,
+        }
+    }
+}
+
+// Outcome of the codec for one chunk
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct QuantizeChunkStats {
+    // Start of the chunk, in microseconds
+    pub start_us: i64,
+    pub events_in: u64,
+    // Binned events sent
+    pub events_out: u64,
+    // Bins over the budget at the coarsest levels
+    pub bins_dropped: u64,
+    pub levels: QuantizeLevels,
+    // Bitrate of the binned events on the link, in megabits per second
+    pub bitrate_mbps: f64,
+}
+
+// Summary of a quantized stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantizeReport {
+    pub options: QuantizeOptions,
+    pub events_in: u64,
+    pub events_out: u64,
+    // Chunks with at least one input event, in stream order
+    pub chunks: Vec<QuantizeChunkStats>,
+}
+
+impl QuantizeReport {
+    pub fn to_json(&self) -> String {
+        let budget = &self.options.budget;
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"bandwidth_mbps\":{},\"packet_payload_bytes\":{},\"packet_overhead_bytes\":{},\"chunk_us\":{},\"max_spatial_level\":{},\"max_temporal_level\":{},",
+            json_number(budget.bandwidth_mbps),
+            budget.packet_payload_bytes,
+            budget.packet_overhead_bytes,
+            self.options.chunk_us,
+            self.options.max_spatial_level,
+            self.options.max_temporal_level
+        );
+        let _ = write!(json, "\"events_in\":{},\"events_out\":{},\"chunks\":[", self.events_in, self.events_out);
+        for (i, chunk) in self.chunks.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"start_us\":{},\"events_in\":{},\"events_out\":{},\"bins_dropped\":{},\"spatial_level\":{},\"temporal_level\":{},\"bin_px\":{},\"bin_us\":{},\"bitrate_mbps\":{}}}",
+                chunk.start_us,
+                chunk.events_in,
+                chunk.events_out,
+                chunk.bins_dropped,
+                chunk.levels.spatial,
+                chunk.levels.temporal,
+                chunk.levels.bin_px(),
+                chunk.levels.bin_us(),
+                json_number(chunk.bitrate_mbps)
+            );
+        }
+        json.push_str("]}\n");
+        json
+    }
+
+    // One row per chunk, after "#" comments with the totals and options
+    pub fn to_csv(&self) -> String {
+        let budget = &self.options.budget;
+        let mut csv = String::new();
+        let _ = writeln!(csv, "# bandwidth_mbps: {}", budget.bandwidth_mbps);
+        let _ = writeln!(csv, "# packet_payload_bytes: {}", budget.packet_payload_bytes);
+        let _ = writeln!(csv, "# packet_overhead_bytes: {}", budget.packet_overhead_bytes);
+        let _ = writeln!(csv, "# chunk_us: {}", self.options.chunk_us);
+        let _ = writeln!(csv, "# max_spatial_level: {}", self.options.max_spatial_level);
+        let _ = writeln!(csv, "# max_temporal_level: {}", self.options.max_temporal_level);
+        let _ = writeln!(csv, "# events_in: {}", self.events_in);
+        let _ = writeln!(csv, "# events_out: {}", self.events_out);
+        csv.push_str("start_us,events_in,events_out,bins_dropped,spatial_level,temporal_level,bin_px,bin_us,bitrate_mbps\n");
+        for chunk in &self.chunks {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{},{}",
+                chunk.start_us,
+                chunk.events_in,
+                chunk.events_out,
+                chunk.bins_dropped,
+                chunk.levels.spatial,
+                chunk.levels.temporal,
+                chunk.levels.bin_px(),
+                chunk.levels.bin_us(),
+                chunk.bitrate_mbps
+            );
+        }
+        csv
+    }
+
+    // Writes the report as CSV if the path ends in .csv, and as JSON otherwise
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let contents = if path.to_lowercase().ends_with(".csv") { self.to_csv() } else { self.to_json() };
+        std::fs::write(path, contents)
+    }
+}
+
+// Bins each chunk of a stream at the finest levels fitting the bandwidth, see the top of this file. Wrap a
+// decoder in a filters::Filtered with a QuantizeFilter to read the binned events of any decoder
+pub struct QuantizeFilter {
+    options: QuantizeOptions,
+    // Sensor geometry from the header, if given
+    geometry: Option<(u32, u32)>,
+    chunker: Chunker,
+    // Bins seen in the chunk, reused between chunks
+    bins: HashSet<(i64, i16, i16, u8)>,
+    // Number of events taken and sent so far
+    pub events_in: u64,
+    pub events_out: u64,
+    // Sums of the levels of the chunks so far, for their means
+    level_sums: (u64, u64),
+    chunks: u64,
+    // Statistics of each chunk, if recording was enabled with record_chunks
+    chunk_stats: Option<Vec<QuantizeChunkStats>>,
+}
+
+impl QuantizeFilter {
+    pub fn new(options: QuantizeOptions) -> Self {
+        Self {
+            options,
+            geometry: None,
+            chunker: Chunker::new(options.chunk_us),
+            bins: HashSet::new(),
+            events_in: 0,
+            events_out: 0,
+            level_sums: (0, 0),
+            chunks: 0,
+            chunk_stats: None,
+        }
+    }
+
+    // Keeps statistics of every chunk for the report. Off by default, as they grow with the recording
+    pub fn record_chunks(mut self) -> Self {
+        self.chunk_stats = Some(Vec::new());
+        self
+    }
+
+    // Summary of the events processed so far
+    pub fn report(&self) -> QuantizeReport {
+        QuantizeReport {
+            options: self.options,
+            events_in: self.events_in,
+            events_out: self.events_out,
+            chunks: self.chunk_stats.clone().unwrap_or_default(),
+        }
+    }
+
+    // Bits needed to send the given number of binned events
+    fn chunk_bits(&self, levels: QuantizeLevels, geometry: (u32, u32), events: usize) -> f64 {
+        let budget = BandwidthBudget { bits_per_event: levels.bits_per_event(geometry, self.options.chunk_us.max(1)), ..self.options.budget };
+        CHUNK_HEADER_BITS + budget.bits_for_events(events)
+    }
+
+    // Number of bins the events of the chunk fall in
+    fn count_bins(&mut self, chunk: &Chunk, levels: QuantizeLevels) -> usize {
+        self.bins.clear();
+        self.bins.extend(chunk.events.iter().map(|event| levels.bin(event, chunk.start_us)));
+        self.bins.len()
+    }
+
+    fn fits(&mut self, chunk: &Chunk, levels: QuantizeLevels, geometry: (u32, u32), budget_bits: f64) -> bool {
+        let bins = self.count_bins(chunk, levels);
+        self.chunk_bits(levels, geometry, bins) <= budget_bits
+    }
+
+    // Coarsest levels tried. Time bins longer than the chunk merge nothing more
+    fn coarsest(&self) -> QuantizeLevels {
+        let chunk_us = self.options.chunk_us.max(1) as u64;
+        QuantizeLevels {
+            spatial: self.options.max_spatial_level,
+            temporal: self.options.max_temporal_level.min(chunk_us.next_power_of_two().trailing_zeros()),
+        }
+    }
+
+    // Finest levels whose bins fit in the budget, or None if even the coarsest don't
+    fn pick_levels(&mut self, chunk: &Chunk, geometry: (u32, u32), budget_bits: f64) -> Option<QuantizeLevels> {
+        if budget_bits.is_infinite() {
+            return Some(QuantizeLevels::default());
+        }
+        let max_temporal = self.coarsest().temporal;
+        let mut best: Option<QuantizeLevels> = None;
+        for spatial in 0..=self.options.max_spatial_level {
+            // Coarser pixel bins can't have a smaller sum of levels
+            if best.is_some_and(|best| spatial >= best.spatial + best.temporal) {
+                break;
+            }
+            if !self.fits(chunk, QuantizeLevels { spatial, temporal: max_temporal }, geometry, budget_bits) {
+                continue;
+            }
+            let (mut low, mut high) = (0, max_temporal);
+            while low < high {
+                let temporal = (low + high) / 2;
+                if self.fits(chunk, QuantizeLevels { spatial, temporal }, geometry, budget_bits) {
+                    high = temporal;
+                } else {
+                    low = temporal + 1;
+                }
+            }
+            if best.is_none_or(|best| spatial + low < best.spatial + best.temporal) {
+                best = Some(QuantizeLevels { spatial, temporal: low });
+            }
+        }
+        best
+    }
+
+    // Bins the events of the chunk, and passes an event per bin to out
+    fn end_chunk(&mut self, chunk: Chunk, out: &mut dyn FnMut(DVSEvent)) {
+        let chunk_us = self.chunker.chunk_us();
+        let chunk_start = chunk.start_us;
+        // Without a geometry in the header, bins cover the pixels of the chunk
+        let geometry = self.geometry.unwrap_or_else(|| {
+            let extent = |coordinate: fn(&DVSEvent) -> i16| chunk.events.iter().map(coordinate).max().map_or(1, |max| max.max(0) as u32 + 1);
+            (extent(|event| event.x), extent(|event| event.y))
+        });
+        let budget_bits = bits_in(self.options.budget.bandwidth_mbps, chunk_us);
+
+        let (levels, limit) = match self.pick_levels(&chunk, geometry, budget_bits) {
+            Some(levels) => (levels, usize::MAX),
+            None => {
+                let levels = self.coarsest();
+                let mut limit = self.count_bins(&chunk, levels);
+                while limit > 0 && self.chunk_bits(levels, geometry, limit) > budget_bits {
+                    limit -= 1;
+                }
+                (levels, limit)
+            }
+        };
+
+        self.bins.clear();
+        let events_in = chunk.events.len();
+        let mut events_out = 0;
+        for event in chunk.events {
+            let bin = levels.bin(&event, chunk_start);
+            if events_out < limit && self.bins.insert(bin) {
+                out(levels.representative(bin, chunk_start, geometry));
+                events_out += 1;
+            }
+        }
+        let bins_dropped = if limit == usize::MAX { 0 } else { self.bins.len().saturating_sub(limit) };
+        self.events_out += events_out as u64;
+        self.level_sums.0 += levels.spatial as u64;
+        self.level_sums.1 += levels.temporal as u64;
+        self.chunks += 1;
+        let bitrate_mbps = mbps(self.chunk_bits(levels, geometry, events_out), chunk_us);
+        if let Some(chunk_stats) = &mut self.chunk_stats {
+            chunk_stats.push(QuantizeChunkStats {
+                start_us: chunk_start,
+                events_in: events_in as u64,
+                events_out: events_out as u64,
+                bins_dropped: bins_dropped as u64,
+                levels,
+                bitrate_mbps,
+            });
+        }
+    }
+}
+
+impl DvsFilter for QuantizeFilter {
+    // Holds events back until their chunk is complete
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        self.events_in += 1;
+        if let Some(chunk) = self.chunker.push(event) {
+            self.end_chunk(chunk, out);
+        }
+    }
+
+    fn finish(&mut self, out: &mut dyn FnMut(DVSEvent)) {
+        if let Some(chunk) = self.chunker.finish() {
+            self.end_chunk(chunk, out);
+        }
+    }
+
+    // Starts over from the first chunk, with the geometry of the header
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        self.geometry = header_geometry(&header);
+        self.chunker.clear();
+        self.events_in = 0;
+        self.events_out = 0;
+        self.level_sums = (0, 0);
+        self.chunks = 0;
+        if let Some(chunk_stats) = &mut self.chunk_stats {
+            chunk_stats.clear();
+        }
+        Ok(header)
+    }
+
+    fn summary(&self) -> Option<String> {
+        let chunks = self.chunks.max(1) as f64;
+        Some(format!(
+            "Quantized {} events to {}, mean spatial level {:.2}, mean temporal level {:.2}",
+            self.events_in,
+            self.events_out,
+            self.level_sums.0 as f64 / chunks,
+            self.level_sums.1 as f64 / chunks
+        ))
+    }
+}
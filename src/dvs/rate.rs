@@ -0,0 +1,127 @@
+use crate::dvs::bitrate::BandwidthBudget;
+use crate::dvs::loss::json_number;
+use crate::dvs::{DVSEvent, DvsRawDecoder};
+use std::fmt::Write;
+use std::io::{self, BufRead, Read, Seek};
+
+/*
+This file implements event rate time series, for plotting the bandwidth profile of a recording before choosing
+loss parameters. Events are counted in fixed windows aligned to multiples of the window length, like the
+chunks of the loss filter, and each window's bitrate is that of sending its events under a bandwidth budget
+(see bitrate.rs), including packet headers. Windows without events between the first and last events are kept,
+so the series has no gaps. Events are assumed to be in time order.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 64 * 1024;
+
+// Events of one window
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RateBin {
+    // Start of the window, in microseconds
+    pub start_us: i64,
+    pub events: u64,
+    // Events per second
+    pub event_rate: f64,
+    // Bitrate of sending the events of the window, in megabits per second
+    pub bitrate_mbps: f64,
+}
+
+// Event rate of a recording over time
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateSeries {
+    pub window_us: i64,
+    pub budget: BandwidthBudget,
+    pub bins: Vec<RateBin>,
+}
+
+impl RateSeries {
+    pub fn new(window_us: i64, budget: BandwidthBudget) -> Self {
+        RateSeries { window_us, budget, bins: Vec::new() }
+    }
+
+    // Counts an event, adding windows up to the one it falls in
+    pub fn add(&mut self, event: &DVSEvent) {
+        let start_us = event.timestamp.div_euclid(self.window_us) * self.window_us;
+        let next_us = self.bins.last().map_or(start_us, |bin| bin.start_us + self.window_us);
+        for empty_us in (next_us..=start_us).step_by(self.window_us as usize) {
+            self.bins.push(RateBin { start_us: empty_us, events: 0, event_rate: 0.0, bitrate_mbps: 0.0 });
+        }
+        // Late events count towards the latest window
+        if let Some(bin) = self.bins.last_mut() {
+            bin.events += 1;
+        }
+    }
+
+    // Fills in the rates of the windows once all events have been added
+    pub fn finish(&mut self) {
+        for bin in self.bins.iter_mut() {
+            bin.event_rate = bin.events as f64 / (self.window_us as f64 / 1e6);
+            bin.bitrate_mbps = self.budget.bitrate_mbps(bin.events as usize, self.window_us);
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        let _ = write!(json, "{{\"window_us\":{},\"bits_per_event\":{},\"bins\":[", self.window_us, json_number(self.budget.bits_per_event));
+        for (i, bin) in self.bins.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(
+                json,
+                "{{\"start_us\":{},\"events\":{},\"event_rate\":{},\"bitrate_mbps\":{}}}",
+                bin.start_us,
+                bin.events,
+                json_number(bin.event_rate),
+                json_number(bin.bitrate_mbps)
+            );
+        }
+        json.push_str("]}\n");
+        json
+    }
+
+    // One row per window, after "#" comments with the options
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        let _ = writeln!(csv, "# window_us: {}", self.window_us);
+        let _ = writeln!(csv, "# bits_per_event: {}", self.budget.bits_per_event);
+        let _ = writeln!(csv, "# packet_payload_bytes: {}", self.budget.packet_payload_bytes);
+        let _ = writeln!(csv, "# packet_overhead_bytes: {}", self.budget.packet_overhead_bytes);
+        csv.push_str("start_us,events,event_rate,bitrate_mbps\n");
+        for bin in &self.bins {
+            let _ = writeln!(csv, "{},{},{},{}", bin.start_us, bin.events, bin.event_rate, bin.bitrate_mbps);
+        }
+        csv
+    }
+
+    // Writes the series as JSON if the path ends in .json, and as CSV otherwise
+    pub fn write(&self, path: &str) -> io::Result<()> {
+        let contents = if path.to_lowercase().ends_with(".json") { self.to_json() } else { self.to_csv() };
+        std::fs::write(path, contents)
+    }
+}
+
+// Reads a whole stream, from its header, and returns its event rate over windows of window_us
+pub fn rate_series<R, D>(decoder: &mut D, window_us: i64, budget: BandwidthBudget) -> anyhow::Result<RateSeries>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+{
+    if window_us <= 0 {
+        anyhow::bail!("Window length must be positive");
+    }
+    decoder.read_header()?;
+    let mut series = RateSeries::new(window_us, budget);
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            series.finish();
+            return Ok(series);
+        }
+        for event in &events {
+            series.add(event);
+        }
+    }
+}
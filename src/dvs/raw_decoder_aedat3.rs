@@ -0,0 +1,148 @@
+#![allow(dead_code)]
+
+use crate::dvs::error::DvsError;
+use crate::dvs::{at_end, read_exact_or_truncated, DvsRawDecoder, DVSEvent};
+use modular_bitfield::bitfield;
+use modular_bitfield::prelude::{B1, B15};
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+/*
+This file implements an AEDAT 3.1 decoder for the files recorded from iniVation DAVIS and DVS sensors.
+An AEDAT 3.1 file starts with "#"-prefixed text header lines, terminated by "#!END-HEADER", followed by packets.
+Each packet has a 28-byte header giving the type, size and number of its events, followed by the events.
+Only polarity events are decoded; other packets (frames, IMU samples, special events) are skipped.
+*/
+
+// First header line of an AEDAT 3.x file
+pub const AEDAT3_MAGIC: &str = "#!AER-DAT3.";
+const END_OF_HEADER: &str = "#!END-HEADER";
+
+// Size in bytes of a packet header
+pub(crate) const PACKET_HEADER_SIZE: usize = 28;
+// Packet event type of polarity events
+pub(crate) const POLARITY_EVENT: i16 = 1;
+// Size in bytes of a polarity event: a 32-bit data word and a 32-bit timestamp
+pub(crate) const POLARITY_EVENT_SIZE: usize = 8;
+
+// A bitfield struct representing the 32-bit data word of a polarity event
+#[bitfield]
+struct RawPolarityEvent {
+    valid: B1,
+    polarity: B1,
+    y: B15,
+    x: B15,
+}
+
+// The fields of a packet header we use. libcaer hands out packets in the same layout, see inivation.rs
+pub(crate) struct PacketHeader {
+    pub(crate) event_type: i16,
+    pub(crate) event_size: usize,
+    // Added to the 31-bit event timestamps of the packet
+    pub(crate) ts_overflow: i64,
+    pub(crate) event_number: usize,
+}
+
+impl PacketHeader {
+    pub(crate) fn from_bytes(bytes: &[u8; PACKET_HEADER_SIZE]) -> Self {
+        let i32_at = |offset: usize| i32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]]);
+        PacketHeader {
+            event_type: i16::from_le_bytes([bytes[0], bytes[1]]),
+            event_size: i32_at(4).max(0) as usize,
+            ts_overflow: (i32_at(12) as i64) << 31,
+            event_number: i32_at(20).max(0) as usize,
+        }
+    }
+}
+
+pub struct DVSRawDecoderAedat3<R: Read + BufRead + Seek> {
+    reader: R,
+    // Header of the packet being read, and the number of its events left to read
+    packet: Option<PacketHeader>,
+    events_left: usize,
+    buffer_read: Vec<u8>,
+}
+
+impl<R: Read + BufRead + Seek> DVSRawDecoderAedat3<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            packet: None,
+            events_left: 0,
+            buffer_read: Vec::new(),
+        }
+    }
+}
+
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderAedat3<R> {
+    // Reads the "#" header lines up to and including "#!END-HEADER"
+    // Returns the header as a vector of strings
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        let mut header: Vec<String> = Vec::new();
+        // Reset the reader to the beginning
+        self.reader.seek(SeekFrom::Start(0))?;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                // The input ended before the END_OF_HEADER line
+                return Err(DvsError::UnexpectedEof);
+            }
+            if header.is_empty() && !line.starts_with(AEDAT3_MAGIC) {
+                return Err(DvsError::UnsupportedFormat("Detected non-AEDAT3 input file".to_string()));
+            }
+            let end = line.trim_end() == END_OF_HEADER;
+            header.push(line);
+            if end {
+                break;
+            }
+        }
+        self.packet = None;
+        self.events_left = 0;
+        Ok(header)
+    }
+
+    // Reads the next valid polarity event, skipping other events. Returns Ok(None) at the end of the file
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        loop {
+            // Move on to the next packet with events left
+            while self.events_left == 0 {
+                if at_end(&mut self.reader)? {
+                    return Ok(None);
+                }
+                let mut bytes = [0u8; PACKET_HEADER_SIZE];
+                read_exact_or_truncated(&mut self.reader, &mut bytes)?;
+                let packet = PacketHeader::from_bytes(&bytes);
+                if packet.event_size == 0 && packet.event_number > 0 {
+                    return Err(DvsError::InvalidData("Invalid AEDAT packet with event size 0".to_string()));
+                }
+                self.events_left = packet.event_number;
+                self.buffer_read.resize(packet.event_size, 0);
+                self.packet = Some(packet);
+            }
+
+            read_exact_or_truncated(&mut self.reader, &mut self.buffer_read)?;
+            self.events_left -= 1;
+            let Some(packet) = &self.packet else { continue };
+            if packet.event_type != POLARITY_EVENT || packet.event_size < POLARITY_EVENT_SIZE {
+                continue;
+            }
+            if let Some(event) = polarity_event(&self.buffer_read, packet.ts_overflow) {
+                return Ok(Some(event));
+            }
+        }
+    }
+}
+
+// Decodes a polarity event of a packet, from its first POLARITY_EVENT_SIZE bytes, or None if it isn't valid
+pub(crate) fn polarity_event(bytes: &[u8], ts_overflow: i64) -> Option<DVSEvent> {
+    let data = RawPolarityEvent::from_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    if data.valid() == 0 {
+        return None;
+    }
+    let timestamp = i32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+    Some(DVSEvent {
+        timestamp: ts_overflow | timestamp as i64,
+        x: data.x() as i16,
+        y: data.y() as i16,
+        polarity: data.polarity(),
+    })
+}
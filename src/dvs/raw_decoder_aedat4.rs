@@ -0,0 +1,245 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::{at_end, read_exact_or_truncated, read_vec_or_truncated, DvsRawDecoder, DVSEvent};
+use std::collections::VecDeque;
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+/*
+This file implements an AEDAT4 decoder for files recorded with iniVation's DV software and dv-processing.
+An AEDAT4 file starts with the "#!AER-DAT4.0\r\n" version line and a size-prefixed IOHeader flatbuffer, which
+gives the packet compression and the position of the data table at the end of the file. It is followed by
+packets, each an 8-byte header (stream id and size) and a, possibly compressed, flatbuffer.
+Event packets (flatbuffer identifier "EVTS") are decoded; other streams (frames, IMU, triggers) are skipped.
+Uncompressed, LZ4 and, with the compression feature, zstd compressed files are supported. Each compressed
+packet is a complete LZ4 or zstd frame.
+Sizes read from the file are not trusted to allocate buffers: packets and the IOHeader are read into buffers
+that grow with the bytes actually in the file.
+*/
+
+// Version line at the start of an AEDAT 4.x file
+pub const AEDAT4_MAGIC: &str = "#!AER-DAT4.";
+
+// IOHeader compression types
+const COMPRESSION_NONE: i32 = 0;
+const COMPRESSION_LZ4: i32 = 1;
+const COMPRESSION_LZ4_HIGH: i32 = 2;
+const COMPRESSION_ZSTD: i32 = 3;
+const COMPRESSION_ZSTD_HIGH: i32 = 4;
+
+// Flatbuffer file identifier of event packets
+const EVENT_PACKET_IDENTIFIER: &[u8; 4] = b"EVTS";
+// Size in bytes of the Event struct: int64 timestamp, int16 x, int16 y, bool on, and padding
+const EVENT_SIZE: usize = 16;
+
+fn truncated() -> anyhow::Error {
+    DvsError::InvalidData("Truncated AEDAT4 flatbuffer".to_string()).into()
+}
+
+// Reads little-endian values out of a flatbuffer, failing instead of panicking on malformed input
+fn read_bytes<const N: usize>(buf: &[u8], pos: usize) -> anyhow::Result<[u8; N]> {
+    let bytes = buf.get(pos..pos + N).ok_or_else(truncated)?;
+    let mut array = [0u8; N];
+    array.copy_from_slice(bytes);
+    Ok(array)
+}
+
+// A table of a flatbuffer, read without generated code
+struct FlatTable<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    vtable: usize,
+}
+
+impl<'a> FlatTable<'a> {
+    // The root table of a flatbuffer without a size prefix
+    fn root(buf: &'a [u8]) -> anyhow::Result<Self> {
+        let pos = u32::from_le_bytes(read_bytes(buf, 0)?) as usize;
+        let vtable_offset = i32::from_le_bytes(read_bytes(buf, pos)?) as i64;
+        let vtable = usize::try_from(pos as i64 - vtable_offset).map_err(|_| truncated())?;
+        Ok(FlatTable { buf, pos, vtable })
+    }
+
+    // Position of a field, or None if it is absent and has its default value
+    fn field(&self, index: usize) -> anyhow::Result<Option<usize>> {
+        let vtable_size = u16::from_le_bytes(read_bytes(self.buf, self.vtable)?) as usize;
+        let entry = 4 + 2 * index;
+        if entry + 2 > vtable_size {
+            return Ok(None);
+        }
+        let offset = u16::from_le_bytes(read_bytes(self.buf, self.vtable + entry)?) as usize;
+        Ok((offset != 0).then_some(self.pos + offset))
+    }
+
+    fn i32_field(&self, index: usize, default: i32) -> anyhow::Result<i32> {
+        match self.field(index)? {
+            Some(pos) => Ok(i32::from_le_bytes(read_bytes(self.buf, pos)?)),
+            None => Ok(default),
+        }
+    }
+
+    fn i64_field(&self, index: usize, default: i64) -> anyhow::Result<i64> {
+        match self.field(index)? {
+            Some(pos) => Ok(i64::from_le_bytes(read_bytes(self.buf, pos)?)),
+            None => Ok(default),
+        }
+    }
+
+    // Bytes of a vector or string field, and its number of elements
+    fn vector(&self, index: usize, element_size: usize) -> anyhow::Result<Option<(&'a [u8], usize)>> {
+        let Some(pos) = self.field(index)? else { return Ok(None) };
+        let start = pos + u32::from_le_bytes(read_bytes(self.buf, pos)?) as usize;
+        let len = u32::from_le_bytes(read_bytes(self.buf, start)?) as usize;
+        let bytes = self.buf.get(start + 4..start + 4 + len * element_size).ok_or_else(truncated)?;
+        Ok(Some((bytes, len)))
+    }
+}
+
+// Finds the value of an integer attribute in the IOHeader's XML info node, e.g. <attr key="sizeX" type="int">346</attr>
+fn info_attribute(info: &str, key: &str) -> Option<u32> {
+    let start = info.find(&format!("key=\"{}\"", key))?;
+    let value = &info[start..];
+    let value = &value[value.find('>')? + 1..];
+    value[..value.find('<')?].trim().parse().ok()
+}
+
+// Decompresses a packet of a file with the given IOHeader compression type
+fn decompress(compression: i32, data: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+    let invalid = |error: std::io::Error| DvsError::InvalidData(format!("Invalid compressed AEDAT4 packet: {}", error));
+    let mut output = Vec::new();
+    match compression {
+        COMPRESSION_LZ4 | COMPRESSION_LZ4_HIGH => {
+            lz4_flex::frame::FrameDecoder::new(data.as_slice()).read_to_end(&mut output).map_err(invalid)?;
+        }
+        #[cfg(feature = "compression")]
+        COMPRESSION_ZSTD | COMPRESSION_ZSTD_HIGH => {
+            zstd::stream::read::Decoder::new(data.as_slice())?.read_to_end(&mut output).map_err(invalid)?;
+        }
+        _ => return Ok(data),
+    }
+    Ok(output)
+}
+
+pub struct DVSRawDecoderAedat4<R: Read + BufRead + Seek> {
+    reader: R,
+    compression: i32,
+    // Position in the file, and the position of the data table that follows the last packet
+    position: u64,
+    data_table_position: Option<u64>,
+    // Events of the current packet that have not been returned yet
+    events: VecDeque<DVSEvent>,
+}
+
+impl<R: Read + BufRead + Seek> DVSRawDecoderAedat4<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            compression: COMPRESSION_NONE,
+            position: 0,
+            data_table_position: None,
+            events: VecDeque::new(),
+        }
+    }
+
+    // Reads the next packet, queueing the events it contains. Packets of other streams add no events.
+    // Returns false once the last packet has been read
+    fn read_packet(&mut self) -> anyhow::Result<bool> {
+        if self.data_table_position.is_some_and(|end| self.position >= end) || at_end(&mut self.reader)? {
+            return Ok(false);
+        }
+        let mut packet_header = [0u8; 8];
+        read_exact_or_truncated(&mut self.reader, &mut packet_header)?;
+        let size = i32::from_le_bytes([packet_header[4], packet_header[5], packet_header[6], packet_header[7]]);
+        let size = usize::try_from(size).map_err(|_| DvsError::InvalidData(format!("Invalid AEDAT4 packet size {}", size)))?;
+        let data = read_vec_or_truncated(&mut self.reader, size as u64)?;
+        self.position += 8 + size as u64;
+
+        let data = decompress(self.compression, data)?;
+        // Packets are size-prefixed flatbuffers
+        let buf = match data.get(..4) {
+            Some(prefix) if u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize == data.len() - 4 => &data[4..],
+            _ => &data[..],
+        };
+        if buf.get(4..8) != Some(&EVENT_PACKET_IDENTIFIER[..]) {
+            return Ok(true);
+        }
+
+        let packet = FlatTable::root(buf)?;
+        let Some((elements, len)) = packet.vector(0, EVENT_SIZE)? else { return Ok(true) };
+        for i in 0..len {
+            let event = &elements[i * EVENT_SIZE..(i + 1) * EVENT_SIZE];
+            self.events.push_back(DVSEvent {
+                timestamp: i64::from_le_bytes(read_bytes(event, 0)?),
+                x: i16::from_le_bytes(read_bytes(event, 8)?),
+                y: i16::from_le_bytes(read_bytes(event, 10)?),
+                polarity: event[12] & 0x1,
+            });
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderAedat4<R> {
+    // Reads the version line and the IOHeader
+    // Returns the version line, and the sensor geometry if the header describes it
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        let mut header: Vec<String> = Vec::new();
+        // Reset the reader to the beginning
+        self.reader.seek(SeekFrom::Start(0))?;
+        let mut line = String::new();
+        self.reader.read_line(&mut line)?;
+        if !line.starts_with(AEDAT4_MAGIC) {
+            return Err(DvsError::UnsupportedFormat("Detected non-AEDAT4 input file".to_string()));
+        }
+
+        let mut size = [0u8; 4];
+        self.reader.read_exact(&mut size)?;
+        let io_header = read_vec_or_truncated(&mut self.reader, u32::from_le_bytes(size) as u64)?;
+        self.position = (line.len() + 4 + io_header.len()) as u64;
+
+        let table = FlatTable::root(&io_header)?;
+        self.compression = table.i32_field(0, COMPRESSION_NONE)?;
+        if cfg!(not(feature = "compression")) && matches!(self.compression, COMPRESSION_ZSTD | COMPRESSION_ZSTD_HIGH) {
+            return Err(DvsError::UnsupportedFormat("Zstd compressed AEDAT4 files need the compression feature".to_string()));
+        } else if !matches!(self.compression, COMPRESSION_NONE | COMPRESSION_LZ4 | COMPRESSION_LZ4_HIGH | COMPRESSION_ZSTD | COMPRESSION_ZSTD_HIGH) {
+            return Err(DvsError::UnsupportedFormat(format!("Unknown AEDAT4 compression type {}", self.compression)));
+        }
+        self.data_table_position = u64::try_from(table.i64_field(1, -1)?).ok();
+
+        header.push(line);
+        if let Some((info, _)) = table.vector(2, 1)? {
+            let info = String::from_utf8_lossy(info);
+            if let (Some(width), Some(height)) = (info_attribute(&info, "sizeX"), info_attribute(&info, "sizeY")) {
+                tracing::debug!(width, height, "sensor geometry");
+                header.push(format!("% geometry {}x{}\n", width, height));
+            }
+        }
+        self.events.clear();
+        Ok(header)
+    }
+
+    // Reads the next event, reading packets until one contains events
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        while self.events.is_empty() {
+            if !self.read_packet()? {
+                return Ok(None);
+            }
+        }
+        Ok(self.events.pop_front())
+    }
+
+    // Moves whole packets of events into the vector
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        let mut count = 0;
+        while count < max {
+            if self.events.is_empty() {
+                if !self.read_packet()? {
+                    break;
+                }
+                continue;
+            }
+            let n = self.events.len().min(max - count);
+            events.extend(self.events.drain(..n));
+            count += n;
+        }
+        Ok(count)
+    }
+}
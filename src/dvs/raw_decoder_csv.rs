@@ -0,0 +1,235 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::{DvsRawDecoder, DVSEvent};
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+/*
+This file implements a decoder for events stored as delimited text, one "t,x,y,p" line per event.
+Lines starting with "#" are comments. An optional row of column names (e.g. "timestamp,x,y,polarity") sets the
+column order, and a "# timestamp unit: <unit>" comment sets the unit of the timestamps. Otherwise the
+decoder's options are used. The delimiter (comma or tab) is detected from the first row.
+*/
+
+// Comment written by the CSV encoder to record the unit of the timestamps
+pub const TIME_UNIT_COMMENT: &str = "# timestamp unit:";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CsvColumn {
+    Timestamp,
+    X,
+    Y,
+    Polarity,
+}
+
+impl CsvColumn {
+    // Name written in the column row
+    pub fn name(self) -> &'static str {
+        match self {
+            CsvColumn::Timestamp => "t",
+            CsvColumn::X => "x",
+            CsvColumn::Y => "y",
+            CsvColumn::Polarity => "p",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "t" | "ts" | "time" | "timestamp" => Some(CsvColumn::Timestamp),
+            "x" => Some(CsvColumn::X),
+            "y" => Some(CsvColumn::Y),
+            "p" | "pol" | "polarity" | "on" => Some(CsvColumn::Polarity),
+            _ => None,
+        }
+    }
+
+    // Parses a column order such as "t,x,y,p". Each column must appear exactly once
+    pub fn parse_order(spec: &str) -> anyhow::Result<[CsvColumn; 4]> {
+        let columns: Vec<CsvColumn> = spec
+            .split([',', '\t'])
+            .map(|name| CsvColumn::from_name(name).ok_or_else(|| anyhow::anyhow!("Unknown CSV column '{}'", name.trim())))
+            .collect::<anyhow::Result<_>>()?;
+        let all = [CsvColumn::Timestamp, CsvColumn::X, CsvColumn::Y, CsvColumn::Polarity];
+        if columns.len() != 4 || !all.iter().all(|column| columns.contains(column)) {
+            anyhow::bail!("CSV columns must name t, x, y and p exactly once, got '{}'", spec);
+        }
+        Ok([columns[0], columns[1], columns[2], columns[3]])
+    }
+}
+
+// Unit of the timestamps in a text file. Events are always microseconds internally
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum TimeUnit {
+    Seconds,
+    Milliseconds,
+    #[default]
+    Microseconds,
+    Nanoseconds,
+}
+
+impl TimeUnit {
+    pub fn name(self) -> &'static str {
+        match self {
+            TimeUnit::Seconds => "s",
+            TimeUnit::Milliseconds => "ms",
+            TimeUnit::Microseconds => "us",
+            TimeUnit::Nanoseconds => "ns",
+        }
+    }
+
+    // Converts a timestamp in this unit to microseconds. Fractional values are rounded
+    fn to_microseconds(self, field: &str) -> Option<i64> {
+        if let Ok(value) = field.parse::<i64>() {
+            return match self {
+                TimeUnit::Seconds => value.checked_mul(1_000_000),
+                TimeUnit::Milliseconds => value.checked_mul(1_000),
+                TimeUnit::Microseconds => Some(value),
+                TimeUnit::Nanoseconds => Some(value.div_euclid(1_000)),
+            };
+        }
+        let value: f64 = field.parse().ok()?;
+        let scale = match self {
+            TimeUnit::Seconds => 1e6,
+            TimeUnit::Milliseconds => 1e3,
+            TimeUnit::Microseconds => 1.0,
+            TimeUnit::Nanoseconds => 1e-3,
+        };
+        value.is_finite().then(|| (value * scale).round() as i64)
+    }
+}
+
+impl std::str::FromStr for TimeUnit {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "s" => Ok(TimeUnit::Seconds),
+            "ms" => Ok(TimeUnit::Milliseconds),
+            "us" => Ok(TimeUnit::Microseconds),
+            "ns" => Ok(TimeUnit::Nanoseconds),
+            _ => anyhow::bail!("Unsupported time unit '{}'. Expected s, ms, us or ns", s),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    pub columns: [CsvColumn; 4],
+    pub delimiter: u8,
+    pub time_unit: TimeUnit,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            columns: [CsvColumn::Timestamp, CsvColumn::X, CsvColumn::Y, CsvColumn::Polarity],
+            delimiter: b',',
+            time_unit: TimeUnit::Microseconds,
+        }
+    }
+}
+
+fn invalid_line(line_number: u64, line: &str) -> DvsError {
+    DvsError::InvalidData(format!("Invalid CSV event on line {}: '{}'", line_number, line.trim_end()))
+}
+
+pub struct DVSRawDecoderCsv<R: Read + BufRead + Seek> {
+    reader: R,
+    options: CsvOptions,
+    line: String,
+    line_number: u64,
+}
+
+impl<R: Read + BufRead + Seek> DVSRawDecoderCsv<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_options(reader, CsvOptions::default())
+    }
+
+    // Creates a decoder for files without a column row or timestamp unit comment in the given layout
+    pub fn with_options(reader: R, options: CsvOptions) -> Self {
+        Self {
+            reader,
+            options,
+            line: String::new(),
+            line_number: 0,
+        }
+    }
+
+    fn parse_event(&self, line: &str) -> Option<DVSEvent> {
+        let delimiter = self.options.delimiter as char;
+        let mut event = DVSEvent::default();
+        let mut fields = line.split(delimiter).map(str::trim);
+        for column in self.options.columns {
+            let field = fields.next()?;
+            match column {
+                CsvColumn::Timestamp => event.timestamp = self.options.time_unit.to_microseconds(field)?,
+                CsvColumn::X => event.x = field.parse().ok()?,
+                CsvColumn::Y => event.y = field.parse().ok()?,
+                CsvColumn::Polarity => {
+                    event.polarity = match field.to_lowercase().as_str() {
+                        "1" | "true" => 1,
+                        "0" | "-1" | "false" => 0,
+                        _ => return None,
+                    }
+                }
+            }
+        }
+        Some(event)
+    }
+}
+
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderCsv<R> {
+    // Reads the comment lines and the column row, if any
+    // Returns the comment lines, as "%" header lines
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        let mut header: Vec<String> = Vec::new();
+        // Reset the reader to the beginning
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.line_number = 0;
+
+        while self.reader.fill_buf()?.first() == Some(&b'#') {
+            let mut line = String::new();
+            self.reader.read_line(&mut line)?;
+            self.line_number += 1;
+            if let Some(unit) = line.strip_prefix(TIME_UNIT_COMMENT) {
+                self.options.time_unit = unit.parse().map_err(|_| DvsError::InvalidHeader { line: line.clone() })?;
+            } else {
+                // Comments carry the header of the file the events were exported from
+                header.push(format!("%{}", &line[1..]));
+            }
+        }
+
+        // Detect the delimiter from the first row, and the column order from the column row
+        let first_row = self.reader.fill_buf()?;
+        let first_row = &first_row[..first_row.iter().position(|b| *b == b'\n').unwrap_or(first_row.len())];
+        if first_row.contains(&b'\t') {
+            self.options.delimiter = b'\t';
+        } else if first_row.contains(&b',') {
+            self.options.delimiter = b',';
+        }
+        if first_row.first().is_some_and(|b| b.is_ascii_alphabetic()) {
+            let mut line = String::new();
+            self.reader.read_line(&mut line)?;
+            self.line_number += 1;
+            self.options.columns = CsvColumn::parse_order(line.trim_end()).map_err(|_| DvsError::InvalidHeader { line: line.clone() })?;
+        }
+        Ok(header)
+    }
+
+    // Reads the next event line, skipping blank lines and comments. Returns Ok(None) at the end of the file
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        loop {
+            self.line.clear();
+            if self.reader.read_line(&mut self.line)? == 0 {
+                return Ok(None);
+            }
+            self.line_number += 1;
+            let line = self.line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return match self.parse_event(line) {
+                Some(event) => Ok(Some(event)),
+                None => Err(invalid_line(self.line_number, line)),
+            };
+        }
+    }
+}
@@ -1,18 +1,31 @@
 #![allow(dead_code)]
 
-use crate::dvs::{DvsRawDecoder, DVSEvent};
+use crate::dvs::error::DvsError;
+use crate::dvs::{at_end, read_exact_or_truncated, DvsRawDecoder, DVSEvent};
 use modular_bitfield::bitfield;
 use modular_bitfield::prelude::{B4, B32, B14};
-use std::io::{self, BufRead, BufReader, Read, Seek};
+use std::io::{BufRead, Read, Seek, SeekFrom};
 
+/*
+This file implements a DAT event decoder for Dynamic Vision Sensor (DVS) data streams.
+A DAT file starts with "%"-prefixed text header lines, followed by a two byte binary preamble (event type
+and event size), followed by fixed-size little-endian event records: a 32-bit timestamp in microseconds and a
+32-bit data word packing x (bits 0-13), y (bits 14-27) and polarity (bits 28-31).
+*/
 
+// Event type of Change Detection events in the DAT preamble
+pub const DAT_EVENT_TYPE_CD: u8 = 0x0C;
+// Size in bytes of a Change Detection event record
+pub const DAT_EVENT_SIZE_CD: u8 = 8;
+
+// A bitfield struct representing the raw 64 bits of a CD event in DAT format
 #[bitfield]
 #[derive(Clone)]
 struct RawEvent {
     timestamp: B32,
-    polarity: B4,
     x: B14,
     y: B14,
+    polarity: B4,
 }
 
 struct Metadata {
@@ -32,95 +45,110 @@ impl Default for Metadata {
 type Timestamp = u64;
 
 pub struct DVSRawDecoderDat<R: Read + BufRead + Seek> {
-    reader: BufReader<R>,
-    buffer_read: Vec<RawEvent>,
+    reader: R,
+    event_type: u8,
+    event_size: u8,
+    buffer_read: Vec<u8>,
 }
 
-impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderDat<R> {
-    fn new(reader: R) -> Self {
-        let _buffer_read: Vec<u8> = vec![0; std::mem::size_of::<RawEvent>()];
-
+impl<R: Read + BufRead + Seek> DVSRawDecoderDat<R> {
+    pub fn new(reader: R) -> Self {
         Self {
-            reader: BufReader::new(reader),
-            buffer_read: vec![RawEvent::new()],
+            reader,
+            event_type: DAT_EVENT_TYPE_CD,
+            event_size: DAT_EVENT_SIZE_CD,
+            buffer_read: vec![0; DAT_EVENT_SIZE_CD as usize],
         }
     }
+}
 
-    fn read_header(&mut self) -> anyhow::Result<Vec<String>> {
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderDat<R> {
+    // Reads the header of the DAT file, extracting metadata and the event type/size preamble
+    // Returns the header as a vector of strings
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
         // Copy header
         let mut header: Vec<String> = Vec::new();
-        loop {
+        let mut metadata = Metadata::default();
+        // Reset the reader to the beginning
+        self.reader.seek(SeekFrom::Start(0))?;
+
+        // Header lines all start with a "%". Peek at the next byte to find the end of the header
+        while self.reader.fill_buf()?.first() == Some(&b'%') {
             let mut line = String::new();
             self.reader.read_line(&mut line)?;
-            // Add line to header
-            header.push(line.clone());
-            if !line.contains("%") {
-                break;
-            }
-        }
-
-        let mut metadata = Metadata::default();
-        let mut first_char = [0; 1];
-        let reader = self.reader.get_mut();
-
-        loop {
-            reader.read_exact(&mut first_char)?;
-            // if the first character ist a %, read the rest of the line
-            if first_char == ['%' as u8] {
-                // read the rest of the line
-                let mut line: String = String::new();
-                reader.read_line(&mut line)?;
-                eprintln!("line: {}", line);
-                // if this is the end of the header, break
-                if !line.contains("%"){
-                    break;
-                } else if line.starts_with("% width ") {
-                    println!("width: {}", line[8..].trim());
-                    metadata.sensor_width = line[8..].trim().parse().unwrap();
-                } else if line.starts_with("% height ") {
-                    print!("height: {}", line[9..].trim());
-                    metadata.sensor_height = line[9..].trim().parse().unwrap();
-                }
-            } else {
-                // Move the reader back one byte if we didn't have a "%" line
-                reader.seek(io::SeekFrom::Current(-1))?;
-                break;
+            let lower = line.to_lowercase();
+            let invalid = || DvsError::InvalidHeader { line: line.clone() };
+            if let Some(width) = lower.strip_prefix("% width ") {
+                metadata.sensor_width = width.trim().parse().map_err(|_| invalid())?;
+            } else if let Some(height) = lower.strip_prefix("% height ") {
+                metadata.sensor_height = height.trim().parse().map_err(|_| invalid())?;
             }
+            // Add line to header
+            header.push(line);
         }
 
         if metadata.sensor_width > 0 && metadata.sensor_height > 0 {
-            println!(
-                "%geometry:{},{}",
-                metadata.sensor_width, metadata.sensor_height
-            );
+            tracing::debug!(width = metadata.sensor_width, height = metadata.sensor_height, "sensor geometry");
         }
 
-        // skip the event type and size details
-        let mut line: String = String::new();
-        let _ = reader.read_line(&mut line)?;
+        // Read the event type and size details
+        let mut preamble = [0u8; 2];
+        self.reader.read_exact(&mut preamble)?;
+        self.event_type = preamble[0];
+        self.event_size = preamble[1];
+        if self.event_size < DAT_EVENT_SIZE_CD {
+            return Err(DvsError::InvalidData(format!("Invalid DAT event size {}", self.event_size)));
+        }
+        self.buffer_read = vec![0; self.event_size as usize];
 
         Ok(header)
     }
 
+    // Reads the next event from the DAT file, returning it as a DVSEvent
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        if at_end(&mut self.reader)? {
+            return Ok(None);
+        }
+        read_exact_or_truncated(&mut self.reader, &mut self.buffer_read)?;
+        Ok(Some(decode_record(&self.buffer_read)))
+    }
 
-    // fn read_event(&mut self) -> anyhow::Result<Option<DVSEvent>> {
-    //     loop {
-    //         self.reader.read_exact(unsafe {
-    //             std::slice::from_raw_parts_mut(self.buffer_read.as_mut_ptr() as *mut u8, 
-    //             std::mem::size_of::<RawEvent>())})?;
-
-    //         let raw_event = self.buffer_read.as_ptr();
-    //         return Ok(Some(DVSEvent {
-    //             timestamp: unsafe { (*raw_event).timestamp() as u64 },
-    //             x: unsafe { (*raw_event).x() as u16 },
-    //             y: unsafe { (*raw_event).y() as u16 },
-    //             polarity: unsafe { (*raw_event).polarity() as u8 },
-    //         }));
-    //     }
+    // Decodes up to max events straight out of the reader's buffer, without a read call per record
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        let event_size = self.event_size as usize;
+        let mut count = 0;
+        while count < max {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                // End of file
+                break;
+            }
+            if buffer.len() < event_size {
+                // A record straddles the end of the buffer
+                read_exact_or_truncated(&mut self.reader, &mut self.buffer_read)?;
+                events.push(decode_record(&self.buffer_read));
+                count += 1;
+                continue;
+            }
 
-    // }
+            let n = (buffer.len() / event_size).min(max - count);
+            events.extend(buffer.chunks_exact(event_size).take(n).map(decode_record));
+            self.reader.consume(n * event_size);
+            count += n;
+        }
+        Ok(count)
+    }
+}
 
-    fn read_event(&mut self) -> anyhow::Result<Option<DVSEvent>> {
-        Ok(None)
+// Decodes a DAT record. Records larger than a CD event carry trailing fields we don't use
+fn decode_record(record: &[u8]) -> DVSEvent {
+    let mut bytes = [0u8; DAT_EVENT_SIZE_CD as usize];
+    bytes.copy_from_slice(&record[..DAT_EVENT_SIZE_CD as usize]);
+    let raw_event = RawEvent::from_bytes(bytes);
+    DVSEvent {
+        timestamp: raw_event.timestamp() as i64,
+        x: raw_event.x() as i16,
+        y: raw_event.y() as i16,
+        polarity: raw_event.polarity(),
     }
 }
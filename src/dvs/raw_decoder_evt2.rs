@@ -1,10 +1,10 @@
-use crate::dvs::DvsRawDecoder;
-use crate::dvs::DVSEvent;
-use anyhow::anyhow;
-use modular_bitfield::bitfield;
-use modular_bitfield::prelude::{B11, B28, B4};
-use modular_bitfield::specifiers::B6;
-use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use crate::dvs::mmap::{self, MmapReader};
+use crate::dvs::error::DvsError;
+use crate::dvs::index::{EventIndex, IndexBuilder, IndexEntry};
+use crate::dvs::{at_end, read_exact_or_truncated, DvsRawDecoder};
+use crate::dvs::{DVSEvent, TriggerEvent};
+use std::io::{BufRead, Read, Seek, SeekFrom};
+use std::path::Path;
 
 /* 
 This file implements an EVT2 raw event decoder for Dynamic Vision Sensor (DVS) data streams.
@@ -18,130 +18,232 @@ enum EventTypes {
     CdOn = 0x1,         // Change Detection event, polarity on.
     EvtTimeHigh = 0x8,  // EVT_TIME_HIGH event, used for timestamp synchronization.
     ExtTrigger = 0xA,   // External trigger event
+    Others = 0xE,       // Vendor events, e.g. sensor monitoring, which carry no CD events
+    Continued = 0xF,    // Extra data of the previous OTHERS word
 }
 
-impl From<u8> for EventTypes {
-    fn from(value: u8) -> Self {
-        match value {
-            0x0 => EventTypes::CdOff,
-            0x1 => EventTypes::CdOn,
-            0x8 => EventTypes::EvtTimeHigh,
-            0xA => EventTypes::ExtTrigger,
-            _ => EventTypes::ExtTrigger,
+pub(crate) struct Metadata {
+    sensor_width: i32,
+    sensor_height: i32,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Metadata {
+            sensor_width: -1,
+            sensor_height: -1,
         }
     }
 }
 
-// A bitfield struct representing the raw 32 bits of an event in EVT2 format
-#[bitfield]
-#[derive(Clone, Debug)]
-struct RawEvent {
-    r#type: B4,
-    pad: B28
+impl Metadata {
+    pub(crate) fn log_geometry(&self) {
+        if self.sensor_width > 0 && self.sensor_height > 0 {
+            tracing::debug!(width = self.sensor_width, height = self.sensor_height, "sensor geometry");
+        }
+    }
 }
 
-// A bitfield struct for EVT_TIME_HIGH events, which contain a timestamp
-#[bitfield]
-struct RawEventTime {
-    r#type: B4,     // Event type
-    timestamp: B28  // Event timestamp
+// Parses a header line, without its leading '%', into the metadata. Returns true for the last line of the header
+pub(crate) fn parse_header_line(line: &str, metadata: &mut Metadata) -> Result<bool, DvsError> {
+    let invalid = || DvsError::InvalidHeader { line: format!("%{}", line) };
+    if line == " end\n" {
+        return Ok(true);
+    } else if let Some(format_str) = line.strip_prefix(" format ") {
+        let mut parts = format_str.split(';');
+        if parts.next().unwrap_or_default().trim() != "EVT2" {
+            return Err(DvsError::UnsupportedFormat("Detected non-EVT2 input file".to_string()));
+        }
+        for option in parts {
+            let (name, value) = option.split_once('=').ok_or_else(invalid)?;
+            if name == "width" {
+                metadata.sensor_width = value.trim().parse().map_err(|_| invalid())?;
+            } else if name == "height" {
+                metadata.sensor_height = value.trim().parse().map_err(|_| invalid())?;
+            }
+        }
+    } else if let Some(geometry_str) = line.strip_prefix(" geometry ") {
+        let (width, height) = geometry_str.trim().split_once('x').ok_or_else(invalid)?;
+        metadata.sensor_width = width.parse().map_err(|_| invalid())?;
+        metadata.sensor_height = height.parse().map_err(|_| invalid())?;
+    } else if line.starts_with(" evt ") && line[5..].trim() != "2.0" {
+        return Err(DvsError::UnsupportedFormat("Detected non-EVT2 input file".to_string()));
+    }
+    Ok(false)
 }
 
-// A bitfield struct for Change Detection events, which contain pixel coordinates, polarity, and timestamp
-#[bitfield]
-struct RawEventCD {
-    r#type: B4,     // Event type
-    timestamp: B6,  // Event timestamp
-    x: B11,         // Pixel X coordinate
-    y: B11,         // Pixel Y coordinate
+// The main decoder struct. Reads from the caller's buffered reader and maintains state for timestamp base and event parsing.
+pub struct DVSRawDecoderEvt2<R: Read + BufRead + Seek> {
+    reader: R,
+    first_time_base_set: bool,
+    time: TimeBase,
+    // Offset of the first word after the header
+    data_start: u64,
+    // Loaded with use_index
+    index: Option<Vec<IndexEntry>>,
+    // Trigger events decoded since the last take_triggers
+    triggers: Vec<TriggerEvent>,
 }
 
-// Conversion from bytes to RawEvent
-impl From<[u8; 4]> for RawEvent {
-    fn from(value: [u8; 4]) -> Self {
-        let mut event = RawEvent::new();
-        event.set_type(value[3] >> 4);
-        event.set_pad(
-            ((value[3] & 0x0F) as u32) << 24
-            | (value[2] as u32) << 16
-            | (value[1] as u32) << 8
-            | value[0] as u32,
-        );
-        event
+impl<R: Read + BufRead + Seek> DVSRawDecoderEvt2<R> {
+    // Creates a new DVSRawDecoderEvt2 instance reading from the given buffered reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            first_time_base_set: false,
+            time: TimeBase::default(),
+            data_start: 0,
+            index: None,
+            triggers: Vec::new(),
+        }
     }
-}
 
-// Conversion from Raw event to RawEventTime
-impl From<RawEvent> for RawEventTime {
-    fn from(event: RawEvent) -> Self {
-        let event_time = RawEventTime::new()
-            .with_timestamp(event.pad())
-            .with_type(event.r#type());
-        event_time
+    // Reads the next 32-bit word
+    fn read_word(&mut self) -> anyhow::Result<[u8; 4]> {
+        let mut word = [0u8; 4];
+        read_exact_or_truncated(&mut self.reader, &mut word)?;
+        Ok(word)
     }
-}
 
-// Conversion from RawEvent to RawEventCD
-impl From<RawEvent> for RawEventCD {
-    fn from(event: RawEvent) -> Self {
-        let event_cd = RawEventCD::new()
-            .with_y((event.pad()& 0x7FF) as u16)
-            .with_x(((event.pad() >> 11) & 0x7FF) as u16)
-            .with_timestamp((event.pad() >> 22) as u8) 
-            .with_type(event.r#type());
-        event_cd
+    // Uses an index of the recording for seek_to_timestamp, see index.rs
+    pub fn use_index(&mut self, index: EventIndex) {
+        self.index = Some(index.entries);
     }
-}
 
+    // Moves to the first EVT_TIME_HIGH word whose events can be at or after the timestamp, so decoding resumes
+    // without skipping any of those events. The events of its time base before the timestamp, up to 63 us
+    // earlier, are returned too. Seeking past the last event moves to the end of the file. Call read_header
+    // first.
+    // The word is found by binary search over the words of the file or, with an index, over the words between
+    // two entries of the index. Without an index, the search assumes the time base doesn't loop within the
+    // file, which it does every 4.8 hours
+    pub fn seek_to_timestamp(&mut self, timestamp: i64) -> anyhow::Result<()> {
+        let words = (self.reader.seek(SeekFrom::End(0))? - self.data_start) / 4;
+        let before = |time_base: u64| time_base as i64 + 63 < timestamp;
+        // The search runs from an EVT_TIME_HIGH word whose events are all before the timestamp, with the time
+        // base after it, to a word from which on none are
+        let (mut low, time, mut high) = match self.index.as_deref() {
+            Some(entries) => {
+                let next = entries.partition_point(|entry| before(entry.time_base as u64));
+                let high = entries.get(next).map_or(words, |entry| (entry.offset - self.data_start) / 4);
+                let Some(entry) = next.checked_sub(1).map(|previous| entries[previous]) else {
+                    return self.resume(high, TimeBase::default());
+                };
+                let time = TimeBase { current_time_base: entry.time_base as u64, n_time_high_loop: entry.n_time_high_loop as u64 };
+                ((entry.offset - self.data_start) / 4, time, high)
+            }
+            None => {
+                // The words before the first EVT_TIME_HIGH word are skipped, as by read_header
+                let Some((first, timestamp)) = self.next_time_high(0, words)? else {
+                    return Ok(());
+                };
+                let mut time = TimeBase::default();
+                time.time_high(timestamp);
+                if !before(time.current_time_base) {
+                    return self.resume(first, TimeBase::default());
+                }
+                (first, time, words)
+            }
+        };
 
+        let time_base_of = |timestamp: u32| {
+            let mut next = time;
+            next.time_high(timestamp);
+            next.current_time_base
+        };
+        while high - low > 1 {
+            let mid = low + (high - low) / 2;
+            match self.next_time_high(mid, high)? {
+                Some((index, timestamp)) if before(time_base_of(timestamp)) => low = index,
+                _ => high = mid,
+            }
+        }
+        let resume = self.next_time_high(low + 1, words)?.map_or(words, |(index, _)| index);
+        self.resume(resume, time)
+    }
 
-struct Metadata {
-    sensor_width: i32,
-    sensor_height: i32,
-}
+    // Resumes decoding at the word at the index, with the time base before it
+    fn resume(&mut self, index: u64, time: TimeBase) -> anyhow::Result<()> {
+        self.reader.seek(SeekFrom::Start(self.data_start + 4 * index))?;
+        self.time = time;
+        self.first_time_base_set = true;
+        Ok(())
+    }
 
-impl Default for Metadata {
-    fn default() -> Self {
-        Metadata {
-            sensor_width: -1,
-            sensor_height: -1,
+    // Finds the first EVT_TIME_HIGH word from the word at index from, up to the one at index to, returning its
+    // index and timestamp
+    fn next_time_high(&mut self, from: u64, to: u64) -> anyhow::Result<Option<(u64, u32)>> {
+        self.reader.seek(SeekFrom::Start(self.data_start + 4 * from))?;
+        for index in from..to {
+            let word = u32::from_le_bytes(self.read_word()?);
+            if is_time_high(word) {
+                return Ok(Some((index, word & 0x0FFF_FFFF)));
+            }
         }
+        Ok(None)
     }
-}
-
-// The main decoder struct. Wraps a buffered reader and maintains state for timestamp base and event parsing.
-pub struct DVSRawDecoderEvt2<R: Read + BufRead + Seek> {
-    reader: BufReader<R>,
-    first_time_base_set: bool,
-    current_time_base: u64,
-    n_time_high_loop: u64,
-    buffer_read: Vec<[u8; 4]>,
-}
 
-impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt2<R> {
-    // Creates a new DVSRawDecoderEvt2 instance with a buffered reader
-    fn new(reader: R) -> Self {
-        let _buffer_read: Vec<u8> = vec![0; std::mem::size_of::<[u8; 4]>()];
+    // Indexes the recording in one pass, keeping an entry every interval
+    pub(crate) fn build_index(&mut self, interval_us: i64) -> anyhow::Result<Vec<IndexEntry>> {
+        let mut builder = IndexBuilder::new(interval_us);
+        let mut time = TimeBase::default();
+        let mut time_high = |word: u32, offset: u64| {
+            if is_time_high(word) {
+                time.time_high(word & 0x0FFF_FFFF);
+                let time_base = time.current_time_base as i64;
+                builder.time_high(&IndexEntry { offset, time_base, n_time_high_loop: time.n_time_high_loop as i64, ..IndexEntry::default() });
+            }
+        };
 
-        Self {
-            reader: BufReader::new(reader),
-            first_time_base_set: false,
-            current_time_base: 0,
-            n_time_high_loop: 0,
-            buffer_read: vec![unsafe { std::mem::zeroed() }],
+        let mut offset = self.reader.seek(SeekFrom::Start(self.data_start))?;
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                break;
+            }
+            if buffer.len() < 4 {
+                // A word straddles the end of the buffer
+                time_high(u32::from_le_bytes(self.read_word()?), offset);
+                offset += 4;
+                continue;
+            }
+            let consumed = buffer.len() & !3;
+            for word in buffer[..consumed].chunks_exact(4) {
+                time_high(u32::from_le_bytes([word[0], word[1], word[2], word[3]]), offset);
+                offset += 4;
+            }
+            self.reader.consume(consumed);
         }
+        Ok(builder.entries)
+    }
+}
+
+impl DVSRawDecoderEvt2<MmapReader> {
+    /// Creates a decoder over a memory map of the file, which parses words straight out of the map. Call
+    /// read_header before reading events, as with new.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be written to or truncated until the decoder is dropped, see mmap.rs.
+    pub unsafe fn from_mmap(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        // Safety: upheld by the caller
+        Ok(Self::new(unsafe { mmap::open_reader(path) }?))
     }
+}
 
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt2<R> {
     // Reads the header of the EVT2 file, extracting metadata and setting the initial time base
     // Returns the header as a vector of strings
-    fn read_header(&mut self) -> anyhow::Result<Vec<String>> {
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
         // Copy header
         let mut header: Vec<String> = Vec::new();
         // Reset the reader to the beginning
         self.reader.seek(SeekFrom::Start(0))?;
         loop {
             let mut line = String::new();
-            self.reader.read_line(&mut line)?;
+            if self.reader.read_line(&mut line)? == 0 {
+                break;
+            }
             // Add line to header
             header.push(line.clone());
             if line.contains("% end") {
@@ -156,38 +258,13 @@ impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt2<R> {
 
         loop {
             self.reader.read_exact(&mut first_char)?;
-            if first_char == ['%' as u8] {
+            if first_char == [b'%'] {
                 // read the rest of the line
                 let mut line: String = String::new();
                 self.reader.read_line(&mut line)?;
-                //eprintln!("line: {}", line);
-                if line == " end\n" {
+                tracing::trace!(?line, "header");
+                if parse_header_line(&line, &mut metadata)? {
                     break;
-                } else if line.starts_with(" format ") {
-                    let format_str = &line[8..];
-                    let mut parts = format_str.split(';');
-                    if parts.next().unwrap() != "EVT2" {
-                        return Err(anyhow!("Error: detected non-EVT2 input file"));
-                    }
-                    for option in parts {
-                        let mut kv = option.split('=');
-                        let name = kv.next().unwrap();
-                        let value = kv.next().unwrap();
-                        if name == "width" {
-                            metadata.sensor_width = value[..value.len() - 1].parse().unwrap();
-                        } else if name == "height" {
-                            metadata.sensor_height = value.parse().unwrap();
-                        }
-                    }
-                } else if line.starts_with(" geometry ") {
-                    let geometry_str = &line[10..line.len() - 1];
-                    let mut parts = geometry_str.split('x');
-                    metadata.sensor_width = parts.next().unwrap().parse().unwrap();
-                    metadata.sensor_height = parts.next().unwrap().parse().unwrap();
-                } else if line.starts_with(" evt ") {
-                    if &line[5..] != "2.0\n" {
-                        return Err(anyhow!("Error: detected non-EVT2 input file"));
-                    }
                 }
             } else {
                 // Move the reader back one byte if we didn't have the "% end\n" line
@@ -196,32 +273,19 @@ impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt2<R> {
             }
         }
 
-        if metadata.sensor_width > 0 && metadata.sensor_height > 0 {
-            println!(
-                "%geometry:{},{}",
-                metadata.sensor_width, metadata.sensor_height
-            );
-        }
+        metadata.log_geometry();
 
+        self.data_start = self.reader.stream_position()?;
 
-        loop {
-            // First, skip any events until we get one of the type EVT_TIME_HIGH
-            self.reader.read_exact(unsafe {
-                std::slice::from_raw_parts_mut(
-                    self.buffer_read.as_mut_ptr() as *mut u8,
-                    std::mem::size_of::<RawEvent>(),
-                )
-            })?;
-            
-            let raw_event = RawEvent::from(self.buffer_read[0]);
-            match raw_event.r#type() {
-                x if x == EventTypes::EvtTimeHigh as u8 => {
-                    let ev_time_high = RawEventTime::from(raw_event);
-                    self.current_time_base = (ev_time_high.timestamp() as u64) << 6;
-                    self.first_time_base_set = true;
-                    break;
-                }
-                _ => {}
+        // First, skip any events until we get one of the type EVT_TIME_HIGH. A file without any has no events
+        while !at_end(&mut self.reader)? {
+            let word = self.read_word()?;
+
+            let word = u32::from_le_bytes(word);
+            if is_time_high(word) {
+                self.time.current_time_base = ((word & 0x0FFF_FFFF) as u64) << 6;
+                self.first_time_base_set = true;
+                break;
             }
         }
         Ok(header)
@@ -229,66 +293,167 @@ impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt2<R> {
 
     
     // Reads the next event from the EVT2 file, returning it as a DVSEvent
-    fn read_event(&mut self) -> anyhow::Result<Option<DVSEvent>> {
-        loop {
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        while !at_end(&mut self.reader)? {
             // Read event
-            self.reader.read_exact(unsafe {
-                std::slice::from_raw_parts_mut(
-                    self.buffer_read.as_mut_ptr() as *mut u8,
-                    std::mem::size_of::<RawEvent>(),
-                )
-            })?;
-
-            let raw_event = RawEvent::from(self.buffer_read[0]);
-            match raw_event.r#type() {
-                x if x == EventTypes::CdOff as u8 => {
-                    let ev_cd = RawEventCD::from(raw_event);
-                    let t = self.current_time_base + ev_cd.timestamp() as u64;
-                    return Ok(Some(DVSEvent {
-                        timestamp: t as i64,
-                        x: ev_cd.x() as i16,
-                        y: ev_cd.y() as i16,
-                        polarity: 0,
-                    }));
-                }
-                x if x == EventTypes::CdOn as u8 => {
-                    let ev_cd = RawEventCD::from(raw_event);
-                    let t = self.current_time_base + ev_cd.timestamp() as u64;
-                    return Ok(Some(DVSEvent {
-                        timestamp: t as i64,
-                        x: ev_cd.x() as i16,
-                        y: ev_cd.y() as i16,
-                        polarity: 1,
-                    }));
-                }
-                x if x == EventTypes::EvtTimeHigh as u8 => {
-                    const MAX_TIMESTAMP_BASE: u64 = ((1 << 28) - 1) << 6;
-                    const TIME_LOOP: u64 = MAX_TIMESTAMP_BASE + (1 << 6);
-                    const LOOP_THRESHOLD: u64 = 10 << 6;
-
-                    let ev_time_high = RawEventTime::from(raw_event);
-                    let mut new_time_base = (ev_time_high.timestamp() as u64) << 6;
-                    new_time_base += self.n_time_high_loop * TIME_LOOP;
-
-                    if self.current_time_base > new_time_base
-                        && self.current_time_base - new_time_base
-                            >= MAX_TIMESTAMP_BASE - LOOP_THRESHOLD
-                    {
-                        new_time_base += TIME_LOOP;
-                        self.n_time_high_loop += 1;
-                    }
+            let word = self.read_word()?;
 
-                    self.current_time_base = new_time_base;
-                    return Ok(None);
+            match self.time.decode(u32::from_le_bytes(word)) {
+                Decoded::Event(event) => return Ok(Some(event)),
+                Decoded::Trigger(trigger) => self.triggers.push(trigger),
+                Decoded::TimeHigh | Decoded::Skipped => {}
+            }
+        }
+        Ok(None)
+    }
 
-                }           
-                x if x == EventTypes::ExtTrigger as u8 => {
-                    // Ignore for now--we're not doing anything with triggers.
+    // Decodes up to max events straight out of the reader's buffer (or the whole memory map), without a read
+    // call per word
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        let mut count = 0;
+        while count < max {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                // End of file
+                break;
+            }
+            if buffer.len() < 4 {
+                // A word straddles the end of the buffer
+                match self.read_event()? {
+                    Some(event) => {
+                        events.push(event);
+                        count += 1;
+                    }
+                    None => break,
                 }
-                _ => {
-                    eprintln!("Error: Invalid event type {}", raw_event.r#type());
+                continue;
+            }
+
+            events.reserve((max - count).min(buffer.len() / 4));
+            let mut consumed = 0;
+            for word in buffer.chunks_exact(4) {
+                consumed += 4;
+                match self.time.decode(u32::from_le_bytes([word[0], word[1], word[2], word[3]])) {
+                    Decoded::Event(event) => {
+                        events.push(event);
+                        count += 1;
+                        if count == max {
+                            break;
+                        }
+                    }
+                    Decoded::Trigger(trigger) => self.triggers.push(trigger),
+                    Decoded::TimeHigh | Decoded::Skipped => {}
                 }
             }
+            self.reader.consume(consumed);
+        }
+        Ok(count)
+    }
+
+    // Triggers are kept until they are taken
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        std::mem::take(&mut self.triggers)
+    }
+}
+
+// Outcome of decoding a single EVT2 word
+pub(crate) enum Decoded {
+    Event(DVSEvent),
+    Trigger(TriggerEvent),
+    TimeHigh,
+    Skipped,
+}
+
+// Largest time base of an EVT_TIME_HIGH word, and the time it takes the time base to loop back to zero
+const MAX_TIMESTAMP_BASE: u64 = ((1 << 28) - 1) << 6;
+pub(crate) const TIME_LOOP: u64 = MAX_TIMESTAMP_BASE + (1 << 6);
+const LOOP_THRESHOLD: u64 = 10 << 6;
+
+// Timestamp state shared by all words of the stream
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct TimeBase {
+    pub current_time_base: u64,
+    pub n_time_high_loop: u64,
+}
+
+impl TimeBase {
+    // Moves to the time base of an EVT_TIME_HIGH word, counting a loop if the time base wrapped around
+    pub(crate) fn time_high(&mut self, timestamp: u32) {
+        let mut new_time_base = (timestamp as u64) << 6;
+        new_time_base += self.n_time_high_loop * TIME_LOOP;
+
+        if self.current_time_base > new_time_base
+            && self.current_time_base - new_time_base
+                >= MAX_TIMESTAMP_BASE - LOOP_THRESHOLD
+        {
+            new_time_base += TIME_LOOP;
+            self.n_time_high_loop += 1;
+        }
+
+        self.current_time_base = new_time_base;
+    }
+
+    // Decodes one little-endian word, updating the time base for EVT_TIME_HIGH words. The fields are
+    // extracted with shifts rather than through the bitfield structs, as this is the hot loop of decoding
+    #[inline]
+    pub(crate) fn decode(&mut self, word: u32) -> Decoded {
+        let r#type = (word >> 28) as u8;
+        match r#type {
+            x if x == EventTypes::CdOff as u8 || x == EventTypes::CdOn as u8 => {
+                let t = self.current_time_base + ((word >> 22) & 0x3F) as u64;
+                Decoded::Event(DVSEvent {
+                    timestamp: t as i64,
+                    x: ((word >> 11) & 0x7FF) as i16,
+                    y: (word & 0x7FF) as i16,
+                    polarity: r#type,
+                })
+            }
+            x if x == EventTypes::EvtTimeHigh as u8 => {
+                self.time_high(word & 0x0FFF_FFFF);
+                Decoded::TimeHigh
+            }
+            x if x == EventTypes::ExtTrigger as u8 => {
+                // The channel is in bits 8 to 12 and the value in bit 0
+                Decoded::Trigger(TriggerEvent {
+                    timestamp: (self.current_time_base + ((word >> 22) & 0x3F) as u64) as i64,
+                    id: ((word >> 8) & 0x1F) as u8,
+                    value: (word & 0x1) as u8,
+                })
+            }
+            x if x == EventTypes::Others as u8 || x == EventTypes::Continued as u8 => Decoded::Skipped,
+            // Logged at debug level, as this runs once per word
+            _ => {
+                tracing::debug!(r#type, "skipping invalid event");
+                Decoded::Skipped
+            }
+        }
+    }
+}
+
+// Whether a little-endian word is an EVT_TIME_HIGH word
+pub(crate) fn is_time_high(word: u32) -> bool {
+    (word >> 28) as u8 == EventTypes::EvtTimeHigh as u8
+}
+
+// The events of a run of words starting with an EVT_TIME_HIGH word, decoded as if the time base had never
+// looped before it. See parallel.rs for how segments are stitched together
+pub(crate) struct Segment {
+    pub events: Vec<DVSEvent>,
+    // Timestamp field of the first EVT_TIME_HIGH word
+    pub first_time_high: u32,
+    // State after the last word
+    pub time: TimeBase,
+}
+
+// Decodes a run of little-endian words that starts with an EVT_TIME_HIGH word
+pub(crate) fn decode_segment(words: &[u8]) -> Segment {
+    let mut time = TimeBase::default();
+    let mut events = Vec::with_capacity(words.len() / 4);
+    let first_time_high = words.get(..4).map_or(0, |word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]) & 0x0FFF_FFFF);
+    for word in words.chunks_exact(4) {
+        if let Decoded::Event(event) = time.decode(u32::from_le_bytes([word[0], word[1], word[2], word[3]])) {
+            events.push(event);
         }
     }
+    Segment { events, first_time_high, time }
 }
@@ -0,0 +1,332 @@
+#![allow(dead_code)]
+
+use crate::dvs::error::DvsError;
+use crate::dvs::{at_end, read_exact_or_truncated, DvsRawDecoder};
+use crate::dvs::DVSEvent;
+use modular_bitfield::bitfield;
+use modular_bitfield::prelude::{B11, B28, B32, B4, B6};
+use std::io::{BufRead, Read, Seek, SeekFrom};
+
+/*
+This file implements an EVT2.1 raw event decoder for Dynamic Vision Sensor (DVS) data streams.
+EVT2.1 is a vectorized EVT2: words are 64 bits (little-endian), and a CD word carries a 32-bit mask of
+events sharing a timestamp, row and polarity, at columns x to x + 31. Time High words are the same as EVT2.
+*/
+
+// An enum representing the possible event types in EVT2.1 streams:
+#[derive(Debug, Clone, Copy)]
+enum EventTypes {
+    CdOff = 0x0,        // Change Detection vector, polarity off.
+    CdOn = 0x1,         // Change Detection vector, polarity on.
+    EvtTimeHigh = 0x8,  // EVT_TIME_HIGH event, used for timestamp synchronization.
+    ExtTrigger = 0xA,   // External trigger event
+    Others = 0xE,       // Vendor specific events
+    Continued = 0xF,    // Extra data of the previous event
+}
+
+// A bitfield struct representing the raw 64 bits of an event in EVT2.1 format
+#[bitfield]
+#[derive(Clone, Copy)]
+struct RawEvent {
+    pad: B32,
+    pad_high: B28,
+    r#type: B4,
+}
+
+// A bitfield struct for EVT_TIME_HIGH events, which contain a timestamp
+#[bitfield]
+struct RawEventTime {
+    unused: B32,
+    timestamp: B28, // Event timestamp
+    r#type: B4,     // Event type
+}
+
+// A bitfield struct for Change Detection vectors, which contain the first pixel's coordinates, a mask of
+// the valid pixels, polarity and timestamp
+#[bitfield]
+struct RawEventCD {
+    valid: B32,    // Bit i set if the pixel at x + i has an event
+    y: B11,        // Pixel Y coordinate
+    x: B11,        // Pixel X coordinate of the first bit of the mask
+    timestamp: B6, // Event timestamp
+    r#type: B4,    // Event type
+}
+
+// Conversion from bytes to RawEvent
+impl From<[u8; 8]> for RawEvent {
+    fn from(value: [u8; 8]) -> Self {
+        RawEvent::from_bytes(value)
+    }
+}
+
+// Conversion from Raw event to RawEventTime
+impl From<RawEvent> for RawEventTime {
+    fn from(event: RawEvent) -> Self {
+        RawEventTime::from_bytes(event.into_bytes())
+    }
+}
+
+// Conversion from RawEvent to RawEventCD
+impl From<RawEvent> for RawEventCD {
+    fn from(event: RawEvent) -> Self {
+        RawEventCD::from_bytes(event.into_bytes())
+    }
+}
+
+struct Metadata {
+    sensor_width: i32,
+    sensor_height: i32,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Metadata {
+            sensor_width: -1,
+            sensor_height: -1,
+        }
+    }
+}
+
+// Events of a CD vector that have not been returned yet
+#[derive(Default)]
+struct Vector {
+    timestamp: i64,
+    x: i16,
+    y: i16,
+    polarity: u8,
+    // Remaining bits of the mask
+    valid: u32,
+}
+
+impl Vector {
+    // Returns the event of the lowest remaining bit of the mask
+    fn next_event(&mut self) -> Option<DVSEvent> {
+        if self.valid == 0 {
+            return None;
+        }
+        let bit = self.valid.trailing_zeros();
+        self.valid &= self.valid - 1;
+        Some(DVSEvent {
+            timestamp: self.timestamp,
+            x: self.x + bit as i16,
+            y: self.y,
+            polarity: self.polarity,
+        })
+    }
+}
+
+// The main decoder struct. Reads from the caller's buffered reader and maintains state for timestamp base and event parsing.
+pub struct DVSRawDecoderEvt21<R: Read + BufRead + Seek> {
+    reader: R,
+    first_time_base_set: bool,
+    time: TimeBase,
+    vector: Vector,
+}
+
+impl<R: Read + BufRead + Seek> DVSRawDecoderEvt21<R> {
+    // Creates a new DVSRawDecoderEvt21 instance reading from the given buffered reader
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            first_time_base_set: false,
+            time: TimeBase::default(),
+            vector: Vector::default(),
+        }
+    }
+
+    // Reads the next 64-bit word
+    fn read_word(&mut self) -> anyhow::Result<RawEvent> {
+        let mut word = [0u8; 8];
+        read_exact_or_truncated(&mut self.reader, &mut word)?;
+        Ok(RawEvent::from(word))
+    }
+}
+
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt21<R> {
+    // Reads the header of the EVT2.1 file, extracting metadata and setting the initial time base
+    // Returns the header as a vector of strings
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        let mut header: Vec<String> = Vec::new();
+        let mut metadata = Metadata::default();
+        // Reset the reader to the beginning
+        self.reader.seek(SeekFrom::Start(0))?;
+        self.vector = Vector::default();
+
+        while self.reader.fill_buf()?.first() == Some(&b'%') {
+            let mut line = String::new();
+            self.reader.read_line(&mut line)?;
+            header.push(line.clone());
+            let line = line.trim_end();
+            let invalid = || DvsError::InvalidHeader { line: line.to_string() };
+            if line == "% end" {
+                break;
+            } else if let Some(format_str) = line.strip_prefix("% format ") {
+                let mut parts = format_str.split(';');
+                if !parts.next().unwrap_or_default().trim().eq_ignore_ascii_case("EVT21") {
+                    return Err(DvsError::UnsupportedFormat("Detected non-EVT2.1 input file".to_string()));
+                }
+                for option in parts {
+                    match option.split_once('=') {
+                        Some(("width", value)) => metadata.sensor_width = value.trim().parse().map_err(|_| invalid())?,
+                        Some(("height", value)) => metadata.sensor_height = value.trim().parse().map_err(|_| invalid())?,
+                        _ => {}
+                    }
+                }
+            } else if let Some(geometry_str) = line.strip_prefix("% geometry ") {
+                let (width, height) = geometry_str.split_once('x').ok_or_else(invalid)?;
+                metadata.sensor_width = width.trim().parse().map_err(|_| invalid())?;
+                metadata.sensor_height = height.trim().parse().map_err(|_| invalid())?;
+            } else if let Some(version) = line.strip_prefix("% evt ") {
+                if version.trim() != "2.1" {
+                    return Err(DvsError::UnsupportedFormat("Detected non-EVT2.1 input file".to_string()));
+                }
+            }
+        }
+
+        if metadata.sensor_width > 0 && metadata.sensor_height > 0 {
+            tracing::debug!(width = metadata.sensor_width, height = metadata.sensor_height, "sensor geometry");
+        }
+
+        // Skip any events until we get one of the type EVT_TIME_HIGH. A file without any has no events
+        while !at_end(&mut self.reader)? {
+            let raw_event = self.read_word()?;
+            if raw_event.r#type() == EventTypes::EvtTimeHigh as u8 {
+                self.time.decode(raw_event);
+                self.first_time_base_set = true;
+                break;
+            }
+        }
+        Ok(header)
+    }
+
+    // Reads the next event from the EVT2.1 file, returning it as a DVSEvent. Events of a CD vector are
+    // returned one per call
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        if let Some(event) = self.vector.next_event() {
+            return Ok(Some(event));
+        }
+        while !at_end(&mut self.reader)? {
+            let raw_event = self.read_word()?;
+            if let Decoded::Vector(vector) = self.time.decode(raw_event) {
+                self.vector = vector;
+                if let Some(event) = self.vector.next_event() {
+                    return Ok(Some(event));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    // Decodes up to max events straight out of the reader's buffer, without a read call per word
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        let mut count = 0;
+        while count < max {
+            // Finish the current vector first, which may have been split by max
+            if let Some(event) = self.vector.next_event() {
+                events.push(event);
+                count += 1;
+                continue;
+            }
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                // End of file
+                break;
+            }
+            if buffer.len() < std::mem::size_of::<RawEvent>() {
+                // A word straddles the end of the buffer
+                match self.read_event()? {
+                    Some(event) => {
+                        events.push(event);
+                        count += 1;
+                    }
+                    None => break,
+                }
+                continue;
+            }
+
+            let mut consumed = 0;
+            for word in buffer.chunks_exact(8) {
+                consumed += 8;
+                let word: [u8; 8] = word.try_into().expect("chunks_exact yields 8 bytes");
+                if let Decoded::Vector(mut vector) = self.time.decode(RawEvent::from(word)) {
+                    while count < max {
+                        match vector.next_event() {
+                            Some(event) => {
+                                events.push(event);
+                                count += 1;
+                            }
+                            None => break,
+                        }
+                    }
+                    if count == max {
+                        self.vector = vector;
+                        break;
+                    }
+                }
+            }
+            self.reader.consume(consumed);
+        }
+        Ok(count)
+    }
+}
+
+// Outcome of decoding a single EVT2.1 word
+enum Decoded {
+    Vector(Vector),
+    TimeHigh,
+    Skipped,
+}
+
+// Timestamp state shared by all words of the stream
+#[derive(Default)]
+struct TimeBase {
+    current_time_base: u64,
+    n_time_high_loop: u64,
+}
+
+impl TimeBase {
+    // Decodes one word, updating the time base for EVT_TIME_HIGH words
+    fn decode(&mut self, raw_event: RawEvent) -> Decoded {
+        match raw_event.r#type() {
+            x if x == EventTypes::CdOff as u8 || x == EventTypes::CdOn as u8 => {
+                let ev_cd = RawEventCD::from(raw_event);
+                let t = self.current_time_base + ev_cd.timestamp() as u64;
+                Decoded::Vector(Vector {
+                    timestamp: t as i64,
+                    x: ev_cd.x() as i16,
+                    y: ev_cd.y() as i16,
+                    polarity: ev_cd.r#type(),
+                    valid: ev_cd.valid(),
+                })
+            }
+            x if x == EventTypes::EvtTimeHigh as u8 => {
+                const MAX_TIMESTAMP_BASE: u64 = ((1 << 28) - 1) << 6;
+                const TIME_LOOP: u64 = MAX_TIMESTAMP_BASE + (1 << 6);
+                const LOOP_THRESHOLD: u64 = 10 << 6;
+
+                let ev_time_high = RawEventTime::from(raw_event);
+                let mut new_time_base = (ev_time_high.timestamp() as u64) << 6;
+                new_time_base += self.n_time_high_loop * TIME_LOOP;
+
+                if self.current_time_base > new_time_base
+                    && self.current_time_base - new_time_base
+                        >= MAX_TIMESTAMP_BASE - LOOP_THRESHOLD
+                {
+                    new_time_base += TIME_LOOP;
+                    self.n_time_high_loop += 1;
+                }
+
+                self.current_time_base = new_time_base;
+                Decoded::TimeHigh
+            }
+            x if x == EventTypes::ExtTrigger as u8 || x == EventTypes::Others as u8 || x == EventTypes::Continued as u8 => {
+                // Ignore for now--we're not doing anything with triggers or vendor events.
+                Decoded::Skipped
+            }
+            _ => {
+                tracing::warn!(r#type = raw_event.r#type(), "skipping invalid event");
+                Decoded::Skipped
+            }
+        }
+    }
+}
@@ -1,10 +1,10 @@
-use crate::dvs::DvsRawDecoder;
-use crate::dvs::DVSEvent;
+use crate::dvs::error::DvsError;
+use crate::dvs::index::{EventIndex, IndexBuilder, IndexEntry, DEFAULT_INTERVAL_US};
+use crate::dvs::{at_end, read_exact_or_truncated, DvsRawDecoder};
+use crate::dvs::{DVSEvent, TriggerEvent};
 use anyhow::Result;
-use modular_bitfield::bitfield;
-use modular_bitfield::prelude::{B1, B11, B12, B4, B7, B8};
 use std::collections::VecDeque;
-use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::io::{BufRead, Read, Seek, SeekFrom};
 
 
 /* 
@@ -26,6 +26,8 @@ enum EventTypes {
     Continued4 = 0x7,
     Continued12 = 0xF,
     Others = 0xE,
+    // Reserved types (0x1, 0x9, 0xB, 0xC, 0xD), which carry nothing the decoder knows
+    Unknown = 0x10,
 }
 
 impl From<u8> for EventTypes {
@@ -42,175 +44,48 @@ impl From<u8> for EventTypes {
             0x7 => EventTypes::Continued4,
             0xF => EventTypes::Continued12,
             0xE => EventTypes::Others,
-            _ => EventTypes::ExtTrigger,
+            _ => EventTypes::Unknown,
         }
     }
 }
 
 
-// A bitfield struct representing the raw 16 bits of an event in EVT3 format
-#[bitfield]
-struct RawEvent {
-    pad: B12,
-    r#type: B4,
-}
+// The raw 16 bits of an event in EVT3 format: the event type in the top 4 bits, and its fields in the other 12
+#[derive(Clone, Copy)]
+struct RawEvent(u16);
 
-// Conversion from bytes to RawEvent
 impl From<[u8; 2]> for RawEvent {
     fn from(value: [u8; 2]) -> Self {
-        let mut event = RawEvent::new();
-        event.set_type(value[1] >> 4);
-        event.set_pad(((value[1] & 0xF) as u16) << 8 | (value[0] as u16));
-        event
+        RawEvent(u16::from_le_bytes(value))
     }
 }
 
-
-#[bitfield]
-struct RawEventEvtAddrY {
-    y: B11,     // Pixel Y coordinate
-    system_type: B1,    // Event polarity
-    r#type: B4, // Event type : EventTypes::EVT_ADDR_Y
-}
-
-// Conversion from Raw event to EvtAddry
-impl From<RawEvent> for RawEventEvtAddrY {
-    fn from(raw_event: RawEvent) -> Self {
-        let event = RawEventEvtAddrY::new()
-            .with_system_type((raw_event.pad() >> 11) as u8)
-            .with_y(raw_event.pad() & 0x7FF)
-            .with_type(raw_event.r#type());
-        event
+impl RawEvent {
+    fn r#type(self) -> u8 {
+        (self.0 >> 12) as u8
     }
-}
-
-
-#[bitfield]
-struct RawEventEvtAddrX {
-    x: B11,     // Pixel X coordinate
-    pol: B1,    // Event polarity
-    r#type: B4, // Event type : EventTypes::EVT_ADDR_X
-}
 
-// Conversion from Raw event to EvtAddrX
-impl From<RawEvent> for RawEventEvtAddrX {
-    fn from(raw_event: RawEvent) -> Self {
-        let event = RawEventEvtAddrX::new()
-            .with_pol((raw_event.pad() >> 11) as u8)
-            .with_x(raw_event.pad() & 0x7FF)
-            .with_type(raw_event.r#type());
-        event
+    // The 12 bits below the type: the time of EVT_TIME_LOW and EVT_TIME_HIGH, the validity of VECT_12
+    fn pad(self) -> u16 {
+        self.0 & 0xFFF
     }
-}
-
-#[bitfield]
-struct RawEventVectBaseX {
-    x: B11,     // Pixel X coordinate
-    pol: B1,    // Event polarity
-    r#type: B4, // Event type : EventTypes::VECT_BASE_X
-}
 
-// Conversion from Raw event to VectBaseX
-impl From<RawEvent> for RawEventVectBaseX {
-    fn from(raw_event: RawEvent) -> Self {
-        let event = RawEventVectBaseX::new()
-            .with_pol((raw_event.pad() >> 11) as u8)
-            .with_x(raw_event.pad() & 0x7FF)
-            .with_type(raw_event.r#type());
-        event
+    // Pixel coordinate of EVT_ADDR_Y, EVT_ADDR_X and VECT_BASE_X
+    fn coordinate(self) -> i16 {
+        (self.0 & 0x7FF) as i16
     }
-}
-
-#[bitfield]
-struct RawEventVect12 {
-    valid: B12, // Encodes the validity of the events in the vector
-    r#type: B4, // Event type : EventTypes::VECT_12
-}
-
-// Conversion from Raw event to Vect12
-impl From<RawEvent> for RawEventVect12 {
-    fn from(raw_event: RawEvent) -> Self {
-        let event = RawEventVect12::new()
-            .with_valid(raw_event.pad())
-            .with_type(raw_event.r#type());
-        event
-    }
-}
-
-#[bitfield]
-struct RawEventVect8 {
-    valid: B8, // Encodes the validity of the events in the vector
-    unused: B4,
-    r#type: B4, // Event type : EventTypes::VECT_8
-}
-
-// Conversion from Raw event to Vect8
-impl From<RawEvent> for RawEventVect8 {
-    fn from(raw_event: RawEvent) -> Self {
-        let event = RawEventVect8::new()
-            .with_valid((raw_event.pad() & 0xFF) as u8)
-            .with_type(raw_event.r#type());
-        event
-    }
-}
-
-#[bitfield]
-struct RawEventEvtTimeLow {
-    time: B12,
-    r#type: B4,
-}
-
-// Conversion from Raw event to EvtTimeLow
-impl From<RawEvent> for RawEventEvtTimeLow {
-    fn from(raw_event: RawEvent) -> Self {
-        let event = RawEventEvtTimeLow::new()
-            .with_time(raw_event.pad())
-            .with_type(raw_event.r#type());
-        event
-    }
-}
 
-#[bitfield]
-struct RawEventContinued4 {
-    field: B4,
-    r#type: B4,
-}
-
-// Conversion from Raw event to Continued4
-impl From<RawEvent> for RawEventContinued4 {
-    fn from(raw_event: RawEvent) -> Self {
-        let event = RawEventContinued4::new()
-            .with_field((raw_event.pad() & 0x0F) as u8)
-            .with_type(raw_event.r#type());
-        event
+    // Event polarity of EVT_ADDR_X and VECT_BASE_X
+    fn pol(self) -> u8 {
+        ((self.0 >> 11) & 0x1) as u8
     }
-}
-
 
-#[bitfield]
-struct RawEventEvtTimeHigh {
-    time: B12,
-    r#type: B4,
-}
-
-// Conversion from Raw event to EvtTimeHigh
-impl From<RawEvent> for RawEventEvtTimeHigh {
-    fn from(raw_event: RawEvent) -> Self {
-        let event = RawEventEvtTimeHigh::new()
-            .with_time(raw_event.pad())
-            .with_type(raw_event.r#type());
-        event
+    // Trigger channel ID and current value (edge polarity) of EXT_TRIGGER
+    fn trigger(self) -> (u8, u8) {
+        (((self.0 >> 8) & 0x0F) as u8, (self.0 & 0x1) as u8)
     }
 }
 
-#[bitfield]
-struct RawEventExtTrigger {
-    value: B1, // Trigger current value (edge polarity)
-    unused: B7,
-    id: B4,     // Trigger channel ID
-    r#type: B4, // Event type : EventTypes::EXT_TRIGGER
-}
-
 
 struct Metadata {
     sensor_width: usize,
@@ -228,7 +103,7 @@ impl Default for Metadata {
 
 
 pub struct DVSRawDecoderEvt3<R: Read + BufRead + Seek> {
-    reader: BufReader<R>,
+    reader: R,
     pub first_time_base_set: bool,
     pub current_time_base: i64,
     pub current_time_low: i32,
@@ -237,16 +112,88 @@ pub struct DVSRawDecoderEvt3<R: Read + BufRead + Seek> {
     pub current_base_x: i16,
     pub current_polarity: u8,
     pub n_time_high_loop: i64,
-    buffer_read: Vec<[u8; 2]>,
+    // Vectors can't set pixels at or past it. Defaults to the 11 bits of x until read_header
+    sensor_width: i16,
     event_queue: VecDeque<DVSEvent>,
+    // Offset of the first word after the header
+    data_start: u64,
+    // Loaded with use_index, or built by the first seek_to_timestamp
+    index: Option<Vec<IndexEntry>>,
+    // Trigger events decoded since the last take_triggers
+    triggers: Vec<TriggerEvent>,
 }
 
-impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt3<R> {
-    fn new(reader: R) -> Self {
-        let _buffer_read: Vec<u8> = vec![0; std::mem::size_of::<RawEvent>()];
+const MAX_TIMESTAMP_BASE: i64 = ((1i64 << 12) - 1) << 12;
+const TIME_LOOP: i64 = MAX_TIMESTAMP_BASE + (1 << 12);
+const LOOP_THRESHOLD: i64 = 10 << 12;
+
+// Returns the time base of an EVT_TIME_HIGH word following the given time base, counting a loop if the time
+// base wrapped around
+fn next_time_base(current_time_base: i64, n_time_high_loop: &mut i64, time: u16) -> i64 {
+    let mut new_time_base = (time as i64) << 12;
+    new_time_base += *n_time_high_loop * TIME_LOOP;
+
+    if (current_time_base > new_time_base)
+        && (current_time_base - new_time_base
+            >= MAX_TIMESTAMP_BASE - LOOP_THRESHOLD)
+    {
+        *n_time_high_loop += 1;
+        new_time_base += TIME_LOOP;
+    }
+    new_time_base
+}
 
+// Follows the decoder state through words, without decoding events
+#[derive(Default)]
+struct TimeWalker {
+    state: IndexEntry,
+    started: bool,
+}
+
+impl TimeWalker {
+    // Updates the state, returning true for EVT_TIME_HIGH words
+    fn word(&mut self, word: [u8; 2], offset: u64) -> bool {
+        let raw_event = RawEvent::from(word);
+        match EventTypes::from(raw_event.r#type()) {
+            EventTypes::EvtTimeHigh => {
+                let time = raw_event.pad();
+                // Like read_header, the first word sets the time base without looking for a loop
+                self.state.time_base = match self.started {
+                    true => next_time_base(self.state.time_base, &mut self.state.n_time_high_loop, time),
+                    false => (time as i64) << 12,
+                };
+                self.state.offset = offset;
+                self.started = true;
+                true
+            }
+            // read_header skips the words before the first EVT_TIME_HIGH word
+            _ if !self.started => false,
+            EventTypes::EvtAddrY => {
+                self.state.ev_addr_y = raw_event.coordinate();
+                false
+            }
+            EventTypes::VectBaseX => {
+                self.state.polarity = raw_event.pol();
+                self.state.base_x = raw_event.coordinate();
+                false
+            }
+            EventTypes::Vect12 => {
+                self.state.base_x = self.state.base_x.saturating_add(12);
+                false
+            }
+            EventTypes::Vect8 => {
+                self.state.base_x = self.state.base_x.saturating_add(8);
+                false
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<R: Read + BufRead + Seek> DVSRawDecoderEvt3<R> {
+    pub fn new(reader: R) -> Self {
         Self {
-            reader: BufReader::new(reader),
+            reader,
             first_time_base_set: false,
             current_time_base: 0,
             current_time_low: 0,
@@ -255,21 +202,152 @@ impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt3<R> {
             current_base_x: 0,
             current_polarity: 0,
             n_time_high_loop: 0,
-            buffer_read: vec![unsafe { std::mem::zeroed() }],
+            sensor_width: 1 << 11,
             event_queue: VecDeque::new(),
+            data_start: 0,
+            index: None,
+            triggers: Vec::new(),
+        }
+    }
+
+    // Reads the next 16-bit word
+    fn read_word(&mut self) -> Result<[u8; 2]> {
+        let mut word = [0u8; 2];
+        read_exact_or_truncated(&mut self.reader, &mut word)?;
+        Ok(word)
+    }
+
+    // Queues the events of a VECT_12 or VECT_8 word, which sets the pixels from the base x, and moves the base
+    // x past them. A vector may run past the edge of the sensor, but not set pixels there
+    fn push_vector(&mut self, mut valid: u16, length: i16) -> Result<()> {
+        let base_x = self.current_base_x;
+        self.current_base_x = base_x.saturating_add(length);
+        for x in base_x..self.current_base_x {
+            if valid & 0x1 != 0 {
+                if x >= self.sensor_width {
+                    return Err(DvsError::InvalidData(format!("Vector event at x={} past the sensor width of {}", x, self.sensor_width)).into());
+                }
+                self.event_queue.push_back(DVSEvent {
+                    timestamp: self.current_time,
+                    x,
+                    y: self.current_ev_addr_y,
+                    polarity: self.current_polarity,
+                });
+            }
+            valid >>= 1;
+        }
+        Ok(())
+    }
+
+    // Uses an index of the recording for seek_to_timestamp, see index.rs
+    pub fn use_index(&mut self, index: EventIndex) {
+        self.index = Some(index.entries);
+    }
+
+    // Moves to the first EVT_TIME_HIGH word whose events can be at or after the timestamp, so decoding resumes
+    // without skipping any of those events. The events of its time base before the timestamp, up to 4095 us
+    // earlier, are returned too. Seeking past the last event moves to the end of the file. Call read_header
+    // first.
+    // The 24-bit time base loops every 16.7 seconds, so a word alone doesn't tell the time. Without an index,
+    // the first seek indexes the recording in memory, reading its words once without decoding events. Seeks
+    // then look up the last entry before the timestamp and follow the words from there
+    pub fn seek_to_timestamp(&mut self, timestamp: i64) -> Result<()> {
+        if self.index.is_none() {
+            self.index = Some(self.build_index(DEFAULT_INTERVAL_US)?);
         }
+        let entries = self.index.as_deref().unwrap_or_default();
+        let before = |time_base: i64| time_base + 4095 < timestamp;
+        let resume = match entries.partition_point(|entry| before(entry.time_base)) {
+            0 => match entries.first().copied() {
+                Some(entry) => {
+                    self.reader.seek(SeekFrom::Start(entry.offset + 2))?;
+                    Some(entry)
+                }
+                None => None,
+            },
+            next => {
+                let entry = entries[next - 1];
+                self.walk_time_highs(entry.offset + 2, Some(entry), |state| !before(state.time_base))?
+            }
+        };
+
+        self.event_queue.clear();
+        let Some(state) = resume else {
+            self.reader.seek(SeekFrom::End(0))?;
+            return Ok(());
+        };
+        self.first_time_base_set = true;
+        self.current_time_base = state.time_base;
+        self.current_time_low = 0;
+        self.current_time = state.time_base;
+        self.n_time_high_loop = state.n_time_high_loop;
+        self.current_ev_addr_y = state.ev_addr_y;
+        self.current_base_x = state.base_x;
+        self.current_polarity = state.polarity;
+        Ok(())
+    }
+
+    // Indexes the recording in one pass, keeping an entry every interval
+    pub(crate) fn build_index(&mut self, interval_us: i64) -> Result<Vec<IndexEntry>> {
+        let mut builder = IndexBuilder::new(interval_us);
+        self.walk_time_highs(self.data_start, None, |state| {
+            builder.time_high(state);
+            false
+        })?;
+        Ok(builder.entries)
     }
 
+    // Follows the decoder state from the word at the offset, starting from the given state (or from the
+    // start of the events, if None), and calls visit after every EVT_TIME_HIGH word until it returns true.
+    // Returns the state then, with the reader just after the word, or None at the end of the file
+    fn walk_time_highs(&mut self, offset: u64, state: Option<IndexEntry>, mut visit: impl FnMut(&IndexEntry) -> bool) -> Result<Option<IndexEntry>> {
+        let mut walker = TimeWalker { state: state.unwrap_or_default(), started: state.is_some() };
+        let mut offset = self.reader.seek(SeekFrom::Start(offset))?;
+        loop {
+            let buffer = self.reader.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(None);
+            }
+            if buffer.len() < 2 {
+                // A word straddles the end of the buffer
+                let word = self.read_word()?;
+                if walker.word(word, offset) && visit(&walker.state) {
+                    return Ok(Some(walker.state));
+                }
+                offset += 2;
+                continue;
+            }
+            let mut consumed = 0;
+            let mut found = false;
+            for word in buffer.chunks_exact(2) {
+                consumed += 2;
+                if walker.word([word[0], word[1]], offset) && visit(&walker.state) {
+                    found = true;
+                    break;
+                }
+                offset += 2;
+            }
+            self.reader.consume(consumed);
+            if found {
+                return Ok(Some(walker.state));
+            }
+        }
+    }
+}
+
+impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt3<R> {
     // Reads the header of the EVT3 file, extracting metadata and setting the initial time base
     // Returns the header as a vector of strings
-    fn read_header(&mut self) -> anyhow::Result<Vec<String>> {
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
         // Copy header
         let mut header: Vec<String> = Vec::new();
         // Reset the reader to the beginning
         self.reader.seek(SeekFrom::Start(0))?;
         loop {
             let mut line = String::new();
-            self.reader.read_line(&mut line)?;
+            if self.reader.read_line(&mut line)? == 0 {
+                break;
+            }
             // Add line to header
             header.push(line.clone());
             if line.contains("% end") {
@@ -285,72 +363,59 @@ impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt3<R> {
 
         loop {
             self.reader.read_exact(&mut first_char)?;
-            if first_char == ['%' as u8] {
+            if first_char == [b'%'] {
                 // read the rest of the line
                 let mut line = String::new();
                 self.reader.read_line(&mut line)?;
+                let invalid = || DvsError::InvalidHeader { line: format!("%{}", line) };
                 if line == " end\n" {
                     break;
-                } else if line.starts_with(" format ") {
-                    let format_str = &line[8..];
+                } else if let Some(format_str) = line.strip_prefix(" format ") {
                     let mut parts = format_str.split(';');
-                    if parts.next().unwrap() != "EVT3" {
-                        return Ok(header);
+                    if parts.next().unwrap_or_default().trim() != "EVT3" {
+                        return Err(DvsError::UnsupportedFormat("Detected non-EVT3 input file".to_string()));
                     }
                     for option in parts {
-                        let mut kv = option.split('=');
-                        let name = kv.next().unwrap();
-                        let value = kv.next().unwrap();
+                        let (name, value) = option.split_once('=').ok_or_else(invalid)?;
                         if name == "width" {
-                            metadata.sensor_width = value[..value.len() - 1].parse().unwrap();
+                            metadata.sensor_width = value.trim().parse().map_err(|_| invalid())?;
                         } else if name == "height" {
-                            metadata.sensor_height = value.parse().unwrap();
+                            metadata.sensor_height = value.trim().parse().map_err(|_| invalid())?;
                         }
                     }
-                } else if line.starts_with(" geometry ") {
-                    let geometry_str = &line[10..line.len() - 1];
-                    let mut parts = geometry_str.split('x');
-                    metadata.sensor_width = parts.next().unwrap().parse().unwrap();
-                    metadata.sensor_height = parts.next().unwrap().parse().unwrap();
-                } else if line.starts_with(" evt ") {
-                    if &line[5..] != "3.0\n" {
-                        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid file format").into());
-                    }
+                } else if let Some(geometry_str) = line.strip_prefix(" geometry ") {
+                    let (width, height) = geometry_str.trim().split_once('x').ok_or_else(invalid)?;
+                    metadata.sensor_width = width.parse().map_err(|_| invalid())?;
+                    metadata.sensor_height = height.parse().map_err(|_| invalid())?;
+                } else if line.starts_with(" evt ") && line[5..].trim() != "3.0" {
+                    return Err(DvsError::UnsupportedFormat("Detected non-EVT3 input file".to_string()));
                 }
             } else {
                 // Move the reader back one byte if we didn't have the "% end\n" line
-                self.reader.seek(SeekFrom::Current(-1))?;
+                self.reader.seek_relative(-1)?;
                 break;
             }
         }
 
+        self.sensor_width = metadata.sensor_width.min(i16::MAX as usize) as i16;
+        if metadata.sensor_width > 0 && metadata.sensor_height > 0 {
+            tracing::debug!(width = metadata.sensor_width, height = metadata.sensor_height, "sensor geometry");
+        }
 
-        // First, skip any events until we get one of the type EVT_TIME_HIGH
-        loop {
-            self.reader.read_exact(unsafe {
-                std::slice::from_raw_parts_mut(
-                    self.buffer_read.as_mut_ptr() as *mut u8,
-                    std::mem::size_of::<RawEvent>(),
-                )
-            })?;
-
-            // Get two bytes slice
-            let raw_event = RawEvent::from(self.buffer_read[0]);
-            let event_type = EventTypes::from(raw_event.r#type());
-            match event_type {
-                EventTypes::EvtTimeHigh => {
-                    // record the EvtTimeHigh event
-                    let ev_time_high = RawEventEvtTimeHigh::from(raw_event);
-                    // Read next 2 bytes for EVT_Time_Low
-                    let mut time_buf = [0u8; 2];
-                    self.reader.read_exact(&mut time_buf)?;
-                    let ev_time_low = RawEventEvtTimeLow::from(RawEvent::from(time_buf));
-                    self.current_time_base = (ev_time_high.time() as i64) << 12 | ev_time_low.time() as i64;
-                    self.current_time = self.current_time_base;
-                    self.first_time_base_set = true;
-                    break;
-                }
-                _ => {}
+        self.data_start = self.reader.stream_position()?;
+
+        // First, skip any events until we get one of the type EVT_TIME_HIGH. A file without any has no events
+        while !at_end(&mut self.reader)? {
+            let word = self.read_word()?;
+
+            let raw_event = RawEvent::from(word);
+            if let EventTypes::EvtTimeHigh = EventTypes::from(raw_event.r#type()) {
+                // Anchor the time base. The EVT_TIME_LOW word that follows is handled by read_event
+                self.current_time_base = (raw_event.pad() as i64) << 12;
+                self.current_time_low = 0;
+                self.current_time = self.current_time_base;
+                self.first_time_base_set = true;
+                break;
             }
         }
 
@@ -358,119 +423,72 @@ impl<R: Read + BufRead + Seek> DvsRawDecoder<R> for DVSRawDecoderEvt3<R> {
     }
 
     // Reads the next event from the EVT3 file, returning it as a DVSEvent, if possible. Otherwise, it
-    // continues processing events until a DVSEvent can be returned.
-    fn read_event(&mut self) -> Result<Option<DVSEvent>> {
+    // continues processing events until a DVSEvent can be returned. Returns Ok(None) at the end of the file.
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
         if let Some(event) = self.event_queue.pop_front() {
             return Ok(Some(event));
         }
 
-        loop {
+        while !at_end(&mut self.reader)? {
             // Read event
-            self.reader.read_exact(unsafe {
-                std::slice::from_raw_parts_mut(
-                    self.buffer_read.as_mut_ptr() as *mut u8,
-                    std::mem::size_of::<RawEvent>(),
-                )
-            })?;
-
-            let raw_event = RawEvent::from(self.buffer_read[0]);
+            let word = self.read_word()?;
+
+            let raw_event = RawEvent::from(word);
             let event_type = EventTypes::from(raw_event.r#type());
             match event_type {
                 EventTypes::EvtAddrX => {
-                    let ev_addr_x = RawEventEvtAddrX::from(raw_event);
-
                     return Ok(Some(DVSEvent {
                         timestamp: self.current_time,
-                        x: ev_addr_x.x() as i16,
+                        x: raw_event.coordinate(),
                         y: self.current_ev_addr_y,
-                        polarity: ev_addr_x.pol(),
+                        polarity: raw_event.pol(),
                     }));
                 }
                 EventTypes::Vect12 => {
-                    let ev_vect12 = RawEventVect12::from(raw_event);
-                    let end = self.current_base_x + 12;
-                    let mut valid = ev_vect12.valid();
-                    for i in self.current_base_x..end {
-                        if valid & 0x1 != 0 {
-                            self.event_queue.push_back(DVSEvent {
-                                timestamp: self.current_time,
-                                x: i,
-                                y: self.current_ev_addr_y,
-                                polarity: self.current_polarity,
-                            });
-                        }
-                        valid >>= 1;
-                    }
-                    self.current_base_x = end;
+                    self.push_vector(raw_event.pad(), 12)?;
                     if let Some(event) = self.event_queue.pop_front() {
                         return Ok(Some(event));
                     }
                 }
                 EventTypes::Vect8 => {
-                    let ev_vect8 = RawEventVect8::from(raw_event);
-                    let end = self.current_base_x + 8;
-                    let mut valid = ev_vect8.valid();
-                    for i in self.current_base_x..end {
-                        if valid & 0x1 != 0 {
-                            self.event_queue.push_back(DVSEvent {
-                                timestamp: self.current_time,
-                                x: i,
-                                y: self.current_ev_addr_y,
-                                polarity: self.current_polarity,
-                            });
-                        }
-                        valid >>= 1;
-                    }
-                    self.current_base_x = end;
+                    self.push_vector(raw_event.pad() & 0xFF, 8)?;
                     if let Some(event) = self.event_queue.pop_front() {
                         return Ok(Some(event));
                     }
                 }
                 EventTypes::EvtAddrY => {
-                    let ev_addr_y = RawEventEvtAddrY::from(raw_event);
-                    self.current_ev_addr_y = ev_addr_y.y() as i16;
+                    self.current_ev_addr_y = raw_event.coordinate();
                 }
                 EventTypes::VectBaseX => {
-                    let ev_xbase = RawEventVectBaseX::from(raw_event);
-                    self.current_polarity = ev_xbase.pol();
-                    self.current_base_x = ev_xbase.x() as i16;
+                    self.current_polarity = raw_event.pol();
+                    self.current_base_x = raw_event.coordinate();
                 }
                 EventTypes::EvtTimeHigh => {
-                    static MAX_TIMESTAMP_BASE: i64 = ((1i64 << 12) - 1) << 12;
-                    static TIME_LOOP: i64 = MAX_TIMESTAMP_BASE + (1 << 12);
-                    static LOOP_THRESHOLD: i64 = 10 << 12;
-                    let ev_time_high = RawEventEvtTimeHigh::from(raw_event);
-                    let mut new_time_base = (ev_time_high.time() as i64) << 12;
-                    new_time_base += self.n_time_high_loop * TIME_LOOP;
-
-                    if (self.current_time_base > new_time_base)
-                        && (self.current_time_base - new_time_base
-                            >= MAX_TIMESTAMP_BASE - LOOP_THRESHOLD)
-                    {
-                        self.n_time_high_loop += 1;
-                        new_time_base += TIME_LOOP;
-                    }
-
-                    self.current_time_base = new_time_base;
+                    self.current_time_base = next_time_base(self.current_time_base, &mut self.n_time_high_loop, raw_event.pad());
                     self.current_time = self.current_time_base;
                 }
                 EventTypes::EvtTimeLow => {
-                    let ev_time_low = RawEventEvtTimeLow::from(raw_event);
-                    self.current_time_low = ev_time_low.time() as i32;
+                    self.current_time_low = raw_event.pad() as i32;
                     self.current_time = self.current_time_base + self.current_time_low as i64;
                 }
-                EventTypes::Continued4 => {
-
+                EventTypes::ExtTrigger => {
+                    let (id, value) = raw_event.trigger();
+                    self.triggers.push(TriggerEvent { timestamp: self.current_time, id, value });
                 }
-                EventTypes::Continued12 => {
-
-                }
-                _ => {
+                EventTypes::Continued4
+                | EventTypes::Continued12
+                | EventTypes::Others
+                | EventTypes::Unknown => {
+                    // Ignore for now--these words carry no CD events.
                 }
-            }   
+            }
         }
+        Ok(None)
+    }
 
-     }
-
+    // Triggers are kept until they are taken
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        std::mem::take(&mut self.triggers)
+    }
 }
 
@@ -0,0 +1,86 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::raw_decoder_csv::{CsvColumn, CsvOptions, TimeUnit, TIME_UNIT_COMMENT};
+use crate::dvs::{DVSEvent, DvsRawEncoder};
+use std::io::{BufWriter, Seek, Write};
+
+/*
+This file implements an encoder for events stored as delimited text, one line per event.
+The "%" lines of the input header are carried over as "#" comments, followed by a comment recording the
+timestamp unit and a row of column names, so the CSV decoder can read the file back without options.
+*/
+
+pub struct DVSRawEncoderCsv<R: Write + Seek> {
+    writer: BufWriter<R>,
+    options: CsvOptions,
+}
+
+impl<R: Write + Seek> DVSRawEncoderCsv<R> {
+    pub fn new(writer: R) -> Self {
+        Self::with_options(writer, CsvOptions::default())
+    }
+
+    pub fn with_options(writer: R, options: CsvOptions) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            options,
+        }
+    }
+
+    // Formats a timestamp in microseconds in the configured unit, without losing precision. Fractions of negative
+    // timestamps are written after the sign, as "-0.000001"
+    fn format_timestamp(&self, timestamp: i64) -> String {
+        let sign = if timestamp < 0 { "-" } else { "" };
+        let magnitude = timestamp.unsigned_abs();
+        match self.options.time_unit {
+            TimeUnit::Seconds => format!("{}{}.{:06}", sign, magnitude / 1_000_000, magnitude % 1_000_000),
+            TimeUnit::Milliseconds => format!("{}{}.{:03}", sign, magnitude / 1_000, magnitude % 1_000),
+            TimeUnit::Microseconds => timestamp.to_string(),
+            TimeUnit::Nanoseconds => (timestamp * 1_000).to_string(),
+        }
+    }
+}
+
+impl<R: Write + Seek> DvsRawEncoder<R> for DVSRawEncoderCsv<R> {
+    // Writes the input header as comments, the timestamp unit comment and the column row
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        for line in &header {
+            let line = line.trim_end();
+            // Lines of other text formats, e.g. AEDAT's "#!" lines, are not carried over
+            if let Some(comment) = line.strip_prefix('%') {
+                if line != "% end" {
+                    writeln!(self.writer, "#{}", comment)?;
+                }
+            }
+        }
+        writeln!(self.writer, "{} {}", TIME_UNIT_COMMENT, self.options.time_unit.name())?;
+        let names: Vec<&str> = self.options.columns.iter().map(|column| column.name()).collect();
+        writeln!(self.writer, "{}", names.join(&(self.options.delimiter as char).to_string()))?;
+        Ok(())
+    }
+
+    // Writes a DVSEvent as a single line
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        let delimiter = self.options.delimiter as char;
+        let mut line = String::with_capacity(32);
+        for (i, column) in self.options.columns.iter().enumerate() {
+            if i > 0 {
+                line.push(delimiter);
+            }
+            match column {
+                CsvColumn::Timestamp => line.push_str(&self.format_timestamp(event.timestamp)),
+                CsvColumn::X => line.push_str(&event.x.to_string()),
+                CsvColumn::Y => line.push_str(&event.y.to_string()),
+                CsvColumn::Polarity => line.push_str(&event.polarity.to_string()),
+            }
+        }
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        Ok(1)
+    }
+
+    // Flushes the underlying writer
+    fn flush(&mut self) -> Result<(), DvsError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
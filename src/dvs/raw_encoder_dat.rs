@@ -0,0 +1,94 @@
+#![allow(dead_code)]
+
+use crate::dvs::error::DvsError;
+use crate::dvs::raw_decoder_dat::{DAT_EVENT_SIZE_CD, DAT_EVENT_TYPE_CD};
+use crate::dvs::{header_geometry, DVSEvent, DvsRawEncoder};
+use modular_bitfield::bitfield;
+use modular_bitfield::prelude::{B4, B32, B14};
+use std::io::{BufWriter, Seek, Write};
+
+/*
+This file implements a DAT event encoder for Dynamic Vision Sensor (DVS) data streams.
+It writes the "%"-prefixed text header, the event type/size preamble, and one 8-byte little-endian record
+per event: a 32-bit timestamp in microseconds and a 32-bit data word packing x, y and polarity.
+*/
+
+// A bitfield struct representing the raw 64 bits of a CD event in DAT format
+#[bitfield]
+#[derive(Clone)]
+struct RawEvent {
+    timestamp: B32,
+    x: B14,
+    y: B14,
+    polarity: B4,
+}
+
+pub struct DVSRawEncoderDat<R: Write + Seek> {
+    writer: BufWriter<R>,
+}
+
+impl<R: Write + Seek> DVSRawEncoderDat<R> {
+    pub fn new(writer: R) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+        }
+    }
+}
+
+impl<R: Write + Seek> DvsRawEncoder<R> for DVSRawEncoderDat<R> {
+    // Writes the DAT header, carrying over the sensor geometry and any comment lines of the input header,
+    // followed by the event type and size preamble
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        let mut lines: Vec<String> = vec![
+            "% Data file containing CD events.\n".to_string(),
+            "% Version 2\n".to_string(),
+        ];
+        if let Some((width, height)) = header_geometry(&header) {
+            lines.push(format!("% Height {}\n", height));
+            lines.push(format!("% Width {}\n", width));
+        }
+        for line in &header {
+            let line = line.trim_end();
+            let lower = line.to_lowercase();
+            // Skip lines describing the input format, which we have already replaced
+            if !line.starts_with('%')
+                || line == "% end"
+                || ["% evt ", "% format ", "% geometry ", "% height ", "% width ", "% version ", "% data file "]
+                    .iter()
+                    .any(|prefix| lower.starts_with(prefix))
+            {
+                continue;
+            }
+            lines.push(format!("{}\n", line));
+        }
+
+        for line in lines {
+            self.writer.write_all(line.as_bytes())?;
+        }
+        self.writer.write_all(&[DAT_EVENT_TYPE_CD, DAT_EVENT_SIZE_CD])?;
+        Ok(())
+    }
+
+    // Writes a DVSEvent as a single DAT record. Coordinates have 14 bits and timestamps 32, so events outside
+    // them are rejected rather than truncated
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        if !(0..1 << 14).contains(&event.x) || !(0..1 << 14).contains(&event.y) {
+            return Err(DvsError::InvalidData(format!("Event at ({}, {}) out of the DAT coordinate range", event.x, event.y)));
+        }
+        let timestamp = u32::try_from(event.timestamp)
+            .map_err(|_| DvsError::InvalidData(format!("Timestamp {} us out of the 32-bit DAT range", event.timestamp)))?;
+        let raw_event = RawEvent::new()
+            .with_timestamp(timestamp)
+            .with_x(event.x as u16)
+            .with_y(event.y as u16)
+            .with_polarity(event.polarity & 0xF);
+        self.writer.write_all(&raw_event.into_bytes())?;
+        Ok(1)
+    }
+
+    // Flushes the underlying writer
+    fn flush(&mut self) -> Result<(), DvsError> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
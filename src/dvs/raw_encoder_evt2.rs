@@ -1,12 +1,12 @@
-use crate::dvs::DVSEvent;
+
+use crate::dvs::error::DvsError;
+use crate::dvs::{rewrite_raw_header, DVSEvent, TriggerEvent};
 use crate::dvs::DvsRawEncoder;
-use modular_bitfield::bitfield;
-use modular_bitfield::prelude::{B28, B4, B11, B6};
 use std::io::{BufWriter, Write, Seek};
 
 /* 
 This file implements an EVT2 raw event encoder for Dynamic Vision Sensor (DVS) data streams.
-It provides types and logic to parse a vector of DVSRaWEvents into an EVT2-formatted event file, extract sensor metadata, and decode individual events.
+It provides types and logic to parse a vector of DVSEvents into an EVT2-formatted event file, extract sensor metadata, and decode individual events.
 */
 
 // An enum representing the possible event types in EVT2 streams:
@@ -18,158 +18,112 @@ enum EventTypes {
     ExtTrigger = 0xA,   // External trigger event
 }
 
-impl From<u8> for EventTypes {
-    fn from(value: u8) -> Self {
-        match value {
-            0x0 => EventTypes::CdOff,
-            0x1 => EventTypes::CdOn,
-            0x8 => EventTypes::EvtTimeHigh,
-            0xA => EventTypes::ExtTrigger,
-            _ => EventTypes::ExtTrigger,
-        }
-    }
-}
-
-// A bitfield struct representing the raw 32 bits of an event in EVT2 format
-#[bitfield]
-#[derive(Clone)]
-struct RawEvent {
-    r#type: B4,
-    pad: B28,
+// The raw 32 bits of an event in EVT2 format, little-endian, with the event type in the top 4 bits
+fn raw_event(r#type: EventTypes, pad: u32) -> [u8; 4] {
+    ((r#type as u32) << 28 | (pad & 0x0FFF_FFFF)).to_le_bytes()
 }
 
-// A bitfield struct for EVT_TIME_HIGH events, which contain a timestamp
-#[bitfield]
-struct RawEventTime {
-    r#type: B4,     // Event type
-    timestamp: B28  // Event timestamp
-}
 
-// A bitfield struct for Change Detection events, which contain pixel coordinates, polarity, and timestamp
-#[bitfield]
-struct RawEventCD {
-    r#type: B4,     // Event type
-    timestamp: B6,  // Event timestamp
-    x: B11,         // Pixel X coordinate
-    y: B11,         // Pixel Y coordinate
+// Time base state of an EVT2 stream, writing the words of events and triggers to any writer. Shared with the
+// asynchronous encoder, which writes them to a buffer
+#[derive(Default)]
+pub(crate) struct Evt2Words {
+    first_timehigh_written: bool,
+    ts_last_timehigh: i64,
 }
 
-// Conversion from RawEventCD to RawEvent
-impl From<RawEventCD> for RawEvent {
-    fn from(event: RawEventCD) -> Self {
-        let event_cd = RawEvent::new()
-            .with_pad((event.timestamp() as u32) << 22 | (event.x() as u32) << 11 | (event.y() as u32))
-            .with_type(event.r#type());
-        event_cd
+impl Evt2Words {
+    // Writes a Time High event if the event at the timestamp is in another 64 us time base than the last one
+    // written, returning the number of words written. Events sharing a time base share its Time High event
+    fn write_time_high<W: Write>(&mut self, writer: &mut W, timestamp: i64) -> std::io::Result<usize> {
+        let time_base = timestamp & !0x3F; // Get the upper 28 bits of the event's timestamp
+        if self.first_timehigh_written && time_base == self.ts_last_timehigh {
+            return Ok(0);
+        }
+        self.first_timehigh_written = true;
+        self.ts_last_timehigh = time_base;
+        // Generate a Time High Event. Time High words hold 28 bits, so timestamps past 2^34 us (e.g. epoch-based
+        // ones) wrap around
+        let raw_time_event = raw_event(EventTypes::EvtTimeHigh, ((self.ts_last_timehigh >> 6) & 0xFFFFFFF) as u32);
+        writer.write_all(&raw_time_event)?;
+        Ok(1)
     }
-}
 
-// Conversion from RawEventTime to RawEvent
-impl From<RawEventTime> for RawEvent {
-    fn from(event: RawEventTime) -> Self {
-        let event_time = RawEvent::new()
-            .with_pad(event.timestamp() as u32)
-            .with_type(event.r#type());
-        event_time
+    // Writes a DVSEvent as a CD word after the Time High word of its time base
+    pub(crate) fn write_event<W: Write>(&mut self, writer: &mut W, event: DVSEvent) -> Result<usize, DvsError> {
+        // Coordinates have 11 bits
+        if !(0..1 << 11).contains(&event.x) || !(0..1 << 11).contains(&event.y) {
+            return Err(DvsError::InvalidData(format!("Event at ({}, {}) out of the EVT2 coordinate range", event.x, event.y)));
+        }
+        let mut events_written = self.write_time_high(writer, event.timestamp)?;
+
+        // Then, write the CD Event
+        // Determine event type and polarity
+        let event_type = match event.polarity {
+            0 => EventTypes::CdOff,
+            1 => EventTypes::CdOn,
+            _ => EventTypes::CdOff, // Default or error handling
+        };
+        // Write just the lower 6 bits of the timestamp as part of the CD Event
+        let timestamp_low = (event.timestamp & 0x3F) as u8;
+        let raw_event_cd = raw_event(event_type, (timestamp_low as u32) << 22 | (event.x as u32) << 11 | event.y as u32);
+        writer.write_all(&raw_event_cd)?;
+        events_written+=1;
+
+        Ok(events_written)
     }
-}
 
-// Conversion from RawEvent to a byte array for writing to the EVT2 file
-impl From<RawEvent> for [u8; 4] {
-    fn from(event: RawEvent) -> Self {
-        let mut value = [0u8; 4];
-        value[0] = (event.pad() & 0xFF) as u8;
-        value[1] = ((event.pad() >> 8) & 0xFF) as u8;
-        value[2] = ((event.pad() >> 16) & 0xFF) as u8;
-        value[3] = ((event.pad() >> 24) & 0x0F) as u8 | (event.r#type() << 4) as u8;
-        value
+    // Writes an EXT_TRIGGER word after the Time High event of its timestamp. The channel is in bits 8 to 12 and
+    // the value in bit 0
+    pub(crate) fn write_trigger<W: Write>(&mut self, writer: &mut W, trigger: TriggerEvent) -> Result<usize, DvsError> {
+        let events_written = self.write_time_high(writer, trigger.timestamp)?;
+        let raw_trigger = raw_event(EventTypes::ExtTrigger, ((trigger.timestamp & 0x3F) as u32) << 22 | ((trigger.id & 0x1F) as u32) << 8 | (trigger.value & 0x1) as u32);
+        writer.write_all(&raw_trigger)?;
+        Ok(events_written + 1)
     }
 }
 
+// The header lines of an EVT2 file, rewriting the format lines to describe EVT2 data
+pub(crate) fn evt2_header(header: Vec<String>) -> Vec<String> {
+    rewrite_raw_header(header, "EVT2", "2.0")
+}
 
 pub struct DVSRawEncoderEvt2<R: Write + Seek> {
     writer: BufWriter<R>,
-    first_timehigh_written: bool,
-    ts_last_timehigh: i64,
+    words: Evt2Words,
 }
 
-impl<R: Write + Seek> DvsRawEncoder<R> for DVSRawEncoderEvt2<R> {
-    fn new(writer: R) -> Self {
-        let _buffer_write: Vec<u8> = vec![0; std::mem::size_of::<RawEvent>()];
-
+impl<R: Write + Seek> DVSRawEncoderEvt2<R> {
+    pub fn new(writer: R) -> Self {
         Self {
             writer: BufWriter::new(writer),
-            first_timehigh_written: false,
-            ts_last_timehigh: 0,
+            words: Evt2Words::default(),
         }
     }
+}
 
-    // Writes the header to the EVT2 file, including sensor metadata and initial timestamp
-    fn write_header(&mut self, header: Vec<String>) -> anyhow::Result<()> {
-        let writer = self.writer.get_mut();
-        for line in header {
-            let buf = line.as_bytes();
-            let _res = writer.write_all(buf);
+impl<R: Write + Seek> DvsRawEncoder<R> for DVSRawEncoderEvt2<R> {
+    // Writes the header to the EVT2 file, rewriting the format lines to describe EVT2 data
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        for line in evt2_header(header) {
+            self.writer.write_all(line.as_bytes())?;
         }
 
         Ok(())
     }
 
-    // Writes a DVSRawEvent to the EVT2 file, converting it to the appropriate RawEvent format
-    fn write_event(&mut self, event: DVSEvent) -> anyhow::Result<u8> {
-        let mut events_written: u8 = 0;
-        // If necessary, write a Time High event
-        // if we haven't generated any time high events yet 
-        if !self.first_timehigh_written {
-            self.first_timehigh_written = true;
-            self.ts_last_timehigh = event.timestamp & !0x3F; // Get the upper 28 bits of the event's timestamp
-            // Generate a Time High Event with the same timestamp as the first CD event in the stream
-            let raw_time_event = RawEventTime::new()
-                .with_timestamp((self.ts_last_timehigh >> 6) as u32)
-                .with_type(EventTypes::EvtTimeHigh as u8);
-            // Convert to RawEvent
-            let raw_event = RawEvent::from(raw_time_event);
-            // Convert to bytes and write
-            self.writer.write_all(&<[u8; 4]>::from(raw_event))?;
-            events_written+=1;
-        } else {
-            // Find the timestamp of a time high event just before the CD event we are trying to write
-            while (self.ts_last_timehigh) < (event.timestamp & !0x3F) {
-                // Increment the Time High Timestamp
-                self.ts_last_timehigh = self.ts_last_timehigh + 0x40;
-            }
-            // Generate a Time High Event
-            let raw_time_event = RawEventTime::new()
-                .with_timestamp((self.ts_last_timehigh >> 6) as u32)
-                .with_type(EventTypes::EvtTimeHigh as u8);
-            // Convert to RawEvent
-            let raw_event = RawEvent::from(raw_time_event);
-            // Convert to bytes and write
-            self.writer.write_all(&<[u8; 4]>::from(raw_event))?;
-            events_written+=1;
-        }
+    // Writes a DVSEvent to the EVT2 file, see Evt2Words::write_event
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        self.words.write_event(&mut self.writer, event)
+    }
 
-        // Then, write the CD Event
-        // Determine event type and polarity
-        let event_type = match event.polarity {
-            0 => EventTypes::CdOff,
-            1 => EventTypes::CdOn,
-            _ => EventTypes::CdOff, // Default or error handling
-        };
-        // Write just the lower 6 bits of the timestamp as part of the CD Event
-        let timestamp_low = (event.timestamp & 0x3F) as u8;
-        let raw_event_cd = RawEventCD::new()
-            .with_x(event.x as u16)
-            .with_y(event.y as u16)
-            .with_timestamp(timestamp_low)
-            .with_type(event_type as u8);
-
-        // Convert to RawEvent
-        let raw_event = RawEvent::from(raw_event_cd);
-        // Convert to bytes and write
-        self.writer.write_all(&<[u8; 4]>::from(raw_event))?;
-        events_written+=1;
+    fn write_trigger(&mut self, trigger: TriggerEvent) -> Result<usize, DvsError> {
+        self.words.write_trigger(&mut self.writer, trigger)
+    }
 
-        Ok(events_written)
+    // Flushes the underlying writer
+    fn flush(&mut self) -> Result<(), DvsError> {
+        self.writer.flush()?;
+        Ok(())
     }
 }
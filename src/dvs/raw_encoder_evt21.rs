@@ -0,0 +1,152 @@
+#![allow(dead_code)]
+
+use crate::dvs::error::DvsError;
+use crate::dvs::{rewrite_raw_header, DVSEvent};
+use crate::dvs::DvsRawEncoder;
+use modular_bitfield::bitfield;
+use modular_bitfield::prelude::{B11, B28, B32, B4, B6};
+use std::io::{BufWriter, Write, Seek};
+
+/*
+This file implements an EVT2.1 raw event encoder for Dynamic Vision Sensor (DVS) data streams.
+Consecutive events sharing a timestamp, row and polarity within the same 32-column block are merged into
+one 64-bit CD word with a mask of the valid pixels. A Time High word is written whenever the upper bits of
+the timestamp change.
+*/
+
+// An enum representing the possible event types in EVT2.1 streams:
+#[derive(Debug, Clone, Copy)]
+enum EventTypes {
+    CdOff = 0x0,        // Change Detection vector, polarity off.
+    CdOn = 0x1,         // Change Detection vector, polarity on.
+    EvtTimeHigh = 0x8,  // EVT_TIME_HIGH event, used for timestamp synchronization.
+    ExtTrigger = 0xA,   // External trigger event
+}
+
+// A bitfield struct for EVT_TIME_HIGH events, which contain a timestamp
+#[bitfield]
+struct RawEventTime {
+    unused: B32,
+    timestamp: B28, // Event timestamp
+    r#type: B4,     // Event type
+}
+
+// A bitfield struct for Change Detection vectors, which contain the first pixel's coordinates, a mask of
+// the valid pixels, polarity and timestamp
+#[bitfield]
+struct RawEventCD {
+    valid: B32,    // Bit i set if the pixel at x + i has an event
+    y: B11,        // Pixel Y coordinate
+    x: B11,        // Pixel X coordinate of the first bit of the mask
+    timestamp: B6, // Event timestamp
+    r#type: B4,    // Event type
+}
+
+// A CD vector being filled with events
+struct Vector {
+    timestamp: i64,
+    // First column of the 32-column block
+    x: u16,
+    y: u16,
+    polarity: u8,
+    valid: u32,
+}
+
+pub struct DVSRawEncoderEvt21<R: Write + Seek> {
+    writer: BufWriter<R>,
+    // Upper bits of the timestamp in the last Time High word, if any was written
+    ts_last_timehigh: Option<i64>,
+    vector: Option<Vector>,
+}
+
+impl<R: Write + Seek> DVSRawEncoderEvt21<R> {
+    pub fn new(writer: R) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            ts_last_timehigh: None,
+            vector: None,
+        }
+    }
+
+    // Writes the pending CD vector, preceded by a Time High word if needed. Returns the number of words written
+    fn write_vector(&mut self) -> anyhow::Result<usize> {
+        let Some(vector) = self.vector.take() else {
+            return Ok(0);
+        };
+        let mut words_written = 0;
+        let time_high = vector.timestamp & !0x3F;
+        if self.ts_last_timehigh != Some(time_high) {
+            self.ts_last_timehigh = Some(time_high);
+            // Time High words hold 28 bits, so timestamps past 2^34 us (e.g. epoch-based ones) wrap around
+            let raw_time_event = RawEventTime::new()
+                .with_timestamp(((time_high >> 6) & 0xFFFFFFF) as u32)
+                .with_type(EventTypes::EvtTimeHigh as u8);
+            self.writer.write_all(&raw_time_event.into_bytes())?;
+            words_written += 1;
+        }
+
+        let event_type = match vector.polarity {
+            1 => EventTypes::CdOn,
+            _ => EventTypes::CdOff,
+        };
+        let raw_event_cd = RawEventCD::new()
+            .with_valid(vector.valid)
+            .with_y(vector.y)
+            .with_x(vector.x)
+            .with_timestamp((vector.timestamp & 0x3F) as u8)
+            .with_type(event_type as u8);
+        self.writer.write_all(&raw_event_cd.into_bytes())?;
+        words_written += 1;
+        Ok(words_written)
+    }
+}
+
+impl<R: Write + Seek> DvsRawEncoder<R> for DVSRawEncoderEvt21<R> {
+    // Writes the header to the EVT2.1 file, rewriting the format lines to describe EVT2.1 data
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        for line in rewrite_raw_header(header, "EVT21", "2.1") {
+            self.writer.write_all(line.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    // Adds a DVSEvent to the pending CD vector, writing the vector first if the event doesn't fit in it.
+    // Returns the number of words written
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        // Coordinates have 11 bits
+        if !(0..1 << 11).contains(&event.x) || !(0..1 << 11).contains(&event.y) {
+            return Err(DvsError::InvalidData(format!("Event at ({}, {}) out of the EVT2.1 coordinate range", event.x, event.y)));
+        }
+        let (x, y) = (event.x as u16, event.y as u16);
+        let polarity = u8::from(event.polarity == 1);
+        let bit = 1u32 << (x % 32);
+        if let Some(vector) = &mut self.vector {
+            if vector.timestamp == event.timestamp
+                && vector.y == y
+                && vector.polarity == polarity
+                && vector.x == x - x % 32
+                && vector.valid & bit == 0
+            {
+                vector.valid |= bit;
+                return Ok(0);
+            }
+        }
+        let words_written = self.write_vector()?;
+        self.vector = Some(Vector {
+            timestamp: event.timestamp,
+            x: x - x % 32,
+            y,
+            polarity,
+            valid: bit,
+        });
+        Ok(words_written)
+    }
+
+    // Writes the pending CD vector and flushes the underlying writer
+    fn flush(&mut self) -> Result<(), DvsError> {
+        self.write_vector()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,199 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::{rewrite_raw_header, DVSEvent};
+use crate::dvs::DvsRawEncoder;
+use std::io::{BufWriter, Write, Seek};
+
+/*
+This file implements an EVT3 raw event encoder for Dynamic Vision Sensor (DVS) data streams.
+Events that share a timestamp, row and polarity are buffered and written as vectorized VECT_12/VECT_8 words,
+so dense rows take a fraction of the space of one EVT_ADDR_X word per event. Repeated events at the same pixel
+can't share a vector, and the repeats are written as EVT_ADDR_X words after it.
+*/
+
+// An enum representing the event types written to EVT3 streams
+#[derive(Debug, Clone, Copy)]
+enum EventTypes {
+    EvtAddrY = 0x0,
+    EvtAddrX = 0x2,
+    VectBaseX = 0x3,
+    Vect12 = 0x4,
+    Vect8 = 0x5,
+    EvtTimeLow = 0x6,
+    EvtTimeHigh = 0x8,
+}
+
+// Number of pixels covered by the vector words
+const VECT_12_WIDTH: i16 = 12;
+const VECT_8_WIDTH: i16 = 8;
+
+pub struct DVSRawEncoderEvt3<R: Write + Seek> {
+    writer: BufWriter<R>,
+    // Decoder state implied by the words written so far
+    current_time: Option<i64>,
+    current_y: Option<i16>,
+    current_base_x: Option<i16>,
+    current_polarity: u8,
+    // Events sharing a timestamp, row and polarity that have not been written yet
+    pending: Vec<DVSEvent>,
+}
+
+impl<R: Write + Seek> DVSRawEncoderEvt3<R> {
+    pub fn new(writer: R) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            current_time: None,
+            current_y: None,
+            current_base_x: None,
+            current_polarity: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    // Writes a single 16-bit word, little-endian, with the event type in the top 4 bits
+    fn write_word(&mut self, r#type: EventTypes, pad: u16) -> anyhow::Result<()> {
+        self.writer.write_all(&((r#type as u16) << 12 | (pad & 0xFFF)).to_le_bytes())?;
+        Ok(())
+    }
+
+    // Writes the time and row words needed before the pending events. Returns the number of words written
+    fn write_state(&mut self, timestamp: i64, y: i16) -> anyhow::Result<usize> {
+        let mut words_written = 0;
+        let time_high = (timestamp >> 12) & 0xFFF;
+        let time_low = timestamp & 0xFFF;
+        let new_time_high = self.current_time.is_none_or(|t| t >> 12 != timestamp >> 12);
+        if new_time_high {
+            // Decoders count a wraparound of the 24-bit time when a time base near the end of the loop is followed
+            // by one near its start, as cameras write one every 4096 us. Across gaps, write the last and first
+            // time bases of each loop crossed, so the wraparounds aren't missed. Decoders start in the first loop, so
+            // the loops before the first event are written too
+            let loops = (timestamp >> 24) - self.current_time.map_or(0, |t| t >> 24);
+            for _ in 0..loops {
+                self.write_word(EventTypes::EvtTimeHigh, 0xFFF)?;
+                self.write_word(EventTypes::EvtTimeHigh, 0)?;
+                words_written += 2;
+            }
+            self.write_word(EventTypes::EvtTimeHigh, time_high as u16)?;
+            words_written += 1;
+        }
+        if new_time_high || self.current_time != Some(timestamp) {
+            self.write_word(EventTypes::EvtTimeLow, time_low as u16)?;
+            words_written += 1;
+        }
+        self.current_time = Some(timestamp);
+
+        if self.current_y != Some(y) {
+            // The system type bit is left clear
+            self.write_word(EventTypes::EvtAddrY, y as u16)?;
+            self.current_y = Some(y);
+            words_written += 1;
+        }
+        Ok(words_written)
+    }
+
+    // Writes all pending events, which share a timestamp, row and polarity. Returns the number of words written
+    fn write_pending(&mut self) -> anyhow::Result<usize> {
+        if self.pending.is_empty() {
+            return Ok(0);
+        }
+        let mut pending = std::mem::take(&mut self.pending);
+        let first = pending[0];
+        let mut words_written = self.write_state(first.timestamp, first.y)?;
+
+        pending.sort_unstable_by_key(|event| event.x);
+        // Each pixel takes one bit of a vector, so repeats are kept for address words
+        let mut repeats = Vec::new();
+        pending.dedup_by(|event, previous| {
+            let repeat = event.x == previous.x;
+            if repeat {
+                repeats.push(event.x);
+            }
+            repeat
+        });
+        let polarity = first.polarity;
+
+        let mut i = 0;
+        while i < pending.len() {
+            let x = pending[i].x;
+            // Reuse the current vector base if this event falls within the next vector
+            let base = match self.current_base_x {
+                Some(base) if self.current_polarity == polarity && x >= base && x < base + VECT_12_WIDTH => base,
+                _ => x,
+            };
+            let in_window = pending[i..].iter().take_while(|event| event.x < base + VECT_12_WIDTH).count();
+
+            if in_window == 1 && base == x {
+                // A lone event is cheapest as a single address word
+                self.write_word(EventTypes::EvtAddrX, x as u16 | (polarity as u16 & 0x1) << 11)?;
+                words_written += 1;
+                i += 1;
+                continue;
+            }
+
+            if self.current_base_x != Some(base) || self.current_polarity != polarity {
+                self.write_word(EventTypes::VectBaseX, base as u16 | (polarity as u16 & 0x1) << 11)?;
+                self.current_polarity = polarity;
+                words_written += 1;
+            }
+
+            let mut valid: u16 = 0;
+            for event in &pending[i..i + in_window] {
+                valid |= 1 << (event.x - base);
+            }
+            if valid >> VECT_8_WIDTH == 0 {
+                self.write_word(EventTypes::Vect8, valid)?;
+                self.current_base_x = Some(base + VECT_8_WIDTH);
+            } else {
+                self.write_word(EventTypes::Vect12, valid)?;
+                self.current_base_x = Some(base + VECT_12_WIDTH);
+            }
+            words_written += 1;
+            i += in_window;
+        }
+        for x in repeats {
+            self.write_word(EventTypes::EvtAddrX, x as u16 | (polarity as u16 & 0x1) << 11)?;
+            words_written += 1;
+        }
+        Ok(words_written)
+    }
+}
+
+impl<R: Write + Seek> DvsRawEncoder<R> for DVSRawEncoderEvt3<R> {
+    // Writes the header to the EVT3 file, rewriting the format lines to describe EVT3 data
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        for line in rewrite_raw_header(header, "EVT3", "3.0") {
+            self.writer.write_all(line.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    // Buffers a DVSEvent until an event with a different timestamp, row or polarity arrives, then
+    // writes the buffered events. Returns the number of words written
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        // Coordinates have 11 bits
+        if !(0..1 << 11).contains(&event.x) || !(0..1 << 11).contains(&event.y) {
+            return Err(DvsError::InvalidData(format!("Event at ({}, {}) out of the EVT3 coordinate range", event.x, event.y)));
+        }
+        let mut words_written = 0;
+        if let Some(last) = self.pending.last() {
+            if last.timestamp != event.timestamp || last.y != event.y || last.polarity != event.polarity {
+                words_written = self.write_pending()?;
+            }
+        }
+        self.pending.push(event);
+        Ok(words_written)
+    }
+
+    // Writes any buffered events and flushes the underlying writer
+    fn flush(&mut self) -> Result<(), DvsError> {
+        self.write_pending()?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<R: Write + Seek> Drop for DVSRawEncoderEvt3<R> {
+    fn drop(&mut self) {
+        // Best effort: errors can't be reported from drop, call flush() to handle them
+        let _ = self.write_pending();
+    }
+}
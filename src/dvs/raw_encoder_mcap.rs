@@ -0,0 +1,213 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::mcap::McapWriter;
+use crate::dvs::{header_geometry, DVSEvent, DvsRawEncoder};
+use std::collections::BTreeMap;
+use std::io::{BufWriter, Seek, Write};
+
+/*
+This file implements an encoder that writes events to an MCAP file, e.g. for scrubbing in Foxglove Studio.
+Events are grouped into dvs_msgs/msg/EventArray messages covering a fixed time window, serialized as CDR,
+and written to one topic. Messages are stored in time-indexed chunks, so readers can seek by time.
+With the "ros2" profile (the ros feature's rosbag2 format), the file is a ROS 2 bag in MCAP storage,
+which can be played with "ros2 bag play <file>.mcap".
+*/
+
+// Definition of dvs_msgs/msg/EventArray and its dependencies, in the ros2msg schema encoding
+const EVENT_ARRAY_SCHEMA: &str = "\
+std_msgs/Header header
+uint32 height
+uint32 width
+dvs_msgs/Event[] events
+================================================================================
+MSG: std_msgs/Header
+builtin_interfaces/Time stamp
+string frame_id
+================================================================================
+MSG: builtin_interfaces/Time
+int32 sec
+uint32 nanosec
+================================================================================
+MSG: dvs_msgs/Event
+uint16 x
+uint16 y
+builtin_interfaces/Time ts
+bool polarity
+";
+
+#[derive(Debug, Clone)]
+pub struct McapOptions {
+    // MCAP profile, e.g. "ros2" for ROS 2 bags, or empty
+    pub profile: String,
+    pub topic: String,
+    pub frame_id: String,
+    // Time covered by each EventArray message, in microseconds
+    pub message_window_us: i64,
+    // Time covered by each indexed chunk, in microseconds, or None to write messages outside chunks
+    pub chunk_window_us: Option<i64>,
+}
+
+impl Default for McapOptions {
+    fn default() -> Self {
+        McapOptions {
+            profile: String::new(),
+            topic: "/dvs/events".to_string(),
+            frame_id: "camera".to_string(),
+            message_window_us: 10_000,
+            chunk_window_us: Some(1_000_000),
+        }
+    }
+}
+
+impl McapOptions {
+    // Options for a ROS 2 bag in MCAP storage
+    #[cfg(feature = "ros")]
+    pub fn rosbag2() -> Self {
+        McapOptions {
+            profile: "ros2".to_string(),
+            ..Self::default()
+        }
+    }
+}
+
+// Serializes fields as little-endian CDR. Fields are aligned to their size, counted from after the
+// 4-byte encapsulation header
+struct CdrWriter {
+    buf: Vec<u8>,
+}
+
+impl CdrWriter {
+    fn new() -> Self {
+        // CDR_LE encapsulation
+        CdrWriter { buf: vec![0x00, 0x01, 0x00, 0x00] }
+    }
+
+    fn align(&mut self, size: usize) {
+        while !(self.buf.len() - 4).is_multiple_of(size) {
+            self.buf.push(0);
+        }
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.align(2);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.buf.push(value as u8);
+    }
+
+    // Strings are a length including the terminating NUL, the bytes and the NUL
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32 + 1);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    // builtin_interfaces/Time from a timestamp in microseconds
+    fn time(&mut self, timestamp: i64) {
+        self.i32(timestamp.div_euclid(1_000_000) as i32);
+        self.u32((timestamp.rem_euclid(1_000_000) * 1_000) as u32);
+    }
+}
+
+pub struct DVSRawEncoderMcap<R: Write + Seek> {
+    mcap: McapWriter<BufWriter<R>>,
+    options: McapOptions,
+    channel_id: u16,
+    sequence: u32,
+    width: u32,
+    height: u32,
+    // Events of the message being built, and the start of its window
+    packet: Vec<DVSEvent>,
+    packet_start: Option<i64>,
+}
+
+impl<R: Write + Seek> DVSRawEncoderMcap<R> {
+    pub fn new(writer: R) -> Self {
+        Self::with_options(writer, McapOptions::default())
+    }
+
+    pub fn with_options(writer: R, options: McapOptions) -> Self {
+        let writer = BufWriter::new(writer);
+        let mcap = match options.chunk_window_us {
+            Some(window_us) => McapWriter::with_chunk_window(writer, window_us.max(1) as u64 * 1_000),
+            None => McapWriter::new(writer),
+        };
+        Self {
+            mcap,
+            options,
+            channel_id: 0,
+            sequence: 0,
+            width: 0,
+            height: 0,
+            packet: Vec::new(),
+            packet_start: None,
+        }
+    }
+
+    // Writes the buffered events as an EventArray message
+    fn write_packet(&mut self) -> anyhow::Result<()> {
+        let Some(start) = self.packet_start.take() else { return Ok(()) };
+        let mut cdr = CdrWriter::new();
+        cdr.time(start);
+        cdr.string(&self.options.frame_id);
+        cdr.u32(self.height);
+        cdr.u32(self.width);
+        cdr.u32(self.packet.len() as u32);
+        for event in &self.packet {
+            cdr.u16(event.x as u16);
+            cdr.u16(event.y as u16);
+            cdr.time(event.timestamp);
+            cdr.bool(event.polarity != 0);
+        }
+
+        let log_time = start.max(0) as u64 * 1_000;
+        self.mcap.write_message(self.channel_id, self.sequence, log_time, log_time, &cdr.buf)?;
+        self.sequence += 1;
+        self.packet.clear();
+        Ok(())
+    }
+}
+
+impl<R: Write + Seek> DvsRawEncoder<R> for DVSRawEncoderMcap<R> {
+    // Starts the file, with the sensor geometry of the input header in the messages
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        if let Some((width, height)) = header_geometry(&header) {
+            self.width = width;
+            self.height = height;
+        }
+        self.mcap.start(&self.options.profile)?;
+        let schema_id = self.mcap.add_schema("dvs_msgs/msg/EventArray", "ros2msg", EVENT_ARRAY_SCHEMA.as_bytes())?;
+        self.channel_id = self.mcap.add_channel(schema_id, &self.options.topic, "cdr", &BTreeMap::new())?;
+        Ok(())
+    }
+
+    // Adds a DVSEvent to the current message, first writing the message if the event is past its window.
+    // Returns the number of messages written
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        let mut messages_written = 0;
+        if self.packet_start.is_some_and(|start| event.timestamp >= start + self.options.message_window_us) {
+            self.write_packet()?;
+            messages_written = 1;
+        }
+        self.packet_start.get_or_insert(event.timestamp);
+        self.packet.push(event);
+        Ok(messages_written)
+    }
+
+    // Writes the last message and closes the file. No events can be written afterwards
+    fn flush(&mut self) -> Result<(), DvsError> {
+        self.write_packet()?;
+        Ok(self.mcap.finish()?)
+    }
+}
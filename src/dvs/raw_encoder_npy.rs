@@ -0,0 +1,204 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::{DVSEvent, DvsRawEncoder};
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+
+/*
+This file implements an encoder that writes events as a NumPy structured array, for post-processing in Python.
+The array has dtype [('t', '<u8'), ('x', '<u2'), ('y', '<u2'), ('p', 'u1')], 13 packed bytes per event, and
+can be loaded with numpy.load. Writing .npy streams the events and patches the array length into the header
+on flush. Writing .npz stores the array as "events.npy" in an uncompressed zip archive. The archive's CRC
+covers the array header, so events are kept in memory and the archive is written on flush.
+*/
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY\x01\x00";
+// Header lengths (magic, version, header length and header text) are padded to a multiple of this
+const NPY_HEADER_ALIGNMENT: usize = 64;
+const NPY_RECORD_SIZE: usize = 13;
+// Name of the array in .npz archives
+const NPZ_ARRAY_NAME: &str = "events.npy";
+
+// Zip record signatures
+const ZIP_LOCAL_FILE_HEADER: u32 = 0x04034b50;
+const ZIP_CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const ZIP_END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+const ZIP_VERSION: u16 = 20;
+// MS-DOS date of 1980-01-01, the earliest representable date
+const ZIP_DATE: u16 = 0x21;
+
+// CRC-32 lookup table (IEEE polynomial, as used by zip)
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+// Continues a CRC-32 over more bytes, starting from 0
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!crc, |crc, byte| CRC32_TABLE[((crc ^ *byte as u32) & 0xFF) as usize] ^ (crc >> 8))
+}
+
+// The .npy header for an array of count events. The count is padded to a fixed width, so the header
+// length doesn't change when it is rewritten with the final count
+fn npy_header(count: u64) -> Vec<u8> {
+    npy_array_header("[('t', '<u8'), ('x', '<u2'), ('y', '<u2'), ('p', 'u1')]", count, &[])
+}
+
+// The .npy header for a C-order array of the given dtype, with count rows of the given inner shape. The count is
+// padded as in npy_header
+pub(crate) fn npy_array_header(descr: &str, count: u64, inner_shape: &[usize]) -> Vec<u8> {
+    let shape = match inner_shape {
+        [] => format!("{:>20},", count),
+        _ => format!("{:>20}, {}", count, inner_shape.iter().map(|n| n.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let text = format!("{{'descr': {}, 'fortran_order': False, 'shape': ({}), }}", descr, shape);
+    let unpadded = NPY_MAGIC.len() + 2 + text.len() + 1;
+    let padding = (NPY_HEADER_ALIGNMENT - unpadded % NPY_HEADER_ALIGNMENT) % NPY_HEADER_ALIGNMENT;
+    let header_len = text.len() + padding + 1;
+
+    let mut header = NPY_MAGIC.to_vec();
+    header.extend_from_slice(&(header_len as u16).to_le_bytes());
+    header.extend_from_slice(text.as_bytes());
+    header.extend(std::iter::repeat_n(b' ', padding));
+    header.push(b'\n');
+    header
+}
+
+// Packs an event as a record of the structured array. Negative timestamps and coordinates wrap around
+fn npy_record(event: &DVSEvent) -> [u8; NPY_RECORD_SIZE] {
+    let mut record = [0u8; NPY_RECORD_SIZE];
+    record[..8].copy_from_slice(&(event.timestamp as u64).to_le_bytes());
+    record[8..10].copy_from_slice(&(event.x as u16).to_le_bytes());
+    record[10..12].copy_from_slice(&(event.y as u16).to_le_bytes());
+    record[12] = event.polarity;
+    record
+}
+
+pub struct DVSRawEncoderNpy<R: Write + Seek> {
+    writer: BufWriter<R>,
+    // Whether to write a .npz archive instead of a bare .npy array
+    archive: bool,
+    count: u64,
+    // Records of an archive, which is written on flush
+    records: Vec<u8>,
+}
+
+impl<R: Write + Seek> DVSRawEncoderNpy<R> {
+    // Creates an encoder writing a bare .npy array
+    pub fn new(writer: R) -> Self {
+        Self {
+            writer: BufWriter::new(writer),
+            archive: false,
+            count: 0,
+            records: Vec::new(),
+        }
+    }
+
+    // Creates an encoder writing a .npz archive containing the array
+    pub fn new_npz(writer: R) -> Self {
+        Self {
+            archive: true,
+            ..Self::new(writer)
+        }
+    }
+
+    // Writes the whole .npz archive from the start of the file
+    fn write_archive(&mut self) -> anyhow::Result<()> {
+        let header = npy_header(self.count);
+        let size = u32::try_from(header.len() + self.records.len())
+            .map_err(|_| anyhow::anyhow!("too many events for a .npz archive (4 GiB limit), write .npy instead"))?;
+        let crc = crc32(crc32(0, &header), &self.records);
+        let name = NPZ_ARRAY_NAME.as_bytes();
+
+        let w = &mut self.writer;
+        w.seek(SeekFrom::Start(0))?;
+        // Local file header, stored without compression
+        w.write_all(&ZIP_LOCAL_FILE_HEADER.to_le_bytes())?;
+        for field in [ZIP_VERSION, 0, 0, 0, ZIP_DATE] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        for field in [crc, size, size] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        w.write_all(&(name.len() as u16).to_le_bytes())?;
+        w.write_all(&0u16.to_le_bytes())?;
+        w.write_all(name)?;
+        w.write_all(&header)?;
+        w.write_all(&self.records)?;
+
+        // Central directory with the one entry
+        let central_directory_offset = 30 + name.len() as u32 + size;
+        w.write_all(&ZIP_CENTRAL_DIRECTORY_HEADER.to_le_bytes())?;
+        for field in [ZIP_VERSION, ZIP_VERSION, 0, 0, 0, ZIP_DATE] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        for field in [crc, size, size] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        for field in [name.len() as u16, 0, 0, 0, 0] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        for field in [0u32, 0] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        w.write_all(name)?;
+
+        // End of central directory record
+        let central_directory_size = 46 + name.len() as u32;
+        w.write_all(&ZIP_END_OF_CENTRAL_DIRECTORY.to_le_bytes())?;
+        for field in [0u16, 0, 1, 1] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        for field in [central_directory_size, central_directory_offset] {
+            w.write_all(&field.to_le_bytes())?;
+        }
+        w.write_all(&0u16.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+impl<R: Write + Seek> DvsRawEncoder<R> for DVSRawEncoderNpy<R> {
+    // Writes the array header. NumPy arrays have no room for the input header, which is dropped
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        let _ = header;
+        if !self.archive {
+            self.writer.write_all(&npy_header(self.count))?;
+        }
+        Ok(())
+    }
+
+    // Writes a DVSEvent as a single record
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        let record = npy_record(&event);
+        if self.archive {
+            self.records.extend_from_slice(&record);
+        } else {
+            self.writer.write_all(&record)?;
+        }
+        self.count += 1;
+        Ok(1)
+    }
+
+    // Writes the array length into the header (or the whole archive) and flushes the underlying writer
+    fn flush(&mut self) -> Result<(), DvsError> {
+        if self.archive {
+            self.write_archive()?;
+        } else {
+            let end = self.writer.stream_position()?;
+            self.writer.seek(SeekFrom::Start(0))?;
+            self.writer.write_all(&npy_header(self.count))?;
+            self.writer.seek(SeekFrom::Start(end))?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
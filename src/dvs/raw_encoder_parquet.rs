@@ -0,0 +1,164 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::{header_geometry, DVSEvent, DvsRawEncoder};
+use arrow_array::{ArrayRef, Int64Array, RecordBatch, UInt16Array, UInt8Array};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{Compression, Encoding};
+use parquet::file::properties::WriterProperties;
+use parquet::format::KeyValue;
+use parquet::schema::types::ColumnPath;
+use std::io::{Seek, Write};
+use std::sync::Arc;
+
+/*
+This file implements an encoder that writes events to a Parquet file, so analytics tools (DataFusion, pandas,
+polars, DuckDB) can query a recording directly. It is built with the parquet feature.
+Events are stored in the columns t (int64 microseconds), x and y (uint16) and p (uint8), the same columns as the
+NumPy export. Timestamps grow slowly, so they are delta encoded. Coordinates and polarities take few distinct
+values, so they are dictionary encoded, the dictionary indices being RLE/bit-packed. Pages are compressed with
+Snappy.
+Events are buffered into Arrow record batches, which the Parquet writer groups into row groups of ROW_GROUP_SIZE
+rows. The writer needs a Send destination, so it writes to an in-memory buffer, which is moved to the output
+after every batch. Only the row group being encoded is kept in memory. The input header is stored in the file's
+key-value metadata, and the file footer is written on flush, after which no more events can be written.
+*/
+
+// Events per record batch handed to the Parquet writer
+const BATCH_SIZE: usize = 64 * 1024;
+// Rows per row group, the unit readers skip or read in parallel
+const ROW_GROUP_SIZE: usize = 1024 * 1024;
+// Key-value metadata keys
+const HEADER_KEY: &str = "dvs.header";
+const GEOMETRY_KEY: &str = "dvs.geometry";
+
+fn parquet_error(error: impl std::error::Error + Send + Sync + 'static) -> DvsError {
+    DvsError::Other(anyhow::Error::new(error))
+}
+
+// Schema of the event columns
+fn event_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("t", DataType::Int64, false),
+        Field::new("x", DataType::UInt16, false),
+        Field::new("y", DataType::UInt16, false),
+        Field::new("p", DataType::UInt8, false),
+    ]))
+}
+
+fn writer_properties() -> WriterProperties {
+    WriterProperties::builder()
+        .set_compression(Compression::SNAPPY)
+        .set_max_row_group_size(ROW_GROUP_SIZE)
+        .set_dictionary_enabled(true)
+        .set_column_dictionary_enabled(ColumnPath::from("t"), false)
+        .set_column_encoding(ColumnPath::from("t"), Encoding::DELTA_BINARY_PACKED)
+        .build()
+}
+
+// Columns of the events not yet handed to the writer. Negative coordinates wrap around, as in the NumPy export
+#[derive(Default)]
+struct Columns {
+    t: Vec<i64>,
+    x: Vec<u16>,
+    y: Vec<u16>,
+    p: Vec<u8>,
+}
+
+impl Columns {
+    fn push(&mut self, event: &DVSEvent) {
+        self.t.push(event.timestamp);
+        self.x.push(event.x as u16);
+        self.y.push(event.y as u16);
+        self.p.push(event.polarity);
+    }
+
+    fn len(&self) -> usize {
+        self.t.len()
+    }
+
+    // Moves the buffered events into a record batch
+    fn take_batch(&mut self, schema: SchemaRef) -> Result<RecordBatch, DvsError> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Int64Array::from(std::mem::take(&mut self.t))),
+            Arc::new(UInt16Array::from(std::mem::take(&mut self.x))),
+            Arc::new(UInt16Array::from(std::mem::take(&mut self.y))),
+            Arc::new(UInt8Array::from(std::mem::take(&mut self.p))),
+        ];
+        RecordBatch::try_new(schema, columns).map_err(parquet_error)
+    }
+}
+
+pub struct DVSRawEncoderParquet<R: Write + Seek> {
+    writer: R,
+    parquet: ArrowWriter<Vec<u8>>,
+    schema: SchemaRef,
+    columns: Columns,
+    finished: bool,
+}
+
+impl<R: Write + Seek> DVSRawEncoderParquet<R> {
+    pub fn new(writer: R) -> Result<Self, DvsError> {
+        let schema = event_schema();
+        let parquet = ArrowWriter::try_new(Vec::new(), schema.clone(), Some(writer_properties())).map_err(parquet_error)?;
+        Ok(Self {
+            writer,
+            parquet,
+            schema,
+            columns: Columns::default(),
+            finished: false,
+        })
+    }
+
+    // Hands the buffered events to the Parquet writer, and moves what it encoded so far to the output
+    fn write_batch(&mut self) -> Result<(), DvsError> {
+        if self.columns.len() > 0 {
+            let batch = self.columns.take_batch(self.schema.clone())?;
+            self.parquet.write(&batch).map_err(parquet_error)?;
+        }
+        self.write_encoded()
+    }
+
+    // The Parquet writer tracks the file offsets itself, so the bytes can be moved out of its buffer
+    fn write_encoded(&mut self) -> Result<(), DvsError> {
+        let encoded = self.parquet.inner_mut();
+        self.writer.write_all(encoded)?;
+        encoded.clear();
+        Ok(())
+    }
+}
+
+impl<R: Write + Seek> DvsRawEncoder<R> for DVSRawEncoderParquet<R> {
+    // Stores the header lines, and the sensor geometry when the header has one, in the key-value metadata
+    fn write_header(&mut self, header: Vec<String>) -> Result<(), DvsError> {
+        if let Some((width, height)) = header_geometry(&header) {
+            self.parquet.append_key_value_metadata(KeyValue::new(GEOMETRY_KEY.to_string(), format!("{}x{}", width, height)));
+        }
+        let text: String = header.iter().map(|line| line.trim_end()).collect::<Vec<_>>().join("\n");
+        self.parquet.append_key_value_metadata(KeyValue::new(HEADER_KEY.to_string(), text));
+        Ok(())
+    }
+
+    // Buffers a DVSEvent as a row
+    fn write_event(&mut self, event: DVSEvent) -> Result<usize, DvsError> {
+        if self.finished {
+            return Err(DvsError::Other(anyhow::anyhow!("Can't write events to a Parquet file after it was flushed")));
+        }
+        self.columns.push(&event);
+        if self.columns.len() >= BATCH_SIZE {
+            self.write_batch()?;
+        }
+        Ok(1)
+    }
+
+    // Writes the last row group and the footer, then flushes the underlying writer
+    fn flush(&mut self) -> Result<(), DvsError> {
+        if !self.finished {
+            self.write_batch()?;
+            self.parquet.finish().map_err(parquet_error)?;
+            self.finished = true;
+            self.write_encoded()?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
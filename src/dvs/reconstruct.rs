@@ -0,0 +1,188 @@
+use crate::dvs::render::Frame;
+use crate::dvs::{header_geometry, DVSEvent, DvsRawDecoder};
+use std::io::{BufRead, Read, Seek};
+use std::path::{Path, PathBuf};
+
+/*
+This file implements a quick look at the scene behind a recording: approximate intensity frames reconstructed by
+integrating events, without the learned models of methods like E2VID. Each pixel holds a log intensity relative
+to mid-gray, which every ON event raises and every OFF event lowers by the contrast step, and which leaks back to
+zero with a time constant, so that the errors of integration (noise, missed events, threshold mismatch) fade
+rather than accumulate. Static parts of the scene therefore fade to gray after a few time constants, and only
+what moves or changes stays visible: a longer time constant keeps more of the scene, and more of the noise.
+Frames sample the intensities at the end of windows aligned to multiples of their duration, as in render.rs,
+and are written as PNG files or, with the video feature, as a video.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 64 * 1024;
+// Intensity of a pixel at a log intensity of 0, on a 0 to 255 scale
+const MID_GRAY: f32 = 127.5;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ReconstructOptions {
+    // Time covered by each frame, in microseconds
+    pub frame_us: i64,
+    // Change of log intensity of an event
+    pub contrast: f32,
+    // Time constant of the leak back to mid-gray, in microseconds
+    pub tau_us: f64,
+    // Width and height of the frames, overriding the sensor geometry of the header
+    pub geometry: Option<(u32, u32)>,
+}
+
+impl Default for ReconstructOptions {
+    fn default() -> Self {
+        ReconstructOptions {
+            frame_us: 10_000,
+            contrast: 0.2,
+            tau_us: 100_000.0,
+            geometry: None,
+        }
+    }
+}
+
+// Log intensities of the pixels of a sensor, integrated from events with a leak, see the top of this file
+pub struct Integrator {
+    width: u32,
+    height: u32,
+    options: ReconstructOptions,
+    log_intensity: Vec<f32>,
+    // Time each log intensity was last updated
+    updated_us: Vec<i64>,
+}
+
+impl Integrator {
+    pub fn new(width: u32, height: u32, options: ReconstructOptions) -> Self {
+        let pixels = width as usize * height as usize;
+        Integrator { width, height, options, log_intensity: vec![0.0; pixels], updated_us: vec![0; pixels] }
+    }
+
+    // Factor by which a log intensity leaks over a time
+    fn decay(&self, elapsed_us: i64) -> f32 {
+        (-(elapsed_us.max(0) as f64) / self.options.tau_us.max(f64::MIN_POSITIVE)).exp() as f32
+    }
+
+    // Integrates an event. Events outside the sensor are ignored
+    pub fn add(&mut self, event: &DVSEvent) {
+        if event.x < 0 || event.y < 0 || event.x as u32 >= self.width || event.y as u32 >= self.height {
+            return;
+        }
+        let pixel = event.y as usize * self.width as usize + event.x as usize;
+        let step = if event.polarity == 1 { self.options.contrast } else { -self.options.contrast };
+        self.log_intensity[pixel] = self.log_intensity[pixel] * self.decay(event.timestamp - self.updated_us[pixel]) + step;
+        self.updated_us[pixel] = event.timestamp;
+    }
+
+    // Log intensity of a pixel at a time, after its last event
+    pub fn log_intensity_at(&self, x: u32, y: u32, time_us: i64) -> f32 {
+        let pixel = y as usize * self.width as usize + x as usize;
+        self.log_intensity[pixel] * self.decay(time_us - self.updated_us[pixel])
+    }
+
+    // Grayscale image of the intensities at a time, for the window starting at start_us
+    pub fn frame(&self, start_us: i64, time_us: i64) -> Frame {
+        let mut frame = Frame::new(start_us, self.width, self.height, [0, 0, 0]);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let gray = (MID_GRAY * self.log_intensity_at(x, y, time_us).exp()).round().clamp(0.0, 255.0) as u8;
+                frame.set(x as i16, y as i16, [gray; 3]);
+            }
+        }
+        frame
+    }
+}
+
+// Reads a whole stream, from its header, and passes a reconstructed frame of each window to out. The frame size
+// is the sensor geometry of the header, unless given in the options. Returns the number of frames
+pub fn reconstruct_frames<R, D, F>(decoder: &mut D, options: ReconstructOptions, mut out: F) -> anyhow::Result<u64>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+    F: FnMut(Frame) -> anyhow::Result<()>,
+{
+    let header = decoder.read_header()?;
+    let Some((width, height)) = options.geometry.or_else(|| header_geometry(&header)) else {
+        anyhow::bail!("Reconstruction needs the sensor geometry, which the header doesn't give");
+    };
+
+    let frame_us = options.frame_us.max(1);
+    let mut integrator = Integrator::new(width, height, options);
+    // Start of the current window, and the number of events integrated in it
+    let mut window: Option<(i64, u64)> = None;
+    let mut frames = 0;
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            break;
+        }
+        for event in &events {
+            let start_us = event.timestamp.div_euclid(frame_us) * frame_us;
+            // Passes on the windows before the one the event falls in. Late events are added to the current window
+            while let Some((current_us, count)) = window.filter(|(current_us, _)| *current_us < start_us) {
+                let mut frame = integrator.frame(current_us, current_us + frame_us);
+                frame.events = count;
+                out(frame)?;
+                frames += 1;
+                window = Some((current_us + frame_us, 0));
+            }
+            let (current_us, count) = window.unwrap_or((start_us, 0));
+            window = Some((current_us, count + 1));
+            integrator.add(event);
+        }
+    }
+    if let Some((current_us, count)) = window {
+        let mut frame = integrator.frame(current_us, current_us + frame_us);
+        frame.events = count;
+        out(frame)?;
+        frames += 1;
+    }
+    Ok(frames)
+}
+
+// Writes the reconstructed frames of a stream to out_dir as frame_<number>.png, see reconstruct_frames. Returns
+// the paths written, in order
+pub fn reconstruct_png_sequence<R, D>(decoder: &mut D, out_dir: &Path, options: ReconstructOptions) -> anyhow::Result<Vec<PathBuf>>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+{
+    std::fs::create_dir_all(out_dir)?;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    reconstruct_frames(decoder, options, |frame| {
+        let path = out_dir.join(format!("frame_{:06}.png", paths.len()));
+        frame.write_png(&path)?;
+        paths.push(path);
+        Ok(())
+    })?;
+    Ok(paths)
+}
+
+// Encodes the reconstructed frames of a stream into a video file, see reconstruct_frames and VideoWriter. Returns
+// the number of frames
+#[cfg(feature = "video")]
+pub fn reconstruct_video<R, D>(decoder: &mut D, path: &Path, options: ReconstructOptions, fps: f64) -> anyhow::Result<u64>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+{
+    use crate::dvs::render::VideoWriter;
+
+    let mut writer: Option<VideoWriter> = None;
+    let frames = reconstruct_frames(decoder, options, |frame| {
+        // The size of the frames is only known once the header has been read
+        if writer.is_none() {
+            writer = Some(VideoWriter::create(path, frame.width, frame.height, fps)?);
+        }
+        match writer.as_mut() {
+            Some(writer) => writer.write_frame(&frame),
+            None => Ok(()),
+        }
+    });
+    // ffmpeg is waited for even if reconstruction failed, so it doesn't outlive the call
+    let finished = writer.as_mut().map_or(Ok(()), VideoWriter::finish);
+    let frames = frames?;
+    finished?;
+    Ok(frames)
+}
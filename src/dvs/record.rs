@@ -0,0 +1,225 @@
+use crate::dvs::compress::{Compression, OutputFile};
+use crate::dvs::raw_decoder_csv::CsvOptions;
+use crate::dvs::split::{CountingWriter, Segment, SplitLimit};
+use crate::dvs::{prep_encoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, DvsRawEncoderEnum, EventFormat};
+use std::cell::Cell;
+use std::fs::{self, OpenOptions};
+use std::io::{BufRead, BufWriter, Read, Seek};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/*
+This file implements recording live or network streams (cameras, TCP and UDP streams, followed files) to disk.
+Recordings can be rotated into consecutive files like split.rs does for recordings: at multiples of a duration
+of the event timestamps, or once a file reaches a size. Each file is a complete recording with its own header.
+
+Files are written as <name>.part and only renamed once complete, so a recording that was interrupted (the
+process killed, the machine crashing) leaves its last file as a .part file, never as a file that looks complete.
+The header is flushed as soon as a file is opened, and the events every flush interval, so a .part file holds a
+valid header and the events up to the last flush. NPY headers give the number of events, and are rewritten on
+every flush. NPZ and MCAP files are only valid once their footer is written, so they can't be recorded.
+
+Files can be compressed with gzip or zstd as they are written, see compress.rs. Each flush ends a compressed block,
+so an interrupted file decompresses up to the last flush. Rotation sizes are counted before compression.
+*/
+
+// Number of events decoded at a time. Kept small, as live sources only return a batch once it is full
+const BATCH_SIZE: usize = 4096;
+
+// Extension of files being written
+pub const PART_EXTENSION: &str = "part";
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct RecordOptions {
+    pub format: EventFormat,
+    // Where files end, or None to record to a single file
+    pub rotation: Option<SplitLimit>,
+    pub compression: Compression,
+    // How often written events are flushed to disk
+    pub flush_interval: Duration,
+}
+
+impl Default for RecordOptions {
+    fn default() -> Self {
+        RecordOptions {
+            format: EventFormat::default(),
+            rotation: None,
+            compression: Compression::None,
+            flush_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+type RecordEncoder = DvsRawEncoderEnum<CountingWriter<BufWriter<OutputFile>>>;
+
+// A file being recorded
+struct OpenFile {
+    encoder: RecordEncoder,
+    segment: Segment,
+    // Path the .part file is renamed to once complete
+    part_path: PathBuf,
+    len: Rc<Cell<u64>>,
+    // Rotation window of the file
+    window: i64,
+}
+
+// Writes events to files in a directory, rotating them, see the top of this file
+pub struct Recorder {
+    out_dir: PathBuf,
+    name: String,
+    header: Vec<String>,
+    options: RecordOptions,
+    current: Option<OpenFile>,
+    segments: Vec<Segment>,
+    last_flush: Instant,
+}
+
+impl Recorder {
+    // Records to <out_dir>/<name>.<format extension>, or <out_dir>/<name>_<number>.<format extension> numbered from
+    // 0 when rotating, with a .gz or .zst extension when compressed
+    pub fn new(out_dir: &Path, name: &str, header: Vec<String>, options: RecordOptions) -> anyhow::Result<Self> {
+        match options.rotation {
+            Some(SplitLimit::Duration(us)) if us <= 0 => anyhow::bail!("Rotation duration must be positive"),
+            Some(SplitLimit::Bytes(0)) => anyhow::bail!("Rotation size must be positive"),
+            _ => {}
+        }
+        match options.format {
+            EventFormat::Npz | EventFormat::Mcap => anyhow::bail!("{} files are only valid once complete, so they can't be recorded", options.format.extension().to_uppercase()),
+            #[cfg(feature = "ros")]
+            EventFormat::Rosbag2 => anyhow::bail!("ROS 2 bags are only valid once complete, so they can't be recorded"),
+            #[cfg(feature = "parquet")]
+            EventFormat::Parquet => anyhow::bail!("Parquet files are only valid once complete, so they can't be recorded"),
+            EventFormat::Npy if options.compression != Compression::None => anyhow::bail!("NPY files can't be recorded compressed"),
+            _ if cfg!(not(feature = "compression")) && options.compression != Compression::None => anyhow::bail!("Compressed recording needs the compression feature"),
+            _ => {}
+        }
+        fs::create_dir_all(out_dir)?;
+        Ok(Recorder {
+            out_dir: out_dir.to_path_buf(),
+            name: name.to_string(),
+            header,
+            options,
+            current: None,
+            segments: Vec::new(),
+            last_flush: Instant::now(),
+        })
+    }
+
+    // Files completed so far
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    fn file_path(&self) -> PathBuf {
+        let (extension, suffix) = (self.options.format.extension(), self.options.compression.suffix());
+        match self.options.rotation {
+            Some(_) => self.out_dir.join(format!("{}_{:05}.{}{}", self.name, self.segments.len(), extension, suffix)),
+            None => self.out_dir.join(format!("{}.{}{}", self.name, extension, suffix)),
+        }
+    }
+
+    // Opens the next file, writing and flushing its header
+    fn open(&self, event: &DVSEvent, window: i64) -> anyhow::Result<OpenFile> {
+        let path = self.file_path();
+        let part_path = PathBuf::from(format!("{}.{}", path.display(), PART_EXTENSION));
+        let (writer, len) = CountingWriter::new(BufWriter::new(OutputFile::create(&part_path, self.options.compression)?));
+        let mut encoder = prep_encoder(writer, self.options.format, CsvOptions::default())?;
+        encoder.write_header(self.header.clone())?;
+        encoder.flush()?;
+        let segment = Segment {
+            path: path.to_string_lossy().into_owned(),
+            first_us: event.timestamp,
+            last_us: event.timestamp,
+            events: 0,
+            bytes: 0,
+        };
+        Ok(OpenFile { encoder, segment, part_path, len, window })
+    }
+
+    // Flushes the open file, syncs it to disk and gives it its final name
+    fn close(&mut self) -> anyhow::Result<()> {
+        let Some(OpenFile { mut encoder, mut segment, part_path, len, .. }) = self.current.take() else {
+            return Ok(());
+        };
+        encoder.flush()?;
+        segment.bytes = len.get();
+        // Dropping the encoder completes compressed files
+        drop(encoder);
+        OpenOptions::new().write(true).open(&part_path)?.sync_all()?;
+        fs::rename(&part_path, &segment.path)?;
+        self.segments.push(segment);
+        Ok(())
+    }
+
+    // Writes events, rotating and flushing files as needed. Returns the files completed by these events
+    pub fn write_events(&mut self, events: &[DVSEvent]) -> anyhow::Result<&[Segment]> {
+        let completed = self.segments.len();
+        for event in events {
+            let window = match self.options.rotation {
+                Some(SplitLimit::Duration(us)) => event.timestamp.div_euclid(us),
+                _ => 0,
+            };
+            let full = match (&self.current, self.options.rotation) {
+                (Some(open), Some(SplitLimit::Duration(_))) => window != open.window,
+                (Some(open), Some(SplitLimit::Bytes(bytes))) => open.len.get() >= bytes,
+                _ => false,
+            };
+            if full {
+                self.close()?;
+            }
+            if self.current.is_none() {
+                self.current = Some(self.open(event, window)?);
+            }
+            let Some(open) = self.current.as_mut() else { continue };
+            open.encoder.write_event(*event)?;
+            open.segment.last_us = event.timestamp;
+            open.segment.events += 1;
+        }
+        if self.last_flush.elapsed() >= self.options.flush_interval {
+            if let Some(open) = self.current.as_mut() {
+                open.encoder.flush()?;
+            }
+            self.last_flush = Instant::now();
+        }
+        Ok(&self.segments[completed..])
+    }
+
+    // Completes the open file, returning all the files recorded
+    pub fn finish(mut self) -> anyhow::Result<Vec<Segment>> {
+        self.close()?;
+        Ok(std::mem::take(&mut self.segments))
+    }
+}
+
+// Records a stream until it ends, see Recorder. The progress callback is invoked with each file once it is complete
+pub fn record<R, D, F>(decoder: &mut D, out_dir: &Path, name: &str, options: RecordOptions, mut progress: F) -> anyhow::Result<Vec<Segment>>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+    F: FnMut(&Segment),
+{
+    let header = decoder.read_header()?;
+    let mut recorder = Recorder::new(out_dir, name, header, options)?;
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        events.clear();
+        // Complete the open file on errors too, e.g. a camera failing, so the events recorded so far are kept
+        let read = match decoder.read_events_into(&mut events, BATCH_SIZE) {
+            Ok(read) => read,
+            Err(e) => {
+                recorder.write_events(&events)?;
+                recorder.finish()?;
+                return Err(e.into());
+            }
+        };
+        if read == 0 {
+            break;
+        }
+        recorder.write_events(&events)?.iter().for_each(&mut progress);
+    }
+    let completed = recorder.segments().len();
+    let segments = recorder.finish()?;
+    segments[completed..].iter().for_each(&mut progress);
+    Ok(segments)
+}
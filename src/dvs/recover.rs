@@ -0,0 +1,80 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::{DvsRawDecoder, DVSEvent, TriggerEvent};
+use std::io::{BufRead, Read, Seek};
+use std::marker::PhantomData;
+
+/*
+This file implements recovery from truncated input, such as a recording cut off when the camera or the disk
+stopped, or a download that didn't finish. Decoders are strict: input that ends in the middle of an event
+fails with DvsError::Truncated. Recover wraps a decoder and ends the stream there instead, keeping every event
+before the cut, and remembers where the input was cut off.
+*/
+
+// Wraps a decoder, ending the stream at the point where truncated input is cut off
+pub struct Recover<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> {
+    decoder: D,
+    truncated_at: Option<u64>,
+    _reader: PhantomData<R>,
+}
+
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> Recover<R, D> {
+    pub fn new(decoder: D) -> Self {
+        Self { decoder, truncated_at: None, _reader: PhantomData }
+    }
+
+    // Byte offset of the incomplete event the input ended in, once the end of a truncated input was reached
+    pub fn truncated_at(&self) -> Option<u64> {
+        self.truncated_at
+    }
+
+    pub fn into_inner(self) -> D {
+        self.decoder
+    }
+
+    // Ends the stream on a Truncated error, passing any other error on
+    fn recover(&mut self, error: DvsError) -> Result<(), DvsError> {
+        match error {
+            DvsError::Truncated { offset } => {
+                tracing::warn!(offset, "input is truncated, keeping the events before it");
+                self.truncated_at = Some(offset);
+                Ok(())
+            }
+            error => Err(error),
+        }
+    }
+}
+
+// Implemented like DvsRawDecoderEnum, so a recovering decoder can be used wherever a decoder is expected
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> DvsRawDecoder<R> for Recover<R, D> {
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        self.truncated_at = None;
+        self.decoder.read_header()
+    }
+
+    // Returns Ok(None) after the last complete event of a truncated input
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        if self.truncated_at.is_some() {
+            return Ok(None);
+        }
+        match self.decoder.read_event() {
+            Ok(event) => Ok(event),
+            Err(error) => self.recover(error).map(|_| None),
+        }
+    }
+
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        if self.truncated_at.is_some() {
+            return Ok(0);
+        }
+        let before = events.len();
+        match self.decoder.read_events_into(events, max) {
+            Ok(count) => Ok(count),
+            // The decoder appends the events before the cut before failing
+            Err(error) => self.recover(error).map(|_| events.len() - before),
+        }
+    }
+
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        self.decoder.take_triggers()
+    }
+}
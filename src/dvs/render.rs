@@ -0,0 +1,309 @@
+use crate::dvs::{header_geometry, DVSEvent, DvsRawDecoder};
+use std::io::{BufRead, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/*
+This file implements rendering event streams to images, to inspect recordings by eye, e.g. the effect of a loss
+model. Events are accumulated into frames covering a fixed duration, aligned to multiples of it like the chunks
+of the loss filter. Each pixel takes the color of the polarity of its latest event in the frame, and pixels
+without events keep the background color. Frames without events between the first and last events are kept,
+so a sequence plays back at a constant rate. Events are assumed to be in time order.
+Frames are written as RGB PNG files by the png crate.
+With the video feature, frames can instead be encoded into a video by an ffmpeg process, which is fed raw RGB
+frames through a pipe.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 64 * 1024;
+
+// Colors of the background and of ON and OFF events
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Colormap {
+    // ON white and OFF black on gray, like an accumulated intensity change
+    #[default]
+    Gray,
+    // ON red and OFF blue on white
+    RedBlue,
+    // ON white and OFF blue on dark blue, like Prophesee's viewers
+    Dark,
+}
+
+impl Colormap {
+    // The background, ON and OFF colors as RGB
+    pub fn colors(self) -> ([u8; 3], [u8; 3], [u8; 3]) {
+        match self {
+            Colormap::Gray => ([128, 128, 128], [255, 255, 255], [0, 0, 0]),
+            Colormap::RedBlue => ([255, 255, 255], [220, 40, 40], [40, 80, 220]),
+            Colormap::Dark => ([30, 37, 52], [255, 255, 255], [64, 126, 201]),
+        }
+    }
+}
+
+// Parses "gray", "red-blue" or "dark"
+impl std::str::FromStr for Colormap {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "gray" | "grey" => Ok(Colormap::Gray),
+            "red-blue" => Ok(Colormap::RedBlue),
+            "dark" => Ok(Colormap::Dark),
+            _ => anyhow::bail!("Unsupported colormap '{}'. Expected gray, red-blue or dark", s),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    // Time covered by each frame, in microseconds
+    pub frame_us: i64,
+    pub colormap: Colormap,
+    // Width and height of the frames, overriding the sensor geometry of the header
+    pub geometry: Option<(u32, u32)>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            frame_us: 10_000,
+            colormap: Colormap::default(),
+            geometry: None,
+        }
+    }
+}
+
+// An RGB image of the events of one time window
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    // Start of the window, in microseconds
+    pub start_us: i64,
+    pub width: u32,
+    pub height: u32,
+    // Rows of RGB pixels, from the top
+    pub pixels: Vec<u8>,
+    // Number of events drawn
+    pub events: u64,
+}
+
+impl Frame {
+    pub fn new(start_us: i64, width: u32, height: u32, background: [u8; 3]) -> Self {
+        let pixels = background.iter().copied().cycle().take(width as usize * height as usize * 3).collect();
+        Frame { start_us, width, height, pixels, events: 0 }
+    }
+
+    // Colors a pixel. Pixels outside the frame are ignored
+    pub fn set(&mut self, x: i16, y: i16, color: [u8; 3]) {
+        if x < 0 || y < 0 || x as u32 >= self.width || y as u32 >= self.height {
+            return;
+        }
+        let offset = (y as usize * self.width as usize + x as usize) * 3;
+        self.pixels[offset..offset + 3].copy_from_slice(&color);
+    }
+
+    // Encodes the frame as an RGB PNG file
+    pub fn to_png(&self) -> std::io::Result<Vec<u8>> {
+        let mut png = Vec::new();
+        self.encode_png(&mut png)?;
+        Ok(png)
+    }
+
+    pub fn write_png(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.encode_png(&mut file)?;
+        file.flush()
+    }
+
+    fn encode_png(&self, out: impl Write) -> std::io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+// Draws events into frames of a fixed duration, passing each frame on once its window has passed
+pub struct FrameAccumulator {
+    options: RenderOptions,
+    width: u32,
+    height: u32,
+    frame: Option<Frame>,
+}
+
+impl FrameAccumulator {
+    pub fn new(options: RenderOptions, width: u32, height: u32) -> Self {
+        Self { options, width, height, frame: None }
+    }
+
+    fn background(&self) -> [u8; 3] {
+        self.options.colormap.colors().0
+    }
+
+    // Draws an event, passing on the frames before the one it falls in
+    pub fn add(&mut self, event: &DVSEvent, out: &mut impl FnMut(Frame)) {
+        let frame_us = self.options.frame_us.max(1);
+        let start_us = event.timestamp.div_euclid(frame_us) * frame_us;
+        // Late events are drawn into the current frame
+        while self.frame.as_ref().is_none_or(|frame| frame.start_us < start_us) {
+            let next_us = self.frame.as_ref().map_or(start_us, |frame| frame.start_us + frame_us);
+            let next = Frame::new(next_us, self.width, self.height, self.background());
+            if let Some(frame) = self.frame.replace(next) {
+                out(frame);
+            }
+        }
+        let (_, on, off) = self.options.colormap.colors();
+        if let Some(frame) = self.frame.as_mut() {
+            frame.set(event.x, event.y, if event.polarity == 1 { on } else { off });
+            frame.events += 1;
+        }
+    }
+
+    // Passes on the last frame
+    pub fn finish(&mut self, out: &mut impl FnMut(Frame)) {
+        if let Some(frame) = self.frame.take() {
+            out(frame);
+        }
+    }
+}
+
+// Reads a whole stream, from its header, and passes each of its frames to out. The frame size is the sensor
+// geometry of the header, unless given in the options. Returns the number of frames
+pub fn render_frames<R, D, F>(decoder: &mut D, options: RenderOptions, mut out: F) -> anyhow::Result<u64>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+    F: FnMut(Frame) -> anyhow::Result<()>,
+{
+    let header = decoder.read_header()?;
+    let Some((width, height)) = options.geometry.or_else(|| header_geometry(&header)) else {
+        anyhow::bail!("Rendering needs the sensor geometry, which the header doesn't give");
+    };
+
+    let mut accumulator = FrameAccumulator::new(options, width, height);
+    let mut frames = 0;
+    // The first error stops the rendering at the end of the batch
+    let mut result: anyhow::Result<()> = Ok(());
+    let mut pass = |result: &mut anyhow::Result<()>, frame: Frame| {
+        if result.is_ok() {
+            *result = out(frame);
+            frames += 1;
+        }
+    };
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            accumulator.finish(&mut |frame| pass(&mut result, frame));
+            break;
+        }
+        for event in &events {
+            accumulator.add(event, &mut |frame| pass(&mut result, frame));
+        }
+        if result.is_err() {
+            break;
+        }
+    }
+    result?;
+    Ok(frames)
+}
+
+// Writes the frames of a stream to out_dir as frame_<number>.png, see render_frames. Returns the paths written,
+// in order
+pub fn render_png_sequence<R, D>(decoder: &mut D, out_dir: &Path, options: RenderOptions) -> anyhow::Result<Vec<PathBuf>>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+{
+    std::fs::create_dir_all(out_dir)?;
+    let mut paths: Vec<PathBuf> = Vec::new();
+    render_frames(decoder, options, |frame| {
+        let path = out_dir.join(format!("frame_{:06}.png", paths.len()));
+        frame.write_png(&path)?;
+        paths.push(path);
+        Ok(())
+    })?;
+    Ok(paths)
+}
+
+// Whether a path names a video file, by its extension, rather than a directory of PNG frames
+pub fn is_video_path(path: &Path) -> bool {
+    let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+    ["mp4", "mkv", "mov", "avi", "mjpeg", "mjpg"].contains(&extension.as_str())
+}
+
+// Encodes frames into a video file with an ffmpeg process, which must be on the PATH. MP4, MKV and MOV files are
+// encoded with H.264, and AVI and MJPEG files with Motion JPEG
+#[cfg(feature = "video")]
+pub struct VideoWriter {
+    child: std::process::Child,
+    stdin: Option<std::process::ChildStdin>,
+}
+
+#[cfg(feature = "video")]
+impl VideoWriter {
+    // Starts ffmpeg writing frames of the given size to path, played at fps frames per second
+    pub fn create(path: &Path, width: u32, height: u32, fps: f64) -> anyhow::Result<Self> {
+        let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+        let codec: &[&str] = match extension.as_str() {
+            // H.264 in yuv420p, which players expect, needs an even width and height
+            "mp4" | "mkv" | "mov" => &["-c:v", "libx264", "-pix_fmt", "yuv420p", "-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"],
+            "avi" | "mjpeg" | "mjpg" => &["-c:v", "mjpeg", "-q:v", "3"],
+            _ => anyhow::bail!("Unsupported video file {}. Expected .mp4, .mkv, .mov, .avi or .mjpeg", path.display()),
+        };
+        let mut child = std::process::Command::new("ffmpeg")
+            .args(["-y", "-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "rgb24"])
+            .args(["-s", &format!("{}x{}", width, height), "-r", &fps.to_string(), "-i", "-"])
+            .args(codec)
+            .arg(path)
+            .stdin(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to start ffmpeg, which video output needs: {}", e))?;
+        let stdin = child.stdin.take();
+        Ok(VideoWriter { child, stdin })
+    }
+
+    pub fn write_frame(&mut self, frame: &Frame) -> anyhow::Result<()> {
+        use std::io::Write;
+        match self.stdin.as_mut() {
+            Some(stdin) => Ok(stdin.write_all(&frame.pixels)?),
+            None => anyhow::bail!("The video has been finished"),
+        }
+    }
+
+    // Ends the video and waits for ffmpeg to finish writing it
+    pub fn finish(&mut self) -> anyhow::Result<()> {
+        drop(self.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            anyhow::bail!("ffmpeg failed with {}", status);
+        }
+        Ok(())
+    }
+}
+
+// Encodes the frames of a stream into a video file, see render_frames and VideoWriter. Returns the number of frames
+#[cfg(feature = "video")]
+pub fn render_video<R, D>(decoder: &mut D, path: &Path, options: RenderOptions, fps: f64) -> anyhow::Result<u64>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+{
+    let mut writer: Option<VideoWriter> = None;
+    let frames = render_frames(decoder, options, |frame| {
+        // The size of the frames is only known once the header has been read
+        if writer.is_none() {
+            writer = Some(VideoWriter::create(path, frame.width, frame.height, fps)?);
+        }
+        match writer.as_mut() {
+            Some(writer) => writer.write_frame(&frame),
+            None => Ok(()),
+        }
+    });
+    // ffmpeg is waited for even if rendering failed, so it doesn't outlive the call
+    let finished = writer.as_mut().map_or(Ok(()), VideoWriter::finish);
+    let frames = frames?;
+    finished?;
+    Ok(frames)
+}
@@ -0,0 +1,138 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::{DvsRawDecoder, DVSEvent, TriggerEvent};
+use std::io::{BufRead, Read, Seek};
+use std::marker::PhantomData;
+use std::time::{Duration, Instant};
+
+/*
+This file implements real-time replay, which paces a recording so events are emitted when they happened,
+relative to the first event, optionally sped up or slowed down. This turns a file into a stand-in for a live
+camera, e.g. to stream it to a receiver or to write it to a file that another process follows.
+*/
+
+pub const MIN_SPEED: f64 = 0.1;
+pub const MAX_SPEED: f64 = 100.0;
+
+// Fails unless the speed is between MIN_SPEED and MAX_SPEED times real time
+pub fn check_speed(speed: f64) -> anyhow::Result<()> {
+    if !(MIN_SPEED..=MAX_SPEED).contains(&speed) {
+        anyhow::bail!("Replay speed {} is outside of {}x to {}x", speed, MIN_SPEED, MAX_SPEED);
+    }
+    Ok(())
+}
+
+// Events due within this much of now are emitted without sleeping, to avoid sleeping for every event
+const GRANULARITY: Duration = Duration::from_millis(1);
+
+// Maps event timestamps to wall clock times, starting from the first timestamp it is asked about
+#[derive(Debug, Copy, Clone)]
+pub struct ReplayClock {
+    speed: f64,
+    start: Option<(Instant, i64)>,
+}
+
+impl ReplayClock {
+    pub fn new(speed: f64) -> Self {
+        Self { speed, start: None }
+    }
+
+    // Starts over, so the next timestamp is due immediately
+    pub fn reset(&mut self) {
+        self.start = None;
+    }
+
+    // When an event with the given timestamp is due
+    pub fn deadline(&mut self, timestamp: i64) -> Instant {
+        let (start, first) = *self.start.get_or_insert((Instant::now(), timestamp));
+        start + Duration::from_secs_f64((timestamp - first).max(0) as f64 / 1e6 / self.speed)
+    }
+
+    // Whether an event with the given timestamp can be emitted without sleeping
+    pub fn is_due(&mut self, timestamp: i64) -> bool {
+        self.deadline(timestamp) <= Instant::now() + GRANULARITY
+    }
+
+    // Sleeps until an event with the given timestamp is due
+    pub fn wait(&mut self, timestamp: i64) {
+        let deadline = self.deadline(timestamp);
+        let now = Instant::now();
+        if deadline > now + GRANULARITY {
+            std::thread::sleep(deadline - now);
+        }
+    }
+}
+
+// Wraps a decoder, returning each event once it is due
+pub struct Replay<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> {
+    decoder: D,
+    clock: ReplayClock,
+    // An event read ahead that wasn't due yet
+    pending: Option<DVSEvent>,
+    _reader: PhantomData<R>,
+}
+
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> Replay<R, D> {
+    // Fails if the speed is out of range, see check_speed
+    pub fn new(decoder: D, speed: f64) -> anyhow::Result<Self> {
+        check_speed(speed)?;
+        Ok(Self { decoder, clock: ReplayClock::new(speed), pending: None, _reader: PhantomData })
+    }
+
+    pub fn into_inner(self) -> D {
+        self.decoder
+    }
+}
+
+// Implemented like DvsRawDecoderEnum, so a replay can be used wherever a decoder is expected
+impl<R: Read + BufRead + Seek, D: DvsRawDecoder<R>> DvsRawDecoder<R> for Replay<R, D> {
+    // Reads the header of the underlying decoder, and restarts the clock
+    fn read_header(&mut self) -> Result<Vec<String>, DvsError> {
+        self.clock.reset();
+        self.pending = None;
+        self.decoder.read_header()
+    }
+
+    fn read_event(&mut self) -> Result<Option<DVSEvent>, DvsError> {
+        let event = match self.pending.take() {
+            Some(event) => Some(event),
+            None => self.decoder.read_event()?,
+        };
+        if let Some(event) = event {
+            self.clock.wait(event.timestamp);
+        }
+        Ok(event)
+    }
+
+    // Returns the events that are due, sleeping only if none are, so they can be passed on promptly
+    fn read_events_into(&mut self, events: &mut Vec<DVSEvent>, max: usize) -> Result<usize, DvsError> {
+        let mut count = 0;
+        while count < max {
+            let event = match self.pending.take() {
+                Some(event) => event,
+                None => {
+                    if self.decoder.read_events_into(events, 1)? == 0 {
+                        break;
+                    }
+                    match events.pop() {
+                        Some(event) => event,
+                        None => break,
+                    }
+                }
+            };
+            if !self.clock.is_due(event.timestamp) {
+                if count > 0 {
+                    self.pending = Some(event);
+                    break;
+                }
+                self.clock.wait(event.timestamp);
+            }
+            events.push(event);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    fn take_triggers(&mut self) -> Vec<TriggerEvent> {
+        self.decoder.take_triggers()
+    }
+}
@@ -0,0 +1,346 @@
+use crate::dvs::raw_encoder_npy::npy_array_header;
+use crate::dvs::{header_geometry, DVSEvent, DvsRawDecoder};
+use std::io::{BufRead, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/*
+This file implements dense representations of event streams for machine learning pipelines, written as arrays
+of float32.
+A time surface (or surface of active events, SAE) holds the timestamp of the latest event of each pixel. It is
+sampled at the end of windows of a fixed duration, aligned to multiples of it like the frames of render.rs, and
+each pixel is given exp(-(t - t_latest) / tau) at the sampling time t: 1 for an event just now, decaying to 0,
+and 0 for pixels without events so far. Surfaces have one channel for both polarities, or two (OFF, then ON),
+so an export has shape (T, H, W) or (T, 2, H, W). Events are assumed to be in time order.
+A voxel grid counts the events of each pixel in the same windows, with the same channels, for spiking and
+convolutional networks trained on event counts. Windows without events between the first and last events are
+kept, so the rows of an export are evenly spaced in time.
+Arrays are written as .npy files or as safetensors files holding one tensor. Both start with a header giving the
+shape, in which the number of rows is padded to a fixed width and written once the stream has been read.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 64 * 1024;
+// Latest timestamp of pixels without events
+const NO_EVENT: i64 = i64::MIN;
+// dtype of the exported arrays
+const NPY_FLOAT32: &str = "'<f4'";
+// Safetensors headers are padded to a multiple of this
+const SAFETENSORS_ALIGNMENT: usize = 8;
+
+// File formats of exported arrays
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TensorFormat {
+    #[default]
+    Npy,
+    Safetensors,
+}
+
+impl TensorFormat {
+    // Guesses the format from the file extension, .npy or .safetensors
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "npy" => Some(TensorFormat::Npy),
+            "safetensors" => Some(TensorFormat::Safetensors),
+            _ => None,
+        }
+    }
+}
+
+// Parses npy or safetensors
+impl std::str::FromStr for TensorFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "npy" => Ok(TensorFormat::Npy),
+            "safetensors" => Ok(TensorFormat::Safetensors),
+            _ => anyhow::bail!("Unsupported tensor format '{}'. Expected npy or safetensors", s),
+        }
+    }
+}
+
+// A dense representation updated with events and read at the end of each window
+trait Representation {
+    fn add(&mut self, event: &DVSEvent);
+    // Reads the representation at the end of a window, at t_us
+    fn sample(&mut self, t_us: i64, out: &mut Vec<f32>);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TimeSurfaceOptions {
+    // Duration of the windows sampled, in microseconds
+    pub interval_us: i64,
+    // Decay time constant, in microseconds
+    pub tau_us: f64,
+    // One channel per polarity instead of one for both
+    pub split_polarity: bool,
+    // Width and height of the sensor, overriding the header
+    pub geometry: Option<(u32, u32)>,
+}
+
+impl Default for TimeSurfaceOptions {
+    fn default() -> Self {
+        TimeSurfaceOptions { interval_us: 10_000, tau_us: 50_000.0, split_polarity: false, geometry: None }
+    }
+}
+
+// Latest event timestamps of each pixel
+pub struct TimeSurface {
+    width: usize,
+    height: usize,
+    channels: usize,
+    tau_us: f64,
+    // Indexed by channel, then row, then column
+    latest: Vec<i64>,
+}
+
+impl TimeSurface {
+    pub fn new(width: u32, height: u32, split_polarity: bool, tau_us: f64) -> Self {
+        let (width, height) = (width as usize, height as usize);
+        let channels = if split_polarity { 2 } else { 1 };
+        TimeSurface { width, height, channels, tau_us, latest: vec![NO_EVENT; channels * height * width] }
+    }
+
+    // Shape of a sample, (H, W) or (2, H, W)
+    pub fn shape(&self) -> Vec<usize> {
+        match self.channels {
+            1 => vec![self.height, self.width],
+            _ => vec![self.channels, self.height, self.width],
+        }
+    }
+
+    // Records an event. Events outside the sensor are ignored
+    pub fn add(&mut self, event: &DVSEvent) {
+        let (x, y) = (event.x as usize, event.y as usize);
+        if event.x < 0 || event.y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let channel = if self.channels == 2 { (event.polarity == 1) as usize } else { 0 };
+        self.latest[(channel * self.height + y) * self.width + x] = event.timestamp;
+    }
+
+    // Decayed surface at t_us, in the layout of shape. Events after t_us count as just now
+    pub fn sample(&self, t_us: i64, out: &mut Vec<f32>) {
+        let tau_us = self.tau_us.max(f64::MIN_POSITIVE);
+        out.clear();
+        out.extend(self.latest.iter().map(|&latest| match latest {
+            NO_EVENT => 0.0,
+            _ => (-(t_us.saturating_sub(latest).max(0) as f64) / tau_us).exp() as f32,
+        }));
+    }
+}
+
+impl Representation for TimeSurface {
+    fn add(&mut self, event: &DVSEvent) {
+        TimeSurface::add(self, event)
+    }
+
+    fn sample(&mut self, t_us: i64, out: &mut Vec<f32>) {
+        TimeSurface::sample(self, t_us, out)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoxelGridOptions {
+    // Duration of the windows counted, in microseconds
+    pub window_us: i64,
+    // One channel per polarity instead of one for both
+    pub split_polarity: bool,
+    // Width and height of the sensor, overriding the header
+    pub geometry: Option<(u32, u32)>,
+}
+
+impl Default for VoxelGridOptions {
+    fn default() -> Self {
+        VoxelGridOptions { window_us: 10_000, split_polarity: false, geometry: None }
+    }
+}
+
+// Event counts of each pixel in the current window
+pub struct VoxelGrid {
+    width: usize,
+    height: usize,
+    channels: usize,
+    // Indexed by channel, then row, then column
+    counts: Vec<f32>,
+}
+
+impl VoxelGrid {
+    pub fn new(width: u32, height: u32, split_polarity: bool) -> Self {
+        let (width, height) = (width as usize, height as usize);
+        let channels = if split_polarity { 2 } else { 1 };
+        VoxelGrid { width, height, channels, counts: vec![0.0; channels * height * width] }
+    }
+
+    // Shape of the counts of a window, (H, W) or (2, H, W)
+    pub fn shape(&self) -> Vec<usize> {
+        match self.channels {
+            1 => vec![self.height, self.width],
+            _ => vec![self.channels, self.height, self.width],
+        }
+    }
+
+    // Counts an event. Events outside the sensor are ignored
+    pub fn add(&mut self, event: &DVSEvent) {
+        let (x, y) = (event.x as usize, event.y as usize);
+        if event.x < 0 || event.y < 0 || x >= self.width || y >= self.height {
+            return;
+        }
+        let channel = if self.channels == 2 { (event.polarity == 1) as usize } else { 0 };
+        self.counts[(channel * self.height + y) * self.width + x] += 1.0;
+    }
+
+    // Moves the counts into out, in the layout of shape, and starts a new window
+    pub fn take(&mut self, out: &mut Vec<f32>) {
+        out.clear();
+        out.extend_from_slice(&self.counts);
+        self.counts.fill(0.0);
+    }
+}
+
+impl Representation for VoxelGrid {
+    fn add(&mut self, event: &DVSEvent) {
+        VoxelGrid::add(self, event)
+    }
+
+    fn sample(&mut self, _t_us: i64, out: &mut Vec<f32>) {
+        self.take(out)
+    }
+}
+
+// The safetensors header for one float32 tensor of count rows of the given inner shape, padded so its length
+// doesn't change with the count
+fn safetensors_header(name: &str, count: u64, inner_shape: &[usize]) -> Vec<u8> {
+    let row_size = inner_shape.iter().product::<usize>() as u64 * 4;
+    let shape: Vec<String> = std::iter::once(format!("{:>20}", count)).chain(inner_shape.iter().map(|n| n.to_string())).collect();
+    let text = format!(
+        "{{\"{}\":{{\"dtype\":\"F32\",\"shape\":[{}],\"data_offsets\":[0,{:>20}]}}}}",
+        name,
+        shape.join(","),
+        count * row_size
+    );
+    let padding = (SAFETENSORS_ALIGNMENT - text.len() % SAFETENSORS_ALIGNMENT) % SAFETENSORS_ALIGNMENT;
+    let mut header = ((text.len() + padding) as u64).to_le_bytes().to_vec();
+    header.extend_from_slice(text.as_bytes());
+    header.extend(std::iter::repeat_n(b' ', padding));
+    header
+}
+
+// Writes a float32 array row by row, where rows are the entries of the first dimension. The number of rows is
+// written into the header by finish
+pub struct TensorWriter<W: Write + Seek> {
+    writer: BufWriter<W>,
+    format: TensorFormat,
+    // Name of the tensor in safetensors files
+    name: String,
+    inner_shape: Vec<usize>,
+    rows: u64,
+}
+
+impl<W: Write + Seek> TensorWriter<W> {
+    // Starts an array whose rows have the given shape
+    pub fn new(writer: W, format: TensorFormat, name: &str, inner_shape: Vec<usize>) -> anyhow::Result<Self> {
+        let mut array = TensorWriter { writer: BufWriter::new(writer), format, name: name.to_string(), inner_shape, rows: 0 };
+        array.writer.write_all(&array.header())?;
+        Ok(array)
+    }
+
+    fn header(&self) -> Vec<u8> {
+        match self.format {
+            TensorFormat::Npy => npy_array_header(NPY_FLOAT32, self.rows, &self.inner_shape),
+            TensorFormat::Safetensors => safetensors_header(&self.name, self.rows, &self.inner_shape),
+        }
+    }
+
+    pub fn write_row(&mut self, row: &[f32]) -> anyhow::Result<()> {
+        let size: usize = self.inner_shape.iter().product();
+        if row.len() != size {
+            anyhow::bail!("Row of {} values for an array of rows of shape {:?}", row.len(), self.inner_shape);
+        }
+        for value in row {
+            self.writer.write_all(&value.to_le_bytes())?;
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    // Writes the number of rows into the header and flushes the writer. Returns the number of rows
+    pub fn finish(mut self) -> anyhow::Result<u64> {
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        let header = self.header();
+        self.writer.write_all(&header)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+        Ok(self.rows)
+    }
+}
+
+// Reads a whole stream from its header and writes its time surfaces to writer, see the top of this file. Returns
+// the number of surfaces written
+pub fn export_time_surfaces<R, D, W>(decoder: &mut D, writer: W, format: TensorFormat, options: TimeSurfaceOptions) -> anyhow::Result<u64>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+    W: Write + Seek,
+{
+    let header = decoder.read_header()?;
+    let Some((width, height)) = options.geometry.or_else(|| header_geometry(&header)) else {
+        anyhow::bail!("Time surfaces need the sensor geometry, which the header doesn't give");
+    };
+    let mut surface = TimeSurface::new(width, height, options.split_polarity, options.tau_us);
+    let array = TensorWriter::new(writer, format, "time_surfaces", surface.shape())?;
+    export_windows(decoder, &mut surface, options.interval_us, array)
+}
+
+// Reads a whole stream from its header and writes its event counts per window to writer, see the top of this
+// file. Returns the number of windows written
+pub fn export_voxel_grids<R, D, W>(decoder: &mut D, writer: W, format: TensorFormat, options: VoxelGridOptions) -> anyhow::Result<u64>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+    W: Write + Seek,
+{
+    let header = decoder.read_header()?;
+    let Some((width, height)) = options.geometry.or_else(|| header_geometry(&header)) else {
+        anyhow::bail!("Voxel grids need the sensor geometry, which the header doesn't give");
+    };
+    let mut grid = VoxelGrid::new(width, height, options.split_polarity);
+    let array = TensorWriter::new(writer, format, "voxels", grid.shape())?;
+    export_windows(decoder, &mut grid, options.window_us, array)
+}
+
+// Feeds the events of a stream, after its header, to a representation and writes a row at the end of each window
+fn export_windows<R, D, W>(decoder: &mut D, representation: &mut impl Representation, window_us: i64, mut array: TensorWriter<W>) -> anyhow::Result<u64>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+    W: Write + Seek,
+{
+    let window_us = window_us.max(1);
+    // End of the current window, once there are events
+    let mut window_end_us: Option<i64> = None;
+    let mut row = Vec::new();
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            break;
+        }
+        for event in &events {
+            let end_us = window_end_us.get_or_insert((event.timestamp.div_euclid(window_us) + 1) * window_us);
+            while event.timestamp >= *end_us {
+                representation.sample(*end_us, &mut row);
+                array.write_row(&row)?;
+                *end_us += window_us;
+            }
+            representation.add(event);
+        }
+    }
+    // The last window, which holds the last event
+    if let Some(end_us) = window_end_us {
+        representation.sample(end_us, &mut row);
+        array.write_row(&row)?;
+    }
+    array.finish()
+}
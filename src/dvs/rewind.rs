@@ -0,0 +1,95 @@
+use std::io::{self, Read, Seek, SeekFrom};
+
+/*
+This file implements a reader that makes non-seekable sources (pipes, stdin, sockets) usable by the decoders.
+The decoders rewind to the start of the stream while parsing the header, so the first bytes read are recorded
+and replayed when the reader seeks back. Once more than the recording limit has been read, only seeks to the
+current position are supported.
+*/
+
+// Bytes recorded from the start of the stream. Headers are a few hundred bytes, but format detection and
+// header parsing may read ahead by up to a BufReader's capacity
+pub const DEFAULT_RECORD_LIMIT: usize = 1024 * 1024;
+
+pub struct RewindReader<R: Read> {
+    inner: R,
+    // Bytes read from the start of the stream, while still recording
+    recorded: Vec<u8>,
+    recording: bool,
+    record_limit: usize,
+    // Position of the next byte returned to the caller
+    position: u64,
+    // Number of bytes read from the inner reader
+    read_total: u64,
+}
+
+impl<R: Read> RewindReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self::with_record_limit(inner, DEFAULT_RECORD_LIMIT)
+    }
+
+    pub fn with_record_limit(inner: R, record_limit: usize) -> Self {
+        Self {
+            inner,
+            recorded: Vec::new(),
+            recording: true,
+            record_limit,
+            position: 0,
+            read_total: 0,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for RewindReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Replay recorded bytes after a rewind
+        if self.position < self.read_total {
+            let start = self.position as usize;
+            let n = buf.len().min(self.recorded.len() - start);
+            buf[..n].copy_from_slice(&self.recorded[start..start + n]);
+            self.position += n as u64;
+            return Ok(n);
+        }
+
+        let n = self.inner.read(buf)?;
+        if self.recording {
+            if self.recorded.len() + n <= self.record_limit {
+                self.recorded.extend_from_slice(&buf[..n]);
+            } else {
+                self.recording = false;
+                self.recorded = Vec::new();
+            }
+        }
+        self.position += n as u64;
+        self.read_total += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: Read> Seek for RewindReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(_) => None,
+        };
+        match target {
+            Some(target) if target == self.position || (self.recording && target <= self.read_total) => {
+                self.position = target;
+                Ok(target)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Cannot seek outside the recorded start of a non-seekable stream",
+            )),
+        }
+    }
+}
@@ -0,0 +1,57 @@
+/*
+This file implements a small, seedable pseudo-random number generator (SplitMix64), so that simulations
+such as random loss are reproducible from a seed.
+Simulations made of several random stages (loss, jitter, retransmissions) draw each stage's seed from one seed
+with derive_seed, by the stage's name. A stage's stream then only depends on the seed and its name, and not on
+which other stages run or in which order, so adding a stage leaves the output of the others unchanged.
+Generators can also be split, giving an independent generator, e.g. one per worker.
+*/
+
+const GOLDEN_GAMMA: u64 = 0x9e3779b97f4a7c15;
+
+// SplitMix64 finalizer, which maps each input to a well-mixed output
+pub fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(GOLDEN_GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+// Seed of the named stream of a seed, see the top of this file
+pub fn derive_seed(seed: u64, name: &str) -> u64 {
+    // FNV-1a hash of the name
+    let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    mix(mix(seed) ^ hash)
+}
+
+#[derive(Debug, Clone)]
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let value = mix(self.state);
+        self.state = self.state.wrapping_add(GOLDEN_GAMMA);
+        value
+    }
+
+    // A generator independent of this one, which advances this one by a step
+    pub fn split(&mut self) -> Self {
+        SplitMix64::new(mix(self.next_u64()))
+    }
+
+    // A uniformly distributed value in [0, 1)
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Whether an event with the given probability happens
+    pub fn chance(&mut self, probability: f64) -> bool {
+        self.next_f64() < probability
+    }
+}
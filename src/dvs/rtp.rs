@@ -0,0 +1,206 @@
+use crate::dvs::bitrate::UDP_PAYLOAD_BYTES;
+use crate::dvs::packet::{Packet, Packetizer, PacketizerOptions};
+use crate::dvs::DVSEvent;
+
+/*
+This file implements an RTP payload format for events (RFC 3550), so event streams can be carried by
+existing RTP infrastructure and inspected with tools like Wireshark. Each RTP packet carries one event
+packet (see packet.rs) after the 12 byte RTP header:
+  version 2, no padding, extension or CSRCs
+  marker bit: set on the last packet of each chunk of chunk_us microseconds
+  payload type: dynamic, 96 by default
+  sequence number: the event packet's sequence number, modulo 2^16
+  timestamp: the base timestamp of the event packet, on a 90 kHz or microsecond clock, modulo 2^32
+  SSRC: identifies the stream
+The event packet keeps the full 64-bit timestamps and 32-bit sequence number, so no information is lost
+to the wrapping RTP fields.
+*/
+
+pub const RTP_HEADER_BYTES: usize = 12;
+const RTP_VERSION: u8 = 2;
+
+// Clock of the RTP timestamps
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum RtpClock {
+    // The usual clock of video payloads
+    #[default]
+    Khz90,
+    Microseconds,
+}
+
+impl RtpClock {
+    // Converts a timestamp in microseconds to RTP timestamp units, modulo 2^32
+    pub fn rtp_timestamp(&self, timestamp_us: i64) -> u32 {
+        match self {
+            RtpClock::Khz90 => (timestamp_us as i128 * 9 / 100) as u32,
+            RtpClock::Microseconds => timestamp_us as u32,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RtpOptions {
+    pub payload_type: u8,
+    pub ssrc: u32,
+    pub clock: RtpClock,
+    // Duration of the chunks whose last packet has the marker bit set, in microseconds
+    pub chunk_us: i64,
+    // Largest RTP packet, including the RTP header, in bytes
+    pub max_bytes: usize,
+}
+
+impl Default for RtpOptions {
+    fn default() -> Self {
+        RtpOptions {
+            payload_type: 96,
+            ssrc: 0,
+            clock: RtpClock::default(),
+            chunk_us: 10_000,
+            max_bytes: UDP_PAYLOAD_BYTES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RtpPacket {
+    pub marker: bool,
+    pub payload_type: u8,
+    pub sequence: u16,
+    pub timestamp: u32,
+    pub ssrc: u32,
+    pub packet: Packet,
+}
+
+impl RtpPacket {
+    pub fn encoded_len(&self) -> usize {
+        RTP_HEADER_BYTES + self.packet.encoded_len()
+    }
+
+    // Appends the encoded RTP packet to buf
+    pub fn encode_into(&self, buf: &mut Vec<u8>) {
+        buf.reserve(self.encoded_len());
+        buf.push(RTP_VERSION << 6);
+        buf.push(((self.marker as u8) << 7) | (self.payload_type & 0x7F));
+        buf.extend_from_slice(&self.sequence.to_be_bytes());
+        buf.extend_from_slice(&self.timestamp.to_be_bytes());
+        buf.extend_from_slice(&self.ssrc.to_be_bytes());
+        self.packet.encode_into(buf);
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.encoded_len());
+        self.encode_into(&mut buf);
+        buf
+    }
+
+    // Decodes an RTP packet carrying events. CSRCs, header extensions and padding are skipped
+    pub fn decode(bytes: &[u8]) -> anyhow::Result<RtpPacket> {
+        if bytes.len() < RTP_HEADER_BYTES {
+            anyhow::bail!("RTP packet of {} bytes is shorter than its header", bytes.len());
+        }
+        if bytes[0] >> 6 != RTP_VERSION {
+            anyhow::bail!("Unsupported RTP version {}", bytes[0] >> 6);
+        }
+        let padding = bytes[0] & 0x20 != 0;
+        let extension = bytes[0] & 0x10 != 0;
+        let csrc_count = (bytes[0] & 0x0F) as usize;
+
+        let mut start = RTP_HEADER_BYTES + 4 * csrc_count;
+        if extension {
+            let Some(words) = bytes.get(start + 2..start + 4) else {
+                anyhow::bail!("Truncated RTP header extension");
+            };
+            start += 4 + 4 * u16::from_be_bytes([words[0], words[1]]) as usize;
+        }
+        let mut end = bytes.len();
+        if padding {
+            end = end.saturating_sub(bytes[end - 1] as usize);
+        }
+        if start > end {
+            anyhow::bail!("Truncated RTP packet");
+        }
+
+        Ok(RtpPacket {
+            marker: bytes[1] & 0x80 != 0,
+            payload_type: bytes[1] & 0x7F,
+            sequence: u16::from_be_bytes([bytes[2], bytes[3]]),
+            timestamp: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            ssrc: u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            packet: Packet::decode(&bytes[start..end])?,
+        })
+    }
+}
+
+// Segments an event stream into RTP packets. Packets never span chunks, so the marker bit delimits chunks
+pub struct RtpPacketizer {
+    options: RtpOptions,
+    packetizer: Packetizer,
+    ready: Vec<Packet>,
+    // The latest packet, held back until it is known whether it ends its chunk
+    held: Option<Packet>,
+    chunk: Option<i64>,
+}
+
+impl RtpPacketizer {
+    pub fn new(options: RtpOptions) -> Self {
+        let packet = PacketizerOptions {
+            max_bytes: options.max_bytes.saturating_sub(RTP_HEADER_BYTES),
+            max_span_us: None,
+        };
+        Self {
+            options,
+            packetizer: Packetizer::new(packet),
+            ready: Vec::new(),
+            held: None,
+            chunk: None,
+        }
+    }
+
+    fn rtp_packet(&self, packet: Packet, marker: bool) -> RtpPacket {
+        RtpPacket {
+            marker,
+            payload_type: self.options.payload_type,
+            sequence: packet.sequence as u16,
+            timestamp: self.options.clock.rtp_timestamp(packet.base_timestamp),
+            ssrc: self.options.ssrc,
+            packet,
+        }
+    }
+
+    // Passes the packets completed by the packetizer to out, holding back the latest one. If end_chunk is
+    // set, the held packet is passed too, with the marker bit set
+    fn emit(&mut self, end_chunk: bool, out: &mut impl FnMut(RtpPacket)) {
+        for packet in std::mem::take(&mut self.ready) {
+            if let Some(held) = self.held.replace(packet) {
+                out(self.rtp_packet(held, false));
+            }
+        }
+        if end_chunk {
+            if let Some(held) = self.held.take() {
+                out(self.rtp_packet(held, true));
+            }
+        }
+    }
+
+    // Adds the next event of a time-ordered stream, passing any RTP packets it completes to out
+    pub fn process(&mut self, event: DVSEvent, out: &mut impl FnMut(RtpPacket)) {
+        let chunk = event.timestamp.div_euclid(self.options.chunk_us.max(1));
+        if self.chunk.is_some_and(|current| current != chunk) {
+            let ready = &mut self.ready;
+            self.packetizer.finish(&mut |packet| ready.push(packet));
+            self.emit(true, out);
+        }
+        self.chunk = Some(chunk);
+
+        let ready = &mut self.ready;
+        self.packetizer.process(event, &mut |packet| ready.push(packet));
+        self.emit(false, out);
+    }
+
+    // Passes the remaining packets to out, ending the last chunk
+    pub fn finish(&mut self, out: &mut impl FnMut(RtpPacket)) {
+        let ready = &mut self.ready;
+        self.packetizer.finish(&mut |packet| ready.push(packet));
+        self.emit(true, out);
+    }
+}
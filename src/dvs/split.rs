@@ -0,0 +1,153 @@
+use crate::dvs::raw_decoder_csv::CsvOptions;
+use crate::dvs::{prep_encoder, prep_file_decoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, DvsRawEncoderEnum, EventFormat};
+use std::cell::Cell;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+/*
+This file implements splitting a recording into consecutive segments, for sharding datasets and for segmented
+streaming. Segments end at multiples of a duration, or once their file reaches a size. Each segment is a
+complete file: the header of the recording is written to every segment, and the encoders derive the
+EVT_TIME_HIGH words of a segment from its own events, so every segment starts with a time base.
+
+Sizes are counted as the encoders write to the file. Encoders buffer a few kilobytes of output, so segments
+can exceed the size by that much. NPZ archives are only written on flush, so they can't be split by size.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 64 * 1024;
+
+// Where segments end
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SplitLimit {
+    // Segments cover consecutive windows of this many microseconds, starting at timestamp 0. Windows
+    // without events are skipped
+    Duration(i64),
+    // A segment ends once this many bytes have been written to its file
+    Bytes(u64),
+}
+
+// A segment written by split_file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub path: String,
+    // Timestamps of the first and last event of the segment
+    pub first_us: i64,
+    pub last_us: i64,
+    pub events: u64,
+    pub bytes: u64,
+}
+
+// Writer that counts the bytes of the file it writes, shared with the splitter while the encoder owns it
+pub(crate) struct CountingWriter<W> {
+    inner: W,
+    position: u64,
+    len: Rc<Cell<u64>>,
+}
+
+impl<W> CountingWriter<W> {
+    // Returns the writer and its byte count
+    pub(crate) fn new(inner: W) -> (Self, Rc<Cell<u64>>) {
+        let len = Rc::new(Cell::new(0));
+        (CountingWriter { inner, position: 0, len: len.clone() }, len)
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.position += n as u64;
+        self.len.set(self.len.get().max(self.position));
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Seek> Seek for CountingWriter<W> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+type SegmentEncoder = DvsRawEncoderEnum<CountingWriter<BufWriter<File>>>;
+
+// Splits a file into segments written to <out_dir>/<file stem>_<segment number>.<format extension>, numbered
+// from 0. The progress callback is invoked with each segment once it is complete
+pub fn split_file<F>(input_path: &str, out_dir: &Path, to: EventFormat, limit: SplitLimit, mut progress: F) -> anyhow::Result<Vec<Segment>>
+where
+    F: FnMut(&Segment),
+{
+    match limit {
+        SplitLimit::Duration(us) if us <= 0 => anyhow::bail!("Segment duration must be positive"),
+        SplitLimit::Bytes(0) => anyhow::bail!("Segment size must be positive"),
+        SplitLimit::Bytes(_) if to == EventFormat::Npz => anyhow::bail!("NPZ output can't be split by size"),
+        // Rows are only written once a whole row group is encoded
+        #[cfg(feature = "parquet")]
+        SplitLimit::Bytes(_) if to == EventFormat::Parquet => anyhow::bail!("Parquet output can't be split by size"),
+        _ => {}
+    }
+    let mut decoder = prep_file_decoder::<BufReader<File>>(input_path)?;
+    let header = decoder.read_header()?;
+    let stem = Path::new(input_path).file_stem().and_then(|s| s.to_str()).unwrap_or("segment").to_string();
+    fs::create_dir_all(out_dir)?;
+
+    let mut segments: Vec<Segment> = Vec::new();
+    // Open segment, its byte count and the duration window it belongs to
+    let mut current: Option<(SegmentEncoder, Segment, Rc<Cell<u64>>, i64)> = None;
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            break;
+        }
+        for event in events.iter() {
+            let window = match limit {
+                SplitLimit::Duration(us) => event.timestamp.div_euclid(us),
+                SplitLimit::Bytes(_) => 0,
+            };
+            let full = match (&current, limit) {
+                (Some((_, _, _, open_window)), SplitLimit::Duration(_)) => window != *open_window,
+                (Some((_, _, len, _)), SplitLimit::Bytes(bytes)) => len.get() >= bytes,
+                (None, _) => false,
+            };
+            if full {
+                if let Some((encoder, segment, len, _)) = current.take() {
+                    segments.push(finish_segment(encoder, segment, &len)?);
+                    progress(segments.last().unwrap());
+                }
+            }
+            let (encoder, segment, _, _) = match &mut current {
+                Some(open) => open,
+                None => {
+                    let path = out_dir.join(format!("{}_{:05}.{}", stem, segments.len(), to.extension()));
+                    let path = path.to_string_lossy().into_owned();
+                    let (writer, len) = CountingWriter::new(BufWriter::new(File::create(&path)?));
+                    let mut encoder = prep_encoder(writer, to, CsvOptions::default())?;
+                    encoder.write_header(header.clone())?;
+                    let segment = Segment { path, first_us: event.timestamp, last_us: event.timestamp, events: 0, bytes: 0 };
+                    current.insert((encoder, segment, len, window))
+                }
+            };
+            encoder.write_event(*event)?;
+            segment.last_us = event.timestamp;
+            segment.events += 1;
+        }
+    }
+    if let Some((encoder, segment, len, _)) = current.take() {
+        segments.push(finish_segment(encoder, segment, &len)?);
+        progress(segments.last().unwrap());
+    }
+    Ok(segments)
+}
+
+fn finish_segment(mut encoder: SegmentEncoder, mut segment: Segment, len: &Cell<u64>) -> anyhow::Result<Segment> {
+    encoder.flush()?;
+    segment.bytes = len.get();
+    Ok(segment)
+}
@@ -0,0 +1,200 @@
+use crate::dvs::loss::json_number;
+use crate::dvs::{header_geometry, DVSEvent, DvsRawDecoder};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write;
+use std::io::{BufRead, Read, Seek};
+
+/*
+This file implements summary statistics of a recording, computed in one streaming pass: the number of events and
+their duration, the mean event rate and the peak rate over a sliding window, the share of ON events, and event
+counts per pixel with the hottest pixels. Events outside the sensor geometry of the header, if it gives one, are
+counted too, see bounds.rs to drop them.
+The sliding window holds the timestamps of the events within it, so it assumes events come in time order.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StatsOptions {
+    // Length of the sliding window of the peak rate, in microseconds
+    pub window_us: i64,
+    // Number of hottest pixels to report
+    pub top_pixels: usize,
+}
+
+impl Default for StatsOptions {
+    fn default() -> Self {
+        StatsOptions {
+            window_us: 1000,
+            top_pixels: 10,
+        }
+    }
+}
+
+// Numbers of ON and OFF events, e.g. before and after a filter
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct PolarityCounts {
+    pub on: u64,
+    pub off: u64,
+}
+
+impl PolarityCounts {
+    pub fn add(&mut self, event: &DVSEvent) {
+        match event.polarity {
+            1 => self.on += 1,
+            _ => self.off += 1,
+        }
+    }
+}
+
+// Statistics of a whole recording
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventStats {
+    pub events: u64,
+    // Timestamps of the first and last events, or None without events
+    pub first_us: Option<i64>,
+    pub last_us: Option<i64>,
+    pub on_events: u64,
+    // Peak number of events within any window of window_us
+    pub peak_window_events: u64,
+    pub window_us: i64,
+    // Sensor width and height from the header, if it gives them
+    pub geometry: Option<(u32, u32)>,
+    // Events outside the geometry
+    pub out_of_bounds: u64,
+    // Number of pixels with at least one event
+    pub active_pixels: u64,
+    // Pixels with the most events, as (x, y, events), most events first
+    pub hottest_pixels: Vec<(i16, i16, u64)>,
+}
+
+impl EventStats {
+    pub fn duration_us(&self) -> i64 {
+        match (self.first_us, self.last_us) {
+            (Some(first), Some(last)) => last - first,
+            _ => 0,
+        }
+    }
+
+    // Mean event rate over the duration, in events per second
+    pub fn mean_rate(&self) -> f64 {
+        self.events as f64 / (self.duration_us() as f64 / 1e6)
+    }
+
+    // Peak event rate over the sliding window, in events per second
+    pub fn peak_rate(&self) -> f64 {
+        self.peak_window_events as f64 / (self.window_us as f64 / 1e6)
+    }
+
+    // Share of ON events, from 0 to 1
+    pub fn polarity_ratio(&self) -> f64 {
+        self.on_events as f64 / self.events as f64
+    }
+
+    pub fn to_json(&self) -> String {
+        let optional = |value: Option<i64>| value.map_or("null".to_string(), |value| value.to_string());
+        let mut json = String::new();
+        let _ = write!(
+            json,
+            "{{\"events\":{},\"first_us\":{},\"last_us\":{},\"duration_us\":{},\"mean_rate\":{},\"peak_rate\":{},\"window_us\":{},",
+            self.events,
+            optional(self.first_us),
+            optional(self.last_us),
+            self.duration_us(),
+            json_number(self.mean_rate()),
+            json_number(self.peak_rate()),
+            self.window_us
+        );
+        let _ = write!(json, "\"on_events\":{},\"off_events\":{},\"polarity_ratio\":{},", self.on_events, self.events - self.on_events, json_number(self.polarity_ratio()));
+        match self.geometry {
+            Some((width, height)) => {
+                let _ = write!(json, "\"width\":{},\"height\":{},", width, height);
+            }
+            None => json.push_str("\"width\":null,\"height\":null,"),
+        }
+        let _ = write!(json, "\"out_of_bounds\":{},\"active_pixels\":{},\"hottest_pixels\":[", self.out_of_bounds, self.active_pixels);
+        for (i, (x, y, events)) in self.hottest_pixels.iter().enumerate() {
+            if i > 0 {
+                json.push(',');
+            }
+            let _ = write!(json, "{{\"x\":{},\"y\":{},\"events\":{}}}", x, y, events);
+        }
+        json.push_str("]}\n");
+        json
+    }
+}
+
+// Accumulates statistics one event at a time
+pub struct StatsCollector {
+    options: StatsOptions,
+    stats: EventStats,
+    // Timestamps of the events in the sliding window
+    window: VecDeque<i64>,
+    pixels: HashMap<(i16, i16), u64>,
+}
+
+impl StatsCollector {
+    // Collects statistics for a sensor of the given geometry, if known
+    pub fn new(options: StatsOptions, geometry: Option<(u32, u32)>) -> Self {
+        Self {
+            options,
+            stats: EventStats { window_us: options.window_us, geometry, ..EventStats::default() },
+            window: VecDeque::new(),
+            pixels: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, event: &DVSEvent) {
+        let stats = &mut self.stats;
+        stats.events += 1;
+        stats.first_us.get_or_insert(event.timestamp);
+        stats.last_us = Some(event.timestamp);
+        if event.polarity == 1 {
+            stats.on_events += 1;
+        }
+        if let Some((width, height)) = stats.geometry {
+            if event.x < 0 || event.y < 0 || event.x as u32 >= width || event.y as u32 >= height {
+                stats.out_of_bounds += 1;
+            }
+        }
+
+        self.window.push_back(event.timestamp);
+        while self.window.front().is_some_and(|first| *first <= event.timestamp - self.options.window_us) {
+            self.window.pop_front();
+        }
+        stats.peak_window_events = stats.peak_window_events.max(self.window.len() as u64);
+
+        *self.pixels.entry((event.x, event.y)).or_insert(0) += 1;
+    }
+
+    pub fn finish(mut self) -> EventStats {
+        let mut pixels: Vec<(i16, i16, u64)> = self.pixels.into_iter().map(|((x, y), events)| (x, y, events)).collect();
+        self.stats.active_pixels = pixels.len() as u64;
+        // Ties are broken by position, so the report doesn't depend on the order of the map
+        pixels.sort_unstable_by(|a, b| b.2.cmp(&a.2).then((a.1, a.0).cmp(&(b.1, b.0))));
+        pixels.truncate(self.options.top_pixels);
+        self.stats.hottest_pixels = pixels;
+        self.stats
+    }
+}
+
+// Reads a whole stream, from its header, and returns its statistics
+pub fn collect_stats<R, D>(decoder: &mut D, options: StatsOptions) -> anyhow::Result<EventStats>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+{
+    let header = decoder.read_header()?;
+    let mut collector = StatsCollector::new(options, header_geometry(&header));
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        events.clear();
+        if decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            return Ok(collector.finish());
+        }
+        for event in &events {
+            collector.add(event);
+        }
+    }
+}
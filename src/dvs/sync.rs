@@ -0,0 +1,136 @@
+use crate::dvs::convert::TranscodeProgress;
+use crate::dvs::merge::{merge_files, MergeOffset};
+use crate::dvs::{prep_file_decoder, DVSEvent, DvsRawDecoder, EventFormat, TriggerEvent};
+use std::cmp::Reverse;
+use std::fs::File;
+use std::io::BufReader;
+
+/*
+This file implements clock synchronization of two recordings, such as the cameras of a stereo pair, from the
+edges of a trigger signal wired to both of them. The cameras timestamp the same edges with their own clocks,
+so the offset between the clocks is the difference between the timestamps of matching edges.
+Either recording can have edges the other misses, e.g. if one camera started later, so edges aren't paired
+by their order. Instead, each of the first edges of one recording is tried against each of the first edges of
+the other, the candidate offset under which the most edges line up within a tolerance wins, and the offset is
+the median difference of the edges it matches. This assumes the clocks don't drift apart over the recordings.
+A periodic trigger lines up equally well under offsets a whole number of periods apart, and the offset closest
+to zero is taken then, as cameras started together have clocks that are close.
+*/
+
+// Number of edges at the start of each recording tried as anchors
+const CANDIDATE_EDGES: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct SyncOptions {
+    // Trigger channel to match, or None for all channels
+    pub channel: Option<u8>,
+    // Edges to match: 1 for rising edges, 0 for falling edges
+    pub value: u8,
+    // Largest difference, in microseconds, between the timestamps of matching edges after the offset
+    pub tolerance_us: i64,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        SyncOptions {
+            channel: None,
+            value: 1,
+            tolerance_us: 100,
+        }
+    }
+}
+
+// Offset of the clock of one recording relative to another
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ClockOffset {
+    // Microseconds to add to the timestamps of the second recording to bring them onto the clock of the first
+    pub offset_us: i64,
+    // Number of edges matched between the recordings
+    pub matched: usize,
+    // Largest difference between matched edges after the offset is applied
+    pub max_error_us: i64,
+}
+
+// Decodes a whole recording, returning its trigger events
+pub fn read_triggers(path: &str) -> anyhow::Result<Vec<TriggerEvent>> {
+    let mut decoder = prep_file_decoder::<BufReader<File>>(path)?;
+    decoder.read_header()?;
+    let mut triggers = decoder.take_triggers();
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(64 * 1024);
+    loop {
+        events.clear();
+        let count = decoder.read_events_into(&mut events, 64 * 1024)?;
+        triggers.extend(decoder.take_triggers());
+        if count == 0 {
+            return Ok(triggers);
+        }
+    }
+}
+
+// Estimates the offset of the clock of the second recording from the trigger events of both. Fails if no two
+// edges line up
+pub fn estimate_offset(first: &[TriggerEvent], second: &[TriggerEvent], options: &SyncOptions) -> anyhow::Result<ClockOffset> {
+    let edges = |triggers: &[TriggerEvent]| -> Vec<i64> {
+        let mut edges: Vec<i64> = triggers
+            .iter()
+            .filter(|trigger| trigger.value == options.value && options.channel.is_none_or(|channel| trigger.id == channel))
+            .map(|trigger| trigger.timestamp)
+            .collect();
+        edges.sort_unstable();
+        edges
+    };
+    let (first, second) = (edges(first), edges(second));
+
+    // Differences of the matched edges under the best candidate offset so far, and the offset
+    let mut best: Option<(Vec<i64>, i64)> = None;
+    for a in first.iter().take(CANDIDATE_EDGES) {
+        for b in second.iter().take(CANDIDATE_EDGES) {
+            let candidate = a - b;
+            let differences = match_edges(&first, &second, candidate, options.tolerance_us);
+            let better = best.as_ref().is_none_or(|(best, best_candidate)| {
+                (differences.len(), Reverse(candidate.unsigned_abs())) > (best.len(), Reverse(best_candidate.unsigned_abs()))
+            });
+            if better {
+                best = Some((differences, candidate));
+            }
+        }
+    }
+    let Some((mut differences, _)) = best.filter(|(differences, _)| differences.len() >= 2) else {
+        anyhow::bail!("No trigger edges line up between the recordings");
+    };
+    differences.sort_unstable();
+    let offset_us = differences[differences.len() / 2];
+    let max_error_us = differences.iter().map(|difference| (difference - offset_us).abs()).max().unwrap_or(0);
+    Ok(ClockOffset { offset_us, matched: differences.len(), max_error_us })
+}
+
+// Pairs each edge of the second recording, shifted by the offset, with the nearest edge of the first within the
+// tolerance, returning the differences between the paired timestamps
+fn match_edges(first: &[i64], second: &[i64], offset_us: i64, tolerance_us: i64) -> Vec<i64> {
+    let mut differences = Vec::new();
+    for b in second {
+        let shifted = b + offset_us;
+        let next = first.partition_point(|a| *a < shifted);
+        let nearest = [next.checked_sub(1), Some(next)]
+            .into_iter()
+            .flatten()
+            .filter_map(|index| first.get(index))
+            .min_by_key(|a| (**a - shifted).abs());
+        if let Some(a) = nearest.filter(|a| (**a - shifted).abs() <= tolerance_us) {
+            differences.push(a - b);
+        }
+    }
+    differences
+}
+
+// Estimates the clock offset between two recordings, see estimate_offset
+pub fn sync_files(first_path: &str, second_path: &str, options: &SyncOptions) -> anyhow::Result<ClockOffset> {
+    estimate_offset(&read_triggers(first_path)?, &read_triggers(second_path)?, options)
+}
+
+// Writes a recording with the offset added to its timestamps, to bring it onto the clock of another
+pub fn restamp_file(input_path: &str, output_path: &str, to: EventFormat, offset: &ClockOffset) -> anyhow::Result<TranscodeProgress> {
+    let offset = MergeOffset { time_us: offset.offset_us, ..MergeOffset::default() };
+    let (totals, _) = merge_files(&[(input_path.to_string(), offset)], output_path, to, |_| {})?;
+    Ok(totals)
+}
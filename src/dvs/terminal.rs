@@ -0,0 +1,317 @@
+use crate::dvs::replay::ReplayClock;
+use crate::dvs::{header_geometry, DVSEvent, DvsRawDecoder};
+use crossterm::cursor::{self, MoveTo};
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::{Color, ResetColor, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType};
+use crossterm::{execute, queue, Command};
+use std::io::{BufRead, IsTerminal, Read, Seek, Write};
+use std::time::{Duration, Instant};
+
+/*
+This file implements a live view of event streams in a terminal, to preview recordings and network streams
+without a GUI. The sensor is scaled down to fit the terminal and drawn with braille characters, which hold 2x4
+dots per character, or with ASCII characters holding 1x2 dots for terminals without braille fonts. Like the
+frames of render.rs, a view shows the events of a fixed duration, aligned to multiples of it, and each dot takes
+the polarity of its latest event: red for ON, blue for OFF, and magenta for characters with dots of both.
+The terminal is driven with crossterm: the screen is cleared once and each view is drawn over the previous one
+from the top left corner, followed by a status line. Programs showing the view on a terminal put it in raw mode
+with RawTerminal, which hides the cursor and stops typed keys from being echoed over the view; q, Esc or Ctrl-C
+then end the view, as Ctrl-C no longer interrupts the process in raw mode.
+Recordings can be played back at the pace of their timestamps (see replay.rs). Live streams are drawn as their
+events arrive.
+*/
+
+// Number of events decoded at a time. Kept small so live streams are drawn without waiting for a large batch
+const BATCH_SIZE: usize = 1024;
+
+const ON_COLOR: Color = Color::DarkRed;
+const OFF_COLOR: Color = Color::DarkBlue;
+const MIXED_COLOR: Color = Color::DarkMagenta;
+// Lines end with a carriage return too, which raw mode doesn't add
+const NEWLINE: &str = "\r\n";
+
+// First braille character, without dots
+const BRAILLE_BASE: u32 = 0x2800;
+// Bits of the braille dots, by row and then column
+const BRAILLE_DOTS: [[u8; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+// ASCII characters for no dot, the top dot, the bottom dot and both
+const ASCII_DOTS: [char; 4] = [' ', '\'', '.', ':'];
+
+// Characters drawing the events
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum TerminalStyle {
+    // 2x4 dots per character
+    #[default]
+    Braille,
+    // 1x2 dots per character
+    Ascii,
+}
+
+impl TerminalStyle {
+    // Dots per character, horizontally and vertically
+    fn cell_size(self) -> (usize, usize) {
+        match self {
+            TerminalStyle::Braille => (2, 4),
+            TerminalStyle::Ascii => (1, 2),
+        }
+    }
+
+    // Bit of the dot at (dx, dy) in a character
+    fn dot_bit(self, dx: usize, dy: usize) -> u8 {
+        match self {
+            TerminalStyle::Braille => BRAILLE_DOTS[dy][dx],
+            TerminalStyle::Ascii => 1 << dy,
+        }
+    }
+}
+
+// Parses braille or ascii
+impl std::str::FromStr for TerminalStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "braille" => Ok(TerminalStyle::Braille),
+            "ascii" => Ok(TerminalStyle::Ascii),
+            _ => anyhow::bail!("Unsupported terminal style '{}'. Expected braille or ascii", s),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct TerminalOptions {
+    // Views drawn per second of event time (or of wall time, when playing back in real time)
+    pub fps: f64,
+    // Plays recordings back at this multiple of real time. Live streams are drawn as their events arrive
+    pub speed: Option<f64>,
+    pub style: TerminalStyle,
+    // Size of the terminal in characters. The last row holds the status line
+    pub columns: usize,
+    pub rows: usize,
+    // Colors events by polarity
+    pub color: bool,
+    // Width and height of the sensor, overriding the header
+    pub geometry: Option<(u32, u32)>,
+}
+
+impl Default for TerminalOptions {
+    fn default() -> Self {
+        TerminalOptions { fps: 30.0, speed: None, style: TerminalStyle::Braille, columns: 80, rows: 24, color: true, geometry: None }
+    }
+}
+
+impl TerminalOptions {
+    // Event time covered by each view, in microseconds
+    pub fn frame_us(&self) -> i64 {
+        ((1e6 / self.fps.max(f64::MIN_POSITIVE)) * self.speed.unwrap_or(1.0)).max(1.0) as i64
+    }
+}
+
+// Dots of a sensor scaled down to the size of a terminal
+pub struct TerminalRaster {
+    style: TerminalStyle,
+    // Sensor pixels per dot, in both directions
+    scale: usize,
+    // Size in dots
+    width: usize,
+    height: usize,
+    // Polarity of the latest event of each dot: 0 without events, 1 for ON and 2 for OFF
+    dots: Vec<u8>,
+}
+
+impl TerminalRaster {
+    // Fits a sensor into columns x rows characters
+    pub fn new(sensor_width: u32, sensor_height: u32, columns: usize, rows: usize, style: TerminalStyle) -> Self {
+        let (cell_width, cell_height) = style.cell_size();
+        let (sensor_width, sensor_height) = (sensor_width as usize, sensor_height as usize);
+        let scale = sensor_width.div_ceil((columns * cell_width).max(1)).max(sensor_height.div_ceil((rows * cell_height).max(1))).max(1);
+        let (width, height) = (sensor_width.div_ceil(scale), sensor_height.div_ceil(scale));
+        TerminalRaster { style, scale, width, height, dots: vec![0; width * height] }
+    }
+
+    // Size of the drawing in characters
+    pub fn size(&self) -> (usize, usize) {
+        let (cell_width, cell_height) = self.style.cell_size();
+        (self.width.div_ceil(cell_width), self.height.div_ceil(cell_height))
+    }
+
+    // Marks the dot of an event. Events outside the sensor are ignored
+    pub fn add(&mut self, event: &DVSEvent) {
+        if event.x < 0 || event.y < 0 {
+            return;
+        }
+        let (x, y) = (event.x as usize / self.scale, event.y as usize / self.scale);
+        if x < self.width && y < self.height {
+            self.dots[y * self.width + x] = if event.polarity == 1 { 1 } else { 2 };
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.dots.fill(0);
+    }
+
+    // Draws the dots as lines of characters, each ending with a newline
+    pub fn draw(&self, color: bool) -> String {
+        let (cell_width, cell_height) = self.style.cell_size();
+        let (columns, rows) = self.size();
+        let mut text = String::new();
+        for row in 0..rows {
+            let mut current_color = None;
+            for column in 0..columns {
+                let (mut bits, mut polarities) = (0u8, 0u8);
+                for dy in 0..cell_height {
+                    for dx in 0..cell_width {
+                        let (x, y) = (column * cell_width + dx, row * cell_height + dy);
+                        let dot = if x < self.width && y < self.height { self.dots[y * self.width + x] } else { 0 };
+                        if dot != 0 {
+                            bits |= self.style.dot_bit(dx, dy);
+                            polarities |= dot;
+                        }
+                    }
+                }
+                let cell_color = match polarities {
+                    1 => Some(ON_COLOR),
+                    2 => Some(OFF_COLOR),
+                    3 => Some(MIXED_COLOR),
+                    _ => None,
+                };
+                if let Some(cell_color) = cell_color.filter(|_| color) {
+                    if current_color != Some(cell_color) {
+                        push_command(&mut text, SetForegroundColor(cell_color));
+                        current_color = Some(cell_color);
+                    }
+                }
+                text.push(match self.style {
+                    TerminalStyle::Braille => char::from_u32(BRAILLE_BASE + bits as u32).unwrap_or(' '),
+                    TerminalStyle::Ascii => ASCII_DOTS[bits as usize],
+                });
+            }
+            if current_color.is_some() {
+                push_command(&mut text, ResetColor);
+            }
+            push_command(&mut text, Clear(ClearType::UntilNewLine));
+            text.push_str(NEWLINE);
+        }
+        text
+    }
+}
+
+// Appends the escape sequence of a command
+fn push_command(text: &mut String, command: impl Command) {
+    // Writing to a String can't fail
+    command.write_ansi(text).ok();
+}
+
+// Reads a whole stream from its header and draws it to out as it goes, see the top of this file. Returns the
+// number of views drawn
+pub fn view<R, D, W>(decoder: &mut D, out: &mut W, options: TerminalOptions) -> anyhow::Result<u64>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+    W: Write,
+{
+    let header = decoder.read_header()?;
+    let Some((width, height)) = options.geometry.or_else(|| header_geometry(&header)) else {
+        anyhow::bail!("Viewing needs the sensor geometry, which the header doesn't give");
+    };
+
+    let mut raster = TerminalRaster::new(width, height, options.columns, options.rows.saturating_sub(1), options.style);
+    let mut clock = options.speed.map(ReplayClock::new);
+    let frame_us = options.frame_us();
+    // Start of the current view and its number of events, once there are events
+    let mut frame: Option<(i64, u64)> = None;
+    let mut frames = 0;
+    queue!(out, Clear(ClearType::All))?;
+    // Returns true instead of drawing if a quit key was pressed
+    let mut draw = |raster: &mut TerminalRaster, start_us: i64, events: u64| -> anyhow::Result<bool> {
+        if wait_or_quit(clock.as_mut().map(|clock| clock.deadline(start_us + frame_us)))? {
+            return Ok(true);
+        }
+        queue!(out, MoveTo(0, 0))?;
+        write!(out, "{}{:.3} s  {} events", raster.draw(options.color), start_us as f64 / 1e6, events)?;
+        queue!(out, Clear(ClearType::UntilNewLine))?;
+        out.flush()?;
+        raster.clear();
+        frames += 1;
+        Ok(false)
+    };
+
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    'read: loop {
+        events.clear();
+        if wait_or_quit(None)? || decoder.read_events_into(&mut events, BATCH_SIZE)? == 0 {
+            break;
+        }
+        for event in &events {
+            let start_us = event.timestamp.div_euclid(frame_us) * frame_us;
+            // Late events are drawn into the current view. Views without events are skipped
+            let (current_us, count) = frame.get_or_insert((start_us, 0));
+            if start_us > *current_us {
+                if draw(&mut raster, *current_us, *count)? {
+                    frame = None;
+                    break 'read;
+                }
+                (*current_us, *count) = (start_us, 0);
+            }
+            raster.add(event);
+            *count += 1;
+        }
+    }
+    if let Some((start_us, count)) = frame {
+        draw(&mut raster, start_us, count)?;
+    }
+    write!(out, "{}", NEWLINE)?;
+    Ok(frames)
+}
+
+// Size in characters of the terminal stdout is written to, if it is one
+pub fn terminal_size() -> Option<(usize, usize)> {
+    if !std::io::stdout().is_terminal() {
+        return None;
+    }
+    terminal::size().ok().map(|(columns, rows)| (columns as usize, rows as usize))
+}
+
+// Raw mode with a hidden cursor on stdout, while it is alive. The terminal is restored when dropped, also after
+// errors
+pub struct RawTerminal(());
+
+impl RawTerminal {
+    pub fn enter() -> anyhow::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let raw = RawTerminal(());
+        execute!(std::io::stdout(), cursor::Hide)?;
+        Ok(raw)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        execute!(std::io::stdout(), ResetColor, cursor::Show).ok();
+        terminal::disable_raw_mode().ok();
+    }
+}
+
+// Waits until the deadline, if any, returning true as soon as q, Esc or Ctrl-C is pressed. Keys are only read in
+// raw mode, where they would otherwise be left for the shell
+fn wait_or_quit(deadline: Option<Instant>) -> anyhow::Result<bool> {
+    if !terminal::is_raw_mode_enabled()? {
+        if let Some(wait) = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now())) {
+            std::thread::sleep(wait);
+        }
+        return Ok(false);
+    }
+    loop {
+        let wait = deadline.map_or(Duration::ZERO, |deadline| deadline.saturating_duration_since(Instant::now()));
+        if !event::poll(wait)? {
+            return Ok(false);
+        }
+        if let Event::Key(key) = event::read()? {
+            let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+            if key.kind == KeyEventKind::Press && (ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc)) {
+                return Ok(true);
+            }
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use crate::dvs::error::DvsError;
+use crate::dvs::filters::DvsFilter;
+use crate::dvs::{header_geometry, set_header_geometry, DVSEvent};
+
+/*
+This file implements geometric transforms of event coordinates, for cameras mounted upside down or sideways.
+Transformer is a filter (see filters.rs) applying a list of transforms to every event, in order, and rewriting
+the geometry in the header, whose width and height swap under a quarter turn. Flips and rotations need the size of the sensor,
+which is read from the header (see header_geometry) or can be given if the header has none. Rotations are
+clockwise, as seen with y pointing down.
+*/
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Transform {
+    // Mirror left and right
+    FlipX,
+    // Mirror top and bottom
+    FlipY,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    // Swap x and y
+    Transpose,
+}
+
+impl Transform {
+    // Applies the transform to a pixel of a sensor of the given size, returning the pixel and the new size
+    pub fn apply(self, x: i32, y: i32, width: u32, height: u32) -> (i32, i32, u32, u32) {
+        let (w, h) = (width as i32, height as i32);
+        match self {
+            Transform::FlipX => (w - 1 - x, y, width, height),
+            Transform::FlipY => (x, h - 1 - y, width, height),
+            Transform::Rotate90 => (h - 1 - y, x, height, width),
+            Transform::Rotate180 => (w - 1 - x, h - 1 - y, width, height),
+            Transform::Rotate270 => (y, w - 1 - x, height, width),
+            Transform::Transpose => (y, x, height, width),
+        }
+    }
+}
+
+// Parses flip-x, flip-y, rotate90, rotate180, rotate270 or transpose
+impl std::str::FromStr for Transform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "flip-x" | "flipx" => Ok(Transform::FlipX),
+            "flip-y" | "flipy" => Ok(Transform::FlipY),
+            "rotate90" => Ok(Transform::Rotate90),
+            "rotate180" => Ok(Transform::Rotate180),
+            "rotate270" => Ok(Transform::Rotate270),
+            "transpose" => Ok(Transform::Transpose),
+            _ => anyhow::bail!("Unsupported transform '{}'. Expected flip-x, flip-y, rotate90, rotate180, rotate270 or transpose", s),
+        }
+    }
+}
+
+// Applies transforms to the coordinates of events
+pub struct Transformer {
+    transforms: Vec<Transform>,
+    // Given with with_geometry, overriding the header
+    fixed_geometry: Option<(u32, u32)>,
+    // Width and height of the input, once the header has been read
+    geometry: (u32, u32),
+}
+
+impl Transformer {
+    pub fn new(transforms: Vec<Transform>) -> Self {
+        Self { transforms, fixed_geometry: None, geometry: (0, 0) }
+    }
+
+    // Transforms a sensor of the given width and height instead of the geometry in the header
+    pub fn with_geometry(mut self, width: u32, height: u32) -> Self {
+        self.fixed_geometry = Some((width, height));
+        self
+    }
+
+    // Width and height of the output, once the header has been read
+    pub fn output_geometry(&self) -> (u32, u32) {
+        let (_, _, width, height) = self.transform_pixel(0, 0);
+        (width, height)
+    }
+
+    fn transform_pixel(&self, x: i32, y: i32) -> (i32, i32, u32, u32) {
+        let (width, height) = self.geometry;
+        self.transforms.iter().fold((x, y, width, height), |(x, y, width, height), transform| transform.apply(x, y, width, height))
+    }
+}
+
+impl DvsFilter for Transformer {
+    fn process(&mut self, event: DVSEvent, out: &mut dyn FnMut(DVSEvent)) {
+        let (x, y, _, _) = self.transform_pixel(event.x as i32, event.y as i32);
+        out(DVSEvent { x: x as i16, y: y as i16, ..event });
+    }
+
+    fn header(&mut self, header: Vec<String>) -> anyhow::Result<Vec<String>> {
+        self.geometry = self.fixed_geometry.or_else(|| header_geometry(&header)).ok_or_else(|| {
+            DvsError::InvalidData("Transforming coordinates needs the sensor geometry, which the header doesn't give".to_string())
+        })?;
+        let (width, height) = self.output_geometry();
+        Ok(set_header_geometry(header, width, height))
+    }
+}
@@ -0,0 +1,371 @@
+use crate::dvs::{DVSEvent, DvsRawEncoder};
+use std::io::{BufRead, BufReader, Seek, Write};
+use std::path::{Path, PathBuf};
+
+/*
+This file implements converting frame videos into event streams, to build event datasets from conventional
+footage without an event camera, in the manner of v2e (Hu, Liu and Delbruck, 2021) without its noise models.
+Each pixel remembers the log intensity at its last event, its reference. When the log intensity of a frame has
+moved away from the reference by the contrast threshold, the pixel fires an event, ON for an increase and OFF for
+a decrease, and the reference moves by the threshold; a change of several thresholds fires several events. The
+times of the events are interpolated linearly between the frames, so the events of fast changes are spread over
+the interval between frames rather than all stamped with the time of the frame. After an event, a pixel fires no
+other event during the refractory period; the crossings it would have fired are dropped, and its reference
+follows them. The first frame only sets the references.
+Frames are read as grayscale: color is converted to luma (Rec. 601) and the log is taken of the intensity plus a
+small offset, which keeps the noise of dark pixels from firing events. Images are read from binary or ASCII
+PGM/PPM files, without dependencies. Videos, and images in other formats, are decoded by an ffmpeg process, which
+must be on the PATH, and piped in as PGM frames.
+*/
+
+// Added to intensities, from 0 to 1, before taking their log, so that dark pixels, whose relative noise is the
+// largest, don't fire bursts of events
+const DARK_OFFSET: f64 = 10.0 / 255.0;
+// Extensions of the images read from directories
+pub const IMAGE_EXTENSIONS: [&str; 3] = ["pgm", "ppm", "pnm"];
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct V2eOptions {
+    // Change of log intensity that fires an event
+    pub contrast_threshold: f64,
+    // Time after an event of a pixel during which it fires no other event, in microseconds
+    pub refractory_us: i64,
+    // Frames per second of the input, which sets the time between frames
+    pub fps: f64,
+}
+
+impl Default for V2eOptions {
+    fn default() -> Self {
+        V2eOptions {
+            contrast_threshold: 0.2,
+            refractory_us: 500,
+            fps: 30.0,
+        }
+    }
+}
+
+impl V2eOptions {
+    pub fn check(&self) -> anyhow::Result<()> {
+        if !self.contrast_threshold.is_finite() || self.contrast_threshold <= 0.0 {
+            anyhow::bail!("The contrast threshold must be positive, got {}", self.contrast_threshold);
+        }
+        if self.refractory_us < 0 {
+            anyhow::bail!("The refractory period can't be negative, got {} us", self.refractory_us);
+        }
+        if !self.fps.is_finite() || self.fps <= 0.0 {
+            anyhow::bail!("The frame rate must be positive, got {}", self.fps);
+        }
+        Ok(())
+    }
+}
+
+// A grayscale image, with intensities from 0 to 1 in row-major order
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrayFrame {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<f32>,
+}
+
+// Reads one byte, or None at the end of the input
+fn read_byte<R: BufRead>(reader: &mut R) -> anyhow::Result<Option<u8>> {
+    let byte = reader.fill_buf()?.first().copied();
+    if byte.is_some() {
+        reader.consume(1);
+    }
+    Ok(byte)
+}
+
+// Reads the next whitespace separated token of a PNM header, skipping # comments, or None at the end of the input
+fn read_token<R: BufRead>(reader: &mut R) -> anyhow::Result<Option<String>> {
+    let mut token = String::new();
+    while let Some(byte) = read_byte(reader)? {
+        match byte {
+            b'#' if token.is_empty() => while !matches!(read_byte(reader)?, Some(b'\n') | None) {},
+            byte if byte.is_ascii_whitespace() => {
+                if !token.is_empty() {
+                    return Ok(Some(token));
+                }
+            }
+            byte => token.push(byte as char),
+        }
+    }
+    Ok((!token.is_empty()).then_some(token))
+}
+
+fn read_number<R: BufRead>(reader: &mut R, name: &str) -> anyhow::Result<u32> {
+    let token = read_token(reader)?.ok_or_else(|| anyhow::anyhow!("PNM image ends before its {}", name))?;
+    token.parse().map_err(|_| anyhow::anyhow!("Invalid PNM {} '{}'", name, token))
+}
+
+// Reads a binary (P5, P6) or ASCII (P2, P3) PGM or PPM image, or returns None at the end of the input. Several
+// images can follow each other, as ffmpeg pipes them
+pub fn read_pnm<R: BufRead>(reader: &mut R) -> anyhow::Result<Option<GrayFrame>> {
+    let Some(magic) = read_token(reader)? else {
+        return Ok(None);
+    };
+    let (channels, binary) = match magic.as_str() {
+        "P2" => (1, false),
+        "P3" => (3, false),
+        "P5" => (1, true),
+        "P6" => (3, true),
+        _ => anyhow::bail!("Unsupported image '{}'. Expected a PGM or PPM image (P2, P3, P5 or P6)", magic),
+    };
+    let width = read_number(reader, "width")?;
+    let height = read_number(reader, "height")?;
+    let max_value = read_number(reader, "maximum value")?;
+    if width == 0 || height == 0 || !(1..=65535).contains(&max_value) {
+        anyhow::bail!("Invalid PNM image of {}x{} with maximum value {}", width, height, max_value);
+    }
+    let samples = width as usize * height as usize * channels;
+    let values: Vec<u32> = if binary {
+        // A single whitespace byte separates the header from the samples, which are big-endian if 16-bit
+        let sample_bytes = if max_value < 256 { 1 } else { 2 };
+        let mut bytes = vec![0u8; samples * sample_bytes];
+        reader.read_exact(&mut bytes).map_err(|e| anyhow::anyhow!("PNM image of {}x{} is truncated: {}", width, height, e))?;
+        match sample_bytes {
+            1 => bytes.iter().map(|&byte| byte as u32).collect(),
+            _ => bytes.chunks_exact(2).map(|pair| u16::from_be_bytes([pair[0], pair[1]]) as u32).collect(),
+        }
+    } else {
+        (0..samples).map(|_| read_number(reader, "sample")).collect::<anyhow::Result<_>>()?
+    };
+    let scale = 1.0 / max_value as f32;
+    let pixels = match channels {
+        1 => values.iter().map(|&value| value.min(max_value) as f32 * scale).collect(),
+        _ => values
+            .chunks_exact(3)
+            .map(|rgb| (0.299 * rgb[0].min(max_value) as f32 + 0.587 * rgb[1].min(max_value) as f32 + 0.114 * rgb[2].min(max_value) as f32) * scale)
+            .collect(),
+    };
+    Ok(Some(GrayFrame { width, height, pixels }))
+}
+
+// Reads a PGM/PPM image file, see read_pnm
+pub fn read_pnm_file(path: &Path) -> anyhow::Result<GrayFrame> {
+    let mut reader = BufReader::new(std::fs::File::open(path)?);
+    read_pnm(&mut reader)?.ok_or_else(|| anyhow::anyhow!("Empty image"))
+}
+
+// The PGM/PPM images of a directory, in file name order
+pub struct ImageSequence {
+    paths: Vec<PathBuf>,
+    next: usize,
+}
+
+impl ImageSequence {
+    // Fails if the directory has no images, by IMAGE_EXTENSIONS
+    pub fn open(dir: &Path) -> anyhow::Result<Self> {
+        let mut paths = Vec::new();
+        for entry in std::fs::read_dir(dir).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", dir.display(), e))? {
+            let path = entry?.path();
+            let extension = path.extension().and_then(|extension| extension.to_str()).unwrap_or("").to_lowercase();
+            if path.is_file() && IMAGE_EXTENSIONS.contains(&extension.as_str()) {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            anyhow::bail!("No PGM or PPM images found in {}", dir.display());
+        }
+        paths.sort();
+        Ok(ImageSequence { paths, next: 0 })
+    }
+}
+
+impl Iterator for ImageSequence {
+    type Item = anyhow::Result<GrayFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let path = self.paths.get(self.next)?;
+        self.next += 1;
+        Some(read_pnm_file(path).map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e)))
+    }
+}
+
+// The frames of a video, or of an image pattern such as frames/%04d.png, decoded by an ffmpeg process at a fixed
+// frame rate: videos are resampled to it, and the images of a pattern are taken to be that far apart
+pub struct VideoFrames {
+    child: std::process::Child,
+    stdout: Option<BufReader<std::process::ChildStdout>>,
+}
+
+impl VideoFrames {
+    pub fn open(input: &str, fps: f64) -> anyhow::Result<Self> {
+        let mut command = std::process::Command::new("ffmpeg");
+        command.args(["-loglevel", "error"]);
+        if input.contains('%') {
+            command.args(["-framerate", &fps.to_string(), "-i", input]);
+        } else {
+            command.args(["-i", input, "-vf", &format!("fps={}", fps)]);
+        }
+        let mut child = command
+            .args(["-f", "image2pipe", "-c:v", "pgm", "-"])
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to start ffmpeg, which reading videos needs: {}", e))?;
+        let stdout = child.stdout.take().map(BufReader::new);
+        Ok(VideoFrames { child, stdout })
+    }
+
+    // Waits for ffmpeg, failing if it did
+    fn finish(&mut self) -> anyhow::Result<()> {
+        drop(self.stdout.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            anyhow::bail!("ffmpeg failed with {}", status);
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for VideoFrames {
+    type Item = anyhow::Result<GrayFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let frame = read_pnm(self.stdout.as_mut()?);
+        match frame {
+            Ok(Some(frame)) => Some(Ok(frame)),
+            Ok(None) => self.finish().err().map(Err),
+            Err(e) => {
+                // Stops ffmpeg rather than leaving it blocked on a full pipe
+                let _ = self.child.kill();
+                let _ = self.finish();
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+impl Drop for VideoFrames {
+    fn drop(&mut self) {
+        if self.stdout.is_some() {
+            let _ = self.child.kill();
+            let _ = self.finish();
+        }
+    }
+}
+
+// Opens the frames of a directory of PGM/PPM images, or of any other input through ffmpeg, see VideoFrames
+pub fn open_frames(input: &str, fps: f64) -> anyhow::Result<Box<dyn Iterator<Item = anyhow::Result<GrayFrame>>>> {
+    match Path::new(input).is_dir() {
+        true => Ok(Box::new(ImageSequence::open(Path::new(input))?)),
+        false => Ok(Box::new(VideoFrames::open(input, fps)?)),
+    }
+}
+
+// Turns frames into events, see the top of this file
+pub struct EventSimulator {
+    width: u32,
+    height: u32,
+    options: V2eOptions,
+    // Log intensity of each pixel at its last event
+    reference: Vec<f64>,
+    // Log intensity of each pixel in the previous frame
+    previous: Vec<f64>,
+    // Time of the last event of each pixel
+    last_event: Vec<i64>,
+    // Frames added so far
+    frames: u64,
+}
+
+impl EventSimulator {
+    pub fn new(width: u32, height: u32, options: V2eOptions) -> Self {
+        let pixels = width as usize * height as usize;
+        EventSimulator {
+            width,
+            height,
+            options,
+            reference: vec![0.0; pixels],
+            previous: vec![0.0; pixels],
+            last_event: vec![i64::MIN; pixels],
+            frames: 0,
+        }
+    }
+
+    // Time of a frame, in microseconds, the first frame being at 0
+    pub fn frame_time(&self, frame: u64) -> i64 {
+        (frame as f64 * 1e6 / self.options.fps).round() as i64
+    }
+
+    // Adds the next frame, appending the events fired since the previous frame to events, in time order
+    pub fn add_frame(&mut self, frame: &GrayFrame, events: &mut Vec<DVSEvent>) -> anyhow::Result<()> {
+        if (frame.width, frame.height) != (self.width, self.height) {
+            anyhow::bail!("Frame {} is {}x{}, expected {}x{}", self.frames, frame.width, frame.height, self.width, self.height);
+        }
+        let log_intensities = frame.pixels.iter().map(|&intensity| (intensity as f64 + DARK_OFFSET).ln());
+        if self.frames == 0 {
+            self.previous = log_intensities.collect();
+            self.reference = self.previous.clone();
+            self.frames = 1;
+            return Ok(());
+        }
+        let (start, end) = (self.frame_time(self.frames - 1), self.frame_time(self.frames));
+        let threshold = self.options.contrast_threshold;
+        let first = events.len();
+        for (pixel, current) in log_intensities.enumerate() {
+            let previous = self.previous[pixel];
+            let (sign, polarity) = if current > self.reference[pixel] { (1.0, 1) } else { (-1.0, 0) };
+            while (current - self.reference[pixel]) * sign >= threshold {
+                let level = self.reference[pixel] + sign * threshold;
+                self.reference[pixel] = level;
+                // When the level was crossed, between the frames
+                let fraction = ((level - previous) / (current - previous)).clamp(0.0, 1.0);
+                let timestamp = start + (fraction * (end - start) as f64).round() as i64;
+                if timestamp.saturating_sub(self.last_event[pixel]) < self.options.refractory_us {
+                    continue;
+                }
+                self.last_event[pixel] = timestamp;
+                let (x, y) = (pixel as u32 % self.width, pixel as u32 / self.width);
+                events.push(DVSEvent { timestamp, x: x as i16, y: y as i16, polarity });
+            }
+            self.previous[pixel] = current;
+        }
+        // Pixels were visited in raster order; the sort is stable, so events at the same time stay in it
+        events[first..].sort_by_key(|event| event.timestamp);
+        self.frames += 1;
+        Ok(())
+    }
+}
+
+// Numbers of frames read and events written by frames_to_events
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct V2eTotals {
+    pub frames: u64,
+    pub events: u64,
+}
+
+// Converts frames into events written to an encoder, with a header giving the size of the frames as the sensor
+// geometry
+pub fn frames_to_events<W, E, I>(frames: I, options: V2eOptions, encoder: &mut E) -> anyhow::Result<V2eTotals>
+where
+    W: Write + Seek,
+    E: DvsRawEncoder<W>,
+    I: IntoIterator<Item = anyhow::Result<GrayFrame>>,
+{
+    options.check()?;
+    let mut simulator: Option<EventSimulator> = None;
+    let mut totals = V2eTotals::default();
+    let mut events = Vec::new();
+    for frame in frames {
+        let frame = frame?;
+        let simulator = match simulator.as_mut() {
+            Some(simulator) => simulator,
+            None => {
+                encoder.write_header(vec![format!("% geometry {}x{}\n", frame.width, frame.height)])?;
+                simulator.insert(EventSimulator::new(frame.width, frame.height, options))
+            }
+        };
+        events.clear();
+        simulator.add_frame(&frame, &mut events)?;
+        for event in &events {
+            encoder.write_event(*event)?;
+        }
+        totals.frames += 1;
+        totals.events += events.len() as u64;
+    }
+    if simulator.is_none() {
+        anyhow::bail!("The input has no frames");
+    }
+    encoder.flush()?;
+    Ok(totals)
+}
@@ -0,0 +1,331 @@
+use crate::dvs::rewind::RewindReader;
+use crate::dvs::{prep_stream_decoder, DvsRawDecoderEnum, EventFormat, FormatHint};
+use std::ffi::{c_int, c_long, c_ulong, c_void};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Chain, Cursor, ErrorKind, Read};
+use std::os::fd::AsRawFd;
+use std::time::{Duration, Instant};
+
+/*
+This file implements capture from event cameras that expose their raw event words over a UVC/V4L2 interface, as
+some research cameras do. The device node (/dev/videoN) is read with V4L2 memory-mapped streaming I/O, which
+uvcvideo supports unlike read(): each buffer the driver fills holds raw EVT2, EVT2.1 or EVT3 words, passed on as
+they are. The words carry no header, so one like those of Prophesee .raw files is written before them, giving the
+format and geometry of the camera, and the stream is decoded like any other (see prep_stream_decoder).
+The device is used in the pixel format it is configured with, e.g. by v4l2-ctl or the camera's own tools.
+Capture runs until the given duration has passed, the device is unplugged, or the capture is dropped, which
+stops streaming.
+Devices are named by URIs, v4l2:<device node> with optional format=<evt2|evt21|evt3> (EVT3 by default),
+geometry=<width>x<height> and duration=<seconds> parameters, e.g. v4l2:/dev/video0?format=evt2&geometry=640x480.
+*/
+
+// Scheme of the URIs naming devices
+pub const V4L2_SCHEME: &str = "v4l2:";
+
+// Buffers requested from the driver. More buffers ride out longer stalls of the reader
+const BUFFER_COUNT: u32 = 8;
+
+// Constants of linux/videodev2.h and sys/mman.h
+const V4L2_BUF_TYPE_VIDEO_CAPTURE: u32 = 1;
+const V4L2_MEMORY_MMAP: u32 = 1;
+const V4L2_CAP_VIDEO_CAPTURE: u32 = 0x00000001;
+const V4L2_CAP_STREAMING: u32 = 0x04000000;
+const V4L2_CAP_DEVICE_CAPS: u32 = 0x80000000;
+const ENODEV: i32 = 19;
+const PROT_READ: c_int = 1;
+const MAP_SHARED: c_int = 1;
+
+// struct v4l2_capability
+#[repr(C)]
+struct V4l2Capability {
+    driver: [u8; 16],
+    card: [u8; 32],
+    bus_info: [u8; 32],
+    version: u32,
+    capabilities: u32,
+    device_caps: u32,
+    reserved: [u32; 3],
+}
+
+// struct v4l2_requestbuffers
+#[repr(C)]
+struct V4l2RequestBuffers {
+    count: u32,
+    buffer_type: u32,
+    memory: u32,
+    capabilities: u32,
+    flags: u8,
+    reserved: [u8; 3],
+}
+
+// struct timeval
+#[repr(C)]
+struct Timeval {
+    tv_sec: c_long,
+    tv_usec: c_long,
+}
+
+// struct v4l2_timecode
+#[repr(C)]
+struct V4l2Timecode {
+    timecode_type: u32,
+    flags: u32,
+    frames: u8,
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    userbits: [u8; 4],
+}
+
+// The m union of struct v4l2_buffer
+#[repr(C)]
+union V4l2BufferM {
+    offset: u32,
+    userptr: c_ulong,
+    planes: *mut c_void,
+    fd: i32,
+}
+
+// struct v4l2_buffer
+#[repr(C)]
+struct V4l2Buffer {
+    index: u32,
+    buffer_type: u32,
+    bytesused: u32,
+    flags: u32,
+    field: u32,
+    timestamp: Timeval,
+    timecode: V4l2Timecode,
+    sequence: u32,
+    memory: u32,
+    m: V4l2BufferM,
+    length: u32,
+    reserved2: u32,
+    request_fd: i32,
+}
+
+// The _IOR, _IOW and _IOWR macros of asm-generic/ioctl.h, for the 'V' ioctls of V4L2
+const fn v4l2_ioctl(direction: c_ulong, number: c_ulong, size: usize) -> c_ulong {
+    (direction << 30) | ((size as c_ulong) << 16) | ((b'V' as c_ulong) << 8) | number
+}
+const IOC_WRITE: c_ulong = 1;
+const IOC_READ: c_ulong = 2;
+const VIDIOC_QUERYCAP: c_ulong = v4l2_ioctl(IOC_READ, 0, size_of::<V4l2Capability>());
+const VIDIOC_REQBUFS: c_ulong = v4l2_ioctl(IOC_READ | IOC_WRITE, 8, size_of::<V4l2RequestBuffers>());
+const VIDIOC_QUERYBUF: c_ulong = v4l2_ioctl(IOC_READ | IOC_WRITE, 9, size_of::<V4l2Buffer>());
+const VIDIOC_QBUF: c_ulong = v4l2_ioctl(IOC_READ | IOC_WRITE, 15, size_of::<V4l2Buffer>());
+const VIDIOC_DQBUF: c_ulong = v4l2_ioctl(IOC_READ | IOC_WRITE, 17, size_of::<V4l2Buffer>());
+const VIDIOC_STREAMON: c_ulong = v4l2_ioctl(IOC_WRITE, 18, size_of::<c_int>());
+const VIDIOC_STREAMOFF: c_ulong = v4l2_ioctl(IOC_WRITE, 19, size_of::<c_int>());
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+    fn mmap(addr: *mut c_void, length: usize, prot: c_int, flags: c_int, fd: c_int, offset: c_long) -> *mut c_void;
+    fn munmap(addr: *mut c_void, length: usize) -> c_int;
+}
+
+// Runs an ioctl on the device, retrying when interrupted by a signal
+fn xioctl<T>(file: &File, request: c_ulong, arg: &mut T) -> std::io::Result<()> {
+    loop {
+        // SAFETY: arg is the struct the request reads and writes, see the VIDIOC_ constants
+        if unsafe { ioctl(file.as_raw_fd(), request, arg as *mut T) } != -1 {
+            return Ok(());
+        }
+        let error = std::io::Error::last_os_error();
+        if error.kind() != ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+}
+
+// A buffer for V4L2 ioctls, all fields zero but its type and memory
+fn capture_buffer() -> V4l2Buffer {
+    // SAFETY: all-zero bytes are a valid v4l2_buffer
+    let mut buffer: V4l2Buffer = unsafe { std::mem::zeroed() };
+    buffer.buffer_type = V4L2_BUF_TYPE_VIDEO_CAPTURE;
+    buffer.memory = V4L2_MEMORY_MMAP;
+    buffer
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct V4l2Options {
+    // Device node, e.g. /dev/video0
+    pub device: String,
+    // Encoding of the raw words: EVT2, EVT2.1 or EVT3
+    pub format: EventFormat,
+    // Sensor geometry (width, height) written to the header, if known
+    pub geometry: Option<(u32, u32)>,
+    // How long to capture for, or None until the capture is dropped
+    pub duration: Option<Duration>,
+}
+
+impl V4l2Options {
+    // Parses a device URI, see the top of this file
+    pub fn from_uri(uri: &str) -> anyhow::Result<Self> {
+        let Some(rest) = uri.strip_prefix(V4L2_SCHEME) else {
+            anyhow::bail!("Invalid device {}. Expected {}<device node>[?format=<evt2|evt21|evt3>&geometry=<width>x<height>&duration=<seconds>]", uri, V4L2_SCHEME);
+        };
+        let (device, parameters) = rest.split_once('?').unwrap_or((rest, ""));
+        if device.is_empty() {
+            anyhow::bail!("Invalid device {}. Expected a device node, e.g. {}/dev/video0", uri, V4L2_SCHEME);
+        }
+        let mut options = V4l2Options { device: device.to_string(), format: EventFormat::Evt3, geometry: None, duration: None };
+        for parameter in parameters.split('&').filter(|parameter| !parameter.is_empty()) {
+            match parameter.split_once('=') {
+                Some(("format", format)) => {
+                    options.format = match format.to_lowercase().as_str() {
+                        "evt2" => EventFormat::Evt2,
+                        "evt21" => EventFormat::Evt21,
+                        "evt3" => EventFormat::Evt3,
+                        _ => anyhow::bail!("Unsupported device format '{}'. Expected evt2, evt21 or evt3", format),
+                    }
+                }
+                Some(("geometry", geometry)) => {
+                    let invalid = || anyhow::anyhow!("Invalid geometry '{}'. Expected <width>x<height>", geometry);
+                    let (width, height) = geometry.split_once('x').ok_or_else(invalid)?;
+                    options.geometry = Some((width.parse().map_err(|_| invalid())?, height.parse().map_err(|_| invalid())?));
+                }
+                Some(("duration", seconds)) => {
+                    let seconds: f64 = seconds.parse().map_err(|_| anyhow::anyhow!("Invalid capture duration '{}'", seconds))?;
+                    options.duration = Some(Duration::try_from_secs_f64(seconds).map_err(|e| anyhow::anyhow!("Invalid capture duration: {}", e))?);
+                }
+                _ => anyhow::bail!("Unsupported device parameter '{}'. Expected format, geometry or duration", parameter),
+            }
+        }
+        Ok(options)
+    }
+
+    // The header written before the raw words
+    fn header(&self, card: &str) -> String {
+        let (name, version) = match self.format {
+            EventFormat::Evt2 => ("EVT2", "2.0"),
+            EventFormat::Evt21 => ("EVT21", "2.1"),
+            _ => ("EVT3", "3.0"),
+        };
+        let mut header = format!("% evt {}\n", version);
+        match self.geometry {
+            Some((width, height)) => header.push_str(&format!("% format {};height={};width={}\n% geometry {}x{}\n", name, height, width, width, height)),
+            None => header.push_str(&format!("% format {}\n", name)),
+        }
+        if !card.is_empty() {
+            header.push_str(&format!("% plugin_name {}\n", card));
+        }
+        header.push_str("% end\n");
+        header
+    }
+}
+
+// The raw words of a V4L2 device, read from the buffers the driver fills. Dropping it stops streaming
+pub struct V4l2Device {
+    file: File,
+    // Name of the device reported by the driver
+    pub card: String,
+    // Memory-mapped buffers, indexed like the driver's
+    buffers: Vec<(*mut c_void, usize)>,
+    // Buffer dequeued from the driver, with the bytes of it already read
+    current: Option<(V4l2Buffer, usize)>,
+    deadline: Option<Instant>,
+}
+
+impl V4l2Device {
+    pub fn open(path: &str, duration: Option<Duration>) -> anyhow::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path).map_err(|e| anyhow::anyhow!("Failed to open {}: {}", path, e))?;
+        // SAFETY: all-zero bytes are a valid v4l2_capability
+        let mut capability: V4l2Capability = unsafe { std::mem::zeroed() };
+        xioctl(&file, VIDIOC_QUERYCAP, &mut capability).map_err(|e| anyhow::anyhow!("{} is not a V4L2 device: {}", path, e))?;
+        let capabilities = match capability.capabilities & V4L2_CAP_DEVICE_CAPS {
+            0 => capability.capabilities,
+            _ => capability.device_caps,
+        };
+        if capabilities & V4L2_CAP_VIDEO_CAPTURE == 0 || capabilities & V4L2_CAP_STREAMING == 0 {
+            anyhow::bail!("{} is not a video capture device supporting streaming I/O", path);
+        }
+        let card = capability.card.iter().position(|b| *b == 0).unwrap_or(capability.card.len());
+        let card = String::from_utf8_lossy(&capability.card[..card]).into_owned();
+
+        let mut request = V4l2RequestBuffers {
+            count: BUFFER_COUNT,
+            buffer_type: V4L2_BUF_TYPE_VIDEO_CAPTURE,
+            memory: V4L2_MEMORY_MMAP,
+            capabilities: 0,
+            flags: 0,
+            reserved: [0; 3],
+        };
+        xioctl(&file, VIDIOC_REQBUFS, &mut request).map_err(|e| anyhow::anyhow!("Failed to allocate the buffers of {}: {}", path, e))?;
+        if request.count == 0 {
+            anyhow::bail!("{} allocated no buffers", path);
+        }
+        let mut device = V4l2Device { file, card, buffers: Vec::new(), current: None, deadline: None };
+        for index in 0..request.count {
+            let mut buffer = capture_buffer();
+            buffer.index = index;
+            xioctl(&device.file, VIDIOC_QUERYBUF, &mut buffer)?;
+            let length = buffer.length as usize;
+            // SAFETY: maps the buffer at the offset the driver gave for it. The mapping is released on drop
+            let data = unsafe { mmap(std::ptr::null_mut(), length, PROT_READ, MAP_SHARED, device.file.as_raw_fd(), buffer.m.offset as c_long) };
+            if data as isize == -1 {
+                anyhow::bail!("Failed to map the buffers of {}: {}", path, std::io::Error::last_os_error());
+            }
+            device.buffers.push((data, length));
+            xioctl(&device.file, VIDIOC_QBUF, &mut buffer)?;
+        }
+        let mut buffer_type = V4L2_BUF_TYPE_VIDEO_CAPTURE as c_int;
+        xioctl(&device.file, VIDIOC_STREAMON, &mut buffer_type).map_err(|e| anyhow::anyhow!("Failed to start streaming from {}: {}", path, e))?;
+        device.deadline = duration.map(|duration| Instant::now() + duration);
+        Ok(device)
+    }
+}
+
+impl Read for V4l2Device {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some((buffer, position)) = &mut self.current {
+                let (data, length) = self.buffers[buffer.index as usize];
+                let used = (buffer.bytesused as usize).min(length);
+                if *position < used {
+                    // SAFETY: the buffer is dequeued, so the driver doesn't write to it, and is used bytes long
+                    let bytes = unsafe { std::slice::from_raw_parts(data as *const u8, used) };
+                    let read = buf.len().min(used - *position);
+                    buf[..read].copy_from_slice(&bytes[*position..*position + read]);
+                    *position += read;
+                    return Ok(read);
+                }
+                // Hand the buffer back to the driver once read
+                let (mut buffer, _) = self.current.take().unwrap();
+                xioctl(&self.file, VIDIOC_QBUF, &mut buffer)?;
+            }
+            if self.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                return Ok(0);
+            }
+            let mut buffer = capture_buffer();
+            match xioctl(&self.file, VIDIOC_DQBUF, &mut buffer) {
+                Ok(()) => {}
+                // The device was unplugged, which ends the stream
+                Err(e) if e.raw_os_error() == Some(ENODEV) => return Ok(0),
+                Err(e) => return Err(e),
+            }
+            self.current = Some((buffer, 0));
+        }
+    }
+}
+
+impl Drop for V4l2Device {
+    fn drop(&mut self) {
+        let mut buffer_type = V4L2_BUF_TYPE_VIDEO_CAPTURE as c_int;
+        let _ = xioctl(&self.file, VIDIOC_STREAMOFF, &mut buffer_type);
+        for (data, length) in self.buffers.drain(..) {
+            // SAFETY: the buffers were mapped in open, and streaming has stopped
+            unsafe { munmap(data, length) };
+        }
+    }
+}
+
+// The raw words of a device, after the header written before them
+pub type V4l2Stream = Chain<Cursor<Vec<u8>>, V4l2Device>;
+
+// Starts capturing from a device, returning a decoder of its events, positioned after the header
+pub fn prep_v4l2_decoder(options: &V4l2Options) -> anyhow::Result<DvsRawDecoderEnum<BufReader<RewindReader<V4l2Stream>>>> {
+    let device = V4l2Device::open(&options.device, options.duration)?;
+    let header = Cursor::new(options.header(&device.card).into_bytes());
+    prep_stream_decoder(header.chain(device), FormatHint::Format(options.format))
+}
@@ -0,0 +1,224 @@
+use crate::dvs::{DVSEvent, DvsRawDecoder};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::io::{BufRead, Read, Seek};
+
+/*
+This file implements checking that the timestamps of a stream never go backwards, and repairing streams in
+which they do, such as recordings with glitches from the sensor or streams reordered in transit. An event is a
+violation if its timestamp is before the latest timestamp seen so far, so a single late event counts once
+rather than making every event after it look out of order.
+Repairs either clamp late events to the latest timestamp, keeping the order of the stream, or reorder events
+within a window, holding each event back until no event within the window can come before it. Events later
+than the window are clamped.
+*/
+
+// Number of events decoded at a time
+const BATCH_SIZE: usize = 64 * 1024;
+
+// An event whose timestamp is before the latest timestamp seen before it
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Violation {
+    // Position of the event in the stream, from 0
+    pub index: u64,
+    pub timestamp: i64,
+    // Timestamp of the event minus the latest timestamp before it. Always negative
+    pub delta_us: i64,
+}
+
+// Outcome of check_monotonic
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MonotonicReport {
+    pub events: u64,
+    // Number of violations, including those past the ones kept in violations
+    pub violation_count: u64,
+    // The first violations, up to the number asked for
+    pub violations: Vec<Violation>,
+    // Largest step back, in microseconds
+    pub max_backwards_us: i64,
+}
+
+impl MonotonicReport {
+    pub fn is_monotonic(&self) -> bool {
+        self.violation_count == 0
+    }
+}
+
+// Follows the latest timestamp of a stream, one event at a time
+#[derive(Debug, Clone, Default)]
+pub struct MonotonicCheck {
+    latest: Option<i64>,
+    index: u64,
+}
+
+impl MonotonicCheck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Returns the violation if the event is before the latest timestamp so far
+    pub fn check(&mut self, event: &DVSEvent) -> Option<Violation> {
+        let index = self.index;
+        self.index += 1;
+        match self.latest {
+            Some(latest) if event.timestamp < latest => Some(Violation { index, timestamp: event.timestamp, delta_us: event.timestamp - latest }),
+            _ => {
+                self.latest = Some(event.timestamp);
+                None
+            }
+        }
+    }
+}
+
+// Reads the rest of a stream, whose header has been read, reporting events that go back in time. Keeps the
+// first max_reported violations
+pub fn check_monotonic<R, D>(decoder: &mut D, max_reported: usize) -> anyhow::Result<MonotonicReport>
+where
+    R: Read + BufRead + Seek,
+    D: DvsRawDecoder<R>,
+{
+    let mut check = MonotonicCheck::new();
+    let mut report = MonotonicReport::default();
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(BATCH_SIZE);
+    loop {
+        events.clear();
+        let count = decoder.read_events_into(&mut events, BATCH_SIZE)?;
+        if count == 0 {
+            return Ok(report);
+        }
+        report.events += count as u64;
+        for event in &events {
+            if let Some(violation) = check.check(event) {
+                report.violation_count += 1;
+                report.max_backwards_us = report.max_backwards_us.max(-violation.delta_us);
+                if report.violations.len() < max_reported {
+                    report.violations.push(violation);
+                }
+            }
+        }
+    }
+}
+
+// How to repair events that go back in time
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RepairMode {
+    // Set late events to the latest timestamp so far
+    Clamp,
+    // Sort events that are at most window_us late back into place. Later ones are clamped
+    Reorder { window_us: i64 },
+}
+
+// Parses "clamp" or "reorder:<window us>"
+impl std::str::FromStr for RepairMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let (name, param) = s.split_once(':').unwrap_or((s, ""));
+        match (name.trim().to_lowercase().as_str(), param.trim()) {
+            ("clamp", "") => Ok(RepairMode::Clamp),
+            ("reorder", window) => match window.parse::<i64>() {
+                Ok(window_us) if window_us >= 0 => Ok(RepairMode::Reorder { window_us }),
+                _ => anyhow::bail!("Invalid reorder window in '{}'", s),
+            },
+            _ => anyhow::bail!("Unsupported repair '{}'. Expected clamp or reorder:<window us>", s),
+        }
+    }
+}
+
+// An event held back for reordering, ordered by timestamp and then by arrival
+#[derive(Debug, Copy, Clone)]
+struct Held {
+    event: DVSEvent,
+    seq: u64,
+}
+
+impl PartialEq for Held {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Held {}
+
+impl PartialOrd for Held {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Held {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.event.timestamp, self.seq).cmp(&(other.event.timestamp, other.seq))
+    }
+}
+
+// Makes a stream monotonic, see RepairMode
+pub struct Repair {
+    mode: RepairMode,
+    buffer: BinaryHeap<Reverse<Held>>,
+    seq: u64,
+    // Latest timestamp passed to out
+    emitted: Option<i64>,
+    // Latest timestamp passed to process
+    latest: Option<i64>,
+    // Number of events whose timestamp was changed
+    pub events_clamped: u64,
+    // Number of events moved before events that came before them
+    pub events_reordered: u64,
+}
+
+impl Repair {
+    pub fn new(mode: RepairMode) -> Self {
+        Self {
+            mode,
+            buffer: BinaryHeap::new(),
+            seq: 0,
+            emitted: None,
+            latest: None,
+            events_clamped: 0,
+            events_reordered: 0,
+        }
+    }
+
+    // Passes an event on, clamped to the latest timestamp passed to out
+    fn emit(&mut self, mut event: DVSEvent, out: &mut impl FnMut(DVSEvent)) {
+        if let Some(emitted) = self.emitted.filter(|emitted| event.timestamp < *emitted) {
+            event.timestamp = emitted;
+            self.events_clamped += 1;
+        }
+        self.emitted = Some(event.timestamp);
+        out(event);
+    }
+
+    // Takes the next event of the stream, passing any events that are ready to out
+    pub fn process(&mut self, event: DVSEvent, out: &mut impl FnMut(DVSEvent)) {
+        let window_us = match self.mode {
+            RepairMode::Clamp => return self.emit(event, out),
+            RepairMode::Reorder { window_us } => window_us,
+        };
+        if self.latest.is_some_and(|latest| event.timestamp < latest) && self.emitted.is_none_or(|emitted| event.timestamp >= emitted) {
+            self.events_reordered += 1;
+        }
+        let latest = self.latest.map_or(event.timestamp, |latest| latest.max(event.timestamp));
+        self.latest = Some(latest);
+        self.buffer.push(Reverse(Held { event, seq: self.seq }));
+        self.seq += 1;
+
+        // Events more than the window before the latest timestamp can't have any event still to come before them
+        while let Some(Reverse(held)) = self.buffer.peek() {
+            if held.event.timestamp.saturating_add(window_us) >= latest {
+                break;
+            }
+            if let Some(Reverse(held)) = self.buffer.pop() {
+                self.emit(held.event, out);
+            }
+        }
+    }
+
+    // Passes all held events to out
+    pub fn finish(&mut self, out: &mut impl FnMut(DVSEvent)) {
+        while let Some(Reverse(held)) = self.buffer.pop() {
+            self.emit(held.event, out);
+        }
+    }
+}
@@ -1,85 +1,2079 @@
-use std::io::BufReader;
-use dvs::dvs::{prep_file_decoder, prep_file_encoder, DvsRawDecoder, DvsRawEncoder, DVSEvent};
-use clap::Parser;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, Write};
+use std::process::ExitCode;
+use std::time::Duration;
+use dvs::dvs::arq::{Arq, ArqDelivery, ArqOptions};
+use dvs::dvs::batch::{find_inputs, input_root, is_glob, output_path, BatchCounts, BatchReport};
+use dvs::dvs::bitrate::{format_bits_per_event, BandwidthBudget};
+use dvs::dvs::bounds::{Bounds, BoundsMode};
+#[cfg(feature = "capture")]
+use dvs::dvs::capture::{prep_capture_decoder, CaptureOptions};
+#[cfg(feature = "inivation")]
+use dvs::dvs::inivation::{CameraConfig, InivationCapture};
+#[cfg(all(feature = "v4l2", target_os = "linux"))]
+use dvs::dvs::v4l2::{prep_v4l2_decoder, V4l2Options};
+#[cfg(feature = "viz")]
+use dvs::dvs::compare::compare_png_sequence;
+use dvs::dvs::compress::Compression;
+use dvs::dvs::config::{ConfigValue, ExperimentConfig};
+use dvs::dvs::convert::{cut_file, write_with_triggers};
+use dvs::dvs::dataset::{partition_recordings, partition_segments, write_manifests, Split, SplitRatios};
+use dvs::dvs::error::DvsError;
+use dvs::dvs::filters::{DvsFilter, Filtered, HotPixelFilter, HotPixelOptions, PolarityFilter, PolarityMode, Roi, RoiFilter};
+use dvs::dvs::pipeline::{DvsFilters, Pipeline as FilterPipeline, StageConfig};
+use dvs::dvs::index::EventIndex;
+use dvs::dvs::interpolate::{InterpolationStrategy, Interpolator};
+use dvs::dvs::merge::{merge_files, MergeOffset};
+#[cfg(feature = "viz")]
+use dvs::dvs::metrics::{compare_streams, MetricsOptions};
+use dvs::dvs::entropy::{EntropyCoding, EntropyOptions, EntropyStats, DEFAULT_TRAIN_PACKETS};
+use dvs::dvs::jitter::{DelayDistribution, Jitter, JitterOptions};
+use dvs::dvs::packet::PacketizerOptions;
+use dvs::dvs::loss::{GilbertElliottParams, LossFilter, LossModel, LossModels, LossOptions, LossParams};
+use dvs::dvs::quantize::{QuantizeFilter, QuantizeOptions};
+use dvs::dvs::rate::rate_series;
+#[cfg(feature = "viz")]
+use dvs::dvs::reconstruct::{reconstruct_png_sequence, ReconstructOptions};
+use dvs::dvs::record::{record, RecordOptions};
+use dvs::dvs::recover::Recover;
+#[cfg(feature = "viz")]
+use dvs::dvs::render::{is_video_path, render_png_sequence, Colormap, RenderOptions};
+use dvs::dvs::replay::{self, Replay};
+use dvs::dvs::rng::derive_seed;
+use dvs::dvs::representation::{export_time_surfaces, export_voxel_grids, TensorFormat, TimeSurfaceOptions, VoxelGridOptions};
+use dvs::dvs::split::{split_file, SplitLimit};
+use dvs::dvs::stats::{collect_stats, StatsOptions};
+use dvs::dvs::sync::{restamp_file, sync_files, SyncOptions};
+#[cfg(feature = "viz")]
+use dvs::dvs::terminal::{terminal_size, view, RawTerminal, TerminalOptions, TerminalStyle};
+#[cfg(feature = "gui")]
+use dvs::dvs::gui::{play, GuiOptions, Player};
+use dvs::dvs::transforms::{Transform, Transformer};
+#[cfg(feature = "v2e")]
+use dvs::dvs::v2e::{frames_to_events, open_frames, V2eOptions};
+use dvs::dvs::validate::{MonotonicCheck, Repair, RepairMode};
+use dvs::dvs::raw_decoder_csv::{CsvColumn, CsvOptions, TimeUnit};
+use dvs::dvs::{prep_file_decoder, prep_file_encoder_compressed, prep_file_encoder_with_options, prep_follow_decoder, prep_stream_decoder, DvsRawDecoder, DvsRawEncoder, DVSEvent, EventFormat, FormatHint, TriggerEvent};
+use clap::{ArgAction, ArgMatches, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
 
 pub type Timestamp = u64;
 // Struct to help with parsing command line args
 #[derive(Parser, Default, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
-    // Input event stream file path
-    #[arg(short = 'f', long = "file")]
-    file_path: String,
-    // Output file path (Optional. Default: <input_file>_loss.bin)
+    /// Run a subcommand instead of transcoding a single file
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Transcode a single file without a subcommand, as with the transcode subcommand
+    #[command(flatten)]
+    transcode: TranscodeArgs,
+    /// Report errors on stderr as a single JSON object (Optional. Default: false)
+    #[arg(long = "json-errors", global = true)]
+    json_errors: bool,
+    /// Log what the decoders and stages do on stderr, -vv to also trace each step (Optional. Default: warnings
+    /// only)
+    #[arg(short = 'v', long = "verbose", action = ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Don't log warnings, only report errors (Optional. Default: false)
+    #[arg(short = 'q', long = "quiet", global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+// Options of transcoding a stream, optionally through the simulation stages (loss, retransmission, jitter,
+// interpolation)
+#[derive(Args, Default, Debug)]
+struct TranscodeArgs {
+    /// Read options from a TOML configuration file, see the README. Options given on the command line take
+    /// precedence (Optional)
+    #[arg(long = "config")]
+    config_path: Option<String>,
+    /// Write every option of the run, as resolved from the command line, --config and the defaults, to this TOML
+    /// file, which --config reads back, or to stdout for - (Optional)
+    #[arg(long = "dump-config")]
+    dump_config_path: Option<String>,
+    // Filters run after the transforms, from the [[stage]] tables of --config
+    #[arg(skip)]
+    stages: Vec<StageConfig>,
+    // The options of the run, as --dump-config writes them
+    #[arg(skip)]
+    resolved_config: ExperimentConfig,
+    /// Input event stream file path, or - to read from stdin. A directory or a glob pattern (quoted, e.g.
+    /// 'data/**/*.raw') transcodes every recording it names into the --output directory. With the capture
+    /// feature, prophesee://[serial] captures from a live camera, with the inivation feature
+    /// inivation://<model>[/serial] from a live iniVation camera, and with the v4l2 feature v4l2:/dev/videoN from
+    /// a V4L2/UVC device sending raw EVT3 (or ?format=evt2, evt21) words
+    #[arg(short = 'f', long = "file", alias = "input")]
+    file_path: Option<String>,
+    /// Output file path, or output directory when transcoding several recordings
     #[arg(short = 'o', long = "output")]
+    output_path: Option<String>,
+    /// Also transcode the recordings in subdirectories of an input directory (Optional. Default: false)
+    #[arg(short = 'r', long = "recursive")]
+    recursive: bool,
+    /// Recordings transcoded at a time when transcoding several, or 0 for one per CPU core (Optional. Default: 0)
+    #[arg(short = 'j', long = "jobs", default_value_t = 0)]
+    jobs: usize,
+    /// Write the outcome of each recording transcoded to this path, as JSON for .json paths and CSV otherwise
+    /// (Optional)
+    #[arg(long = "batch-report")]
+    batch_report_path: Option<String>,
+    /// Output event format, evt2, evt21, evt3, dat, csv, tsv, npy, npz, mcap, delta, or parquet with the parquet
+    /// feature (Optional. Default: from the output file extension, or evt2)
+    #[arg(long = "format")]
+    format: Option<EventFormat>,
+    /// Compress the output with gzip or zstd, adding .gz or .zst to output file names in a directory (Optional.
+    /// Default: from the output file extension, uncompressed unless it ends in .gz or .zst)
+    #[arg(long = "compress")]
+    compression: Option<Compression>,
+    /// Column order of csv/tsv output (Optional. Default: t,x,y,p)
+    #[arg(long = "csv-columns", default_value = "t,x,y,p")]
+    csv_columns: String,
+    /// Timestamp unit of csv/tsv output, s, ms, us or ns (Optional. Default: us)
+    #[arg(long = "csv-time-unit", default_value = "us")]
+    csv_time_unit: TimeUnit,
+    /// Follow the input file while it is still being written (Optional. Default: false)
+    #[arg(long = "follow")]
+    follow: bool,
+    /// Seconds without new data before a followed file is considered complete (Optional. Default: 5)
+    #[arg(long = "follow-timeout", default_value_t = 5)]
+    follow_timeout: u64,
+    /// Keep the events of an input that is cut off in the middle of an event, instead of failing (Optional.
+    /// Default: false)
+    #[arg(long = "recover")]
+    recover: bool,
+    /// Drop or fail on events outside the sensor geometry given in the input header, drop or error (Optional.
+    /// Default: no check)
+    #[arg(long = "bounds")]
+    bounds: Option<BoundsMode>,
+    /// Transform event coordinates, e.g. for a camera mounted upside down, with a comma separated list of flip-x,
+    /// flip-y, rotate90, rotate180, rotate270 and transpose applied in order. Needs the sensor geometry in the
+    /// input header (Optional. Default: no transform)
+    #[arg(long = "transform", value_delimiter = ',')]
+    transforms: Vec<Transform>,
+    /// Replay the input in real time, sleeping so events are emitted when they happened, optionally sped up
+    /// by a factor from 0.1 to 100 (Optional. Default: as fast as possible, or 1 if given without a factor)
+    #[arg(long = "realtime", num_args = 0..=1, default_missing_value = "1.0")]
+    realtime: Option<f64>,
+    /// Seed of all the random stages (loss models, retransmissions, jitter), each drawing its own stream from it,
+    /// so the same seed gives the same output. --loss-seed, --arq-seed and --jitter-seed override it for one
+    /// stage (Optional. Default: the stages are seeded with 0)
+    #[arg(long = "seed")]
+    seed: Option<u64>,
+    /// Simulate streaming over a link of this many megabits per second, dropping events that don't fit
+    /// (Optional. Default: no loss)
+    #[arg(long = "bandwidth")]
+    bandwidth_mbps: Option<f64>,
+    /// Write a loss report with per-chunk statistics to this path, as CSV for .csv paths and JSON otherwise
+    /// (Optional)
+    #[arg(long = "report")]
+    report_path: Option<String>,
+    /// Size of an event on the simulated link, in bits (Optional. Default: typical size in the output format)
+    #[arg(long = "bits-per-event")]
+    bits_per_event: Option<f64>,
+    /// Payload of each packet on the simulated link, in bytes, or 0 to ignore packet overhead (Optional.
+    /// Default: 1472, a full UDP datagram over Ethernet)
+    #[arg(long = "packet-size", default_value_t = 1472)]
+    packet_size: usize,
+    /// Header bytes added to each packet (Optional. Default: 28, IPv4 and UDP headers)
+    #[arg(long = "packet-overhead", default_value_t = 28)]
+    packet_overhead: usize,
+    /// Loss model deciding which events are dropped, end-biased, evenly-distributed, age-weighted, spatial,
+    /// random, token-bucket, gilbert-elliott, adaptive or adaptive-throughput (Optional. Default: end-biased if a bandwidth is given)
+    #[arg(long = "loss-type", alias = "loss")]
+    loss_type: Option<String>,
+    /// Instead of dropping events to fit --bandwidth, merge them into bins of pixels and time, picking the finest
+    /// bins that fit for each chunk (Optional. Default: false)
+    #[arg(long = "quantize", requires = "bandwidth_mbps", conflicts_with = "loss_type")]
+    quantize: bool,
+    /// Drop probability of the random loss model (Optional. Default: 0.1)
+    #[arg(long = "loss-probability", default_value_t = 0.1)]
+    loss_probability: f64,
+    /// Seed of the random and gilbert-elliott loss models, and of loss stages without a seed (Optional. Default:
+    /// derived from --seed, or 0)
+    #[arg(long = "loss-seed")]
+    loss_seed: Option<u64>,
+    /// Burst size of the token-bucket loss model, in bits (Optional. Default: 100000)
+    #[arg(long = "burst", default_value_t = 100_000.0)]
+    burst_bits: f64,
+    /// Tile size of the spatial loss model, in pixels (Optional. Default: 32)
+    #[arg(long = "tile-size", default_value_t = 32)]
+    tile_size: u16,
+    /// Per-event probability of the gilbert-elliott model moving from the good to the bad state (Optional.
+    /// Default: 0.01)
+    #[arg(long = "ge-good-to-bad", default_value_t = 0.01)]
+    ge_good_to_bad: f64,
+    /// Per-event probability of moving from the bad to the good state (Optional. Default: 0.1)
+    #[arg(long = "ge-bad-to-good", default_value_t = 0.1)]
+    ge_bad_to_good: f64,
+    /// Drop probability in the good state (Optional. Default: 0)
+    #[arg(long = "ge-loss-good", default_value_t = 0.0)]
+    ge_loss_good: f64,
+    /// Drop probability in the bad state (Optional. Default: 1)
+    #[arg(long = "ge-loss-bad", default_value_t = 1.0)]
+    ge_loss_bad: f64,
+    /// Time covered by each loss chunk, in microseconds (Optional. Default: 10000)
+    #[arg(long = "loss-chunk", default_value_t = 10000)]
+    loss_chunk_us: i64,
+    /// Simulate sending packets of events with retransmissions, losing each transmission with this
+    /// probability, and re-emit events in order of arrival (Optional. Default: no retransmission simulation)
+    #[arg(long = "arq-loss")]
+    arq_loss: Option<f64>,
+    /// Round trip time of the simulated link, in microseconds (Optional. Default: 20000)
+    #[arg(long = "arq-rtt", default_value_t = 20_000)]
+    arq_rtt_us: i64,
+    /// Retransmissions of a packet before it is given up on (Optional. Default: 3)
+    #[arg(long = "arq-retries", default_value_t = 3)]
+    arq_retries: u32,
+    /// Longest time covered by a packet of the retransmission simulation, in microseconds, which bounds the
+    /// time events wait for their packet to fill (Optional. Default: packets are only limited by --packet-size)
+    #[arg(long = "arq-packet-span")]
+    arq_packet_span_us: Option<i64>,
+    /// Seed of the simulated transmission losses (Optional. Default: derived from --seed, or 0)
+    #[arg(long = "arq-seed")]
+    arq_seed: Option<u64>,
+    /// Write the arrival time and latency of each event delivered by the retransmission simulation to this
+    /// CSV file (Optional)
+    #[arg(long = "latency-report", requires = "arq_loss")]
+    latency_report_path: Option<String>,
+    /// Measure how small each packet of --packet-size bytes gets when entropy coded on its own, with range or
+    /// zstd-dict (Optional. Default: no entropy coding)
+    #[arg(long = "entropy")]
+    entropy: Option<EntropyCoding>,
+    /// Write the per-chunk sizes of the entropy coded packets to this path, as CSV for .csv paths and JSON
+    /// otherwise (Optional)
+    #[arg(long = "entropy-report", requires = "entropy")]
+    entropy_report_path: Option<String>,
+    /// Packets at the start of the stream the entropy coding model is trained on (Optional. Default: 256)
+    #[arg(long = "entropy-train", default_value_t = DEFAULT_TRAIN_PACKETS)]
+    entropy_train_packets: usize,
+    /// Delay each event by a random amount drawn from fixed:<us>, uniform:<min>,<max>, normal:<mean>,<std dev>
+    /// or pareto:<scale>,<shape>, and re-emit events in order of arrival (Optional. Default: no jitter)
+    #[arg(long = "jitter")]
+    jitter: Option<DelayDistribution>,
+    /// Replace timestamps with arrival times instead of keeping the original timestamps (Optional. Default: false)
+    #[arg(long = "jitter-restamp")]
+    jitter_restamp: bool,
+    /// Seed of the jitter delays (Optional. Default: derived from --seed, or 0)
+    #[arg(long = "jitter-seed")]
+    jitter_seed: Option<u64>,
+    /// Fill gaps between surviving events of a lossy stream, linear or hold (Optional. Default: off)
+    #[arg(long = "interpolate")]
+    interpolate: Option<InterpolateArg>,
+    /// Upsampling factor for linear interpolation (Optional. Default: 2)
+    #[arg(long = "interpolate-factor", default_value_t = 2)]
+    interpolate_factor: u32,
+    /// Repeat interval in microseconds for hold interpolation (Optional. Default: 1000)
+    #[arg(long = "interpolate-interval", default_value_t = 1000)]
+    interpolate_interval: i64,
+    /// Largest gap in microseconds between events of a pixel that is interpolated (Optional. Default: 10000)
+    #[arg(long = "interpolate-max-gap", default_value_t = 10000)]
+    interpolate_max_gap: i64,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Transcode a recording or a stream to another format, optionally simulating a lossy link on the way
+    #[command(visible_alias = "loss")]
+    Transcode(Box<TranscodeArgs>),
+    /// Assign recordings, or time segments of recordings, to reproducible train/val/test splits
+    Partition {
+        /// Input event stream file paths
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Directory for the split manifests (and cut segments)
+        #[arg(short = 'o', long = "output")]
+        out_dir: String,
+        /// Seed mixed into the content hashes (Optional. Default: 0)
+        #[arg(long = "seed", default_value_t = 0)]
+        seed: u64,
+        /// Relative size of the train split (Optional. Default: 0.8)
+        #[arg(long = "train", default_value_t = 0.8)]
+        train: f64,
+        /// Relative size of the validation split (Optional. Default: 0.1)
+        #[arg(long = "val", default_value_t = 0.1)]
+        val: f64,
+        /// Relative size of the test split (Optional. Default: 0.1)
+        #[arg(long = "test", default_value_t = 0.1)]
+        test: f64,
+        /// Split recordings into segments of this many seconds instead of assigning whole files (Optional)
+        #[arg(long = "segment")]
+        segment_secs: Option<f64>,
+        /// Write each segment to <output>/<split>/ (Optional. Default: false)
+        #[arg(long = "cut")]
+        cut: bool,
+        /// Format of cut segments, evt2, evt3, dat, csv, tsv, npy or npz (Optional. Default: evt2)
+        #[arg(long = "format", default_value = "evt2")]
+        format: EventFormat,
+    },
+    /// Write the events of a time window of a recording to a new file
+    Cut {
+        /// Input event stream file path
+        input: String,
+        /// Output file path
+        #[arg(short = 'o', long = "output")]
+        output: String,
+        /// Start of the window, in seconds (Optional. Default: 0)
+        #[arg(long = "start", default_value_t = 0.0)]
+        start_secs: f64,
+        /// End of the window, in seconds, excluded (Optional. Default: the end of the recording)
+        #[arg(long = "end")]
+        end_secs: Option<f64>,
+        /// Output event format (Optional. Default: from the output file extension, or evt2)
+        #[arg(long = "format")]
+        format: Option<EventFormat>,
+    },
+    /// Interleave several recordings by timestamp into one file
+    Merge {
+        /// Input event stream file paths
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Output file path
+        #[arg(short = 'o', long = "output")]
+        output: String,
+        /// Microseconds added to the timestamps of each input, comma separated in input order (Optional. Default: 0)
+        #[arg(long = "time-offset", value_delimiter = ',', allow_negative_numbers = true)]
+        time_offsets: Vec<i64>,
+        /// Pixels added to the x coordinates of each input, comma separated in input order (Optional. Default: 0)
+        #[arg(long = "x-offset", value_delimiter = ',', allow_negative_numbers = true)]
+        x_offsets: Vec<i16>,
+        /// Pixels added to the y coordinates of each input, comma separated in input order (Optional. Default: 0)
+        #[arg(long = "y-offset", value_delimiter = ',', allow_negative_numbers = true)]
+        y_offsets: Vec<i16>,
+        /// Output event format (Optional. Default: from the output file extension, or evt2)
+        #[arg(long = "format")]
+        format: Option<EventFormat>,
+    },
+    /// Split a recording into segments of a duration or size, written to <output>/<input stem>_<number>
+    Split {
+        /// Input event stream file path
+        input: String,
+        /// Directory for the segments
+        #[arg(short = 'o', long = "output")]
+        out_dir: String,
+        /// Segment duration in seconds
+        #[arg(long = "duration", required_unless_present = "size_mb", conflicts_with = "size_mb")]
+        duration_secs: Option<f64>,
+        /// Segment size in megabytes
+        #[arg(long = "size")]
+        size_mb: Option<f64>,
+        /// Format of the segments, evt2, evt3, dat, csv, tsv, npy, npz, mcap or delta (Optional. Default: evt2)
+        #[arg(long = "format", default_value = "evt2")]
+        format: EventFormat,
+    },
+    /// Record a live or network stream to <output>/<name>.<extension>, optionally rotating files by duration or
+    /// size. Inputs are those of view; files are followed as they grow. Files are written as .part files until
+    /// complete
+    Record {
+        /// Input event stream
+        input: String,
+        /// Directory for the recorded files
+        #[arg(short = 'o', long = "output")]
+        out_dir: String,
+        /// Name of the recorded files, numbered <name>_<number> when rotating (Optional. Default: recording)
+        #[arg(long = "name", default_value = "recording")]
+        name: String,
+        /// Format of the recorded files, evt2, evt21, evt3, dat, csv, tsv, npy or delta (Optional. Default: evt2)
+        #[arg(long = "format", default_value = "evt2")]
+        format: EventFormat,
+        /// Start a new file at every multiple of this many seconds of event time
+        #[arg(long = "rotate-duration", conflicts_with = "rotate_mb")]
+        rotate_secs: Option<f64>,
+        /// Start a new file once a file reaches this many megabytes, counted before compression
+        #[arg(long = "rotate-size")]
+        rotate_mb: Option<f64>,
+        /// Compression of the recorded files, none, gzip or zstd (with the compression feature) (Optional. Default: none)
+        #[arg(long = "compress", default_value = "none")]
+        compression: Compression,
+        /// Seconds between flushes of the recorded events to disk (Optional. Default: 1)
+        #[arg(long = "flush-interval", default_value_t = 1.0)]
+        flush_secs: f64,
+        /// Seconds without new data before a followed file is considered complete (Optional. Default: 5)
+        #[arg(long = "follow-timeout", default_value_t = 5)]
+        follow_timeout: u64,
+    },
+    /// Estimate the clock offset between two recordings from shared trigger edges
+    Sync {
+        /// Reference recording
+        first: String,
+        /// Recording whose clock offset is estimated
+        second: String,
+        /// Trigger channel to match (Optional. Default: all channels)
+        #[arg(long = "channel")]
+        channel: Option<u8>,
+        /// Match falling instead of rising edges (Optional. Default: false)
+        #[arg(long = "falling")]
+        falling: bool,
+        /// Largest difference between matching edges, in microseconds (Optional. Default: 100)
+        #[arg(long = "tolerance", default_value_t = 100)]
+        tolerance_us: i64,
+        /// Write the second recording re-stamped onto the clock of the first to this path (Optional)
+        #[arg(short = 'o', long = "output")]
+        output: Option<String>,
+        /// Output event format (Optional. Default: from the output file extension, or evt2)
+        #[arg(long = "format")]
+        format: Option<EventFormat>,
+    },
+    /// Check that the timestamps of a recording never go backwards, and optionally write a repaired copy
+    Validate {
+        /// Input event stream file path
+        input: String,
+        /// Number of violations to list (Optional. Default: 10)
+        #[arg(long = "max-reported", default_value_t = 10)]
+        max_reported: usize,
+        /// Repair the stream: clamp or reorder:<window us> (Optional. Requires --output)
+        #[arg(long = "repair", requires = "output")]
+        repair: Option<RepairMode>,
+        /// Output file path for the repaired stream
+        #[arg(short = 'o', long = "output")]
+        output: Option<String>,
+        /// Output event format (Optional. Default: from the output file extension, or evt2)
+        #[arg(long = "format")]
+        format: Option<EventFormat>,
+    },
+    /// Print statistics of a recording as JSON: event count, duration, mean and peak rates, polarity and hottest pixels
+    Stats {
+        /// Input event stream file path
+        input: String,
+        /// Sliding window of the peak rate, in milliseconds (Optional. Default: 1)
+        #[arg(long = "window", default_value_t = 1.0)]
+        window_ms: f64,
+        /// Number of hottest pixels to list (Optional. Default: 10)
+        #[arg(long = "top", default_value_t = 10)]
+        top_pixels: usize,
+        /// Write the JSON to this path instead of stdout (Optional)
+        #[arg(short = 'o', long = "output")]
+        output: Option<String>,
+    },
+    /// Write the event rate and bitrate of a recording over time, as CSV, or JSON for .json output paths
+    Rate {
+        /// Input event stream file path
+        input: String,
+        /// Length of each window, in milliseconds (Optional. Default: 1)
+        #[arg(long = "window", default_value_t = 1.0)]
+        window_ms: f64,
+        /// Format whose event size the bitrate is computed for (Optional. Default: evt2)
+        #[arg(long = "format", default_value = "evt2")]
+        format: EventFormat,
+        /// Size of an event, in bits (Optional. Default: typical size in --format)
+        #[arg(long = "bits-per-event")]
+        bits_per_event: Option<f64>,
+        /// Payload of each packet, in bytes, or 0 to ignore packet overhead (Optional. Default: 1472)
+        #[arg(long = "packet-size", default_value_t = 1472)]
+        packet_size: usize,
+        /// Header bytes added to each packet (Optional. Default: 28)
+        #[arg(long = "packet-overhead", default_value_t = 28)]
+        packet_overhead: usize,
+        /// Write the series to this path instead of stdout (Optional)
+        #[arg(short = 'o', long = "output")]
+        output: Option<String>,
+    },
+    /// Write a copy of a recording without noise
+    Filter {
+        /// Input event stream file path
+        input: String,
+        /// Output file path
+        #[arg(short = 'o', long = "output")]
+        output: String,
+        /// Run the stages of a pipeline description file, before those given by the options below (Optional)
+        #[arg(long = "pipeline")]
+        pipeline_path: Option<String>,
+        /// Drop the events of hot pixels, whose rate over the calibration window is above this multiple of the
+        /// median pixel rate (Optional. Default: no filter, or 10 if given without a multiple)
+        #[arg(long = "hot-pixels", num_args = 0..=1, default_missing_value = "10")]
+        hot_pixels: Option<f64>,
+        /// Calibration window of the hot pixel filter at the start of the recording, in milliseconds (Optional.
+        /// Default: 100)
+        #[arg(long = "calibration", default_value_t = 100.0)]
+        calibration_ms: f64,
+        /// Keep only the events inside a region, <x>,<y>,<width>,<height>, with coordinates relative to its corner
+        /// (Optional. Default: the whole sensor)
+        #[arg(long = "roi")]
+        roi: Option<Roi>,
+        /// Keep only ON or OFF events, or flip the polarity of all events: on, off or flip (Optional. Default: keep
+        /// all events)
+        #[arg(long = "polarity")]
+        polarity: Option<PolarityMode>,
+        /// Output event format (Optional. Default: from the output file extension, or evt2)
+        #[arg(long = "format")]
+        format: Option<EventFormat>,
+    },
+    /// Draw a recording as PNG frames in the output directory, or as a video for .mp4, .mkv, .mov, .avi and .mjpeg
+    /// outputs, which needs the video feature and ffmpeg
+    #[cfg(feature = "viz")]
+    Render {
+        /// Input event stream file path
+        input: String,
+        /// Output directory, or video file path
+        #[arg(short = 'o', long = "output")]
+        output: String,
+        /// Time covered by each frame, in milliseconds (Optional. Default: 10)
+        #[arg(long = "frame", default_value_t = 10.0)]
+        frame_ms: f64,
+        /// Frames per second of video output (Optional. Default: real time, 1000 / --frame)
+        #[arg(long = "fps")]
+        fps: Option<f64>,
+        /// Colors of the frames, gray, red-blue or dark (Optional. Default: gray)
+        #[arg(long = "colormap", default_value = "gray")]
+        colormap: Colormap,
+    },
+    /// Reconstruct approximate intensity frames by integrating events with a leak, as PNG frames in the output
+    /// directory or as a video (see render)
+    #[cfg(feature = "viz")]
+    Reconstruct {
+        /// Input event stream file path
+        input: String,
+        /// Output directory, or video file path
+        #[arg(short = 'o', long = "output")]
+        output: String,
+        /// Time covered by each frame, in milliseconds (Optional. Default: 10)
+        #[arg(long = "frame", default_value_t = 10.0)]
+        frame_ms: f64,
+        /// Frames per second of video output (Optional. Default: real time, 1000 / --frame)
+        #[arg(long = "fps")]
+        fps: Option<f64>,
+        /// Change of log intensity of each event (Optional. Default: 0.2)
+        #[arg(long = "contrast", default_value_t = 0.2)]
+        contrast: f32,
+        /// Time constant of the leak back to gray, in milliseconds (Optional. Default: 100)
+        #[arg(long = "tau", default_value_t = 100.0)]
+        tau_ms: f64,
+    },
+    /// Draw a recording and its copy after loss side by side, as PNG frames in the output directory or as a
+    /// video (see render), and report the events of each frame in both. With --metrics, measure how far the copy
+    /// is from the recording
+    #[cfg(feature = "viz")]
+    Compare {
+        /// Original event stream file path
+        original: String,
+        /// Event stream file path of the copy after loss
+        lossy: String,
+        /// Output directory, or video file path (Optional. Default: no frames, only --metrics)
+        #[arg(short = 'o', long = "output", required_unless_present = "metrics")]
+        output: Option<String>,
+        /// Print distortion measures of the copy, computed over windows of --frame, and write them to the given
+        /// file, as JSON if it ends in .json and CSV otherwise (Optional. Default: no measures)
+        #[arg(long = "metrics", num_args = 0..=1)]
+        metrics: Option<Option<String>>,
+        /// Time scale of the Chamfer distance of --metrics: microseconds counting as one pixel (Optional.
+        /// Default: 1000)
+        #[arg(long = "time-scale", default_value_t = 1000.0)]
+        time_scale_us: f64,
+        /// Time covered by each frame, in milliseconds (Optional. Default: 10)
+        #[arg(long = "frame", default_value_t = 10.0)]
+        frame_ms: f64,
+        /// Frames per second of video output (Optional. Default: real time, 1000 / --frame)
+        #[arg(long = "fps")]
+        fps: Option<f64>,
+        /// Colors of the frames, gray, red-blue or dark (Optional. Default: gray)
+        #[arg(long = "colormap", default_value = "gray")]
+        colormap: Colormap,
+        /// Write the event counts of each frame to this file, as JSON if it ends in .json and CSV otherwise
+        /// (Optional. Default: only print the totals)
+        #[arg(long = "stats")]
+        stats: Option<String>,
+    },
+    /// Preview a recording or a live stream in the terminal. Inputs are file paths, - for stdin, and with the
+    /// transport feature tcp://<host>:<port> for a TCP relay or udp://<address>:<port> to receive UDP packets, with
+    /// the quic feature quic://<address>:<port> to receive QUIC streams, and
+    /// with the capture, inivation and v4l2 features prophesee://[serial], inivation://<model>[/serial] and
+    /// v4l2:/dev/videoN for a live camera
+    #[cfg(feature = "viz")]
+    View {
+        /// Input event stream
+        input: String,
+        /// Views drawn per second (Optional. Default: 30)
+        #[arg(long = "fps", default_value_t = 30.0)]
+        fps: f64,
+        /// Playback speed of files, as a factor of real time from 0.1 to 100. Streams are drawn as their events
+        /// arrive (Optional. Default: 1)
+        #[arg(long = "speed", default_value_t = 1.0)]
+        speed: f64,
+        /// Draw with ASCII characters instead of braille (Optional. Default: braille)
+        #[arg(long = "ascii")]
+        ascii: bool,
+        /// Draw without colors (Optional. Default: ON red, OFF blue)
+        #[arg(long = "no-color")]
+        no_color: bool,
+        /// Width of the terminal in characters (Optional. Default: $COLUMNS, or 80)
+        #[arg(long = "columns")]
+        columns: Option<usize>,
+        /// Height of the terminal in characters (Optional. Default: $LINES, or 24)
+        #[arg(long = "rows")]
+        rows: Option<usize>,
+    },
+    /// Play a recording back in a window, with space to pause, left and right (page up and down) to seek by 1 s
+    /// (10 s), home to restart, up and down to change the speed and q to quit. The overlay shows the bitrate of
+    /// the events shown and, with --bandwidth or --loss-type, the share of events the loss model drops
+    #[cfg(feature = "gui")]
+    Gui {
+        /// Input event stream file path
+        input: String,
+        /// Frames drawn per second (Optional. Default: 30)
+        #[arg(long = "fps", default_value_t = 30.0)]
+        fps: f64,
+        /// Playback speed, as a factor of real time from 0.1 to 100 (Optional. Default: 1)
+        #[arg(long = "speed", default_value_t = 1.0)]
+        speed: f64,
+        /// Colors of the frames, gray, red-blue or dark (Optional. Default: dark)
+        #[arg(long = "colormap", default_value = "dark")]
+        colormap: Colormap,
+        /// Simulate streaming over a link of this many megabits per second (Optional. Default: no loss)
+        #[arg(long = "bandwidth")]
+        bandwidth_mbps: Option<f64>,
+        /// Size of an event on the link, in bits (Optional. Default: typical size in the input format)
+        #[arg(long = "bits-per-event")]
+        bits_per_event: Option<f64>,
+        /// Loss model deciding which events are dropped, as for transcoding (Optional. Default: end-biased if a
+        /// bandwidth is given)
+        #[arg(long = "loss-type", alias = "loss")]
+        loss_type: Option<String>,
+        /// Drop probability of the random loss model (Optional. Default: 0.1)
+        #[arg(long = "loss-probability", default_value_t = 0.1)]
+        loss_probability: f64,
+        /// Seed of the random loss models (Optional. Default: 0)
+        #[arg(long = "loss-seed", default_value_t = 0)]
+        loss_seed: u64,
+        /// Time covered by each loss chunk, in microseconds (Optional. Default: 10000)
+        #[arg(long = "loss-chunk", default_value_t = 10000)]
+        loss_chunk_us: i64,
+    },
+    /// Write the time surfaces of a recording as a float32 array of shape (T, H, W), or (T, 2, H, W) with
+    /// --split-polarity
+    TimeSurface {
+        /// Input event stream file path
+        input: String,
+        /// Output .npy or .safetensors file path
+        #[arg(short = 'o', long = "output")]
+        output: String,
+        /// Milliseconds between surfaces (Optional. Default: 10)
+        #[arg(long = "interval", default_value_t = 10.0)]
+        interval_ms: f64,
+        /// Decay time constant in milliseconds (Optional. Default: 50)
+        #[arg(long = "tau", default_value_t = 50.0)]
+        tau_ms: f64,
+        /// Separate OFF and ON channels (Optional. Default: one channel for both)
+        #[arg(long = "split-polarity")]
+        split_polarity: bool,
+    },
+    /// Write the event counts of each pixel per window as a float32 array of shape (T, H, W), or (T, 2, H, W) with
+    /// --split-polarity
+    VoxelGrid {
+        /// Input event stream file path
+        input: String,
+        /// Output .npy or .safetensors file path
+        #[arg(short = 'o', long = "output")]
+        output: String,
+        /// Milliseconds covered by each grid (Optional. Default: 10)
+        #[arg(long = "window", default_value_t = 10.0)]
+        window_ms: f64,
+        /// Separate OFF and ON channels (Optional. Default: one channel for both)
+        #[arg(long = "split-polarity")]
+        split_polarity: bool,
+    },
+    /// Convert a frame video into an event stream, firing events where the log intensity changes by the contrast
+    /// threshold. Inputs are directories of PGM/PPM images, or through ffmpeg video files and image patterns such
+    /// as frames/%04d.png
+    #[cfg(feature = "v2e")]
+    V2e {
+        /// Input video file, image directory or image pattern
+        input: String,
+        /// Output file path
+        #[arg(short = 'o', long = "output")]
+        output: String,
+        /// Change of log intensity that fires an event (Optional. Default: 0.2)
+        #[arg(long = "threshold", default_value_t = 0.2)]
+        threshold: f64,
+        /// Time after an event of a pixel during which it fires no other, in milliseconds (Optional. Default: 0.5)
+        #[arg(long = "refractory", default_value_t = 0.5)]
+        refractory_ms: f64,
+        /// Frames per second of the input: videos are resampled to it, and images are taken to be this far apart
+        /// (Optional. Default: 30)
+        #[arg(long = "fps", default_value_t = 30.0)]
+        fps: f64,
+        /// Output event format (Optional. Default: from the output file extension, or evt2)
+        #[arg(long = "format")]
+        format: Option<EventFormat>,
+    },
+    /// Write an index sidecar (<input>.idx) next to EVT2 and EVT3 recordings, for instant seeking
+    Index {
+        /// Input event stream file paths
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        /// Milliseconds between index entries (Optional. Default: 10)
+        #[arg(long = "interval", default_value_t = 10.0)]
+        interval_ms: f64,
+    },
+}
+
+// Interpolation strategies selectable from the command line
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum InterpolateArg {
+    Linear,
+    Hold,
+}
+
+// Number of events decoded per batch
+const READ_BATCH_SIZE: usize = 64 * 1024;
+
+// Scheme of inputs naming a live camera, see dvs::capture
+const CAPTURE_SCHEME: &str = "prophesee://";
+// Scheme of inputs naming a live iniVation camera, see dvs::inivation
+const INIVATION_SCHEME: &str = "inivation://";
+// Scheme of inputs naming a V4L2 device, see dvs::v4l2
+const V4L2_SCHEME: &str = "v4l2:";
+// How often a followed file is polled for new data
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// Process exit codes, so that scripts can branch on the type of failure.
+// Exit code 2 is reserved for command line usage errors, which clap reports itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    BadInputFormat = 3,
+    DecodeError = 4,
+    // A stream doesn't meet the constraints checked by a command, e.g. validate
+    ValidationFailed = 5,
+    #[allow(dead_code)]
+    OverBudget = 6,
+    IoError = 7,
+}
+
+impl Status {
+    // Stable name of the status for machine-readable output
+    fn name(self) -> &'static str {
+        match self {
+            Status::BadInputFormat => "bad_input_format",
+            Status::DecodeError => "decode_error",
+            Status::ValidationFailed => "validation_failed",
+            Status::OverBudget => "over_budget",
+            Status::IoError => "io_error",
+        }
+    }
+}
+
+// An error from one of the CLI stages, tagged with the exit status it maps to
+#[derive(Debug)]
+struct CliError {
+    status: Status,
+    message: String,
+}
+
+impl CliError {
+    fn new(status: Status, error: impl std::fmt::Display) -> Self {
+        CliError { status, message: error.to_string() }
+    }
+
+    fn io(error: impl std::fmt::Display) -> Self {
+        CliError::new(Status::IoError, error)
+    }
+
+    // Errors from opening an input are I/O errors unless the contents could not be parsed
+    fn from_open(error: anyhow::Error) -> Self {
+        match DvsError::from(error) {
+            error @ DvsError::Io(_) => CliError::new(Status::IoError, error),
+            error => CliError::new(Status::BadInputFormat, error),
+        }
+    }
+
+    fn report(&self, json: bool) {
+        if json {
+            eprintln!(
+                "{{\"error\":\"{}\",\"code\":{},\"message\":\"{}\"}}",
+                self.status.name(),
+                self.status as u8,
+                json_escape(&self.message)
+            );
+        } else {
+            eprintln!("Error: {}", self.message);
+        }
+    }
+}
+
+// Escapes a string for embedding in a JSON string literal
+fn json_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+
+// Stages applied to the decoded events before they are encoded
+struct Pipeline {
+    recover: bool,
+    bounds: Option<BoundsMode>,
+    transforms: Vec<Transform>,
+    filters: Option<FilterPipeline>,
+    realtime: Option<f64>,
+    loss: Option<(LossOptions, Box<dyn LossModel>)>,
+    quantize: Option<QuantizeOptions>,
+    report_path: Option<String>,
+    arq: Option<ArqOptions>,
+    latency_report_path: Option<String>,
+    entropy: Option<EntropyOptions>,
+    entropy_report_path: Option<String>,
+    jitter: Option<JitterOptions>,
+    interpolation: Option<(InterpolationStrategy, i64)>,
     output_path: String,
+    format: EventFormat,
+    compression: Compression,
+    csv_options: CsvOptions,
+    // Events decoded and written, and what the stages did, once the stream has been transcoded
+    counts: BatchCounts,
+    summary: Vec<String>,
 }
 
 
-fn decode_events(path: &str) -> Result<(Vec<DVSEvent>, Vec<String>, i64), Box<dyn std::error::Error>> {
+fn convert_events(path: &str, follow_timeout: Option<Duration>, pipeline: &mut Pipeline) -> Result<(), CliError> {
+    // Read from stdin, which waits for the writer on its own
+    if path == "-" {
+        return apply_recover(prep_stream_decoder(std::io::stdin().lock(), FormatHint::Auto).map_err(CliError::from_open)?, pipeline);
+    }
+    if path.starts_with(CAPTURE_SCHEME) {
+        #[cfg(feature = "capture")]
+        return apply_recover(prep_capture_decoder(&capture_options(path)).map_err(CliError::from_open)?, pipeline);
+        #[cfg(not(feature = "capture"))]
+        Cli::command().error(clap::error::ErrorKind::InvalidValue, format!("capturing from {} needs the capture feature", path)).exit()
+    }
+    if path.starts_with(INIVATION_SCHEME) {
+        #[cfg(feature = "inivation")]
+        return apply_recover::<BufReader<std::fs::File>, _>(open_inivation(path)?, pipeline);
+        #[cfg(not(feature = "inivation"))]
+        Cli::command().error(clap::error::ErrorKind::InvalidValue, format!("capturing from {} needs the inivation feature", path)).exit()
+    }
+    if path.starts_with(V4L2_SCHEME) {
+        #[cfg(all(feature = "v4l2", target_os = "linux"))]
+        return apply_recover(prep_v4l2_decoder(&v4l2_options(path)).map_err(CliError::from_open)?, pipeline);
+        #[cfg(not(all(feature = "v4l2", target_os = "linux")))]
+        Cli::command().error(clap::error::ErrorKind::InvalidValue, format!("capturing from {} needs the v4l2 feature, on Linux", path)).exit()
+    }
     // Open file
-    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(path)?;
+    match follow_timeout {
+        Some(timeout) => apply_recover(prep_follow_decoder(path, FOLLOW_POLL_INTERVAL, Some(timeout)).map_err(CliError::from_open)?, pipeline),
+        None => apply_recover(prep_file_decoder::<BufReader<std::fs::File>>(path).map_err(CliError::from_open)?, pipeline),
+    }
+}
 
-    let header = decoder.read_header()?;
 
-    // Create a vector to hold events
-    let mut events: Vec<DVSEvent> = Vec::new();
+// Options of capturing from the camera named by an input. DVS_CAPTURE_COMMAND replaces the helper program streaming
+// the camera, e.g. with one built against the C++ HAL
+#[cfg(feature = "capture")]
+fn capture_options(input: &str) -> CaptureOptions {
+    let options = CaptureOptions::from_uri(input).unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit());
+    let command = std::env::var("DVS_CAPTURE_COMMAND").ok().map(|command| command.split_whitespace().map(String::from).collect::<Vec<_>>());
+    CaptureOptions { command: command.filter(|command| !command.is_empty()), ..options }
+}
 
-    // while events can be read from the file
-    let mut num_events: i64 = 0;
-    while let Ok(event_option) = decoder.read_event() {
-        match event_option {
-            Some(event) =>  {
-                events.push(event);
-                num_events+=1;
-            }
-            None => num_events+=1,
-        }
+
+// Opens the iniVation camera named by an input
+#[cfg(feature = "inivation")]
+fn open_inivation(input: &str) -> Result<InivationCapture, CliError> {
+    let config = CameraConfig::from_uri(input).unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit());
+    InivationCapture::open(&config).map_err(CliError::from_open)
+}
+
+
+// Options of capturing from the V4L2 device named by an input
+#[cfg(all(feature = "v4l2", target_os = "linux"))]
+fn v4l2_options(input: &str) -> V4l2Options {
+    V4l2Options::from_uri(input).unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit())
+}
+
+
+// Ends the stream at the point where a truncated input is cut off, if recovery was requested
+fn apply_recover<R: Read + BufRead + Seek, D: DvsRawDecoder<R>>(decoder: D, pipeline: &mut Pipeline) -> Result<(), CliError> {
+    match pipeline.recover {
+        true => apply_bounds(Recover::new(decoder), pipeline),
+        false => apply_bounds(decoder, pipeline),
+    }
+}
+
+
+// Checks event coordinates against the sensor geometry, if requested
+fn apply_bounds<R: Read + BufRead + Seek, D: DvsRawDecoder<R>>(decoder: D, pipeline: &mut Pipeline) -> Result<(), CliError> {
+    match pipeline.bounds {
+        Some(mode) => apply_transforms(Bounds::new(decoder, mode), pipeline),
+        None => apply_transforms(decoder, pipeline),
     }
+}
+
+
+// Flips or rotates event coordinates, if requested
+fn apply_transforms<R: Read + BufRead + Seek, D: DvsRawDecoder<R>>(decoder: D, pipeline: &mut Pipeline) -> Result<(), CliError> {
+    match pipeline.transforms.is_empty() {
+        true => apply_filters(decoder, pipeline),
+        false => apply_filters(Filtered::new(decoder, Transformer::new(pipeline.transforms.clone())), pipeline),
+    }
+}
 
-    Ok((events, header, num_events))
+
+// Runs the filters of the configuration file, if any
+fn apply_filters<R: Read + BufRead + Seek, D: DvsRawDecoder<R>>(decoder: D, pipeline: &mut Pipeline) -> Result<(), CliError> {
+    match pipeline.filters.take() {
+        Some(filters) => apply_replay(Filtered::new(decoder, filters), pipeline),
+        None => apply_replay(decoder, pipeline),
+    }
+}
+
+
+// Paces the decoder to the event timestamps, if real-time replay was requested
+fn apply_replay<R: Read + BufRead + Seek, D: DvsRawDecoder<R>>(decoder: D, pipeline: &mut Pipeline) -> Result<(), CliError> {
+    match pipeline.realtime {
+        // The speed was checked when parsing the arguments
+        Some(speed) => apply_loss(Replay::new(decoder, speed).expect("replay speed in range"), pipeline),
+        None => apply_loss(decoder, pipeline),
+    }
+}
+
+
+// Passes the decoder through a LossFilter, if a bandwidth was given, or a QuantizeFilter with --quantize, and
+// streams its events to the output
+fn apply_loss<R: Read + BufRead + Seek, D: DvsRawDecoder<R>>(decoder: D, pipeline: &mut Pipeline) -> Result<(), CliError> {
+    if let Some(options) = pipeline.quantize.take() {
+        return apply_quantize(decoder, options, pipeline);
+    }
+    let Some((options, model)) = pipeline.loss.take() else {
+        let mut decoder = decoder;
+        return stream_events(&mut decoder, pipeline);
+    };
+    let mut filter = LossFilter::new(options, model);
+    if pipeline.report_path.is_some() {
+        filter = filter.record_chunks();
+    }
+    let mut decoder = Filtered::new(decoder, filter);
+    stream_events(&mut decoder, pipeline)?;
+    let filter = decoder.filter();
+    pipeline.summary.push(format!("Kept {} of {} events ({} dropped)", filter.events_out, filter.events_in, filter.events_dropped()));
+    // Events dropped by the filter were still decoded
+    pipeline.counts.events_in = filter.events_in;
+    if let Some(path) = &pipeline.report_path {
+        filter.report().write(path).map_err(|e| CliError::new(Status::IoError, e))?;
+    }
+    Ok(())
+}
+
+
+fn apply_quantize<R: Read + BufRead + Seek, D: DvsRawDecoder<R>>(decoder: D, options: QuantizeOptions, pipeline: &mut Pipeline) -> Result<(), CliError> {
+    let mut filter = QuantizeFilter::new(options);
+    if pipeline.report_path.is_some() {
+        filter = filter.record_chunks();
+    }
+    let mut decoder = Filtered::new(decoder, filter);
+    stream_events(&mut decoder, pipeline)?;
+    let filter = decoder.filter();
+    pipeline.summary.extend(filter.summary());
+    // Events merged by the filter were still decoded
+    pipeline.counts.events_in = filter.events_in;
+    if let Some(path) = &pipeline.report_path {
+        filter.report().write(path).map_err(|e| CliError::new(Status::IoError, e))?;
+    }
+    Ok(())
 }
 
 
-fn encode_events(path: &str, events: Vec<DVSEvent>, header: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+// Decodes events in batches and writes them to the output, without holding the whole file in memory
+fn stream_events<R: Read + BufRead + Seek, D: DvsRawDecoder<R>>(decoder: &mut D, pipeline: &mut Pipeline) -> Result<(), CliError> {
+    let header = decoder.read_header().map_err(|e| CliError::new(Status::DecodeError, e))?;
+
     // Open or create file
-    let mut encoder = prep_file_encoder::<std::io::BufWriter<std::fs::File>>(path).unwrap();
+    let mut encoder = prep_file_encoder_compressed(&pipeline.output_path, pipeline.format, pipeline.csv_options, pipeline.compression).map_err(CliError::io)?;
     // Write header to the file
-    let _ = DvsRawEncoder::write_header(&mut encoder, header);
-    // Write all events to the file
-    for event in events {
-        let _ = DvsRawEncoder::write_event(&mut encoder, event);
+    DvsRawEncoder::write_header(&mut encoder, header).map_err(CliError::io)?;
+
+    // Retransmit or delay events and fill in dropped events, if requested
+    let latency_report = match &pipeline.latency_report_path {
+        Some(path) => {
+            let mut writer = BufWriter::new(std::fs::File::create(path).map_err(CliError::io)?);
+            writeln!(writer, "t,x,y,p,arrival,latency_us,attempts").map_err(CliError::io)?;
+            Some(writer)
+        }
+        None => None,
+    };
+    let mut stages = Stages {
+        entropy: pipeline.entropy.map(EntropyStats::new),
+        arq: pipeline.arq.map(Arq::new),
+        latency_report,
+        jitter: pipeline.jitter.map(Jitter::new),
+        interpolator: pipeline.interpolation.map(|(strategy, max_gap_us)| Interpolator::new(strategy, max_gap_us)),
+        scratch: Vec::new(),
+    };
+
+    // Read events in batches until the end of the file
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(READ_BATCH_SIZE);
+    let mut triggers: VecDeque<TriggerEvent> = VecDeque::new();
+    let mut counts = BatchCounts::default();
+    loop {
+        events.clear();
+        match decoder.read_events_into(&mut events, READ_BATCH_SIZE) {
+            Ok(0) => break,
+            Ok(n) => counts.events_in += n as u64,
+            Err(e) => return Err(CliError::new(Status::DecodeError, e)),
+        }
+        triggers.extend(decoder.take_triggers());
+        stages.process(&mut events).map_err(CliError::io)?;
+        counts.events_out += events.len() as u64;
+        write_with_triggers(&mut encoder, &events, &mut triggers).map_err(CliError::io)?;
+    }
+    events.clear();
+    stages.finish(&mut events).map_err(CliError::io)?;
+    counts.events_out += events.len() as u64;
+    triggers.extend(decoder.take_triggers());
+    write_with_triggers(&mut encoder, &events, &mut triggers).map_err(CliError::io)?;
+    write_with_triggers(&mut encoder, &[], &mut triggers).map_err(CliError::io)?;
+    if let Some(entropy) = &stages.entropy {
+        if let Some(path) = &pipeline.entropy_report_path {
+            entropy.report().write(path).map_err(CliError::io)?;
+        }
+        pipeline.summary.extend(entropy.summary());
+    }
+    if let Some(arq) = &stages.arq {
+        pipeline.summary.push(format!(
+            "Retransmitted {} packets, lost {} of {} packets ({} events), mean latency {:.0} us, max {} us",
+            arq.retransmissions, arq.packets_lost, arq.packets_sent, arq.events_lost, arq.mean_latency_us(), arq.latency_max_us
+        ));
+    }
+    if let Some(jitter) = &stages.jitter {
+        pipeline.summary.push(format!("Reordered {} events", jitter.events_reordered));
+    }
+    if let Some(interpolator) = &stages.interpolator {
+        pipeline.summary.push(format!("Interpolated {} events", interpolator.events_inserted));
+    }
+    // The number of events read
+    pipeline.summary.push(format!("Decoded {} events", counts.events_in));
+    pipeline.counts = counts;
+    DvsRawEncoder::flush(&mut encoder).map_err(CliError::io)
+}
+
+
+// The stages applied to each batch of decoded events, in order
+struct Stages {
+    // Measures the packets as sent, before the link
+    entropy: Option<EntropyStats>,
+    arq: Option<Arq>,
+    // Per-event latencies of the retransmission simulation
+    latency_report: Option<BufWriter<std::fs::File>>,
+    jitter: Option<Jitter>,
+    interpolator: Option<Interpolator>,
+    scratch: Vec<DVSEvent>,
+}
+
+impl Stages {
+    // Replaces a batch of events with the events that are ready after passing through each stage
+    fn process(&mut self, events: &mut Vec<DVSEvent>) -> std::io::Result<()> {
+        let scratch = &mut self.scratch;
+        if let Some(entropy) = self.entropy.as_mut() {
+            for event in events.iter() {
+                entropy.process(*event, &mut |_| {});
+            }
+        }
+        if let Some(arq) = self.arq.as_mut() {
+            let mut deliveries = Vec::new();
+            for event in events.drain(..) {
+                arq.process(event, &mut |delivery| deliveries.push(delivery));
+            }
+            Self::record_latencies(&mut self.latency_report, &deliveries)?;
+            events.extend(deliveries.iter().map(|delivery| delivery.event));
+        }
+        if let Some(jitter) = self.jitter.as_mut() {
+            scratch.clear();
+            for event in events.drain(..) {
+                jitter.process(event, &mut |e| scratch.push(e));
+            }
+            std::mem::swap(events, scratch);
+        }
+        if let Some(interpolator) = self.interpolator.as_mut() {
+            scratch.clear();
+            for event in events.drain(..) {
+                interpolator.process(event, &mut |e| scratch.push(e));
+            }
+            std::mem::swap(events, scratch);
+        }
+        Ok(())
+    }
+
+    // Appends the events still buffered by the stages
+    fn finish(&mut self, events: &mut Vec<DVSEvent>) -> std::io::Result<()> {
+        if let Some(entropy) = self.entropy.as_mut() {
+            entropy.finish(&mut |_| {});
+        }
+        if let Some(arq) = self.arq.as_mut() {
+            let mut deliveries = Vec::new();
+            arq.finish(&mut |delivery| deliveries.push(delivery));
+            Self::record_latencies(&mut self.latency_report, &deliveries)?;
+            events.extend(deliveries.iter().map(|delivery| delivery.event));
+            if let Some(writer) = self.latency_report.as_mut() {
+                writer.flush()?;
+            }
+        }
+        if let Some(jitter) = self.jitter.as_mut() {
+            let delayed = std::mem::take(events);
+            for event in delayed {
+                jitter.process(event, &mut |e| events.push(e));
+            }
+            jitter.finish(&mut |e| events.push(e));
+        }
+        if let Some(interpolator) = self.interpolator.as_mut() {
+            let scratch = &mut self.scratch;
+            scratch.clear();
+            for event in events.drain(..) {
+                interpolator.process(event, &mut |e| scratch.push(e));
+            }
+            interpolator.finish(&mut |e| scratch.push(e));
+            std::mem::swap(events, scratch);
+        }
+        Ok(())
+    }
+
+    fn record_latencies(writer: &mut Option<BufWriter<std::fs::File>>, deliveries: &[ArqDelivery]) -> std::io::Result<()> {
+        if let Some(writer) = writer {
+            for delivery in deliveries {
+                let event = delivery.event;
+                writeln!(
+                    writer,
+                    "{},{},{},{},{},{},{}",
+                    event.timestamp, event.x, event.y, event.polarity, delivery.arrival, delivery.latency_us(), delivery.attempts
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+fn run_partition(inputs: Vec<String>, out_dir: String, seed: u64, ratios: SplitRatios, segment_secs: Option<f64>, cut: Option<EventFormat>) -> Result<(), CliError> {
+    let out_dir = std::path::Path::new(&out_dir);
+    let entries = match segment_secs {
+        None => partition_recordings(&inputs, seed, &ratios).map_err(CliError::from_open)?,
+        Some(secs) => {
+            let segment_us = (secs * 1e6) as i64;
+            let mut entries = Vec::new();
+            for input in &inputs {
+                let cut = cut.map(|format| (out_dir, format));
+                entries.extend(partition_segments(input, segment_us, seed, &ratios, cut).map_err(CliError::from_open)?);
+            }
+            entries
+        }
+    };
+    write_manifests(&entries, out_dir).map_err(|e| CliError::new(Status::IoError, e))?;
+    for split in [Split::Train, Split::Val, Split::Test] {
+        println!("{}: {} entries", split.name(), entries.iter().filter(|entry| entry.split == split).count());
     }
     Ok(())
 }
 
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Parse command line args
-    let args = Cli::parse();
-    let file_path = args.file_path;
-    let output_path: String = args.output_path;
+fn run_cut(input: String, output: String, start_secs: f64, end_secs: Option<f64>, format: Option<EventFormat>) -> Result<(), CliError> {
+    let format = format.or_else(|| EventFormat::from_path(&output)).unwrap_or_default();
+    let start_us = (start_secs * 1e6) as i64;
+    let end_us = end_secs.map_or(i64::MAX, |secs| (secs * 1e6) as i64);
+    let totals = cut_file(&input, &output, format, start_us, end_us, |_| {}).map_err(CliError::from_open)?;
+    println!("Wrote {} events", totals.events);
+    Ok(())
+}
+
+
+fn run_merge(inputs: Vec<String>, output: String, time_offsets: Vec<i64>, x_offsets: Vec<i16>, y_offsets: Vec<i16>, format: Option<EventFormat>) -> Result<(), CliError> {
+    for (name, count) in [("--time-offset", time_offsets.len()), ("--x-offset", x_offsets.len()), ("--y-offset", y_offsets.len())] {
+        if count != 0 && count != inputs.len() {
+            Cli::command()
+                .error(clap::error::ErrorKind::WrongNumberOfValues, format!("{} needs one value per input, got {} for {} inputs", name, count, inputs.len()))
+                .exit();
+        }
+    }
+    let inputs: Vec<(String, MergeOffset)> = inputs
+        .into_iter()
+        .enumerate()
+        .map(|(i, path)| {
+            let offset = MergeOffset {
+                time_us: time_offsets.get(i).copied().unwrap_or(0),
+                x: x_offsets.get(i).copied().unwrap_or(0),
+                y: y_offsets.get(i).copied().unwrap_or(0),
+            };
+            (path, offset)
+        })
+        .collect();
+    let format = format.or_else(|| EventFormat::from_path(&output)).unwrap_or_default();
+    let (totals, dropped) = merge_files(&inputs, &output, format, |_| {}).map_err(CliError::from_open)?;
+    println!("Wrote {} events", totals.events);
+    if dropped > 0 {
+        println!("Dropped {} events shifted off the merged sensor", dropped);
+    }
+    Ok(())
+}
+
+
+fn run_split(input: String, out_dir: String, duration_secs: Option<f64>, size_mb: Option<f64>, format: EventFormat) -> Result<(), CliError> {
+    let limit = match (duration_secs, size_mb) {
+        (Some(secs), _) => SplitLimit::Duration((secs * 1e6) as i64),
+        (None, Some(mb)) => SplitLimit::Bytes((mb * 1e6) as u64),
+        (None, None) => unreachable!("clap requires --duration or --size"),
+    };
+    let segments = split_file(&input, std::path::Path::new(&out_dir), format, limit, |segment| {
+        println!("{}: {} events, {} bytes", segment.path, segment.events, segment.bytes);
+    })
+    .map_err(CliError::from_open)?;
+    println!("Wrote {} segments", segments.len());
+    Ok(())
+}
+
+
+// Records an input until it ends, opening it like run_view
+fn run_record(input: String, out_dir: String, name: String, options: RecordOptions, follow_timeout: Duration) -> Result<(), CliError> {
+    let out_dir = std::path::Path::new(&out_dir);
+    let progress = |segment: &dvs::dvs::split::Segment| println!("{}: {} events, {} bytes", segment.path, segment.events, segment.bytes);
+    let record_error = |e| CliError::new(Status::IoError, e);
+    let segments = if input == "-" {
+        let mut decoder = prep_stream_decoder(std::io::stdin().lock(), FormatHint::Auto).map_err(CliError::from_open)?;
+        record(&mut decoder, out_dir, &name, options, progress).map_err(record_error)?
+    } else if input.starts_with(CAPTURE_SCHEME) {
+        #[cfg(feature = "capture")]
+        {
+            let mut decoder = prep_capture_decoder(&capture_options(&input)).map_err(CliError::from_open)?;
+            record(&mut decoder, out_dir, &name, options, progress).map_err(record_error)?
+        }
+        #[cfg(not(feature = "capture"))]
+        Cli::command().error(clap::error::ErrorKind::InvalidValue, format!("capturing from {} needs the capture feature", input)).exit()
+    } else if input.starts_with(INIVATION_SCHEME) {
+        #[cfg(feature = "inivation")]
+        {
+            let mut capture = open_inivation(&input)?;
+            record::<BufReader<std::fs::File>, _, _>(&mut capture, out_dir, &name, options, progress).map_err(record_error)?
+        }
+        #[cfg(not(feature = "inivation"))]
+        Cli::command().error(clap::error::ErrorKind::InvalidValue, format!("capturing from {} needs the inivation feature", input)).exit()
+    } else if input.starts_with(V4L2_SCHEME) {
+        #[cfg(all(feature = "v4l2", target_os = "linux"))]
+        {
+            let mut decoder = prep_v4l2_decoder(&v4l2_options(&input)).map_err(CliError::from_open)?;
+            record(&mut decoder, out_dir, &name, options, progress).map_err(record_error)?
+        }
+        #[cfg(not(all(feature = "v4l2", target_os = "linux")))]
+        Cli::command().error(clap::error::ErrorKind::InvalidValue, format!("capturing from {} needs the v4l2 feature, on Linux", input)).exit()
+    } else if let Some(address) = input.strip_prefix("quic://") {
+        #[cfg(feature = "quic")]
+        {
+            use dvs::dvs::net::quic::{QuicEventReceiver, QuicReceiverOptions};
+            let mut receiver = QuicEventReceiver::bind(address, QuicReceiverOptions::default()).map_err(|e| CliError::new(Status::IoError, e))?;
+            record::<BufReader<std::fs::File>, _, _>(&mut receiver, out_dir, &name, options, progress).map_err(record_error)?
+        }
+        #[cfg(not(feature = "quic"))]
+        Cli::command().error(clap::error::ErrorKind::InvalidValue, format!("receiving from quic://{} needs the quic feature", address)).exit()
+    } else if input.starts_with("tcp://") || input.starts_with("udp://") {
+        #[cfg(feature = "transport")]
+        {
+            use dvs::dvs::net::tcp::TcpEventClient;
+            use dvs::dvs::net::udp::{UdpEventReceiver, UdpReceiverOptions};
+            match input.strip_prefix("tcp://") {
+                Some(address) => {
+                    let mut client = TcpEventClient::connect(address).map_err(|e| CliError::new(Status::IoError, e))?;
+                    record::<BufReader<std::fs::File>, _, _>(&mut client, out_dir, &name, options, progress).map_err(record_error)?
+                }
+                None => {
+                    let address = &input["udp://".len()..];
+                    let mut receiver = UdpEventReceiver::bind(address, UdpReceiverOptions::default()).map_err(|e| CliError::new(Status::IoError, e))?;
+                    record::<BufReader<std::fs::File>, _, _>(&mut receiver, out_dir, &name, options, progress).map_err(record_error)?
+                }
+            }
+        }
+        #[cfg(not(feature = "transport"))]
+        Cli::command().error(clap::error::ErrorKind::InvalidValue, format!("receiving from {} needs the transport feature", input)).exit()
+    } else {
+        let mut decoder = prep_follow_decoder(&input, FOLLOW_POLL_INTERVAL, Some(follow_timeout)).map_err(CliError::from_open)?;
+        record(&mut decoder, out_dir, &name, options, progress).map_err(record_error)?
+    };
+    println!("Recorded {} files", segments.len());
+    Ok(())
+}
+
+
+fn run_sync(first: String, second: String, options: SyncOptions, output: Option<String>, format: Option<EventFormat>) -> Result<(), CliError> {
+    let offset = sync_files(&first, &second, &options).map_err(CliError::from_open)?;
+    println!("Offset of {}: {} us ({} edges matched, max error {} us)", second, offset.offset_us, offset.matched, offset.max_error_us);
+    if let Some(output) = output {
+        let format = format.or_else(|| EventFormat::from_path(&output)).unwrap_or_default();
+        let totals = restamp_file(&second, &output, format, &offset).map_err(CliError::from_open)?;
+        println!("Wrote {} events", totals.events);
+    }
+    Ok(())
+}
+
+
+// Checks the timestamps of a recording in one pass, writing a repaired copy if asked. Fails with
+// Status::ValidationFailed if the recording isn't monotonic and isn't repaired
+fn run_validate(input: String, max_reported: usize, repair: Option<RepairMode>, output: Option<String>, format: Option<EventFormat>) -> Result<(), CliError> {
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(&input).map_err(CliError::from_open)?;
+    let header = decoder.read_header().map_err(|e| CliError::new(Status::DecodeError, e))?;
+    let mut writer = match (repair, &output) {
+        (Some(mode), Some(output)) => {
+            let format = format.or_else(|| EventFormat::from_path(output)).unwrap_or_default();
+            let mut encoder = prep_file_encoder_with_options(output, format, CsvOptions::default()).map_err(CliError::io)?;
+            DvsRawEncoder::write_header(&mut encoder, header).map_err(CliError::io)?;
+            Some((encoder, Repair::new(mode)))
+        }
+        _ => None,
+    };
+
+    let mut check = MonotonicCheck::new();
+    let (mut events_in, mut violations, mut max_backwards_us) = (0u64, 0u64, 0i64);
+    let mut events: Vec<DVSEvent> = Vec::with_capacity(READ_BATCH_SIZE);
+    let mut repaired: Vec<DVSEvent> = Vec::with_capacity(READ_BATCH_SIZE);
+    let mut triggers: VecDeque<TriggerEvent> = VecDeque::new();
+    loop {
+        events.clear();
+        let count = decoder.read_events_into(&mut events, READ_BATCH_SIZE).map_err(|e| CliError::new(Status::DecodeError, e))?;
+        triggers.extend(decoder.take_triggers());
+        if count == 0 {
+            break;
+        }
+        events_in += count as u64;
+        for event in &events {
+            if let Some(violation) = check.check(event) {
+                if violations < max_reported as u64 {
+                    println!("Event {} at {} us is {} us before the latest timestamp", violation.index, violation.timestamp, -violation.delta_us);
+                }
+                violations += 1;
+                max_backwards_us = max_backwards_us.max(-violation.delta_us);
+            }
+        }
+        if let Some((encoder, repair)) = writer.as_mut() {
+            repaired.clear();
+            for event in &events {
+                repair.process(*event, &mut |e| repaired.push(e));
+            }
+            write_with_triggers(encoder, &repaired, &mut triggers).map_err(CliError::io)?;
+        }
+    }
+    println!("{} of {} events go back in time (at most {} us)", violations, events_in, max_backwards_us);
+
+    let Some((mut encoder, mut repair)) = writer else {
+        return match violations {
+            0 => Ok(()),
+            _ => Err(CliError::new(Status::ValidationFailed, format!("{} is not monotonic", input))),
+        };
+    };
+    repaired.clear();
+    repair.finish(&mut |e| repaired.push(e));
+    write_with_triggers(&mut encoder, &repaired, &mut triggers).map_err(CliError::io)?;
+    write_with_triggers(&mut encoder, &[], &mut triggers).map_err(CliError::io)?;
+    DvsRawEncoder::flush(&mut encoder).map_err(CliError::io)?;
+    println!("Repaired: {} events reordered, {} events clamped", repair.events_reordered, repair.events_clamped);
+    Ok(())
+}
+
+
+fn run_stats(input: String, options: StatsOptions, output: Option<String>) -> Result<(), CliError> {
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(&input).map_err(CliError::from_open)?;
+    let stats = collect_stats(&mut decoder, options).map_err(|e| CliError::new(Status::DecodeError, e))?;
+    match output {
+        Some(path) => std::fs::write(path, stats.to_json()).map_err(|e| CliError::new(Status::IoError, e)),
+        None => {
+            print!("{}", stats.to_json());
+            Ok(())
+        }
+    }
+}
+
+
+fn run_rate(input: String, window_ms: f64, budget: BandwidthBudget, output: Option<String>) -> Result<(), CliError> {
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(&input).map_err(CliError::from_open)?;
+    let series = rate_series(&mut decoder, (window_ms * 1000.0) as i64, budget).map_err(|e| CliError::new(Status::DecodeError, e))?;
+    match output {
+        Some(path) => series.write(&path).map_err(|e| CliError::new(Status::IoError, e)),
+        None => {
+            print!("{}", series.to_csv());
+            Ok(())
+        }
+    }
+}
+
+
+// Passes the events of a recording through the stages of a filter pipeline, printing what each stage did
+fn run_filter(input: String, output: String, mut pipeline: FilterPipeline, format: Option<EventFormat>) -> Result<(), CliError> {
+    let format = format.or_else(|| EventFormat::from_path(&output)).unwrap_or_default();
+    let decoder = prep_file_decoder::<BufReader<std::fs::File>>(&input).map_err(CliError::from_open)?;
+    let mut encoder = prep_file_encoder_with_options(&output, format, CsvOptions::default()).map_err(|e| CliError::new(Status::IoError, e))?;
+    let totals = pipeline.run(decoder, &mut encoder, |_| {}).map_err(|e| CliError::new(Status::DecodeError, e))?;
+    for summary in pipeline.summaries() {
+        println!("{}", summary);
+    }
+    println!("Wrote {} events", totals.events);
+    Ok(())
+}
+
+
+#[cfg(feature = "viz")]
+fn run_render(input: String, output: String, options: RenderOptions, fps: f64) -> Result<(), CliError> {
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(&input).map_err(CliError::from_open)?;
+    let path = std::path::Path::new(&output);
+    if !is_video_path(path) {
+        let paths = render_png_sequence(&mut decoder, path, options).map_err(|e| CliError::new(Status::IoError, e))?;
+        println!("Wrote {} frames to {}", paths.len(), output);
+        return Ok(());
+    }
+    #[cfg(feature = "video")]
+    {
+        let frames = dvs::dvs::render::render_video(&mut decoder, path, options, fps).map_err(|e| CliError::new(Status::IoError, e))?;
+        println!("Wrote {} frames to {}", frames, output);
+        Ok(())
+    }
+    #[cfg(not(feature = "video"))]
+    {
+        let _ = fps;
+        Cli::command()
+            .error(clap::error::ErrorKind::InvalidValue, format!("writing {} needs the video feature", output))
+            .exit()
+    }
+}
+
+
+#[cfg(feature = "viz")]
+fn run_reconstruct(input: String, output: String, options: ReconstructOptions, fps: f64) -> Result<(), CliError> {
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(&input).map_err(CliError::from_open)?;
+    let path = std::path::Path::new(&output);
+    if !is_video_path(path) {
+        let paths = reconstruct_png_sequence(&mut decoder, path, options).map_err(|e| CliError::new(Status::IoError, e))?;
+        println!("Wrote {} frames to {}", paths.len(), output);
+        return Ok(());
+    }
+    #[cfg(feature = "video")]
+    {
+        let frames = dvs::dvs::reconstruct::reconstruct_video(&mut decoder, path, options, fps).map_err(|e| CliError::new(Status::IoError, e))?;
+        println!("Wrote {} frames to {}", frames, output);
+        Ok(())
+    }
+    #[cfg(not(feature = "video"))]
+    {
+        let _ = fps;
+        Cli::command()
+            .error(clap::error::ErrorKind::InvalidValue, format!("writing {} needs the video feature", output))
+            .exit()
+    }
+}
+
+
+#[cfg(feature = "viz")]
+fn run_compare(original: String, lossy: String, output: Option<String>, options: RenderOptions, fps: f64, stats: Option<String>) -> Result<(), CliError> {
+    let Some(output) = output else {
+        return Ok(());
+    };
+    let mut original_decoder = prep_file_decoder::<BufReader<std::fs::File>>(&original).map_err(CliError::from_open)?;
+    let mut lossy_decoder = prep_file_decoder::<BufReader<std::fs::File>>(&lossy).map_err(CliError::from_open)?;
+    let path = std::path::Path::new(&output);
+    let series = if is_video_path(path) {
+        #[cfg(feature = "video")]
+        {
+            dvs::dvs::compare::compare_video(&mut original_decoder, &mut lossy_decoder, path, options, fps).map_err(|e| CliError::new(Status::IoError, e))?
+        }
+        #[cfg(not(feature = "video"))]
+        {
+            let _ = fps;
+            Cli::command()
+                .error(clap::error::ErrorKind::InvalidValue, format!("writing {} needs the video feature", output))
+                .exit()
+        }
+    } else {
+        let (_, series) = compare_png_sequence(&mut original_decoder, &mut lossy_decoder, path, options).map_err(|e| CliError::new(Status::IoError, e))?;
+        series
+    };
+    println!("Wrote {} frames to {}", series.frames.len(), output);
+    println!("Original: {} events, lossy: {} events", series.original_events(), series.lossy_events());
+    if let Some(stats) = stats {
+        series.write(&stats).map_err(|e| CliError::new(Status::IoError, e))?;
+    }
+    Ok(())
+}
+
+
+// Prints how far a copy of a recording is from it, and writes the measures to output if given
+#[cfg(feature = "viz")]
+fn run_metrics(original: String, lossy: String, options: MetricsOptions, output: Option<String>) -> Result<(), CliError> {
+    let mut original_decoder = prep_file_decoder::<BufReader<std::fs::File>>(&original).map_err(CliError::from_open)?;
+    let mut lossy_decoder = prep_file_decoder::<BufReader<std::fs::File>>(&lossy).map_err(CliError::from_open)?;
+    let metrics = compare_streams(&mut original_decoder, &mut lossy_decoder, options).map_err(|e| CliError::new(Status::DecodeError, e))?;
+    print!("{}", metrics);
+    if let Some(output) = output {
+        metrics.write(&output).map_err(|e| CliError::new(Status::IoError, e))?;
+    }
+    Ok(())
+}
 
-    // Decode events from file
-    let events_ = decode_events(file_path.as_str());
 
-    let (events, header, num_events): (Vec<DVSEvent>, Vec<String>, i64);
-    match events_ {
-        Ok((ev, hdr, ne)) => {
-            events = ev;
-            header = hdr;
-            num_events = ne;
-        },
-        Err(e) =>  {
-            println!("Error decoding events");
-            return Err(e)
-        },
+// Draws a stream to stdout until it ends. Streams read from stdin or the network aren't paced
+#[cfg(feature = "viz")]
+fn run_view(input: String, options: TerminalOptions) -> Result<(), CliError> {
+    // Restores the terminal when the view ends, however it ends
+    let _raw = std::io::stdout().is_terminal().then(RawTerminal::enter).transpose().map_err(CliError::io)?;
+    let mut out = std::io::stdout().lock();
+    let draw_error = |e| CliError::new(Status::DecodeError, e);
+    let live = TerminalOptions { speed: None, ..options };
+    if input == "-" {
+        let mut decoder = prep_stream_decoder(std::io::stdin().lock(), FormatHint::Auto).map_err(CliError::from_open)?;
+        view(&mut decoder, &mut out, live).map_err(draw_error)?;
+        return Ok(());
+    }
+    #[cfg(feature = "capture")]
+    if input.starts_with(CAPTURE_SCHEME) {
+        let mut decoder = prep_capture_decoder(&capture_options(&input)).map_err(CliError::from_open)?;
+        view(&mut decoder, &mut out, live).map_err(draw_error)?;
+        return Ok(());
+    }
+    #[cfg(feature = "inivation")]
+    if input.starts_with(INIVATION_SCHEME) {
+        let mut capture = open_inivation(&input)?;
+        view::<BufReader<std::fs::File>, _, _>(&mut capture, &mut out, live).map_err(draw_error)?;
+        return Ok(());
     }
-    // print the number of events read
-    println!("Decoded {} events", num_events);
+    #[cfg(all(feature = "v4l2", target_os = "linux"))]
+    if input.starts_with(V4L2_SCHEME) {
+        let mut decoder = prep_v4l2_decoder(&v4l2_options(&input)).map_err(CliError::from_open)?;
+        view(&mut decoder, &mut out, live).map_err(draw_error)?;
+        return Ok(());
+    }
+    #[cfg(feature = "transport")]
+    {
+        use dvs::dvs::net::tcp::TcpEventClient;
+        use dvs::dvs::net::udp::{UdpEventReceiver, UdpReceiverOptions};
+        if let Some(address) = input.strip_prefix("tcp://") {
+            let mut client = TcpEventClient::connect(address).map_err(|e| CliError::new(Status::IoError, e))?;
+            view::<BufReader<std::fs::File>, _, _>(&mut client, &mut out, live).map_err(draw_error)?;
+            return Ok(());
+        }
+        if let Some(address) = input.strip_prefix("udp://") {
+            let mut receiver = UdpEventReceiver::bind(address, UdpReceiverOptions::default()).map_err(|e| CliError::new(Status::IoError, e))?;
+            view::<BufReader<std::fs::File>, _, _>(&mut receiver, &mut out, live).map_err(draw_error)?;
+            return Ok(());
+        }
+    }
+    #[cfg(feature = "quic")]
+    if let Some(address) = input.strip_prefix("quic://") {
+        use dvs::dvs::net::quic::{QuicEventReceiver, QuicReceiverOptions};
+        let mut receiver = QuicEventReceiver::bind(address, QuicReceiverOptions::default()).map_err(|e| CliError::new(Status::IoError, e))?;
+        view::<BufReader<std::fs::File>, _, _>(&mut receiver, &mut out, live).map_err(draw_error)?;
+        return Ok(());
+    }
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(&input).map_err(CliError::from_open)?;
+    view(&mut decoder, &mut out, options).map_err(draw_error)?;
+    Ok(())
+}
 
 
-    // Write events out to .raw file
-    let _ = encode_events(&output_path, events, header);
+// Creates the output of a tensor export, in the format of its extension
+fn create_tensor_output(output: &str) -> Result<(std::fs::File, TensorFormat), CliError> {
+    let Some(format) = TensorFormat::from_path(std::path::Path::new(output)) else {
+        Cli::command().error(clap::error::ErrorKind::InvalidValue, format!("{} should end in .npy or .safetensors", output)).exit()
+    };
+    let file = std::fs::File::create(output).map_err(|e| CliError::new(Status::IoError, e))?;
+    Ok((file, format))
+}
 
+
+fn run_time_surface(input: String, output: String, options: TimeSurfaceOptions) -> Result<(), CliError> {
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(&input).map_err(CliError::from_open)?;
+    let (file, format) = create_tensor_output(&output)?;
+    let surfaces = export_time_surfaces(&mut decoder, file, format, options).map_err(|e| CliError::new(Status::DecodeError, e))?;
+    println!("Wrote {} time surfaces to {}", surfaces, output);
     Ok(())
 }
+
+
+fn run_voxel_grid(input: String, output: String, options: VoxelGridOptions) -> Result<(), CliError> {
+    let mut decoder = prep_file_decoder::<BufReader<std::fs::File>>(&input).map_err(CliError::from_open)?;
+    let (file, format) = create_tensor_output(&output)?;
+    let grids = export_voxel_grids(&mut decoder, file, format, options).map_err(|e| CliError::new(Status::DecodeError, e))?;
+    println!("Wrote {} voxel grids to {}", grids, output);
+    Ok(())
+}
+
+
+fn run_index(inputs: Vec<String>, interval_ms: f64) -> Result<(), CliError> {
+    for input in &inputs {
+        let index = EventIndex::build(input, (interval_ms * 1000.0) as i64).map_err(CliError::from_open)?;
+        let path = EventIndex::sidecar_path(input);
+        index.write(&path).map_err(|e| CliError::new(Status::IoError, e))?;
+        println!("Indexed {}: {} entries, written to {}", input, index.entries.len(), path.display());
+    }
+    Ok(())
+}
+
+
+#[cfg(feature = "v2e")]
+fn run_v2e(input: String, output: String, options: V2eOptions, format: Option<EventFormat>) -> Result<(), CliError> {
+    let format = format.or_else(|| EventFormat::from_path(&output)).unwrap_or_default();
+    let frames = open_frames(&input, options.fps).map_err(|e| CliError::new(Status::IoError, e))?;
+    let mut encoder = prep_file_encoder_with_options(&output, format, CsvOptions::default()).map_err(|e| CliError::new(Status::IoError, e))?;
+    let totals = frames_to_events(frames, options, &mut encoder).map_err(|e| CliError::new(Status::BadInputFormat, e))?;
+    println!("Converted {} frames into {} events, written to {}", totals.frames, totals.events, output);
+    Ok(())
+}
+
+
+fn run(args: Cli) -> Result<(), CliError> {
+    if let Some(command) = args.command {
+        return match command {
+            Command::Transcode(args) => run_transcode(*args),
+            Command::Partition { inputs, out_dir, seed, train, val, test, segment_secs, cut, format } => {
+                run_partition(inputs, out_dir, seed, SplitRatios { train, val, test }, segment_secs, cut.then_some(format))
+            }
+            Command::Cut { input, output, start_secs, end_secs, format } => run_cut(input, output, start_secs, end_secs, format),
+            Command::Merge { inputs, output, time_offsets, x_offsets, y_offsets, format } => {
+                run_merge(inputs, output, time_offsets, x_offsets, y_offsets, format)
+            }
+            Command::Split { input, out_dir, duration_secs, size_mb, format } => run_split(input, out_dir, duration_secs, size_mb, format),
+            Command::Record { input, out_dir, name, format, rotate_secs, rotate_mb, compression, flush_secs, follow_timeout } => {
+                let rotation = match (rotate_secs, rotate_mb) {
+                    (Some(secs), _) => Some(SplitLimit::Duration((secs * 1e6) as i64)),
+                    (None, Some(mb)) => Some(SplitLimit::Bytes((mb * 1e6) as u64)),
+                    (None, None) => None,
+                };
+                let flush_interval = Duration::try_from_secs_f64(flush_secs)
+                    .unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, format!("Invalid flush interval: {}", e)).exit());
+                let options = RecordOptions { format, rotation, compression, flush_interval };
+                run_record(input, out_dir, name, options, Duration::from_secs(follow_timeout))
+            }
+            Command::Sync { first, second, channel, falling, tolerance_us, output, format } => {
+                let options = SyncOptions { channel, value: if falling { 0 } else { 1 }, tolerance_us };
+                run_sync(first, second, options, output, format)
+            }
+            Command::Validate { input, max_reported, repair, output, format } => run_validate(input, max_reported, repair, output, format),
+            Command::Stats { input, window_ms, top_pixels, output } => {
+                run_stats(input, StatsOptions { window_us: (window_ms * 1000.0) as i64, top_pixels }, output)
+            }
+            Command::Rate { input, window_ms, format, bits_per_event, packet_size, packet_overhead, output } => {
+                let budget = BandwidthBudget {
+                    bits_per_event: bits_per_event.unwrap_or(format_bits_per_event(format)),
+                    packet_payload_bytes: packet_size,
+                    packet_overhead_bytes: packet_overhead,
+                    ..BandwidthBudget::default()
+                };
+                run_rate(input, window_ms, budget, output)
+            }
+            Command::Filter { input, output, pipeline_path, hot_pixels, calibration_ms, roi, polarity, format } => {
+                // The stages of the description file come first, then those of the options
+                let mut pipeline = match pipeline_path {
+                    Some(path) => FilterPipeline::load(&path).unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit()),
+                    None => FilterPipeline::new(),
+                };
+                if let Some(threshold) = hot_pixels {
+                    pipeline = pipeline.stage(HotPixelFilter::new(HotPixelOptions { calibration_us: (calibration_ms * 1000.0) as i64, threshold }));
+                }
+                if let Some(roi) = roi {
+                    pipeline = pipeline.stage(RoiFilter::new(roi));
+                }
+                if let Some(mode) = polarity {
+                    pipeline = pipeline.stage(PolarityFilter::new(mode));
+                }
+                run_filter(input, output, pipeline, format)
+            }
+            #[cfg(feature = "viz")]
+            Command::Render { input, output, frame_ms, fps, colormap } => {
+                let options = RenderOptions { frame_us: (frame_ms * 1000.0) as i64, colormap, ..RenderOptions::default() };
+                run_render(input, output, options, fps.unwrap_or(1000.0 / frame_ms))
+            }
+            #[cfg(feature = "viz")]
+            Command::Reconstruct { input, output, frame_ms, fps, contrast, tau_ms } => {
+                let options = ReconstructOptions { frame_us: (frame_ms * 1000.0) as i64, contrast, tau_us: tau_ms * 1000.0, geometry: None };
+                run_reconstruct(input, output, options, fps.unwrap_or(1000.0 / frame_ms))
+            }
+            #[cfg(feature = "viz")]
+            Command::Compare { original, lossy, output, metrics, time_scale_us, frame_ms, fps, colormap, stats } => {
+                let options = RenderOptions { frame_us: (frame_ms * 1000.0) as i64, colormap, ..RenderOptions::default() };
+                run_compare(original.clone(), lossy.clone(), output, options, fps.unwrap_or(1000.0 / frame_ms), stats)?;
+                match metrics {
+                    Some(metrics_output) => {
+                        let options = MetricsOptions { window_us: (frame_ms * 1000.0) as i64, time_scale_us, ..MetricsOptions::default() };
+                        run_metrics(original, lossy, options, metrics_output)
+                    }
+                    None => Ok(()),
+                }
+            }
+            #[cfg(feature = "viz")]
+            Command::View { input, fps, speed, ascii, no_color, columns, rows } => {
+                replay::check_speed(speed).unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit());
+                // Outside a terminal, e.g. piped to a file, shells set COLUMNS and LINES without always exporting them
+                let env_size = |name: &str, default: usize| std::env::var(name).ok().and_then(|value| value.parse().ok()).unwrap_or(default);
+                let (terminal_columns, terminal_rows) = terminal_size().unwrap_or_else(|| (env_size("COLUMNS", 80), env_size("LINES", 24)));
+                let options = TerminalOptions {
+                    fps,
+                    speed: Some(speed),
+                    style: if ascii { TerminalStyle::Ascii } else { TerminalStyle::Braille },
+                    columns: columns.unwrap_or(terminal_columns),
+                    rows: rows.unwrap_or(terminal_rows),
+                    color: !no_color,
+                    geometry: None,
+                };
+                run_view(input, options)
+            }
+            #[cfg(feature = "gui")]
+            Command::Gui { input, fps, speed, colormap, bandwidth_mbps, bits_per_event, loss_type, loss_probability, loss_seed, loss_chunk_us } => {
+                replay::check_speed(speed).unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit());
+                let format = EventFormat::from_path(&input).unwrap_or_default();
+                let budget = BandwidthBudget {
+                    bandwidth_mbps: bandwidth_mbps.unwrap_or(f64::INFINITY),
+                    bits_per_event: bits_per_event.unwrap_or_else(|| format_bits_per_event(format)),
+                    ..BandwidthBudget::default()
+                };
+                // As for transcoding, loss is applied if a bandwidth or a loss model is given
+                let loss = match (loss_type, bandwidth_mbps) {
+                    (None, None) => None,
+                    (loss_type, _) => {
+                        let params = LossParams { probability: loss_probability, seed: loss_seed, budget, chunk_us: loss_chunk_us, ..LossParams::default() };
+                        let model = LossModels::default()
+                            .create(loss_type.as_deref().unwrap_or("end-biased"), &params)
+                            .unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit());
+                        Some((LossOptions { budget, chunk_us: loss_chunk_us }, model))
+                    }
+                };
+                let options = GuiOptions { fps, speed, colormap, geometry: None, budget };
+                let player = Player::open(&input, options, loss).map_err(CliError::from_open)?;
+                play(player).map_err(|e| CliError::new(Status::IoError, e))
+            }
+            Command::TimeSurface { input, output, interval_ms, tau_ms, split_polarity } => {
+                let options = TimeSurfaceOptions {
+                    interval_us: (interval_ms * 1000.0) as i64,
+                    tau_us: tau_ms * 1000.0,
+                    split_polarity,
+                    geometry: None,
+                };
+                run_time_surface(input, output, options)
+            }
+            Command::VoxelGrid { input, output, window_ms, split_polarity } => {
+                let options = VoxelGridOptions { window_us: (window_ms * 1000.0) as i64, split_polarity, geometry: None };
+                run_voxel_grid(input, output, options)
+            }
+            Command::Index { inputs, interval_ms } => run_index(inputs, interval_ms),
+            #[cfg(feature = "v2e")]
+            Command::V2e { input, output, threshold, refractory_ms, fps, format } => {
+                let options = V2eOptions { contrast_threshold: threshold, refractory_us: (refractory_ms * 1000.0) as i64, fps };
+                options.check().unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit());
+                run_v2e(input, output, options, format)
+            }
+        };
+    }
+
+    // Without a subcommand, the input and output files are required
+    if args.transcode.file_path.is_none() || args.transcode.output_path.is_none() {
+        Cli::command()
+            .error(clap::error::ErrorKind::MissingRequiredArgument, "--file and --output are required unless a subcommand is given")
+            .exit();
+    }
+    run_transcode(args.transcode)
+}
+
+
+// Decodes a stream, passes it through the stages given in the arguments and writes it out. A directory or glob
+// pattern input transcodes every recording it names, see run_batch
+fn run_transcode(args: TranscodeArgs) -> Result<(), CliError> {
+    let (Some(file_path), Some(output_path)) = (args.file_path.clone(), args.output_path.clone()) else {
+        Cli::command().error(clap::error::ErrorKind::MissingRequiredArgument, "--file and --output are required").exit();
+    };
+    match args.dump_config_path.as_deref() {
+        Some("-") => print!("{}", args.resolved_config.to_toml()),
+        Some(path) => args.resolved_config.write(path).map_err(|e| CliError::new(Status::IoError, e))?,
+        None => {}
+    }
+    let live = [CAPTURE_SCHEME, INIVATION_SCHEME, V4L2_SCHEME].iter().any(|scheme| file_path.starts_with(scheme));
+    if !live && (is_glob(&file_path) || std::path::Path::new(&file_path).is_dir()) {
+        return run_batch(&args, &file_path, &output_path);
+    }
+    if args.recursive {
+        Cli::command().error(clap::error::ErrorKind::ArgumentConflict, "--recursive needs a directory as --file").exit();
+    }
+    let follow_timeout = args.follow.then(|| Duration::from_secs(args.follow_timeout));
+    let format = args.format.or_else(|| EventFormat::from_path(&output_path)).unwrap_or_default();
+    let mut pipeline = build_pipeline(&args, output_path, format).unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit());
+    convert_events(file_path.as_str(), follow_timeout, &mut pipeline)?;
+    for line in &pipeline.summary {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+
+// Transcodes every recording under a directory or matching a glob pattern into out_dir, on a pool of workers,
+// mirroring the paths of the inputs. Every recording is attempted; the batch fails if any of them did
+fn run_batch(args: &TranscodeArgs, input: &str, out_dir: &str) -> Result<(), CliError> {
+    // Per-file reports would overwrite each other
+    for (given, name) in [(args.follow, "--follow"), (args.report_path.is_some(), "--report"), (args.latency_report_path.is_some(), "--latency-report"), (args.entropy_report_path.is_some(), "--entropy-report")] {
+        if given {
+            let message = format!("{} can't be used with a directory or glob pattern as --file", name);
+            Cli::command().error(clap::error::ErrorKind::ArgumentConflict, message).exit();
+        }
+    }
+    if args.recursive && is_glob(input) {
+        Cli::command().error(clap::error::ErrorKind::ArgumentConflict, "--recursive needs a directory as --file, use ** in glob patterns").exit();
+    }
+    let format = args.format.unwrap_or_default();
+    // The options are checked once, before any file is started
+    build_pipeline(args, String::new(), format).unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit());
+
+    let inputs = find_inputs(input, args.recursive).map_err(CliError::from_open)?;
+    let root = input_root(input);
+    let outputs: Vec<std::path::PathBuf> = inputs.iter().map(|path| output_path(path, &root, std::path::Path::new(out_dir), format, args.compression.unwrap_or_default())).collect();
+    let mut seen = std::collections::HashSet::new();
+    for (input, output) in inputs.iter().zip(&outputs) {
+        if !seen.insert(output) {
+            return Err(CliError::new(Status::IoError, format!("{} would overwrite the output of another recording: {}", input.display(), output.display())));
+        }
+    }
+
+    let report = BatchReport::run(&inputs, &outputs, args.jobs, |input, output| {
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut pipeline = build_pipeline(args, output.display().to_string(), format)?;
+        let path = input.to_str().ok_or_else(|| anyhow::anyhow!("{} is not a valid UTF-8 path", input.display()))?;
+        convert_events(path, None, &mut pipeline).map_err(|e| anyhow::anyhow!(e.message))?;
+        Ok(pipeline.counts)
+    });
+    for entry in &report.entries {
+        match &entry.error {
+            Some(error) => println!("{}: failed: {}", entry.input.display(), error),
+            None => println!(
+                "{} -> {}: kept {} of {} events in {:.2} s",
+                entry.input.display(),
+                entry.output.display(),
+                entry.counts.events_out,
+                entry.counts.events_in,
+                entry.seconds
+            ),
+        }
+    }
+    let totals = report.totals();
+    println!("Transcoded {} of {} recordings, kept {} of {} events", report.entries.len() - report.failures(), report.entries.len(), totals.events_out, totals.events_in);
+    if let Some(path) = &args.batch_report_path {
+        report.write(path).map_err(|e| CliError::new(Status::IoError, e))?;
+    }
+    match report.failures() {
+        0 => Ok(()),
+        failures => Err(CliError::new(Status::DecodeError, format!("{} of {} recordings failed", failures, report.entries.len()))),
+    }
+}
+
+
+// Builds the stages given in the arguments for one output. Fails on invalid options
+fn build_pipeline(args: &TranscodeArgs, output_path: String, format: EventFormat) -> anyhow::Result<Pipeline> {
+    let columns = CsvColumn::parse_order(&args.csv_columns)?;
+    let csv_options = CsvOptions { columns, time_unit: args.csv_time_unit, ..CsvOptions::default() };
+    if let Some(speed) = args.realtime {
+        replay::check_speed(speed)?;
+    }
+    // Checked by clap on the command line, but not for the options of a configuration file
+    if args.latency_report_path.is_some() && args.arq_loss.is_none() {
+        anyhow::bail!("--latency-report needs --arq-loss");
+    }
+    if args.entropy_report_path.is_some() && args.entropy.is_none() {
+        anyhow::bail!("--entropy-report needs --entropy");
+    }
+    let interpolation = args.interpolate.map(|strategy| match strategy {
+        InterpolateArg::Linear => InterpolationStrategy::Linear { factor: args.interpolate_factor },
+        InterpolateArg::Hold => InterpolationStrategy::Hold { interval_us: args.interpolate_interval },
+    });
+    // Each random stage gets its own stream of the seed
+    let stage_seed = |seed: Option<u64>, name: &str| seed.or_else(|| args.seed.map(|seed| derive_seed(seed, name))).unwrap_or(0);
+    let loss_seed = stage_seed(args.loss_seed, "loss");
+    // Loss stages without a seed get the stream of their position
+    let mut stages = args.stages.iter().enumerate().map(|(index, stage)| match (stage.name.as_str(), stage.get("seed")) {
+        ("loss", None) => stage.clone().set("seed", &stage_seed(args.loss_seed, &format!("stage-{}", index)).to_string()),
+        _ => stage.clone(),
+    });
+    let registry = DvsFilters::default();
+    let filters = stages.try_fold(FilterPipeline::new(), |filters, config| anyhow::Ok(filters.boxed_stage(registry.create(&config)?)))?;
+
+    // The cost of events on the link depends on the output format, unless overridden
+    let budget = BandwidthBudget {
+        bandwidth_mbps: args.bandwidth_mbps.unwrap_or(f64::INFINITY),
+        bits_per_event: args.bits_per_event.unwrap_or_else(|| format_bits_per_event(format)),
+        packet_payload_bytes: args.packet_size,
+        packet_overhead_bytes: args.packet_overhead,
+    };
+
+    // Checked by clap on the command line, but not for the options of a configuration file
+    if args.quantize && (args.bandwidth_mbps.is_none() || args.loss_type.is_some()) {
+        anyhow::bail!("--quantize needs --bandwidth, and can't be used with --loss-type");
+    }
+    let quantize = args.quantize.then(|| QuantizeOptions { budget, chunk_us: args.loss_chunk_us, ..QuantizeOptions::default() });
+
+    // Loss is applied if a bandwidth or a loss model is given. Without a bandwidth, no chunk is over budget
+    let loss_model = match (&args.loss_type, args.bandwidth_mbps) {
+        _ if args.quantize => None,
+        (None, None) => None,
+        (loss_type, _) => {
+            let params = LossParams {
+                probability: args.loss_probability,
+                seed: loss_seed,
+                budget,
+                burst_bits: args.burst_bits,
+                tile_size: args.tile_size,
+                chunk_us: args.loss_chunk_us,
+                gilbert_elliott: GilbertElliottParams {
+                    good_to_bad: args.ge_good_to_bad,
+                    bad_to_good: args.ge_bad_to_good,
+                    loss_good: args.ge_loss_good,
+                    loss_bad: args.ge_loss_bad,
+                },
+            };
+            Some(LossModels::default().create(loss_type.as_deref().unwrap_or("end-biased"), &params)?)
+        }
+    };
+    let loss = loss_model.map(|model| {
+        let options = LossOptions { budget, chunk_us: args.loss_chunk_us };
+        (options, model)
+    });
+
+    // Decode events from file, apply loss and interpolation, and write them out
+    Ok(Pipeline {
+        recover: args.recover,
+        bounds: args.bounds,
+        transforms: args.transforms.clone(),
+        filters: (!filters.is_empty()).then_some(filters),
+        realtime: args.realtime,
+        loss,
+        quantize,
+        report_path: args.report_path.clone(),
+        arq: args.arq_loss.map(|loss_probability| ArqOptions {
+            loss_probability,
+            rtt_us: args.arq_rtt_us,
+            max_retries: args.arq_retries,
+            seed: stage_seed(args.arq_seed, "arq"),
+            packet: PacketizerOptions { max_bytes: args.packet_size, max_span_us: args.arq_packet_span_us },
+        }),
+        latency_report_path: args.latency_report_path.clone(),
+        entropy: args.entropy.map(|coding| EntropyOptions {
+            coding,
+            chunk_us: args.loss_chunk_us,
+            packet: PacketizerOptions { max_bytes: args.packet_size, max_span_us: None },
+            train_packets: args.entropy_train_packets,
+        }),
+        entropy_report_path: args.entropy_report_path.clone(),
+        jitter: args.jitter.map(|delay| JitterOptions { delay, restamp: args.jitter_restamp, seed: stage_seed(args.jitter_seed, "jitter") }),
+        interpolation: interpolation.map(|strategy| (strategy, args.interpolate_max_gap)),
+        format,
+        compression: args.compression.unwrap_or_else(|| Compression::from_path(&output_path)),
+        output_path,
+        csv_options,
+        counts: BatchCounts::default(),
+        summary: Vec::new(),
+    })
+}
+
+
+// Parses the command line. The options of a --config file become the defaults of transcoding, so options given on
+// the command line take precedence
+fn parse_args() -> Cli {
+    let matches = Cli::command().get_matches();
+    let config = match transcode_matches(&matches).and_then(|matches| matches.get_one::<String>("config_path")) {
+        Some(path) => ExperimentConfig::load(path).unwrap_or_else(|e| Cli::command().error(clap::error::ErrorKind::ValueValidation, e).exit()),
+        None => ExperimentConfig::default(),
+    };
+    let matches = match config.options.is_empty() {
+        true => matches,
+        false => {
+            let with_defaults = |command: clap::Command| config_defaults(command, &config);
+            match matches.subcommand_name() {
+                Some(name) => Cli::command().mut_subcommand(name, with_defaults).get_matches(),
+                None => with_defaults(Cli::command()).get_matches(),
+            }
+        }
+    };
+
+    let mut cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let resolved = transcode_matches(&matches).map(|matches| resolve_config(matches, &config.stages));
+    let args = match cli.command.as_mut() {
+        Some(Command::Transcode(args)) => Some(args.as_mut()),
+        Some(_) => None,
+        None => Some(&mut cli.transcode),
+    };
+    if let (Some(args), Some(resolved)) = (args, resolved) {
+        args.stages = config.stages;
+        args.resolved_config = resolved;
+    }
+    cli
+}
+
+
+// Matches of the transcoding options: those of the transcode subcommand, or those given without a subcommand
+fn transcode_matches(matches: &ArgMatches) -> Option<&ArgMatches> {
+    match matches.subcommand() {
+        Some(("transcode", matches)) => Some(matches),
+        Some(_) => None,
+        None => Some(matches),
+    }
+}
+
+
+// Options of a configuration file, by their long names, except those naming configuration files
+fn config_options() -> Vec<clap::Arg> {
+    let options = TranscodeArgs::augment_args(clap::Command::new("dvs")).get_arguments().cloned().collect::<Vec<_>>();
+    options.into_iter().filter(|option| option.get_long().is_some_and(|long| long != "config" && long != "dump-config")).collect()
+}
+
+
+// Sets the options of a configuration file as the defaults of the transcoding options of a command
+fn config_defaults(command: clap::Command, config: &ExperimentConfig) -> clap::Command {
+    let options = config_options();
+    config.options.iter().fold(command, |command, (key, value)| {
+        let Some(option) = options.iter().find(|option| option.get_long() == Some(key.as_str())) else {
+            let names: Vec<&str> = options.iter().filter_map(|option| option.get_long()).collect();
+            let message = format!("Unknown option '{}' in the config file. Expected one of: {}", key, names.join(", "));
+            Cli::command().error(clap::error::ErrorKind::UnknownArgument, message).exit();
+        };
+        command.mut_arg(option.get_id().clone(), |arg| arg.default_values(value.items()))
+    })
+}
+
+
+// The transcoding options given by matches, with their defaults, as a configuration that reproduces them
+fn resolve_config(matches: &ArgMatches, stages: &[StageConfig]) -> ExperimentConfig {
+    let mut config = ExperimentConfig { stages: stages.to_vec(), ..ExperimentConfig::default() };
+    for option in config_options() {
+        let (Some(long), Some(raw)) = (option.get_long(), matches.get_raw(option.get_id().as_str())) else {
+            continue;
+        };
+        let items: Vec<String> = raw.map(|item| item.to_string_lossy().into_owned()).collect();
+        let value = match (option.get_action(), items.as_slice()) {
+            (ArgAction::Append, _) => ConfigValue::Array(items),
+            (_, [item]) => ConfigValue::from_text(item),
+            _ => continue,
+        };
+        config = config.set(long, value);
+    }
+    config
+}
+
+
+fn main() -> ExitCode {
+    // Parse command line args
+    let args = parse_args();
+    let json_errors = args.json_errors;
+    // Diagnostics go to stderr, so they never mix with a stream written to stdout. Spans report their duration
+    // when they close
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(match (args.quiet, args.verbose) {
+            (true, _) => LevelFilter::OFF,
+            (false, 0) => LevelFilter::WARN,
+            (false, 1) => LevelFilter::DEBUG,
+            (false, _) => LevelFilter::TRACE,
+        })
+        .with_span_events(FmtSpan::CLOSE)
+        .with_ansi(std::io::stderr().is_terminal())
+        .init();
+
+    match run(args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            e.report(json_errors);
+            ExitCode::from(e.status as u8)
+        }
+    }
+}
@@ -0,0 +1,125 @@
+// Checks the AEDAT4 decoder on files built here: uncompressed, LZ4 and zstd compressed event packets decode to
+// the same events, and sizes read from a corrupted file don't allocate more than the file holds
+
+use dvs::dvs::error::DvsError;
+use dvs::dvs::raw_decoder_aedat4::DVSRawDecoderAedat4;
+use dvs::dvs::{DVSEvent, DvsRawDecoder};
+use std::io::{Cursor, Write};
+
+const COMPRESSION_NONE: i32 = 0;
+const COMPRESSION_LZ4: i32 = 1;
+#[cfg(feature = "compression")]
+const COMPRESSION_ZSTD: i32 = 3;
+
+// An IOHeader flatbuffer with only the compression field
+fn io_header(compression: i32) -> Vec<u8> {
+    let mut buf = Vec::new();
+    // Root offset, then a vtable of one field at offset 4 in an 8-byte table, and the table
+    buf.extend(12u32.to_le_bytes());
+    buf.extend([6u16, 8, 4].iter().flat_map(|value| value.to_le_bytes()));
+    buf.extend([0, 0]);
+    buf.extend(8i32.to_le_bytes());
+    buf.extend(compression.to_le_bytes());
+    buf
+}
+
+// A size-prefixed event packet flatbuffer
+fn event_packet(events: &[DVSEvent]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    // Root offset and the EVTS identifier, a vtable of one field, then the table and its vector of events
+    buf.extend(16u32.to_le_bytes());
+    buf.extend(b"EVTS");
+    buf.extend([6u16, 8, 4].iter().flat_map(|value| value.to_le_bytes()));
+    buf.extend([0, 0]);
+    buf.extend(8i32.to_le_bytes());
+    buf.extend(4u32.to_le_bytes());
+    buf.extend((events.len() as u32).to_le_bytes());
+    for event in events {
+        buf.extend(event.timestamp.to_le_bytes());
+        buf.extend(event.x.to_le_bytes());
+        buf.extend(event.y.to_le_bytes());
+        buf.extend([event.polarity, 0, 0, 0]);
+    }
+    let mut packet = (buf.len() as u32).to_le_bytes().to_vec();
+    packet.extend(buf);
+    packet
+}
+
+fn compress(compression: i32, data: Vec<u8>) -> Vec<u8> {
+    match compression {
+        COMPRESSION_LZ4 => {
+            let mut encoder = lz4_flex::frame::FrameEncoder::new(Vec::new());
+            encoder.write_all(&data).unwrap();
+            encoder.finish().unwrap()
+        }
+        #[cfg(feature = "compression")]
+        COMPRESSION_ZSTD => zstd::encode_all(data.as_slice(), 0).unwrap(),
+        _ => data,
+    }
+}
+
+// An AEDAT4 file of the events, in packets of up to 100 events
+fn aedat4(compression: i32, events: &[DVSEvent]) -> Vec<u8> {
+    let mut bytes = b"#!AER-DAT4.0\r\n".to_vec();
+    let header = io_header(compression);
+    bytes.extend((header.len() as u32).to_le_bytes());
+    bytes.extend(header);
+    for packet in events.chunks(100) {
+        let data = compress(compression, event_packet(packet));
+        bytes.extend(0i32.to_le_bytes());
+        bytes.extend((data.len() as i32).to_le_bytes());
+        bytes.extend(data);
+    }
+    bytes
+}
+
+fn events() -> Vec<DVSEvent> {
+    (0..250).map(|i| DVSEvent { timestamp: 1_000_000 + i * 3, x: (i % 346) as i16, y: (i % 260) as i16, polarity: (i % 2) as u8 }).collect()
+}
+
+fn decode(bytes: Vec<u8>) -> anyhow::Result<Vec<DVSEvent>> {
+    let mut decoder = DVSRawDecoderAedat4::new(Cursor::new(bytes));
+    decoder.read_header()?;
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 64)? > 0 {}
+    Ok(events)
+}
+
+fn key(event: &DVSEvent) -> (i64, i16, i16, u8) {
+    (event.timestamp, event.x, event.y, event.polarity)
+}
+
+#[test]
+fn compressed_packets_decode_like_uncompressed_ones() {
+    let expected: Vec<_> = events().iter().map(key).collect();
+    #[cfg(feature = "compression")]
+    let compressions = [COMPRESSION_NONE, COMPRESSION_LZ4, COMPRESSION_ZSTD];
+    #[cfg(not(feature = "compression"))]
+    let compressions = [COMPRESSION_NONE, COMPRESSION_LZ4];
+    for compression in compressions {
+        let events = decode(aedat4(compression, &events())).unwrap();
+        assert_eq!(events.iter().map(key).collect::<Vec<_>>(), expected, "compression {}", compression);
+    }
+}
+
+#[test]
+fn corrupted_sizes_are_truncation_errors() {
+    let bytes = aedat4(COMPRESSION_LZ4, &events());
+    // The size of the IOHeader, then the size of the first packet, set to their maximum
+    let packet_size = 14 + 4 + io_header(COMPRESSION_LZ4).len() + 4;
+    for (offset, size) in [(14, u32::MAX), (packet_size, i32::MAX as u32)] {
+        let mut bytes = bytes.clone();
+        bytes[offset..offset + 4].copy_from_slice(&size.to_le_bytes());
+        let error = decode(bytes).map(|_| ()).map_err(DvsError::from).unwrap_err();
+        assert!(matches!(error, DvsError::Truncated { .. }), "{:?}", error);
+    }
+}
+
+#[test]
+fn corrupted_compressed_packets_are_errors() {
+    let mut bytes = aedat4(COMPRESSION_LZ4, &events());
+    let packet = 14 + 4 + io_header(COMPRESSION_LZ4).len() + 8;
+    // The LZ4 frame magic of the first packet
+    bytes[packet] ^= 0xFF;
+    assert!(decode(bytes).is_err());
+}
@@ -0,0 +1,77 @@
+// Checks the retransmission simulator of arq.rs: packets arrive half a round trip after their last event plus a
+// round trip per retransmission, packets lost more times than the retry limit are counted as lost, and the
+// losses are reproducible from the seed
+
+use dvs::dvs::arq::{Arq, ArqDelivery, ArqOptions};
+use dvs::dvs::packet::{PacketizerOptions, PACKET_EVENT_BYTES, PACKET_HEADER_BYTES};
+use dvs::dvs::DVSEvent;
+
+const EVENTS: i64 = 1_000;
+const EVENTS_PER_PACKET: i64 = 10;
+
+// Events every 100 us, numbered by x
+fn stream() -> Vec<DVSEvent> {
+    (0..EVENTS).map(|i| DVSEvent { timestamp: i * 100, x: i as i16, y: 0, polarity: 1 }).collect()
+}
+
+fn options(loss_probability: f64, seed: u64) -> ArqOptions {
+    let packet = PacketizerOptions { max_bytes: PACKET_HEADER_BYTES + EVENTS_PER_PACKET as usize * PACKET_EVENT_BYTES, max_span_us: None };
+    ArqOptions { loss_probability, rtt_us: 20_000, max_retries: 3, seed, packet }
+}
+
+fn run(options: ArqOptions) -> (Vec<ArqDelivery>, Arq) {
+    let mut arq = Arq::new(options);
+    let mut out = Vec::new();
+    for event in stream() {
+        arq.process(event, &mut |delivery| out.push(delivery));
+    }
+    arq.finish(&mut |delivery| out.push(delivery));
+    (out, arq)
+}
+
+// When the packet of an event was first sent: once its last event happened
+fn sent(event: &DVSEvent) -> i64 {
+    (event.x as i64 / EVENTS_PER_PACKET * EVENTS_PER_PACKET + EVENTS_PER_PACKET - 1) * 100
+}
+
+#[test]
+fn lossless_links_deliver_every_event_after_half_a_round_trip() {
+    let (out, arq) = run(options(0.0, 0));
+    assert_eq!(out.iter().map(|delivery| delivery.event.x).collect::<Vec<_>>(), (0..EVENTS as i16).collect::<Vec<_>>());
+    assert!(out.iter().all(|delivery| delivery.attempts == 1 && delivery.arrival == sent(&delivery.event) + 10_000));
+    assert_eq!((arq.packets_sent, arq.retransmissions, arq.packets_lost, arq.events_lost), (100, 0, 0, 0));
+    assert_eq!(arq.events_delivered, EVENTS as u64);
+    assert_eq!(arq.latency_max_us, 10_000 + (EVENTS_PER_PACKET - 1) * 100);
+    assert_eq!(arq.mean_latency_us(), out.iter().map(ArqDelivery::latency_us).sum::<i64>() as f64 / EVENTS as f64);
+}
+
+#[test]
+fn packets_are_lost_after_the_last_retry() {
+    let (out, arq) = run(options(1.0, 0));
+    assert!(out.is_empty());
+    assert_eq!((arq.packets_sent, arq.retransmissions, arq.packets_lost, arq.events_lost), (100, 300, 100, EVENTS as u64));
+    assert_eq!(arq.events_delivered, 0);
+    assert_eq!(arq.mean_latency_us(), 0.0);
+}
+
+#[test]
+fn retransmissions_add_a_round_trip_each() {
+    let (out, arq) = run(options(0.5, 11));
+    assert!(arq.retransmissions > 0 && arq.packets_lost > 0, "{} {}", arq.retransmissions, arq.packets_lost);
+    assert_eq!(arq.events_delivered + arq.events_lost, EVENTS as u64);
+    assert_eq!(out.len() as u64, arq.events_delivered);
+    assert_eq!(arq.events_lost, arq.packets_lost * EVENTS_PER_PACKET as u64);
+    for delivery in &out {
+        assert!((1..=4).contains(&delivery.attempts));
+        assert_eq!(delivery.arrival, sent(&delivery.event) + 10_000 + (delivery.attempts as i64 - 1) * 20_000);
+    }
+    // Events come out in order of arrival, with the events of a packet together
+    assert!(out.windows(2).all(|pair| pair[0].arrival <= pair[1].arrival));
+    assert!(out.iter().any(|delivery| delivery.attempts > 1));
+
+    // The seed decides which transmissions are lost
+    let (again, _) = run(options(0.5, 11));
+    let attempts = |out: &[ArqDelivery]| out.iter().map(|delivery| (delivery.event.x, delivery.attempts)).collect::<Vec<_>>();
+    assert_eq!(attempts(&again), attempts(&out));
+    assert_ne!(attempts(&run(options(0.5, 12)).0), attempts(&out));
+}
@@ -0,0 +1,114 @@
+// Checks the asynchronous EVT2 decoder and encoder against the synchronous ones: they read and write the same
+// bytes, and event_stream yields every event of a stream. Built with the async feature
+#![cfg(feature = "async")]
+
+use dvs::dvs::async_io::{event_stream, AsyncDVSRawDecoderEvt2, AsyncDVSRawEncoderEvt2, AsyncDvsRawDecoder, AsyncDvsRawEncoder};
+use dvs::dvs::error::DvsError;
+use dvs::dvs::raw_decoder_evt2::DVSRawDecoderEvt2;
+use dvs::dvs::raw_encoder_evt2::DVSRawEncoderEvt2;
+use dvs::dvs::{DVSEvent, DvsRawDecoder, DvsRawEncoder, TriggerEvent};
+use futures_util::StreamExt;
+use std::io::Cursor;
+use tokio::io::BufReader;
+
+fn golden() -> Vec<u8> {
+    std::fs::read(format!("{}/tests/data/golden_evt2.raw", env!("CARGO_MANIFEST_DIR"))).unwrap()
+}
+
+fn key(event: &DVSEvent) -> (i64, i16, i16, u8) {
+    (event.timestamp, event.x, event.y, event.polarity)
+}
+
+fn sync_decode(bytes: Vec<u8>) -> (Vec<String>, Vec<DVSEvent>) {
+    let mut decoder = DVSRawDecoderEvt2::new(Cursor::new(bytes));
+    let header = decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 256).unwrap() > 0 {}
+    (header, events)
+}
+
+// A small buffer, so words straddle the end of the reader's buffer
+fn async_decoder(bytes: &[u8]) -> AsyncDVSRawDecoderEvt2<BufReader<&[u8]>> {
+    AsyncDVSRawDecoderEvt2::new(BufReader::with_capacity(7, bytes))
+}
+
+#[tokio::test]
+async fn async_decoder_matches_the_sync_decoder() {
+    let bytes = golden();
+    let (header, expected) = sync_decode(bytes.clone());
+
+    let mut decoder = async_decoder(&bytes);
+    assert_eq!(decoder.read_header().await.unwrap(), header);
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 5).await.unwrap() > 0 {}
+    assert_eq!(events.iter().map(key).collect::<Vec<_>>(), expected.iter().map(key).collect::<Vec<_>>());
+
+    let mut decoder = async_decoder(&bytes);
+    decoder.read_header().await.unwrap();
+    let mut events = Vec::new();
+    while let Some(event) = decoder.read_event().await.unwrap() {
+        events.push(event);
+    }
+    assert_eq!(events.len(), expected.len());
+}
+
+#[tokio::test]
+async fn event_stream_yields_every_event() {
+    let bytes = golden();
+    let (_, expected) = sync_decode(bytes.clone());
+    let mut decoder = async_decoder(&bytes);
+    decoder.read_header().await.unwrap();
+    let events: Vec<DVSEvent> = event_stream(decoder).map(Result::unwrap).collect().await;
+    assert_eq!(events.iter().map(key).collect::<Vec<_>>(), expected.iter().map(key).collect::<Vec<_>>());
+}
+
+#[tokio::test]
+async fn async_encoder_writes_the_bytes_of_the_sync_encoder() {
+    let (header, events) = sync_decode(golden());
+    let trigger = TriggerEvent { timestamp: events[3].timestamp, id: 2, value: 1 };
+
+    let mut expected = Cursor::new(Vec::new());
+    let mut encoder = DVSRawEncoderEvt2::new(&mut expected);
+    encoder.write_header(header.clone()).unwrap();
+    for (i, event) in events.iter().enumerate() {
+        encoder.write_event(*event).unwrap();
+        if i == 3 {
+            encoder.write_trigger(trigger).unwrap();
+        }
+    }
+    encoder.flush().unwrap();
+    drop(encoder);
+
+    let mut encoder = AsyncDVSRawEncoderEvt2::new(Vec::new());
+    encoder.write_header(header).await.unwrap();
+    for (i, event) in events.iter().enumerate() {
+        encoder.write_event(*event).await.unwrap();
+        if i == 3 {
+            encoder.write_trigger(trigger).await.unwrap();
+        }
+    }
+    encoder.flush().await.unwrap();
+    let bytes = encoder.into_inner();
+    assert_eq!(bytes, expected.into_inner());
+
+    let mut decoder = async_decoder(&bytes);
+    decoder.read_header().await.unwrap();
+    while decoder.read_event().await.unwrap().is_some() {}
+    assert_eq!(decoder.take_triggers(), vec![trigger]);
+}
+
+#[tokio::test]
+async fn async_decoder_reports_truncation() {
+    let mut bytes = golden();
+    bytes.truncate(bytes.len() - 2);
+    let mut decoder = async_decoder(&bytes);
+    decoder.read_header().await.unwrap();
+    let error = loop {
+        match decoder.read_event().await {
+            Ok(Some(_)) => {}
+            Ok(None) => panic!("expected a truncation error"),
+            Err(error) => break error,
+        }
+    };
+    assert!(matches!(error, DvsError::Truncated { offset } if offset == bytes.len() as u64 - 2), "{:?}", error);
+}
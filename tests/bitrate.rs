@@ -0,0 +1,54 @@
+// Checks the bitrate accounting of bitrate.rs: the bits events take on a link with packet headers, the number of
+// events fitting in a bandwidth, conversions between bits, durations and bitrates, and running averages
+
+use dvs::dvs::bitrate::{bits_in, format_bits_per_event, mbps, transmission_time, BandwidthBudget, RunningBitrate};
+use dvs::dvs::EventFormat;
+use std::time::Duration;
+
+#[test]
+fn counts_a_header_per_started_packet() {
+    // 368 events of 32 bits fill a 1472 byte payload, with 28 bytes of headers
+    let budget = BandwidthBudget::default();
+    assert_eq!(budget.bits_for_events(0), 0.0);
+    assert_eq!(budget.bits_for_events(368), (368 * 32 + 28 * 8) as f64);
+    assert_eq!(budget.bits_for_events(369), (369 * 32 + 2 * 28 * 8) as f64);
+    let unpacketized = BandwidthBudget { packet_payload_bytes: 0, ..budget };
+    assert_eq!(unpacketized.bits_for_events(369), (369 * 32) as f64);
+}
+
+#[test]
+fn fits_as_many_events_as_the_bandwidth_allows() {
+    for bandwidth_mbps in [0.0, 0.01, 1.0, 10.0, 123.4] {
+        for format in [EventFormat::Evt2, EventFormat::Evt3, EventFormat::Dat, EventFormat::Delta] {
+            let budget = BandwidthBudget::for_format(bandwidth_mbps, format);
+            let events = budget.events_in(10_000);
+            let bits = bits_in(bandwidth_mbps, 10_000);
+            assert!(budget.bits_for_events(events) <= bits, "{} Mbps, {:?}", bandwidth_mbps, format);
+            assert!(budget.bits_for_events(events + 1) > bits, "{} Mbps, {:?}", bandwidth_mbps, format);
+            assert!((budget.bitrate_mbps(events, 10_000) - mbps(budget.bits_for_events(events), 10_000)).abs() < 1e-12);
+        }
+    }
+    assert_eq!(BandwidthBudget { bandwidth_mbps: f64::INFINITY, ..BandwidthBudget::default() }.events_in(10_000), usize::MAX);
+}
+
+#[test]
+fn converts_between_bits_durations_and_bitrates() {
+    assert_eq!(mbps(1000.0, 1000), 1.0);
+    assert_eq!(bits_in(2.0, 500), 1000.0);
+    // Empty durations count as a microsecond
+    assert_eq!(mbps(1000.0, 0), 1000.0);
+    assert_eq!(transmission_time(8e6, 8.0), Duration::from_secs(1));
+    assert_eq!(transmission_time(1.0, 0.0), Duration::MAX);
+    assert!(format_bits_per_event(EventFormat::Evt3) < format_bits_per_event(EventFormat::Evt2));
+    assert!(format_bits_per_event(EventFormat::Evt2) < format_bits_per_event(EventFormat::Dat));
+}
+
+#[test]
+fn averages_chunks() {
+    let mut bitrate = RunningBitrate::new(0.5, 0.0);
+    bitrate.add(1000.0, 1000);
+    assert_eq!((bitrate.mean_mbps(), bitrate.smoothed_mbps), (1.0, 0.5));
+    bitrate.add(3000.0, 1000);
+    assert_eq!((bitrate.mean_mbps(), bitrate.smoothed_mbps), (2.0, 1.75));
+    assert_eq!(bitrate.bits(), 4000.0);
+}
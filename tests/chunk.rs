@@ -0,0 +1,36 @@
+// Checks where chunk boundaries fall: windows are aligned to multiples of the chunk length from timestamp 0
+// rather than to the first event, empty windows are skipped, and late events join the window being filled
+
+use dvs::dvs::chunk::{chunks, Chunker};
+use dvs::dvs::DVSEvent;
+
+fn event(timestamp: i64) -> DVSEvent {
+    DVSEvent { timestamp, x: 0, y: 0, polarity: 1 }
+}
+
+fn timestamps(events: &[DVSEvent]) -> Vec<i64> {
+    events.iter().map(|event| event.timestamp).collect()
+}
+
+#[test]
+fn aligns_windows_to_multiples_of_the_chunk_length() {
+    let stream = [1_500, 1_999, 2_000, 2_500, 7_000, 6_900, 7_999, 8_000].map(event);
+    let chunks: Vec<_> = chunks(stream, 1_000).map(|chunk| (chunk.start_us, timestamps(&chunk.events))).collect();
+    assert_eq!(
+        chunks,
+        vec![(1_000, vec![1_500, 1_999]), (2_000, vec![2_000, 2_500]), (7_000, vec![7_000, 6_900, 7_999]), (8_000, vec![8_000])]
+    );
+}
+
+#[test]
+fn pushes_complete_chunks_and_starts_over() {
+    let mut chunker = Chunker::new(1_000);
+    assert!(chunker.push(event(-1)).is_none());
+    let chunk = chunker.push(event(0)).unwrap();
+    assert_eq!((chunk.start_us, timestamps(&chunk.events)), (-1_000, vec![-1]));
+    assert_eq!(timestamps(chunker.pending()), vec![0]);
+    chunker.clear();
+    assert!(chunker.finish().is_none());
+    assert!(chunker.push(event(5_000)).is_none());
+    assert_eq!(chunker.finish().map(|chunk| chunk.start_us), Some(5_000));
+}
@@ -0,0 +1,68 @@
+// Checks that configuration files and pipeline descriptions are read as TOML: comments, multi-line arrays and
+// escaped strings are understood, tables other than [[stage]] are rejected, and what to_toml writes reads back the
+// same.
+
+use dvs::dvs::config::{ConfigValue, ExperimentConfig};
+use dvs::dvs::pipeline::{parse_stages, DvsFilters, Pipeline, StageConfig};
+
+const CONFIG: &str = r#"
+# An experiment
+file = "recordings/a # b.raw"   # the # in the string isn't a comment
+output = 'lossy\'
+bandwidth = 1.5e1
+loss-seed = 0x10
+recover = true
+transform = [
+    "rotate180",  # turned around
+    "flip-x",
+]
+label = "say \"hi\""
+
+[[stage]]
+type = "hot-pixels"
+threshold = 1_000
+
+[[stage]]
+type = "transform"
+transforms = ["rotate90", "flip-y"]
+"#;
+
+#[test]
+fn configs_are_read_as_toml() {
+    let config = ExperimentConfig::parse(CONFIG).unwrap();
+    let keys: Vec<&str> = config.options.iter().map(|(key, _)| key.as_str()).collect();
+    assert_eq!(keys, ["file", "output", "bandwidth", "loss-seed", "recover", "transform", "label"]);
+    assert_eq!(config.get("file"), Some(&ConfigValue::String("recordings/a # b.raw".into())));
+    assert_eq!(config.get("output"), Some(&ConfigValue::String("lossy\\".into())));
+    assert_eq!(config.get("bandwidth"), Some(&ConfigValue::Number("15".into())));
+    assert_eq!(config.get("loss-seed"), Some(&ConfigValue::Number("16".into())));
+    assert_eq!(config.get("recover"), Some(&ConfigValue::Bool(true)));
+    assert_eq!(config.get("transform"), Some(&ConfigValue::Array(vec!["rotate180".into(), "flip-x".into()])));
+    assert_eq!(config.get("label"), Some(&ConfigValue::String("say \"hi\"".into())));
+    assert_eq!(config.stages, vec![StageConfig::new("hot-pixels").set("threshold", "1000"), StageConfig::new("transform").set("transforms", "rotate90,flip-y")]);
+
+    // What is written reads back the same
+    assert_eq!(ExperimentConfig::parse(&config.to_toml()).unwrap(), config);
+}
+
+#[test]
+fn invalid_configs_are_errors() {
+    for text in ["file = ", "file = \"unterminated", "[filters]\nx = 1", "when = 1979-05-27", "nested = [[1], [2]]", "[[stage]]\nthreshold = 1", "a = 1\na = 2"] {
+        assert!(ExperimentConfig::parse(text).is_err(), "{}", text);
+    }
+}
+
+#[test]
+fn pipelines_are_read_as_toml() {
+    let text = "[[stage]]\ntype = 'roi' # a region\nx = 1\ny = 2\nwidth = 30\nheight = 40\n\n[[stage]]\ntype = \"polarity\"\nmode = \"on\"\n";
+    let stages = parse_stages(text).unwrap();
+    assert_eq!(stages, vec![StageConfig::new("roi").set("x", "1").set("y", "2").set("width", "30").set("height", "40"), StageConfig::new("polarity").set("mode", "on")]);
+    assert_eq!(Pipeline::from_toml(text, &DvsFilters::default()).unwrap().len(), 2);
+    for stage in &stages {
+        assert_eq!(parse_stages(&stage.to_toml()).unwrap(), vec![stage.clone()]);
+    }
+    // Descriptions hold nothing but stages
+    assert!(parse_stages("x = 1\n[[stage]]\ntype = \"roi\"").is_err());
+    assert!(parse_stages("[stage]\ntype = \"roi\"").is_err());
+    assert!(parse_stages("[[stage]]\ntype = \"roi\"\nwhere = { x = 1 }").is_err());
+}
@@ -0,0 +1,96 @@
+// Checks the CSV encoder of raw_encoder_csv.rs: the "%" header lines become "#" comments, followed by the
+// timestamp unit comment and the column row, events are written in the configured columns, delimiter and unit
+// without losing precision, and the CSV decoder reads the file back without options
+
+use dvs::dvs::raw_decoder_csv::{CsvColumn, CsvOptions, DVSRawDecoderCsv, TimeUnit};
+use dvs::dvs::raw_encoder_csv::DVSRawEncoderCsv;
+use dvs::dvs::{DVSEvent, DvsRawDecoder, DvsRawEncoder};
+use std::io::Cursor;
+
+fn events() -> Vec<DVSEvent> {
+    vec![
+        DVSEvent { timestamp: 0, x: 0, y: 0, polarity: 0 },
+        DVSEvent { timestamp: 1_500_042, x: 1279, y: 719, polarity: 1 },
+        DVSEvent { timestamp: 2_000_000, x: 17, y: 3, polarity: 0 },
+    ]
+}
+
+fn key(event: &DVSEvent) -> (i64, i16, i16, u8) {
+    (event.timestamp, event.x, event.y, event.polarity)
+}
+
+fn encode(options: CsvOptions, header: &[&str], events: &[DVSEvent]) -> String {
+    let mut bytes = Cursor::new(Vec::new());
+    let mut encoder = DVSRawEncoderCsv::with_options(&mut bytes, options);
+    encoder.write_header(header.iter().map(|line| line.to_string()).collect()).unwrap();
+    for event in events {
+        assert_eq!(encoder.write_event(*event).unwrap(), 1);
+    }
+    encoder.flush().unwrap();
+    drop(encoder);
+    String::from_utf8(bytes.into_inner()).unwrap()
+}
+
+fn decode(text: &str) -> (Vec<String>, Vec<DVSEvent>) {
+    let mut decoder = DVSRawDecoderCsv::new(Cursor::new(text.as_bytes()));
+    let header = decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while let Some(event) = decoder.read_event().unwrap() {
+        events.push(event);
+    }
+    (header, events)
+}
+
+#[test]
+fn headers_become_comments_before_the_column_row() {
+    let header = ["% evt 2.0\n", "% geometry 1280x720\n", "#! AER-DAT3.1\n", "% end\n"];
+    let text = encode(CsvOptions::default(), &header, &events());
+    assert_eq!(
+        text,
+        "# evt 2.0\n# geometry 1280x720\n# timestamp unit: us\nt,x,y,p\n0,0,0,0\n1500042,1279,719,1\n2000000,17,3,0\n"
+    );
+
+    let (decoded_header, decoded) = decode(&text);
+    assert_eq!(decoded_header, ["% evt 2.0\n", "% geometry 1280x720\n"]);
+    assert_eq!(decoded.iter().map(key).collect::<Vec<_>>(), events().iter().map(key).collect::<Vec<_>>());
+}
+
+#[test]
+fn timestamps_are_written_in_the_configured_unit() {
+    let event = [DVSEvent { timestamp: 1_500_042, x: 1, y: 2, polarity: 1 }];
+    for (time_unit, expected) in [
+        (TimeUnit::Seconds, "1.500042"),
+        (TimeUnit::Milliseconds, "1500.042"),
+        (TimeUnit::Microseconds, "1500042"),
+        (TimeUnit::Nanoseconds, "1500042000"),
+    ] {
+        let text = encode(CsvOptions { time_unit, ..CsvOptions::default() }, &[], &event);
+        assert_eq!(text, format!("# timestamp unit: {}\nt,x,y,p\n{},1,2,1\n", time_unit.name(), expected));
+        // Decoding reads the unit from the comment and gets the microseconds back exactly
+        assert_eq!(decode(&text).1.iter().map(key).collect::<Vec<_>>(), [(1_500_042, 1, 2, 1)]);
+    }
+
+    // The fraction of a negative timestamp is written after the sign
+    for (time_unit, timestamp, expected) in [
+        (TimeUnit::Seconds, -1, "-0.000001"),
+        (TimeUnit::Seconds, -1_500_042, "-1.500042"),
+        (TimeUnit::Milliseconds, -1_500_042, "-1500.042"),
+    ] {
+        let text = encode(CsvOptions { time_unit, ..CsvOptions::default() }, &[], &[DVSEvent { timestamp, x: 0, y: 0, polarity: 0 }]);
+        assert!(text.ends_with(&format!("\n{},0,0,0\n", expected)), "{}", text);
+        assert_eq!(decode(&text).1[0].timestamp, timestamp);
+    }
+}
+
+#[test]
+fn columns_and_delimiter_follow_the_options() {
+    let options = CsvOptions { columns: CsvColumn::parse_order("x,y,p,t").unwrap(), delimiter: b'\t', time_unit: TimeUnit::Microseconds };
+    let text = encode(options, &[], &events());
+    assert_eq!(text, "# timestamp unit: us\nx\ty\tp\tt\n0\t0\t0\t0\n1279\t719\t1\t1500042\n17\t3\t0\t2000000\n");
+    // The decoder detects the tab and the column order from the column row
+    assert_eq!(decode(&text).1.iter().map(key).collect::<Vec<_>>(), events().iter().map(key).collect::<Vec<_>>());
+
+    for spec in ["t,x,y", "t,x,y,p,p", "t,x,x,p", "t,x,y,q"] {
+        assert!(CsvColumn::parse_order(spec).is_err(), "{}", spec);
+    }
+}
@@ -0,0 +1,279 @@
+// Feeds corrupted recordings to every decoder, which must return errors rather than panic. Valid recordings of
+// each format are mutated with a seeded generator: flipped bytes, truncations, inserted bytes and damaged header
+// lines. The fuzz targets in fuzz/ explore the same decoders with coverage guidance; this is the quick version
+// that runs with the other tests, starting from the same seed corpus.
+
+use dvs::dvs::codec::DVSRawDecoderDelta;
+use dvs::dvs::error::DvsError;
+use dvs::dvs::parallel::parallel_decode;
+use dvs::dvs::raw_decoder_aedat3::DVSRawDecoderAedat3;
+use dvs::dvs::raw_decoder_aedat4::DVSRawDecoderAedat4;
+use dvs::dvs::raw_decoder_csv::{CsvOptions, DVSRawDecoderCsv};
+use dvs::dvs::raw_decoder_dat::DVSRawDecoderDat;
+use dvs::dvs::raw_decoder_evt2::DVSRawDecoderEvt2;
+use dvs::dvs::raw_decoder_evt21::DVSRawDecoderEvt21;
+use dvs::dvs::raw_decoder_evt3::DVSRawDecoderEvt3;
+use dvs::dvs::rng::SplitMix64;
+use dvs::dvs::{prep_file_encoder_with_options, prep_stream_decoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat, FormatHint};
+use std::io::{BufRead, Cursor, Read, Seek, Write};
+use std::sync::{Arc, Mutex};
+
+// Mutations of each recording
+const MUTATIONS: usize = 300;
+// Batches read from a decoder before giving up, in case a mutation makes it loop
+const MAX_BATCHES: usize = 1000;
+
+// A recording of a few hundred events in a format, with a header giving the geometry
+fn recording(format: EventFormat) -> Vec<u8> {
+    let path = std::env::temp_dir().join(format!("dvs-robustness-{}-{:?}", std::process::id(), format));
+    let path_str = path.to_str().unwrap();
+    let mut encoder = prep_file_encoder_with_options(path_str, format, CsvOptions::default()).unwrap();
+    encoder.write_header(vec!["% format EVT3;height=720;width=1280\n".into(), "% geometry 1280x720\n".into()]).unwrap();
+    for i in 0..500 {
+        encoder.write_event(DVSEvent { timestamp: i * 37, x: (i * 7 % 1280) as i16, y: (i * 3 % 720) as i16, polarity: (i % 2) as u8 }).unwrap();
+    }
+    encoder.flush().unwrap();
+    drop(encoder);
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    bytes
+}
+
+// A corrupted copy of bytes
+fn mutate(bytes: &[u8], rng: &mut SplitMix64) -> Vec<u8> {
+    let mut bytes = bytes.to_vec();
+    let index = |rng: &mut SplitMix64, len: usize| (rng.next_u64() % len.max(1) as u64) as usize;
+    match rng.next_u64() % 5 {
+        0 => {
+            for _ in 0..1 + rng.next_u64() % 8 {
+                let i = index(rng, bytes.len());
+                if let Some(byte) = bytes.get_mut(i) {
+                    *byte ^= 1 << (rng.next_u64() % 8);
+                }
+            }
+        }
+        1 => bytes.truncate(index(rng, bytes.len())),
+        2 => {
+            let i = index(rng, bytes.len());
+            let inserted: Vec<u8> = (0..1 + rng.next_u64() % 16).map(|_| rng.next_u64() as u8).collect();
+            bytes.splice(i..i, inserted);
+        }
+        3 => {
+            // Damages the header, where the parsers of text lines are
+            let line = ["% format EVT3;height=;width=x\n", "% geometry 12x\n", "% geometry x\n", "% evt \n", "% format\n", "%\n"];
+            let line = line[index(rng, line.len())].as_bytes();
+            bytes.splice(0..0, line.iter().copied());
+        }
+        _ => {
+            for byte in bytes.iter_mut().take(64) {
+                *byte = rng.next_u64() as u8;
+            }
+        }
+    }
+    bytes
+}
+
+// Reads a decoder to the end or to its first error
+fn drain<R: Read + BufRead + Seek>(mut decoder: impl DvsRawDecoder<R>) {
+    if decoder.read_header().is_err() {
+        return;
+    }
+    let mut events = Vec::new();
+    for _ in 0..MAX_BATCHES {
+        events.clear();
+        match decoder.read_events_into(&mut events, 256) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {
+                decoder.take_triggers();
+            }
+        }
+    }
+}
+
+// Runs every decoder on the bytes
+fn decode_all(bytes: &[u8]) {
+    drain(DVSRawDecoderEvt2::new(Cursor::new(bytes)));
+    drain(DVSRawDecoderEvt21::new(Cursor::new(bytes)));
+    drain(DVSRawDecoderEvt3::new(Cursor::new(bytes)));
+    drain(DVSRawDecoderDat::new(Cursor::new(bytes)));
+    drain(DVSRawDecoderAedat3::new(Cursor::new(bytes)));
+    drain(DVSRawDecoderAedat4::new(Cursor::new(bytes)));
+    drain(DVSRawDecoderCsv::new(Cursor::new(bytes)));
+    drain(DVSRawDecoderDelta::new(Cursor::new(bytes)));
+    if let Ok(decoder) = prep_stream_decoder(bytes, FormatHint::Auto) {
+        drain(decoder);
+    }
+}
+
+#[test]
+fn decoders_reject_corrupted_recordings_without_panicking() {
+    let mut rng = SplitMix64::new(4094);
+    let mut seeds: Vec<Vec<u8>> = [EventFormat::Evt2, EventFormat::Evt21, EventFormat::Evt3, EventFormat::Dat, EventFormat::Csv, EventFormat::Delta].into_iter().map(recording).collect();
+    // AEDAT files only need their magic line to reach the packet parsers
+    seeds.push(b"#!AER-DAT3.1\r\n#End Of ASCII Header\r\n".iter().copied().chain((0..512).map(|i| (i * 31) as u8)).collect());
+    seeds.push(b"#!AER-DAT4.0\r\n".iter().copied().chain((0..512).map(|i| (i * 17) as u8)).collect());
+    // The fuzz targets' seed corpus, including inputs that once made a decoder panic
+    for target in std::fs::read_dir(format!("{}/fuzz/corpus", env!("CARGO_MANIFEST_DIR"))).unwrap() {
+        for entry in std::fs::read_dir(target.unwrap().path()).unwrap() {
+            let path = entry.unwrap().path();
+            if path.file_name().unwrap().to_string_lossy().starts_with("seed_") {
+                seeds.push(std::fs::read(path).unwrap());
+            }
+        }
+    }
+    for seed in &seeds {
+        decode_all(seed);
+        for _ in 0..MUTATIONS {
+            let bytes = mutate(seed, &mut rng);
+            let result = std::panic::catch_unwind(|| decode_all(&bytes));
+            assert!(result.is_ok(), "a decoder panicked on {:02x?}", &bytes[..bytes.len().min(256)]);
+        }
+    }
+}
+
+#[test]
+fn evt3_skips_reserved_words() {
+    let mut bytes = b"% evt 3.0\n% end\n".to_vec();
+    // EVT_TIME_HIGH and EVT_TIME_LOW, the reserved types 0x1, 0x9, 0xB, 0xC and 0xD, then EVT_ADDR_Y and EVT_ADDR_X
+    for word in [0x8000u16, 0x6010, 0x1abc, 0x9abc, 0xbabc, 0xcabc, 0xdabc, 0x0005, 0x2803] {
+        bytes.extend(word.to_le_bytes());
+    }
+    let mut decoder = DVSRawDecoderEvt3::new(Cursor::new(bytes));
+    decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 256).unwrap() > 0 {}
+    assert_eq!(events.len(), 1);
+    assert_eq!((events[0].timestamp, events[0].x, events[0].y, events[0].polarity), (0x10, 3, 5, 1));
+    assert!(decoder.take_triggers().is_empty());
+}
+
+#[test]
+fn evt3_rejects_vectors_past_the_sensor_width() {
+    let mut bytes = b"% format EVT3;height=720;width=1280\n% end\n".to_vec();
+    // EVT_TIME_HIGH, EVT_ADDR_Y and VECT_BASE_X at x=1270, then enough full VECT_12 words to run the base x past
+    // the range of an i16
+    for word in [0x8000u16, 0x0005, 0x3000 | 1270] {
+        bytes.extend(word.to_le_bytes());
+    }
+    for _ in 0..3000 {
+        bytes.extend(0x4FFFu16.to_le_bytes());
+    }
+    let mut decoder = DVSRawDecoderEvt3::new(Cursor::new(bytes.clone()));
+    decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    let error = loop {
+        match decoder.read_events_into(&mut events, 256) {
+            Ok(0) => panic!("expected an error for the pixels past the sensor"),
+            Ok(_) => {}
+            Err(error) => break error,
+        }
+    };
+    assert!(matches!(error, DvsError::InvalidData(_)), "{:?}", error);
+    assert!(events.iter().all(|event| (1270..1280).contains(&event.x)), "{:?}", events);
+
+    // Seeking follows the base x through the same words without decoding them
+    let mut decoder = DVSRawDecoderEvt3::new(Cursor::new(bytes));
+    decoder.read_header().unwrap();
+    decoder.seek_to_timestamp(100).unwrap();
+}
+
+#[test]
+fn parallel_evt2_decoding_reports_truncation_like_the_sequential_decoder() {
+    let mut bytes = std::fs::read(format!("{}/tests/data/golden_evt2.raw", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    bytes.truncate(bytes.len() - 1);
+    let path = std::env::temp_dir().join(format!("dvs-robustness-{}-truncated.raw", std::process::id()));
+    std::fs::write(&path, &bytes).unwrap();
+    // Safety: the test wrote the file, and nothing changes it while it is decoded
+    let parallel = unsafe { parallel_decode(&path, 2) }.err().map(DvsError::from);
+    std::fs::remove_file(&path).unwrap();
+
+    let mut decoder = DVSRawDecoderEvt2::new(Cursor::new(bytes));
+    decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    let sequential = loop {
+        match decoder.read_events_into(&mut events, 256) {
+            Ok(0) => break None,
+            Ok(_) => {}
+            Err(error) => break Some(error),
+        }
+    };
+    match (parallel, sequential) {
+        (Some(DvsError::Truncated { offset }), Some(DvsError::Truncated { offset: expected })) => assert_eq!(offset, expected),
+        other => panic!("expected two truncation errors, got {:?}", other),
+    }
+}
+
+#[test]
+fn parallel_evt2_decoding_matches_the_sequential_decoder() {
+    let path = format!("{}/tests/data/golden_evt2.raw", env!("CARGO_MANIFEST_DIR"));
+    let mut decoder = DVSRawDecoderEvt2::new(Cursor::new(std::fs::read(&path).unwrap()));
+    let header = decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 256).unwrap() > 0 {}
+    let key = |event: &DVSEvent| (event.timestamp, event.x, event.y, event.polarity);
+    for threads in [0, 1, 3, 64] {
+        // Safety: nothing writes to the checked-in samples
+        let parallel = unsafe { parallel_decode(&path, threads) }.unwrap();
+        assert_eq!(parallel.header, header);
+        assert_eq!(parallel.events.iter().map(key).collect::<Vec<_>>(), events.iter().map(key).collect::<Vec<_>>(), "{} threads", threads);
+    }
+}
+
+// Collects the messages written by a tracing subscriber
+#[derive(Clone, Default)]
+struct Messages(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Messages {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn evt2_skips_others_and_continued_words_silently() {
+    let messages = Messages::default();
+    let writer = messages.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_writer(move || writer.clone())
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .without_time()
+        .finish();
+
+    let mut bytes = b"% evt 2.0\n% end\n".to_vec();
+    // EVT_TIME_HIGH, OTHERS, CONTINUED, the unused type 0x3, then CD_ON at (5, 7)
+    for word in [0x8000_0000u32, 0xE000_0001, 0xF000_0002, 0x3000_0000, 0x1000_0000 | 5 << 11 | 7] {
+        bytes.extend(word.to_le_bytes());
+    }
+    // The subscriber only sees this thread, not the other tests
+    let events = tracing::subscriber::with_default(subscriber, || {
+        let mut decoder = DVSRawDecoderEvt2::new(Cursor::new(bytes));
+        decoder.read_header().unwrap();
+        let mut events = Vec::new();
+        while decoder.read_events_into(&mut events, 256).unwrap() > 0 {}
+        events
+    });
+
+    assert_eq!(events.len(), 1);
+    let messages = String::from_utf8(messages.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<&str> = messages.lines().filter(|line| line.contains("raw_decoder_evt2")).collect();
+    assert!(lines.iter().all(|line| line.trim_start().starts_with("DEBUG") && !line.contains("type=14") && !line.contains("type=15")), "{:?}", lines);
+    assert!(lines.iter().any(|line| line.contains("type=3")), "{:?}", lines);
+}
+
+#[test]
+fn errors_keep_their_kind_through_anyhow() {
+    let truncated = DvsError::from(anyhow::Error::from(DvsError::Truncated { offset: 12 }));
+    assert!(matches!(truncated, DvsError::Truncated { offset: 12 }), "{:?}", truncated);
+    let eof = DvsError::from(anyhow::Error::from(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)));
+    assert!(matches!(eof, DvsError::UnexpectedEof), "{:?}", eof);
+    // Errors that aren't about the data, e.g. of a filter, are not reported as corrupt data
+    let other = DvsError::from(anyhow::anyhow!("Unknown filter 'blur'"));
+    assert!(matches!(other, DvsError::Other(_)), "{:?}", other);
+    assert_eq!(other.to_string(), "Unknown filter 'blur'");
+}
@@ -0,0 +1,99 @@
+// Checks that the binary encoders reject events their formats can't represent, coordinates past the bits of a
+// word or timestamps out of range, with an error rather than a panic or a silently truncated word
+
+use dvs::dvs::error::DvsError;
+use dvs::dvs::raw_decoder_csv::CsvOptions;
+use dvs::dvs::{prep_encoder, prep_stream_decoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat, FormatHint};
+use std::io::Cursor;
+
+// The error of writing the event after a valid one, if any
+fn write_error(format: EventFormat, event: DVSEvent) -> Option<DvsError> {
+    let mut bytes = Cursor::new(Vec::new());
+    let mut encoder = prep_encoder(&mut bytes, format, CsvOptions::default()).unwrap();
+    encoder.write_header(vec!["% geometry 1280x720\n".into()]).unwrap();
+    encoder.write_event(DVSEvent { timestamp: 0, x: 1, y: 1, polarity: 1 }).unwrap();
+    encoder.write_event(event).and_then(|_| encoder.flush()).err()
+}
+
+#[test]
+fn dat_rejects_coordinates_and_timestamps_out_of_range() {
+    let event = DVSEvent { timestamp: 10, x: 5, y: 1, polarity: 1 };
+    assert!(write_error(EventFormat::Dat, event).is_none());
+    assert!(write_error(EventFormat::Dat, DVSEvent { x: 5000, ..event }).is_none());
+    for event in [
+        DVSEvent { x: 20000, ..event },
+        DVSEvent { y: -1, ..event },
+        DVSEvent { timestamp: 1 << 32, ..event },
+        DVSEvent { timestamp: -1, ..event },
+    ] {
+        assert!(matches!(write_error(EventFormat::Dat, event), Some(DvsError::InvalidData(_))), "{:?}", event);
+    }
+}
+
+#[test]
+fn evt3_rejects_coordinates_out_of_range() {
+    let event = DVSEvent { timestamp: 10, x: 5, y: 1, polarity: 1 };
+    assert!(write_error(EventFormat::Evt3, DVSEvent { x: 2047, y: 2047, ..event }).is_none());
+    for event in [DVSEvent { x: 2048, ..event }, DVSEvent { y: 2048, ..event }, DVSEvent { x: -1, ..event }] {
+        assert!(matches!(write_error(EventFormat::Evt3, event), Some(DvsError::InvalidData(_))), "{:?}", event);
+    }
+}
+
+#[test]
+fn evt3_keeps_repeated_events_and_counts_their_words() {
+    // 600 events at the same pixel and time, then a few sharing a vector with a repeat
+    let mut events = vec![DVSEvent { timestamp: 0, x: 7, y: 1, polarity: 1 }; 600];
+    events.extend([3, 4, 4, 9].map(|x| DVSEvent { timestamp: 5, x, y: 2, polarity: 0 }));
+    let mut bytes = Cursor::new(Vec::new());
+    let mut encoder = prep_encoder(&mut bytes, EventFormat::Evt3, CsvOptions::default()).unwrap();
+    encoder.write_header(Vec::new()).unwrap();
+    let words: Vec<usize> = events.iter().map(|event| encoder.write_event(*event).unwrap()).collect();
+    encoder.flush().unwrap();
+    drop(encoder);
+    // EVT_TIME_HIGH, EVT_TIME_LOW and EVT_ADDR_Y, then one EVT_ADDR_X word per event
+    assert_eq!(words[600], 603);
+
+    let bytes = bytes.into_inner();
+    let mut decoder = prep_stream_decoder(bytes.as_slice(), FormatHint::Auto).unwrap();
+    decoder.read_header().unwrap();
+    let mut decoded = Vec::new();
+    while decoder.read_events_into(&mut decoded, 256).unwrap() > 0 {}
+    let key = |event: &DVSEvent| (event.timestamp, event.x, event.y, event.polarity);
+    let mut expected: Vec<_> = events.iter().map(key).collect();
+    let mut decoded: Vec<_> = decoded.iter().map(key).collect();
+    expected.sort_unstable();
+    decoded.sort_unstable();
+    assert_eq!(decoded, expected);
+}
+
+#[test]
+fn evt2_and_evt21_reject_coordinates_out_of_range() {
+    let event = DVSEvent { timestamp: 10, x: 5, y: 1, polarity: 1 };
+    for format in [EventFormat::Evt2, EventFormat::Evt21] {
+        assert!(write_error(format, DVSEvent { x: 2047, y: 2047, ..event }).is_none());
+        for event in [DVSEvent { x: 2048, ..event }, DVSEvent { y: 2048, ..event }, DVSEvent { x: -1, ..event }, DVSEvent { y: i16::MIN, ..event }] {
+            assert!(matches!(write_error(format, event), Some(DvsError::InvalidData(_))), "{:?} {:?}", format, event);
+        }
+    }
+}
+
+#[test]
+fn evt3_keeps_the_time_loops_before_the_first_event() {
+    // The first event past the 24-bit time loop of EVT3, then one after a gap of more than a loop
+    let events = [22_627_861, 22_627_900, 40_000_000].map(|timestamp| DVSEvent { timestamp, x: 5, y: 1, polarity: 1 });
+    let mut bytes = Cursor::new(Vec::new());
+    let mut encoder = prep_encoder(&mut bytes, EventFormat::Evt3, CsvOptions::default()).unwrap();
+    encoder.write_header(Vec::new()).unwrap();
+    for event in events {
+        encoder.write_event(event).unwrap();
+    }
+    encoder.flush().unwrap();
+    drop(encoder);
+
+    let bytes = bytes.into_inner();
+    let mut decoder = prep_stream_decoder(bytes.as_slice(), FormatHint::Auto).unwrap();
+    decoder.read_header().unwrap();
+    let mut decoded = Vec::new();
+    while decoder.read_events_into(&mut decoded, 256).unwrap() > 0 {}
+    assert_eq!(decoded.iter().map(|event| event.timestamp).collect::<Vec<_>>(), events.map(|event| event.timestamp));
+}
@@ -0,0 +1,46 @@
+// Checks the entropy coding statistics of entropy.rs: events pass through unchanged, and every chunk is counted
+// once, packetized on its own, including the chunks held back until the model is trained
+
+use dvs::dvs::chunk::chunks;
+use dvs::dvs::entropy::{EntropyCoding, EntropyOptions, EntropyStats};
+use dvs::dvs::filters::DvsFilter;
+use dvs::dvs::packet::{Packetizer, PacketizerOptions};
+use dvs::dvs::rng::SplitMix64;
+use dvs::dvs::DVSEvent;
+
+#[test]
+fn entropy_stats_count_every_chunk() {
+    // Edges moving across the sensor, so that packets compress
+    let mut rng = SplitMix64::new(4105);
+    let events: Vec<DVSEvent> = (0..100_000i64)
+        .map(|i| DVSEvent { timestamp: i * 5, x: ((i / 40) % 1280) as i16, y: (i % 40 + (rng.next_u64() % 4) as i64) as i16, polarity: (i / 1000 % 2) as u8 })
+        .collect();
+    let options = EntropyOptions { coding: EntropyCoding::Range, chunk_us: 10_000, train_packets: 64, ..EntropyOptions::default() };
+    let mut stats = EntropyStats::new(options);
+    stats.header(vec!["% geometry 1280x720\n".into()]).unwrap();
+    let mut out = Vec::new();
+    for event in &events {
+        stats.process(*event, &mut |event| out.push(event));
+    }
+    stats.finish(&mut |event| out.push(event));
+    assert_eq!(out.iter().map(|event| event.timestamp).collect::<Vec<_>>(), events.iter().map(|event| event.timestamp).collect::<Vec<_>>());
+
+    let report = stats.report();
+    let expected: Vec<_> = chunks(events.iter().copied(), options.chunk_us).collect();
+    assert_eq!(report.chunks.len(), expected.len());
+    for (chunk, expected) in report.chunks.iter().zip(&expected) {
+        let mut packetizer = Packetizer::new(PacketizerOptions::default());
+        let mut packet_bytes = Vec::new();
+        for event in &expected.events {
+            packetizer.process(*event, &mut |packet| packet_bytes.push(packet.encoded_len() as u64));
+        }
+        packetizer.finish(&mut |packet| packet_bytes.push(packet.encoded_len() as u64));
+        assert_eq!((chunk.start_us, chunk.events), (expected.start_us, expected.events.len() as u64));
+        assert_eq!((chunk.packets, chunk.packet_bytes), (packet_bytes.len() as u64, packet_bytes.iter().sum()));
+        assert!(chunk.compressed_bytes > 0 && chunk.compressed_bytes < chunk.packet_bytes, "{:?}", chunk);
+    }
+    let totals = report.totals();
+    assert_eq!(totals.events, events.len() as u64);
+    assert_eq!(report.model_bytes, 512);
+    assert!(totals.ratio() < 1.0);
+}
@@ -0,0 +1,38 @@
+// Checks the wire layout of DVSEvent: fixed offsets, little-endian fields and lossless round trips
+
+use dvs::dvs::{DVSEvent, DVS_EVENT_WIRE_BYTES};
+
+fn assert_same(a: DVSEvent, b: DVSEvent) {
+    assert_eq!((a.timestamp, a.x, a.y, a.polarity), (b.timestamp, b.x, b.y, b.polarity));
+}
+
+#[test]
+fn layout_is_little_endian_without_padding() {
+    let event = DVSEvent { timestamp: 0x0102_0304_0506_0708, x: 0x090A, y: 0x0B0C, polarity: 1 };
+    assert_eq!(
+        event.to_le_bytes(),
+        [0x08, 0x07, 0x06, 0x05, 0x04, 0x03, 0x02, 0x01, 0x0A, 0x09, 0x0C, 0x0B, 0x01]
+    );
+}
+
+#[test]
+fn round_trips_extreme_values() {
+    for event in [
+        DVSEvent::default(),
+        DVSEvent { timestamp: i64::MAX, x: i16::MAX, y: i16::MAX, polarity: u8::MAX },
+        DVSEvent { timestamp: i64::MIN, x: i16::MIN, y: i16::MIN, polarity: 0 },
+        DVSEvent { timestamp: -1, x: -1, y: 1279, polarity: 1 },
+    ] {
+        assert_same(DVSEvent::from_le_bytes(event.to_le_bytes()), event);
+        let bytes: [u8; DVS_EVENT_WIRE_BYTES] = event.into();
+        assert_same(DVSEvent::from(bytes), event);
+        assert_same(DVSEvent::try_from(&bytes[..]).unwrap(), event);
+    }
+}
+
+#[test]
+fn rejects_slices_of_the_wrong_length() {
+    let bytes = DVSEvent::default().to_le_bytes();
+    assert!(DVSEvent::try_from(&bytes[..DVS_EVENT_WIRE_BYTES - 1]).is_err());
+    assert!(DVSEvent::try_from(&[0u8; DVS_EVENT_WIRE_BYTES + 1][..]).is_err());
+}
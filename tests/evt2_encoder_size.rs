@@ -0,0 +1,52 @@
+// Checks that the EVT2 encoder writes one EVT_TIME_HIGH word per 64 us time base rather than one per event,
+// and that the output still decodes to the same events
+
+use dvs::dvs::raw_decoder_evt2::DVSRawDecoderEvt2;
+use dvs::dvs::raw_encoder_evt2::DVSRawEncoderEvt2;
+use dvs::dvs::{DVSEvent, DvsRawDecoder, DvsRawEncoder};
+use std::io::Cursor;
+
+const HEADER: &str = "% evt 2.0\n% format EVT2;height=720;width=1280\n% end\n";
+
+// A recording of 1 ms at 1 event per microsecond, spread over the sensor
+fn recording() -> Vec<DVSEvent> {
+    (0..1000)
+        .map(|i| DVSEvent { timestamp: 5_000 + i, x: (i * 7 % 1280) as i16, y: (i * 13 % 720) as i16, polarity: (i % 2) as u8 })
+        .collect()
+}
+
+fn encode(events: &[DVSEvent]) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    let mut encoder = DVSRawEncoderEvt2::new(&mut bytes);
+    encoder.write_header(HEADER.split_inclusive('\n').map(String::from).collect()).unwrap();
+    for event in events {
+        encoder.write_event(*event).unwrap();
+    }
+    encoder.flush().unwrap();
+    drop(encoder);
+    bytes.into_inner()
+}
+
+#[test]
+fn writes_one_time_high_word_per_time_base() {
+    let events = recording();
+    let bytes = encode(&events);
+    // 5000 us to 5999 us spans the time bases 4992 to 5952
+    let time_bases = (5_999 / 64 - 5_000 / 64 + 1) as usize;
+    assert_eq!(bytes.len(), HEADER.len() + 4 * (events.len() + time_bases));
+}
+
+#[test]
+fn round_trips_events() {
+    let events = recording();
+    let mut decoder = DVSRawDecoderEvt2::new(Cursor::new(encode(&events)));
+    decoder.read_header().unwrap();
+    let mut decoded = Vec::new();
+    while let Some(event) = decoder.read_event().unwrap() {
+        decoded.push(event);
+    }
+    assert_eq!(decoded.len(), events.len());
+    for (a, b) in decoded.iter().zip(&events) {
+        assert_eq!((a.timestamp, a.x, a.y, a.polarity), (b.timestamp, b.x, b.y, b.polarity));
+    }
+}
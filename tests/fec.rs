@@ -0,0 +1,84 @@
+// Checks forward error correction in fec.rs: packets lost from a group are recovered from its parity packets,
+// including in the shorter last group of a stream, and malformed packets are rejected rather than panicking
+
+use dvs::dvs::fec::{FecDecoder, FecEncoder, FecOptions, FecPacket, FecScheme};
+use dvs::dvs::packet::Packet;
+use dvs::dvs::DVSEvent;
+
+// Packets of different lengths, so that parity packets cover padded sources
+fn packets(count: u32) -> Vec<Packet> {
+    (0..count)
+        .map(|sequence| {
+            let base_timestamp = sequence as i64 * 1000;
+            let events = (0..1 + sequence as i64 % 5)
+                .map(|i| DVSEvent { timestamp: base_timestamp + i * 7, x: (sequence * 3 + i as u32) as i16, y: i as i16, polarity: (i % 2) as u8 })
+                .collect();
+            Packet { sequence, base_timestamp, events }
+        })
+        .collect()
+}
+
+// Sends the packets through FEC, dropping the FEC packets for which lost returns true, and returns the encoded
+// packets received, by sequence number, and the decoder
+fn send(options: FecOptions, packets: &[Packet], lost: impl Fn(&FecPacket) -> bool) -> (Vec<Vec<u8>>, FecDecoder) {
+    let mut encoder = FecEncoder::new(options).unwrap();
+    let mut fec_packets = Vec::new();
+    for packet in packets {
+        encoder.process(packet, &mut |fec_packet| fec_packets.push(fec_packet));
+    }
+    encoder.finish(&mut |fec_packet| fec_packets.push(fec_packet));
+
+    let mut decoder = FecDecoder::new(4);
+    let mut received = Vec::new();
+    for fec_packet in fec_packets.into_iter().filter(|fec_packet| !lost(fec_packet)) {
+        // Packets go through their wire encoding, as on a link
+        let fec_packet = FecPacket::decode(&fec_packet.encode()).unwrap();
+        decoder.receive(fec_packet, &mut |packet| received.push(packet)).unwrap();
+    }
+    decoder.finish();
+    received.sort_by_key(|packet| packet.sequence);
+    (received.iter().map(Packet::encode).collect(), decoder)
+}
+
+#[test]
+fn recovers_up_to_as_many_lost_packets_as_parity_packets() {
+    // 15 packets in groups of 6 leave a last group of 3
+    let packets = packets(15);
+    let expected: Vec<Vec<u8>> = packets.iter().map(Packet::encode).collect();
+    for scheme in [FecScheme::Xor, FecScheme::ReedSolomon { parity: 1 }, FecScheme::ReedSolomon { parity: 3 }] {
+        let options = FecOptions { group_size: 6, scheme };
+        for drops in 1..=scheme.parity_packets() {
+            // Each group loses a run of packets starting at a different index, sources or parity
+            let lost = |fec_packet: &FecPacket| {
+                let first = fec_packet.group % 3;
+                (first..first + drops as u32).contains(&(fec_packet.index as u32))
+            };
+            let (received, decoder) = send(options, &packets, lost);
+            assert_eq!(received, expected, "{:?} with {} lost per group", scheme, drops);
+            assert_eq!(decoder.packets_lost, 0);
+            assert!(decoder.packets_recovered > 0);
+        }
+    }
+}
+
+#[test]
+fn recovers_packets_of_the_short_last_group() {
+    let packets = packets(15);
+    let expected: Vec<Vec<u8>> = packets.iter().map(Packet::encode).collect();
+    for scheme in [FecScheme::Xor, FecScheme::ReedSolomon { parity: 2 }] {
+        let options = FecOptions { group_size: 6, scheme };
+        let lost = |fec_packet: &FecPacket| fec_packet.group == 2 && fec_packet.index < scheme.parity_packets() as u8;
+        let (received, decoder) = send(options, &packets, lost);
+        assert_eq!(received, expected, "{:?}", scheme);
+        assert_eq!(decoder.packets_recovered, scheme.parity_packets() as u64);
+        assert_eq!(decoder.packets_lost, 0);
+    }
+}
+
+#[test]
+fn rejects_parity_packets_too_short_to_hold_a_length() {
+    // A parity packet of a group of 1 source packet, with an empty payload
+    let fec_packet = FecPacket::decode(&[0, 0, 0, 0, 1, 1, 1, 0]).unwrap();
+    let mut decoder = FecDecoder::new(4);
+    assert!(decoder.receive(fec_packet, &mut |_| panic!("no packet can be recovered")).is_err());
+}
@@ -0,0 +1,109 @@
+// Checks the player behind the playback window, without opening one: frames hold every event once and in
+// order, seeking lands on the same frames in seekable and other formats, and the loss model's drops show in its
+// statistics. Built with the gui feature
+#![cfg(feature = "gui")]
+
+use dvs::dvs::gui::{overlay_text, GuiOptions, Player};
+use dvs::dvs::loss::{LossOptions, RandomDrop};
+use dvs::dvs::render::Frame;
+use dvs::dvs::{prep_file_encoder, DVSEvent, DvsRawEncoder, EventFormat};
+use std::fs::File;
+use std::path::PathBuf;
+
+// Events every 60 us over 3 seconds
+fn events() -> Vec<DVSEvent> {
+    (0..50_000).map(|i| DVSEvent { timestamp: 1_000 + i * 60, x: (i % 128) as i16, y: (i % 96) as i16, polarity: (i % 2) as u8 }).collect()
+}
+
+fn write_recording(name: &str, format: EventFormat) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("dvs-gui-{}-{}", std::process::id(), name));
+    let mut encoder = prep_file_encoder::<File>(path.to_str().unwrap(), format).unwrap();
+    encoder.write_header(vec!["% geometry 128x96\n".to_string()]).unwrap();
+    for event in events() {
+        encoder.write_event(event).unwrap();
+    }
+    encoder.flush().unwrap();
+    path
+}
+
+// Start times and event counts of the frames left to play
+fn play_out(player: &mut Player) -> Vec<(i64, u64)> {
+    let mut frames = Vec::new();
+    while let Some(frame) = player.next_frame().unwrap() {
+        frames.push((frame.start_us, frame.events));
+    }
+    frames
+}
+
+#[test]
+fn frames_hold_every_event_in_order() {
+    let path = write_recording("frames.raw", EventFormat::Evt2);
+    let mut player = Player::open(path.to_str().unwrap(), GuiOptions::default(), None).unwrap();
+    assert_eq!(player.size(), (128, 96));
+    let frames = play_out(&mut player);
+    assert!(player.ended());
+    let frame_us = player.frame_us();
+    assert_eq!(frames[0].0, 0);
+    assert!(frames.windows(2).all(|pair| pair[1].0 == pair[0].0 + frame_us));
+    assert_eq!(frames.iter().map(|(_, events)| events).sum::<u64>(), events().len() as u64);
+    // Without a loss model, the bitrate is that of every event
+    assert!(player.bitrate_mbps() > 0.0);
+    assert_eq!(player.loss_ratio(), None);
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn seeking_lands_on_the_same_frames() {
+    for (name, format) in [("seek.raw", EventFormat::Evt2), ("seek.csv", EventFormat::Csv)] {
+        let path = write_recording(name, format);
+        let mut player = Player::open(path.to_str().unwrap(), GuiOptions::default(), None).unwrap();
+        let frame_us = player.frame_us();
+        let expected = play_out(&mut player);
+
+        // Back to the start, after the end
+        player.seek(i64::MIN).unwrap();
+        assert_eq!(play_out(&mut player), expected, "{}", name);
+
+        // Forward, then back again
+        let mut player = Player::open(path.to_str().unwrap(), GuiOptions::default(), None).unwrap();
+        player.next_frame().unwrap();
+        let target = 2_000_000 + frame_us / 2;
+        player.seek(target).unwrap();
+        assert_eq!(player.position_us(), target / frame_us * frame_us);
+        let frame = player.next_frame().unwrap().unwrap();
+        assert_eq!(frame.start_us, target / frame_us * frame_us);
+        // Events of the frame before the target are skipped
+        let after = events().iter().filter(|event| event.timestamp >= target && event.timestamp < frame.start_us + frame_us).count();
+        assert_eq!(frame.events, after as u64, "{}", name);
+        player.seek(1_000_000).unwrap();
+        let rest = play_out(&mut player);
+        assert_eq!(rest, expected.iter().copied().filter(|(start_us, _)| *start_us >= 1_000_000 / frame_us * frame_us).collect::<Vec<_>>(), "{}", name);
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn loss_models_drop_events_shown() {
+    let path = write_recording("loss.raw", EventFormat::Evt2);
+    let loss = (LossOptions::default(), Box::new(RandomDrop::new(0.5, 7)) as _);
+    let mut player = Player::open(path.to_str().unwrap(), GuiOptions::default(), Some(loss)).unwrap();
+    let shown: u64 = play_out(&mut player).iter().map(|(_, events)| events).sum();
+    assert!(shown > 20_000 && shown < 30_000, "{}", shown);
+    let ratio = player.loss_ratio().unwrap();
+    assert!(ratio > 0.3 && ratio < 0.7, "{}", ratio);
+    assert!(player.status(false).contains("LOSS"));
+    assert!(player.status(true).ends_with("END"));
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn status_lines_are_drawn_over_frames() {
+    let mut frame = Frame::new(0, 640, 480, [30, 37, 52]);
+    overlay_text(&mut frame, "1.000 S  1X");
+    let row = |y: usize| &frame.pixels[y * 640 * 3..(y + 1) * 640 * 3];
+    // The band behind the text, and the text itself, at twice the size on a sensor this wide
+    assert!(row(0).iter().all(|&value| value == 0));
+    assert!(row(3).chunks(3).any(|pixel| pixel == [255, 255, 0]));
+    assert!(row(13).iter().all(|&value| value == 0));
+    assert!(row(14).chunks(3).all(|pixel| pixel == [30, 37, 52]));
+}
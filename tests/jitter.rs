@@ -0,0 +1,82 @@
+// Checks the jitter simulator of jitter.rs: every event comes out once, in order of arrival, delays larger than
+// the spacing of events reorder them and are counted, the same seed gives the same delays, and delay
+// distributions parse from their command line form
+
+use dvs::dvs::jitter::{DelayDistribution, Jitter, JitterOptions};
+use dvs::dvs::DVSEvent;
+
+// Events every 10 us, numbered by x
+fn stream() -> Vec<DVSEvent> {
+    (0..5_000).map(|i| DVSEvent { timestamp: i * 10, x: (i % 1000) as i16, y: (i / 1000) as i16, polarity: (i % 2) as u8 }).collect()
+}
+
+fn key(event: &DVSEvent) -> (i16, i16) {
+    (event.x, event.y)
+}
+
+fn run(delay: DelayDistribution, restamp: bool, seed: u64) -> (Vec<DVSEvent>, u64) {
+    let mut jitter = Jitter::new(JitterOptions { delay, restamp, seed });
+    let mut out = Vec::new();
+    for event in stream() {
+        jitter.process(event, &mut |event| out.push(event));
+    }
+    jitter.finish(&mut |event| out.push(event));
+    (out, jitter.events_reordered)
+}
+
+#[test]
+fn fixed_delays_shift_events_without_reordering() {
+    let (out, reordered) = run(DelayDistribution::Fixed { delay_us: 250.0 }, true, 0);
+    assert_eq!(reordered, 0);
+    assert_eq!(out.iter().map(|event| event.timestamp).collect::<Vec<_>>(), stream().iter().map(|event| event.timestamp + 250).collect::<Vec<_>>());
+    assert_eq!(out.iter().map(key).collect::<Vec<_>>(), stream().iter().map(key).collect::<Vec<_>>());
+}
+
+#[test]
+fn random_delays_reorder_events() {
+    let delay = DelayDistribution::Uniform { min_us: 0.0, max_us: 100.0 };
+    let (restamped, reordered) = run(delay, true, 3);
+    assert!(reordered > 1_000, "{}", reordered);
+    // Arrivals come out in order, each within the range of delays of its sending time
+    assert!(restamped.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+    let sent: std::collections::HashMap<(i16, i16), i64> = stream().iter().map(|event| (key(event), event.timestamp)).collect();
+    assert!(restamped.iter().all(|event| (0..=100).contains(&(event.timestamp - sent[&key(event)]))));
+
+    // Every event comes out once
+    let mut keys: Vec<(i16, i16)> = restamped.iter().map(key).collect();
+    keys.sort();
+    let mut expected: Vec<(i16, i16)> = stream().iter().map(key).collect();
+    expected.sort();
+    assert_eq!(keys, expected);
+
+    // Without restamping, the same events come out in the same order with their original timestamps
+    let (original, _) = run(delay, false, 3);
+    assert_eq!(original.iter().map(key).collect::<Vec<_>>(), restamped.iter().map(key).collect::<Vec<_>>());
+    assert!(original.iter().all(|event| event.timestamp == sent[&key(event)]));
+    assert!(original.windows(2).any(|pair| pair[0].timestamp > pair[1].timestamp));
+
+    // The seed decides the delays
+    assert_eq!(run(delay, true, 3).0.iter().map(key).collect::<Vec<_>>(), restamped.iter().map(key).collect::<Vec<_>>());
+    assert_ne!(run(delay, true, 4).0.iter().map(key).collect::<Vec<_>>(), restamped.iter().map(key).collect::<Vec<_>>());
+}
+
+#[test]
+fn delays_are_never_negative() {
+    for delay in [DelayDistribution::Normal { mean_us: 0.0, std_dev_us: 50.0 }, DelayDistribution::Pareto { scale_us: 5.0, shape: 1.5 }] {
+        let (out, _) = run(delay, true, 9);
+        let sent: std::collections::HashMap<(i16, i16), i64> = stream().iter().map(|event| (key(event), event.timestamp)).collect();
+        assert_eq!(out.len(), stream().len());
+        assert!(out.iter().all(|event| event.timestamp >= sent[&key(event)]), "{:?}", delay);
+    }
+}
+
+#[test]
+fn distributions_parse_from_text() {
+    assert_eq!("fixed:100".parse::<DelayDistribution>().unwrap(), DelayDistribution::Fixed { delay_us: 100.0 });
+    assert_eq!("Uniform: 10, 20".parse::<DelayDistribution>().unwrap(), DelayDistribution::Uniform { min_us: 10.0, max_us: 20.0 });
+    assert_eq!("normal:50,5".parse::<DelayDistribution>().unwrap(), DelayDistribution::Normal { mean_us: 50.0, std_dev_us: 5.0 });
+    assert_eq!("pareto:10,2.5".parse::<DelayDistribution>().unwrap(), DelayDistribution::Pareto { scale_us: 10.0, shape: 2.5 });
+    for text in ["fixed", "fixed:1,2", "uniform:20,10", "pareto:10,0", "normal:a,b", "gamma:1,2"] {
+        assert!(text.parse::<DelayDistribution>().is_err(), "{}", text);
+    }
+}
@@ -0,0 +1,173 @@
+// Checks the loss models of loss.rs: which events of a chunk each built-in model keeps for a given budget, that
+// the random ones are reproducible from their seed, and that LossFilter hands each model whole chunks and counts
+// what it kept
+
+use dvs::dvs::bitrate::BandwidthBudget;
+use dvs::dvs::filters::DvsFilter;
+use dvs::dvs::loss::{
+    GilbertElliott, GilbertElliottParams, LossFilter, LossModel, LossModels, LossOptions, LossParams, PriorityLoss, RandomDrop, Spatial, TailDrop, TokenBucket,
+    UniformThinning,
+};
+use dvs::dvs::DVSEvent;
+
+fn event(timestamp: i64, x: i16, y: i16) -> DVSEvent {
+    DVSEvent { timestamp, x, y, polarity: 1 }
+}
+
+// Events at 0, 1, 2... us
+fn stream(len: usize) -> Vec<DVSEvent> {
+    (0..len as i64).map(|i| event(i, (i % 64) as i16, (i / 64) as i16)).collect()
+}
+
+// Offers a chunk to a model, returning the indices of the events it keeps
+fn kept(model: &mut impl LossModel, chunk: &[DVSEvent], budget: usize) -> Vec<usize> {
+    model.begin_chunk(chunk, budget);
+    chunk.iter().enumerate().filter(|(_, event)| model.admit(event)).map(|(i, _)| i).collect()
+}
+
+#[test]
+fn tail_drop_keeps_the_first_events_of_each_chunk() {
+    let mut model = TailDrop::default();
+    assert_eq!(kept(&mut model, &stream(10), 4), [0, 1, 2, 3]);
+    // The budget of the previous chunk doesn't carry over
+    assert_eq!(kept(&mut model, &stream(10), 0), Vec::<usize>::new());
+    assert_eq!(kept(&mut model, &stream(3), 5), [0, 1, 2]);
+}
+
+#[test]
+fn uniform_thinning_keeps_exactly_the_budget_evenly_spaced() {
+    let mut model = UniformThinning::default();
+    assert_eq!(kept(&mut model, &stream(10), 5), [1, 3, 5, 7, 9]);
+    assert_eq!(kept(&mut model, &stream(10), 3), [3, 6, 9]);
+    assert_eq!(kept(&mut model, &stream(4), 10), [0, 1, 2, 3]);
+    assert_eq!(kept(&mut model, &stream(4), 0), Vec::<usize>::new());
+}
+
+#[test]
+fn random_drop_is_reproducible_and_ignores_the_budget() {
+    let events = stream(10_000);
+    let first = kept(&mut RandomDrop::new(0.3, 42), &events, 0);
+    assert_eq!(kept(&mut RandomDrop::new(0.3, 42), &events, 0), first);
+    assert_ne!(kept(&mut RandomDrop::new(0.3, 43), &events, 0), first);
+    assert!((6_700..7_300).contains(&first.len()), "{}", first.len());
+    assert_eq!(kept(&mut RandomDrop::new(0.0, 1), &events, 0).len(), events.len());
+    assert!(kept(&mut RandomDrop::new(1.0, 1), &events, usize::MAX).is_empty());
+}
+
+#[test]
+fn token_bucket_absorbs_bursts_up_to_its_size() {
+    // 32 bits per event at 1 Mbps, without packet headers: one event's worth of credit every 32 us
+    let budget = BandwidthBudget { bandwidth_mbps: 1.0, bits_per_event: 32.0, packet_payload_bytes: 0, packet_overhead_bytes: 0 };
+    let mut bucket = TokenBucket::new(&budget, 320.0);
+    let admitted = |bucket: &mut TokenBucket, timestamps: &[i64]| timestamps.iter().filter(|&&timestamp| bucket.admit(&event(timestamp, 0, 0))).count();
+    // The bucket starts full
+    assert_eq!(admitted(&mut bucket, &[0; 11]), 10);
+    assert_eq!(admitted(&mut bucket, &[31]), 0);
+    assert_eq!(admitted(&mut bucket, &[32]), 1);
+    // Out of order events add no credit
+    assert_eq!(admitted(&mut bucket, &[10]), 0);
+    // Credit is capped at the burst size however long the link is idle
+    assert_eq!(admitted(&mut bucket, &[1_000_000; 20]), 10);
+}
+
+#[test]
+fn priority_loss_keeps_the_highest_priorities() {
+    let chunk: Vec<DVSEvent> = [5, 1, 4, 2, 3].into_iter().map(|timestamp| event(timestamp, 0, 0)).collect();
+    let mut newest = PriorityLoss::age_weighted();
+    assert_eq!(kept(&mut newest, &chunk, 2), [0, 2]);
+    assert_eq!(kept(&mut newest, &chunk, 5), [0, 1, 2, 3, 4]);
+    // Ties keep the later events
+    let mut flat = PriorityLoss::new(|_: &DVSEvent| 1.0);
+    assert_eq!(kept(&mut flat, &chunk, 2), [3, 4]);
+    let mut by_x = PriorityLoss::new(|event: &DVSEvent| event.x as f64);
+    let chunk: Vec<DVSEvent> = [3, 9, 1, 7].into_iter().map(|x| event(0, x, 0)).collect();
+    assert_eq!(kept(&mut by_x, &chunk, 3), [0, 1, 3]);
+}
+
+#[test]
+fn spatial_keeps_the_most_active_tiles() {
+    // Five events in the tile at the origin, three in the one to its right and one further along
+    let mut chunk = Vec::new();
+    for (i, x) in [25, 1, 12, 2, 13, 3, 14, 4, 5].into_iter().enumerate() {
+        chunk.push(event(i as i64, x, 0));
+    }
+    let mut model = Spatial::new(10);
+    // The second tile crosses the budget and keeps its first event
+    assert_eq!(kept(&mut model, &chunk, 6), [1, 2, 3, 5, 7, 8]);
+    assert_eq!(kept(&mut model, &chunk, 5), [1, 3, 5, 7, 8]);
+    assert_eq!(kept(&mut model, &chunk, 9).len(), 9);
+    // Negative coordinates fall in their own tiles
+    let chunk = [event(0, -1, 0), event(1, -2, 0), event(2, 1, 0)];
+    assert_eq!(kept(&mut model, &chunk, 2), [0, 1]);
+}
+
+#[test]
+fn gilbert_elliott_drops_in_bursts() {
+    let events = stream(100_000);
+    let never_bad = GilbertElliottParams { good_to_bad: 0.0, ..GilbertElliottParams::default() };
+    assert_eq!(kept(&mut GilbertElliott::new(never_bad, 1), &events, 0).len(), events.len());
+    let always_bad = GilbertElliottParams { good_to_bad: 1.0, bad_to_good: 0.0, ..GilbertElliottParams::default() };
+    assert!(kept(&mut GilbertElliott::new(always_bad, 1), &events, 0).is_empty());
+
+    // By default, the chain spends 0.01 / (0.01 + 0.1) of the time in the bad state, in runs of about 10 events
+    let kept = kept(&mut GilbertElliott::new(GilbertElliottParams::default(), 7), &events, 0);
+    let dropped = events.len() - kept.len();
+    assert!((7_000..11_000).contains(&dropped), "{}", dropped);
+    let bursts = kept.windows(2).filter(|pair| pair[1] > pair[0] + 1).count();
+    assert!(dropped / bursts.max(1) >= 7, "{} drops in {} bursts", dropped, bursts);
+}
+
+#[test]
+fn models_are_created_by_name() {
+    let mut models = LossModels::default();
+    for name in ["end-biased", "evenly-distributed", "random", "token-bucket", "age-weighted", "spatial", "gilbert-elliott", "adaptive", "adaptive-throughput"] {
+        assert!(models.names().contains(&name), "{}", name);
+        assert!(models.create(name, &LossParams::default()).is_ok(), "{}", name);
+    }
+    let error = models.create("lossless", &LossParams::default()).err().unwrap().to_string();
+    assert!(error.contains("Unknown loss model 'lossless'") && error.contains("end-biased"), "{}", error);
+
+    // Parameters reach the model, and registering a name again replaces its model
+    let params = LossParams { probability: 1.0, ..LossParams::default() };
+    assert!(kept(&mut models.create("random", &params).unwrap(), &stream(100), 100).is_empty());
+    models.register("random", |_| Box::new(TailDrop::default()));
+    assert_eq!(kept(&mut models.create("random", &params).unwrap(), &stream(100), 3), [0, 1, 2]);
+    assert_eq!(models.names().iter().filter(|&&name| name == "random").count(), 1);
+}
+
+#[test]
+fn loss_filters_pass_whole_chunks_to_the_model() {
+    let options = LossOptions { chunk_us: 1_000, ..LossOptions::default() };
+    let budget = options.events_per_chunk();
+    // Three chunks of twice the budget, and a last one that fits
+    let events: Vec<DVSEvent> = (0..3)
+        .flat_map(|chunk| (0..2 * budget as i64).map(move |i| event(chunk * 1_000 + i * 500 / budget as i64, 0, 0)))
+        .chain([event(3_000, 0, 0)])
+        .collect();
+    let mut filter = LossFilter::new(options, TailDrop::default()).record_chunks();
+    let mut out = Vec::new();
+    for event in &events {
+        filter.process(*event, &mut |event| out.push(event));
+    }
+    // The last chunk is held back until the stream ends
+    assert_eq!(out.len(), 3 * budget);
+    assert_eq!(filter.events_dropped(), 3 * budget as u64);
+    filter.finish(&mut |event| out.push(event));
+    let expected: Vec<i64> = (0..3).flat_map(|chunk| events[chunk * 2 * budget..][..budget].iter().map(|event| event.timestamp)).chain([3_000]).collect();
+    assert_eq!(out.iter().map(|event| event.timestamp).collect::<Vec<_>>(), expected);
+
+    let report = filter.report();
+    assert_eq!((report.events_in, report.events_out, report.events_dropped()), (events.len() as u64, 3 * budget as u64 + 1, 3 * budget as u64));
+    let chunks: Vec<(i64, u64, u64)> = report.chunks.iter().map(|chunk| (chunk.start_us, chunk.events_in, chunk.events_out)).collect();
+    let (full, budget) = (2 * budget as u64, budget as u64);
+    assert_eq!(chunks, [(0, full, budget), (1_000, full, budget), (2_000, full, budget), (3_000, 1, 1)]);
+    assert!(report.chunks.iter().all(|chunk| chunk.bitrate_mbps <= options.budget.bandwidth_mbps), "{:?}", report.chunks);
+    assert_eq!(report.to_csv().lines().filter(|line| !line.starts_with('#')).count(), 5);
+    assert!(report.to_json().contains(&format!("\"events_dropped\":{}", 3 * budget)));
+    assert_eq!(filter.summary().unwrap(), format!("Kept {} of {} events ({} dropped)", report.events_out, report.events_in, report.events_dropped()));
+
+    // A new header starts over
+    filter.header(Vec::new()).unwrap();
+    assert_eq!((filter.events_in, filter.events_out), (0, 0));
+    assert!(filter.report().chunks.is_empty());
+}
@@ -0,0 +1,249 @@
+// Checks the MCAP encoder of raw_encoder_mcap.rs and mcap.rs: the file is the magic, records and the magic again,
+// events come back from the CDR EventArray messages grouped by their time window, messages are stored in chunks
+// of the chunk window with message indexes, and the summary counts the messages and indexes every chunk
+
+use dvs::dvs::raw_encoder_mcap::{DVSRawEncoderMcap, McapOptions};
+use dvs::dvs::{DVSEvent, DvsRawEncoder};
+use std::io::Cursor;
+
+const MAGIC: &[u8] = b"\x89MCAP0\r\n";
+
+// Reads little-endian MCAP fields in order
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, offset: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> &'a [u8] {
+        let bytes = &self.bytes[self.offset..self.offset + len];
+        self.offset += len;
+        bytes
+    }
+
+    fn u8(&mut self) -> u8 {
+        self.take(1)[0]
+    }
+
+    fn u16(&mut self) -> u16 {
+        u16::from_le_bytes(self.take(2).try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes(self.take(4).try_into().unwrap())
+    }
+
+    fn u64(&mut self) -> u64 {
+        u64::from_le_bytes(self.take(8).try_into().unwrap())
+    }
+
+    fn string(&mut self) -> &'a str {
+        let len = self.u32() as usize;
+        std::str::from_utf8(self.take(len)).unwrap()
+    }
+
+    fn done(&self) -> bool {
+        self.offset == self.bytes.len()
+    }
+}
+
+// The records of a run of records, with their opcode, content and offset
+fn records(bytes: &[u8], base: usize) -> Vec<(u8, &[u8], usize)> {
+    let mut reader = Reader::new(bytes);
+    let mut records = Vec::new();
+    while !reader.done() {
+        let offset = base + reader.offset;
+        let opcode = reader.u8();
+        let len = reader.u64() as usize;
+        records.push((opcode, reader.take(len), offset));
+    }
+    records
+}
+
+#[derive(Debug, PartialEq)]
+struct Message {
+    sequence: u32,
+    log_time: u64,
+    // Header stamp, frame id, height and width of the EventArray
+    stamp: (i32, u32),
+    frame_id: String,
+    geometry: (u32, u32),
+    events: Vec<(u16, u16, (i32, u32), bool)>,
+}
+
+// Decodes a Message record with a CDR EventArray. Alignment is counted after the encapsulation header
+fn message(content: &[u8]) -> Message {
+    let mut reader = Reader::new(content);
+    assert_eq!(reader.u16(), 0);
+    let sequence = reader.u32();
+    let log_time = reader.u64();
+    assert_eq!(reader.u64(), log_time);
+    let data = &content[reader.offset..];
+    assert_eq!(&data[..4], &[0x00, 0x01, 0x00, 0x00]);
+    let mut cdr = Reader::new(&data[4..]);
+    let align = |cdr: &mut Reader, size: usize| cdr.offset = cdr.offset.div_ceil(size) * size;
+    let time = |cdr: &mut Reader| {
+        align(cdr, 4);
+        (cdr.u32() as i32, cdr.u32())
+    };
+    let stamp = time(&mut cdr);
+    let frame_id = cdr.string();
+    let frame_id = frame_id.strip_suffix('\0').unwrap().to_string();
+    align(&mut cdr, 4);
+    let geometry = (cdr.u32(), cdr.u32());
+    let count = cdr.u32();
+    let events = (0..count)
+        .map(|_| {
+            align(&mut cdr, 2);
+            let (x, y) = (cdr.u16(), cdr.u16());
+            let ts = time(&mut cdr);
+            (x, y, ts, cdr.u8() != 0)
+        })
+        .collect();
+    assert!(cdr.done());
+    Message { sequence, log_time, stamp, frame_id, geometry, events }
+}
+
+fn encode(options: McapOptions, events: &[DVSEvent]) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    let mut encoder = DVSRawEncoderMcap::with_options(&mut bytes, options);
+    encoder.write_header(vec!["% geometry 1280x720\n".to_string()]).unwrap();
+    let messages_written: usize = events.iter().map(|event| encoder.write_event(*event).unwrap()).sum();
+    encoder.flush().unwrap();
+    drop(encoder);
+    let bytes = bytes.into_inner();
+    // Messages are written once the next event is past their window, and the last one on flush
+    assert_eq!(messages_written + !events.is_empty() as usize, messages(&bytes).len());
+    bytes
+}
+
+// The records between the magic bytes
+fn file_records(bytes: &[u8]) -> Vec<(u8, &[u8], usize)> {
+    assert!(bytes.starts_with(MAGIC) && bytes.ends_with(MAGIC));
+    records(&bytes[MAGIC.len()..bytes.len() - MAGIC.len()], MAGIC.len())
+}
+
+// The messages of the file, whether inside chunks or not
+fn messages(bytes: &[u8]) -> Vec<Message> {
+    let mut messages = Vec::new();
+    for (opcode, content, _) in file_records(bytes) {
+        match opcode {
+            0x05 => messages.push(message(content)),
+            0x06 => {
+                let mut chunk = Reader::new(content);
+                chunk.take(8 + 8 + 8 + 4);
+                assert_eq!(chunk.string(), "");
+                let len = chunk.u64() as usize;
+                for (opcode, content, _) in records(chunk.take(len), 0) {
+                    assert_eq!(opcode, 0x05);
+                    messages.push(message(content));
+                }
+            }
+            _ => {}
+        }
+    }
+    messages
+}
+
+fn event(timestamp: i64, x: i16, polarity: u8) -> DVSEvent {
+    DVSEvent { timestamp, x, y: x / 2, polarity }
+}
+
+#[test]
+fn events_are_grouped_into_messages_by_time_window() {
+    let events = [event(0, 1, 1), event(5_000, 2, 0), event(9_999, 3, 1), event(10_000, 4, 1), event(25_000, 5, 0), event(2_500_000, 1279, 1)];
+    let options = McapOptions { frame_id: "dvs".to_string(), ..McapOptions::default() };
+    let decoded = messages(&encode(options, &events));
+
+    let windows: Vec<Vec<i16>> = vec![vec![1, 2, 3], vec![4], vec![5], vec![1279]];
+    assert_eq!(decoded.len(), windows.len());
+    for (i, (message, xs)) in decoded.iter().zip(windows).enumerate() {
+        let first = events.iter().find(|event| event.x == xs[0]).unwrap();
+        assert_eq!(message.sequence, i as u32);
+        assert_eq!(message.log_time, first.timestamp as u64 * 1_000);
+        assert_eq!(message.stamp, ((first.timestamp / 1_000_000) as i32, (first.timestamp % 1_000_000 * 1_000) as u32));
+        assert_eq!((message.frame_id.as_str(), message.geometry), ("dvs", (720, 1280)));
+        let expected: Vec<_> = events
+            .iter()
+            .filter(|event| xs.contains(&event.x))
+            .map(|event| (event.x as u16, event.y as u16, ((event.timestamp / 1_000_000) as i32, (event.timestamp % 1_000_000 * 1_000) as u32), event.polarity != 0))
+            .collect();
+        assert_eq!(message.events, expected);
+    }
+
+    // Times before zero keep a positive nanosecond field, and log times stop at zero
+    let negative = messages(&encode(McapOptions::default(), &[event(-1, 0, 1)]));
+    assert_eq!(negative[0].log_time, 0);
+    assert_eq!((negative[0].stamp, negative[0].events[0].2), ((-1, 999_999_000), (-1, 999_999_000)));
+}
+
+#[test]
+fn chunks_are_indexed_in_the_summary() {
+    let events: Vec<DVSEvent> = (0..30).map(|i| event(i * 100_000, i as i16, 1)).collect();
+    let options = McapOptions { profile: "ros2".to_string(), message_window_us: 200_000, chunk_window_us: Some(1_000_000), ..McapOptions::default() };
+    let bytes = encode(options, &events);
+    let records = file_records(&bytes);
+    let opcodes: Vec<u8> = records.iter().map(|(opcode, _, _)| *opcode).collect();
+
+    let mut header = Reader::new(records[0].1);
+    assert_eq!(opcodes[..3], [0x01, 0x03, 0x04]);
+    assert_eq!(header.string(), "ros2");
+    assert!(header.string().starts_with("dvs-streaming "));
+    let mut channel = Reader::new(records[2].1);
+    assert_eq!((channel.u16(), channel.u16(), channel.string(), channel.string()), (0, 1, "/dvs/events", "cdr"));
+
+    // Each chunk covers a second of log time, five messages, and is followed by its message index
+    let chunks: Vec<_> = records.iter().filter(|(opcode, _, _)| *opcode == 0x06).collect();
+    assert_eq!(chunks.len(), 3);
+    for (i, (_, content, offset)) in chunks.iter().enumerate() {
+        let mut chunk = Reader::new(content);
+        assert_eq!((chunk.u64(), chunk.u64()), (i as u64 * 1_000_000_000, i as u64 * 1_000_000_000 + 800_000_000));
+        let position = records.iter().position(|(_, _, record_offset)| record_offset == offset).unwrap();
+        assert_eq!(records[position + 1].0, 0x07);
+        let mut index = Reader::new(records[position + 1].1);
+        assert_eq!(index.u16(), 0);
+        assert_eq!(index.u32() as usize, 5 * 16);
+    }
+    assert_eq!(messages(&bytes).iter().flat_map(|message| message.events.iter().map(|event| event.0)).collect::<Vec<_>>(), (0..30).collect::<Vec<u16>>());
+
+    // The chunk indexes point at the chunks
+    let chunk_indexes: Vec<_> = records.iter().filter(|(opcode, _, _)| *opcode == 0x08).collect();
+    assert_eq!(chunk_indexes.len(), 3);
+    for ((_, index, _), (_, chunk, offset)) in chunk_indexes.iter().zip(&chunks) {
+        let mut index = Reader::new(index);
+        index.take(16);
+        assert_eq!((index.u64() as usize, index.u64() as usize), (*offset, 9 + chunk.len()));
+    }
+
+    let mut statistics = Reader::new(records.iter().find(|(opcode, _, _)| *opcode == 0x0B).unwrap().1);
+    assert_eq!(statistics.u64(), 15);
+    assert_eq!((statistics.u16(), statistics.u32(), statistics.u32(), statistics.u32(), statistics.u32()), (1, 1, 0, 0, 3));
+    assert_eq!((statistics.u64(), statistics.u64()), (0, 2_800_000_000));
+
+    // The footer points at the summary section, which starts after the DataEnd record
+    let (footer_opcode, footer, _) = records.last().unwrap();
+    assert_eq!(*footer_opcode, 0x02);
+    let summary_start = Reader::new(footer).u64() as usize;
+    let data_end = records.iter().position(|(opcode, _, _)| *opcode == 0x0F).unwrap();
+    assert_eq!(records[data_end + 1].2, summary_start);
+}
+
+#[test]
+fn messages_can_be_written_outside_chunks() {
+    let events: Vec<DVSEvent> = (0..10).map(|i| event(i * 10_000, i as i16, 0)).collect();
+    let bytes = encode(McapOptions { chunk_window_us: None, ..McapOptions::default() }, &events);
+    let records = file_records(&bytes);
+    assert!(records.iter().all(|(opcode, _, _)| ![0x06, 0x07, 0x08].contains(opcode)));
+    assert_eq!(records.iter().filter(|(opcode, _, _)| *opcode == 0x05).count(), 10);
+    assert_eq!(messages(&bytes).iter().map(|message| message.events.len()).collect::<Vec<_>>(), [1; 10]);
+    assert_eq!(Reader::new(records[0].1).string(), "");
+
+    // An empty recording is still a complete file
+    let empty = encode(McapOptions::default(), &[]);
+    assert!(messages(&empty).is_empty());
+    assert_eq!(file_records(&empty).last().unwrap().0, 0x02);
+}
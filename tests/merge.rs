@@ -0,0 +1,95 @@
+// Checks merging in merge.rs: streams are interleaved by their shifted timestamps, with ties in the order of the
+// streams, and events shifted off the merged sensor are dropped and counted rather than handed to the encoder
+
+use dvs::dvs::merge::{merge_files, Merge, MergeOffset};
+use dvs::dvs::raw_decoder_evt2::DVSRawDecoderEvt2;
+use dvs::dvs::{prep_file_decoder, DVSEvent, DvsRawDecoder, EventFormat};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+
+fn golden_path() -> String {
+    format!("{}/tests/data/golden_evt2.raw", env!("CARGO_MANIFEST_DIR"))
+}
+
+// Header and events of the golden EVT2 sample merged with itself, the copy shifted by the offset
+fn merge_golden(offset: MergeOffset) -> (Vec<String>, Vec<DVSEvent>, u64) {
+    let bytes = std::fs::read(golden_path()).unwrap();
+    let streams = vec![(DVSRawDecoderEvt2::new(Cursor::new(bytes.clone())), MergeOffset::default()), (DVSRawDecoderEvt2::new(Cursor::new(bytes)), offset)];
+    let mut merge = Merge::new(streams);
+    let header = merge.read_header().unwrap();
+    let mut events = Vec::new();
+    while let Some(event) = merge.read_event().unwrap() {
+        events.push(event);
+    }
+    (header, events, merge.events_dropped)
+}
+
+fn golden_events() -> Vec<DVSEvent> {
+    let mut decoder = DVSRawDecoderEvt2::new(Cursor::new(std::fs::read(golden_path()).unwrap()));
+    decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 256).unwrap() > 0 {}
+    events
+}
+
+#[test]
+fn merged_events_are_in_time_order() {
+    let (_, events, dropped) = merge_golden(MergeOffset { time_us: 5, ..MergeOffset::default() });
+    assert_eq!(dropped, 0);
+    assert_eq!(events.len(), 2 * golden_events().len());
+    assert!(events.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+}
+
+#[test]
+fn time_offsets_shift_the_streams_and_ties_keep_the_stream_order() {
+    let golden = golden_events();
+    let key = |event: &DVSEvent| (event.timestamp, event.x, event.y, event.polarity);
+    for time_us in [0, 63, 1_000_000] {
+        let (_, events, _) = merge_golden(MergeOffset { time_us, ..MergeOffset::default() });
+        // The first stream, then the shifted copy, sorted by timestamp alone so that ties keep that order
+        let mut expected: Vec<DVSEvent> = golden.iter().copied().chain(golden.iter().map(|event| DVSEvent { timestamp: event.timestamp + time_us, ..*event })).collect();
+        expected.sort_by_key(|event| event.timestamp);
+        assert_eq!(events.iter().map(key).collect::<Vec<_>>(), expected.iter().map(key).collect::<Vec<_>>(), "offset {}", time_us);
+    }
+}
+
+#[test]
+fn events_shifted_off_the_sensor_are_dropped() {
+    let golden = golden_events();
+    for (x, y) in [(-10, 0), (0, -700), (-1300, 0)] {
+        let (header, events, dropped) = merge_golden(MergeOffset { x, y, ..MergeOffset::default() });
+        let expected = golden.iter().filter(|event| event.x + x >= 0 && event.y + y >= 0).count();
+        assert_eq!(events.len(), golden.len() + expected, "offset ({}, {})", x, y);
+        assert_eq!(dropped as usize, golden.len() - expected);
+        assert!(events.iter().all(|event| (0..1280).contains(&event.x) && (0..720).contains(&event.y)), "offset ({}, {})", x, y);
+        assert!(header.iter().any(|line| line == "% geometry 1280x720\n"), "{:?}", header);
+    }
+}
+
+#[test]
+fn merged_sensor_spans_the_shifted_sensors() {
+    let (header, events, dropped) = merge_golden(MergeOffset { x: 100, y: 10, ..MergeOffset::default() });
+    assert_eq!(dropped, 0);
+    assert!(header.iter().any(|line| line == "% geometry 1380x730\n"), "{:?}", header);
+    assert!(events.iter().any(|event| event.x == 1379 && event.y == 729));
+}
+
+#[test]
+fn merged_files_with_negative_offsets_encode() {
+    let output = std::env::temp_dir().join(format!("dvs-merge-{}.raw", std::process::id()));
+    let inputs = [(golden_path(), MergeOffset::default()), (golden_path(), MergeOffset { x: -10, ..MergeOffset::default() })];
+    let (totals, dropped) = merge_files(&inputs, output.to_str().unwrap(), EventFormat::Evt2, |_| {}).unwrap();
+    let mut decoder = prep_file_decoder::<BufReader<File>>(output.to_str().unwrap()).unwrap();
+    decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 256).unwrap() > 0 {}
+    std::fs::remove_file(&output).unwrap();
+    assert_eq!(events.len() as u64, totals.events);
+    assert_eq!(totals.events + dropped, 2 * golden_events().len() as u64);
+    assert!(dropped > 0);
+
+    // A merged sensor wider than EVT2 coordinates can hold is an error, not a panic
+    let inputs = [(golden_path(), MergeOffset::default()), (golden_path(), MergeOffset { x: 1280, ..MergeOffset::default() })];
+    assert!(merge_files(&inputs, output.to_str().unwrap(), EventFormat::Evt2, |_| {}).is_err());
+    std::fs::remove_file(&output).ok();
+}
@@ -0,0 +1,129 @@
+// Checks the NumPy encoder of raw_encoder_npy.rs: .npy files have a header padded to 64 bytes naming the
+// structured dtype and the final event count, followed by 13-byte little-endian records, and .npz files are a
+// stored zip archive holding the same array as "events.npy", with sizes, CRC and directory that unzip accepts
+
+use dvs::dvs::raw_encoder_npy::DVSRawEncoderNpy;
+use dvs::dvs::{DVSEvent, DvsRawEncoder};
+use std::io::Cursor;
+
+fn events() -> Vec<DVSEvent> {
+    vec![
+        DVSEvent { timestamp: 7, x: 1, y: 2, polarity: 1 },
+        DVSEvent { timestamp: 1 << 40, x: 1279, y: 719, polarity: 0 },
+        // Negative values wrap around, as numpy does when casting to unsigned types
+        DVSEvent { timestamp: -1, x: -2, y: -3, polarity: 1 },
+    ]
+}
+
+fn encode(mut encoder: DVSRawEncoderNpy<&mut Cursor<Vec<u8>>>, events: &[DVSEvent]) {
+    encoder.write_header(vec!["% geometry 1280x720\n".to_string()]).unwrap();
+    for event in events {
+        assert_eq!(encoder.write_event(*event).unwrap(), 1);
+    }
+    encoder.flush().unwrap();
+}
+
+fn npy(events: &[DVSEvent]) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    encode(DVSRawEncoderNpy::new(&mut bytes), events);
+    bytes.into_inner()
+}
+
+fn npz(events: &[DVSEvent]) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    encode(DVSRawEncoderNpy::new_npz(&mut bytes), events);
+    bytes.into_inner()
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+// Bitwise CRC-32 (IEEE), independent of the encoder's table
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { 0xEDB88320 ^ (crc >> 1) } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+// The header text and the records of a .npy array
+fn split_npy(bytes: &[u8]) -> (&str, &[u8]) {
+    assert_eq!(&bytes[..8], b"\x93NUMPY\x01\x00");
+    let end = 10 + u16_at(bytes, 8) as usize;
+    (std::str::from_utf8(&bytes[10..end]).unwrap(), &bytes[end..])
+}
+
+#[test]
+fn arrays_hold_the_final_count_and_packed_records() {
+    let bytes = npy(&events());
+    let (header, records) = split_npy(&bytes);
+    assert_eq!((10 + header.len()) % 64, 0);
+    assert!(header.ends_with('\n'));
+    assert!(header.starts_with("{'descr': [('t', '<u8'), ('x', '<u2'), ('y', '<u2'), ('p', 'u1')], 'fortran_order': False, 'shape': ("));
+    let shape = header.split("'shape': (").nth(1).unwrap().split(')').next().unwrap();
+    assert_eq!(shape.trim(), "3,");
+
+    assert_eq!(records.len(), 3 * 13);
+    let mut expected = Vec::new();
+    expected.extend_from_slice(&7u64.to_le_bytes());
+    expected.extend_from_slice(&[1, 0, 2, 0, 1]);
+    expected.extend_from_slice(&(1u64 << 40).to_le_bytes());
+    expected.extend_from_slice(&[0xFF, 0x04, 0xCF, 0x02, 0]);
+    expected.extend_from_slice(&u64::MAX.to_le_bytes());
+    expected.extend_from_slice(&[0xFE, 0xFF, 0xFD, 0xFF, 1]);
+    assert_eq!(records, expected.as_slice());
+
+    // Patching the count keeps the header length, so an empty array has the same header size
+    let empty = npy(&[]);
+    let (empty_header, empty_records) = split_npy(&empty);
+    assert_eq!(empty_header.len(), header.len());
+    assert!(empty_records.is_empty());
+    assert!(empty_header.contains("'shape': (                   0,)"));
+}
+
+#[test]
+fn archives_store_the_array_as_events_npy() {
+    assert_eq!(crc32(b"123456789"), 0xCBF43926);
+    let array = npy(&events());
+    let archive = npz(&events());
+    let name = b"events.npy";
+
+    // Local file header, stored, followed by the array as written to .npy
+    assert_eq!(u32_at(&archive, 0), 0x04034b50);
+    assert_eq!(u16_at(&archive, 8), 0);
+    assert_eq!(u32_at(&archive, 14), crc32(&array));
+    assert_eq!(u32_at(&archive, 18) as usize, array.len());
+    assert_eq!(u32_at(&archive, 22) as usize, array.len());
+    assert_eq!(u16_at(&archive, 26) as usize, name.len());
+    assert_eq!(u16_at(&archive, 28), 0);
+    assert_eq!(&archive[30..30 + name.len()], name);
+    let data_start = 30 + name.len();
+    assert_eq!(&archive[data_start..data_start + array.len()], array.as_slice());
+
+    // The end of central directory record points to the one central directory entry
+    let eocd = archive.len() - 22;
+    assert_eq!(u32_at(&archive, eocd), 0x06054b50);
+    assert_eq!((u16_at(&archive, eocd + 8), u16_at(&archive, eocd + 10)), (1, 1));
+    let directory = u32_at(&archive, eocd + 16) as usize;
+    assert_eq!(directory, data_start + array.len());
+    assert_eq!(u32_at(&archive, eocd + 12) as usize, eocd - directory);
+    assert_eq!(u32_at(&archive, directory), 0x02014b50);
+    assert_eq!(u32_at(&archive, directory + 16), crc32(&array));
+    assert_eq!(u32_at(&archive, directory + 20) as usize, array.len());
+    assert_eq!(u32_at(&archive, directory + 42), 0);
+    assert_eq!(&archive[directory + 46..eocd], name);
+
+    // An empty archive still holds a valid empty array
+    let empty = npz(&[]);
+    assert_eq!(u32_at(&empty, 14), crc32(&npy(&[])));
+    assert_eq!(&empty[30 + name.len()..empty.len() - 22 - 46 - name.len()], npy(&[]).as_slice());
+}
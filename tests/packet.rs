@@ -0,0 +1,83 @@
+// Checks the packetizer of packet.rs: packets decode to the events they were built from, and a packet closes
+// once it is full, once it spans too long, or when an event can't be stored relative to its base timestamp
+
+use dvs::dvs::packet::{Packet, Packetizer, PacketizerOptions, PACKET_EVENT_BYTES, PACKET_HEADER_BYTES};
+use dvs::dvs::DVSEvent;
+
+fn event(timestamp: i64) -> DVSEvent {
+    DVSEvent { timestamp, x: (timestamp % 1280) as i16, y: (timestamp % 720) as i16, polarity: (timestamp % 2) as u8 }
+}
+
+fn key(event: &DVSEvent) -> (i64, i16, i16, u8) {
+    (event.timestamp, event.x, event.y, event.polarity)
+}
+
+fn packetize(options: PacketizerOptions, timestamps: &[i64]) -> Vec<Packet> {
+    let mut packetizer = Packetizer::new(options);
+    let mut packets = Vec::new();
+    for &timestamp in timestamps {
+        packetizer.process(event(timestamp), &mut |packet| packets.push(packet));
+    }
+    packetizer.finish(&mut |packet| packets.push(packet));
+    assert_eq!(packetizer.packets() as usize, packets.len());
+    packets
+}
+
+fn timestamps(packets: &[Packet]) -> Vec<Vec<i64>> {
+    packets.iter().map(|packet| packet.events.iter().map(|event| event.timestamp).collect()).collect()
+}
+
+#[test]
+fn packets_decode_to_their_events() {
+    let events = vec![
+        DVSEvent { timestamp: 1_000_000_007, x: 1279, y: 719, polarity: 1 },
+        DVSEvent { timestamp: 1_000_000_007, x: 0, y: 0, polarity: 0 },
+        DVSEvent { timestamp: 1_000_400_000, x: 640, y: 0x7FFF, polarity: 1 },
+    ];
+    let packet = Packet { sequence: 0xDEADBEEF, base_timestamp: 1_000_000_007, events: events.clone() };
+    let bytes = packet.encode();
+    assert_eq!(bytes.len(), packet.encoded_len());
+    assert_eq!(bytes.len(), PACKET_HEADER_BYTES + 3 * PACKET_EVENT_BYTES);
+    // The polarity shares the y field, in its top bit
+    assert_eq!(&bytes[PACKET_HEADER_BYTES + 6..PACKET_HEADER_BYTES + 8], &(719u16 | 0x8000).to_le_bytes());
+
+    let decoded = Packet::decode(&bytes).unwrap();
+    assert_eq!((decoded.sequence, decoded.base_timestamp), (0xDEADBEEF, 1_000_000_007));
+    assert_eq!(decoded.events.iter().map(key).collect::<Vec<_>>(), events.iter().map(key).collect::<Vec<_>>());
+
+    assert!(Packet::decode(&bytes[..PACKET_HEADER_BYTES - 1]).is_err());
+    assert!(Packet::decode(&bytes[..bytes.len() - 1]).is_err());
+    // Bytes past the events are ignored
+    let mut padded = bytes.clone();
+    padded.extend_from_slice(&[0xFF; 5]);
+    assert_eq!(Packet::decode(&padded).unwrap().events.len(), 3);
+}
+
+#[test]
+fn packets_close_when_full() {
+    let options = PacketizerOptions { max_bytes: PACKET_HEADER_BYTES + 4 * PACKET_EVENT_BYTES + 7, max_span_us: None };
+    assert_eq!(options.max_events(), 4);
+    let packets = packetize(options, &(0..10).collect::<Vec<_>>());
+    assert_eq!(timestamps(&packets), [vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]]);
+    assert_eq!(packets.iter().map(|packet| packet.sequence).collect::<Vec<_>>(), [0, 1, 2]);
+    assert_eq!(packets.iter().map(|packet| packet.base_timestamp).collect::<Vec<_>>(), [0, 4, 8]);
+    assert!(packets.iter().all(|packet| packet.encoded_len() <= options.max_bytes));
+    // A packet holds at least one event, however small the limit
+    assert_eq!(PacketizerOptions { max_bytes: 0, max_span_us: None }.max_events(), 1);
+    assert_eq!(PacketizerOptions::default().max_events(), (1472 - PACKET_HEADER_BYTES) / PACKET_EVENT_BYTES);
+}
+
+#[test]
+fn packets_close_when_they_span_too_long() {
+    let options = PacketizerOptions { max_span_us: Some(100), ..PacketizerOptions::default() };
+    let packets = packetize(options, &[0, 50, 99, 100, 150, 1_000]);
+    assert_eq!(timestamps(&packets), [vec![0, 50, 99], vec![100, 150], vec![1_000]]);
+
+    // Offsets are unsigned 32-bit, so events before the base or too far after it start a new packet
+    let packets = packetize(PacketizerOptions::default(), &[10, 20, 5, 6, 5 + u32::MAX as i64, 6 + u32::MAX as i64]);
+    assert_eq!(timestamps(&packets), [vec![10, 20], vec![5, 6, 5 + u32::MAX as i64], vec![6 + u32::MAX as i64]]);
+    for packet in &packets {
+        assert_eq!(timestamps(&[Packet::decode(&packet.encode()).unwrap()]), timestamps(std::slice::from_ref(packet)));
+    }
+    assert!(packetize(PacketizerOptions::default(), &[]).is_empty());
+}
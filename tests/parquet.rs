@@ -0,0 +1,103 @@
+// Checks the Parquet encoder by reading its files back with the parquet crate: the columns hold the events,
+// the header is kept in the metadata, and the columns use the intended encodings. Built with the parquet feature
+#![cfg(feature = "parquet")]
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{Int64Type, UInt16Type, UInt8Type};
+use dvs::dvs::raw_decoder_evt2::DVSRawDecoderEvt2;
+use dvs::dvs::raw_encoder_parquet::DVSRawEncoderParquet;
+use dvs::dvs::{prep_file_encoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat};
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use parquet::basic::Encoding;
+use std::fs::File;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+fn golden() -> (Vec<String>, Vec<DVSEvent>) {
+    let bytes = std::fs::read(format!("{}/tests/data/golden_evt2.raw", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let mut decoder = DVSRawDecoderEvt2::new(Cursor::new(bytes));
+    let header = decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 256).unwrap() > 0 {}
+    (header, events)
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dvs-parquet-{}-{}.parquet", std::process::id(), name))
+}
+
+fn write(path: &Path, header: Vec<String>, events: &[DVSEvent]) {
+    let mut encoder = prep_file_encoder::<File>(path.to_str().unwrap(), EventFormat::Parquet).unwrap();
+    encoder.write_header(header).unwrap();
+    for event in events {
+        encoder.write_event(*event).unwrap();
+    }
+    encoder.flush().unwrap();
+}
+
+fn read(path: &Path) -> Vec<(i64, i16, i16, u8)> {
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap().build().unwrap();
+    let mut rows = Vec::new();
+    for batch in reader {
+        let batch = batch.unwrap();
+        let t = batch.column_by_name("t").unwrap().as_primitive::<Int64Type>();
+        let x = batch.column_by_name("x").unwrap().as_primitive::<UInt16Type>();
+        let y = batch.column_by_name("y").unwrap().as_primitive::<UInt16Type>();
+        let p = batch.column_by_name("p").unwrap().as_primitive::<UInt8Type>();
+        for i in 0..batch.num_rows() {
+            rows.push((t.value(i), x.value(i) as i16, y.value(i) as i16, p.value(i)));
+        }
+    }
+    rows
+}
+
+fn key(event: &DVSEvent) -> (i64, i16, i16, u8) {
+    (event.timestamp, event.x, event.y, event.polarity)
+}
+
+#[test]
+fn parquet_files_hold_the_events_and_header() {
+    let (header, events) = golden();
+    let path = temp_path("golden");
+    write(&path, header.clone(), &events);
+    assert_eq!(read(&path), events.iter().map(key).collect::<Vec<_>>());
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+    let metadata = builder.metadata().file_metadata().key_value_metadata().unwrap();
+    let value = |key: &str| metadata.iter().find(|kv| kv.key == key).and_then(|kv| kv.value.clone());
+    let expected: Vec<&str> = header.iter().map(|line| line.trim_end()).collect();
+    assert_eq!(value("dvs.header").unwrap(), expected.join("\n"));
+    assert!(value("dvs.geometry").is_some());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn columns_are_delta_and_dictionary_encoded() {
+    // More events than a record batch, with coordinates taking few values
+    let events: Vec<DVSEvent> = (0..150_000).map(|i| DVSEvent { timestamp: i * 2, x: (i % 640) as i16, y: (i % 480) as i16, polarity: (i % 2) as u8 }).collect();
+    let path = temp_path("encodings");
+    write(&path, Vec::new(), &events);
+    assert_eq!(read(&path), events.iter().map(key).collect::<Vec<_>>());
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+    for row_group in builder.metadata().row_groups() {
+        for column in row_group.columns() {
+            let encodings = column.encodings();
+            match column.column_path().string().as_str() {
+                "t" => assert!(encodings.contains(&Encoding::DELTA_BINARY_PACKED), "{:?}", encodings),
+                name => assert!(encodings.contains(&Encoding::RLE_DICTIONARY), "{}: {:?}", name, encodings),
+            }
+        }
+    }
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn events_after_flush_are_errors() {
+    let mut encoder = DVSRawEncoderParquet::new(Cursor::new(Vec::new())).unwrap();
+    encoder.write_header(Vec::new()).unwrap();
+    encoder.write_event(DVSEvent { timestamp: 1, x: 2, y: 3, polarity: 1 }).unwrap();
+    encoder.flush().unwrap();
+    encoder.flush().unwrap();
+    assert!(encoder.write_event(DVSEvent { timestamp: 2, x: 2, y: 3, polarity: 1 }).is_err());
+}
@@ -0,0 +1,103 @@
+// Checks the lossy codec of quantize.rs: binned chunks fit in the bandwidth, including during bursts, and binned
+// events stay on the sensor and in the time bin of the events they stand for
+
+use dvs::dvs::bitrate::{bits_in, BandwidthBudget};
+use dvs::dvs::filters::DvsFilter;
+use dvs::dvs::quantize::{QuantizeChunkStats, QuantizeFilter, QuantizeOptions, QuantizeReport};
+use dvs::dvs::rng::SplitMix64;
+use dvs::dvs::DVSEvent;
+use std::collections::{HashMap, HashSet};
+
+const CHUNK_US: i64 = 10_000;
+
+// Events spread over the sensor, with the given number of events in each chunk
+fn events((width, height): (u32, u32), per_chunk: &[usize]) -> Vec<DVSEvent> {
+    let mut rng = SplitMix64::new(4106);
+    let mut events = Vec::new();
+    for (chunk, &count) in per_chunk.iter().enumerate() {
+        for i in 0..count {
+            events.push(DVSEvent {
+                timestamp: chunk as i64 * CHUNK_US + (i as i64 * CHUNK_US) / count as i64,
+                x: (rng.next_u64() % width as u64) as i16,
+                y: (rng.next_u64() % height as u64) as i16,
+                polarity: (rng.next_u64() & 1) as u8,
+            });
+        }
+    }
+    events
+}
+
+// Runs a QuantizeFilter on the events of a sensor, returning the binned events and the report
+fn quantize(events: &[DVSEvent], (width, height): (u32, u32), bandwidth_mbps: f64, max_spatial_level: u32) -> (Vec<DVSEvent>, QuantizeReport) {
+    let options = QuantizeOptions {
+        budget: BandwidthBudget { bandwidth_mbps, ..BandwidthBudget::default() },
+        chunk_us: CHUNK_US,
+        max_spatial_level,
+        ..QuantizeOptions::default()
+    };
+    let mut filter = QuantizeFilter::new(options).record_chunks();
+    filter.header(vec![format!("% geometry {}x{}\n", width, height)]).unwrap();
+    let mut out = Vec::new();
+    for event in events {
+        filter.process(*event, &mut |event| out.push(event));
+    }
+    filter.finish(&mut |event| out.push(event));
+    (out, filter.report())
+}
+
+// Statistics of the chunk holding the event
+fn chunk_of<'a>(stats: &'a HashMap<i64, QuantizeChunkStats>, event: &DVSEvent) -> &'a QuantizeChunkStats {
+    &stats[&(event.timestamp.div_euclid(CHUNK_US) * CHUNK_US)]
+}
+
+#[test]
+fn binned_chunks_fit_in_the_bandwidth() {
+    let geometry = (640, 480);
+    // A quiet stream with a burst of 20 thousand events per chunk in the middle
+    let per_chunk: Vec<usize> = (0..20).map(|chunk| if (5..9).contains(&chunk) { 20_000 } else { 200 }).collect();
+    let events = events(geometry, &per_chunk);
+    for bandwidth_mbps in [0.1, 0.5, 2.0, 20.0] {
+        let (out, report) = quantize(&events, geometry, bandwidth_mbps, 5);
+        assert_eq!(report.chunks.len(), per_chunk.len());
+        assert_eq!(report.events_out, out.len() as u64);
+        let budget_bits = bits_in(bandwidth_mbps, CHUNK_US);
+        let stats: HashMap<i64, QuantizeChunkStats> = report.chunks.iter().map(|chunk| (chunk.start_us, *chunk)).collect();
+        for chunk in &report.chunks {
+            let bits_per_event = chunk.levels.bits_per_event(geometry, CHUNK_US);
+            let budget = BandwidthBudget { bandwidth_mbps, bits_per_event, ..BandwidthBudget::default() };
+            let bits = budget.bits_for_events(chunk.events_out as usize);
+            assert!(bits <= budget_bits, "{} Mbps, chunk at {} us takes {} bits of {}", bandwidth_mbps, chunk.start_us, bits, budget_bits);
+            assert!(chunk.bitrate_mbps <= bandwidth_mbps * (1.0 + 1e-9));
+            let sent = out.iter().filter(|event| chunk_of(&stats, event).start_us == chunk.start_us).count();
+            assert_eq!(sent as u64, chunk.events_out);
+        }
+        // Bursts are binned at least as coarsely as the quiet chunks around them
+        let coarseness = |chunk: &QuantizeChunkStats| chunk.levels.spatial + chunk.levels.temporal;
+        assert!(coarseness(&report.chunks[6]) >= coarseness(&report.chunks[0]), "{} Mbps", bandwidth_mbps);
+    }
+}
+
+#[test]
+fn binned_events_stay_within_the_recording() {
+    // Sides that aren't multiples of the bins, so the last bins are cut by the edges of the sensor
+    let geometry = (100, 75);
+    let events = events(geometry, &[3000, 50, 3000, 3000, 1]);
+    for bandwidth_mbps in [0.02, 0.2, 2.0] {
+        let (out, report) = quantize(&events, geometry, bandwidth_mbps, 6);
+        let stats: HashMap<i64, QuantizeChunkStats> = report.chunks.iter().map(|chunk| (chunk.start_us, *chunk)).collect();
+        // Bins of an event at the levels of its chunk
+        let bin = |event: &DVSEvent| {
+            let chunk = chunk_of(&stats, event);
+            let (bin_px, bin_us) = (chunk.levels.bin_px(), chunk.levels.bin_us());
+            (chunk.start_us, (event.timestamp - chunk.start_us) / bin_us, event.x as i64 / bin_px, event.y as i64 / bin_px, event.polarity)
+        };
+        let input_bins: HashSet<_> = events.iter().map(bin).collect();
+        for event in &out {
+            let chunk = chunk_of(&stats, event);
+            assert!((0..geometry.0 as i16).contains(&event.x) && (0..geometry.1 as i16).contains(&event.y), "{:?}", event);
+            assert!((chunk.start_us..chunk.start_us + CHUNK_US).contains(&event.timestamp), "{:?}", event);
+            // The event falls in the bin of at least one input event
+            assert!(input_bins.contains(&bin(event)), "{} Mbps, {:?} at levels {:?}", bandwidth_mbps, event, chunk.levels);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+// Checks the QUIC transport over the loopback interface: a recording arrives whole and in order, chunks
+// abandoned by the sender are counted as lost by the receiver, and a sender only connects to the receiver whose
+// certificate it trusts. Built with the quic feature
+#![cfg(feature = "quic")]
+
+use dvs::dvs::net::quic::{QuicEventReceiver, QuicEventSender, QuicReceiverOptions, QuicSenderOptions};
+use dvs::dvs::raw_decoder_evt2::DVSRawDecoderEvt2;
+use dvs::dvs::{DVSEvent, DvsRawDecoder};
+use std::fs::File;
+use std::io::{BufReader, Cursor};
+use std::time::Duration;
+
+fn golden() -> (Vec<String>, Vec<DVSEvent>) {
+    let bytes = std::fs::read(format!("{}/tests/data/golden_evt2.raw", env!("CARGO_MANIFEST_DIR"))).unwrap();
+    let mut decoder = DVSRawDecoderEvt2::new(Cursor::new(bytes));
+    let header = decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 256).unwrap() > 0 {}
+    (header, events)
+}
+
+fn key(event: &DVSEvent) -> (i64, i16, i16, u8) {
+    (event.timestamp, event.x, event.y, event.polarity)
+}
+
+// Events spread over many chunks
+fn spread_events() -> Vec<DVSEvent> {
+    (0..20_000).map(|i| DVSEvent { timestamp: i * 50, x: (i % 640) as i16, y: (i % 480) as i16, polarity: (i % 2) as u8 }).collect()
+}
+
+fn bind_receiver() -> QuicEventReceiver {
+    QuicEventReceiver::bind("127.0.0.1:0", QuicReceiverOptions { timeout: Some(Duration::from_secs(10)) }).unwrap()
+}
+
+// Sends the events from another thread, returning the sender once it has finished
+fn send(receiver: &QuicEventReceiver, options: QuicSenderOptions, header: Vec<String>, events: Vec<DVSEvent>) -> std::thread::JoinHandle<QuicEventSender> {
+    let address = receiver.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let mut sender = QuicEventSender::connect(address, options).unwrap();
+        sender.send_header(&header).unwrap();
+        sender.send_events(&events).unwrap();
+        sender.finish().unwrap();
+        sender
+    })
+}
+
+fn receive(receiver: &mut QuicEventReceiver) -> (Vec<String>, Vec<DVSEvent>) {
+    let header = DvsRawDecoder::<BufReader<File>>::read_header(receiver).unwrap();
+    let mut events = Vec::new();
+    while DvsRawDecoder::<BufReader<File>>::read_events_into(receiver, &mut events, 1000).unwrap() > 0 {}
+    (header, events)
+}
+
+#[test]
+fn recordings_arrive_whole_and_in_order() {
+    let (header, events) = golden();
+    let mut receiver = bind_receiver();
+    // Without a deadline, every chunk is delivered however long it takes
+    let options = QuicSenderOptions { deadline: None, certificate: Some(receiver.certificate().to_vec()), ..QuicSenderOptions::default() };
+    let sender = send(&receiver, options.clone(), header.clone(), events.clone());
+    let (received_header, received) = receive(&mut receiver);
+    // Header lines are sent without their line endings
+    let lines = |header: &[String]| header.iter().map(|line| line.trim_end().to_string()).filter(|line| !line.is_empty()).collect::<Vec<_>>();
+    assert_eq!(lines(&received_header), lines(&header));
+    assert_eq!(received.iter().map(key).collect::<Vec<_>>(), events.iter().map(key).collect::<Vec<_>>());
+    let sender = sender.join().unwrap();
+    assert_eq!(receiver.chunks_received, sender.chunks_sent);
+    assert_eq!(receiver.chunks_lost, 0);
+    assert_eq!(sender.chunks_abandoned(), 0);
+
+    let events = spread_events();
+    let mut receiver = bind_receiver();
+    let sender = send(&receiver, QuicSenderOptions { certificate: None, ..options }, Vec::new(), events.clone());
+    let (_, received) = receive(&mut receiver);
+    assert_eq!(received.iter().map(key).collect::<Vec<_>>(), events.iter().map(key).collect::<Vec<_>>());
+    assert_eq!(receiver.chunks_received, sender.join().unwrap().chunks_sent);
+}
+
+#[test]
+fn abandoned_chunks_are_lost() {
+    let events = spread_events();
+    let mut receiver = bind_receiver();
+    // Chunks are abandoned unless they are acknowledged within a tick of the timer
+    let options = QuicSenderOptions { deadline: Some(Duration::ZERO), ..QuicSenderOptions::default() };
+    let sender = send(&receiver, options, Vec::new(), events.clone());
+    let (_, received) = receive(&mut receiver);
+    let sender = sender.join().unwrap();
+    assert!(sender.chunks_abandoned() > 0);
+
+    // A reset may reach the receiver after the whole chunk, which is then kept
+    assert_eq!(receiver.chunks_received + receiver.chunks_lost, sender.chunks_sent);
+    assert!(receiver.chunks_lost <= sender.chunks_abandoned());
+    let mut expected = events.iter().map(key);
+    for event in received.iter().map(key) {
+        assert!(expected.by_ref().any(|e| e == event), "{:?} is out of order", event);
+    }
+}
+
+#[test]
+fn senders_check_the_receiver_certificate() {
+    let receiver = bind_receiver();
+    let other = QuicEventReceiver::bind("127.0.0.1:0", QuicReceiverOptions::default()).unwrap();
+    let options = QuicSenderOptions { certificate: Some(other.certificate().to_vec()), ..QuicSenderOptions::default() };
+    // The receiver has to accept the connection for the handshake to complete
+    let address = receiver.local_addr().unwrap();
+    let accept = std::thread::spawn(move || {
+        let mut receiver = receiver;
+        DvsRawDecoder::<BufReader<File>>::read_header(&mut receiver).map(|_| ())
+    });
+    assert!(QuicEventSender::connect(address, options).is_err());
+    assert!(accept.join().unwrap().is_err());
+}
@@ -0,0 +1,28 @@
+// Checks the PNG frames of the render module by decoding them with the png crate: the image has the frame's size
+// and pixels, and is compressed. Built with the viz feature
+#![cfg(feature = "viz")]
+
+use dvs::dvs::render::Frame;
+
+#[test]
+fn frames_decode_to_their_pixels() {
+    let mut frame = Frame::new(0, 320, 240, [128, 128, 128]);
+    for i in 0..200 {
+        frame.set(i, i / 2, [255, 255, 255]);
+        frame.set(319 - i, i, [0, 0, 0]);
+    }
+    let png = frame.to_png().unwrap();
+    // Mostly background, so far smaller than the raw pixels
+    assert!(png.len() < frame.pixels.len() / 10, "{}", png.len());
+
+    let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).unwrap();
+    assert_eq!((info.width, info.height, info.color_type, info.bit_depth), (320, 240, png::ColorType::Rgb, png::BitDepth::Eight));
+    assert_eq!(&pixels[..info.buffer_size()], frame.pixels.as_slice());
+
+    let path = std::env::temp_dir().join(format!("dvs-render-{}.png", std::process::id()));
+    frame.write_png(&path).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), png);
+    std::fs::remove_file(path).unwrap();
+}
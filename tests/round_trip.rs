@@ -0,0 +1,174 @@
+// Checks that the binary formats store CD events losslessly: decoding a checked-in sample of each format gives
+// the events listed in tests/data/golden.csv, and decode -> encode -> decode gives the same events and
+// timestamps, both for the samples and for event streams generated with proptest, which shrinks a failing stream
+// to a minimal one. Entropy coded packets decompress to the packets they were compressed from
+
+use dvs::dvs::entropy::{EntropyCoder, EntropyCoding};
+use dvs::dvs::packet::{Packetizer, PacketizerOptions};
+use dvs::dvs::raw_decoder_csv::CsvOptions;
+use dvs::dvs::{prep_encoder, prep_stream_decoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat, FormatHint};
+use proptest::prelude::*;
+use proptest::strategy::ValueTree;
+use proptest::test_runner::TestRunner;
+use std::io::Cursor;
+use std::sync::{Mutex, OnceLock};
+
+const WIDTH: u64 = 1280;
+const HEIGHT: u64 = 720;
+// Random streams generated per test
+const CASES: u32 = 64;
+
+// Samples checked in under tests/data, and their format
+const GOLDEN: [(&str, EventFormat); 4] =
+    [("golden_evt2.raw", EventFormat::Evt2), ("golden_evt21.raw", EventFormat::Evt21), ("golden_evt3.raw", EventFormat::Evt3), ("golden.dat", EventFormat::Dat)];
+
+fn data_path(name: &str) -> String {
+    format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+// Events listed in golden.csv, one "t,x,y,p" line each
+fn golden_events() -> Vec<DVSEvent> {
+    let text = std::fs::read_to_string(data_path("golden.csv")).unwrap();
+    text.lines()
+        .filter(|line| !line.starts_with('%') && !line.is_empty())
+        .map(|line| {
+            let fields: Vec<i64> = line.split(',').map(|field| field.trim().parse().unwrap()).collect();
+            DVSEvent { timestamp: fields[0], x: fields[1] as i16, y: fields[2] as i16, polarity: fields[3] as u8 }
+        })
+        .collect()
+}
+
+// Header and events of a recording, detecting its format
+fn decode(bytes: &[u8]) -> (Vec<String>, Vec<DVSEvent>) {
+    let mut decoder = prep_stream_decoder(bytes, FormatHint::Auto).unwrap();
+    let header = decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 4096).unwrap() > 0 {}
+    (header, events)
+}
+
+fn encode(format: EventFormat, header: Vec<String>, events: &[DVSEvent]) -> Vec<u8> {
+    let mut bytes = Cursor::new(Vec::new());
+    let mut encoder = prep_encoder(&mut bytes, format, CsvOptions::default()).unwrap();
+    encoder.write_header(header).unwrap();
+    for event in events {
+        encoder.write_event(*event).unwrap();
+    }
+    encoder.flush().unwrap();
+    drop(encoder);
+    bytes.into_inner()
+}
+
+// Fields of the events, with runs sharing a timestamp, row and polarity in column order: the vectorized formats
+// store such runs as bitmasks over the row, which don't keep the order the events came in
+fn fields(events: &[DVSEvent]) -> Vec<(i64, i16, i16, u8)> {
+    events
+        .chunk_by(|a, b| (a.timestamp, a.y, a.polarity) == (b.timestamp, b.y, b.polarity))
+        .flat_map(|run| {
+            let mut run: Vec<_> = run.iter().map(|event| (event.timestamp, event.x, event.y, event.polarity)).collect();
+            run.sort_by_key(|&(_, x, _, _)| x);
+            run
+        })
+        .collect()
+}
+
+// One step of a random stream: the gap to the previous event, mostly short and sometimes none (a burst sharing a
+// timestamp, which the vectorized formats group into one word) or up to 40 s (across wraparounds of the EVT3 time
+// base), whether the event neighbours the previous one on its row, as edges moving across the sensor produce,
+// then its position and polarity
+fn step() -> impl Strategy<Value = (i64, bool, u64, u64, u8)> {
+    let gap = prop_oneof![40 => Just(0i64), 59 => 0..200i64, 1 => 0..40_000_000i64];
+    (gap, prop::bool::weighted(1.0 / 3.0), 0..WIDTH, 0..HEIGHT, 0..2u8)
+}
+
+// A time ordered stream of up to a few thousand events over the sensor. Failing streams shrink to fewer events
+// and shorter gaps
+fn event_stream() -> impl Strategy<Value = Vec<DVSEvent>> {
+    (0..100_000i64, prop::collection::vec(step(), 0..4000)).prop_map(|(start, steps)| {
+        let mut timestamp = start;
+        let mut events: Vec<DVSEvent> = Vec::with_capacity(steps.len());
+        for (gap, neighbour, x, y, polarity) in steps {
+            timestamp += gap;
+            let (x, y) = match events.last() {
+                Some(&DVSEvent { x: last_x, y: last_y, .. }) if neighbour => ((last_x as u64 + 1 + x % 4) % WIDTH, last_y as u64),
+                _ => (x, y),
+            };
+            events.push(DVSEvent { timestamp, x: x as i16, y: y as i16, polarity });
+        }
+        events
+    })
+}
+
+// Coders trained once on packets of a few streams drawn with a fixed seed
+fn coders() -> &'static Mutex<Vec<(EntropyCoding, EntropyCoder)>> {
+    static CODERS: OnceLock<Mutex<Vec<(EntropyCoding, EntropyCoder)>>> = OnceLock::new();
+    CODERS.get_or_init(|| {
+        let mut runner = TestRunner::deterministic();
+        let samples: Vec<Vec<u8>> = (0..8).flat_map(|_| packets(&event_stream().new_tree(&mut runner).unwrap().current())).collect();
+        let codings = [
+            EntropyCoding::Range,
+            #[cfg(feature = "compression")]
+            EntropyCoding::ZstdDictionary,
+        ];
+        Mutex::new(codings.into_iter().map(|coding| (coding, EntropyCoder::train(coding, &samples).unwrap())).collect())
+    })
+}
+
+fn packets(events: &[DVSEvent]) -> Vec<Vec<u8>> {
+    let mut packetizer = Packetizer::new(PacketizerOptions::default());
+    let mut packets = Vec::new();
+    for event in events {
+        packetizer.process(*event, &mut |packet| packets.push(packet.encode()));
+    }
+    packetizer.finish(&mut |packet| packets.push(packet.encode()));
+    packets
+}
+
+#[test]
+fn golden_samples_decode_to_the_listed_events() {
+    let expected = golden_events();
+    for (name, _) in GOLDEN {
+        let (_, events) = decode(&std::fs::read(data_path(name)).unwrap());
+        assert_eq!(fields(&events), fields(&expected), "{}", name);
+    }
+}
+
+#[test]
+fn golden_samples_round_trip() {
+    for (name, format) in GOLDEN {
+        let (header, events) = decode(&std::fs::read(data_path(name)).unwrap());
+        let (_, round_tripped) = decode(&encode(format, header, &events));
+        assert_eq!(fields(&round_tripped), fields(&events), "{}", name);
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+    #[test]
+    fn random_streams_round_trip(events in event_stream()) {
+        let header = vec!["% geometry 1280x720\n".to_string()];
+        for format in [EventFormat::Evt2, EventFormat::Evt21, EventFormat::Evt3, EventFormat::Dat, EventFormat::Delta] {
+            let (decoded_header, decoded) = decode(&encode(format, header.clone(), &events));
+            prop_assert_eq!(fields(&decoded), fields(&events), "{:?}", format);
+            let (_, round_tripped) = decode(&encode(format, decoded_header, &decoded));
+            prop_assert_eq!(fields(&round_tripped), fields(&events), "{:?} re-encoded", format);
+        }
+    }
+
+    // Packets of streams not trained on, and bytes unlike any packet
+    #[test]
+    fn entropy_coded_packets_round_trip(events in event_stream(), bytes in prop::collection::vec(any::<u8>(), 0..2000)) {
+        let mut inputs = packets(&events);
+        inputs.push(Vec::new());
+        inputs.push(bytes);
+        for (coding, coder) in coders().lock().unwrap().iter_mut() {
+            for (i, input) in inputs.iter().enumerate() {
+                let (mut compressed, mut decompressed) = (Vec::new(), Vec::new());
+                coder.compress(input, &mut compressed).unwrap();
+                coder.decompress(&compressed, &mut decompressed).unwrap();
+                prop_assert_eq!(&decompressed, input, "{:?}, input {}", coding, i);
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+// Checks the RTP payload format of rtp.rs: headers carry the fields of RFC 3550, packets decode to their events,
+// including past CSRCs, extensions and padding, and the marker bit is set on the last packet of each chunk
+
+use dvs::dvs::packet::{PACKET_EVENT_BYTES, PACKET_HEADER_BYTES};
+use dvs::dvs::rtp::{RtpClock, RtpOptions, RtpPacket, RtpPacketizer, RTP_HEADER_BYTES};
+use dvs::dvs::DVSEvent;
+
+fn event(timestamp: i64) -> DVSEvent {
+    DVSEvent { timestamp, x: (timestamp % 640) as i16, y: (timestamp % 480) as i16, polarity: (timestamp % 2) as u8 }
+}
+
+fn packetize(options: RtpOptions, timestamps: &[i64]) -> Vec<RtpPacket> {
+    let mut packetizer = RtpPacketizer::new(options);
+    let mut packets = Vec::new();
+    for &timestamp in timestamps {
+        packetizer.process(event(timestamp), &mut |packet| packets.push(packet));
+    }
+    packetizer.finish(&mut |packet| packets.push(packet));
+    packets
+}
+
+#[test]
+fn headers_follow_rfc_3550() {
+    let options = RtpOptions { payload_type: 100, ssrc: 0x01020304, clock: RtpClock::Khz90, ..RtpOptions::default() };
+    let packets = packetize(options, &[1_000_000, 1_000_010]);
+    assert_eq!(packets.len(), 1);
+    let bytes = packets[0].encode();
+    assert_eq!(bytes.len(), packets[0].encoded_len());
+    // Version 2, then the marker bit and payload type
+    assert_eq!(bytes[0], 0x80);
+    assert_eq!(bytes[1], 0x80 | 100);
+    assert_eq!(&bytes[2..4], &0u16.to_be_bytes());
+    // One second on the 90 kHz clock
+    assert_eq!(&bytes[4..8], &90_000u32.to_be_bytes());
+    assert_eq!(&bytes[8..12], &0x01020304u32.to_be_bytes());
+
+    let decoded = RtpPacket::decode(&bytes).unwrap();
+    assert_eq!((decoded.marker, decoded.payload_type, decoded.sequence, decoded.timestamp, decoded.ssrc), (true, 100, 0, 90_000, 0x01020304));
+    assert_eq!(decoded.packet.events.iter().map(|event| event.timestamp).collect::<Vec<_>>(), [1_000_000, 1_000_010]);
+
+    assert_eq!(RtpClock::Microseconds.rtp_timestamp(1 << 32 | 5), 5);
+    assert_eq!(RtpClock::Khz90.rtp_timestamp(100), 9);
+}
+
+#[test]
+fn decoding_skips_csrcs_extensions_and_padding() {
+    let packet = packetize(RtpOptions::default(), &[5, 6, 7]).remove(0);
+    let bytes = packet.encode();
+    // Two CSRCs, an extension of one word, and four bytes of padding
+    let mut extended = vec![0x80 | 0x20 | 0x10 | 2];
+    extended.extend_from_slice(&bytes[1..RTP_HEADER_BYTES]);
+    extended.extend_from_slice(&[0xAA; 8]);
+    extended.extend_from_slice(&[0xBE, 0xDE, 0, 1, 0xBB, 0xBB, 0xBB, 0xBB]);
+    extended.extend_from_slice(&bytes[RTP_HEADER_BYTES..]);
+    extended.extend_from_slice(&[0, 0, 0, 4]);
+    let decoded = RtpPacket::decode(&extended).unwrap();
+    assert_eq!(decoded.packet.events.iter().map(|event| event.timestamp).collect::<Vec<_>>(), [5, 6, 7]);
+
+    assert!(RtpPacket::decode(&bytes[..RTP_HEADER_BYTES - 1]).is_err());
+    let mut version_1 = bytes.clone();
+    version_1[0] = 0x40;
+    assert!(RtpPacket::decode(&version_1).is_err());
+    assert!(RtpPacket::decode(&extended[..RTP_HEADER_BYTES + 10]).is_err());
+}
+
+#[test]
+fn markers_end_each_chunk() {
+    // Three events per packet, in chunks of 100 us
+    let options = RtpOptions { chunk_us: 100, max_bytes: RTP_HEADER_BYTES + PACKET_HEADER_BYTES + 3 * PACKET_EVENT_BYTES, ..RtpOptions::default() };
+    let packets = packetize(options, &[0, 10, 20, 30, 40, 99, 100, 150, 250, 260, 270, 280]);
+    let layout: Vec<(Vec<i64>, bool)> = packets.iter().map(|packet| (packet.packet.events.iter().map(|event| event.timestamp).collect(), packet.marker)).collect();
+    assert_eq!(
+        layout,
+        [(vec![0, 10, 20], false), (vec![30, 40, 99], true), (vec![100, 150], true), (vec![250, 260, 270], false), (vec![280], true)]
+    );
+    assert_eq!(packets.iter().map(|packet| packet.sequence).collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+    assert!(packets.iter().all(|packet| packet.encoded_len() <= options.max_bytes));
+    assert!(packetize(options, &[]).is_empty());
+}
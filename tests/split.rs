@@ -0,0 +1,117 @@
+// Checks splitting and cutting recordings, in split.rs and convert.rs: segments cover consecutive windows or sizes,
+// each decodes on its own with the header of the recording, and together they hold every event once; cuts hold
+// the events of their window, whether the input seeks to it or is read from the start
+
+use dvs::dvs::convert::cut_file;
+use dvs::dvs::split::{split_file, SplitLimit};
+use dvs::dvs::{prep_file_decoder, prep_file_encoder, DVSEvent, DvsRawDecoder, DvsRawEncoder, EventFormat};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+// Events every 50 us over a second, with a gap between 400 and 600 ms
+fn events() -> Vec<DVSEvent> {
+    (0..20_000)
+        .map(|i| DVSEvent { timestamp: i * 50, x: (i % 1280) as i16, y: (i % 720) as i16, polarity: (i % 2) as u8 })
+        .filter(|event| !(400_000..600_000).contains(&event.timestamp))
+        .collect()
+}
+
+fn key(event: &DVSEvent) -> (i64, i16, i16, u8) {
+    (event.timestamp, event.x, event.y, event.polarity)
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("dvs-split-{}-{}", std::process::id(), name))
+}
+
+fn write_recording(path: &Path, format: EventFormat) {
+    let mut encoder = prep_file_encoder::<File>(path.to_str().unwrap(), format).unwrap();
+    encoder.write_header(vec!["% geometry 1280x720\n".to_string()]).unwrap();
+    for event in events() {
+        encoder.write_event(event).unwrap();
+    }
+    encoder.flush().unwrap();
+}
+
+fn read_recording(path: &str) -> (Vec<String>, Vec<DVSEvent>) {
+    let mut decoder = prep_file_decoder::<BufReader<File>>(path).unwrap();
+    let header = decoder.read_header().unwrap();
+    let mut events = Vec::new();
+    while decoder.read_events_into(&mut events, 4096).unwrap() > 0 {}
+    (header, events)
+}
+
+#[test]
+fn segments_cover_consecutive_windows() {
+    let input = temp_path("windows.raw");
+    write_recording(&input, EventFormat::Evt2);
+    let out_dir = temp_path("windows");
+    let mut reported = Vec::new();
+    let segments = split_file(input.to_str().unwrap(), &out_dir, EventFormat::Evt2, SplitLimit::Duration(100_000), |segment| reported.push(segment.clone())).unwrap();
+    assert_eq!(reported, segments);
+    // The windows of the gap have no segments
+    let windows: Vec<i64> = segments.iter().map(|segment| segment.first_us / 100_000).collect();
+    assert_eq!(windows, [0, 1, 2, 3, 6, 7, 8, 9]);
+
+    let stem = input.file_stem().unwrap().to_str().unwrap();
+    let mut all = Vec::new();
+    for (i, segment) in segments.iter().enumerate() {
+        assert_eq!(Path::new(&segment.path), out_dir.join(format!("{}_{:05}.raw", stem, i)));
+        assert_eq!(segment.bytes, std::fs::metadata(&segment.path).unwrap().len());
+        let (header, events) = read_recording(&segment.path);
+        assert!(header.iter().any(|line| line.trim_end() == "% geometry 1280x720"), "{:?}", header);
+        assert_eq!(segment.events, events.len() as u64);
+        assert_eq!((segment.first_us, segment.last_us), (events[0].timestamp, events.last().unwrap().timestamp));
+        assert!(events.iter().all(|event| event.timestamp / 100_000 == segment.first_us / 100_000));
+        all.extend(events);
+    }
+    assert_eq!(all.iter().map(key).collect::<Vec<_>>(), events().iter().map(key).collect::<Vec<_>>());
+    std::fs::remove_dir_all(out_dir).unwrap();
+    std::fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn segments_end_once_they_reach_their_size() {
+    let input = temp_path("sizes.raw");
+    write_recording(&input, EventFormat::Evt2);
+    let out_dir = temp_path("sizes");
+    let segments = split_file(input.to_str().unwrap(), &out_dir, EventFormat::Csv, SplitLimit::Bytes(50_000), |_| {}).unwrap();
+    assert!(segments.len() > 2);
+    let (last, full) = segments.split_last().unwrap();
+    // Buffered output can take a segment past the size, but not by more than the buffer
+    assert!(full.iter().all(|segment| (50_000..50_000 + 16_384).contains(&segment.bytes)), "{:?}", segments);
+    assert!(last.bytes > 0);
+
+    let mut all = Vec::new();
+    for segment in &segments {
+        assert!(segment.path.ends_with(".csv"));
+        all.extend(read_recording(&segment.path).1);
+    }
+    assert_eq!(all.iter().map(key).collect::<Vec<_>>(), events().iter().map(key).collect::<Vec<_>>());
+    assert!(split_file(input.to_str().unwrap(), &out_dir, EventFormat::Evt2, SplitLimit::Duration(0), |_| {}).is_err());
+    assert!(split_file(input.to_str().unwrap(), &out_dir, EventFormat::Evt2, SplitLimit::Bytes(0), |_| {}).is_err());
+    assert!(split_file(input.to_str().unwrap(), &out_dir, EventFormat::Npz, SplitLimit::Bytes(1_000), |_| {}).is_err());
+    std::fs::remove_dir_all(out_dir).unwrap();
+    std::fs::remove_file(input).unwrap();
+}
+
+#[test]
+fn cuts_hold_the_events_of_their_window() {
+    // EVT2 inputs seek to the start of the window, CSV inputs are read from the start
+    for (name, format) in [("cut.raw", EventFormat::Evt2), ("cut.csv", EventFormat::Csv)] {
+        let input = temp_path(name);
+        write_recording(&input, format);
+        let output = temp_path(&format!("{}.out.raw", name));
+        for (start_us, end_us) in [(250_000, 750_001), (0, 1), (450_000, 550_000), (999_950, i64::MAX)] {
+            let totals = cut_file(input.to_str().unwrap(), output.to_str().unwrap(), EventFormat::Evt2, start_us, end_us, |_| {}).unwrap();
+            let (header, cut) = read_recording(output.to_str().unwrap());
+            let expected: Vec<_> = events().iter().filter(|event| (start_us..end_us).contains(&event.timestamp)).map(key).collect();
+            assert_eq!(cut.iter().map(key).collect::<Vec<_>>(), expected, "{} {}..{}", name, start_us, end_us);
+            assert_eq!(totals.events, expected.len() as u64);
+            assert!(header.iter().any(|line| line.trim_end() == "% geometry 1280x720"), "{:?}", header);
+        }
+        std::fs::remove_file(output).unwrap();
+        std::fs::remove_file(input).unwrap();
+    }
+}